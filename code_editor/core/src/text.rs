@@ -1,8 +1,12 @@
-use super::{Delta, Size};
+use {
+    super::{Delta, Position, Size},
+    makepad_rope::{Lines, Rope},
+    std::borrow::Cow,
+};
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Text {
-    lines: Vec<String>,
+    rope: Rope,
 }
 
 impl Text {
@@ -10,49 +14,73 @@ impl Text {
         Self::default()
     }
 
-    pub fn from_lines(mut lines: Vec<String>) -> Self {
-        if lines.is_empty() {
-            lines.push(String::new());
-        }
-        Self { lines }
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        Self::from_rope(Rope::from(lines.join("\n")))
+    }
+
+    pub fn from_rope(rope: Rope) -> Self {
+        Self { rope }
+    }
+
+    pub fn as_rope(&self) -> &Rope {
+        &self.rope
     }
 
     pub fn len(&self) -> Size {
+        let last_line = self.line_count() - 1;
         Size {
-            line: self.lines.len() - 1,
-            byte: self.lines.last().unwrap().len(),
+            line: last_line,
+            byte: self.rope.len_bytes() - self.rope.line_to_byte(last_line),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.is_empty()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        let start = self.rope.line_to_byte(line);
+        if line + 1 < self.line_count() {
+            self.rope.line_to_byte(line + 1) - 1 - start
+        } else {
+            self.rope.len_bytes() - start
         }
     }
 
-    pub fn as_lines(&self) -> &[String] {
-        self.lines.as_slice()
+    pub fn line(&self, line: usize) -> Cow<'_, str> {
+        self.lines_at(line).next().unwrap()
+    }
+
+    pub fn lines(&self) -> Lines<'_> {
+        self.rope.lines()
     }
 
-    pub fn apply_delta(&mut self, mut delta: Delta) {
-        delta.replace_with.lines.first_mut().unwrap().replace_range(
-            ..0,
-            &self.lines[delta.range.start().line][..delta.range.start().byte],
-        );
-        delta
-            .replace_with
-            .lines
-            .last_mut()
-            .unwrap()
-            .push_str(&self.lines[delta.range.end().line][delta.range.end().byte..]);
-        self.lines.splice(
-            delta.range.start().line..=delta.range.end().line,
-            delta.replace_with.lines,
-        );
+    pub fn lines_at(&self, line: usize) -> Lines<'_> {
+        self.rope.lines_at(line)
+    }
+
+    pub fn apply_delta(&mut self, delta: Delta) {
+        let start = self.byte_index(delta.range.start());
+        let end = self.byte_index(delta.range.end());
+        self.rope.remove(start..end);
+        let mut byte_index = start;
+        for chunk in delta.replace_with.rope.chunks() {
+            self.rope.insert(byte_index, chunk);
+            byte_index += chunk.len();
+        }
     }
 
     pub fn into_lines(self) -> Vec<String> {
-        self.lines
+        self.lines().map(Cow::into_owned).collect()
     }
-}
 
-impl Default for Text {
-    fn default() -> Self {
-        Self::from_lines(vec!["".into()])
+    fn byte_index(&self, position: Position) -> usize {
+        assert!(position.byte <= self.line_len(position.line));
+        self.rope.line_to_byte(position.line) + position.byte
     }
 }
 
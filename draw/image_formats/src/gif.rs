@@ -0,0 +1,520 @@
+// image_formats::gif
+
+use crate::{ImageBuffer, ImageFrame, AnimatedImage};
+
+const DISPOSE_BACKGROUND: u8 = 2;
+const DISPOSE_PREVIOUS: u8 = 3;
+
+fn from_le16(src: &[u8]) -> u16 {
+    ((src[1] as u16) << 8) | (src[0] as u16)
+}
+
+struct Reader<'a> {
+    src: &'a [u8],
+    sp: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn get8(&mut self) -> Result<u8, String> {
+        if self.sp >= self.src.len() {
+            return Err("Invalid GIF (unexpected end of data)".to_string());
+        }
+        let b = self.src[self.sp];
+        self.sp += 1;
+        Ok(b)
+    }
+
+    fn get16(&mut self) -> Result<u16, String> {
+        let lo = self.get8()? as u16;
+        let hi = self.get8()? as u16;
+        Ok((hi << 8) | lo)
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.sp + len > self.src.len() {
+            return Err("Invalid GIF (unexpected end of data)".to_string());
+        }
+        let result = &self.src[self.sp..self.sp + len];
+        self.sp += len;
+        Ok(result)
+    }
+
+    fn palette(&mut self, size: usize) -> Result<Vec<u32>, String> {
+        let src = self.slice(size * 3)?;
+        Ok((0..size).map( | i | 0xFF000000 | ((src[i * 3] as u32) << 16) | ((src[i * 3 + 1] as u32) << 8) | (src[i * 3 + 2] as u32)).collect())
+    }
+
+    // concatenate data sub-blocks up to the block terminator
+    fn sub_blocks(&mut self) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        loop {
+            let len = self.get8()? as usize;
+            if len == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(self.slice(len)?);
+        }
+    }
+
+    fn skip_sub_blocks(&mut self) -> Result<(), String> {
+        loop {
+            let len = self.get8()? as usize;
+            if len == 0 {
+                return Ok(());
+            }
+            self.slice(len)?;
+        }
+    }
+}
+
+// variable length LZW decoding, returns color indices (truncated streams produce what is available)
+fn lzw_decode(src: &[u8], min_code_size: u8, pixels: usize) -> Result<Vec<u8>, String> {
+    if !(1..=11).contains(&min_code_size) {
+        return Err("Invalid GIF (LZW code size)".to_string());
+    }
+    let clear_code = 1usize << min_code_size;
+    let end_code = clear_code + 1;
+    let mut prefix = [0u16; 4096];
+    let mut suffix = [0u8; 4096];
+    let mut length = [0u16; 4096];
+    for i in 0..clear_code {
+        suffix[i] = i as u8;
+        length[i] = 1;
+    }
+    let mut result: Vec<u8> = Vec::with_capacity(pixels);
+    let mut code_size = min_code_size as usize + 1;
+    let mut next_code = end_code + 1;
+    let mut prev: Option<usize> = None;
+    let mut bits: u32 = 0;
+    let mut nbits = 0;
+    let mut sp = 0;
+    let mut string = [0u8; 4096];
+    while result.len() < pixels {
+        while nbits < code_size {
+            if sp >= src.len() {
+                return Ok(result);
+            }
+            bits |= (src[sp] as u32) << nbits;
+            sp += 1;
+            nbits += 8;
+        }
+        let code = (bits & ((1 << code_size) - 1)) as usize;
+        bits >>= code_size;
+        nbits -= code_size;
+        if code == clear_code {
+            code_size = min_code_size as usize + 1;
+            next_code = end_code + 1;
+            prev = None;
+            continue;
+        }
+        if code == end_code {
+            break;
+        }
+        let prev_code = match prev {
+            None => {
+                if code >= clear_code {
+                    return Err("Invalid GIF (LZW code)".to_string());
+                }
+                result.push(code as u8);
+                prev = Some(code);
+                continue;
+            },
+            Some(prev_code) => prev_code,
+        };
+        // the first character of the new string, for the KwKwK case it is the first of the previous string
+        let known = code < next_code;
+        if !known && (code != next_code) {
+            return Err("Invalid GIF (LZW code)".to_string());
+        }
+        let mut c = if known {code} else {prev_code};
+        let len = length[c] as usize;
+        for i in (0..len).rev() {
+            string[i] = suffix[c];
+            c = prefix[c] as usize;
+        }
+        let first = string[0];
+        result.extend_from_slice(&string[0..len]);
+        if !known {
+            result.push(first);
+        }
+        if next_code < 4096 {
+            prefix[next_code] = prev_code as u16;
+            suffix[next_code] = first;
+            length[next_code] = length[prev_code] + 1;
+            next_code += 1;
+            if (next_code == (1 << code_size)) && (code_size < 12) {
+                code_size += 1;
+            }
+        }
+        prev = Some(code);
+    }
+    result.truncate(pixels);
+    Ok(result)
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    global_palette: Option<Vec<u32>>,
+}
+
+fn parse_header(reader: &mut Reader) -> Result<Header, String> {
+    let signature = reader.slice(6)?;
+    if (signature != b"GIF87a") && (signature != b"GIF89a") {
+        return Err("Invalid GIF".to_string());
+    }
+    let width = reader.get16()? as usize;
+    let height = reader.get16()? as usize;
+    let flags = reader.get8()?;
+    let _background_index = reader.get8()?;
+    let _aspect = reader.get8()?;
+    if (width == 0) || (height == 0) {
+        return Err("Invalid GIF (empty image)".to_string());
+    }
+    let global_palette = if (flags & 0x80) != 0 {
+        Some(reader.palette(2 << (flags & 7))?)
+    }
+    else {
+        None
+    };
+    Ok(Header {width, height, global_palette})
+}
+
+pub fn test(src: &[u8]) -> Option<(usize, usize)> {
+    if (src.len() < 10) || ((&src[0..6] != b"GIF87a") && (&src[0..6] != b"GIF89a")) {
+        return None;
+    }
+    let width = from_le16(&src[6..]) as usize;
+    let height = from_le16(&src[8..]) as usize;
+    Some((width, height))
+}
+
+/// Decode the first frame of a GIF.
+pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
+    let animation = decode_frames(src, true)?;
+    Ok(animation.frames.into_iter().next().unwrap().image)
+}
+
+/// Decode all frames of a GIF, composited onto the logical screen.
+pub fn decode_animated(src: &[u8]) -> Result<AnimatedImage, String> {
+    decode_frames(src, false)
+}
+
+fn decode_frames(src: &[u8], first_only: bool) -> Result<AnimatedImage, String> {
+    let mut reader = Reader {src, sp: 0};
+    let header = parse_header(&mut reader)?;
+    let width = header.width;
+    let height = header.height;
+
+    let mut canvas = vec![0u32; width * height];
+    let mut frames = Vec::new();
+    let mut loop_count = 1;
+
+    // graphic control extension state, applies to the next image only
+    let mut disposal = 0;
+    let mut delay = 0.0;
+    let mut transparent: Option<u8> = None;
+
+    // a missing trailer just ends the image
+    while let Ok(introducer) = reader.get8() {
+        match introducer {
+            0x21 => { // extension
+                let label = reader.get8()?;
+                match label {
+                    0xF9 => { // graphic control extension
+                        let data = reader.sub_blocks()?;
+                        if data.len() >= 4 {
+                            disposal = (data[0] >> 2) & 7;
+                            // browsers show very short delays at 10 centiseconds
+                            let centiseconds = from_le16(&data[1..]);
+                            delay = if centiseconds < 2 {0.1} else {centiseconds as f64 / 100.0};
+                            transparent = if (data[0] & 1) != 0 {Some(data[3])} else {None};
+                        }
+                    },
+                    0xFF => { // application extension
+                        let data = reader.sub_blocks()?;
+                        if (data.len() >= 14) && ((&data[0..11] == b"NETSCAPE2.0") || (&data[0..11] == b"ANIMEXTS1.0")) && (data[11] == 1) {
+                            let repeat = from_le16(&data[12..]) as u32;
+                            loop_count = if repeat == 0 {0} else {repeat + 1};
+                        }
+                    },
+                    _ => {
+                        reader.skip_sub_blocks()?;
+                    },
+                }
+            },
+            0x2C => { // image descriptor
+                let fx = reader.get16()? as usize;
+                let fy = reader.get16()? as usize;
+                let fw = reader.get16()? as usize;
+                let fh = reader.get16()? as usize;
+                let flags = reader.get8()?;
+                let local_palette = if (flags & 0x80) != 0 {
+                    Some(reader.palette(2 << (flags & 7))?)
+                }
+                else {
+                    None
+                };
+                let interlaced = (flags & 0x40) != 0;
+                let palette = match local_palette.as_ref().or(header.global_palette.as_ref()) {
+                    Some(palette) => palette,
+                    None => {return Err("Invalid GIF (no palette)".to_string());}
+                };
+                let min_code_size = reader.get8()?;
+                let data = reader.sub_blocks()?;
+                let indices = lzw_decode(&data, min_code_size, fw * fh)?;
+
+                // row order of the frame data
+                let rows: Vec<usize> = if interlaced {
+                    (0..fh).step_by(8).chain((4..fh).step_by(8)).chain((2..fh).step_by(4)).chain((1..fh).step_by(2)).collect()
+                }
+                else {
+                    (0..fh).collect()
+                };
+
+                let previous = if disposal == DISPOSE_PREVIOUS {Some(canvas.clone())} else {None};
+                for (i, index) in indices.iter().enumerate() {
+                    let y = fy + rows[i / fw];
+                    let x = fx + (i % fw);
+                    if (x >= width) || (y >= height) || (Some(*index) == transparent) {
+                        continue;
+                    }
+                    // out of range indices are black
+                    canvas[y * width + x] = palette.get(*index as usize).copied().unwrap_or(0xFF000000);
+                }
+                frames.push(ImageFrame {
+                    image: ImageBuffer {width, height, data: canvas.clone()},
+                    delay,
+                });
+                if first_only {
+                    break;
+                }
+
+                if disposal == DISPOSE_BACKGROUND {
+                    for y in fy..(fy + fh).min(height) {
+                        for x in fx..(fx + fw).min(width) {
+                            canvas[y * width + x] = 0;
+                        }
+                    }
+                }
+                else if let Some(previous) = previous {
+                    canvas = previous;
+                }
+                disposal = 0;
+                delay = 0.0;
+                transparent = None;
+            },
+            0x3B => { // trailer
+                break;
+            },
+            _ => {
+                return Err("Invalid GIF (unknown block)".to_string());
+            },
+        }
+    }
+    if frames.is_empty() {
+        return Err("Invalid GIF (no frames)".to_string());
+    }
+    if frames.len() == 1 {
+        frames[0].delay = 0.0;
+    }
+    Ok(AnimatedImage {
+        width,
+        height,
+        frames,
+        loop_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // simple LZW encoder, emits a clear code whenever the table is full
+    fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+        use std::collections::HashMap;
+        let clear_code = 1u32 << min_code_size;
+        let mut out = Vec::new();
+        let mut bits: u32 = 0;
+        let mut nbits = 0;
+        let mut code_size = min_code_size as u32 + 1;
+        let mut put = | code: u32, code_size: u32, out: &mut Vec<u8> | {
+            bits |= code << nbits;
+            nbits += code_size;
+            while nbits >= 8 {
+                out.push(bits as u8);
+                bits >>= 8;
+                nbits -= 8;
+            }
+        };
+        let mut table: HashMap<(u32, u8), u32> = HashMap::new();
+        let mut next_code = clear_code + 2;
+        put(clear_code, code_size, &mut out);
+        let mut current = indices[0] as u32;
+        for index in &indices[1..] {
+            if let Some(code) = table.get(&(current, *index)) {
+                current = *code;
+                continue;
+            }
+            put(current, code_size, &mut out);
+            if next_code < 4096 {
+                table.insert((current, *index), next_code);
+                next_code += 1;
+                if next_code > (1 << code_size) && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            else {
+                put(clear_code, code_size, &mut out);
+                table.clear();
+                next_code = clear_code + 2;
+                code_size = min_code_size as u32 + 1;
+            }
+            current = *index as u32;
+        }
+        put(current, code_size, &mut out);
+        put(clear_code + 1, code_size, &mut out);
+        put(0, 7, &mut out);
+        out
+    }
+
+    fn push_sub_blocks(dst: &mut Vec<u8>, data: &[u8]) {
+        for chunk in data.chunks(255) {
+            dst.push(chunk.len() as u8);
+            dst.extend_from_slice(chunk);
+        }
+        dst.push(0);
+    }
+
+    const PALETTE: [u32; 4] = [0xFF000000, 0xFFFF0000, 0xFF00FF00, 0xFF0000FF];
+
+    fn gif_header(dst: &mut Vec<u8>, width: u16, height: u16, repeat: Option<u16>) {
+        dst.extend_from_slice(b"GIF89a");
+        dst.extend_from_slice(&width.to_le_bytes());
+        dst.extend_from_slice(&height.to_le_bytes());
+        dst.extend_from_slice(&[0x81, 0, 0]);
+        for c in PALETTE {
+            dst.extend_from_slice(&[(c >> 16) as u8, (c >> 8) as u8, c as u8]);
+        }
+        if let Some(repeat) = repeat {
+            dst.extend_from_slice(&[0x21, 0xFF, 11]);
+            dst.extend_from_slice(b"NETSCAPE2.0");
+            dst.extend_from_slice(&[3, 1]);
+            dst.extend_from_slice(&repeat.to_le_bytes());
+            dst.push(0);
+        }
+    }
+
+    fn gif_frame(dst: &mut Vec<u8>, rect: (u16, u16, u16, u16), disposal: u8, delay: u16, transparent: Option<u8>, indices: &[u8]) {
+        dst.extend_from_slice(&[0x21, 0xF9, 4, (disposal << 2) | transparent.is_some() as u8]);
+        dst.extend_from_slice(&delay.to_le_bytes());
+        dst.extend_from_slice(&[transparent.unwrap_or(0), 0]);
+        dst.push(0x2C);
+        for v in [rect.0, rect.1, rect.2, rect.3] {
+            dst.extend_from_slice(&v.to_le_bytes());
+        }
+        dst.push(0);
+        dst.push(2);
+        push_sub_blocks(dst, &lzw_encode(indices, 2));
+    }
+
+    #[test]
+    fn lzw_roundtrip() {
+        // long enough to fill the code table and force clear codes
+        let mut seed = 1u32;
+        let indices: Vec<u8> = (0..20000).map( | i | {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            if i % 3 == 0 {((seed >> 16) & 3) as u8} else {(i / 50 % 4) as u8}
+        }).collect();
+        let encoded = lzw_encode(&indices, 2);
+        assert_eq!(lzw_decode(&encoded, 2, indices.len()).unwrap(), indices);
+    }
+
+    #[test]
+    fn decode_single_frame() {
+        let indices: Vec<u8> = (0..12 * 5).map( | i | (i % 4) as u8).collect();
+        let mut src = Vec::new();
+        gif_header(&mut src, 12, 5, None);
+        gif_frame(&mut src, (0, 0, 12, 5), 0, 0, None, &indices);
+        src.push(0x3B);
+        assert_eq!(test(&src), Some((12, 5)));
+        let image = decode(&src).unwrap();
+        assert_eq!((image.width, image.height), (12, 5));
+        for (i, index) in indices.iter().enumerate() {
+            assert_eq!(image.data[i], PALETTE[*index as usize]);
+        }
+        let animation = decode_animated(&src).unwrap();
+        assert_eq!(animation.frames.len(), 1);
+        assert_eq!(animation.loop_count, 1);
+    }
+
+    #[test]
+    fn decode_animation_with_disposal() {
+        let mut src = Vec::new();
+        gif_header(&mut src, 4, 4, Some(0));
+        gif_frame(&mut src, (0, 0, 4, 4), 0, 50, None, &[1; 16]);
+        // 2x2 blue square, restored afterwards
+        gif_frame(&mut src, (1, 1, 2, 2), DISPOSE_PREVIOUS, 1, None, &[3; 4]);
+        // green with a transparent hole, cleared to background afterwards
+        gif_frame(&mut src, (0, 0, 2, 2), DISPOSE_BACKGROUND, 20, Some(0), &[2, 0, 2, 2]);
+        gif_frame(&mut src, (3, 3, 1, 1), 0, 20, None, &[0]);
+        src.push(0x3B);
+        let animation = decode_animated(&src).unwrap();
+        assert_eq!(animation.loop_count, 0);
+        assert_eq!(animation.frames.len(), 4);
+        let delays: Vec<f64> = animation.frames.iter().map( | frame | frame.delay).collect();
+        assert_eq!(delays, vec![0.5, 0.1, 0.2, 0.2]);
+
+        let red = PALETTE[1];
+        let frame1 = &animation.frames[1].image.data;
+        assert_eq!(frame1[5], PALETTE[3]);
+        assert_eq!(frame1[0], red);
+        let frame2 = &animation.frames[2].image.data;
+        assert_eq!(frame2[10], red); // frame 1 was restored
+        assert_eq!(frame2[0], PALETTE[2]);
+        assert_eq!(frame2[1], red); // transparent
+        let frame3 = &animation.frames[3].image.data;
+        assert_eq!(frame3[0], 0); // cleared
+        assert_eq!(frame3[4], 0);
+        assert_eq!(frame3[2], red);
+        assert_eq!(frame3[15], PALETTE[0]);
+
+        assert_eq!(animation.frame_index_at(0.55), 1);
+        assert_eq!(animation.frame_index_at(1.0 + 0.65), 2);
+    }
+
+    #[test]
+    fn decode_interlaced() {
+        let (w, h) = (3usize, 10usize);
+        // row y is filled with index y % 4, stored in interlaced order
+        let order: Vec<usize> = (0..h).step_by(8).chain((4..h).step_by(8)).chain((2..h).step_by(4)).chain((1..h).step_by(2)).collect();
+        let indices: Vec<u8> = order.iter().flat_map( | y | std::iter::repeat_n((*y % 4) as u8, w)).collect();
+        let mut src = Vec::new();
+        gif_header(&mut src, w as u16, h as u16, None);
+        src.push(0x2C);
+        for v in [0u16, 0, w as u16, h as u16] {
+            src.extend_from_slice(&v.to_le_bytes());
+        }
+        src.push(0x40);
+        src.push(2);
+        push_sub_blocks(&mut src, &lzw_encode(&indices, 2));
+        src.push(0x3B);
+        let image = decode(&src).unwrap();
+        for y in 0..h {
+            for x in 0..w {
+                assert_eq!(image.data[y * w + x], PALETTE[y % 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_does_not_panic() {
+        let mut src = Vec::new();
+        gif_header(&mut src, 8, 8, None);
+        gif_frame(&mut src, (0, 0, 8, 8), 0, 0, None, &[1; 64]);
+        src.push(0x3B);
+        for len in 0..src.len() {
+            let _ = decode_animated(&src[0..len]);
+        }
+    }
+}
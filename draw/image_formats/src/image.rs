@@ -1,7 +1,7 @@
 // image_formats::image
 // by Desmond Germans, 2019
 
-#[derive(Default)] 
+#[derive(Clone, Default)]
 pub struct ImageBuffer {
     pub width: usize,
     pub height: usize,
@@ -17,3 +17,107 @@ impl ImageBuffer {
         }
     }
 }
+
+/// Orientation of stored pixels relative to how the image should be displayed,
+/// numbered like the EXIF/TIFF `Orientation` tag.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    Normal = 1,
+    FlipHorizontal = 2,
+    Rotate180 = 3,
+    FlipVertical = 4,
+    Transpose = 5,
+    Rotate90 = 6,
+    Transverse = 7,
+    Rotate270 = 8,
+}
+
+impl Orientation {
+    pub fn from_exif(value: u16) -> Option<Orientation> {
+        match value {
+            1 => Some(Orientation::Normal),
+            2 => Some(Orientation::FlipHorizontal),
+            3 => Some(Orientation::Rotate180),
+            4 => Some(Orientation::FlipVertical),
+            5 => Some(Orientation::Transpose),
+            6 => Some(Orientation::Rotate90),
+            7 => Some(Orientation::Transverse),
+            8 => Some(Orientation::Rotate270),
+            _ => None,
+        }
+    }
+    
+    /// Whether width and height trade places when displayed.
+    pub fn swaps_axes(self) -> bool {
+        (self as u16) >= 5
+    }
+}
+
+impl ImageBuffer {
+    /// Returns the image transformed so that it displays the right way round.
+    pub fn oriented(self, orientation: Orientation) -> ImageBuffer {
+        if orientation == Orientation::Normal {
+            return self;
+        }
+        let (w, h) = (self.width, self.height);
+        let mut result = if orientation.swaps_axes() {ImageBuffer::new(h, w)} else {ImageBuffer::new(w, h)};
+        for y in 0..result.height {
+            for x in 0..result.width {
+                let (sx, sy) = match orientation {
+                    Orientation::Normal => (x, y),
+                    Orientation::FlipHorizontal => (w - 1 - x, y),
+                    Orientation::Rotate180 => (w - 1 - x, h - 1 - y),
+                    Orientation::FlipVertical => (x, h - 1 - y),
+                    Orientation::Transpose => (y, x),
+                    Orientation::Rotate90 => (y, h - 1 - x),
+                    Orientation::Transverse => (w - 1 - y, h - 1 - x),
+                    Orientation::Rotate270 => (w - 1 - y, x),
+                };
+                result.data[y * result.width + x] = self.data[sy * w + sx];
+            }
+        }
+        result
+    }
+}
+
+/// One frame of an animation, fully composited onto the image canvas.
+pub struct ImageFrame {
+    pub image: ImageBuffer,
+    /// How long this frame is shown, in seconds.
+    pub delay: f64,
+}
+
+/// A decoded animated image (GIF or APNG).
+pub struct AnimatedImage {
+    pub width: usize,
+    pub height: usize,
+    pub frames: Vec<ImageFrame>,
+    /// Number of times the animation plays, 0 means forever.
+    pub loop_count: u32,
+}
+
+impl AnimatedImage {
+    /// Total time of a single play of the animation, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.frames.iter().map( | frame | frame.delay).sum()
+    }
+    
+    /// Index of the frame to show `time` seconds after the animation started.
+    pub fn frame_index_at(&self, time: f64) -> usize {
+        let duration = self.duration();
+        if self.frames.len() <= 1 || duration <= 0.0 || time < 0.0 {
+            return 0;
+        }
+        if (self.loop_count != 0) && (time >= duration * self.loop_count as f64) {
+            return self.frames.len() - 1;
+        }
+        let mut time = time % duration;
+        for (index, frame) in self.frames.iter().enumerate() {
+            if time < frame.delay {
+                return index;
+            }
+            time -= frame.delay;
+        }
+        self.frames.len() - 1
+    }
+}
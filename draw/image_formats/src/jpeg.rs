@@ -1,7 +1,7 @@
 // image_formats::jpeg
 // by Desmond Germans, 2019
 
-use crate::ImageBuffer;
+use crate::{ImageBuffer, Orientation};
 
 const TYPE_Y: u16 = 0x000C;
 const TYPE_YUV420: u16 = 0x3900;
@@ -101,63 +101,54 @@ impl Table {
     }
 }
 
-fn jpeg_get8(block: &[u8], rp: &mut usize) -> u8 {
-    if *rp >= block.len() {
-        return 0;
-    }
-    let mut b = block[*rp];
-    //println!("[{:02X}]",b);
-    *rp += 1;
-    if b == 0xFF {
-        b = block[*rp];
-        //println!("[{:02X}]",b);
-        *rp += 1;
-        if b == 0 {
-            return 0xFF;
-        }
-    }
-    b
-}
-
-fn jpeg_unget8(block: &[u8], rp: &mut usize) {
-    *rp -= 1;
-    if block[*rp] == 0x00 {
-        if block[*rp - 1] == 0xFF {
-            *rp -= 1;
-        }
-    }
-}
-
+// Reader over entropy coded data. It never consumes markers: when one is hit, it
+// feeds zero bits until the scan decoder is done, so the marker can be found
+// again afterwards (restart intervals, next segment).
 struct Reader<'a> {
     block: &'a [u8],
     rp: usize, // the next byte to get from the cache
     bit: u32, // number of bits available in the cache
     cache: u32,
-    //pub debug_enabled: bool,
 }
 
 impl<'a> Reader<'a> {
-    pub fn new(block: &'a [u8]) -> Reader<'a> {
-        let mut rp = 0usize;
-        let mut bit = 0u32;
-        let mut cache = 0u32;
-        while bit <= 24 {
-            let b = jpeg_get8(block, &mut rp);
-            cache |= (b as u32) << (24 - bit);
-            bit += 8;
-        }
-        Reader {
+    pub fn new(block: &'a [u8], rp: usize) -> Reader<'a> {
+        let mut reader = Reader {
             block,
             rp,
-            bit,
-            cache,
-            //debug_enabled: false,
+            bit: 0,
+            cache: 0,
+        };
+        reader.restock();
+        reader
+    }
+    
+    fn get8(&mut self) -> u8 {
+        if self.rp >= self.block.len() {
+            return 0;
         }
+        let b = self.block[self.rp];
+        if b == 0xFF {
+            if self.rp + 1 >= self.block.len() {
+                return 0;
+            }
+            match self.block[self.rp + 1] {
+                0x00 => { // stuffed 0xFF
+                    self.rp += 2;
+                    return 0xFF;
+                },
+                _ => { // marker, stay here
+                    return 0;
+                },
+            }
+        }
+        self.rp += 1;
+        b
     }
     
     fn restock(&mut self) {
         while self.bit <= 24 {
-            let b = jpeg_get8(self.block, &mut self.rp);
+            let b = self.get8();
             self.cache |= (b as u32) << (24 - self.bit);
             self.bit += 8;
         }
@@ -201,19 +192,17 @@ impl<'a> Reader<'a> {
         self.restock();
     }
     
-    pub fn leave(&mut self) -> usize {
-        //println!("leave: bit = {}, rp = {}, cache = {:08X}, byte-aligned cache = {:08X}",self.bit,self.rp,self.cache,self.cache >> (32 - self.bit));
-
-        // rewind the cache
-        let bytes = (self.bit >> 3) + 1;
-        //println!("rewinding {} bytes",bytes);
-        for _ in 0..bytes {
-            jpeg_unget8(self.block,&mut self.rp);
+    // position of the next marker (or the end of the data), skipping whatever
+    // padding or garbage is left in the entropy coded segment
+    pub fn find_marker(&self) -> usize {
+        let mut rp = self.rp;
+        while rp + 1 < self.block.len() {
+            if (self.block[rp] == 0xFF) && (self.block[rp + 1] != 0x00) && (self.block[rp + 1] != 0xFF) {
+                return rp;
+            }
+            rp += 1;
         }
-
-        //println!("and leaving with rp = {} ({:02X} {:02X})",self.rp,self.block[self.rp],self.block[self.rp + 1]);
-
-        self.rp
+        self.block.len()
     }
 }
 
@@ -279,6 +268,9 @@ fn unpack_progressive_start_ac(reader: &mut Reader, coeffs: &mut[i32], acht: &Ta
                 let code = reader.getn(cat as usize);
                 let coeff = make_coeff(cat, code as isize);
                 i += run;
+                if i > end {
+                    break;
+                }
                 coeffs[FOLDING[i as usize] as usize] = (coeff << shift) as i32;
             }
             else {
@@ -305,13 +297,16 @@ fn unpack_progressive_refine_dc(reader: &mut Reader, coeffs: &mut[i32], shift: u
     }
 }
 
+// refine the nonzero coefficients from start, until count zero coefficients are
+// skipped, returns the index of the next zero coefficient (or end + 1)
 fn update_nonzeros(reader: &mut Reader, coeffs: &mut[i32], start: u8, end: u8, shift: u8, count: u8) -> u8 {
     let mut i = start;
     let mut k = count;
     while i <= end {
-        if coeffs[FOLDING[i as usize] as usize] != 0 {
-            if reader.get1() {
-                if coeffs[FOLDING[i as usize] as usize] > 0 {
+        let coeff = coeffs[FOLDING[i as usize] as usize];
+        if coeff != 0 {
+            if reader.get1() && ((coeff & (1 << shift)) == 0) {
+                if coeff > 0 {
                     coeffs[FOLDING[i as usize] as usize] += 1 << shift;
                 }
                 else {
@@ -344,18 +339,21 @@ fn unpack_progressive_refine_ac(reader: &mut Reader, coeffs: &mut[i32], acht: &T
             if cat != 0 {
                 let sb = reader.get1();
                 i = update_nonzeros(reader, &mut coeffs[0..64], i, end, shift, run);
+                if i > end {
+                    break;
+                }
                 if sb {
                     coeffs[FOLDING[i as usize] as usize] = 1 << shift;
                 }
                 else {
-                    coeffs[FOLDING[i as usize] as usize] = 11 << shift;
+                    coeffs[FOLDING[i as usize] as usize] = -1 << shift;
                 }
             }
             else {
-                if run == 15 {
+                if run == 15 { // ZRL
                     i = update_nonzeros(reader, &mut coeffs[0..64], i, end, shift, 15);
                 }
-                else {
+                else { // EOB run
                     *eobrun = 1 << run;
                     if run != 0 {
                         *eobrun += reader.getn(run as usize) as usize;
@@ -365,6 +363,7 @@ fn unpack_progressive_refine_ac(reader: &mut Reader, coeffs: &mut[i32], acht: &T
                     break;
                 }
             }
+            i += 1;
         }
     }
 }
@@ -393,86 +392,100 @@ fn unpack_block(reader: &mut Reader, coeffs: &mut [i32], dcht: &Table, acht: &Ta
     }
 }
 
-fn unpack_macroblock(
-    reader: &mut Reader,
-    coeffs: &mut [i32],
-    dcht: &[Table],
-    acht: &[Table],
-    dt: &[usize],
-    at: &[usize],
-    dc: &mut [i32],
+#[derive(Clone, Copy, Default)]
+struct Component {
+    id: u8,
+    h: usize, // horizontal sampling factor
+    v: usize, // vertical sampling factor
+    offset: usize, // offset of the first block of this component inside a macroblock
+}
+
+// where the coefficients of the macroblocks live, and what the scan looks like
+struct Scan<'a> {
+    components: &'a [Component],
+    indices: &'a [usize], // components in this scan
+    width: usize,
+    height: usize,
+    hmax: usize,
+    vmax: usize,
+    mbwidth: usize,
+    mbheight: usize,
+    cpmb: usize,
     start: u8,
     end: u8,
     shift: u8,
     refine: bool,
-    eobrun: &mut usize,
-    itype: u16,
-    rescnt: &mut usize,
     resint: usize,
-    mask: u8
-) {
-    match itype {
-        TYPE_Y => {
-            if (mask & 1) != 0 {
-                unpack_block(reader, &mut coeffs[0..64], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-            }
-        },
-        TYPE_YUV420 => {
-            if (mask & 1) != 0 {
-                unpack_block(reader, &mut coeffs[0..64], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-                unpack_block(reader, &mut coeffs[64..128], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-                unpack_block(reader, &mut coeffs[128..192], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-                unpack_block(reader, &mut coeffs[192..256], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-            }
-            if (mask & 2) != 0 {
-                unpack_block(reader, &mut coeffs[256..320], &dcht[dt[1]], &acht[at[1]], &mut dc[1], start, end, shift, refine, eobrun);
-            }
-            if (mask & 4) != 0 {
-                unpack_block(reader, &mut coeffs[320..384], &dcht[dt[2]], &acht[at[2]], &mut dc[2], start, end, shift, refine, eobrun);
-            }
-        },
-        TYPE_YUV422 | TYPE_YUV440 => {
-            if (mask & 1) != 0 {
-                unpack_block(reader, &mut coeffs[0..64], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-                unpack_block(reader, &mut coeffs[64..128], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-            }
-            if (mask & 2) != 0 {
-                unpack_block(reader, &mut coeffs[128..192], &dcht[dt[1]], &acht[at[1]], &mut dc[1], start, end, shift, refine, eobrun);
-            }
-            if (mask & 4) != 0 {
-                unpack_block(reader, &mut coeffs[192..256], &dcht[dt[2]], &acht[at[2]], &mut dc[2], start, end, shift, refine, eobrun);
-            }
-        },
-        TYPE_YUV444 | TYPE_RGB444 => {
-            if (mask & 1) != 0 {
-                unpack_block(reader, &mut coeffs[0..64], &dcht[dt[0]], &acht[at[0]], &mut dc[0], start, end, shift, refine, eobrun);
-            }
-            if (mask & 2) != 0 {
-                unpack_block(reader, &mut coeffs[64..128], &dcht[dt[1]], &acht[at[1]], &mut dc[1], start, end, shift, refine, eobrun);
+    dt: [usize; 3], // dc and ac huffman table per component
+    at: [usize; 3],
+}
+
+impl<'a> Scan<'a> {
+    fn block_offset(&self, c: usize, bx: usize, by: usize) -> usize {
+        let component = &self.components[c];
+        let mb = (by / component.v) * self.mbwidth + bx / component.h;
+        mb * self.cpmb + component.offset + ((by % component.v) * component.h + bx % component.h) * 64
+    }
+}
+
+// decode one scan starting at sp, returns the position of the marker following it
+fn decode_scan(src: &[u8], sp: usize, scan: &Scan, coeffs: &mut [i32], dcht: &[Table], acht: &[Table]) -> usize {
+    let mut reader = Reader::new(src, sp);
+    let mut eobrun = 0;
+    let mut dc = [0i32; 3];
+    let mut units = 0;
+    let mut unpack = | reader: &mut Reader, eobrun: &mut usize, dc: &mut [i32; 3], c: usize, bx: usize, by: usize | {
+        let offset = scan.block_offset(c, bx, by);
+        unpack_block(reader, &mut coeffs[offset..offset + 64], &dcht[scan.dt[c]], &acht[scan.at[c]], &mut dc[c], scan.start, scan.end, scan.shift, scan.refine, eobrun);
+    };
+    let restart = | reader: &mut Reader, eobrun: &mut usize, dc: &mut [i32; 3], units: usize | {
+        if (scan.resint != 0) && units.is_multiple_of(scan.resint) {
+            let mut rp = reader.find_marker();
+            if (rp + 1 < src.len()) && (src[rp + 1] >= 0xD0) && (src[rp + 1] <= 0xD7) {
+                rp += 2;
             }
-            if (mask & 4) != 0 {
-                unpack_block(reader, &mut coeffs[128..192], &dcht[dt[2]], &acht[at[2]], &mut dc[2], start, end, shift, refine, eobrun);
+            reader.enter(rp);
+            *eobrun = 0;
+            *dc = [0; 3];
+        }
+    };
+    if scan.indices.len() == 1 {
+        // non-interleaved, walk over the blocks of the component itself
+        let c = scan.indices[0];
+        let component = &scan.components[c];
+        let cwidth = (scan.width * component.h).div_ceil(scan.hmax);
+        let cheight = (scan.height * component.v).div_ceil(scan.vmax);
+        let bwidth = cwidth.div_ceil(8);
+        let bheight = cheight.div_ceil(8);
+        for by in 0..bheight {
+            for bx in 0..bwidth {
+                unpack(&mut reader, &mut eobrun, &mut dc, c, bx, by);
+                units += 1;
+                if units < bwidth * bheight {
+                    restart(&mut reader, &mut eobrun, &mut dc, units);
+                }
             }
-        },
-        _ => {},
+        }
     }
-    if resint != 0 {
-        *rescnt -= 1;
-        if *rescnt == 0 {
-            let mut tsp = reader.leave();
-            //if reader.debug_enabled {
-            //    println!("{:02X} {:02X} {:02X} {:02X} [{:02X}] {:02X} {:02X} {:02X}",reader.block[tsp - 4],reader.block[tsp - 3],reader.block[tsp - 2],reader.block[tsp - 1],reader.block[tsp],reader.block[tsp + 1],reader.block[tsp + 2],reader.block[tsp + 3])
-            //}
-            if (reader.block[tsp] == 0xFF) && ((reader.block[tsp + 1] >= 0xD0) && (reader.block[tsp + 1] < 0xD8)) {
-                tsp += 2;
-                *rescnt = resint;
-                dc[0] = 0;
-                dc[1] = 0;
-                dc[2] = 0;
+    else {
+        for mby in 0..scan.mbheight {
+            for mbx in 0..scan.mbwidth {
+                for c in scan.indices {
+                    let component = &scan.components[*c];
+                    for v in 0..component.v {
+                        for h in 0..component.h {
+                            unpack(&mut reader, &mut eobrun, &mut dc, *c, mbx * component.h + h, mby * component.v + v);
+                        }
+                    }
+                }
+                units += 1;
+                if units < scan.mbwidth * scan.mbheight {
+                    restart(&mut reader, &mut eobrun, &mut dc, units);
+                }
             }
-            reader.enter(tsp);
         }
     }
+    reader.find_marker()
 }
 
 fn partial_idct(out: &mut [i32], inp: &[i32]) {
@@ -879,7 +892,7 @@ fn draw_macroblock_yuv440(image: &mut ImageBuffer, x0: usize, y0: usize, width:
     for i in 0..height {
         for k in 0..width {
             let by = i >> 3;
-            let si = k & 7;
+            let si = i & 7;
             let y = coeffs[by * 64 + si * 8 + k] + 128;
             let hi = i >> 1;
             let u = coeffs[128 + hi * 8 + k];
@@ -911,21 +924,101 @@ fn draw_macroblock_rgb444(image: &mut ImageBuffer, x0: usize, y0: usize, width:
     }
 }
 
+fn exif16(src: &[u8], le: bool) -> u16 {
+    if le {from_le16(src)} else {from_be16(src)}
+}
+
+fn exif32(src: &[u8], le: bool) -> u32 {
+    if le {from_le32(src)} else {from_be32(src)}
+}
+
+#[derive(Default)]
+struct Exif {
+    orientation: Option<Orientation>,
+    rgb: bool,
+}
+
+// parse the interesting parts of IFD0 of an APP1 Exif segment
+fn parse_exif(data: &[u8]) -> Exif {
+    let mut exif = Exif::default();
+    if (data.len() < 14) || (&data[0..6] != b"Exif\0\0") {
+        return exif;
+    }
+    let tiff = &data[6..];
+    let le = from_be16(&tiff[0..2]) == 0x4949; // figure out endianness
+    let mut tsp = exif32(&tiff[4..8], le) as usize; // go to IFD0
+    if tsp + 2 > tiff.len() {
+        return exif;
+    }
+    let entries = exif16(&tiff[tsp..tsp + 2], le) as usize; // number of entries
+    tsp += 2;
+    for _i in 0..entries {
+        if tsp + 12 > tiff.len() {
+            break;
+        }
+        let tag = exif16(&tiff[tsp..tsp + 2], le);
+        let format = exif16(&tiff[tsp + 2..tsp + 4], le);
+        // both tags of interest are a single inline SHORT
+        if format == 3 {
+            let value = exif16(&tiff[tsp + 8..tsp + 10], le);
+            match tag {
+                0x0106 => { // photometric interpretation
+                    exif.rgb = value == 2;
+                },
+                0x0112 => { // orientation
+                    exif.orientation = Orientation::from_exif(value);
+                },
+                _ => {
+                }
+            }
+        }
+        tsp += 12;
+    }
+    exif
+}
+
+/// Returns the EXIF orientation of a JPEG file, if it has one.
+pub fn orientation(src: &[u8]) -> Option<Orientation> {
+    let mut sp = 2;
+    while sp + 4 <= src.len() {
+        let marker = from_be16(&src[sp..sp + 2]);
+        let length = from_be16(&src[sp + 2..sp + 4]) as usize;
+        match marker {
+            0xFFE1 if sp + length + 2 <= src.len() => {
+                if let Some(orientation) = parse_exif(&src[sp + 4..sp + length + 2]).orientation {
+                    return Some(orientation);
+                }
+            },
+            0xFFDA | 0xFFD9 => {
+                break;
+            },
+            _ => {},
+        }
+        sp += length + 2;
+    }
+    None
+}
+
+/// Returns the size of the JPEG image as it will be displayed (after EXIF orientation).
 pub fn test(src: &[u8]) -> Option<(usize, usize)> {
     let mut sp = 0;
-    if from_be16(&src[sp..sp + 2]) != 0xFFD8 {
+    if (src.len() < 4) || (from_be16(&src[sp..sp + 2]) != 0xFFD8) {
         return None;
     }
     sp += 2;
-    while sp < src.len() {
+    while sp + 10 <= src.len() {
         let marker = from_be16(&src[sp..sp + 2]);
         let length = from_be16(&src[sp + 2..sp + 4]) as usize;
         match marker {
             0xFFC0 | 0xFFC1 | 0xFFC2 => {
-                let width = from_be16(&src[sp + 5..sp + 7]) as usize;
-                let height = from_be16(&src[sp + 7..sp + 9]) as usize;
+                // SOF stores the height first, this used to return the two swapped
+                let height = from_be16(&src[sp + 5..sp + 7]) as usize;
+                let width = from_be16(&src[sp + 7..sp + 9]) as usize;
                 let components = src[sp + 9];
                 if (components == 1) || (components == 3) { // does not support RGBA or CMYK JPEGs
+                    if orientation(src).is_some_and( | o | o.swaps_axes()) {
+                        return Some((height, width));
+                    }
                     return Some((width, height));
                 }
                 return None;
@@ -937,8 +1030,76 @@ pub fn test(src: &[u8]) -> Option<(usize, usize)> {
     None
 }
 
+fn draw_image(coeffs: &mut [i32], itype: u16, (width, height): (usize, usize), (mbwidth, mbheight): (usize, usize), qtable: &[[i32; 64]], qt: &[usize; 3]) -> ImageBuffer {
+    let mbtotal = mbwidth * mbheight;
+    let mut image = ImageBuffer::new(width, height);
+    match itype {
+        TYPE_Y => {convert_blocks(coeffs, mbtotal, TYPE_Y, qtable, qt);},
+        TYPE_YUV420 => {convert_blocks(coeffs, mbtotal * 6, TYPE_YUV420, qtable, qt);},
+        TYPE_YUV422 => {convert_blocks(coeffs, mbtotal * 4, TYPE_YUV422, qtable, qt);},
+        TYPE_YUV440 => {convert_blocks(coeffs, mbtotal * 4, TYPE_YUV440, qtable, qt);},
+        TYPE_YUV444 => {convert_blocks(coeffs, mbtotal * 3, TYPE_YUV444, qtable, qt);},
+        TYPE_RGB444 => {convert_blocks(coeffs, mbtotal * 3, TYPE_RGB444, qtable, qt);},
+        _ => {},
+    }
+    let mut mb = 0;
+    for i in 0..mbheight - 1 {
+        for k in 0..mbwidth - 1 {
+            match itype {
+                TYPE_Y => {draw_macroblock_y(&mut image, k * 8, i * 8, 8, 8, &coeffs[mb..mb + 64]); mb += 64;},
+                TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, k * 16, i * 16, 16, 16, &coeffs[mb..mb + 384]); mb += 384;},
+                TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, k * 16, i * 8, 16, 8, &coeffs[mb..mb + 256]); mb += 256;},
+                TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, k * 8, i * 16, 8, 16, &coeffs[mb..mb + 256]); mb += 256;},
+                TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, k * 8, i * 8, 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
+                TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, k * 8, i * 8, 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
+                _ => {},
+            }
+        }
+        match itype {
+            TYPE_Y => {draw_macroblock_y(&mut image, mbwidth * 8 - 8, i * 8, width - (mbwidth - 1) * 8, 8, &coeffs[mb..mb + 64]); mb += 64;},
+            TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, mbwidth * 16 - 16, i * 16, width - (mbwidth - 1) * 16, 16, &coeffs[mb..mb + 384]); mb += 384;},
+            TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, mbwidth * 16 - 16, i * 8, width - (mbwidth - 1) * 16, 8, &coeffs[mb..mb + 256]); mb += 256;},
+            TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, mbwidth * 8 - 8, i * 16, width - (mbwidth - 1) * 8, 16, &coeffs[mb..mb + 256]); mb += 256;},
+            TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, mbwidth * 8 - 8, i * 8, width - (mbwidth - 1) * 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
+            TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, mbwidth * 8 - 8, i * 8, width - (mbwidth - 1) * 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
+            _ => {},
+        }
+    }
+    for k in 0..mbwidth - 1 {
+        match itype {
+            TYPE_Y => {draw_macroblock_y(&mut image, k * 8, mbheight * 8 - 8, 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 64]); mb += 64;},
+            TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, k * 16, mbheight * 16 - 16, 16, height - (mbheight - 1) * 16, &coeffs[mb..mb + 384]); mb += 384;},
+            TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, k * 16, mbheight * 8 - 8, 16, height - (mbheight - 1) * 8, &coeffs[mb..mb + 256]); mb += 256;},
+            TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, k * 8, mbheight * 16 - 16, 8, height - (mbheight - 1) * 16, &coeffs[mb..mb + 256]); mb += 256;},
+            TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, k * 8, mbheight * 8 - 8, 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]); mb += 192;},
+            TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, k * 8, mbheight * 8 - 8, 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]); mb += 192;},
+            _ => {},
+        }
+    }
+    match itype {
+        TYPE_Y => {draw_macroblock_y(&mut image, mbwidth * 8 - 8, mbheight * 8 - 8, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 64]);},
+        TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, mbwidth * 16 - 16, mbheight * 16 - 16, width - (mbwidth - 1) * 16, height - (mbheight - 1) * 16, &coeffs[mb..mb + 384]);},
+        TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, mbwidth * 16 - 16, mbheight * 8 - 8, width - (mbwidth - 1) * 16, height - (mbheight - 1) * 8, &coeffs[mb..mb + 256]);},
+        TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, mbwidth * 8 - 8, mbheight * 16 - 16, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 16, &coeffs[mb..mb + 256]);},
+        TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, mbwidth * 8 - 8, mbheight * 8 - 8, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]);},
+        TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, mbwidth * 8 - 8, mbheight * 8 - 8, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]);},
+        _ => {},
+    }
+    image
+}
+
+/// Decode a baseline or progressive JPEG, rotated and flipped according to its EXIF orientation.
 pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
-    if from_be16(&src[0..2]) != 0xFFD8 {
+    let (image, orientation) = decode_unoriented(src)?;
+    Ok(match orientation {
+        Some(orientation) => image.oriented(orientation),
+        None => image,
+    })
+}
+
+/// Decode a baseline or progressive JPEG as stored, also returning its EXIF orientation.
+pub fn decode_unoriented(src: &[u8]) -> Result<(ImageBuffer, Option<Orientation>), String> {
+    if (src.len() < 4) || (from_be16(&src[0..2]) != 0xFFD8) {
         return Err("Invalid JPEG 1".to_string());
     }
     let mut qtable = [[0i32; 64]; 4];
@@ -947,63 +1108,82 @@ pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
     let mut qt = [0usize; 3];
     let mut dt = [0usize; 3];
     let mut at = [0usize; 3];
-    #[allow(unused_assignments)]
-    let mut width = 1;
-    #[allow(unused_assignments)]
-    let mut height = 1;
-    #[allow(unused_assignments)]
+    let mut width = 0;
+    let mut height = 0;
     let mut itype = 0; // image type
-    #[allow(unused_assignments)]
-    let mut mbtotal = 0; // total number of macroblocks
-    #[allow(unused_assignments)]
     let mut mbwidth = 0;
-    #[allow(unused_assignments)]
     let mut mbheight = 0;
-    #[allow(unused_assignments)]
     let mut cpmb = 0;
+    let mut components: Vec<Component> = Vec::new();
     let mut coeffs: Vec<i32> = Vec::new(); // the coefficients
-    #[allow(unused_assignments)]
     let mut resint = 0;
-    #[allow(unused_assignments)]
+    let mut scans = 0;
+    let mut exif = Exif::default();
     let mut sp = 2;
-    while sp < src.len() {
+    while sp + 2 <= src.len() {
         let marker = from_be16(&src[sp..sp + 2]);
-        let length = if marker != 0xFFD9 {from_be16(&src[sp + 2..sp + 4]) as usize} else {0};
+        if (marker == 0xFFD9) || (marker == 0xFFD8) || (0xFFD0..=0xFFD7).contains(&marker) || (marker == 0xFF01) {
+            // markers without a length, stray restart markers are skipped
+            if marker == 0xFFD9 {
+                break;
+            }
+            sp += 2;
+            continue;
+        }
+        if (marker >> 8) != 0xFF {
+            // garbage between segments, resynchronize on the next marker
+            sp += 1;
+            continue;
+        }
+        if (marker & 0xFF) == 0xFF { // fill byte
+            sp += 1;
+            continue;
+        }
+        if sp + 4 > src.len() {
+            break;
+        }
+        let length = from_be16(&src[sp + 2..sp + 4]) as usize;
+        if (length < 2) || (sp + length + 2 > src.len()) {
+            return Err("Invalid JPEG 10".to_string());
+        }
+        let segment = &src[sp + 4..sp + length + 2];
         //println!("marker {:04X}, length {}",marker,length);
         match marker {
             0xFFC0 | 0xFFC1 | 0xFFC2 => { // baseline sequential, extended sequential, progressive
-                //println!("precision {}",src[sp + 4]);
-                if src[sp + 4] != 8 {
+                if (segment.len() < 6) || (segment[0] != 8) {
                     return Err("Invalid JPEG 2".to_string());
                 }
-                height = from_be16(&src[sp + 5..sp + 7]) as usize;
-                width = from_be16(&src[sp + 7..sp + 9]) as usize;
-                let components = src[sp + 9];
-                //println!("size {}x{}, components {}",width,height,components);
-                if (components != 1) && (components != 3) {
+                height = from_be16(&segment[1..3]) as usize;
+                width = from_be16(&segment[3..5]) as usize;
+                let count = segment[5] as usize;
+                if ((count != 1) && (count != 3)) || (segment.len() < 6 + count * 3) || (width == 0) || (height == 0) {
                     return Err("Invalid JPEG 3".to_string());
                 }
                 let mut samp = [0u8; 3];
-                let mut tsp = sp + 10;
-                for i in 0..components {
-                    if src[tsp] != i + 1 {
-                        return Err("Invalid JPEG 4".to_string());
+                components.clear();
+                for i in 0..count {
+                    let tsp = 6 + i * 3;
+                    samp[i] = segment[tsp + 1];
+                    // sampling factors are 1 to 4, anything else would divide by zero below
+                    if !(1..=4).contains(&(samp[i] >> 4)) || !(1..=4).contains(&(samp[i] & 15)) {
+                        return Err("Invalid JPEG 5".to_string());
                     }
-                    samp[i as usize] = src[tsp + 1];
-                    qt[i as usize] = src[tsp + 2] as usize;
-                    tsp += 3;
-                    //println!("{}: samp {:02X}, qt {}",i,samp[i as usize],qt[i as usize]);
+                    qt[i] = (segment[tsp + 2] & 3) as usize;
+                    components.push(Component {
+                        id: segment[tsp],
+                        h: (samp[i] >> 4) as usize,
+                        v: (samp[i] & 15) as usize,
+                        offset: 0,
+                    });
                 }
-                if components == 3 {
+                if count == 3 {
                     if (samp[1] != 0x11) || (samp[2] != 0x11) {
                         return Err("Invalid JPEG 5".to_string());
                     }
                     let sw = ((samp[0] >> 4) * 8) as usize;
                     let sh = ((samp[0] & 15) * 8) as usize;
-                    //println!("one macroblock = {}x{}",sw,sh);
                     mbwidth = (width + sw - 1) / sw;
                     mbheight = (height + sh - 1) / sh;
-                    //println!("{}x{} macroblocks ({}x{} pixels)",mbwidth,mbheight,mbwidth * sw,mbheight * sh);
                     cpmb = 128 + 64 * ((samp[0] >> 4) as usize) * ((samp[0] & 15) as usize);
                     itype = match samp[0] {
                         0x11 => TYPE_YUV444,
@@ -1014,236 +1194,899 @@ pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
                             return Err("Invalid JPEG 6".to_string());
                         },
                     };
+                    components[1].offset = cpmb - 128;
+                    components[2].offset = cpmb - 64;
                 }
                 else {
+                    // a single component is never subsampled
+                    components[0].h = 1;
+                    components[0].v = 1;
                     mbwidth = (width + 7) / 8;
                     mbheight = (height + 7) / 8;
                     cpmb = 64;
                     itype = TYPE_Y;
                 }
-                mbtotal = mbwidth * mbheight;
-                coeffs.resize(mbtotal * cpmb as usize, 0);
-                //println!("type {:04X}, {} macroblocks in total, {} coefficients per row",itype,mbtotal,mbstride);
-                //println!("size {}x{}, macroblocks {}",width,height,mbtotal);
+                coeffs = vec![0; mbwidth * mbheight * cpmb];
             },
             0xFFC4 => { // huffman tables
-                let mut tsp = sp + 4;
-                while tsp < sp + length + 2 {
-                    let d = src[tsp];
+                let mut tsp = 0;
+                while tsp + 17 <= segment.len() {
+                    let d = segment[tsp];
                     tsp += 1;
                     let tc = d >> 4;
-                    let n = d & 15;
-                    //println!("tc = {}, n = {}",tc,n);
+                    let n = (d & 3) as usize;
                     let mut bits = [0u8; 16];
-                    let mut total = 0usize;
-                    for i in 0..16 {
-                        bits[i] = src[tsp];
-                        tsp += 1;
-                        total += bits[i] as usize;
-                    }
-                    if total >= 256 {
+                    bits.copy_from_slice(&segment[tsp..tsp + 16]);
+                    tsp += 16;
+                    let total = bits.iter().map( | b | *b as usize).sum::<usize>();
+                    if (total >= 256) || (tsp + total > segment.len()) {
                         return Err("Invalid JPEG 7".to_string());
                     }
-                    //println!("total codes: {}",total);
                     let mut huffval = [0u8; 256];
-                    for i in 0..total {
-                        huffval[i] = src[tsp];
-                        //println!("code {}: run {}, cat {}",i,huffval[i] >> 4,huffval[i] & 15);
-                        tsp += 1;
-                    }
+                    huffval[0..total].copy_from_slice(&segment[tsp..tsp + total]);
+                    tsp += total;
                     let table = Table::new(bits, huffval);
                     if tc != 0 {
-                        acht[n as usize] = table;
+                        acht[n] = table;
                     }
                     else {
-                        dcht[n as usize] = table;
+                        dcht[n] = table;
                     }
                 }
             },
-            0xFFD8 => { // image start
-            },
             0xFFDA => { // scan start
-                //println!("scan start");
-                let mut tsp = sp + 4;
-                let count = src[tsp];
-                tsp += 1;
-                // acht[4], dcht[4]
-                let mut mask = 0;
-                for _i in 0..count {
-                    let index = src[tsp] - 1;
-                    tsp += 1;
-                    mask |= 1 << index;
-                    let n = src[tsp];
-                    tsp += 1;
-                    dt[index as usize] = (n >> 4) as usize;
-                    at[index as usize] = (n & 15) as usize;
-                    //println!("index {}, dt {}, at {}",index,n >> 4,n & 15);
+                if components.is_empty() || segment.is_empty() {
+                    return Err("Invalid JPEG 4".to_string());
                 }
-                let start = src[tsp];
-                tsp += 1;
-                let end = src[tsp];
-                tsp += 1;
-                let d = src[tsp];
-                tsp += 1;
-                let refine = (d & 0xF0) != 0;
-                let shift = d & 15;
-                //println!("start = {}, end = {}, refine = {}, shift = {}",start,end,refine,shift);
-                let mut reader = Reader::new(&src[tsp..]);
-                let mut rescnt = resint;
-                let mut eobrun = 0;
-                let mut dc = [0i32; 3];
-                for i in 0..mbtotal {
-                    //if i == 79 {
-                    //    reader.debug_enabled = true;
-                    //}
-                    //else if i == 81 {
-                    //    reader.debug_enabled = false;
-                    //}
-                    //if reader.debug_enabled { println!("macroblock {}:",i); }
-                    unpack_macroblock(&mut reader, &mut coeffs[i * cpmb..(i + 1) * cpmb], &dcht, &acht, &dt, &at, &mut dc, start, end, shift, refine, &mut eobrun, itype, &mut rescnt, resint, mask);
+                let count = segment[0] as usize;
+                if (count == 0) || (count > components.len()) || (segment.len() < 4 + count * 2) {
+                    return Err("Invalid JPEG 4".to_string());
+                }
+                let mut indices = Vec::new();
+                for i in 0..count {
+                    let id = segment[1 + i * 2];
+                    let index = match components.iter().position( | c | c.id == id) {
+                        Some(index) => index,
+                        None => {return Err("Invalid JPEG 4".to_string());},
+                    };
+                    let n = segment[2 + i * 2];
+                    dt[index] = (n >> 4) as usize & 3;
+                    at[index] = (n & 15) as usize & 3;
+                    indices.push(index);
                 }
-                sp = (tsp + reader.leave()) - length - 2;
-                //println!("sp = {}, ({:02X} {:02X})",sp,src[sp + length + 2],src[sp + length + 2 + 1]);
+                let tsp = 1 + count * 2;
+                let start = segment[tsp];
+                let end = segment[tsp + 1];
+                let d = segment[tsp + 2];
+                if (start > end) || (end > 63) {
+                    return Err("Invalid JPEG 4".to_string());
+                }
+                let scan = Scan {
+                    components: &components,
+                    indices: &indices,
+                    width,
+                    height,
+                    hmax: components.iter().map( | c | c.h).max().unwrap_or(1),
+                    vmax: components.iter().map( | c | c.v).max().unwrap_or(1),
+                    mbwidth,
+                    mbheight,
+                    cpmb,
+                    start,
+                    end,
+                    shift: d & 15,
+                    refine: (d & 0xF0) != 0,
+                    resint,
+                    dt,
+                    at,
+                };
+                //println!("start = {}, end = {}, refine = {}, shift = {}",start,end,refine,shift);
+                sp = decode_scan(src, sp + length + 2, &scan, &mut coeffs, &dcht, &acht);
+                scans += 1;
+                continue;
             },
             0xFFDB => { // quantization tables
-                let mut tsp = sp + 4;
-                while tsp < sp + length + 2 {
-                    let d = src[tsp];
+                let mut tsp = 0;
+                while tsp < segment.len() {
+                    let d = segment[tsp];
                     tsp += 1;
-                    let n = d & 15;
-                    //println!("updating qtable[{}]",n);
+                    let n = (d & 3) as usize;
                     if (d >> 4) != 0 {
+                        if tsp + 128 > segment.len() {
+                            return Err("Invalid JPEG 8".to_string());
+                        }
                         for k in 0..64 {
-                            qtable[n as usize][FOLDING[k as usize] as usize] = from_be16(&src[tsp..tsp + 2]) as i32;
+                            qtable[n][FOLDING[k] as usize] = from_be16(&segment[tsp..tsp + 2]) as i32;
                             tsp += 2;
                         }
                     }
                     else {
+                        if tsp + 64 > segment.len() {
+                            return Err("Invalid JPEG 8".to_string());
+                        }
                         for k in 0..64 {
-                            qtable[n as usize][FOLDING[k as usize] as usize] = src[tsp] as i32;
+                            qtable[n][FOLDING[k] as usize] = segment[tsp] as i32;
                             tsp += 1;
                         }
                     }
                 }
             },
-            0xFFDD => { // restart interval
-                resint = from_be16(&src[sp + 4..sp + 6]) as usize;
+            0xFFDD if segment.len() >= 2 => { // restart interval
+                resint = from_be16(&segment[0..2]) as usize;
             },
             0xFFE1 => { // EXIF
-                let header = from_be32(&src[sp + 4..sp + 8]);
-                if header == 0x45786966 { // Exif
-                    let start = sp + 10;
-                    let mut tsp = start;
-                    let le = from_be16(&src[tsp..tsp + 2]) == 0x4949; // figure out endianness
-                    tsp += 4; // skip 0x2A
-                    tsp += (if le {from_le32(&src[tsp..tsp + 4])} else {from_be32(&src[tsp..tsp + 4])} -8) as usize; // go to IFD0
-                    let entries = if le {from_le16(&src[tsp..tsp + 2])} else {from_be16(&src[tsp..tsp + 2])}; // number of entries
-                    tsp += 2;
-                    for _i in 0..entries {
-                        let tag = if le {from_le16(&src[tsp..tsp + 2])} else {from_be16(&src[tsp..tsp + 2])};
-                        tsp += 2;
-                        let format = if le {from_le16(&src[tsp..tsp + 2])} else {from_be16(&src[tsp..tsp + 2])};
-                        tsp += 2;
-                        if format > 12 {
-                            return Err("Invalid JPEG 8".to_string());
-                        }
-                        let components = if le {from_le32(&src[tsp..tsp + 4])} else {from_be32(&src[tsp..tsp + 4])};
-                        tsp += 4;
-                        let data = if le {from_le32(&src[tsp..tsp + 4])} else {from_be32(&src[tsp..tsp + 4])};
-                        tsp += 4;
-                        let elsize = [0usize, 1, 1, 2, 4, 8, 1, 0, 2, 4, 8, 4, 8];
-                        let total = elsize[format as usize] * (components as usize);
-                        let mut dsp = start + data as usize;
-                        if total <= 4 {
-                            dsp = tsp - 4;
-                        }
-                        //println!("EXIF tag {:04X}, format {}, components {}, data {:08X}",tag,format,components,data);
-                        match tag {
-                            0x0106 => { // photometric interpretation
-                                let pe = if le {from_le16(&src[dsp..dsp + 2])} else {from_be16(&src[dsp..dsp + 2])};
-                                if (pe != 2) || (itype != TYPE_YUV444) {
-                                    return Err("Invalid JPEG 9".to_string());
-                                }
-                                itype = TYPE_RGB444;
-                            },
-                            0xA001 => { // colorspace
-                            },
-                            _ => {
-                            }
-                        }
-                    }
+                let info = parse_exif(segment);
+                if info.orientation.is_some() {
+                    exif.orientation = info.orientation;
                 }
+                exif.rgb |= info.rgb;
             },
-            0xFFC8 | 0xFFDC | 0xFFE0 | 0xFFE2..=0xFFEF | 0xFFF0..=0xFFFF => { // other accepted markers
+            0xFFC3 | 0xFFC5..=0xFFC7 | 0xFFC9..=0xFFCB | 0xFFCD..=0xFFCF => { // lossless, hierarchical, arithmetic coding
+                return Err("Invalid JPEG 9".to_string());
             },
-            _ => { // image end
-                //println!("end");
-                let mut image = ImageBuffer::new(width, height);
-                match itype {
-                    TYPE_Y => {convert_blocks(&mut coeffs, mbtotal, TYPE_Y, &qtable, &qt);},
-                    TYPE_YUV420 => {convert_blocks(&mut coeffs, mbtotal * 6, TYPE_YUV420, &qtable, &qt);},
-                    TYPE_YUV422 => {convert_blocks(&mut coeffs, mbtotal * 4, TYPE_YUV422, &qtable, &qt);},
-                    TYPE_YUV440 => {convert_blocks(&mut coeffs, mbtotal * 4, TYPE_YUV440, &qtable, &qt);},
-                    TYPE_YUV444 => {convert_blocks(&mut coeffs, mbtotal * 3, TYPE_YUV444, &qtable, &qt);},
-                    TYPE_RGB444 => {convert_blocks(&mut coeffs, mbtotal * 3, TYPE_RGB444, &qtable, &qt);},
-                    _ => {},
+            _ => { // other accepted markers
+            },
+        }
+        sp += length + 2;
+    }
+    if scans == 0 {
+        return Err("Invalid JPEG 11".to_string());
+    }
+    // draw whatever was decoded, even when the file was truncated
+    if exif.rgb && (itype == TYPE_YUV444) {
+        itype = TYPE_RGB444;
+    }
+    Ok((draw_image(&mut coeffs, itype, (width, height), (mbwidth, mbheight), &qtable, &qt), exif.orientation))
+}
+
+// Encoder
+
+const ZIGZAG: [u8; 64] = [0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,];
+
+// standard quantization tables (ITU T.81 annex K.1), natural order
+const QTABLE_Y: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+const QTABLE_C: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+// standard huffman tables (ITU T.81 annex K.3)
+const DC_Y_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_Y_VALS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const DC_C_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_C_VALS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const AC_Y_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D];
+const AC_Y_VALS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+const AC_C_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_C_VALS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+    0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+    0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+/// Chroma subsampling used by the JPEG encoder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subsampling {
+    YUV444,
+    YUV420,
+}
+
+// huffman code and length for every symbol
+struct EncodeTable {
+    code: [u16; 256],
+    length: [u8; 256],
+}
+
+impl EncodeTable {
+    fn new(bits: &[u8; 16], huffval: &[u8]) -> EncodeTable {
+        let mut code = [0u16; 256];
+        let mut length = [0u8; 256];
+        let mut current = 0u16;
+        let mut count = 0;
+        for (i, &n) in bits.iter().enumerate() {
+            for _k in 0..n {
+                code[huffval[count] as usize] = current;
+                length[huffval[count] as usize] = (i + 1) as u8;
+                current += 1;
+                count += 1;
+            }
+            current <<= 1;
+        }
+        EncodeTable {
+            code,
+            length,
+        }
+    }
+}
+
+struct Writer {
+    data: Vec<u8>,
+    cache: u32,
+    bit: u32, // number of bits in the cache
+}
+
+impl Writer {
+    fn new(data: Vec<u8>) -> Writer {
+        Writer {
+            data,
+            cache: 0,
+            bit: 0,
+        }
+    }
+    
+    fn putn(&mut self, value: u32, n: u32) {
+        self.cache = (self.cache << n) | (value & ((1 << n) - 1));
+        self.bit += n;
+        while self.bit >= 8 {
+            let b = (self.cache >> (self.bit - 8)) as u8;
+            self.data.push(b);
+            if b == 0xFF {
+                self.data.push(0x00);
+            }
+            self.bit -= 8;
+        }
+    }
+    
+    fn put_code(&mut self, table: &EncodeTable, symbol: u8) {
+        self.putn(table.code[symbol as usize] as u32, table.length[symbol as usize] as u32);
+    }
+    
+    fn flush(&mut self) {
+        // pad with 1-bits
+        if self.bit > 0 {
+            self.putn(0x7F, 8 - self.bit);
+        }
+    }
+}
+
+fn category(value: i32) -> u32 {
+    32 - value.unsigned_abs().leading_zeros()
+}
+
+// The AC tables of Annex K stop at category 10. That covers every AC coefficient: level shifted
+// 8-bit samples give AC coefficients of at most about 930 in magnitude, below the 1024 where
+// category 11 starts, and quantizing only makes them smaller. The DC tables go up to category 11,
+// enough for the difference of two DC coefficients.
+fn pack_block(writer: &mut Writer, block: &[i32; 64], dcht: &EncodeTable, acht: &EncodeTable, dc: &mut i32) {
+    let diff = block[0] - *dc;
+    *dc = block[0];
+    let cat = category(diff);
+    writer.put_code(dcht, cat as u8);
+    if cat > 0 {
+        writer.putn(if diff < 0 {(diff - 1) as u32} else {diff as u32}, cat);
+    }
+    let mut run = 0;
+    for i in 1..64 {
+        let coeff = block[ZIGZAG[i] as usize];
+        if coeff == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            writer.put_code(acht, 0xF0); // ZRL
+            run -= 16;
+        }
+        let cat = category(coeff);
+        debug_assert!(cat <= 10);
+        writer.put_code(acht, ((run << 4) | cat) as u8);
+        writer.putn(if coeff < 0 {(coeff - 1) as u32} else {coeff as u32}, cat);
+        run = 0;
+    }
+    if run > 0 {
+        writer.put_code(acht, 0x00); // EOB
+    }
+}
+
+// cos((2x + 1)uπ/16) for every sample x and frequency u, computed once per image
+fn dct_cosines() -> [[f32; 8]; 8] {
+    let mut cosines = [[0f32; 8]; 8];
+    for (x, row) in cosines.iter_mut().enumerate() {
+        for (u, cosine) in row.iter_mut().enumerate() {
+            *cosine = (((2 * x + 1) * u) as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    cosines
+}
+
+fn forward_dct(block: &[f32; 64], qtable: &[u8; 64], cosines: &[[f32; 8]; 8]) -> [i32; 64] {
+    let mut temp = [0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for x in 0..8 {
+                sum += block[y * 8 + x] * cosines[x][u];
+            }
+            temp[y * 8 + u] = sum;
+        }
+    }
+    let mut result = [0i32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..8 {
+                sum += temp[y * 8 + u] * cosines[y][v];
+            }
+            let cu = if u == 0 {std::f32::consts::FRAC_1_SQRT_2} else {1.0};
+            let cv = if v == 0 {std::f32::consts::FRAC_1_SQRT_2} else {1.0};
+            let coeff = 0.25 * cu * cv * sum;
+            result[v * 8 + u] = (coeff / qtable[v * 8 + u] as f32).round() as i32;
+        }
+    }
+    result
+}
+
+fn scale_qtable(base: &[u8; 64], quality: u8) -> [u8; 64] {
+    let quality = clamp(quality as u32, 1, 100);
+    let scale = if quality < 50 {5000 / quality} else {200 - quality * 2};
+    let mut result = [0u8; 64];
+    for i in 0..64 {
+        result[i] = clamp((base[i] as u32 * scale + 50) / 100, 1, 255) as u8;
+    }
+    result
+}
+
+fn write_marker(dst: &mut Vec<u8>, marker: u16, data: &[u8]) {
+    dst.extend_from_slice(&marker.to_be_bytes());
+    dst.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+    dst.extend_from_slice(data);
+}
+
+fn write_dht(dst: &mut Vec<u8>, class_index: u8, bits: &[u8; 16], huffval: &[u8]) {
+    let mut data = vec![class_index];
+    data.extend_from_slice(bits);
+    data.extend_from_slice(huffval);
+    write_marker(dst, 0xFFC4, &data);
+}
+
+// fetch an 8x8 block of one channel, averaging `scale`x`scale` pixels and replicating edge pixels
+fn fetch_block(planes: &[Vec<f32>], channel: usize, width: usize, height: usize, x0: usize, y0: usize, scale: usize) -> [f32; 64] {
+    let mut block = [0f32; 64];
+    for i in 0..8 {
+        for k in 0..8 {
+            let mut sum = 0.0;
+            for si in 0..scale {
+                for sk in 0..scale {
+                    let x = (x0 + k * scale + sk).min(width - 1);
+                    let y = (y0 + i * scale + si).min(height - 1);
+                    sum += planes[channel][y * width + x];
                 }
-                #[allow(unused_assignments)]
-                let mut mb = 0;
-                for i in 0..mbheight - 1 {
-                    for k in 0..mbwidth - 1 {
-                        match itype {
-                            TYPE_Y => {draw_macroblock_y(&mut image, k * 8, i * 8, 8, 8, &coeffs[mb..mb + 64]); mb += 64;},
-                            TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, k * 16, i * 16, 16, 16, &coeffs[mb..mb + 384]); mb += 384;},
-                            TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, k * 16, i * 8, 16, 8, &coeffs[mb..mb + 256]); mb += 256;},
-                            TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, k * 8, i * 16, 8, 16, &coeffs[mb..mb + 256]); mb += 256;},
-                            TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, k * 8, i * 8, 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
-                            TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, k * 8, i * 8, 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
-                            _ => {},
-                        }
-                    }
-                    match itype {
-                        TYPE_Y => {draw_macroblock_y(&mut image, mbwidth * 8 - 8, i * 8, width - (mbwidth - 1) * 8, 8, &coeffs[mb..mb + 64]); mb += 64;},
-                        TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, mbwidth * 16 - 16, i * 16, width - (mbwidth - 1) * 16, 16, &coeffs[mb..mb + 384]); mb += 384;},
-                        TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, mbwidth * 16 - 16, i * 8, width - (mbwidth - 1) * 16, 8, &coeffs[mb..mb + 256]); mb += 256;},
-                        TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, mbwidth * 8 - 8, i * 16, width - (mbwidth - 1) * 8, 16, &coeffs[mb..mb + 256]); mb += 256;},
-                        TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, mbwidth * 8 - 8, i * 8, width - (mbwidth - 1) * 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
-                        TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, mbwidth * 8 - 8, i * 8, width - (mbwidth - 1) * 8, 8, &coeffs[mb..mb + 192]); mb += 192;},
-                        _ => {},
+            }
+            block[i * 8 + k] = sum / (scale * scale) as f32;
+        }
+    }
+    block
+}
+
+// convert to level shifted YCbCr planes
+fn ycbcr_planes(image: &ImageBuffer) -> Vec<Vec<f32>> {
+    let mut planes = vec![vec![0f32; image.width * image.height]; 3];
+    for (i, &c) in image.data.iter().enumerate().take(image.width * image.height) {
+        let r = ((c >> 16) & 255) as f32;
+        let g = ((c >> 8) & 255) as f32;
+        let b = (c & 255) as f32;
+        planes[0][i] = 0.299 * r + 0.587 * g + 0.114 * b - 128.0;
+        planes[1][i] = -0.168736 * r - 0.331264 * g + 0.5 * b;
+        planes[2][i] = 0.5 * r - 0.418688 * g - 0.081312 * b;
+    }
+    planes
+}
+
+/// Encode an image as baseline JPEG at quality 90 with 4:2:0 chroma subsampling.
+pub fn encode(image: &ImageBuffer) -> Result<Vec<u8>, String> {
+    encode_with(image, 90, Subsampling::YUV420)
+}
+
+/// Encode an image as baseline JFIF JPEG with the given quality (1-100) and chroma subsampling.
+pub fn encode_with(image: &ImageBuffer, quality: u8, subsampling: Subsampling) -> Result<Vec<u8>, String> {
+    let width = image.width;
+    let height = image.height;
+    if (width == 0) || (height == 0) || (width >= 65536) || (height >= 65536) {
+        return Err(format!("cannot encode JPEG of size {}x{}", width, height));
+    }
+    if image.data.len() < width * height {
+        return Err(format!("image data too small ({} < {})", image.data.len(), width * height));
+    }
+    
+    let planes = ycbcr_planes(image);
+    let qtable_y = scale_qtable(&QTABLE_Y, quality);
+    let qtable_c = scale_qtable(&QTABLE_C, quality);
+    let (samp, mbsize) = match subsampling {
+        Subsampling::YUV444 => (0x11u8, 8),
+        Subsampling::YUV420 => (0x22u8, 16),
+    };
+    
+    let mut dst: Vec<u8> = vec![0xFF, 0xD8];
+    write_marker(&mut dst, 0xFFE0, &[0x4A, 0x46, 0x49, 0x46, 0x00, 1, 1, 0, 0, 1, 0, 1, 0, 0]); // JFIF 1.1, no thumbnail
+    for (n, qtable) in [qtable_y, qtable_c].iter().enumerate() {
+        let mut data = vec![n as u8];
+        for k in 0..64 {
+            data.push(qtable[ZIGZAG[k] as usize]);
+        }
+        write_marker(&mut dst, 0xFFDB, &data);
+    }
+    let mut sof = vec![8];
+    sof.extend_from_slice(&(height as u16).to_be_bytes());
+    sof.extend_from_slice(&(width as u16).to_be_bytes());
+    sof.extend_from_slice(&[3, 1, samp, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    write_marker(&mut dst, 0xFFC0, &sof);
+    write_dht(&mut dst, 0x00, &DC_Y_BITS, &DC_Y_VALS);
+    write_dht(&mut dst, 0x10, &AC_Y_BITS, &AC_Y_VALS);
+    write_dht(&mut dst, 0x01, &DC_C_BITS, &DC_C_VALS);
+    write_dht(&mut dst, 0x11, &AC_C_BITS, &AC_C_VALS);
+    write_marker(&mut dst, 0xFFDA, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+    
+    let dcht_y = EncodeTable::new(&DC_Y_BITS, &DC_Y_VALS);
+    let acht_y = EncodeTable::new(&AC_Y_BITS, &AC_Y_VALS);
+    let dcht_c = EncodeTable::new(&DC_C_BITS, &DC_C_VALS);
+    let acht_c = EncodeTable::new(&AC_C_BITS, &AC_C_VALS);
+    let cosines = dct_cosines();
+    let mut writer = Writer::new(dst);
+    let mut dc = [0i32; 3];
+    for mby in 0..height.div_ceil(mbsize) {
+        for mbx in 0..width.div_ceil(mbsize) {
+            let x0 = mbx * mbsize;
+            let y0 = mby * mbsize;
+            for by in 0..mbsize / 8 {
+                for bx in 0..mbsize / 8 {
+                    let block = fetch_block(&planes, 0, width, height, x0 + bx * 8, y0 + by * 8, 1);
+                    pack_block(&mut writer, &forward_dct(&block, &qtable_y, &cosines), &dcht_y, &acht_y, &mut dc[0]);
+                }
+            }
+            for (channel, dc) in dc.iter_mut().enumerate().skip(1) {
+                let block = fetch_block(&planes, channel, width, height, x0, y0, mbsize / 8);
+                pack_block(&mut writer, &forward_dct(&block, &qtable_c, &cosines), &dcht_c, &acht_c, dc);
+            }
+        }
+    }
+    writer.flush();
+    let mut dst = writer.data;
+    dst.extend_from_slice(&[0xFF, 0xD9]);
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn test_image(width: usize, height: usize) -> ImageBuffer {
+        let mut image = ImageBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let r = (x * 255 / width) as u32;
+                let g = (y * 255 / height) as u32;
+                let b = (((x as f32 * 0.2).sin() * (y as f32 * 0.15).cos() * 100.0) + 128.0) as u32;
+                image.data[y * width + x] = 0xFF000000 | (r << 16) | (g << 8) | b;
+            }
+        }
+        image
+    }
+    
+    fn psnr(a: &ImageBuffer, b: &ImageBuffer) -> f64 {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        let mut error = 0.0;
+        for (ca, cb) in a.data.iter().zip(b.data.iter()) {
+            for shift in [0, 8, 16] {
+                let d = ((ca >> shift) & 255) as f64 - ((cb >> shift) & 255) as f64;
+                error += d * d;
+            }
+        }
+        let mse = error / (a.data.len() * 3) as f64;
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+    
+    #[test]
+    fn huffman_tables_complete() {
+        for (bits, vals) in [(&AC_Y_BITS, &AC_Y_VALS), (&AC_C_BITS, &AC_C_VALS)] {
+            assert_eq!(bits.iter().map( | b | *b as usize).sum::<usize>(), vals.len());
+            let mut seen = [false; 256];
+            for v in vals.iter() {
+                seen[*v as usize] = true;
+            }
+            for run in 0..16 {
+                for cat in 1..11 {
+                    assert!(seen[(run << 4) | cat]);
+                }
+            }
+            assert!(seen[0x00] && seen[0xF0]);
+        }
+    }
+    
+    #[test]
+    fn roundtrip_yuv444() {
+        let image = test_image(64, 48);
+        let data = encode_with(&image, 95, Subsampling::YUV444).unwrap();
+        assert_eq!(test(&data), Some((64, 48)));
+        let decoded = decode(&data).unwrap();
+        assert!(psnr(&image, &decoded) > 38.0);
+    }
+    
+    #[test]
+    fn roundtrip_yuv420() {
+        let image = test_image(80, 64);
+        let data = encode_with(&image, 90, Subsampling::YUV420).unwrap();
+        let decoded = decode(&data).unwrap();
+        assert!(psnr(&image, &decoded) > 32.0);
+    }
+    
+    #[test]
+    fn roundtrip_partial_macroblocks() {
+        let image = test_image(37, 29);
+        for subsampling in [Subsampling::YUV444, Subsampling::YUV420] {
+            let decoded = decode(&encode_with(&image, 90, subsampling).unwrap()).unwrap();
+            assert!(psnr(&image, &decoded) > 30.0);
+        }
+    }
+    
+    #[test]
+    fn quality_affects_size() {
+        let image = test_image(64, 64);
+        let low = encode_with(&image, 20, Subsampling::YUV420).unwrap();
+        let high = encode_with(&image, 95, Subsampling::YUV420).unwrap();
+        assert!(low.len() < high.len());
+        assert!(psnr(&image, &decode(&low).unwrap()) > 22.0);
+    }
+    
+    // minimal progressive encoder, producing the same quantized coefficients as
+    // `encode_with` so both files must decode to identical pixels
+    
+    struct ProgressiveWriter {
+        writer: Writer,
+        dcht: EncodeTable,
+        acht: EncodeTable,
+        eobrun: u32,
+        correction: Vec<u32>, // correction bits belonging to the pending EOB run
+        restarts: usize,
+    }
+    
+    impl ProgressiveWriter {
+        fn emit_eobrun(&mut self) {
+            if self.eobrun > 0 {
+                let nbits = 31 - self.eobrun.leading_zeros();
+                self.writer.put_code(&self.acht, (nbits << 4) as u8);
+                if nbits > 0 {
+                    self.writer.putn(self.eobrun, nbits);
+                }
+                self.eobrun = 0;
+                for bit in std::mem::take(&mut self.correction) {
+                    self.writer.putn(bit, 1);
+                }
+            }
+        }
+        
+        fn restart(&mut self) {
+            self.emit_eobrun();
+            self.writer.flush();
+            self.writer.data.extend_from_slice(&[0xFF, 0xD0 + (self.restarts & 7) as u8]);
+            self.restarts += 1;
+        }
+        
+        fn dc_first(&mut self, block: &[i32; 64], dc: &mut i32, al: u8) {
+            let value = block[0] >> al;
+            let diff = value - *dc;
+            *dc = value;
+            let cat = category(diff);
+            self.writer.put_code(&self.dcht, cat as u8);
+            if cat > 0 {
+                self.writer.putn(if diff < 0 {(diff - 1) as u32} else {diff as u32}, cat);
+            }
+        }
+        
+        fn ac_first(&mut self, block: &[i32; 64], ss: usize, se: usize, al: u8) {
+            let coeff = | k: usize | {
+                let c = block[ZIGZAG[k] as usize];
+                if c >= 0 {c >> al} else {-((-c) >> al)}
+            };
+            let mut run = 0;
+            for k in ss..=se {
+                let c = coeff(k);
+                if c == 0 {
+                    run += 1;
+                    continue;
+                }
+                self.emit_eobrun();
+                while run > 15 {
+                    self.writer.put_code(&self.acht, 0xF0);
+                    run -= 16;
+                }
+                let cat = category(c);
+                self.writer.put_code(&self.acht, ((run << 4) | cat) as u8);
+                self.writer.putn(if c < 0 {(c - 1) as u32} else {c as u32}, cat);
+                run = 0;
+            }
+            if run > 0 {
+                self.eobrun += 1;
+                if self.eobrun == 0x7FFF {
+                    self.emit_eobrun();
+                }
+            }
+        }
+        
+        fn ac_refine(&mut self, block: &[i32; 64], ss: usize, se: usize, al: u8) {
+            let mut absvalues = [0u32; 64];
+            let mut eob = 0;
+            for k in ss..=se {
+                absvalues[k] = block[ZIGZAG[k] as usize].unsigned_abs() >> al;
+                if absvalues[k] == 1 {
+                    eob = k;
+                }
+            }
+            let mut run = 0;
+            let mut bits: Vec<u32> = Vec::new();
+            for k in ss..=se {
+                let temp = absvalues[k];
+                if temp == 0 {
+                    run += 1;
+                    continue;
+                }
+                while (run > 15) && (k <= eob) {
+                    self.emit_eobrun();
+                    self.writer.put_code(&self.acht, 0xF0);
+                    run -= 16;
+                    for bit in bits.drain(..) {
+                        self.writer.putn(bit, 1);
                     }
                 }
-                for k in 0..mbwidth - 1 {
-                    match itype {
-                        TYPE_Y => {draw_macroblock_y(&mut image, k * 8, mbheight * 8 - 8, 8, mbheight * 8 - height, &coeffs[mb..mb + 64]); mb += 64;},
-                        TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, k * 16, mbheight * 16 - 16, 16, height - (mbheight - 1) * 16, &coeffs[mb..mb + 384]); mb += 384;},
-                        TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, k * 16, mbheight * 8 - 8, 16, height - (mbheight - 1) * 8, &coeffs[mb..mb + 256]); mb += 256;},
-                        TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, k * 8, mbheight * 16 - 16, 8, height - (mbheight - 1) * 16, &coeffs[mb..mb + 256]); mb += 256;},
-                        TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, k * 8, mbheight * 8 - 8, 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]); mb += 192;},
-                        TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, k * 8, mbheight * 8 - 8, 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]); mb += 192;},
-                        _ => {},
+                if temp > 1 {
+                    bits.push(temp & 1);
+                    continue;
+                }
+                self.emit_eobrun();
+                self.writer.put_code(&self.acht, ((run << 4) | 1) as u8);
+                self.writer.putn(if block[ZIGZAG[k] as usize] > 0 {1} else {0}, 1);
+                for bit in bits.drain(..) {
+                    self.writer.putn(bit, 1);
+                }
+                run = 0;
+            }
+            if (run > 0) || !bits.is_empty() {
+                self.eobrun += 1;
+                self.correction.extend(bits);
+                if (self.eobrun == 0x7FFF) || (self.correction.len() > 900) {
+                    self.emit_eobrun();
+                }
+            }
+        }
+    }
+    
+    fn encode_progressive(image: &ImageBuffer, quality: u8, subsampling: Subsampling, resint: usize) -> Vec<u8> {
+        let (width, height) = (image.width, image.height);
+        let planes = ycbcr_planes(image);
+        let qtables = [scale_qtable(&QTABLE_Y, quality), scale_qtable(&QTABLE_C, quality)];
+        let (samp, hv) = match subsampling {
+            Subsampling::YUV444 => (0x11u8, 1),
+            Subsampling::YUV420 => (0x22u8, 2),
+        };
+        let mbwidth = (width + hv * 8 - 1) / (hv * 8);
+        let mbheight = (height + hv * 8 - 1) / (hv * 8);
+        
+        // quantized blocks of every component, over the full macroblock grid
+        let cosines = dct_cosines();
+        let mut grids: Vec<(usize, usize, Vec<[i32; 64]>)> = Vec::new();
+        for c in 0..3 {
+            let f = if c == 0 {hv} else {1};
+            let (gw, gh) = (mbwidth * f, mbheight * f);
+            let scale = hv / f;
+            let mut blocks = Vec::new();
+            for by in 0..gh {
+                for bx in 0..gw {
+                    let block = fetch_block(&planes, c, width, height, bx * 8 * scale, by * 8 * scale, scale);
+                    blocks.push(forward_dct(&block, &qtables[if c == 0 {0} else {1}], &cosines));
+                }
+            }
+            grids.push((gw, gh, blocks));
+        }
+        
+        // a single table with every symbol (including EOB runs) in 8 bits
+        let mut ac_vals = Vec::new();
+        for run in 0..15 {
+            ac_vals.push(run << 4);
+        }
+        for run in 0..16 {
+            for cat in 1..11 {
+                ac_vals.push((run << 4) | cat);
+            }
+        }
+        ac_vals.push(0xF0);
+        let mut ac_bits = [0u8; 16];
+        ac_bits[7] = ac_vals.len() as u8;
+        let mut dc_bits = [0u8; 16];
+        dc_bits[3] = 12;
+        
+        let mut dst: Vec<u8> = vec![0xFF, 0xD8];
+        for (n, qtable) in qtables.iter().enumerate() {
+            let mut data = vec![n as u8];
+            for k in 0..64 {
+                data.push(qtable[ZIGZAG[k] as usize]);
+            }
+            write_marker(&mut dst, 0xFFDB, &data);
+        }
+        let mut sof = vec![8];
+        sof.extend_from_slice(&(height as u16).to_be_bytes());
+        sof.extend_from_slice(&(width as u16).to_be_bytes());
+        sof.extend_from_slice(&[3, 1, samp, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        write_marker(&mut dst, 0xFFC2, &sof);
+        write_dht(&mut dst, 0x00, &dc_bits, &DC_Y_VALS);
+        write_dht(&mut dst, 0x10, &ac_bits, &ac_vals);
+        if resint != 0 {
+            write_marker(&mut dst, 0xFFDD, &(resint as u16).to_be_bytes());
+        }
+        
+        let mut pw = ProgressiveWriter {
+            writer: Writer::new(dst),
+            dcht: EncodeTable::new(&dc_bits, &DC_Y_VALS),
+            acht: EncodeTable::new(&ac_bits, &ac_vals),
+            eobrun: 0,
+            correction: Vec::new(),
+            restarts: 0,
+        };
+        
+        // DC scans are interleaved, AC scans visit the blocks covering each component only
+        for (ss, se, ah, al, c) in [(0, 0, 0, 1, 3), (1, 5, 0, 2, 0), (1, 63, 0, 1, 1), (1, 63, 0, 1, 2), (6, 63, 0, 2, 0), (0, 0, 1, 0, 3), (1, 63, 2, 1, 0), (1, 63, 1, 0, 0), (1, 63, 1, 0, 1), (1, 63, 1, 0, 2)] {
+            let mut header = Vec::new();
+            if c == 3 {
+                header.extend_from_slice(&[3, 1, 0x00, 2, 0x00, 3, 0x00]);
+            }
+            else {
+                header.extend_from_slice(&[1, c as u8 + 1, 0x00]);
+            }
+            header.extend_from_slice(&[ss as u8, se as u8, (ah << 4) | al]);
+            write_marker(&mut pw.writer.data, 0xFFDA, &header);
+            pw.restarts = 0;
+            let mut dc = [0i32; 3];
+            let mut units = 0;
+            if c == 3 {
+                for mby in 0..mbheight {
+                    for mbx in 0..mbwidth {
+                        if (resint != 0) && (units != 0) && ((units % resint) == 0) {
+                            pw.restart();
+                            dc = [0; 3];
+                        }
+                        for (c, (gw, _, blocks)) in grids.iter().enumerate() {
+                            let f = if c == 0 {hv} else {1};
+                            for v in 0..f {
+                                for h in 0..f {
+                                    let block = &blocks[(mby * f + v) * gw + mbx * f + h];
+                                    if ah == 0 {
+                                        pw.dc_first(block, &mut dc[c], al);
+                                    }
+                                    else {
+                                        pw.writer.putn(((block[0] >> al) & 1) as u32, 1);
+                                    }
+                                }
+                            }
+                        }
+                        units += 1;
                     }
                 }
-                match itype {
-                    TYPE_Y => {draw_macroblock_y(&mut image, mbwidth * 8 - 8, mbheight * 8 - 8, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 64]);},
-                    TYPE_YUV420 => {draw_macroblock_yuv420(&mut image, mbwidth * 16 - 16, mbheight * 16 - 16, width - (mbwidth - 1) * 16, height - (mbheight - 1) * 16, &coeffs[mb..mb + 384]);},
-                    TYPE_YUV422 => {draw_macroblock_yuv422(&mut image, mbwidth * 16 - 16, mbheight * 8 - 8, width - (mbwidth - 1) * 16, height - (mbheight - 1) * 8, &coeffs[mb..mb + 256]);},
-                    TYPE_YUV440 => {draw_macroblock_yuv440(&mut image, mbwidth * 8 - 8, mbheight * 16 - 16, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 16, &coeffs[mb..mb + 256]);},
-                    TYPE_YUV444 => {draw_macroblock_yuv444(&mut image, mbwidth * 8 - 8, mbheight * 8 - 8, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]);},
-                    TYPE_RGB444 => {draw_macroblock_rgb444(&mut image, mbwidth * 8 - 8, mbheight * 8 - 8, width - (mbwidth - 1) * 8, height - (mbheight - 1) * 8, &coeffs[mb..mb + 192]);},
-                    _ => {},
+            }
+            else {
+                let (gw, _, blocks) = &grids[c];
+                let f = if c == 0 {hv} else {1};
+                let bwidth = ((width * f + hv - 1) / hv + 7) / 8;
+                let bheight = ((height * f + hv - 1) / hv + 7) / 8;
+                for by in 0..bheight {
+                    for bx in 0..bwidth {
+                        if (resint != 0) && (units != 0) && ((units % resint) == 0) {
+                            pw.restart();
+                        }
+                        let block = &blocks[by * gw + bx];
+                        if ah == 0 {
+                            pw.ac_first(block, ss, se, al);
+                        }
+                        else {
+                            pw.ac_refine(block, ss, se, al);
+                        }
+                        units += 1;
+                    }
                 }
-                return Ok(image);
-            },
-            
-            /*_ => {
-                return Err("Invalid JPEG 10".to_string());
-            },*/
+            }
+            pw.emit_eobrun();
+            pw.writer.flush();
+        }
+        let mut dst = pw.writer.data;
+        dst.extend_from_slice(&[0xFF, 0xD9]);
+        dst
+    }
+    
+    #[test]
+    fn progressive_matches_baseline() {
+        for (width, height, subsampling) in [(64, 48, Subsampling::YUV444), (37, 29, Subsampling::YUV420), (80, 64, Subsampling::YUV420)] {
+            let image = test_image(width, height);
+            let baseline = decode(&encode_with(&image, 85, subsampling).unwrap()).unwrap();
+            let data = encode_progressive(&image, 85, subsampling, 0);
+            assert_eq!(test(&data), Some((width, height)));
+            let progressive = decode(&data).unwrap();
+            assert!(progressive.data == baseline.data);
+        }
+    }
+    
+    #[test]
+    fn progressive_with_restart_markers() {
+        let image = test_image(72, 56);
+        let baseline = decode(&encode_with(&image, 75, Subsampling::YUV420).unwrap()).unwrap();
+        for resint in [1, 3, 7] {
+            let progressive = decode(&encode_progressive(&image, 75, Subsampling::YUV420, resint)).unwrap();
+            assert!(progressive.data == baseline.data);
+        }
+    }
+    
+    #[test]
+    fn truncated_data_does_not_panic() {
+        let image = test_image(48, 40);
+        for data in [encode_with(&image, 80, Subsampling::YUV420).unwrap(), encode_progressive(&image, 80, Subsampling::YUV420, 2)] {
+            for len in (4..data.len()).step_by(37) {
+                let _ = decode(&data[0..len]);
+            }
+        }
+    }
+    
+    #[test]
+    fn exif_orientation() {
+        let image = test_image(40, 24);
+        let data = encode_with(&image, 95, Subsampling::YUV444).unwrap();
+        let upright = decode(&data).unwrap();
+        // APP1 with a big endian TIFF header and a single IFD0 entry
+        let mut exif = b"Exif\0\0MM\0\x2A\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0".to_vec();
+        let mut rotated = vec![0xFF, 0xD8];
+        write_marker(&mut rotated, 0xFFE1, &exif);
+        rotated.extend_from_slice(&data[2..]);
+        assert_eq!(orientation(&rotated), Some(Orientation::Rotate90));
+        assert_eq!(test(&rotated), Some((24, 40)));
+        let decoded = decode(&rotated).unwrap();
+        assert_eq!((decoded.width, decoded.height), (24, 40));
+        // the bottom left pixel ends up in the top left corner
+        assert_eq!(decoded.data[0], upright.data[23 * 40]);
+        assert!(decoded.data == decode(&data).unwrap().oriented(Orientation::Rotate90).data);
+        exif[25] = 0x03;
+        let mut flipped = vec![0xFF, 0xD8];
+        write_marker(&mut flipped, 0xFFE1, &exif);
+        flipped.extend_from_slice(&data[2..]);
+        assert_eq!(decode(&flipped).unwrap().data[0], upright.data[40 * 24 - 1]);
+    }
+    
+    #[test]
+    fn zero_sampling_factor() {
+        for samp in [0x01u8, 0x10, 0x00, 0x51] {
+            let mut data = vec![0xFF, 0xD8];
+            // SOF0, 8 bit, 8x8, three components with the luma sampling factors under test
+            write_marker(&mut data, 0xFFC0, &[8, 0, 8, 0, 8, 3, 1, samp, 0, 2, 0x11, 1, 3, 0x11, 1]);
+            data.extend_from_slice(&[0xFF, 0xD9]);
+            assert_eq!(decode(&data).err(), Some("Invalid JPEG 5".to_string()));
         }
-        sp += length + 2;
     }
-    Err("Invalid JPEG 11".to_string())
-}
-
-pub fn encode(_image: &ImageBuffer) -> Result<Vec<u8>, String> {
-    Err("not implemented yet".to_string())
 }
@@ -8,4 +8,5 @@ pub use image::*;
 pub mod bmp;
 pub mod png;
 pub mod jpeg;
+pub mod gif;
 
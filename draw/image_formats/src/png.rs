@@ -1,7 +1,17 @@
 // image_formats::png
 // by Desmond Germans, 2019
 
-use crate::ImageBuffer;
+use crate::{ImageBuffer, ImageFrame, AnimatedImage};
+use makepad_miniz::{
+    compress_to_vec_zlib,
+    decompress_to_vec_zlib,
+    crc32::{crc32, Crc32},
+    inflate::stream::{self, InflateState},
+    DataFormat,
+    MZError,
+    MZFlush,
+    MZStatus,
+};
 
 // Inflate algorithm
 const LITLEN_LENGTH: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
@@ -505,7 +515,7 @@ fn decode_pixels(dst: &mut [u32], src: &[u8], width: usize, height: usize, strid
                 if (width & 7) != 0 {
                     let d = src[sp];
                     sp += 1;
-                    for i in 0..8 {
+                    for i in 0..(width & 7) {
                         let l = if (d & (0x80 >> i)) != 0 {1.0} else {0.0};
                         dst[(y0 + y * dy) * stride + x0 + ((width & 0xFFFFFFF8) + i) * dx] = make_lf(l, gamma);
                     }
@@ -754,8 +764,61 @@ pub fn test(src: &[u8]) -> Option<(usize, usize)> {
     None
 }
 
-pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
-    if (src[0] != 0x89) ||
+// row size in bytes and bytes per complete pixel (for filtering) of an image type
+fn stride_bpp(itype: u16, width: usize) -> Option<(usize, usize)> {
+    match itype {
+        TYPE_L1 | TYPE_C1 => Some((width.div_ceil(8), 1)),
+        TYPE_L2 | TYPE_C2 => Some((width.div_ceil(4), 1)),
+        TYPE_L4 | TYPE_C4 => Some((width.div_ceil(2), 1)),
+        TYPE_L8 | TYPE_C8 => Some((width, 1)),
+        TYPE_RGB8 => Some((width * 3, 3)),
+        TYPE_LA8 => Some((width * 2, 2)),
+        TYPE_RGBA8 => Some((width * 4, 4)),
+        TYPE_L16 => Some((width * 2, 2)),
+        TYPE_RGB16 => Some((width * 6, 6)),
+        TYPE_LA16 => Some((width * 4, 4)),
+        TYPE_RGBA16 => Some((width * 8, 8)),
+        _ => None,
+    }
+}
+
+fn is_palette_type(itype: u16) -> bool {
+    (itype == TYPE_C1) || (itype == TYPE_C2) || (itype == TYPE_C4) || (itype == TYPE_C8)
+}
+
+// APNG frame control (fcTL)
+#[derive(Clone, Copy)]
+struct FrameControl {
+    width: usize,
+    height: usize,
+    x_offset: usize,
+    y_offset: usize,
+    delay: f64,
+    dispose_op: u8,
+    blend_op: u8,
+}
+
+const APNG_DISPOSE_OP_BACKGROUND: u8 = 1;
+const APNG_DISPOSE_OP_PREVIOUS: u8 = 2;
+const APNG_BLEND_OP_OVER: u8 = 1;
+
+// everything read from the chunks of a PNG file
+struct Png {
+    width: usize,
+    height: usize,
+    itype: u16,
+    interlace: u8,
+    palette: [u32; 256],
+    gamma: f32,
+    zipped_data: Vec<u8>, // concatenated IDAT chunks
+    num_plays: Option<u32>, // from acTL, if this is an APNG
+    idat_is_frame: bool, // the default image is the first frame of the animation
+    frames: Vec<(FrameControl, Vec<u8>)>, // frame controls with their concatenated fdAT data (or IDAT)
+}
+
+fn parse(src: &[u8]) -> Result<Png, String> {
+    if (src.len() < 8) ||
+    (src[0] != 0x89) ||
     (src[1] != 0x50) ||
     (src[2] != 0x4E) ||
     (src[3] != 0x47) ||
@@ -765,70 +828,64 @@ pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
     (src[7] != 0x0A) {
         return Err("invalid PNG".to_string());
     }
+    let mut png = Png {
+        width: 0,
+        height: 0,
+        itype: 0,
+        interlace: 0,
+        palette: [0; 256],
+        gamma: 1.0,
+        zipped_data: Vec::new(),
+        num_plays: None,
+        idat_is_frame: false,
+        frames: Vec::new(),
+    };
     let mut sp: usize = 8;
-    let mut width: usize = 0;
-    let mut height: usize = 0;
-    let mut itype: u16 = 0;
-    #[allow(unused_assignments)]
-    let mut compression: u8 = 0;
-    #[allow(unused_assignments)]
-    let mut filter: u8 = 0;
-    let mut interlace: u8 = 0;
-    let mut stride: usize = 0;
-    let mut bpp: usize = 0;
+    let mut ihdr_found = false;
     let mut need_plte = false;
     let mut plte_present = false;
-    let mut zipped_data: Vec<u8> = vec![0; src.len()];
-    let mut dp: usize = 0;
     let mut idat_found = false;
     let mut iend_found = false;
-    let mut palette: [u32; 256] = [0; 256];
     let mut _background: u32 = 0xFF000000;
-    let mut gamma: f32 = 1.0;
-    while sp < src.len() {
+    while sp + 8 <= src.len() {
         let chunk_length = from_be32(&src[sp..sp + 4]) as usize;
         sp += 4;
         let chunk_type = from_be32(&src[sp..sp + 4]);
         sp += 4;
+        if sp + chunk_length > src.len() {
+            return Err("Invalid PNG (chunk exceeds file)".to_string());
+        }
+        let chunk = &src[sp..sp + chunk_length];
         match chunk_type {
             0x49484452 => { // IHDR
-                width = from_be32(&src[sp..]) as usize;
-                height = from_be32(&src[sp + 4..]) as usize;
-                itype = from_be16(&src[sp + 8..]);
-                compression = src[sp + 10];
-                filter = src[sp + 11];
-                interlace = src[sp + 12];
-                if (width >= 65536) ||
-                (height >= 65536) ||
+                if chunk_length < 13 {
+                    return Err("Invalid PNG".to_string());
+                }
+                png.width = from_be32(&chunk[0..]) as usize;
+                png.height = from_be32(&chunk[4..]) as usize;
+                png.itype = from_be16(&chunk[8..]);
+                let compression = chunk[10];
+                let filter = chunk[11];
+                png.interlace = chunk[12];
+                if (png.width == 0) ||
+                (png.height == 0) ||
+                (png.width >= 65536) ||
+                (png.height >= 65536) ||
                 (compression != 0) ||
                 (filter != 0) ||
-                (interlace > 1) {
+                (png.interlace > 1) ||
+                stride_bpp(png.itype, png.width).is_none() {
                     return Err("Invalid PNG".to_string());
                 }
-                match itype {
-                    TYPE_L1 => {stride = (width + 7) / 8; bpp = 1;},
-                    TYPE_C1 => {stride = (width + 7) / 8; bpp = 1; need_plte = true;},
-                    TYPE_L2 => {stride = (width + 3) / 4; bpp = 1;},
-                    TYPE_C2 => {stride = (width + 3) / 4; bpp = 1; need_plte = true;},
-                    TYPE_L4 => {stride = (width + 1) / 2; bpp = 1;},
-                    TYPE_C4 => {stride = (width + 1) / 2; bpp = 1; need_plte = true;},
-                    TYPE_L8 => {stride = width; bpp = 1;},
-                    TYPE_RGB8 => {stride = width * 3; bpp = 3;},
-                    TYPE_C8 => {stride = width; bpp = 1; need_plte = true;},
-                    TYPE_LA8 => {stride = width * 2; bpp = 2;},
-                    TYPE_RGBA8 => {stride = width * 4; bpp = 4;},
-                    TYPE_L16 => {stride = width * 2; bpp = 2;},
-                    TYPE_RGB16 => {stride = width * 6; bpp = 6;},
-                    TYPE_LA16 => {stride = width * 2; bpp = 4;},
-                    TYPE_RGBA16 => {stride = width * 4; bpp = 8;},
-                    _ => {return Err("Invalid PNG".to_string());}
-                }
-                sp += chunk_length;
+                need_plte = is_palette_type(png.itype);
+                ihdr_found = true;
             },
             0x49444154 => { // IDAT
-                zipped_data[dp..dp + chunk_length].copy_from_slice(&src[sp..sp + chunk_length]);
-                sp += chunk_length;
-                dp += chunk_length;
+                png.zipped_data.extend_from_slice(chunk);
+                if !idat_found && (png.frames.len() == 1) {
+                    // an fcTL before the first IDAT makes the default image part of the animation
+                    png.idat_is_frame = true;
+                }
                 idat_found = true;
             },
             0x49454E44 => { // IEND
@@ -841,103 +898,113 @@ pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
                     return Err("Invalid PNG".to_string());
                 }
                 for i in 0..(chunk_length / 3) {
-                    let r = src[sp];
-                    let g = src[sp + 1];
-                    let b = src[sp + 2];
-                    sp += 3;
-                    palette[i] = 0xFF000000 | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
+                    let r = chunk[i * 3];
+                    let g = chunk[i * 3 + 1];
+                    let b = chunk[i * 3 + 2];
+                    png.palette[i] = 0xFF000000 | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
                 }
             },
             0x624B4744 => { // bKGD
-                if (itype == TYPE_C1) || (itype == TYPE_C2) || (itype == TYPE_C4) || (itype == TYPE_C8) {
-                    _background = palette[src[sp] as usize];
+                if is_palette_type(png.itype) && (chunk_length >= 1) {
+                    _background = png.palette[chunk[0] as usize];
                 }
-                else if (itype == TYPE_L1) || (itype == TYPE_L2) || (itype == TYPE_L4) || (itype == TYPE_L8) || (itype == TYPE_LA8) || (itype == TYPE_L16) || (itype == TYPE_LA16) {
-                    let level = src[sp];
-                    _background = 0xFF000000 | ((level as u32) << 16) | ((level as u32) << 8) | (level as u32);
+                else if (png.itype == TYPE_L1) || (png.itype == TYPE_L2) || (png.itype == TYPE_L4) || (png.itype == TYPE_L8) || (png.itype == TYPE_LA8) || (png.itype == TYPE_L16) || (png.itype == TYPE_LA16) {
+                    if chunk_length >= 1 {
+                        let level = chunk[0];
+                        _background = 0xFF000000 | ((level as u32) << 16) | ((level as u32) << 8) | (level as u32);
+                    }
                 }
-                else {
-                    let r = src[sp];
-                    let g = src[sp + 2];
-                    let b = src[sp + 4];
+                else if chunk_length >= 6 {
+                    let r = chunk[0];
+                    let g = chunk[2];
+                    let b = chunk[4];
                     _background = 0xFF000000 | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
                 }
-                sp += chunk_length;
+            },
+            0x6163544C if chunk_length >= 8 => { // acTL
+                png.num_plays = Some(from_be32(&chunk[4..]));
+            },
+            0x6663544C => { // fcTL
+                if chunk_length < 26 {
+                    return Err("Invalid APNG (fcTL)".to_string());
+                }
+                let delay_num = from_be16(&chunk[20..]) as f64;
+                let delay_den = from_be16(&chunk[22..]) as f64;
+                let fc = FrameControl {
+                    width: from_be32(&chunk[4..]) as usize,
+                    height: from_be32(&chunk[8..]) as usize,
+                    x_offset: from_be32(&chunk[12..]) as usize,
+                    y_offset: from_be32(&chunk[16..]) as usize,
+                    delay: delay_num / if delay_den == 0.0 {100.0} else {delay_den},
+                    dispose_op: chunk[24],
+                    blend_op: chunk[25],
+                };
+                if (fc.width == 0) || (fc.height == 0) || (fc.x_offset + fc.width > png.width) || (fc.y_offset + fc.height > png.height) {
+                    return Err("Invalid APNG (frame outside of image)".to_string());
+                }
+                png.frames.push((fc, Vec::new()));
+            },
+            0x66644154 => { // fdAT
+                if let Some((_, data)) = png.frames.last_mut() {
+                    if chunk_length >= 4 {
+                        data.extend_from_slice(&chunk[4..]);
+                    }
+                }
             },
             0x6348524D => { // cHRM
-                //println!("cHRM {}",chunk_length);
                 // chromaticity coordinates of display
-                sp += chunk_length;
             },
             // dSIG (digital signature)
             0x65584966 => { // eXIf
-                //println!("eXIf {}",chunk_length);
                 // EXIF metadata
-                sp += chunk_length;
             },
-            0x67414D41 => { // gAMA
-                let level = ((src[sp] as u32) << 24) | ((src[sp + 1] as u32) << 16) | ((src[sp + 2] as u32) << 8) | (src[sp + 3] as u32);
-                gamma = (level as f32) / 100000.0;
-                sp += chunk_length;
+            0x67414D41 if chunk_length >= 4 => { // gAMA
+                let level = from_be32(chunk);
+                png.gamma = (level as f32) / 100000.0;
             },
             0x68495354 => { // hIST
-                //println!("hIST {}",chunk_length);
                 // histogram
-                sp += chunk_length;
             },
             // iCCP (ICC color profile)
             0x69545874 => { // iTXt
-                //println!("iTXt {}",chunk_length);
                 // UTF-8 text
-                sp += chunk_length;
             },
             0x70485973 => { // pHYs
-                //println!("pHYs {}",chunk_length);
                 // pixel aspect ratio
-                sp += chunk_length;
             },
             0x73424954 => { // sBIT
-                //println!("sBIT {}",chunk_length);
                 // color accuracy
-                sp += chunk_length;
             },
             0x73504C54 => { // sPLT
-                //println!("sPLT {}",chunk_length);
                 // palette in case colors are not available
-                sp += chunk_length;
             },
             // sRGB (sRGB colorspace)
             // sTER (stereo)
             0x74455874 => { // tEXt
-                //println!("tEXt {}",chunk_length);
                 // text in ISO/IEC 8859-1
-                sp += chunk_length;
             },
             0x74494D45 => { // tIME
-                //println!("tIME {}",chunk_length);
                 // time of last change to image
-                sp += chunk_length;
             },
-            0x74524E53 => { // tRNS
-                //println!("tRNS {}",chunk_length);
-                // transparency information
-                sp += chunk_length;
+            0x74524E53 if is_palette_type(png.itype) => { // tRNS
+                // transparency information, only palette alpha is supported
+                for (color, alpha) in png.palette.iter_mut().zip(chunk) {
+                    *color = (*color & 0x00FFFFFF) | ((*alpha as u32) << 24);
+                }
             }
             0x7A545874 => { // zTXt
-                //println!("zTXt {}",chunk_length);
                 // compressed text
-                sp += chunk_length;
             },
             _ => { // anything else just ignore
                 //println!("unknown chunk: {:02X} {:02X} {:02X} {:02X}",chunk_type >> 24,(chunk_type >> 16) & 255,(chunk_type >> 8) & 255,chunk_type & 255);
-                sp += chunk_length;
             },
         }
+        sp += chunk_length;
         sp += 4; // also skip the CRC
     }
     
-    // sanity check the palette
-    if need_plte && !plte_present {
+    // sanity check the header and palette
+    if !ihdr_found || (need_plte && !plte_present) {
         return Err("Invalid PNG".to_string());
     }
     
@@ -946,6 +1013,23 @@ pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
         return Err("Invalid PNG".to_string());
     }
     
+    if png.idat_is_frame {
+        png.frames[0].1 = png.zipped_data.clone();
+    }
+    Ok(png)
+}
+
+fn decode_frame(zipped_data: &[u8], width: usize, height: usize, itype: u16, interlace: u8, palette: &[u32; 256], gamma: f32) -> Result<ImageBuffer, String> {
+    let (stride, bpp) = match stride_bpp(itype, width) {
+        Some(stride_bpp) => stride_bpp,
+        None => {return Err("Invalid PNG".to_string());}
+    };
+    if zipped_data.len() < 6 {
+        return Err("Invalid PNG (no image data)".to_string());
+    }
+    // the inflater may look a few bytes past the end of corrupt streams
+    let mut zipped_data = zipped_data.to_vec();
+    zipped_data.extend_from_slice(&[0; 8]);
     if interlace == 1 {
         let ax0: [usize; 7] = [0, 4, 0, 2, 0, 1, 0];
         let ay0: [usize; 7] = [0, 0, 4, 0, 2, 0, 1];
@@ -957,86 +1041,1035 @@ pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
         let mut apresent: [bool; 7] = [false; 7];
         let mut adsize: [usize; 7] = [0; 7];
         let mut total_dsize = 0;
-        //println!("size: {}x{}",width,height);
         for i in 0..7 {
             awidth[i] = (width + adx[i] - ax0[i] - 1) / adx[i];
             aheight[i] = (height + ady[i] - ay0[i] - 1) / ady[i];
-            astride[i] = match itype {
-                TYPE_L1 => {(awidth[i] + 7) / 8},
-                TYPE_C1 => {(awidth[i] + 7) / 8},
-                TYPE_L2 => {(awidth[i] + 3) / 4},
-                TYPE_C2 => {(awidth[i] + 3) / 4},
-                TYPE_L4 => {(awidth[i] + 1) / 2},
-                TYPE_C4 => {(awidth[i] + 1) / 2},
-                TYPE_L8 => {awidth[i]},
-                TYPE_RGB8 => {awidth[i] * 3},
-                TYPE_C8 => {awidth[i]},
-                TYPE_LA8 => {awidth[i] * 2},
-                TYPE_RGBA8 => {awidth[i] * 4},
-                TYPE_L16 => {awidth[i] * 2},
-                TYPE_RGB16 => {awidth[i] * 6},
-                TYPE_LA16 => {awidth[i] * 4},
-                TYPE_RGBA16 => {awidth[i] * 8},
-                _ => {0},
-            };
+            astride[i] = stride_bpp(itype, awidth[i]).map_or(0, | (stride, _) | stride);
             apresent[i] = (awidth[i] != 0) && (aheight[i] != 0);
             adsize[i] = if apresent[i] {(astride[i] + 1) * aheight[i]} else {0};
             total_dsize += adsize[i];
-            //println!("{}: size {}x{}, offset {},{}, step {},{}",i,awidth[i],aheight[i],ax0[i],ay0[i],adx[i],ady[i]);
         }
-        let filtered_data = match inflate(&zipped_data, total_dsize) {
-            Ok(data) => {data},
-            Err(msg) => {return Err(msg);},
-        };
+        let filtered_data = inflate(&zipped_data, total_dsize)?;
         let mut sp = 0;
         let mut result = ImageBuffer::new(width, height);
         
         for i in 0..7 {
             if apresent[i] {
                 let raw_data = unfilter(&filtered_data[sp..sp + adsize[i]], aheight[i], astride[i], bpp);
-                decode_pixels(&mut result.data, &raw_data, awidth[i], aheight[i], width, ax0[i], ay0[i], adx[i], ady[i], itype as u16, &palette, gamma);
+                decode_pixels(&mut result.data, &raw_data, awidth[i], aheight[i], width, ax0[i], ay0[i], adx[i], ady[i], itype, palette, gamma);
                 sp += adsize[i];
             }
         }
         Ok(result)
-    } else
-    {
-        //let after0 = Instant::now();
-        
-        let filtered_data = match inflate(&zipped_data, (stride + 1) * height) {
-            Ok(data) => {data},
-            Err(msg) => {return Err(msg);},
+    }
+    else {
+        let filtered_data = inflate(&zipped_data, (stride + 1) * height)?;
+        let raw_data = unfilter(&filtered_data, height, stride, bpp);
+        let mut result = ImageBuffer::new(width, height);
+        decode_pixels(&mut result.data, &raw_data, width, height, width, 0, 0, 1, 1, itype, palette, gamma);
+        Ok(result)
+    }
+}
+
+pub fn decode(src: &[u8]) -> Result<ImageBuffer, String> {
+    let png = parse(src)?;
+    decode_frame(&png.zipped_data, png.width, png.height, png.itype, png.interlace, &png.palette, png.gamma)
+}
+
+// alpha composite src over dst, both non-premultiplied ARGB
+fn blend_over(dst: u32, src: u32) -> u32 {
+    let sa = src >> 24;
+    if sa == 255 {
+        return src;
+    }
+    if sa == 0 {
+        return dst;
+    }
+    let da = dst >> 24;
+    let oa = sa * 255 + da * (255 - sa); // scaled by 255
+    let channel = | shift: u32 | {
+        let sc = (src >> shift) & 255;
+        let dc = (dst >> shift) & 255;
+        ((sc * sa * 255 + dc * da * (255 - sa) + oa / 2) / oa) << shift
+    };
+    (((oa + 127) / 255) << 24) | channel(16) | channel(8) | channel(0)
+}
+
+/// Decode all frames of an APNG. A plain PNG is returned as a single frame animation.
+pub fn decode_animated(src: &[u8]) -> Result<AnimatedImage, String> {
+    let png = parse(src)?;
+    let num_plays = match png.num_plays {
+        Some(num_plays) if !png.frames.is_empty() => num_plays,
+        _ => {
+            let image = decode_frame(&png.zipped_data, png.width, png.height, png.itype, png.interlace, &png.palette, png.gamma)?;
+            return Ok(AnimatedImage {
+                width: png.width,
+                height: png.height,
+                frames: vec![ImageFrame {image, delay: 0.0}],
+                loop_count: 1,
+            });
+        }
+    };
+    let mut canvas = ImageBuffer::new(png.width, png.height);
+    let mut frames = Vec::new();
+    for (index, (fc, data)) in png.frames.iter().enumerate() {
+        let image = decode_frame(data, fc.width, fc.height, png.itype, png.interlace, &png.palette, png.gamma)?;
+        let previous = if fc.dispose_op == APNG_DISPOSE_OP_PREVIOUS {Some(canvas.data.clone())} else {None};
+        for y in 0..fc.height {
+            for x in 0..fc.width {
+                let dp = (fc.y_offset + y) * png.width + fc.x_offset + x;
+                let c = image.data[y * fc.width + x];
+                canvas.data[dp] = if fc.blend_op == APNG_BLEND_OP_OVER {blend_over(canvas.data[dp], c)} else {c};
+            }
+        }
+        frames.push(ImageFrame {
+            image: ImageBuffer {width: png.width, height: png.height, data: canvas.data.clone()},
+            delay: fc.delay,
+        });
+        // a first frame that disposes to previous is disposed to background instead
+        if (fc.dispose_op == APNG_DISPOSE_OP_BACKGROUND) || ((fc.dispose_op == APNG_DISPOSE_OP_PREVIOUS) && (index == 0)) {
+            for y in 0..fc.height {
+                let dp = (fc.y_offset + y) * png.width + fc.x_offset;
+                canvas.data[dp..dp + fc.width].fill(0);
+            }
+        }
+        else if let Some(previous) = previous {
+            canvas.data = previous;
+        }
+    }
+    Ok(AnimatedImage {
+        width: png.width,
+        height: png.height,
+        frames,
+        loop_count: num_plays,
+    })
+}
+
+// Streaming decoder
+
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+// area each Adam7 pass pixel stands in for until later passes fill it in
+const ADAM7_BLOCK: [(usize, usize); 7] = [(8, 8), (4, 8), (4, 4), (2, 4), (2, 2), (1, 2), (1, 1)];
+
+/// Color space information from the ancillary chunks in front of the image data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColorInfo {
+    /// Encoding gamma from gAMA, for instance 0.45455.
+    pub gamma: Option<f32>,
+    /// Rendering intent from sRGB, present if the image is in the sRGB color space.
+    pub srgb_intent: Option<u8>,
+    /// Profile name and decompressed profile data from iCCP.
+    pub icc_profile: Option<(String, Vec<u8>)>,
+    /// White point and red, green and blue primaries as x, y pairs from cHRM.
+    pub chromaticities: Option<[f32; 8]>,
+}
+
+/// Image header, available once IHDR has been decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngInfo {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub interlaced: bool,
+    /// Only complete once the first row has been decoded.
+    pub color: ColorInfo,
+}
+
+/// A decoded row of pixels, RGBA with 16 bits per channel.
+pub struct PngRow<'a> {
+    /// Adam7 pass (0..7), always 0 for non-interlaced images.
+    pub pass: usize,
+    /// Image row.
+    pub y: usize,
+    /// Image column of the first pixel.
+    pub x0: usize,
+    /// Distance between pixels in image columns.
+    pub dx: usize,
+    pub samples: &'a [u16],
+    interlaced: bool,
+}
+
+impl<'a> PngRow<'a> {
+    pub fn width(&self) -> usize {
+        self.samples.len() / 4
+    }
+    
+    /// Store the row in `image` as 8-bit ARGB. With `replicate`, pixels of early Adam7 passes also cover the area later passes fill in, for progressive display.
+    pub fn write_to(&self, image: &mut ImageBuffer, replicate: bool) {
+        let (bw, bh) = if replicate && self.interlaced {ADAM7_BLOCK[self.pass]} else {(1, 1)};
+        for (i, s) in self.samples.chunks_exact(4).enumerate() {
+            let c = to_argb8(s);
+            let x = self.x0 + i * self.dx;
+            for y in self.y..(self.y + bh).min(image.height) {
+                for x in x..(x + bw).min(image.width) {
+                    image.data[y * image.width + x] = c;
+                }
+            }
+        }
+    }
+}
+
+fn to_argb8(s: &[u16]) -> u32 {
+    let c = | v: u16 | (v as u32 * 255 + 32767) / 65535;
+    (c(s[3]) << 24) | (c(s[0]) << 16) | (c(s[1]) << 8) | c(s[2])
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StreamState {
+    Signature,
+    ChunkHeader,
+    ChunkData,
+    ChunkCrc,
+    End,
+}
+
+struct Pass {
+    pass: usize,
+    x0: usize,
+    y0: usize,
+    dx: usize,
+    dy: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Incremental PNG decoder. Feed it the file in pieces of any size with `push`, it calls back with rows as soon as they are decoded.
+pub struct StreamingDecoder {
+    state: StreamState,
+    buffer: Vec<u8>, // input that could not be processed yet
+    chunk_type: u32,
+    chunk_remaining: usize,
+    chunk_data: Vec<u8>,
+    crc: Crc32,
+    info: Option<PngInfo>,
+    itype: u16,
+    palette: [u32; 256],
+    transparent_key: Option<[u16; 3]>,
+    inflater: Box<InflateState>,
+    inflate_done: bool,
+    passes: Vec<Pass>,
+    pass: usize,
+    pass_row: usize,
+    stride: usize,
+    bpp: usize,
+    current: Vec<u8>, // filtered row being collected, including the filter type
+    previous: Vec<u8>, // previous unfiltered row of the pass
+    samples: Vec<u16>,
+}
+
+impl Default for StreamingDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingDecoder {
+    pub fn new() -> StreamingDecoder {
+        StreamingDecoder {
+            state: StreamState::Signature,
+            buffer: Vec::new(),
+            chunk_type: 0,
+            chunk_remaining: 0,
+            chunk_data: Vec::new(),
+            crc: Crc32::new(),
+            info: None,
+            itype: 0,
+            palette: [0xFF000000; 256],
+            transparent_key: None,
+            inflater: InflateState::new_boxed(DataFormat::Zlib),
+            inflate_done: false,
+            passes: Vec::new(),
+            pass: 0,
+            pass_row: 0,
+            stride: 0,
+            bpp: 0,
+            current: Vec::new(),
+            previous: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+    
+    /// The image header, once it has been decoded.
+    pub fn info(&self) -> Option<&PngInfo> {
+        self.info.as_ref()
+    }
+    
+    /// True once IEND has been reached with all rows decoded.
+    pub fn is_finished(&self) -> bool {
+        self.state == StreamState::End
+    }
+    
+    /// Decode the next piece of the file, calling `on_row` for every completed row.
+    pub fn push(&mut self, data: &[u8], mut on_row: impl FnMut(&PngRow)) -> Result<(), String> {
+        self.buffer.extend_from_slice(data);
+        let mut sp = 0;
+        let result = self.process(&mut sp, &mut on_row);
+        self.buffer.drain(0..sp);
+        result
+    }
+    
+    fn process(&mut self, sp: &mut usize, on_row: &mut impl FnMut(&PngRow)) -> Result<(), String> {
+        loop {
+            let available = self.buffer.len() - *sp;
+            match self.state {
+                StreamState::Signature => {
+                    if available < 8 {
+                        return Ok(());
+                    }
+                    if self.buffer[*sp..*sp + 8] != [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A] {
+                        return Err("invalid PNG".to_string());
+                    }
+                    *sp += 8;
+                    self.state = StreamState::ChunkHeader;
+                },
+                StreamState::ChunkHeader => {
+                    if available < 8 {
+                        return Ok(());
+                    }
+                    self.chunk_remaining = from_be32(&self.buffer[*sp..]) as usize;
+                    self.chunk_type = from_be32(&self.buffer[*sp + 4..]);
+                    self.crc = Crc32::new();
+                    self.crc.write_slice(&self.buffer[*sp + 4..*sp + 8]);
+                    *sp += 8;
+                    if self.chunk_remaining > 0x7FFFFFFF {
+                        return Err("Invalid PNG (chunk length)".to_string());
+                    }
+                    if self.info.is_none() && (self.chunk_type != 0x49484452) {
+                        return Err("Invalid PNG (IHDR missing)".to_string());
+                    }
+                    self.chunk_data.clear();
+                    self.state = StreamState::ChunkData;
+                },
+                StreamState::ChunkData => {
+                    let len = available.min(self.chunk_remaining);
+                    if self.chunk_type == 0x49444154 { // IDAT, inflated as it arrives
+                        let data = &self.buffer[*sp..*sp + len];
+                        self.crc.write_slice(data);
+                        let data = data.to_vec();
+                        *sp += len;
+                        self.chunk_remaining -= len;
+                        self.inflate_data(&data, on_row)?;
+                    }
+                    else {
+                        self.crc.write_slice(&self.buffer[*sp..*sp + len]);
+                        self.chunk_data.extend_from_slice(&self.buffer[*sp..*sp + len]);
+                        *sp += len;
+                        self.chunk_remaining -= len;
+                    }
+                    if self.chunk_remaining > 0 {
+                        return Ok(());
+                    }
+                    self.state = StreamState::ChunkCrc;
+                },
+                StreamState::ChunkCrc => {
+                    if available < 4 {
+                        return Ok(());
+                    }
+                    if from_be32(&self.buffer[*sp..]) != self.crc.checksum() {
+                        return Err("Invalid PNG (CRC mismatch)".to_string());
+                    }
+                    *sp += 4;
+                    self.state = StreamState::ChunkHeader;
+                    self.handle_chunk()?;
+                },
+                StreamState::End => {
+                    *sp = self.buffer.len();
+                    return Ok(());
+                },
+            }
+        }
+    }
+    
+    fn handle_chunk(&mut self) -> Result<(), String> {
+        let chunk = &self.chunk_data;
+        match self.chunk_type {
+            0x49484452 => { // IHDR
+                if self.info.is_some() || (chunk.len() < 13) {
+                    return Err("Invalid PNG".to_string());
+                }
+                let width = from_be32(&chunk[0..]) as usize;
+                let height = from_be32(&chunk[4..]) as usize;
+                let itype = from_be16(&chunk[8..]);
+                let interlace = chunk[12];
+                let (stride, bpp) = match stride_bpp(itype, width) {
+                    Some(stride_bpp) => stride_bpp,
+                    None => {return Err("Invalid PNG (unsupported image type)".to_string());}
+                };
+                if (width == 0) || (height == 0) || (width >= 65536) || (height >= 65536) || (chunk[10] != 0) || (chunk[11] != 0) || (interlace > 1) {
+                    return Err("Invalid PNG".to_string());
+                }
+                if interlace == 1 {
+                    for (pass, (x0, y0, dx, dy)) in ADAM7.iter().enumerate() {
+                        let pass_width = (width + dx - x0 - 1) / dx;
+                        let pass_height = (height + dy - y0 - 1) / dy;
+                        if (pass_width != 0) && (pass_height != 0) {
+                            self.passes.push(Pass {pass, x0: *x0, y0: *y0, dx: *dx, dy: *dy, width: pass_width, height: pass_height});
+                        }
+                    }
+                }
+                else {
+                    self.passes.push(Pass {pass: 0, x0: 0, y0: 0, dx: 1, dy: 1, width, height});
+                }
+                self.itype = itype;
+                self.stride = stride;
+                self.bpp = bpp;
+                self.info = Some(PngInfo {
+                    width,
+                    height,
+                    bit_depth: chunk[8],
+                    color_type: chunk[9],
+                    interlaced: interlace == 1,
+                    color: ColorInfo::default(),
+                });
+                self.start_pass();
+            },
+            0x504C5445 => { // PLTE
+                for (i, c) in chunk.chunks_exact(3).take(256).enumerate() {
+                    self.palette[i] = 0xFF000000 | ((c[0] as u32) << 16) | ((c[1] as u32) << 8) | (c[2] as u32);
+                }
+            },
+            0x74524E53 => { // tRNS
+                if is_palette_type(self.itype) {
+                    for (i, a) in chunk.iter().take(256).enumerate() {
+                        self.palette[i] = (self.palette[i] & 0x00FFFFFF) | ((*a as u32) << 24);
+                    }
+                }
+                else if (self.itype & 0xFF) == 0 && (chunk.len() >= 2) {
+                    let l = from_be16(chunk);
+                    self.transparent_key = Some([l, l, l]);
+                }
+                else if (self.itype & 0xFF) == 2 && (chunk.len() >= 6) {
+                    self.transparent_key = Some([from_be16(chunk), from_be16(&chunk[2..]), from_be16(&chunk[4..])]);
+                }
+            },
+            0x67414D41 if chunk.len() >= 4 => { // gAMA
+                let color = &mut self.info.as_mut().unwrap().color;
+                color.gamma = Some((from_be32(chunk) as f32) / 100000.0);
+            },
+            0x73524742 if !chunk.is_empty() => { // sRGB
+                self.info.as_mut().unwrap().color.srgb_intent = Some(chunk[0]);
+            },
+            0x69434350 => { // iCCP
+                // null terminated name, compression method, zlib compressed profile
+                if let Some(end) = chunk.iter().position( | c | *c == 0) {
+                    if chunk.len() > end + 2 {
+                        if let Ok(profile) = decompress_to_vec_zlib(&chunk[end + 2..]) {
+                            let name = String::from_utf8_lossy(&chunk[0..end]).to_string();
+                            self.info.as_mut().unwrap().color.icc_profile = Some((name, profile));
+                        }
+                    }
+                }
+            },
+            0x6348524D if chunk.len() >= 32 => { // cHRM
+                let mut chromaticities = [0.0; 8];
+                for (i, c) in chromaticities.iter_mut().enumerate() {
+                    *c = (from_be32(&chunk[i * 4..]) as f32) / 100000.0;
+                }
+                self.info.as_mut().unwrap().color.chromaticities = Some(chromaticities);
+            },
+            0x49454E44 => { // IEND
+                if self.pass < self.passes.len() {
+                    return Err("Invalid PNG (missing image data)".to_string());
+                }
+                self.state = StreamState::End;
+            },
+            _ => {
+            },
+        }
+        Ok(())
+    }
+    
+    fn start_pass(&mut self) {
+        self.pass_row = 0;
+        self.current.clear();
+        if let Some(pass) = self.passes.get(self.pass) {
+            let stride = stride_bpp(self.itype, pass.width).map_or(0, | (stride, _) | stride);
+            self.previous.clear();
+            self.previous.resize(stride, 0);
+        }
+    }
+    
+    fn inflate_data(&mut self, mut input: &[u8], on_row: &mut impl FnMut(&PngRow)) -> Result<(), String> {
+        let mut output = [0u8; 16384];
+        while !self.inflate_done {
+            let result = stream::inflate(&mut self.inflater, input, &mut output, MZFlush::None);
+            input = &input[result.bytes_consumed..];
+            self.filtered_data(&output[0..result.bytes_written], on_row)?;
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    self.inflate_done = true;
+                },
+                Ok(_) | Err(MZError::Buf) => {
+                    if (result.bytes_consumed == 0) && (result.bytes_written == 0) {
+                        break;
+                    }
+                    if input.is_empty() && (result.bytes_written < output.len()) {
+                        break;
+                    }
+                },
+                Err(_) => {
+                    return Err("Invalid PNG (corrupt image data)".to_string());
+                },
+            }
+        }
+        Ok(())
+    }
+    
+    fn filtered_data(&mut self, mut data: &[u8], on_row: &mut impl FnMut(&PngRow)) -> Result<(), String> {
+        while !data.is_empty() && (self.pass < self.passes.len()) {
+            let row_len = self.previous.len() + 1;
+            let len = (row_len - self.current.len()).min(data.len());
+            self.current.extend_from_slice(&data[0..len]);
+            data = &data[len..];
+            if self.current.len() < row_len {
+                break;
+            }
+            let ftype = self.current[0];
+            if ftype > 4 {
+                return Err("Invalid PNG (filter type)".to_string());
+            }
+            unfilter_row(ftype, &mut self.current[1..], &self.previous, self.bpp);
+            self.previous.copy_from_slice(&self.current[1..]);
+            self.current.clear();
+            
+            let pass = &self.passes[self.pass];
+            expand_row(&mut self.samples, &self.previous, pass.width, self.itype, &self.palette, self.transparent_key);
+            on_row(&PngRow {
+                pass: pass.pass,
+                y: pass.y0 + self.pass_row * pass.dy,
+                x0: pass.x0,
+                dx: pass.dx,
+                samples: &self.samples,
+                interlaced: self.passes.len() > 1 || pass.dx > 1,
+            });
+            self.pass_row += 1;
+            if self.pass_row == pass.height {
+                self.pass += 1;
+                self.start_pass();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unfilter_row(ftype: u8, row: &mut [u8], previous: &[u8], bpp: usize) {
+    for x in 0..row.len() {
+        let a = if x >= bpp {row[x - bpp]} else {0};
+        let b = previous[x];
+        let c = if x >= bpp {previous[x - bpp]} else {0};
+        let predictor = match ftype {
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) >> 1) as u8,
+            4 => paeth(a, b, c),
+            _ => 0,
         };
+        row[x] = row[x].wrapping_add(predictor);
+    }
+}
+
+// convert an unfiltered row to RGBA16
+fn expand_row(dst: &mut Vec<u16>, src: &[u8], width: usize, itype: u16, palette: &[u32; 256], transparent_key: Option<[u16; 3]>) {
+    let depth = (itype >> 8) as usize;
+    let color_type = itype & 0xFF;
+    let sample = | i: usize | -> u16 {
+        match depth {
+            16 => from_be16(&src[i * 2..]),
+            8 => src[i] as u16,
+            _ => ((src[i * depth / 8] >> (8 - depth - (i * depth) % 8)) & ((1 << depth) - 1)) as u16,
+        }
+    };
+    let max = (1u32 << depth) - 1;
+    let scale = | v: u16 | ((v as u32 * 65535 + max / 2) / max) as u16;
+    dst.clear();
+    for x in 0..width {
+        let (rgba, key) = match color_type {
+            0 => {
+                let l = sample(x);
+                ([scale(l), scale(l), scale(l), 65535], [l, l, l])
+            },
+            2 => {
+                let (r, g, b) = (sample(x * 3), sample(x * 3 + 1), sample(x * 3 + 2));
+                ([scale(r), scale(g), scale(b), 65535], [r, g, b])
+            },
+            3 => {
+                let c = palette[sample(x) as usize];
+                let channel = | shift: u32 | ((c >> shift) & 255) as u16 * 257;
+                ([channel(16), channel(8), channel(0), channel(24)], [0; 3])
+            },
+            4 => {
+                let l = scale(sample(x * 2));
+                ([l, l, l, scale(sample(x * 2 + 1))], [0; 3])
+            },
+            _ => {
+                ([scale(sample(x * 4)), scale(sample(x * 4 + 1)), scale(sample(x * 4 + 2)), scale(sample(x * 4 + 3))], [0; 3])
+            },
+        };
+        dst.extend_from_slice(&rgba);
+        if (transparent_key == Some(key)) && ((color_type == 0) || (color_type == 2)) {
+            let len = dst.len();
+            dst[len - 1] = 0;
+        }
+    }
+}
+
+/// Decode a whole PNG at full precision, returns the header and RGBA samples with 16 bits per channel.
+pub fn decode_rgba16(src: &[u8]) -> Result<(PngInfo, Vec<u16>), String> {
+    let mut decoder = StreamingDecoder::new();
+    // signature and IHDR
+    let header_len = src.len().min(33);
+    decoder.push(&src[0..header_len], | _ | {})?;
+    let info = match decoder.info() {
+        Some(info) => info.clone(),
+        None => {return Err("Invalid PNG (truncated)".to_string());}
+    };
+    let mut samples = vec![0u16; info.width * info.height * 4];
+    decoder.push(&src[header_len..], | row | {
+        for (i, s) in row.samples.chunks_exact(4).enumerate() {
+            let dp = (row.y * info.width + row.x0 + i * row.dx) * 4;
+            samples[dp..dp + 4].copy_from_slice(s);
+        }
+    })?;
+    if !decoder.is_finished() {
+        return Err("Invalid PNG (truncated)".to_string());
+    }
+    Ok((decoder.info().unwrap().clone(), samples))
+}
+
+// Encoder
+
+/// Pixel layout written by the PNG encoder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorType {
+    RGB8,
+    RGBA8,
+}
+
+fn to_be32(dst: &mut Vec<u8>, value: u32) {
+    dst.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_chunk(dst: &mut Vec<u8>, chunk_type: u32, data: &[u8]) {
+    to_be32(dst, data.len() as u32);
+    let start = dst.len();
+    to_be32(dst, chunk_type);
+    dst.extend_from_slice(data);
+    let crc = crc32(&dst[start..]);
+    to_be32(dst, crc);
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let d = a as i32 + b as i32 - c as i32;
+    let pa = (d - a as i32).abs();
+    let pb = (d - b as i32).abs();
+    let pc = (d - c as i32).abs();
+    if (pa <= pb) && (pa <= pc) {a} else if pb <= pc {b} else {c}
+}
+
+fn filter_row(dst: &mut [u8], ftype: u8, row: &[u8], prev: &[u8], bpp: usize) {
+    for x in 0..row.len() {
+        let a = if x >= bpp {row[x - bpp]} else {0};
+        let b = prev[x];
+        let c = if x >= bpp {prev[x - bpp]} else {0};
+        dst[x] = row[x].wrapping_sub(match ftype {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) >> 1) as u8,
+            _ => paeth(a, b, c),
+        });
+    }
+}
+
+// minimum sum of absolute differences, as recommended by the PNG spec
+fn filter_cost(filtered: &[u8]) -> u64 {
+    filtered.iter().map( | v | (*v as i8).unsigned_abs() as u64).sum()
+}
+
+fn filter(src: &[u8], height: usize, stride: usize, bpp: usize) -> Vec<u8> {
+    let mut dst: Vec<u8> = Vec::with_capacity((stride + 1) * height);
+    let zero_row = vec![0u8; stride];
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    for y in 0..height {
+        let row = &src[y * stride..(y + 1) * stride];
+        let prev = if y >= 1 {&src[(y - 1) * stride..y * stride]} else {&zero_row[..]};
+        let mut best_type = 0;
+        let mut best_cost = u64::MAX;
+        for ftype in 0..5 {
+            filter_row(&mut candidate, ftype, row, prev, bpp);
+            let cost = filter_cost(&candidate);
+            if cost < best_cost {
+                best_cost = cost;
+                best_type = ftype;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+        dst.push(best_type);
+        dst.extend_from_slice(&best);
+    }
+    dst
+}
+
+/// Encode an image as PNG, picking RGB8 when every pixel is opaque and RGBA8 otherwise.
+pub fn encode(image: &ImageBuffer) -> Result<Vec<u8>, String> {
+    let opaque = image.data.iter().all( | c | (c >> 24) == 0xFF);
+    encode_with(image, if opaque {ColorType::RGB8} else {ColorType::RGBA8}, 6)
+}
+
+/// Encode an image as PNG in the given color type, with deflate level `level` (0-10).
+pub fn encode_with(image: &ImageBuffer, color_type: ColorType, level: u8) -> Result<Vec<u8>, String> {
+    if (image.width == 0) || (image.height == 0) || (image.width >= 65536) || (image.height >= 65536) {
+        return Err(format!("cannot encode PNG of size {}x{}", image.width, image.height));
+    }
+    if image.data.len() < image.width * image.height {
+        return Err(format!("image data too small ({} < {})", image.data.len(), image.width * image.height));
+    }
+    let (itype, bpp) = match color_type {
+        ColorType::RGB8 => (TYPE_RGB8, 3),
+        ColorType::RGBA8 => (TYPE_RGBA8, 4),
+    };
+    let stride = image.width * bpp;
+    let mut raw_data: Vec<u8> = Vec::with_capacity(stride * image.height);
+    for c in &image.data[0..image.width * image.height] {
+        raw_data.push((c >> 16) as u8);
+        raw_data.push((c >> 8) as u8);
+        raw_data.push(*c as u8);
+        if bpp == 4 {
+            raw_data.push((c >> 24) as u8);
+        }
+    }
+    let filtered_data = filter(&raw_data, image.height, stride, bpp);
+    let zipped_data = compress_to_vec_zlib(&filtered_data, level);
+    
+    let mut dst: Vec<u8> = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+    let mut ihdr: Vec<u8> = Vec::with_capacity(13);
+    to_be32(&mut ihdr, image.width as u32);
+    to_be32(&mut ihdr, image.height as u32);
+    ihdr.extend_from_slice(&itype.to_be_bytes());
+    ihdr.extend_from_slice(&[0, 0, 0]); // compression, filter, interlace
+    write_chunk(&mut dst, 0x49484452, &ihdr); // IHDR
+    write_chunk(&mut dst, 0x49444154, &zipped_data); // IDAT
+    write_chunk(&mut dst, 0x49454E44, &[]); // IEND
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn test_image(width: usize, height: usize, alpha: bool) -> ImageBuffer {
+        let mut image = ImageBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let r = (x * 255 / width) as u32;
+                let g = (y * 255 / height) as u32;
+                let b = ((x * 7 + y * 13) & 255) as u32;
+                let a = if alpha {(((x + y) * 3) & 255) as u32} else {255};
+                image.data[y * width + x] = (a << 24) | (r << 16) | (g << 8) | b;
+            }
+        }
+        image
+    }
+    
+    #[test]
+    fn roundtrip_rgba8() {
+        let image = test_image(67, 41, true);
+        let data = encode(&image).unwrap();
+        assert_eq!(test(&data), Some((67, 41)));
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.data, image.data);
+    }
+    
+    #[test]
+    fn roundtrip_rgb8() {
+        let image = test_image(33, 70, false);
+        for level in [0, 1, 6, 9] {
+            let data = encode_with(&image, ColorType::RGB8, level).unwrap();
+            assert_eq!(from_be16(&data[24..]), TYPE_RGB8);
+            let decoded = decode(&data).unwrap();
+            assert_eq!(decoded.data, image.data);
+        }
+    }
+    
+    #[test]
+    fn filters_roundtrip() {
+        let image = test_image(19, 11, true);
+        let mut raw_data = Vec::new();
+        for c in &image.data {
+            raw_data.extend_from_slice(&[(c >> 16) as u8, (c >> 8) as u8, *c as u8, (c >> 24) as u8]);
+        }
+        let stride = 19 * 4;
+        for ftype in 0..5 {
+            let mut filtered = Vec::new();
+            let mut row = vec![0u8; stride];
+            for y in 0..11 {
+                let prev = if y >= 1 {raw_data[(y - 1) * stride..y * stride].to_vec()} else {vec![0; stride]};
+                filter_row(&mut row, ftype, &raw_data[y * stride..(y + 1) * stride], &prev, 4);
+                filtered.push(ftype);
+                filtered.extend_from_slice(&row);
+            }
+            assert_eq!(unfilter(&filtered, 11, stride, 4)[0..stride * 11], raw_data[..]);
+        }
+    }
+    
+    // zlib data of the IDAT chunk of an encoded PNG
+    fn idat(data: &[u8]) -> Vec<u8> {
+        parse(data).unwrap().zipped_data
+    }
+    
+    fn fctl(seq: u32, rect: (u32, u32, u32, u32), delay: (u16, u16), dispose_op: u8, blend_op: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        for v in [seq, rect.0, rect.1, rect.2, rect.3] {
+            to_be32(&mut data, v);
+        }
+        data.extend_from_slice(&delay.0.to_be_bytes());
+        data.extend_from_slice(&delay.1.to_be_bytes());
+        data.extend_from_slice(&[dispose_op, blend_op]);
+        data
+    }
+    
+    fn solid(width: usize, height: usize, color: u32) -> ImageBuffer {
+        ImageBuffer {width, height, data: vec![color; width * height]}
+    }
+    
+    #[test]
+    fn decode_apng() {
+        let background = solid(4, 4, 0xFFFF0000);
+        let overlay = solid(2, 2, 0x800000FF);
+        let corner = solid(1, 1, 0xFF00FF00);
+        let mut dst = encode_with(&background, ColorType::RGBA8, 6).unwrap();
+        dst.truncate(dst.len() - 12); // drop IEND
+        let idat_start = 8 + 25;
+        let default_image = dst.split_off(idat_start);
+        let mut actl = Vec::new();
+        to_be32(&mut actl, 3);
+        to_be32(&mut actl, 2);
+        write_chunk(&mut dst, 0x6163544C, &actl); // acTL
+        write_chunk(&mut dst, 0x6663544C, &fctl(0, (4, 4, 0, 0), (1, 2), 0, 0));
+        dst.extend_from_slice(&default_image);
+        write_chunk(&mut dst, 0x6663544C, &fctl(1, (2, 2, 1, 1), (25, 0), APNG_DISPOSE_OP_PREVIOUS, APNG_BLEND_OP_OVER));
+        let mut fdat = vec![0, 0, 0, 2];
+        fdat.extend_from_slice(&idat(&encode_with(&overlay, ColorType::RGBA8, 6).unwrap()));
+        write_chunk(&mut dst, 0x66644154, &fdat);
+        write_chunk(&mut dst, 0x6663544C, &fctl(3, (1, 1, 0, 0), (1, 10), APNG_DISPOSE_OP_BACKGROUND, 0));
+        let mut fdat = vec![0, 0, 0, 4];
+        fdat.extend_from_slice(&idat(&encode_with(&corner, ColorType::RGBA8, 6).unwrap()));
+        write_chunk(&mut dst, 0x66644154, &fdat);
+        write_chunk(&mut dst, 0x49454E44, &[]);
         
-        //let after_inflate = Instant::now();
-        
-        let raw_data =  unfilter(&filtered_data, height, stride, bpp);
-        
-        //let after_unfilter = Instant::now();
+        // plain decoding only sees the default image
+        assert_eq!(decode(&dst).unwrap().data, background.data);
         
-        let mut result = ImageBuffer::new(width, height);
-        decode_pixels(&mut result.data, &raw_data, width, height, width, 0, 0, 1, 1, itype as u16, &palette, gamma);
+        let animation = decode_animated(&dst).unwrap();
+        assert_eq!(animation.loop_count, 2);
+        assert_eq!(animation.frames.len(), 3);
+        let delays: Vec<f64> = animation.frames.iter().map( | frame | frame.delay).collect();
+        assert_eq!(delays, vec![0.5, 0.25, 0.1]);
+        assert_eq!(animation.frames[0].image.data, background.data);
+        let frame1 = &animation.frames[1].image.data;
+        assert_eq!(frame1[0], 0xFFFF0000);
+        assert_eq!(frame1[5], 0xFF7F0080);
+        let frame2 = &animation.frames[2].image.data;
+        assert_eq!(frame2[5], 0xFFFF0000); // previous restored
+        assert_eq!(frame2[0], 0xFF00FF00);
+    }
+    
+    #[test]
+    fn decode_png_as_animation() {
+        let image = test_image(5, 3, true);
+        let animation = decode_animated(&encode(&image).unwrap()).unwrap();
+        assert_eq!(animation.frames.len(), 1);
+        assert_eq!(animation.frames[0].image.data, image.data);
+    }
+    
+    // decode with the streaming decoder, feeding `piece` bytes at a time
+    fn stream_decode(data: &[u8], piece: usize, replicate: bool) -> (ImageBuffer, Vec<usize>) {
+        let mut decoder = StreamingDecoder::new();
+        let mut image = ImageBuffer::new(0, 0);
+        let mut pass_rows = vec![0; 7];
+        for piece in data.chunks(piece) {
+            decoder.push(piece, | row | {
+                if image.width == 0 {
+                    image = ImageBuffer::new(test(data).unwrap().0, test(data).unwrap().1);
+                }
+                pass_rows[row.pass] += 1;
+                row.write_to(&mut image, replicate);
+            }).unwrap();
+        }
+        assert!(decoder.is_finished());
+        (image, pass_rows)
+    }
+    
+    // encode RGBA16 samples, optionally Adam7 interlaced
+    fn encode_rgba16(width: usize, height: usize, samples: &[u16], interlaced: bool, extra_chunks: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let passes: Vec<(usize, usize, usize, usize)> = if interlaced {ADAM7.to_vec()} else {vec![(0, 0, 1, 1)]};
+        let mut filtered_data = Vec::new();
+        for (x0, y0, dx, dy) in passes {
+            let mut raw_data = Vec::new();
+            let mut pass_height = 0;
+            for y in (y0..height).step_by(dy) {
+                pass_height += 1;
+                for x in (x0..width).step_by(dx) {
+                    for s in &samples[(y * width + x) * 4..(y * width + x) * 4 + 4] {
+                        raw_data.extend_from_slice(&s.to_be_bytes());
+                    }
+                }
+            }
+            if !raw_data.is_empty() {
+                filtered_data.extend_from_slice(&filter(&raw_data, pass_height, raw_data.len() / pass_height, 8));
+            }
+        }
+        let mut dst: Vec<u8> = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+        let mut ihdr = Vec::new();
+        to_be32(&mut ihdr, width as u32);
+        to_be32(&mut ihdr, height as u32);
+        ihdr.extend_from_slice(&[16, 6, 0, 0, interlaced as u8]);
+        write_chunk(&mut dst, 0x49484452, &ihdr);
+        for (chunk_type, data) in extra_chunks {
+            write_chunk(&mut dst, *chunk_type, data);
+        }
+        let zipped_data = compress_to_vec_zlib(&filtered_data, 6);
+        // split the image data over several IDAT chunks
+        for part in zipped_data.chunks(100) {
+            write_chunk(&mut dst, 0x49444154, part);
+        }
+        write_chunk(&mut dst, 0x49454E44, &[]);
+        dst
+    }
+    
+    fn test_samples(width: usize, height: usize) -> Vec<u16> {
+        (0..width * height * 4).map( | i | ((i * 7919) % 65536) as u16).collect()
+    }
+    
+    #[test]
+    fn stream_matches_decode() {
+        let image = test_image(45, 23, true);
+        let data = encode(&image).unwrap();
+        for piece in [1, 7, 1000, data.len()] {
+            let (decoded, pass_rows) = stream_decode(&data, piece, false);
+            assert_eq!(decoded.data, image.data);
+            assert_eq!(pass_rows[0], 23);
+        }
+    }
+    
+    #[test]
+    fn stream_adam7_rgba16() {
+        for (width, height) in [(1, 1), (3, 2), (13, 9), (40, 33)] {
+            let samples = test_samples(width, height);
+            let interlaced = encode_rgba16(width, height, &samples, true, &[]);
+            let (info, decoded) = decode_rgba16(&interlaced).unwrap();
+            assert!(info.interlaced);
+            assert_eq!((info.width, info.height, info.bit_depth, info.color_type), (width, height, 16, 6));
+            assert_eq!(decoded, samples);
+            let plain = encode_rgba16(width, height, &samples, false, &[]);
+            assert_eq!(decode_rgba16(&plain).unwrap().1, samples);
+            
+            // the whole file decoder agrees at 8 bits, streaming rounds rather than truncates
+            let (streamed, _) = stream_decode(&interlaced, 5, false);
+            let whole = decode(&interlaced).unwrap();
+            for (a, b) in streamed.data.iter().zip(whole.data.iter()) {
+                for shift in [0, 8, 16, 24] {
+                    assert!((((a >> shift) & 255) as i32 - ((b >> shift) & 255) as i32).abs() <= 1);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn stream_progressive_replicate() {
+        let (width, height) = (16, 16);
+        let samples = test_samples(width, height);
+        let data = encode_rgba16(width, height, &samples, true, &[]);
+        let (_, pass_rows) = stream_decode(&data, 64, true);
+        assert_eq!(pass_rows, vec![2, 2, 2, 4, 4, 8, 8]);
         
-        //let after_decode = Instant::now();
+        // only the first pass, which replicated over the 8x8 blocks
+        let mut decoder = StreamingDecoder::new();
+        let mut image = ImageBuffer::new(width, height);
+        let mut first_pass = Vec::new();
+        decoder.push(&data, | row | {
+            if row.pass == 0 {
+                row.write_to(&mut image, true);
+                first_pass.push(row.samples.to_vec());
+            }
+        }).unwrap();
+        assert_eq!(image.data[7 * width + 7], to_argb8(&first_pass[0][0..4]));
+        assert_eq!(image.data[15 * width + 9], to_argb8(&first_pass[1][4..8]));
+    }
+    
+    #[test]
+    fn stream_color_info() {
+        let mut gama = Vec::new();
+        to_be32(&mut gama, 45455);
+        let mut chrm = Vec::new();
+        for v in [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000] {
+            to_be32(&mut chrm, v);
+        }
+        let profile: Vec<u8> = (0..300).map( | i | (i % 17) as u8).collect();
+        let mut iccp = b"test profile\0\0".to_vec();
+        iccp.extend_from_slice(&compress_to_vec_zlib(&profile, 6));
+        let samples = test_samples(4, 4);
+        let data = encode_rgba16(4, 4, &samples, false, &[
+            (0x67414D41, gama),
+            (0x6348524D, chrm),
+            (0x69434350, iccp),
+            (0x73524742, vec![0]),
+        ]);
+        let (info, decoded) = decode_rgba16(&data).unwrap();
+        assert_eq!(decoded, samples);
+        assert_eq!(info.color.gamma, Some(0.45455));
+        assert_eq!(info.color.srgb_intent, Some(0));
+        assert_eq!(info.color.icc_profile, Some(("test profile".to_string(), profile)));
+        assert_eq!(info.color.chromaticities.unwrap()[0], 0.3127);
+    }
+    
+    #[test]
+    fn stream_low_bit_depths() {
+        // 2-bit gray, 5 pixels per row, with a transparent gray level
+        let rows: [[u8; 5]; 3] = [[0, 1, 2, 3, 0], [3, 3, 2, 1, 1], [1, 0, 0, 2, 3]];
+        let mut filtered_data = Vec::new();
+        for row in &rows {
+            filtered_data.push(0);
+            filtered_data.push((row[0] << 6) | (row[1] << 4) | (row[2] << 2) | row[3]);
+            filtered_data.push(row[4] << 6);
+        }
+        let mut dst: Vec<u8> = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+        let mut ihdr = Vec::new();
+        to_be32(&mut ihdr, 5);
+        to_be32(&mut ihdr, 3);
+        ihdr.extend_from_slice(&[2, 0, 0, 0, 0]);
+        write_chunk(&mut dst, 0x49484452, &ihdr);
+        write_chunk(&mut dst, 0x74524E53, &[0, 2]);
+        write_chunk(&mut dst, 0x49444154, &compress_to_vec_zlib(&filtered_data, 6));
+        write_chunk(&mut dst, 0x49454E44, &[]);
+        let (_, samples) = decode_rgba16(&dst).unwrap();
+        for (i, l) in rows.iter().flatten().enumerate() {
+            let level = *l as u16 * 0x5555;
+            let alpha = if *l == 2 {0} else {65535};
+            assert_eq!(samples[i * 4..i * 4 + 4], [level, level, level, alpha]);
+        }
+    }
+    
+    #[test]
+    fn stream_errors() {
+        let data = encode(&test_image(20, 20, false)).unwrap();
         
-        //let total_duration = after_decode.duration_since(after0);
-        //let inflate_duration = after_inflate.duration_since(after0);
-        //let inflate_percentage = (100.0 * inflate_duration.as_secs_f32()) / (total_duration.as_secs_f32());
-        //let unfilter_duration = after_unfilter.duration_since(after_inflate);
-        //let unfilter_percentage = (100.0 * unfilter_duration.as_secs_f32()) / (total_duration.as_secs_f32());
-        //let decode_duration = after_decode.duration_since(after_unfilter);
-        //let decode_percentage = (100.0 * decode_duration.as_secs_f32()) / (total_duration.as_secs_f32());
+        // a truncated file decodes what is there without finishing
+        let mut decoder = StreamingDecoder::new();
+        let mut rows = 0;
+        decoder.push(&data[0..data.len() - 20], | _ | rows += 1).unwrap();
+        assert!(!decoder.is_finished());
+        assert!(decode_rgba16(&data[0..data.len() - 20]).is_err());
         
-        //println!("inflate: {} us ({}%)",inflate_duration.as_micros(),inflate_percentage);
-        //println!("unfilter: {} us ({}%)",unfilter_duration.as_micros(),unfilter_percentage);
-        //println!("decode: {} us ({}%)",decode_duration.as_micros(),decode_percentage);
-        //println!("------------------");
-        //println!("total: {} us (100.0%)",total_duration.as_micros());
+        // corrupt chunk
+        let mut corrupt = data.clone();
+        corrupt[40] ^= 1;
+        assert!(decode_rgba16(&corrupt).is_err());
         
-        Ok(result)
+        assert!(decode_rgba16(&data[0..10]).is_err());
+        assert!(decode_rgba16(b"not a png at all").is_err());
     }
 }
-
-pub fn encode(_image: &ImageBuffer) -> Result<Vec<u8>, String> {
-    Err("not implemented".to_string())
-}
\ No newline at end of file
@@ -0,0 +1,131 @@
+// Draws a small scene through Window, Pass and View on the headless backend and
+// checks the pixels of the window framebuffer. Needs `--features headless`.
+
+use {
+    std::{
+        rc::Rc,
+        cell::RefCell,
+    },
+    makepad_draw::*,
+};
+
+live_design!{
+    import makepad_draw::shader::std::*;
+    import makepad_draw::shader::draw_color::DrawColor;
+
+    DrawDisc = <DrawColor> {
+        fn pixel(self) -> vec4 {
+            let sdf = Sdf2d::viewport(self.pos * self.rect_size);
+            sdf.circle(8., 8., 6.);
+            return sdf.fill(self.color);
+        }
+    }
+}
+
+struct Scene {
+    window: Window,
+    pass: Pass,
+    main_view: View,
+    quad: DrawColor,
+    disc: DrawColor,
+}
+
+impl Scene {
+    fn new(cx: &mut Cx) -> Self {
+        let window = Window::new(cx);
+        let pass = Pass::new(cx);
+        window.set_pass(cx, &pass);
+        pass.set_window_clear_color(cx, vec4(0.0, 0.0, 0.5, 1.0));
+        let disc_ptr = cx.live_registry.borrow().module_id_and_name_to_ptr(
+            LiveModuleId::from_str(module_path!()).unwrap(),
+            live_id!(DrawDisc)
+        ).unwrap();
+        Self {
+            window,
+            pass,
+            main_view: View::new(cx),
+            quad: DrawColor::new_local(cx),
+            disc: DrawColor::new_from_ptr(cx, Some(disc_ptr)),
+        }
+    }
+
+    fn draw(&mut self, cx: &mut Cx2d) {
+        cx.begin_pass(&self.pass, None);
+        self.main_view.begin_always(cx);
+        let pass_size = cx.current_pass_size();
+        cx.begin_turtle(Walk::fixed_size(pass_size), Layout::default());
+
+        self.quad.color = vec4(1.0, 0.0, 0.0, 1.0);
+        self.quad.draw_abs(cx, Rect {pos: dvec2(2.0, 2.0), size: dvec2(8.0, 4.0)});
+        // half transparent and drawn later, so it blends over the first quad
+        self.quad.color = vec4(0.0, 1.0, 0.0, 0.5);
+        self.quad.draw_abs(cx, Rect {pos: dvec2(6.0, 2.0), size: dvec2(8.0, 4.0)});
+        self.disc.color = vec4(1.0, 1.0, 1.0, 1.0);
+        self.disc.draw_abs(cx, Rect {pos: dvec2(16.0, 4.0), size: dvec2(16.0, 16.0)});
+
+        cx.end_turtle();
+        self.main_view.end(cx);
+        cx.end_pass(&self.pass);
+    }
+}
+
+fn run() -> (Cx, HeadlessApp) {
+    let scene = Rc::new(RefCell::new(None));
+    let mut cx = Cx::new(Box::new({
+        let scene = scene.clone();
+        move | cx, event | match event {
+            Event::Construct => *scene.borrow_mut() = Some(Scene::new(cx)),
+            Event::Draw(draw_event) => scene.borrow_mut().as_mut().unwrap().draw(&mut Cx2d::new(cx, draw_event)),
+            _ => ()
+        }
+    }));
+    makepad_draw::live_design(&mut cx);
+    crate::live_design(&mut cx);
+    cx.init_cx_os();
+
+    let mut headless_app = HeadlessApp::new();
+    headless_app.inner_size = Some(dvec2(32.0, 24.0));
+    headless_app.dpi_factor = 1.0;
+    headless_app.max_frames = None;
+    cx.headless_event_loop(&mut headless_app);
+
+    let window_id = scene.borrow().as_ref().unwrap().window.window_id();
+    assert!(headless_app.window(window_id).is_some());
+    (cx, headless_app)
+}
+
+fn assert_near(a: Vec4, b: Vec4) {
+    let d = a - b;
+    assert!(d.x.abs().max(d.y.abs()).max(d.z.abs()).max(d.w.abs()) < 1e-3, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn draw_window() {
+    let (cx, headless_app) = run();
+    assert!(matches!(cx.os_type(), OsType::LinuxHeadless));
+    // the loop ran until the app was idle, after painting the window once
+    assert_eq!(headless_app.frames, 1);
+
+    let framebuffer = &headless_app.windows[0].framebuffer;
+    assert_eq!((framebuffer.width, framebuffer.height), (32, 24));
+
+    let clear = vec4(0.0, 0.0, 0.5, 1.0);
+    assert_near(framebuffer.pixel(0, 0), clear);
+    assert_near(framebuffer.pixel(3, 3), vec4(1.0, 0.0, 0.0, 1.0));
+    assert_near(framebuffer.pixel(8, 3), vec4(0.5, 0.5, 0.0, 1.0));
+    assert_near(framebuffer.pixel(12, 3), vec4(0.0, 0.5, 0.25, 1.0));
+    assert_near(framebuffer.pixel(14, 3), clear);
+    assert_near(framebuffer.pixel(9, 6), clear);
+    // the middle of the disc, and its antialiased edge
+    assert_near(framebuffer.pixel(24, 12), vec4(1.0, 1.0, 1.0, 1.0));
+    let edge = framebuffer.pixel(18, 12);
+    assert!(edge.x > 0.1 && edge.x < 0.9, "edge {:?}", edge);
+
+    // the png round trips through the decoder in image_formats
+    let image = makepad_image_formats::png::decode(&framebuffer.encode_png()).unwrap();
+    assert_eq!((image.width, image.height), (32, 24));
+    let rgba = framebuffer.to_rgba8();
+    for (argb, rgba) in image.data.iter().zip(rgba.chunks(4)) {
+        assert_eq!(*argb, u32::from_be_bytes([rgba[3], rgba[0], rgba[1], rgba[2]]));
+    }
+}
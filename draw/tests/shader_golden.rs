@@ -0,0 +1,70 @@
+// Compiles the stock draw shaders through every shader backend and compares
+// the output against the files in tests/golden. After an intended change to a
+// generator, rerun with MAKEPAD_BLESS=1 to rewrite the golden files.
+
+use {
+    std::path::PathBuf,
+    makepad_draw::{
+        *,
+        makepad_shader_compiler::{generate_glsl, generate_metal, generate_hlsl, generate_wgsl},
+    },
+};
+
+fn compile_stock_shaders() -> Cx {
+    let mut cx = Cx::new(Box::new( | _, _ | {}));
+    makepad_draw::live_design(&mut cx);
+    cx.live_expand();
+    let _ = DrawQuad::new_local(&mut cx);
+    let _ = DrawColor::new_local(&mut cx);
+    let _ = DrawText::new_local(&mut cx);
+    assert!(cx.draw_shaders.error_set.is_empty(), "stock shaders failed to compile");
+    cx
+}
+
+fn check_golden(name: &str, output: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    if std::env::var("MAKEPAD_BLESS").is_ok() {
+        std::fs::write(&path, output).unwrap();
+        return
+    }
+    let golden = std::fs::read_to_string(&path).unwrap_or_else( | _ | panic!("missing golden file {:?}, run with MAKEPAD_BLESS=1", path));
+    if golden != output {
+        for (line, (golden, output)) in golden.lines().zip(output.lines()).enumerate() {
+            assert_eq!(golden, output, "{} differs at line {}", name, line + 1);
+        }
+        panic!("{} differs in length, {} vs {} lines", name, golden.lines().count(), output.lines().count());
+    }
+}
+
+#[test]
+fn stock_shaders() {
+    let cx = compile_stock_shaders();
+    let mut checked = Vec::new();
+    for (draw_shader_ptr, item) in &cx.draw_shaders.ptr_to_item {
+        let shader = &cx.draw_shaders.shaders[item.draw_shader_id];
+        let name = match shader.type_name {
+            id if id == live_id!(DrawQuad) => "draw_quad",
+            id if id == live_id!(DrawColor) => "draw_color",
+            id if id == live_id!(DrawText) => "draw_text",
+            _ => continue
+        };
+        let draw_shader_def = cx.shader_registry.draw_shader_defs.get(draw_shader_ptr).unwrap();
+        let const_table = &shader.mapping.const_table;
+        
+        let vertex = generate_glsl::generate_vertex_shader(draw_shader_def, const_table, &cx.shader_registry);
+        let pixel = generate_glsl::generate_pixel_shader(draw_shader_def, const_table, &cx.shader_registry);
+        check_golden(&format!("{}.glsl", name), &format!("{}\n{}", vertex, pixel));
+        
+        let metal = generate_metal::generate_shader(draw_shader_def, const_table, &cx.shader_registry);
+        check_golden(&format!("{}.metal", name), &metal.mtlsl);
+        
+        let hlsl = generate_hlsl::generate_shader(draw_shader_def, const_table, &cx.shader_registry);
+        check_golden(&format!("{}.hlsl", name), &hlsl);
+        
+        let wgsl = generate_wgsl::generate_shader(draw_shader_def, const_table, &cx.shader_registry);
+        check_golden(&format!("{}.wgsl", name), &wgsl);
+        checked.push(name);
+    }
+    checked.sort();
+    assert_eq!(checked, ["draw_color", "draw_quad", "draw_text"]);
+}
@@ -0,0 +1,80 @@
+// Runs stock and Sdf2d based draw shaders through the CPU interpreter and
+// checks the resulting pixel colors.
+
+use makepad_draw::{
+    *,
+    makepad_shader_compiler::{
+        DrawShaderPtr,
+        interpret::{ShaderInterpreter, Value},
+    },
+};
+
+live_design!{
+    import makepad_draw::shader::std::*;
+    import makepad_draw::shader::draw_color::DrawColor;
+
+    DrawCircle = <DrawColor> {
+        fn pixel(self) -> vec4 {
+            let sdf = Sdf2d::viewport(self.pos * self.rect_size);
+            sdf.circle(5., 5., 4.5);
+            return sdf.fill(self.color);
+        }
+    }
+}
+
+fn compile() -> (Cx, DrawShaderPtr, DrawShaderPtr) {
+    let mut cx = Cx::new(Box::new( | _, _ | {}));
+    makepad_draw::live_design(&mut cx);
+    crate::live_design(&mut cx);
+    cx.live_expand();
+    let _ = DrawColor::new_local(&mut cx);
+    let circle_ptr = cx.live_registry.borrow().module_id_and_name_to_ptr(
+        LiveModuleId::from_str(module_path!()).unwrap(),
+        live_id!(DrawCircle)
+    ).unwrap();
+    let _ = DrawColor::new_from_ptr(&mut cx, Some(circle_ptr));
+    assert!(cx.draw_shaders.error_set.is_empty(), "draw shaders failed to compile");
+
+    let color_ptr = cx.draw_shaders.ptr_to_item.iter().find( | (_, item) | {
+        cx.draw_shaders.shaders[item.draw_shader_id].type_name == live_id!(DrawColor)
+    }).map( | (ptr, _) | *ptr).unwrap();
+    (cx, color_ptr, DrawShaderPtr(circle_ptr))
+}
+
+fn setup_quad(interpreter: &mut ShaderInterpreter, rect_size: Vec2, color: Vec4) {
+    interpreter.set_field(live_id!(rect_pos), Value::vec2(vec2(0.0, 0.0))).unwrap();
+    interpreter.set_field(live_id!(rect_size), Value::vec2(rect_size)).unwrap();
+    interpreter.set_field(live_id!(draw_clip), Value::vec4(vec4(-1e6, -1e6, 1e6, 1e6))).unwrap();
+    interpreter.set_field(live_id!(color), Value::vec4(color)).unwrap();
+}
+
+fn assert_near(a: Vec4, b: Vec4) {
+    let d = a - b;
+    assert!(d.x.abs().max(d.y.abs()).max(d.z.abs()).max(d.w.abs()) < 1e-3, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn draw_color() {
+    let (cx, color_ptr, _) = compile();
+    let live_registry = cx.live_registry.borrow();
+    let mut interpreter = ShaderInterpreter::new(&live_registry, &cx.shader_registry, color_ptr);
+    setup_quad(&mut interpreter, vec2(10.0, 10.0), vec4(1.0, 0.5, 0.0, 0.5));
+    let color = interpreter.pixel_at(live_id!(geom_pos), vec2(0.5, 0.5), vec2(0.1, 0.1)).unwrap();
+    assert_near(color, vec4(0.5, 0.25, 0.0, 0.5));
+    // the vertex shader wrote the varying
+    assert_eq!(interpreter.field(live_id!(pos)).unwrap().as_vec2(), Some(vec2(0.5, 0.5)));
+}
+
+#[test]
+fn sdf_circle() {
+    let (cx, _, circle_ptr) = compile();
+    let live_registry = cx.live_registry.borrow();
+    let mut interpreter = ShaderInterpreter::new(&live_registry, &cx.shader_registry, circle_ptr);
+    setup_quad(&mut interpreter, vec2(10.0, 10.0), vec4(1.0, 1.0, 1.0, 1.0));
+    let pixels = interpreter.render(live_id!(geom_pos), 10, 10).unwrap();
+    // inside, outside and on the antialiased edge of the circle
+    assert_near(pixels[5 * 10 + 5], vec4(1.0, 1.0, 1.0, 1.0));
+    assert_near(pixels[0], vec4(0.0, 0.0, 0.0, 0.0));
+    // 0.965 inside the edge, times an antialias factor of 1/sqrt(2)
+    assert!((pixels[5 * 10 + 1].w - 0.682).abs() < 1e-3, "edge coverage {}", pixels[5 * 10 + 1].w);
+}
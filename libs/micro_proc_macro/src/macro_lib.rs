@@ -661,7 +661,7 @@ impl TokenParser {
                             if self.eat_punct_alone(',') { // next one
                                 if let Some(add_where) = add_where {
                                     tb.add("+");
-                                    tb.ident(add_where);
+                                    tb.add(add_where);
                                 }
                                 tb.add(",");
                                 break
@@ -669,7 +669,7 @@ impl TokenParser {
                             if self.is_brace() || self.is_punct_alone(';') { // upnext is a brace.. we're done
                                 if let Some(add_where) = add_where {
                                     tb.add("+");
-                                    tb.ident(add_where);
+                                    tb.add(add_where);
                                 }
                                 return Some(tb.end())
                             }
@@ -757,12 +757,13 @@ impl TokenParser {
         let mut tb = TokenBuilder::new();
         // if we have a <, keep running and keep a < stack
         
-        if self.eat_punct_alone('<') {
+        // a < followed by a lifetime or a & is joint
+        if self.eat_punct_any('<') {
             tb.add("<");
             let mut stack = 1;
             // keep eating things till we are at stack 0 for a ">"
             while stack > 0 {
-                if self.eat_punct_alone('<') {
+                if self.eat_punct_any('<') {
                     tb.add("<");
                     stack += 1;
                 }
@@ -808,7 +809,7 @@ impl TokenParser {
     
     pub fn eat_type(&mut self) -> Option<TokenStream> {
         let mut tb = TokenBuilder::new();
-        if self.eat_punct_alone('&'){
+        if self.eat_punct_any('&'){
             tb.add("&");
             if self.eat_punct_any('\''){
                 tb.lifetime_mark();
@@ -1,11 +1,26 @@
-use proc_macro::{TokenStream};
+use proc_macro::{TokenStream, TokenTree};
 use makepad_micro_proc_macro::*;
+use crate::serde_attr::*;
+
+// versioned containers prefix their body with its u64 length, so readers can
+// default trailing fields they don't get and skip trailing data they don't know
+fn ser_bin_versioned_begin(tb: &mut TokenBuilder) {
+    tb.add("let vo = s . len ( ) ; s . extend_from_slice ( & [ 0 ; 8 ] ) ;");
+}
+
+fn ser_bin_versioned_end(tb: &mut TokenBuilder) {
+    tb.add("let vl = ( s . len ( ) - vo - 8 ) as u64 ;");
+    tb.add("s [ vo .. vo + 8 ] . copy_from_slice ( & vl . to_le_bytes ( ) ) ;");
+}
 
 pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
-    parser.eat_attributes();
+
+    let main_attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+        Ok(attr) => attr,
+        Err(err) => return err
+    };
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
@@ -17,21 +32,33 @@ pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
             tb.add("impl").stream(generic.clone());
             tb.add("SerBin for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_bin ( & self , s : & mut Vec < u8 > ) {");
+            if main_attr.versioned {
+                ser_bin_versioned_begin(&mut tb);
+            }
 
             if let Some(types) = types{
                 for i in 0..types.len(){
                      tb.add("self .").unsuf_usize(i).add(". ser_bin ( s ) ;");
                 }
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match serde_fields(fields) {
+                    Ok(fields) => fields,
+                    Err(err) => return err
+                };
                 for field in fields{
-                    tb.add("self .").ident(&field.name).add(". ser_bin ( s ) ;");
+                    if !field.attr.skip {
+                        tb.add("self .").ident(&field.name).add(". ser_bin ( s ) ;");
+                    }
                 }
             }
             else{
                 return parser.unexpected()
             }
-            tb.add("} } ;"); 
+            if main_attr.versioned {
+                ser_bin_versioned_end(&mut tb);
+            }
+            tb.add("} } ;");
             return tb.end();
         }
     }
@@ -39,18 +66,22 @@ pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let where_clause = parser.eat_where_clause(Some("SerBin"));
-            
+
             tb.add("impl").stream(generic.clone());
             tb.add("SerBin for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_bin ( & self , s : & mut Vec < u8 > ) {");
             tb.add("match self {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
             let mut index = 0;
             while !parser.eat_eot(){
-                parser.eat_attributes();
+                let attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+                    Ok(attr) => attr,
+                    Err(err) => return err
+                };
+                let tag = attr.tag.unwrap_or(index);
                 // parse ident
                 if let Some(variant) = parser.eat_any_ident(){
                     if let Some(types) = parser.eat_all_types(){
@@ -58,31 +89,51 @@ pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(",");
                         }
-                        tb.add(") => {").suf_u16(index).add(". ser_bin ( s ) ;");
+                        tb.add(") => {").suf_u16(tag).add(". ser_bin ( s ) ;");
+                        if main_attr.versioned {
+                            ser_bin_versioned_begin(&mut tb);
+                        }
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(". ser_bin ( s ) ;");
                         }
-                        tb.add("}");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match serde_fields(fields) {
+                            Ok(fields) => fields,
+                            Err(err) => return err
+                        };
                         tb.add("Self ::").ident(&variant).add("{");
                         for field in fields.iter(){
                             tb.ident(&field.name).add(",");
                         }
-                        tb.add("} => {").suf_u16(index).add(". ser_bin ( s ) ;");
+                        tb.add("} => {").suf_u16(tag).add(". ser_bin ( s ) ;");
+                        if main_attr.versioned {
+                            ser_bin_versioned_begin(&mut tb);
+                        }
                         for field in fields{
-                            tb.ident(&field.name).add(". ser_bin ( s ) ;");
+                            if field.attr.skip {
+                                tb.add("let _ =").ident(&field.name).add(";");
+                            }
+                            else {
+                                tb.ident(&field.name).add(". ser_bin ( s ) ;");
+                            }
                         }
-                        tb.add("}");
                     }
                     else if parser.is_punct_alone(',') || parser.is_eot(){ // bare variant
                         tb.add("Self ::").ident(&variant).add("=> {");
-                        tb.suf_u16(index).add(". ser_bin ( s ) ; }");
+                        tb.suf_u16(tag).add(". ser_bin ( s ) ;");
+                        if main_attr.versioned {
+                            ser_bin_versioned_begin(&mut tb);
+                        }
                     }
                     else{
                         return parser.unexpected();
                     }
-                    index += 1;
+                    if main_attr.versioned {
+                        ser_bin_versioned_end(&mut tb);
+                    }
+                    tb.add("}");
+                    index = tag + 1;
                     parser.eat_punct_alone(',');
                 }
                 else{
@@ -94,103 +145,251 @@ pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
         }
     }
     return parser.unexpected()
-} 
+}
 
-pub fn derive_de_bin_impl(input: TokenStream) -> TokenStream {
+// the lifetime DeBinBorrow borrows with: the first lifetime parameter, or a new 'de
+fn borrow_generic(generic: &Option<TokenStream>) -> (TokenStream, String) {
+    if let Some(generic) = generic {
+        let mut iter = generic.clone().into_iter();
+        while let Some(tt) = iter.next() {
+            if let TokenTree::Punct(punct) = &tt {
+                if punct.as_char() == '\'' {
+                    if let Some(TokenTree::Ident(ident)) = iter.next() {
+                        return (generic.clone(), ident.to_string())
+                    }
+                }
+            }
+        }
+    }
+    let mut tb = TokenBuilder::new();
+    tb.add("< 'de");
+    if let Some(generic) = generic {
+        tb.add(",");
+        for tt in generic.clone().into_iter().skip(1) {
+            tb.extend(tt);
+        }
+    }
+    else {
+        tb.add(">");
+    }
+    (tb.end(), "de".to_string())
+}
+
+// DeBin and DeBinBorrow only differ in the trait and the lifetime of the input
+struct DeBinKind {
+    borrow: bool
+}
+
+impl DeBinKind {
+    fn impl_header(&self, tb: &mut TokenBuilder, name: &str, generic: Option<TokenStream>, where_clause: Option<TokenStream>) {
+        if self.borrow {
+            let (impl_generic, lifetime) = borrow_generic(&generic);
+            tb.add("impl").stream(Some(impl_generic));
+            tb.add("DeBinBorrow <").add(&format!("'{}", lifetime)).add("> for").ident(name).stream(generic).stream(where_clause);
+            tb.add("{ fn de_bin_borrow ( o : & mut usize , d : &").add(&format!("'{}", lifetime)).add("[ u8 ] )");
+        }
+        else {
+            tb.add("impl").stream(generic.clone());
+            tb.add("DeBin for").ident(name).stream(generic).stream(where_clause);
+            tb.add("{ fn de_bin ( o : & mut usize , d : & [ u8 ] )");
+        }
+        tb.add("-> std :: result :: Result < Self , DeBinErr > {");
+    }
+
+    // the bound added to the where clause predicates, like the derived SerBin and DeBin do
+    fn where_bound(&self, generic: &Option<TokenStream>) -> String {
+        if self.borrow {
+            format!("DeBinBorrow < '{} >", borrow_generic(generic).1)
+        }
+        else {
+            "DeBin".to_string()
+        }
+    }
+
+    fn method(&self) -> &'static str {
+        if self.borrow {"DeBinBorrow :: de_bin_borrow"} else {"DeBin :: de_bin"}
+    }
+
+    // reads the length prefix of a versioned body into `ve`, the offset its data ends at
+    fn versioned_begin(&self, tb: &mut TokenBuilder, name: &str) {
+        tb.add("let ve = { let vl : u64 = DeBin :: de_bin ( o , d ) ? ;");
+        tb.add("if vl > ( d . len ( ) - * o ) as u64 {");
+        tb.add("return std :: result :: Result :: Err ( DeBinErr { o : * o , l : vl as usize , s : d . len ( ) , msg :").string(name).add(". to_string ( ) } ) }");
+        tb.add("* o + vl as usize } ;");
+    }
+
+    // a field value, in a versioned body missing trailing fields get their default
+    fn field(&self, tb: &mut TokenBuilder, versioned: bool, field: Option<&SerdeField>, missing: &str) {
+        if let Some(field) = field {
+            if field.attr.skip {
+                field.attr.default_value(tb);
+                return
+            }
+        }
+        if versioned {
+            tb.add("if * o < ve {").add(self.method()).add("( o , & d [ .. ve ] ) ? } else {");
+            if let Some(field) = field {
+                field.missing_value(tb, missing);
+            }
+            else {
+                tb.add(missing);
+            }
+            tb.add("}");
+        }
+        else {
+            tb.add(self.method()).add("( o , d ) ?");
+        }
+    }
+}
+
+fn de_bin_missing(name: &str) -> String {
+    format!("return std :: result :: Result :: Err ( DeBinErr {{ o : * o , l : 0 , s : d . len ( ) , msg : \"{}\" . to_string ( ) }} )", name)
+}
+
+fn derive_de_bin_kind(input: TokenStream, kind: DeBinKind) -> TokenStream {
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
-    parser.eat_attributes();
+
+    let main_attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+        Ok(attr) => attr,
+        Err(err) => return err
+    };
+    let versioned = main_attr.versioned;
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let types = parser.eat_all_types();
-            let where_clause = parser.eat_where_clause(Some("DeBin"));
+            let where_clause = parser.eat_where_clause(Some(&kind.where_bound(&generic)));
 
-            tb.add("impl").stream(generic.clone());
-            tb.add("DeBin for").ident(&name).stream(generic).stream(where_clause);
-            tb.add("{ fn de_bin ( o : & mut usize , d : & [ u8 ] )");
-            tb.add("-> std :: result :: Result < Self , DeBinErr > { ");
-            tb.add("std :: result :: Result :: Ok ( Self");
+            kind.impl_header(&mut tb, &name, generic, where_clause);
+            if versioned {
+                kind.versioned_begin(&mut tb, &name);
+            }
+            tb.add("let r = Self");
 
             if let Some(types) = types{
                 tb.add("(");
-                for _ in 0..types.len(){
-                     tb.add("DeBin :: de_bin ( o , d ) ? ,");
+                for i in 0..types.len(){
+                    kind.field(&mut tb, versioned, None, &de_bin_missing(&format!("{}.{}", name, i)));
+                    tb.add(",");
                 }
                 tb.add(")");
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match serde_fields(fields) {
+                    Ok(fields) => fields,
+                    Err(err) => return err
+                };
                 tb.add("{");
-                for field in fields{
-                    tb.ident(&field.name).add(": DeBin :: de_bin ( o , d ) ? ,");
+                for field in &fields{
+                    tb.ident(&field.name).add(":");
+                    kind.field(&mut tb, versioned, Some(field), &de_bin_missing(&format!("{}.{}", name, field.name)));
+                    tb.add(",");
                 }
                 tb.add("}");
             }
             else{
                 return parser.unexpected()
             }
-            tb.add(") } } ;"); 
+            tb.add(";");
+            if versioned {
+                tb.add("* o = ve ;");
+            }
+            tb.add("std :: result :: Result :: Ok ( r ) } } ;");
             return tb.end();
         }
     }
     else if parser.eat_ident("enum"){
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
-            let where_clause = parser.eat_where_clause(Some("DeBin"));
-            
-            tb.add("impl").stream(generic.clone());
-            tb.add("DeBin for").ident(&name).stream(generic).stream(where_clause);
-            tb.add("{ fn de_bin ( o : & mut usize , d : & [ u8 ] )");
-            tb.add("-> std :: result :: Result < Self , DeBinErr > {");
+            let where_clause = parser.eat_where_clause(Some(&kind.where_bound(&generic)));
+
+            kind.impl_header(&mut tb, &name, generic, where_clause);
             tb.add("let id : u16 = DeBin :: de_bin ( o , d ) ? ;");
-            tb.add("match id {");
-            
+            if versioned {
+                kind.versioned_begin(&mut tb, &name);
+            }
+            tb.add("let r = match id {");
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
             let mut index = 0;
+            let mut other = None;
             while !parser.eat_eot(){
-                // parse ident
-                parser.eat_attributes();
+                let attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+                    Ok(attr) => attr,
+                    Err(err) => return err
+                };
+                let tag = attr.tag.unwrap_or(index);
                 if let Some(variant) = parser.eat_any_ident(){
-                    tb.suf_u16(index as u16).add("=> {");
+                    tb.suf_u16(tag).add("=> {");
                     tb.add("std :: result :: Result :: Ok ( Self ::");
                     if let Some(types) = parser.eat_all_types(){
                         tb.ident(&variant).add("(");
-                        for _ in 0..types.len(){
-                            tb.add("DeBin :: de_bin ( o , d ) ? ,");
+                        for i in 0..types.len(){
+                            kind.field(&mut tb, versioned, None, &de_bin_missing(&format!("{}::{}.{}", name, variant, i)));
+                            tb.add(",");
                         }
                         tb.add(")");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match serde_fields(fields) {
+                            Ok(fields) => fields,
+                            Err(err) => return err
+                        };
                         tb.ident(&variant).add("{");
                         for field in fields.iter(){
-                            tb.ident(&field.name).add(": DeBin :: de_bin ( o , d ) ? ,");
+                            tb.ident(&field.name).add(":");
+                            kind.field(&mut tb, versioned, Some(field), &de_bin_missing(&format!("{}::{}.{}", name, variant, field.name)));
+                            tb.add(",");
                         }
                         tb.add("}");
                     }
                     else if parser.is_punct_alone(',') || parser.is_eot(){ // bare variant
+                        if attr.other {
+                            other = Some(variant.clone());
+                        }
                         tb.ident(&variant);
                     }
                     else{
                         return parser.unexpected();
                     }
-                    
+
                     tb.add(") }");
-                    index += 1;
+                    index = tag + 1;
                     parser.eat_punct_alone(',');
                 }
                 else{
                     return parser.unexpected()
                 }
-            } 
-            tb.add("_ => std :: result :: Result :: Err ( DeBinErr { o : * o , l :");
-            tb.unsuf_usize(1).add(", s : d . len ( ) , msg : ").string(&name).add(". to_string ( ) } )");
-            tb.add("} } } ;");
+            }
+            // without a length prefix we can't skip the data of unknown variants
+            match other {
+                Some(other) if versioned => {
+                    tb.add("_ => std :: result :: Result :: Ok ( Self ::").ident(&other).add(")");
+                }
+                _ => {
+                    tb.add("_ => std :: result :: Result :: Err ( DeBinErr { o : * o , l :");
+                    tb.unsuf_usize(1).add(", s : d . len ( ) , msg : ").string(&name).add(". to_string ( ) } )");
+                }
+            }
+            tb.add("} ;");
+            if versioned {
+                tb.add("* o = ve ;");
+            }
+            tb.add("r } } ;");
             return tb.end();
         }
     }
     return parser.unexpected()
 }
+
+pub fn derive_de_bin_impl(input: TokenStream) -> TokenStream {
+    derive_de_bin_kind(input, DeBinKind {borrow: false})
+}
+
+pub fn derive_de_bin_borrow_impl(input: TokenStream) -> TokenStream {
+    derive_de_bin_kind(input, DeBinKind {borrow: true})
+}
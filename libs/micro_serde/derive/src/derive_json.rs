@@ -1,16 +1,41 @@
 use proc_macro::{TokenStream};
 use makepad_micro_proc_macro::*;
+use crate::serde_attr::*;
+
+// named fields of the struct itself, or the bindings of a matched enum variant
+fn ser_json_fields(tb: &mut TokenBuilder, fields: &[SerdeField], on_self: bool) {
+    tb.add("s . st_pre ( ) ;");
+    for field in fields{
+        if field.attr.skip {
+            if !on_self {
+                tb.add("let _ =").ident(&field.name).add(";");
+            }
+            continue;
+        }
+        let name = field.attr.name(&field.name);
+        if field.option{
+            tb.add("if let Some ( t ) = ").add(if on_self {"& self ."} else {""}).ident(&field.name).add("{");
+            tb.add("s . field ( d + 1 ,").string(name).add(") ;");
+            tb.add("t . ser_json ( d + 1 , s ) ; s . conl ( ) ; } ;");
+        }
+        else{
+            tb.add("s . field ( d + 1 ,").string(name).add(" ) ;");
+            tb.add(if on_self {"self ."} else {""}).ident(&field.name).add(". ser_json ( d + 1 , s ) ; s . conl ( ) ;");
+        }
+    }
+    tb.add("s . st_post ( d ) ;");
+}
 
 pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
 
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
+
     parser.eat_attributes();
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
-            
+
             let generic = parser.eat_generic();
             let types = parser.eat_all_types();
             let where_clause = parser.eat_where_clause(Some("SerJson"));
@@ -18,7 +43,7 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
             tb.add("impl").stream(generic.clone());
             tb.add("crate::makepad_micro_serde::SerJson for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_json ( & self , d : usize , s : & mut crate::makepad_micro_serde::SerJsonState ) {");
-            
+
             if let Some(types) = types{
                 tb.add("s . out . push (").chr('[').add(") ;");
                 for i in 0..types.len(){
@@ -30,20 +55,12 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
                 tb.add("s . out . push (").chr(']').add(") ;");
             }
             else if let Some(fields) = parser.eat_all_struct_fields(){
-                tb.add("s . st_pre ( ) ;");
                 // named struct
-                for field in fields{
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) = ").add("& self .").ident(&field.name).add("{");
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
-                        tb.add("t . ser_json ( d + 1 , s ) ; s . conl ( ) ; } ;");
-                    }
-                    else{
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
-                        tb.add("self .").ident(&field.name).add(". ser_json ( d + 1 , s ) ; s . conl ( ) ;");
-                    }
-                }
-                tb.add("s . st_post ( d ) ;");
+                let fields = match serde_fields(fields) {
+                    Ok(fields) => fields,
+                    Err(err) => return err
+                };
+                ser_json_fields(&mut tb, &fields, true);
             }
             else{
                 return parser.unexpected()
@@ -62,26 +79,29 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
             tb.add("{ fn ser_json ( & self , d : usize , s : & mut  crate::makepad_micro_serde::SerJsonState ) {");
             tb.add("s . out . push (").chr('{').add(") ;");
             tb.add("match self {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
 
             while !parser.eat_eot(){
                 // parse ident
-                parser.eat_attributes();
+                let attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+                    Ok(attr) => attr,
+                    Err(err) => return err
+                };
                 if let Some(variant) = parser.eat_any_ident(){
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("Self ::").ident(&variant).add("(");
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(",");
                         }
                         tb.add(") => {");
-                        tb.add("s . label (").string(&variant).add(") ;");
+                        tb.add("s . label (").string(attr.name(&variant)).add(") ;");
                         tb.add("s . out . push (").chr(':').add(") ;");
                         tb.add("s . out . push (").chr('[').add(") ;");
-                        
+
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(". ser_json ( d , s ) ;");
                             if i != types.len() - 1{
@@ -92,32 +112,24 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
                         tb.add("}");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match serde_fields(fields) {
+                            Ok(fields) => fields,
+                            Err(err) => return err
+                        };
                         tb.add("Self ::").ident(&variant).add("{");
                         for field in fields.iter(){
                             tb.ident(&field.name).add(",");
                         }
                         tb.add("} => {");
-                        
-                        tb.add("s . label (").string(&variant).add(") ;");
+
+                        tb.add("s . label (").string(attr.name(&variant)).add(") ;");
                         tb.add("s . out . push (").chr(':').add(") ;");
-                        tb.add("s . st_pre ( ) ;");
-                        
-                        for field in fields{
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if let Some ( t ) = ").ident(&field.name).add("{");
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
-                                tb.add("t . ser_json ( d + 1 , s ) ; s . conl ( ) ; } ;");
-                            }
-                            else{
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
-                                tb.ident(&field.name).add(". ser_json ( d + 1 , s ) ; s . conl ( ) ;");
-                            }
-                        }
-                        tb.add("s . st_post ( d ) ; }");
+                        ser_json_fields(&mut tb, &fields, false);
+                        tb.add("}");
                     }
                     else if parser.is_punct_alone(',') || parser.is_eot(){ // bare variant
                         tb.add("Self ::").ident(&variant).add("=> {");
-                        tb.add("s . label (").string(&variant).add(") ;");
+                        tb.add("s . label (").string(attr.name(&variant)).add(") ;");
                         tb.add("s . out . push_str (").string(":[]").add(") ; }");
                     }
                     else{
@@ -138,11 +150,55 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
     return parser.unexpected()
 }
 
+// parses a { } object into the fields, leaves `_name` variables with the values
+fn de_json_fields(tb: &mut TokenBuilder, fields: &[SerdeField], versioned: bool) {
+    tb.add("s . curly_open ( i ) ? ;");
+    for field in fields{
+        if !field.attr.skip {
+            tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
+        }
+    }
+    tb.add("while let Some ( _ ) = s . next_str ( ) {");
+    tb.add("match s . strbuf . as_ref ( ) {");
+    for field in fields{
+        if !field.attr.skip {
+            tb.string(field.attr.name(&field.name)).add("=> { s . next_colon ( i ) ? ;");
+            tb.ident(&format!("_{}",field.name)).add("= Some ( crate::makepad_micro_serde ::DeJson :: de_json ( s , i ) ? ) ; } ,");
+        }
+    }
+    if versioned {
+        tb.add("_ => { s . next_colon ( i ) ? ; s . skip_value ( i ) ? ; }");
+    }
+    else {
+        tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
+    }
+    tb.add("} ; s . eat_comma_curly ( i ) ? ;");
+    tb.add("} ; s . curly_close ( i ) ? ;");
+}
+
+fn de_json_field_values(tb: &mut TokenBuilder, fields: &[SerdeField]) {
+    for field in fields{
+        tb.ident(&field.name).add(":");
+        if field.attr.skip {
+            field.attr.default_value(tb);
+        }
+        else {
+            tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name)).add("{ t } else {");
+            field.missing_value(tb, &format!("return Err ( s . err_nf ( \"{}\" ) )", field.attr.name(&field.name)));
+            tb.add("}");
+        }
+        tb.add(",");
+    }
+}
+
 pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
-    parser.eat_attributes();
+
+    let main_attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+        Ok(attr) => attr,
+        Err(err) => return err
+    };
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
@@ -166,45 +222,25 @@ pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
                 tb.add("s . block_close ( i ) ? ;");
                 tb.add("std :: result :: Result :: Ok ( r )");
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                tb.add("s . curly_open ( i ) ? ;");
-                for field in &fields{
-                    tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
-                }
-                tb.add("while let Some ( _ ) = s . next_str ( ) {");
-                tb.add("match s . strbuf . as_ref ( ) {");
-                for field in &fields{
-                    tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                    tb.ident(&format!("_{}",field.name)).add("= Some ( crate::makepad_micro_serde ::DeJson :: de_json ( s , i ) ? ) ; } ,");
-                }
-                tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
-                tb.add("} ; s . eat_comma_curly ( i ) ? ;");
-                tb.add("} ; s . curly_close ( i ) ? ;");
-                
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match serde_fields(fields) {
+                    Ok(fields) => fields,
+                    Err(err) => return err
+                };
+                de_json_fields(&mut tb, &fields, main_attr.versioned);
                 tb.add("std :: result :: Result :: Ok ( Self {");
-                for field in fields{
-                    tb.ident(&field.name).add(":");
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { None } ,");
-                    }
-                    else{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { return Err ( s . err_nf (");
-                        tb.string(&field.name).add(") ) } ,");
-                    }
-                }
+                de_json_field_values(&mut tb, &fields);
                 tb.add("} )");
             }
             else{
                 return parser.unexpected()
             }
-            tb.add("} } ;"); 
+            tb.add("} } ;");
             return tb.end();
         }
     }
     else if parser.eat_ident("enum"){
-        
+
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let where_clause = parser.eat_where_clause(Some("DeJson"));
@@ -217,17 +253,21 @@ pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
             tb.add("let _ = s . string ( i ) ? ;");
             tb.add("s . colon ( i ) ? ;");
             tb.add("let r = std :: result :: Result :: Ok ( match s . strbuf . as_ref ( ) {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
+            let mut other = None;
             while !parser.eat_eot(){
                 // parse ident
-                parser.eat_attributes();
+                let attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+                    Ok(attr) => attr,
+                    Err(err) => return err
+                };
                 if let Some(variant) = parser.eat_any_ident(){
-                    tb.string(&variant).add("=> {");
+                    tb.string(attr.name(&variant)).add("=> {");
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("s . block_open ( i ) ? ;");
                         tb.add("let r = Self ::").ident(&variant).add("(");
                         for _ in 0..types.len(){
@@ -237,42 +277,25 @@ pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
                         tb.add("s . block_close ( i ) ? ; r");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
-                        tb.add("s . curly_open ( i ) ? ;");
-                        for field in &fields{
-                            tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
-                        }
-                        tb.add("while let Some ( _ ) = s . next_str ( ) {");
-                        tb.add("match s . strbuf . as_ref ( ) {");
-                        for field in &fields{
-                            tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                            tb.ident(&format!("_{}",field.name)).add("= Some (crate::makepad_micro_serde ::DeJson :: de_json ( s , i ) ? ) ; } ,");
-                        }
-                        tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
-                        tb.add("} s . eat_comma_curly ( i ) ? ;");
-                        tb.add("} s . curly_close ( i ) ? ;");
-                        
+                        let fields = match serde_fields(fields) {
+                            Ok(fields) => fields,
+                            Err(err) => return err
+                        };
+                        de_json_fields(&mut tb, &fields, main_attr.versioned);
                         tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields{
-                            tb.ident(&field.name).add(":");
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { None } ,");
-                            }
-                            else{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { return Err ( s . err_nf (");
-                                tb.string(&field.name).add(") ) } ,");
-                            }
-                        }
+                        de_json_field_values(&mut tb, &fields);
                         tb.add("}");
                     }
                     else if parser.is_punct_alone(',') || parser.is_eot(){ // bare variant
+                        if attr.other {
+                            other = Some(variant.clone());
+                        }
                         tb.add("s . block_open ( i ) ? ; s . block_close ( i ) ? ; Self ::").ident(&variant);
                     }
                     else{
                         return parser.unexpected();
                     }
-                    
+
                     tb.add("}");
                     parser.eat_punct_alone(',');
                 }
@@ -280,7 +303,12 @@ pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
                     return parser.unexpected()
                 }
             }
-            tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
+            if let Some(other) = other {
+                tb.add("_ => { s . skip_value ( i ) ? ; Self ::").ident(&other).add("}");
+            }
+            else {
+                tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
+            }
             tb.add("} ) ; s . curly_close ( i ) ? ; r } }");
             return tb.end();
         }
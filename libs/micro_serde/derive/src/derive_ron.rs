@@ -1,17 +1,17 @@
- 
 use proc_macro::{TokenStream};
 use makepad_micro_proc_macro::*;
+use crate::serde_attr::*;
 
 pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
 
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
+
     parser.eat_attributes();
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
-            
+
             let generic = parser.eat_generic();
             let types = parser.eat_all_types();
             let where_clause = parser.eat_where_clause(Some("SerRon"));
@@ -20,7 +20,7 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("SerRon for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{");
             tb.add("fn ser_ron(&self, d: usize, s: &mut SerRonState){");
-            
+
             if let Some(types) = types{
                 tb.add("s.out.push(").chr('(').add(");");
                 for i in 0..types.len(){
@@ -31,17 +31,24 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
                 }
                 tb.add("s.out.push(").chr(')').add(");");
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match serde_fields(fields) {
+                    Ok(fields) => fields,
+                    Err(err) => return err
+                };
                 tb.add("s.st_pre( ) ;");
                 // named struct
                 for field in fields{
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
+                    if field.attr.skip {
+                        continue;
+                    }
+                    if field.option{
                         tb.add("if let Some ( t ) = ").add("& self .").ident(&field.name).add("{");
-                        tb.add("s.field ( d + 1 ,").string(&field.name).add(") ;");
+                        tb.add("s.field ( d + 1 ,").string(field.attr.name(&field.name)).add(") ;");
                         tb.add("t.ser_ron ( d + 1 , s ) ; s . conl ( ) ; } ;");
                     }
                     else{
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
+                        tb.add("s . field ( d + 1 ,").string(field.attr.name(&field.name)).add(" ) ;");
                         tb.add("self .").ident(&field.name).add(". ser_ron ( d + 1 , s ) ; s . conl ( ) ;");
                     }
                 }
@@ -63,25 +70,28 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("SerRon for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_ron ( & self , d : usize , s : & mut  SerRonState ) {");
             tb.add("match self {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
 
             while !parser.eat_eot(){
                 // parse ident
-                parser.eat_attributes();
+                let attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+                    Ok(attr) => attr,
+                    Err(err) => return err
+                };
                 if let Some(variant) = parser.eat_any_ident(){
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("Self ::").ident(&variant).add("(");
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(",");
                         }
                         tb.add(") => {");
-                        tb.add("s . out . push_str (").string(&variant).add(") ;");
+                        tb.add("s . out . push_str (").string(attr.name(&variant)).add(") ;");
                         tb.add("s . out . push (").chr('(').add(") ;");
-                        
+
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(". ser_ron ( d , s ) ;");
                             if i != types.len() - 1{
@@ -92,23 +102,30 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
                         tb.add("}");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match serde_fields(fields) {
+                            Ok(fields) => fields,
+                            Err(err) => return err
+                        };
                         tb.add("Self ::").ident(&variant).add("{");
                         for field in fields.iter(){
                             tb.ident(&field.name).add(",");
                         }
                         tb.add("} => {");
-                        
-                        tb.add("s . out . push_str (").string(&variant).add(") ;");
+
+                        tb.add("s . out . push_str (").string(attr.name(&variant)).add(") ;");
                         tb.add("s . st_pre ( ) ;");
-                        
+
                         for field in fields{
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
+                            if field.attr.skip {
+                                tb.add("let _ =").ident(&field.name).add(";");
+                            }
+                            else if field.option{
                                 tb.add("if ").ident(&field.name).add(". is_some ( ) {");
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
+                                tb.add("s . field ( d + 1 ,").string(field.attr.name(&field.name)).add(") ;");
                                 tb.ident(&field.name).add(" . ser_ron ( d + 1 , s ) ; s . conl ( ) ; } ;");
                             }
                             else{
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
+                                tb.add("s . field ( d + 1 ,").string(field.attr.name(&field.name)).add(" ) ;");
                                 tb.ident(&field.name).add(". ser_ron ( d + 1 , s ) ; s . conl ( ) ;");
                             }
                         }
@@ -116,7 +133,7 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
                     }
                     else if parser.is_punct_alone(',') || parser.is_eot(){ // bare variant
                         tb.add("Self ::").ident(&variant).add("=> {");
-                        tb.add("s . out . push_str (").string(&variant).add(") ; }");
+                        tb.add("s . out . push_str (").string(attr.name(&variant)).add(") ; }");
                     }
                     else{
                         return parser.unexpected();
@@ -135,10 +152,54 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
     return parser.unexpected()
 }
 
+// parses a ( ) of named fields, leaves `_name` variables with the values
+fn de_ron_fields(tb: &mut TokenBuilder, fields: &[SerdeField], versioned: bool) {
+    tb.add("s . paren_open ( i ) ? ;");
+    for field in fields{
+        if !field.attr.skip {
+            tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
+        }
+    }
+    tb.add("while let Some ( _ ) = s . next_ident ( ) {");
+    tb.add("match s . identbuf . as_ref ( ) {");
+    for field in fields{
+        if !field.attr.skip {
+            tb.string(field.attr.name(&field.name)).add("=> { s . next_colon ( i ) ? ;");
+            tb.ident(&format!("_{}",field.name)).add("= Some ( DeRon :: de_ron ( s , i ) ? ) ; } ,");
+        }
+    }
+    if versioned {
+        tb.add("_ => { s . next_colon ( i ) ? ; s . skip_value ( i ) ? ; }");
+    }
+    else {
+        tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . identbuf ) )");
+    }
+    tb.add("} ; s . eat_comma_paren ( i ) ? ;");
+    tb.add("} ; s . paren_close ( i ) ? ;");
+}
+
+fn de_ron_field_values(tb: &mut TokenBuilder, fields: &[SerdeField]) {
+    for field in fields{
+        tb.ident(&field.name).add(":");
+        if field.attr.skip {
+            field.attr.default_value(tb);
+        }
+        else {
+            tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name)).add("{ t } else {");
+            field.missing_value(tb, &format!("return Err ( s . err_nf ( \"{}\" ) )", field.attr.name(&field.name)));
+            tb.add("}");
+        }
+        tb.add(",");
+    }
+}
+
 pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    parser.eat_attributes();
+    let main_attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+        Ok(attr) => attr,
+        Err(err) => return err
+    };
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
@@ -162,45 +223,25 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
                 tb.add("s . paren_close ( i ) ? ;");
                 tb.add("std :: result :: Result :: Ok ( r ) ");
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                tb.add("s . paren_open ( i ) ? ;");
-                for field in &fields{
-                    tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
-                }
-                tb.add("while let Some ( _ ) = s . next_ident ( ) {");
-                tb.add("match s . identbuf . as_ref ( ) {");
-                for field in &fields{
-                    tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                    tb.ident(&format!("_{}",field.name)).add("= Some ( DeRon :: de_ron ( s , i ) ? ) ; } ,");
-                }
-                tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . identbuf ) )");
-                tb.add("} ; s . eat_comma_paren ( i ) ? ;");
-                tb.add("} ; s . paren_close ( i ) ? ;");
-                
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match serde_fields(fields) {
+                    Ok(fields) => fields,
+                    Err(err) => return err
+                };
+                de_ron_fields(&mut tb, &fields, main_attr.versioned);
                 tb.add("std :: result :: Result :: Ok ( Self {");
-                for field in fields{
-                    tb.ident(&field.name).add(":");
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { None } ,");
-                    }
-                    else{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { return Err ( s . err_nf (");
-                        tb.string(&field.name).add(") ) } ,");
-                    }
-                }
+                de_ron_field_values(&mut tb, &fields);
                 tb.add("} )");
             }
             else{
                 return parser.unexpected()
             }
-            tb.add("} } ;"); 
+            tb.add("} } ;");
             return tb.end();
         }
     }
     else if parser.eat_ident("enum"){
-         
+
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let where_clause = parser.eat_where_clause(Some("DeRon"));
@@ -208,20 +249,24 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("impl").stream(generic.clone());
             tb.add("DeRon for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn de_ron ( s : & mut  DeRonState , i : & mut std :: str :: Chars )");
-            tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeRonErr > { ");
+            tb.add("-> std :: result :: Result < Self , DeRonErr > { ");
             tb.add("s . ident ( i ) ? ;");
             tb.add("std :: result :: Result :: Ok ( match s . identbuf . as_ref ( ) {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
+            let mut other = None;
             while !parser.eat_eot(){
                 // parse ident
-                parser.eat_attributes();
+                let attr = match SerdeAttr::parse(&parser.eat_attributes()) {
+                    Ok(attr) => attr,
+                    Err(err) => return err
+                };
                 if let Some(variant) = parser.eat_any_ident(){
-                    tb.string(&variant).add("=> {");
+                    tb.string(attr.name(&variant)).add("=> {");
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("s . paren_open ( i ) ? ;");
                         tb.add("let r = Self ::").ident(&variant).add("(");
                         for _ in 0..types.len(){
@@ -231,50 +276,38 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
                         tb.add("s . paren_close ( i ) ? ; r");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
-                        tb.add("s . paren_open ( i ) ? ;");
-                        for field in &fields{
-                            tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
-                        }
-                        tb.add("while let Some ( _ ) = s . next_ident ( ) {");
-                        tb.add("match s . identbuf . as_ref ( ) {");
-                        for field in &fields{
-                            tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                            tb.ident(&format!("_{}",field.name)).add("= Some ( DeRon :: de_ron ( s , i ) ? ) ; } ,");
-                        }
-                        tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
-                        tb.add("} ; s . eat_comma_paren ( i ) ? ;");
-                        tb.add("} ; s . paren_close ( i ) ? ;");
-                        
+                        let fields = match serde_fields(fields) {
+                            Ok(fields) => fields,
+                            Err(err) => return err
+                        };
+                        de_ron_fields(&mut tb, &fields, main_attr.versioned);
                         tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields{
-                            tb.ident(&field.name).add(":");
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { None } ,");
-                            }
-                            else{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { return Err ( s . err_nf (");
-                                tb.string(&field.name).add(") ) } ,");
-                            }
-                        }
+                        de_ron_field_values(&mut tb, &fields);
                         tb.add("}");
                     }
                     else if parser.is_punct_alone(',') || parser.is_eot(){ // bare variant
+                        if attr.other {
+                            other = Some(variant.clone());
+                        }
                         tb.add("Self ::").ident(&variant);
                     }
                     else{
                         return parser.unexpected();
                     }
-                    
+
                     tb.add("}");
                     parser.eat_punct_alone(',');
                 }
                 else{
                     return parser.unexpected()
                 }
-            } 
-            tb.add("_ => return std :: result :: Result :: Err ( s . err_enum ( & s . identbuf ) )");
+            }
+            if let Some(other) = other {
+                tb.add("_ => { s . skip_paren_group ( i ) ? ; Self ::").ident(&other).add("}");
+            }
+            else {
+                tb.add("_ => return std :: result :: Result :: Err ( s . err_enum ( & s . identbuf ) )");
+            }
             tb.add("} ) } }");
            return tb.end();
         }
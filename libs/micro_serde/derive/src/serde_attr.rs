@@ -0,0 +1,127 @@
+use proc_macro::{TokenStream};
+use makepad_micro_proc_macro::*;
+
+// #[serde(...)] attributes shared by all the derives:
+// fields:     default, default = "path::to::fn", rename = "name", skip
+// variants:   rename = "name", tag = 3, other
+// containers: versioned
+#[derive(Default)]
+pub struct SerdeAttr {
+    pub default: bool,
+    pub default_fn: Option<String>,
+    pub rename: Option<String>,
+    pub skip: bool,
+    pub tag: Option<u16>,
+    pub other: bool,
+    pub versioned: bool,
+}
+
+fn unquote(lit: &str) -> Option<String> {
+    if lit.len() >= 2 && lit.starts_with('"') && lit.ends_with('"') {
+        return Some(lit[1..lit.len() - 1].to_string())
+    }
+    None
+}
+
+impl SerdeAttr {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self, TokenStream> {
+        let mut out = SerdeAttr::default();
+        for attr in attrs {
+            if attr.name != "serde" {
+                continue;
+            }
+            let args = match &attr.args {
+                Some(args) => args.clone(),
+                None => return Err(error("expected #[serde(...)]"))
+            };
+            let mut parser = TokenParser::new(args);
+            while !parser.eat_eot() {
+                let key = match parser.eat_any_ident() {
+                    Some(key) => key,
+                    None => return Err(error("expected a serde attribute name"))
+                };
+                let value = if parser.eat_punct_alone('=') {
+                    match parser.eat_literal() {
+                        Some(lit) => Some(lit.to_string()),
+                        None => return Err(error(&format!("expected a literal after serde({} =", key)))
+                    }
+                }
+                else {
+                    None
+                };
+                match (key.as_ref(), value) {
+                    ("default", None) => out.default = true,
+                    ("default", Some(lit)) => {
+                        out.default = true;
+                        out.default_fn = Some(unquote(&lit).ok_or_else( || error("serde(default = ...) needs a string"))?);
+                    }
+                    ("rename", Some(lit)) => {
+                        out.rename = Some(unquote(&lit).ok_or_else( || error("serde(rename = ...) needs a string"))?);
+                    }
+                    ("skip", None) => out.skip = true,
+                    ("tag", Some(lit)) => {
+                        out.tag = Some(lit.parse().map_err( | _ | error("serde(tag = ...) needs a u16"))?);
+                    }
+                    ("other", None) => out.other = true,
+                    ("versioned", None) => out.versioned = true,
+                    (key, _) => return Err(error(&format!("unknown serde attribute {}", key)))
+                }
+                parser.eat_punct_alone(',');
+            }
+        }
+        Ok(out)
+    }
+
+    // the name used for a field or variant in json and ron
+    pub fn name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.rename.as_deref().unwrap_or(name)
+    }
+
+    // the value for a skipped or missing defaulted field
+    pub fn default_value(&self, tb: &mut TokenBuilder) {
+        if let Some(default_fn) = &self.default_fn {
+            tb.stream(default_fn.parse().ok()).add("( )");
+        }
+        else {
+            tb.add("std :: default :: Default :: default ( )");
+        }
+    }
+}
+
+pub fn is_option(field: &StructField) -> bool {
+    field.ty.clone().into_iter().next().unwrap().to_string() == "Option"
+}
+
+// a struct field with its parsed serde attributes
+pub struct SerdeField {
+    pub name: String,
+    pub option: bool,
+    pub attr: SerdeAttr,
+}
+
+pub fn serde_fields(fields: Vec<StructField>) -> Result<Vec<SerdeField>, TokenStream> {
+    let mut out = Vec::new();
+    for field in fields {
+        out.push(SerdeField {
+            option: is_option(&field),
+            attr: SerdeAttr::parse(&field.attrs)?,
+            name: field.name,
+        });
+    }
+    Ok(out)
+}
+
+impl SerdeField {
+    // the value for a field missing from the input, or an early error return
+    pub fn missing_value(&self, tb: &mut TokenBuilder, missing_error: &str) {
+        if self.attr.default || self.attr.skip {
+            self.attr.default_value(tb);
+        }
+        else if self.option {
+            tb.add("None");
+        }
+        else {
+            tb.add(missing_error);
+        }
+    }
+}
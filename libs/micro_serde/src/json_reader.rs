@@ -0,0 +1,545 @@
+use std::io::{self, Read};
+use crate::serde_json::*;
+use crate::json_value::*;
+
+/// A token of a JSON document read by `JsonReader`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonToken {
+    ObjectStart,
+    ObjectEnd,
+    ArrayStart,
+    ArrayEnd,
+    Key(String),
+    String(String),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum JsonExpect {
+    TopLevel,
+    Value,
+    ValueOrArrayEnd,
+    KeyOrObjectEnd,
+    Key,
+    Colon,
+    CommaOrEnd,
+}
+
+const BUFFER_SIZE: usize = 16 * 1024;
+
+// -?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?
+fn is_json_number(num: &[u8]) -> bool {
+    fn digits(num: &[u8], o: &mut usize) -> usize {
+        let start = *o;
+        while *o < num.len() && num[*o].is_ascii_digit() {
+            *o += 1;
+        }
+        *o - start
+    }
+    let mut o = 0;
+    if num.first() == Some(&b'-') {
+        o += 1;
+    }
+    let int_start = o;
+    let int_len = digits(num, &mut o);
+    if int_len == 0 || int_len > 1 && num[int_start] == b'0' {
+        return false
+    }
+    if o < num.len() && num[o] == b'.' {
+        o += 1;
+        if digits(num, &mut o) == 0 {
+            return false
+        }
+    }
+    if o < num.len() && (num[o] == b'e' || num[o] == b'E') {
+        o += 1;
+        if o < num.len() && (num[o] == b'+' || num[o] == b'-') {
+            o += 1;
+        }
+        if digits(num, &mut o) == 0 {
+            return false
+        }
+    }
+    o == num.len()
+}
+
+/// A pull parser that reads JSON from a `Read` a token at a time, so large or endless
+/// inputs (like a line per message from `rustc --message-format=json`) don't need to be
+/// in memory as a whole. Any number of whitespace separated top level values can follow
+/// each other. Errors carry the line and column they happened at.
+///
+/// ```
+/// use makepad_micro_serde::*;
+///
+/// let input = "{\"reason\":\"compiler-message\",\"id\":[1,2]}\n{\"reason\":\"build-finished\"}";
+/// let mut reader = JsonReader::new(input.as_bytes());
+/// assert_eq!(reader.next_token().unwrap(), Some(JsonToken::ObjectStart));
+/// assert_eq!(reader.next_token().unwrap(), Some(JsonToken::Key("reason".to_string())));
+/// reader.skip_value().unwrap();
+/// assert_eq!(reader.next_token().unwrap(), Some(JsonToken::Key("id".to_string())));
+/// assert_eq!(reader.read_value().unwrap().unwrap()[1].as_u64(), Some(2));
+/// assert_eq!(reader.next_token().unwrap(), Some(JsonToken::ObjectEnd));
+/// let value = reader.read_value().unwrap().unwrap();
+/// assert_eq!(value["reason"].as_str(), Some("build-finished"));
+/// assert_eq!(reader.read_value().unwrap(), None);
+/// ```
+pub struct JsonReader<R: Read> {
+    input: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    len: usize,
+    line: usize,
+    col: usize,
+    // true for an object, false for an array
+    stack: Vec<bool>,
+    expect: JsonExpect,
+    strbuf: Vec<u8>,
+    numbuf: String,
+}
+
+impl<R: Read> JsonReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            buffer: vec![0; BUFFER_SIZE],
+            pos: 0,
+            len: 0,
+            line: 0,
+            col: 0,
+            stack: Vec::new(),
+            expect: JsonExpect::TopLevel,
+            strbuf: Vec::new(),
+            numbuf: String::new(),
+        }
+    }
+
+    /// The zero based line of the next character.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The zero based column of the next character.
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    /// How many objects and arrays we are inside of.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    fn err(&self, msg: &str) -> DeJsonErr {
+        DeJsonErr {msg: msg.to_string(), line: self.line, col: self.col}
+    }
+
+    fn err_io(&self, err: io::Error) -> DeJsonErr {
+        self.err(&format!("Read error {}", err))
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, DeJsonErr> {
+        if self.pos == self.len {
+            self.pos = 0;
+            self.len = loop {
+                match self.input.read(&mut self.buffer) {
+                    Ok(len) => break len,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(self.err_io(e))
+                }
+            };
+            if self.len == 0 {
+                return Ok(None)
+            }
+        }
+        Ok(Some(self.buffer[self.pos]))
+    }
+
+    fn bump(&mut self) {
+        let c = self.buffer[self.pos];
+        self.pos += 1;
+        if c == b'\n' {
+            self.line += 1;
+            self.col = 0;
+        }
+        else if c & 0xc0 != 0x80 { // count utf8 characters, not bytes
+            self.col += 1;
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<u8, DeJsonErr> {
+        match self.peek() ? {
+            Some(c) => {
+                self.bump();
+                Ok(c)
+            }
+            None => Err(self.err("Unexpected end of input"))
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, DeJsonErr> {
+        while let Some(c) = self.peek() ? {
+            match c {
+                b' ' | b'\t' | b'\n' | b'\r' => self.bump(),
+                _ => return Ok(Some(c))
+            }
+        }
+        Ok(None)
+    }
+
+    fn after_value(&mut self) {
+        self.expect = if self.stack.is_empty() {JsonExpect::TopLevel} else {JsonExpect::CommaOrEnd};
+    }
+
+    /// The next token, `None` at the end of the input between top level values.
+    pub fn next_token(&mut self) -> Result<Option<JsonToken>, DeJsonErr> {
+        loop {
+            let c = match self.skip_whitespace() ? {
+                Some(c) => c,
+                None if self.expect == JsonExpect::TopLevel => return Ok(None),
+                None => return Err(self.err("Unexpected end of input"))
+            };
+            match self.expect {
+                JsonExpect::Colon => {
+                    if c != b':' {
+                        return Err(self.err("Expected :"))
+                    }
+                    self.bump();
+                    self.expect = JsonExpect::Value;
+                }
+                JsonExpect::CommaOrEnd => {
+                    let in_object = *self.stack.last().unwrap();
+                    match c {
+                        b',' => {
+                            self.bump();
+                            self.expect = if in_object {JsonExpect::Key} else {JsonExpect::Value};
+                        }
+                        b'}' if in_object => return self.end_container(JsonToken::ObjectEnd),
+                        b']' if !in_object => return self.end_container(JsonToken::ArrayEnd),
+                        _ => return Err(self.err(if in_object {"Expected , or }"} else {"Expected , or ]"}))
+                    }
+                }
+                JsonExpect::KeyOrObjectEnd | JsonExpect::Key => {
+                    if c == b'}' && self.expect == JsonExpect::KeyOrObjectEnd {
+                        return self.end_container(JsonToken::ObjectEnd)
+                    }
+                    if c != b'"' {
+                        return Err(self.err("Expected a key string"))
+                    }
+                    let key = self.read_string() ?;
+                    self.expect = JsonExpect::Colon;
+                    return Ok(Some(JsonToken::Key(key)))
+                }
+                JsonExpect::ValueOrArrayEnd if c == b']' => {
+                    return self.end_container(JsonToken::ArrayEnd)
+                }
+                JsonExpect::TopLevel | JsonExpect::Value | JsonExpect::ValueOrArrayEnd => {
+                    return self.read_value_token(c).map(Some)
+                }
+            }
+        }
+    }
+
+    fn end_container(&mut self, token: JsonToken) -> Result<Option<JsonToken>, DeJsonErr> {
+        self.bump();
+        self.stack.pop();
+        self.after_value();
+        Ok(Some(token))
+    }
+
+    fn read_value_token(&mut self, c: u8) -> Result<JsonToken, DeJsonErr> {
+        let token = match c {
+            b'{' => {
+                self.bump();
+                self.stack.push(true);
+                self.expect = JsonExpect::KeyOrObjectEnd;
+                return Ok(JsonToken::ObjectStart)
+            }
+            b'[' => {
+                self.bump();
+                self.stack.push(false);
+                self.expect = JsonExpect::ValueOrArrayEnd;
+                return Ok(JsonToken::ArrayStart)
+            }
+            b'"' => JsonToken::String(self.read_string() ?),
+            b'-' | b'0'..=b'9' => self.read_number() ?,
+            b'a'..=b'z' => {
+                let (line, col) = (self.line, self.col);
+                self.numbuf.clear();
+                while let Some(c @ b'a'..=b'z') = self.peek() ? {
+                    self.numbuf.push(c as char);
+                    self.bump();
+                }
+                match self.numbuf.as_ref() {
+                    "true" => JsonToken::Bool(true),
+                    "false" => JsonToken::Bool(false),
+                    "null" => JsonToken::Null,
+                    _ => return Err(DeJsonErr {msg: format!("Unexpected {}", self.numbuf), line, col})
+                }
+            }
+            _ => return Err(self.err(&format!("Unexpected character {:?}", c as char)))
+        };
+        self.after_value();
+        Ok(token)
+    }
+
+    fn read_number(&mut self) -> Result<JsonToken, DeJsonErr> {
+        let (line, col) = (self.line, self.col);
+        self.numbuf.clear();
+        while let Some(c @ (b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) = self.peek() ? {
+            self.numbuf.push(c as char);
+            self.bump();
+        }
+        let num = &self.numbuf;
+        if !is_json_number(num.as_bytes()) {
+            return Err(DeJsonErr {msg: format!("Cannot parse number {}", num), line, col})
+        }
+        if !num.contains(['.', 'e', 'E']) {
+            if num.starts_with('-') {
+                if let Ok(v) = num.parse() {
+                    return Ok(JsonToken::I64(v))
+                }
+            }
+            else if let Ok(v) = num.parse() {
+                return Ok(JsonToken::U64(v))
+            }
+        }
+        // integers that don't fit 64 bits become floats
+        Ok(JsonToken::F64(num.parse().unwrap()))
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, DeJsonErr> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let c = self.next_byte() ?;
+            value = value * 16 + (c as char).to_digit(16).ok_or_else( || self.err("Invalid unicode escape")) ?;
+        }
+        Ok(value)
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut bytes = [0; 4];
+        self.strbuf.extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+    }
+
+    fn read_string(&mut self) -> Result<String, DeJsonErr> {
+        let (line, col) = (self.line, self.col);
+        self.bump();
+        self.strbuf.clear();
+        // a high surrogate waits for the escape after it, which may be its low half
+        let mut high_surrogate: Option<u32> = None;
+        loop {
+            let c = self.next_byte() ?;
+            let code = if c == b'\\' && self.peek() ? == Some(b'u') {
+                self.bump();
+                Some(self.read_hex4() ?)
+            }
+            else {
+                None
+            };
+            if let Some(high) = high_surrogate.take() {
+                if let Some(low @ 0xdc00..=0xdfff) = code {
+                    self.push_char(char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).unwrap());
+                    continue;
+                }
+                // lone surrogates
+                self.push_char('\u{fffd}');
+            }
+            if let Some(code) = code {
+                if (0xd800..0xdc00).contains(&code) {
+                    high_surrogate = Some(code);
+                }
+                else {
+                    self.push_char(char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+                continue;
+            }
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let c = match self.next_byte() ? {
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'/' => '/',
+                        b'\\' => '\\',
+                        b'"' => '"',
+                        _ => return Err(self.err("Invalid escape"))
+                    };
+                    self.push_char(c);
+                }
+                0..=0x1f => return Err(self.err("Control character in string")),
+                _ => self.strbuf.push(c)
+            }
+        }
+        match String::from_utf8(std::mem::take(&mut self.strbuf)) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(DeJsonErr {msg: "Invalid utf8 in string".to_string(), line, col})
+        }
+    }
+
+    /// Reads the next value as a whole, `None` at the end of the input between top level values.
+    /// Inside an object this has to be called after reading the key.
+    pub fn read_value(&mut self) -> Result<Option<JsonValue>, DeJsonErr> {
+        match self.next_token() ? {
+            None => Ok(None),
+            Some(token) => self.value_from(token).map(Some)
+        }
+    }
+
+    fn value_from(&mut self, token: JsonToken) -> Result<JsonValue, DeJsonErr> {
+        Ok(match token {
+            JsonToken::ObjectStart => {
+                let mut object = Vec::new();
+                while let Some(JsonToken::Key(key)) = self.next_token() ? {
+                    let token = self.next_token() ?.unwrap();
+                    object.push((key, self.value_from(token) ?));
+                }
+                JsonValue::Object(object)
+            }
+            JsonToken::ArrayStart => {
+                let mut array = Vec::new();
+                loop {
+                    match self.next_token() ? {
+                        Some(JsonToken::ArrayEnd) | None => break,
+                        Some(token) => array.push(self.value_from(token) ?)
+                    }
+                }
+                JsonValue::Array(array)
+            }
+            JsonToken::String(v) => JsonValue::String(v),
+            JsonToken::U64(v) => JsonValue::U64(v),
+            JsonToken::I64(v) => JsonValue::I64(v),
+            JsonToken::F64(v) => JsonValue::F64(v),
+            JsonToken::Bool(v) => JsonValue::Bool(v),
+            JsonToken::Null => JsonValue::Null,
+            JsonToken::ObjectEnd | JsonToken::ArrayEnd | JsonToken::Key(_) => {
+                return Err(self.err("Expected a value"))
+            }
+        })
+    }
+
+    /// Skips the next value without building it, returns false at the end of the input.
+    pub fn skip_value(&mut self) -> Result<bool, DeJsonErr> {
+        let depth = self.stack.len();
+        match self.next_token() ? {
+            None => return Ok(false),
+            Some(JsonToken::ObjectStart) | Some(JsonToken::ArrayStart) => (),
+            Some(JsonToken::ObjectEnd) | Some(JsonToken::ArrayEnd) | Some(JsonToken::Key(_)) => {
+                return Err(self.err("Expected a value"))
+            }
+            Some(_) => return Ok(true)
+        }
+        while self.stack.len() > depth {
+            self.next_token() ?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::io::Read;
+
+    // hands out a byte at a time, to cross every buffer boundary
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0)
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    fn tokens(input: &str) -> Result<Vec<JsonToken>, DeJsonErr> {
+        let mut reader = JsonReader::new(Trickle(input.as_bytes()));
+        let mut out = Vec::new();
+        while let Some(token) = reader.next_token() ? {
+            out.push(token);
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn token_stream() {
+        use JsonToken::*;
+        let tokens = tokens(" {\"a\": [1, -2, 0.5e1, \"\\u00e9h\", true, null, {}], \"b\": false}\n[]\n\"x\" 7").unwrap();
+        assert_eq!(tokens, vec![
+            ObjectStart, Key("a".to_string()), ArrayStart, U64(1), I64(-2), F64(5.0), String("éh".to_string()),
+            Bool(true), Null, ObjectStart, ObjectEnd, ArrayEnd, Key("b".to_string()), Bool(false), ObjectEnd,
+            ArrayStart, ArrayEnd, String("x".to_string()), U64(7),
+        ]);
+    }
+
+    #[test]
+    fn surrogates() {
+        for (input, string) in [
+            (r#""\ud83d\ude00""#, "\u{1f600}"),
+            (r#""\ud800\u0041""#, "\u{fffd}A"),
+            (r#""\ud800\n""#, "\u{fffd}\n"),
+            (r#""\ud800\ud83d\ude00""#, "\u{fffd}\u{1f600}"),
+            (r#""\ude00\ud800""#, "\u{fffd}\u{fffd}"),
+        ] {
+            assert_eq!(tokens(input).unwrap(), vec![JsonToken::String(string.to_string())], "{}", input);
+        }
+    }
+
+    #[test]
+    fn error_positions() {
+        for (input, line, col) in [
+            ("{\"a\":1,}", 0, 7),
+            ("[1,\n  2 3]", 1, 4),
+            ("{\"a\" 1}", 0, 5),
+            ("[01]", 0, 1),
+            ("[1.]", 0, 1),
+            ("[tru]", 0, 1),
+            ("{\"é\":\n\"a\u{1}\"}", 1, 3),
+            ("[1, 2", 0, 5),
+            ("{\"a\":}", 0, 5),
+            ("]", 0, 0),
+        ] {
+            let err = tokens(input).unwrap_err();
+            assert_eq!((err.line, err.col), (line, col), "{} {:?}", input, err);
+        }
+    }
+
+    #[test]
+    fn values_and_skipping() {
+        let input = "{\"reason\":\"compiler-artifact\",\"big\":{\"x\":[[1],{\"y\":[]}]},\"keep\":[1,2]}\n{\"reason\":\"build-finished\",\"success\":true}\n";
+        let mut reader = JsonReader::new(Trickle(input.as_bytes()));
+        let mut reasons = Vec::new();
+        while let Some(value) = reader.read_value().unwrap() {
+            reasons.push(value["reason"].as_str().unwrap().to_string());
+        }
+        assert_eq!(reasons, ["compiler-artifact", "build-finished"]);
+
+        let mut reader = JsonReader::new(input.as_bytes());
+        assert_eq!(reader.next_token().unwrap(), Some(JsonToken::ObjectStart));
+        let mut keep = None;
+        while let Some(JsonToken::Key(key)) = reader.next_token().unwrap() {
+            if key == "keep" {
+                keep = reader.read_value().unwrap();
+            }
+            else {
+                assert!(reader.skip_value().unwrap());
+            }
+        }
+        assert_eq!(keep.unwrap()[1].as_u64(), Some(2));
+        assert_eq!(reader.depth(), 0);
+        assert!(reader.skip_value().unwrap());
+        assert!(!reader.skip_value().unwrap());
+    }
+}
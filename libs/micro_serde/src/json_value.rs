@@ -0,0 +1,239 @@
+use std::str::Chars;
+use std::ops::Index;
+use crate::serde_json::*;
+
+/// A JSON document of unknown shape. Objects keep their keys in input order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum JsonValue {
+    #[default]
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+static JSON_NULL: JsonValue = JsonValue::Null;
+
+impl JsonValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, JsonValue::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::U64(v) => Some(*v),
+            JsonValue::I64(v) if *v >= 0 => Some(*v as u64),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::I64(v) => Some(*v),
+            JsonValue::U64(v) if *v <= i64::MAX as u64 => Some(*v as i64),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::U64(v) => Some(*v as f64),
+            JsonValue::I64(v) => Some(*v as f64),
+            JsonValue::F64(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            JsonValue::Object(v) => Some(v),
+            _ => None
+        }
+    }
+
+    /// The value of `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.as_object()?.iter().find( | (k, _) | k == key).map( | (_, v) | v)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        match self {
+            JsonValue::Object(v) => v.iter_mut().find( | (k, _) | k == key).map( | (_, v) | v),
+            _ => None
+        }
+    }
+
+    /// Looks up a JSON pointer (RFC 6901) like `/children/0/spans`, `""` is the value itself.
+    pub fn pointer(&self, pointer: &str) -> Option<&JsonValue> {
+        if pointer.is_empty() {
+            return Some(self)
+        }
+        if !pointer.starts_with('/') {
+            return None
+        }
+        let mut value = self;
+        for part in pointer[1..].split('/') {
+            let part = part.replace("~1", "/").replace("~0", "~");
+            value = match value {
+                JsonValue::Object(_) => value.get(&part) ?,
+                JsonValue::Array(items) => {
+                    // no leading zeros or signs in array indices
+                    if part.len() > 1 && part.starts_with('0') || part.starts_with('+') {
+                        return None
+                    }
+                    items.get(part.parse::<usize>().ok() ?) ?
+                }
+                _ => return None
+            };
+        }
+        Some(value)
+    }
+}
+
+impl Index<&str> for JsonValue {
+    type Output = JsonValue;
+    /// Missing keys and non-objects give `JsonValue::Null`.
+    fn index(&self, key: &str) -> &JsonValue {
+        self.get(key).unwrap_or(&JSON_NULL)
+    }
+}
+
+impl Index<usize> for JsonValue {
+    type Output = JsonValue;
+    fn index(&self, index: usize) -> &JsonValue {
+        self.as_array().and_then( | v | v.get(index)).unwrap_or(&JSON_NULL)
+    }
+}
+
+impl SerJson for JsonValue {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        match self {
+            JsonValue::Null => s.out.push_str("null"),
+            JsonValue::Bool(v) => v.ser_json(d, s),
+            JsonValue::U64(v) => v.ser_json(d, s),
+            JsonValue::I64(v) => v.ser_json(d, s),
+            JsonValue::F64(v) => {
+                if v.is_finite() {
+                    let start = s.out.len();
+                    v.ser_json(d, s);
+                    // keep it a float when read back
+                    if !s.out[start..].contains(['.', 'e', 'E']) {
+                        s.out.push_str(".0");
+                    }
+                }
+                else {
+                    s.out.push_str("null");
+                }
+            }
+            JsonValue::String(v) => v.ser_json(d, s),
+            JsonValue::Array(v) => v.ser_json(d, s),
+            JsonValue::Object(v) => {
+                s.out.push('{');
+                for (index, (key, value)) in v.iter().enumerate() {
+                    if index != 0 {
+                        s.out.push(',');
+                    }
+                    s.indent(d + 1);
+                    key.ser_json(d + 1, s);
+                    s.out.push(':');
+                    value.ser_json(d + 1, s);
+                }
+                s.indent(d);
+                s.out.push('}');
+            }
+        }
+    }
+}
+
+impl DeJson for JsonValue {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+        let value = match s.tok {
+            DeJsonTok::CurlyOpen => {
+                s.curly_open(i) ?;
+                let mut object = Vec::new();
+                while s.tok != DeJsonTok::CurlyClose {
+                    let key = s.as_string() ?;
+                    s.next_colon(i) ?;
+                    object.push((key, DeJson::de_json(s, i) ?));
+                    s.eat_comma_curly(i) ?;
+                }
+                s.curly_close(i) ?;
+                return Ok(JsonValue::Object(object))
+            }
+            DeJsonTok::BlockOpen => {
+                return Ok(JsonValue::Array(DeJson::de_json(s, i) ?))
+            }
+            DeJsonTok::Str => JsonValue::String(s.as_string() ?),
+            DeJsonTok::U64(v) => JsonValue::U64(v),
+            DeJsonTok::I64(v) => JsonValue::I64(v),
+            DeJsonTok::F64(v) => JsonValue::F64(v),
+            DeJsonTok::Bool(v) => JsonValue::Bool(v),
+            DeJsonTok::Null => JsonValue::Null,
+            _ => return Err(s.err_token("json value"))
+        };
+        s.next_tok(i) ?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let json = r#"{"reason":"compiler-message","message":{"rendered":"\u001b[1m\ud83e\udd80 x\/y\u00e9","spans":[{"line_start":3,"score":-1.5e2,"big":18446744073709551616}],"code":null},"a/b":{"~":true}}"#;
+        let value = JsonValue::deserialize_json(json).unwrap();
+        assert_eq!(value["reason"].as_str(), Some("compiler-message"));
+        assert_eq!(value["message"]["rendered"].as_str(), Some("\u{1b}[1m\u{1f980} x/y\u{e9}"));
+        assert_eq!(value.pointer("/message/spans/0/line_start").and_then( | v | v.as_u64()), Some(3));
+        assert_eq!(value.pointer("/message/spans/0/score"), Some(&JsonValue::F64(-150.0)));
+        assert_eq!(value.pointer("/message/spans/0/big").and_then( | v | v.as_f64()), Some(18446744073709551616.0));
+        assert_eq!(value.pointer("/a~1b/~0"), Some(&JsonValue::Bool(true)));
+        assert_eq!(value.pointer("/message/spans/01"), None);
+        assert_eq!(value.pointer("/message/missing"), None);
+        assert!(value["message"]["code"].is_null());
+        assert!(value["nothing"][3]["here"].is_null());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let value = JsonValue::Object(vec![
+            ("s".to_string(), JsonValue::String("line\n\"quoted\"\u{0}\u{1f}".to_string())),
+            ("list".to_string(), JsonValue::Array(vec![JsonValue::U64(1), JsonValue::I64(-2), JsonValue::F64(3.0), JsonValue::Null])),
+            ("empty".to_string(), JsonValue::Object(vec![])),
+            ("nan".to_string(), JsonValue::F64(f64::NAN)),
+        ]);
+        let json = value.serialize_json();
+        assert_eq!(json, r#"{"s":"line\n\"quoted\"\u0000\u001f","list":[1,-2,3.0,null],"empty":{},"nan":null}"#);
+        let back = JsonValue::deserialize_json(&json).unwrap();
+        assert_eq!(back["list"], value["list"]);
+        assert_eq!(back["s"], value["s"]);
+        assert!(back["nan"].is_null());
+    }
+}
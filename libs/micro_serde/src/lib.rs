@@ -7,4 +7,13 @@ mod serde_json;
 pub use crate::serde_json::*;
 
 mod serde_ron;
-pub use crate::serde_ron::*;
\ No newline at end of file
+pub use crate::serde_ron::*;
+
+mod json_value;
+pub use crate::json_value::*;
+
+mod json_reader;
+pub use crate::json_reader::*;
+// the json derive refers to crate::makepad_micro_serde
+#[cfg(test)]
+extern crate self as makepad_micro_serde;
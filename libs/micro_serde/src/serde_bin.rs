@@ -29,6 +29,16 @@ pub trait DeBin:Sized {
     fn de_bin(o:&mut usize, d:&[u8]) -> Result<Self, DeBinErr>;
 }
 
+/// Like `DeBin`, but the result may borrow from the input buffer: `&'a str` and `&'a [u8]`
+/// read the same bytes `String` and `Vec<u8>` write, without copying them.
+pub trait DeBinBorrow<'a>:Sized {
+    fn deserialize_bin_borrow(d:&'a [u8])->Result<Self, DeBinErr>{
+        DeBinBorrow::de_bin_borrow(&mut 0, d)
+    }
+
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8]) -> Result<Self, DeBinErr>;
+}
+
 
 pub struct DeBinErr{
     pub msg: String,
@@ -136,7 +146,7 @@ impl DeBin for bool {
     }
 }
 
-impl SerBin for String {
+impl SerBin for str {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         let len = self.len();
         len.ser_bin(s);
@@ -144,6 +154,12 @@ impl SerBin for String {
     }
 }
 
+impl SerBin for String {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        self.as_str().ser_bin(s);
+    }
+}
+
 impl DeBin for String {
     fn de_bin(o:&mut usize, d:&[u8])->Result<String, DeBinErr> {
         let len:u64 = DeBin::de_bin(o,d)?;
@@ -234,8 +250,12 @@ impl<T, E> DeBin for Result<T, E> where T: DeBin, E: DeBin {
     }
 }
 
+// same layout as Vec<T>, so borrowed slices read back. Up to 0.3 slices were written without the
+// length. Fixed size arrays have their own impl without the length
 impl<T> SerBin for [T] where T: SerBin {
     fn ser_bin(&self, s: &mut Vec<u8>) {
+        let len = self.len() as u64;
+        len.ser_bin(s);
         for item in self {
             item.ser_bin(s);
         }
@@ -250,20 +270,47 @@ unsafe fn de_bin_array_impl_inner<T>(top: *mut T, count: usize, o:&mut usize, d:
     Ok(())
 }
 
+unsafe fn de_bin_borrow_array_impl_inner<'a, T>(top: *mut T, count: usize, o:&mut usize, d:&'a [u8]) -> Result<(), DeBinErr> where T:DeBinBorrow<'a>{
+    for c in 0..count {
+        top.add(c).write(DeBinBorrow::de_bin_borrow(o, d) ?);
+    }
+    Ok(())
+}
+
 macro_rules!de_bin_array_impl {
     ( $($count:expr),*) => {
         $(
+        impl<T> SerBin for [T; $count] where T: SerBin {
+            fn ser_bin(&self, s: &mut Vec<u8>) {
+                for item in self {
+                    item.ser_bin(s);
+                }
+            }
+        }
+
         impl<T> DeBin for [T; $count] where T: DeBin {
             fn de_bin(o:&mut usize, d:&[u8]) -> Result<Self,
             DeBinErr> {
                 unsafe{
                     let mut to = std::mem::MaybeUninit::<[T; $count]>::uninit();
-                    let top: *mut T = std::mem::transmute(&mut to);
+                    let top = to.as_mut_ptr() as *mut T;
                     de_bin_array_impl_inner(top, $count, o, d)?;
                     Ok(to.assume_init())
                 }
             }
         }
+
+        impl<'a, T> DeBinBorrow<'a> for [T; $count] where T: DeBinBorrow<'a> {
+            fn de_bin_borrow(o:&mut usize, d:&'a [u8]) -> Result<Self,
+            DeBinErr> {
+                unsafe{
+                    let mut to = std::mem::MaybeUninit::<[T; $count]>::uninit();
+                    let top = to.as_mut_ptr() as *mut T;
+                    de_bin_borrow_array_impl_inner(top, $count, o, d)?;
+                    Ok(to.assume_init())
+                }
+            }
+        }
         )*
     }
 }
@@ -333,6 +380,12 @@ V: DeBin {
 }
 
 
+impl<T> SerBin for &T where T: SerBin + ?Sized {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (**self).ser_bin(s)
+    }
+}
+
 impl<T> SerBin for Box<T> where T: SerBin {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         (**self).ser_bin(s)
@@ -378,7 +431,7 @@ impl SerBin for OsStr {
 impl SerBin for char {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         let mut bytes = [0; 4];
-        self.encode_utf8(&mut bytes).as_bytes().ser_bin(s);
+        s.extend_from_slice(self.encode_utf8(&mut bytes).as_bytes());
     }
 }
 
@@ -400,20 +453,121 @@ impl DeBin for OsString {
 
 impl DeBin for char {
     fn de_bin(o: &mut usize, d: &[u8]) -> Result<Self, DeBinErr> {
+        let start = *o;
         let mut bytes = [0; 4];
         bytes[0] = u8::de_bin(o, d)?;
-        let width = utf8_char_width(bytes[0]);
-        for index in 1..width {
-            bytes[index] = u8::de_bin(o, d)?;
+        // A width of 0 means the first byte cannot start a char.
+        let width = utf8_char_width(bytes[0]).max(1);
+        for byte in &mut bytes[1..width] {
+            *byte = u8::de_bin(o, d)?;
+        }
+        str::from_utf8(&bytes[..width])
+            .ok()
+            .and_then( | string | string.chars().next())
+            .ok_or_else( | | DeBinErr {o: start, l: width, s: d.len(), msg: "char (invalid utf8)".to_string()})
+    }
+}
+
+fn de_bin_borrow_bytes<'a>(o:&mut usize, d:&'a [u8], msg:&str)->Result<&'a [u8], DeBinErr> {
+    let len:u64 = DeBin::de_bin(o,d)?;
+    if len > (d.len() - *o) as u64{
+        return Err(DeBinErr{o:*o, l:len as usize, s:d.len(), msg:msg.to_string()})
+    }
+    let r = &d[*o..*o + len as usize];
+    *o += len as usize;
+    Ok(r)
+}
+
+impl<'a> DeBinBorrow<'a> for &'a [u8] {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<&'a [u8], DeBinErr> {
+        de_bin_borrow_bytes(o, d, "&[u8]")
+    }
+}
+
+impl<'a> DeBinBorrow<'a> for &'a str {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<&'a str, DeBinErr> {
+        let start = *o;
+        let bytes = de_bin_borrow_bytes(o, d, "&str")?;
+        str::from_utf8(bytes).map_err(|_| DeBinErr{o:start, l:bytes.len(), s:d.len(), msg:"&str (invalid utf8)".to_string()})
+    }
+}
+
+macro_rules! impl_de_bin_borrow_for_owned {
+    ( $($ty:ty),*) => {
+        $(
+        impl<'a> DeBinBorrow<'a> for $ty {
+            fn de_bin_borrow(o:&mut usize, d:&'a [u8]) -> Result<$ty, DeBinErr> {
+                DeBin::de_bin(o, d)
+            }
+        }
+        )*
+    }
+}
+
+impl_de_bin_borrow_for_owned!(f64, f32, u64, i64, u32, i32, u16, i16, u8, usize, bool, char, String);
+
+impl<'a, T> DeBinBorrow<'a> for Vec<T> where T: DeBinBorrow<'a> {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<Vec<T>, DeBinErr> {
+        let len:u64 = DeBin::de_bin(o,d)?;
+        let mut out = Vec::new();
+        for _ in 0..len{
+            out.push(DeBinBorrow::de_bin_borrow(o,d)?)
+        }
+        Ok(out)
+    }
+}
+
+impl<'a, T> DeBinBorrow<'a> for Option<T> where T: DeBinBorrow<'a> {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<Option<T>, DeBinErr> {
+        match u8::de_bin(o, d).map_err(|e| DeBinErr{msg:"Option<T>".to_string(), ..e})? {
+            0 => Ok(None),
+            1 => Ok(Some(DeBinBorrow::de_bin_borrow(o,d)?)),
+            _ => Err(DeBinErr{o:*o, l:0, s:d.len(), msg:"Option<T>".to_string()}),
+        }
+    }
+}
+
+impl<'a, T, E> DeBinBorrow<'a> for Result<T, E> where T: DeBinBorrow<'a>, E: DeBinBorrow<'a> {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<Self, DeBinErr> {
+        match u8::de_bin(o, d).map_err(|e| DeBinErr{msg:"Result<T, E>".to_string(), ..e})? {
+            0 => Ok(Ok(T::de_bin_borrow(o, d)?)),
+            1 => Ok(Err(E::de_bin_borrow(o, d)?)),
+            _ => Err(DeBinErr{o:*o, l:0, s:d.len(), msg:"Result<T, E>".to_string()}),
+        }
+    }
+}
+
+impl<'a, T> DeBinBorrow<'a> for Box<T> where T: DeBinBorrow<'a> {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<Box<T>, DeBinErr> {
+        Ok(Box::new(DeBinBorrow::de_bin_borrow(o,d)?))
+    }
+}
+
+impl<'a, K, V> DeBinBorrow<'a> for HashMap<K, V> where K: DeBinBorrow<'a> + Eq + Hash, V: DeBinBorrow<'a> {
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<Self, DeBinErr>{
+        let len:u64 = DeBin::de_bin(o,d)?;
+        let mut h = HashMap::new();
+        for _ in 0..len{
+            let k = DeBinBorrow::de_bin_borrow(o,d)?;
+            let v = DeBinBorrow::de_bin_borrow(o,d)?;
+            h.insert(k, v);
         }
-        Ok(str::from_utf8(&bytes[..width])
-            .unwrap()
-            .chars()
-            .next()
-            .unwrap())
+        Ok(h)
     }
 }
 
+impl<'a, A, B> DeBinBorrow<'a> for (A,B) where A:DeBinBorrow<'a>, B:DeBinBorrow<'a>{
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<(A,B), DeBinErr> {Ok((DeBinBorrow::de_bin_borrow(o,d)?,DeBinBorrow::de_bin_borrow(o,d)?))}
+}
+
+impl<'a, A, B, C> DeBinBorrow<'a> for (A,B,C) where A:DeBinBorrow<'a>, B:DeBinBorrow<'a>, C:DeBinBorrow<'a>{
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<(A,B,C), DeBinErr> {Ok((DeBinBorrow::de_bin_borrow(o,d)?,DeBinBorrow::de_bin_borrow(o,d)?,DeBinBorrow::de_bin_borrow(o,d)?))}
+}
+
+impl<'a, A, B, C, D> DeBinBorrow<'a> for (A,B,C,D) where A:DeBinBorrow<'a>, B:DeBinBorrow<'a>, C:DeBinBorrow<'a>, D:DeBinBorrow<'a>{
+    fn de_bin_borrow(o:&mut usize, d:&'a [u8])->Result<(A,B,C,D), DeBinErr> {Ok((DeBinBorrow::de_bin_borrow(o,d)?,DeBinBorrow::de_bin_borrow(o,d)?,DeBinBorrow::de_bin_borrow(o,d)?,DeBinBorrow::de_bin_borrow(o,d)?))}
+}
+
 // Given a first byte, determines how many bytes are in this UTF-8 character.
 #[inline]
 pub fn utf8_char_width(b: u8) -> usize {
@@ -438,3 +592,188 @@ pub fn utf8_char_width(b: u8) -> usize {
 
     UTF8_CHAR_WIDTH[b as usize] as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[derive(SerBin, DeBin)]
+    struct Owned {
+        path: String,
+        data: Vec<u8>,
+        lines: Vec<String>,
+        id: u32,
+    }
+
+    #[derive(SerBin, DeBinBorrow, PartialEq, Debug)]
+    struct Borrowed<'a> {
+        path: &'a str,
+        data: &'a [u8],
+        lines: Vec<&'a str>,
+        id: u32,
+    }
+
+    #[derive(SerBin, DeBinBorrow, PartialEq, Debug)]
+    enum Message<'a> {
+        Empty,
+        Text(&'a str, Option<&'a [u8]>),
+        Open {name: &'a str, grid: [u32; 2]},
+    }
+
+    #[derive(SerBin, DeBin, DeBinBorrow, PartialEq, Debug)]
+    struct NoLifetime(u64, String);
+
+    #[test]
+    fn borrow_matches_owned_layout() {
+        let owned = Owned {
+            path: "src/main.rs".to_string(),
+            data: vec![1, 2, 3, 255],
+            lines: vec!["a".to_string(), "bc".to_string()],
+            id: 7,
+        };
+        let bin = owned.serialize_bin();
+        let borrowed = Borrowed::deserialize_bin_borrow(&bin).unwrap();
+        assert_eq!(borrowed, Borrowed {path: "src/main.rs", data: &[1, 2, 3, 255], lines: vec!["a", "bc"], id: 7});
+        assert_eq!(borrowed.data.as_ptr(), bin[27..].as_ptr());
+        assert_eq!(borrowed.serialize_bin(), bin);
+
+        let owned = Owned::deserialize_bin(&borrowed.serialize_bin()).unwrap();
+        assert_eq!(owned.lines, ["a", "bc"]);
+    }
+
+    #[test]
+    fn borrow_enum_roundtrip() {
+        for message in [
+            Message::Empty,
+            Message::Text("hi", None),
+            Message::Text("hi", Some(b"there")),
+            Message::Open {name: "x", grid: [3, 4]},
+        ] {
+            let bin = message.serialize_bin();
+            assert_eq!(Message::deserialize_bin_borrow(&bin).unwrap(), message);
+        }
+        let value = NoLifetime(1, "one".to_string());
+        assert_eq!(NoLifetime::deserialize_bin_borrow(&value.serialize_bin()).unwrap(), value);
+    }
+
+    #[derive(SerBin, DeBin, PartialEq, Debug)]
+    #[serde(versioned)]
+    struct SettingsV1 {
+        name: String,
+        size: u32,
+    }
+
+    fn default_scale() -> f32 {1.5}
+
+    #[derive(SerBin, DeBin, PartialEq, Debug)]
+    #[serde(versioned)]
+    struct SettingsV2 {
+        name: String,
+        size: u32,
+        #[serde(skip)]
+        cache: Vec<u8>,
+        #[serde(default = "default_scale")]
+        scale: f32,
+        theme: Option<String>,
+    }
+
+    #[derive(SerBin, DeBin, PartialEq, Debug)]
+    #[serde(versioned)]
+    enum CommandV1 {
+        #[serde(tag = 1)]
+        Open(String),
+        Close {id: u32},
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[derive(SerBin, DeBin, PartialEq, Debug)]
+    #[serde(versioned)]
+    enum CommandV2 {
+        #[serde(tag = 1)]
+        Open(String),
+        Close {id: u32, #[serde(default)] force: bool},
+        Unknown,
+        Save(String, u64),
+    }
+
+    #[test]
+    fn versioned_struct() {
+        let v1 = SettingsV1 {name: "a".to_string(), size: 3};
+        let v2 = SettingsV2::deserialize_bin(&v1.serialize_bin()).unwrap();
+        assert_eq!(v2, SettingsV2 {name: "a".to_string(), size: 3, cache: vec![], scale: 1.5, theme: None});
+
+        let v2 = SettingsV2 {name: "b".to_string(), size: 4, cache: vec![1], scale: 2.0, theme: Some("dark".to_string())};
+        let mut bin = v2.serialize_bin();
+        bin.push(42);
+        let mut o = 0;
+        assert_eq!(SettingsV1::de_bin(&mut o, &bin).unwrap(), SettingsV1 {name: "b".to_string(), size: 4});
+        assert_eq!(bin[o], 42);
+        assert_eq!(SettingsV2::deserialize_bin(&bin).unwrap().cache, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn versioned_enum() {
+        for (v1, v2) in [
+            (CommandV1::Open("x".to_string()), CommandV2::Open("x".to_string())),
+            (CommandV1::Close {id: 2}, CommandV2::Close {id: 2, force: false}),
+        ] {
+            assert_eq!(CommandV2::deserialize_bin(&v1.serialize_bin()).unwrap(), v2);
+        }
+        let bin = (CommandV2::Save("y".to_string(), 5), 7u32).serialize_bin();
+        let (command, next): (CommandV1, u32) = DeBin::deserialize_bin(&bin).unwrap();
+        assert_eq!(command, CommandV1::Unknown);
+        assert_eq!(next, 7);
+        // explicit tags are stable on the wire
+        assert_eq!(&CommandV2::Open("x".to_string()).serialize_bin()[0..2], &[1, 0]);
+        assert_eq!(&CommandV2::Close {id: 0, force: true}.serialize_bin()[0..2], &[2, 0]);
+    }
+
+    #[derive(SerBin, DeBinBorrow, PartialEq, Debug)]
+    struct Tagged<'a, T> where T: Clone {
+        tag: &'a str,
+        value: T,
+    }
+
+    #[test]
+    fn borrow_generic_roundtrip() {
+        let tagged = Tagged {tag: "lines", value: vec!["a", "bc"]};
+        let bin = tagged.serialize_bin();
+        assert_eq!(Tagged::<Vec<&str>>::deserialize_bin_borrow(&bin).unwrap(), tagged);
+    }
+
+    // slices, and the os strings and paths that are serialized as slices, have a u64 length
+    // prefix like a vec
+    #[cfg(unix)]
+    #[test]
+    fn path_roundtrip() {
+        use std::{ffi::OsString, path::PathBuf};
+
+        let path = PathBuf::from("src/dé jà.rs");
+        let bin = path.serialize_bin();
+        assert_eq!(bin, path.to_str().unwrap().as_bytes().to_vec().serialize_bin());
+        assert_eq!(PathBuf::deserialize_bin(&bin).unwrap(), path);
+
+        let string = OsString::from("");
+        assert_eq!(string.serialize_bin(), 0u64.serialize_bin());
+        assert_eq!(OsString::deserialize_bin(&string.serialize_bin()).unwrap(), string);
+        let pair = (OsString::from("a"), PathBuf::from("/b"));
+        assert_eq!(<(OsString, PathBuf)>::deserialize_bin(&pair.serialize_bin()).unwrap(), pair);
+    }
+
+    #[test]
+    fn borrow_errors() {
+        let bin = Borrowed {path: "abc", data: &[], lines: vec![], id: 0}.serialize_bin();
+        assert!(Borrowed::deserialize_bin_borrow(&bin[..10]).is_err());
+        let mut bad = "ab".serialize_bin();
+        bad[8] = 0xff;
+        assert!(<&str>::deserialize_bin_borrow(&bad).is_err());
+        for bad in [&[0xff][..], &[0xc3, 0x28], &[0xe2, 0x82]] {
+            assert!(char::deserialize_bin(bad).is_err());
+        }
+        assert_eq!(char::deserialize_bin("é".as_bytes()).unwrap(), 'é');
+        let mut huge = u64::MAX.serialize_bin();
+        huge.push(0);
+        assert!(<&[u8]>::deserialize_bin_borrow(&huge).is_err());
+    }
+}
@@ -268,6 +268,25 @@ impl DeJsonState {
         Err(self.err_token("}"))
     }
     
+    // skips over a whole value, used for fields and variants a versioned reader doesn't know
+    pub fn skip_value(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+        let mut depth = 0;
+        loop {
+            match self.tok {
+                DeJsonTok::CurlyOpen | DeJsonTok::BlockOpen => depth += 1,
+                DeJsonTok::CurlyClose | DeJsonTok::BlockClose if depth > 0 => depth -= 1,
+                DeJsonTok::Eof | DeJsonTok::Bof => return Err(self.err_token("value")),
+                DeJsonTok::CurlyClose | DeJsonTok::BlockClose |
+                DeJsonTok::Colon | DeJsonTok::Comma if depth == 0 => return Err(self.err_token("value")),
+                _ => ()
+            }
+            self.next_tok(i) ?;
+            if depth == 0 {
+                return Ok(())
+            }
+        }
+    }
+    
     pub fn u64_range(&mut self, max: u64) -> Result<u64, DeJsonErr> {
         if let DeJsonTok::U64(value) = self.tok {
             if value > max {
@@ -323,6 +342,36 @@ impl DeJsonState {
         Err(self.err_token("string"))
     }
     
+    // reads the 4 hex digits after the current \u, leaves cur after them
+    fn hex4(&mut self, i: &mut Chars) -> Result<u32, DeJsonErr> {
+        let mut value = 0;
+        for _ in 0..4 {
+            self.next(i);
+            value = value * 16 + self.cur.to_digit(16).ok_or_else( || self.err_parse("unicode escape")) ?;
+        }
+        self.next(i);
+        Ok(value)
+    }
+    
+    fn unicode_escape(&mut self, i: &mut Chars) -> Result<char, DeJsonErr> {
+        let high = self.hex4(i) ?;
+        if (0xd800..0xdc00).contains(&high) && self.cur == '\\' {
+            // only consume the next escape if it is the low half of the pair, any other escape
+            // is read on its own after the lone high surrogate
+            let mut ahead = i.clone();
+            if ahead.next() == Some('u') {
+                let low = ahead.take(4).try_fold(0u32, | value, c | Some(value * 16 + c.to_digit(16) ?));
+                if let Some(low @ 0xdc00..=0xdfff) = low {
+                    self.next(i);
+                    self.hex4(i) ?;
+                    return Ok(char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).unwrap())
+                }
+            }
+        }
+        // lone surrogates
+        Ok(char::from_u32(high).unwrap_or('\u{fffd}'))
+    }
+    
     pub fn next_tok(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
         while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' {
             self.next(i);
@@ -376,38 +425,48 @@ impl DeJsonState {
                     self.numbuf.push(self.cur);
                     self.next(i);
                 }
+                let mut is_float = false;
                 if self.cur == '.' {
+                    is_float = true;
                     self.numbuf.push(self.cur);
                     self.next(i);
                     while self.cur >= '0' && self.cur <= '9' {
                         self.numbuf.push(self.cur);
                         self.next(i);
                     }
-                    if let Ok(num) = self.numbuf.parse() {
-                        self.tok = DeJsonTok::F64(num);
-                        return Ok(())
+                }
+                if self.cur == 'e' || self.cur == 'E' {
+                    is_float = true;
+                    self.numbuf.push(self.cur);
+                    self.next(i);
+                    if self.cur == '+' || self.cur == '-' {
+                        self.numbuf.push(self.cur);
+                        self.next(i);
                     }
-                    else {
-                        return Err(self.err_parse("number"));
+                    while self.cur >= '0' && self.cur <= '9' {
+                        self.numbuf.push(self.cur);
+                        self.next(i);
                     }
                 }
-                else {
+                if !is_float {
                     if is_neg {
                         if let Ok(num) = self.numbuf.parse() {
                             self.tok = DeJsonTok::I64(num);
                             return Ok(())
                         }
-                        else {
-                            return Err(self.err_parse("number"));
-                        }
                     }
-                    if let Ok(num) = self.numbuf.parse() {
+                    else if let Ok(num) = self.numbuf.parse() {
                         self.tok = DeJsonTok::U64(num);
                         return Ok(())
                     }
-                    else {
-                        return Err(self.err_parse("number"));
-                    }
+                }
+                // integers that don't fit 64 bits become floats
+                if let Ok(num) = self.numbuf.parse() {
+                    self.tok = DeJsonTok::F64(num);
+                    Ok(())
+                }
+                else {
+                    Err(self.err_parse("number"))
                 }
             },
             'a'..='z' | 'A'..='Z' | '_' => {
@@ -443,7 +502,14 @@ impl DeJsonState {
                             'n'=>self.strbuf.push('\n'),
                             'r'=>self.strbuf.push('\r'),
                             't'=>self.strbuf.push('\t'),
+                            'b'=>self.strbuf.push('\u{8}'),
+                            'f'=>self.strbuf.push('\u{c}'),
                             '0'=>self.strbuf.push('\0'),
+                            'u'=>{
+                                let c = self.unicode_escape(i) ?;
+                                self.strbuf.push(c);
+                                continue;
+                            }
                             '\0'=>{
                                 return Err(self.err_parse("string"));
                             },
@@ -590,7 +656,7 @@ impl SerJson for String {
                 '\n'=>{s.out.push('\\');s.out.push('n');},
                 '\r'=>{s.out.push('\\');s.out.push('r');},
                 '\t'=>{s.out.push('\\');s.out.push('t');},
-                '\0'=>{s.out.push('\\');s.out.push('0');},
+                '\u{0}'..='\u{1f}'=>{s.out.push_str(&format!("\\u{:04x}", c as u32));},
                 '\\'=>{s.out.push('\\');s.out.push('\\');},
                 '"'=>{s.out.push('\\');s.out.push('"');},
                 _=>s.out.push(c)
@@ -813,4 +879,72 @@ impl<T> DeJson for Box<T> where T: DeJson {
     fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Box<T>, DeJsonErr> {
         Ok(Box::new(DeJson::de_json(s, i) ?))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn default_port() -> u16 {8080}
+
+    #[derive(SerJson, DeJson, PartialEq, Debug)]
+    #[serde(versioned)]
+    struct Config {
+        #[serde(rename = "fileName")]
+        file_name: String,
+        #[serde(default = "default_port")]
+        port: u16,
+        #[serde(default)]
+        verbose: bool,
+        #[serde(skip)]
+        dirty: bool,
+        mode: Mode,
+    }
+
+    #[derive(SerJson, DeJson, PartialEq, Debug)]
+    enum Mode {
+        #[serde(rename = "fast")]
+        Fast,
+        Custom {#[serde(rename = "lvl")] level: u32},
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[derive(SerJson, DeJson, PartialEq, Debug)]
+    struct Strict {
+        a: u32,
+    }
+
+    #[test]
+    fn attributes() {
+        let config = Config {file_name: "a.rs".to_string(), port: 1, verbose: true, dirty: true, mode: Mode::Custom {level: 3}};
+        let json = config.serialize_json();
+        assert!(json.contains("\"fileName\":\"a.rs\""));
+        assert!(json.contains("\"lvl\":3"));
+        assert!(!json.contains("dirty"));
+        let back = Config::deserialize_json(&json).unwrap();
+        assert_eq!(back, Config {dirty: false, ..config});
+
+        let json = r#"{"fileName":"b.rs","extra":{"x":[1,{"y":null}],"z":"}"},"mode":{"fast":[]},"more":-1.5}"#;
+        let config = Config::deserialize_json(json).unwrap();
+        assert_eq!(config, Config {file_name: "b.rs".to_string(), port: 8080, verbose: false, dirty: false, mode: Mode::Fast});
+
+        let config = Config::deserialize_json(r#"{"fileName":"c","mode":{"Turbo":{"x":[1,2]}}}"#).unwrap();
+        assert_eq!(config.mode, Mode::Unknown);
+
+        assert!(Config::deserialize_json(r#"{"mode":{"fast":[]}}"#).is_err());
+        assert!(Strict::deserialize_json(r#"{"a":1,"b":2}"#).is_err());
+    }
+
+    #[test]
+    fn surrogates() {
+        for (json, string) in [
+            (r#""\ud83d\ude00""#, "\u{1f600}"),
+            (r#""\ud800\u0041""#, "\u{fffd}A"),
+            (r#""\ud800\n""#, "\u{fffd}\n"),
+            (r#""\ud800\ud83d\ude00""#, "\u{fffd}\u{1f600}"),
+            (r#""\ude00\ud800""#, "\u{fffd}\u{fffd}"),
+        ] {
+            assert_eq!(String::deserialize_json(json).unwrap(), string, "{}", json);
+        }
+    }
+}
@@ -291,6 +291,37 @@ impl DeRonState {
     }
     
     
+    // skips over a whole value, used for fields and variants a versioned reader doesn't know
+    pub fn skip_value(&mut self, i: &mut Chars) -> Result<(), DeRonErr> {
+        if self.tok == DeRonTok::Ident { // an enum variant, maybe with data
+            self.next_tok(i) ?;
+            return self.skip_paren_group(i)
+        }
+        let mut depth = 0;
+        loop {
+            match self.tok {
+                DeRonTok::ParenOpen | DeRonTok::CurlyOpen | DeRonTok::BlockOpen => depth += 1,
+                DeRonTok::ParenClose | DeRonTok::CurlyClose | DeRonTok::BlockClose if depth > 0 => depth -= 1,
+                DeRonTok::Eof | DeRonTok::Bof => return Err(self.err_token("value")),
+                DeRonTok::ParenClose | DeRonTok::CurlyClose | DeRonTok::BlockClose |
+                DeRonTok::Colon | DeRonTok::Comma if depth == 0 => return Err(self.err_token("value")),
+                _ => ()
+            }
+            self.next_tok(i) ?;
+            if depth == 0 {
+                return Ok(())
+            }
+        }
+    }
+    
+    // skips the ( ) following an enum variant, if there is one
+    pub fn skip_paren_group(&mut self, i: &mut Chars) -> Result<(), DeRonErr> {
+        if self.tok == DeRonTok::ParenOpen {
+            self.skip_value(i) ?;
+        }
+        Ok(())
+    }
+    
     pub fn u64_range(&mut self, max: u64) -> Result<u64, DeRonErr> {
         if let DeRonTok::U64(value) = self.tok {
             if value > max {
@@ -880,4 +911,45 @@ impl<T> DeRon for Box<T> where T: DeRon {
     fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Box<T>, DeRonErr> {
         Ok(Box::new(DeRon::de_ron(s, i) ?))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[derive(SerRon, DeRon, PartialEq, Debug)]
+    #[serde(versioned)]
+    struct Config {
+        #[serde(rename = "file")]
+        file_name: String,
+        #[serde(default)]
+        port: u16,
+        #[serde(skip)]
+        dirty: bool,
+        mode: Mode,
+    }
+
+    #[derive(SerRon, DeRon, PartialEq, Debug)]
+    enum Mode {
+        #[serde(rename = "fast")]
+        Fast,
+        Custom(u32),
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[test]
+    fn attributes() {
+        let config = Config {file_name: "a.rs".to_string(), port: 1, dirty: true, mode: Mode::Custom(3)};
+        let ron = config.serialize_ron();
+        assert!(ron.contains("file:\"a.rs\""));
+        assert!(!ron.contains("dirty"));
+        assert_eq!(Config::deserialize_ron(&ron).unwrap(), Config {dirty: false, ..config});
+
+        let ron = "(file: \"b\", extra: Some((x: [1, 2], y: {\"k\": 3})), mode: fast, more: None)";
+        assert_eq!(Config::deserialize_ron(ron).unwrap(), Config {file_name: "b".to_string(), port: 0, dirty: false, mode: Mode::Fast});
+
+        let ron = "(file: \"c\", mode: Turbo(1, (a: 2)))";
+        assert_eq!(Config::deserialize_ron(ron).unwrap().mode, Mode::Unknown);
+        assert!(Config::deserialize_ron("(mode: fast)").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! CRC-32 (IEEE 802.3, reflected polynomial `0xEDB88320`) checksum.
+//!
+//! This is the checksum used by the zip, gzip and png container formats.
+
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const fn make_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {CRC32_POLYNOMIAL ^ (c >> 1)} else {c >> 1};
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = make_crc32_table();
+
+/// Running CRC-32 checksum calculator.
+///
+/// ```
+/// use makepad_miniz::crc32::Crc32;
+///
+/// let mut crc = Crc32::new();
+/// crc.write_slice(b"1234");
+/// crc.write_slice(b"56789");
+///
+/// assert_eq!(crc.checksum(), 0xCBF43926);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    /// Creates a new calculator in the initial (empty input) state.
+    pub const fn new() -> Self {
+        Self {state: 0xFFFF_FFFF}
+    }
+
+    /// Continues a checksum from a previously computed `checksum` value.
+    pub const fn from_checksum(checksum: u32) -> Self {
+        Self {state: !checksum}
+    }
+
+    /// Adds `data` to the checksum.
+    pub fn write_slice(&mut self, data: &[u8]) {
+        let mut state = self.state;
+        for byte in data {
+            state = CRC32_TABLE[((state ^ *byte as u32) & 0xFF) as usize] ^ (state >> 8);
+        }
+        self.state = state;
+    }
+
+    /// Returns the checksum of all data written so far.
+    pub const fn checksum(&self) -> u32 {
+        !self.state
+    }
+}
+
+/// Computes the CRC-32 of `data` in one go.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.write_slice(data);
+    crc.checksum()
+}
+
+/// Continues the CRC-32 `crc` with `data`, in the style of zlib's `crc32()`.
+pub fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut hash = Crc32::from_checksum(crc);
+    hash.write_slice(data);
+    hash.checksum()
+}
@@ -0,0 +1,434 @@
+//! gzip (RFC 1952) streams with `Write` compression and `Read` decompression.
+
+use std::io::{self, Read, Write};
+
+use crate::crc32::Crc32;
+use crate::stream_io::{invalid_data, DeflateWriter, InflateReader};
+use crate::DataFormat;
+
+const GZIP_ID1: u8 = 0x1f;
+const GZIP_ID2: u8 = 0x8b;
+const GZIP_CM_DEFLATE: u8 = 8;
+
+const FLAG_TEXT: u8 = 0x01;
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// Operating system value for "unknown".
+pub const GZIP_OS_UNKNOWN: u8 = 255;
+
+/// The header of a gzip member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzHeader {
+    /// Original file name, ISO 8859-1 without the terminating zero.
+    pub filename: Option<Vec<u8>>,
+    /// File comment, ISO 8859-1 without the terminating zero.
+    pub comment: Option<Vec<u8>>,
+    /// Contents of the extra field.
+    pub extra: Option<Vec<u8>>,
+    /// Modification time in seconds since the unix epoch, 0 if unknown.
+    pub mtime: u32,
+    pub operating_system: u8,
+    /// The data is probably text.
+    pub text: bool,
+}
+
+impl Default for GzHeader {
+    fn default() -> Self {
+        Self {
+            filename: None,
+            comment: None,
+            extra: None,
+            mtime: 0,
+            operating_system: GZIP_OS_UNKNOWN,
+            text: false,
+        }
+    }
+}
+
+impl GzHeader {
+    fn to_bytes(&self, level: u8) -> Vec<u8> {
+        let mut flags = 0;
+        if self.text {flags |= FLAG_TEXT}
+        if self.extra.is_some() {flags |= FLAG_EXTRA}
+        if self.filename.is_some() {flags |= FLAG_NAME}
+        if self.comment.is_some() {flags |= FLAG_COMMENT}
+        let xfl = match level {
+            0..=1 => 4,
+            9..=10 => 2,
+            _ => 0,
+        };
+        let mut header = vec![GZIP_ID1, GZIP_ID2, GZIP_CM_DEFLATE, flags];
+        header.extend_from_slice(&self.mtime.to_le_bytes());
+        header.push(xfl);
+        header.push(self.operating_system);
+        if let Some(extra) = &self.extra {
+            header.extend_from_slice(&(extra.len().min(0xFFFF) as u16).to_le_bytes());
+            header.extend_from_slice(&extra[0..extra.len().min(0xFFFF)]);
+        }
+        for text in [&self.filename, &self.comment].iter().copied().flatten() {
+            header.extend(text.iter().filter(|c| **c != 0));
+            header.push(0);
+        }
+        header
+    }
+
+    // reads a header, the two id bytes have already been read
+    fn from_reader<R: Read>(reader: &mut InflateReader<R>) -> io::Result<Self> {
+        let mut crc = Crc32::new();
+        crc.write_slice(&[GZIP_ID1, GZIP_ID2]);
+        let mut fixed = [0u8; 8];
+        reader.read_bytes(&mut fixed)?;
+        crc.write_slice(&fixed);
+        if fixed[0] != GZIP_CM_DEFLATE {
+            return Err(invalid_data("unsupported gzip compression method"));
+        }
+        let flags = fixed[1];
+        let mut header = GzHeader {
+            mtime: u32::from_le_bytes([fixed[2], fixed[3], fixed[4], fixed[5]]),
+            operating_system: fixed[7],
+            text: flags & FLAG_TEXT != 0,
+            ..Default::default()
+        };
+        if flags & FLAG_EXTRA != 0 {
+            let mut len = [0u8; 2];
+            reader.read_bytes(&mut len)?;
+            let mut extra = vec![0u8; u16::from_le_bytes(len) as usize];
+            reader.read_bytes(&mut extra)?;
+            crc.write_slice(&len);
+            crc.write_slice(&extra);
+            header.extra = Some(extra);
+        }
+        let mut read_zero_terminated = |reader: &mut InflateReader<R>| -> io::Result<Vec<u8>> {
+            let mut text = Vec::new();
+            loop {
+                let c = reader.read_u8()?;
+                crc.write_slice(&[c]);
+                if c == 0 {
+                    return Ok(text);
+                }
+                text.push(c);
+            }
+        };
+        if flags & FLAG_NAME != 0 {
+            header.filename = Some(read_zero_terminated(reader)?);
+        }
+        if flags & FLAG_COMMENT != 0 {
+            header.comment = Some(read_zero_terminated(reader)?);
+        }
+        if flags & FLAG_HCRC != 0 {
+            let mut hcrc = [0u8; 2];
+            reader.read_bytes(&mut hcrc)?;
+            if u16::from_le_bytes(hcrc) != crc.checksum() as u16 {
+                return Err(invalid_data("gzip header checksum mismatch"));
+            }
+        }
+        Ok(header)
+    }
+}
+
+/// Compresses everything written to it as a single gzip member into `W`.
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use makepad_miniz::gzip::{GzDecoder, GzEncoder};
+///
+/// let mut encoder = GzEncoder::new(Vec::new(), 6);
+/// encoder.write_all(b"hello gzip").unwrap();
+/// let compressed = encoder.finish().unwrap();
+///
+/// let mut decompressed = Vec::new();
+/// GzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+/// assert_eq!(decompressed, b"hello gzip");
+/// ```
+pub struct GzEncoder<W: Write> {
+    writer: DeflateWriter<W>,
+    header: Option<Vec<u8>>, // written before the first data
+    crc: Crc32,
+    size: u32,
+}
+
+impl<W: Write> GzEncoder<W> {
+    /// `level` is the deflate level 0-10.
+    pub fn new(output: W, level: u8) -> Self {
+        Self::with_header(output, level, &GzHeader::default())
+    }
+
+    pub fn with_header(output: W, level: u8, header: &GzHeader) -> Self {
+        Self {
+            writer: DeflateWriter::new(output, level, false),
+            header: Some(header.to_bytes(level)),
+            crc: Crc32::new(),
+            size: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if let Some(header) = self.header.take() {
+            self.writer.get_mut().write_all(&header)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the rest of the stream including the CRC32 and size trailer and returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take_output())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.writer.is_finished() {
+            return Ok(());
+        }
+        self.write_header()?;
+        self.writer.finish_stream()?;
+        let mut trailer = [0u8; 8];
+        trailer[0..4].copy_from_slice(&self.crc.checksum().to_le_bytes());
+        trailer[4..8].copy_from_slice(&self.size.to_le_bytes());
+        self.writer.get_mut().write_all(&trailer)
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        self.writer.write_data(buf)?;
+        self.crc.write_slice(buf);
+        self.size = self.size.wrapping_add(buf.len() as u32);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.writer.flush_data()
+    }
+}
+
+impl<W: Write> Drop for GzEncoder<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GzState {
+    Header,
+    Body,
+    Done,
+}
+
+/// Decompresses gzip data read from `R`. Concatenated members are decoded as one stream,
+/// like the `gzip` tool does. CRC32 or size mismatches are `InvalidData` errors.
+pub struct GzDecoder<R: Read> {
+    reader: InflateReader<R>,
+    state: GzState,
+    header: Option<GzHeader>,
+    multi_member: bool,
+    crc: Crc32,
+    size: u32,
+}
+
+impl<R: Read> GzDecoder<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            reader: InflateReader::new(input, DataFormat::Raw),
+            state: GzState::Header,
+            header: None,
+            multi_member: true,
+            crc: Crc32::new(),
+            size: 0,
+        }
+    }
+
+    /// Stops after the first member instead of decoding the members that follow it. The input is
+    /// read in blocks, so bytes after the first member may already have been taken from `R`.
+    pub fn single_member(mut self) -> Self {
+        self.multi_member = false;
+        self
+    }
+
+    /// Header of the first member, once it has been read.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        if self.header.is_some() && (!self.multi_member || self.reader.at_end()?) {
+            self.state = GzState::Done;
+            return Ok(());
+        }
+        let id1 = self.reader.read_u8()?;
+        let id2 = self.reader.read_u8()?;
+        if id1 != GZIP_ID1 || id2 != GZIP_ID2 {
+            return Err(invalid_data("not a gzip stream"));
+        }
+        let header = GzHeader::from_reader(&mut self.reader)?;
+        if self.header.is_none() {
+            self.header = Some(header);
+        }
+        self.reader.reset(DataFormat::Raw);
+        self.crc = Crc32::new();
+        self.size = 0;
+        self.state = GzState::Body;
+        Ok(())
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        let mut trailer = [0u8; 8];
+        self.reader.read_bytes(&mut trailer)?;
+        if u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != self.crc.checksum() {
+            return Err(invalid_data("gzip CRC32 mismatch"));
+        }
+        if u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) != self.size {
+            return Err(invalid_data("gzip size mismatch"));
+        }
+        self.state = GzState::Header;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.state {
+                GzState::Header => self.read_header()?,
+                GzState::Body => {
+                    let len = self.reader.read_inflated(buf)?;
+                    if len > 0 {
+                        self.crc.write_slice(&buf[0..len]);
+                        self.size = self.size.wrapping_add(len as u32);
+                        return Ok(len);
+                    }
+                    self.read_trailer()?;
+                }
+                GzState::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Compresses `input` into a single member gzip file.
+pub fn compress_to_vec_gzip(input: &[u8], level: u8) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(input.len() / 2 + 32), level);
+    encoder.write_all(input).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Decompresses all members of a gzip file.
+pub fn decompress_to_vec_gzip(input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 2);
+    GzDecoder::new(input).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| ((i * 31) ^ (i >> 9)) as u8).collect()
+    }
+
+    #[test]
+    fn roundtrip_with_header() {
+        let header = GzHeader {
+            filename: Some(b"data.bin".to_vec()),
+            comment: Some(b"a comment".to_vec()),
+            extra: Some(vec![1, 2, 3, 4]),
+            mtime: 1_600_000_000,
+            operating_system: 3,
+            text: true,
+        };
+        let data = test_data(100000);
+        let mut encoder = GzEncoder::with_header(Vec::new(), 6, &header);
+        for chunk in data.chunks(999) {
+            encoder.write_all(chunk).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(&compressed[0..3], &[0x1f, 0x8b, 8]);
+
+        let mut decoder = GzDecoder::new(&compressed[..]);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+        assert_eq!(decoder.header(), Some(&header));
+    }
+
+    #[test]
+    fn empty_and_levels() {
+        for level in [0, 1, 6, 10] {
+            for len in [0, 1, 70000] {
+                let data = test_data(len);
+                assert_eq!(decompress_to_vec_gzip(&compress_to_vec_gzip(&data, level)).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn multi_member() {
+        let mut compressed = compress_to_vec_gzip(b"first ", 6);
+        compressed.extend_from_slice(&compress_to_vec_gzip(b"", 6));
+        compressed.extend_from_slice(&compress_to_vec_gzip(b"second", 1));
+        assert_eq!(decompress_to_vec_gzip(&compressed).unwrap(), b"first second");
+
+        let mut output = Vec::new();
+        GzDecoder::new(&compressed[..]).single_member().read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"first ");
+
+        // garbage after a member is an error
+        compressed.extend_from_slice(b"junk");
+        assert!(decompress_to_vec_gzip(&compressed).is_err());
+    }
+
+    #[test]
+    fn header_checksum() {
+        // a hand made header with FHCRC
+        let mut compressed = vec![0x1f, 0x8b, 8, FLAG_HCRC, 0, 0, 0, 0, 0, 255];
+        let crc = crate::crc32::crc32(&compressed) as u16;
+        compressed.extend_from_slice(&crc.to_le_bytes());
+        compressed.extend_from_slice(&crate::compress_to_vec(b"abc", 6));
+        compressed.extend_from_slice(&crate::crc32::crc32(b"abc").to_le_bytes());
+        compressed.extend_from_slice(&3u32.to_le_bytes());
+        assert_eq!(decompress_to_vec_gzip(&compressed).unwrap(), b"abc");
+        compressed[10] ^= 1;
+        assert!(decompress_to_vec_gzip(&compressed).is_err());
+    }
+
+    #[test]
+    fn corrupt_trailer() {
+        let compressed = compress_to_vec_gzip(&test_data(5000), 6);
+        let len = compressed.len();
+        for pos in [len - 8, len - 1] {
+            let mut corrupt = compressed.clone();
+            corrupt[pos] ^= 0x10;
+            let error = decompress_to_vec_gzip(&corrupt).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        let error = decompress_to_vec_gzip(&compressed[0..len - 3]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(decompress_to_vec_gzip(b"").is_err());
+    }
+
+    #[test]
+    fn drop_finishes_stream() {
+        let mut output = Vec::new();
+        {
+            let mut encoder = GzEncoder::new(&mut output, 6);
+            encoder.write_all(b"dropped").unwrap();
+        }
+        assert_eq!(decompress_to_vec_gzip(&output).unwrap(), b"dropped");
+    }
+}
@@ -6,7 +6,7 @@ use ::core::usize;
 
 pub mod core;
 mod output_buffer;
-//pub mod stream;
+pub mod stream;
 use self::core::*;
 
 const TINFL_STATUS_FAILED_CANNOT_MAKE_PROGRESS: i32 = -4;
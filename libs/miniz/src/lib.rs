@@ -22,10 +22,14 @@
 //! ```
 
 pub mod adler32;
+pub mod crc32;
 pub mod deflate;
+pub mod gzip;
 pub mod inflate;
 pub mod zip_file;
+pub mod zlib;
 mod shared;
+mod stream_io;
 
 pub use crate::deflate::compress_to_vec;
 pub use crate::deflate::compress_to_vec_zlib;
@@ -33,7 +37,10 @@ pub use crate::deflate::compress_to_vec_zlib;
 pub use crate::inflate::decompress_to_vec;
 pub use crate::inflate::decompress_to_vec_zlib;
 
+pub use crate::gzip::{compress_to_vec_gzip, decompress_to_vec_gzip};
+
 pub use crate::shared::update_adler32 as mz_adler32_oxide;
+pub use crate::crc32::update_crc32 as mz_crc32_oxide;
 pub use crate::shared::{MZ_ADLER32_INIT, MZ_DEFAULT_WINDOW_BITS};
 
 /// A list of flush types.
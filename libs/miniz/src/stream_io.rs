@@ -0,0 +1,180 @@
+//! Buffered deflate `Write` and inflate `Read` cores shared by the gzip and zlib wrappers.
+
+use std::io::{self, Read, Write};
+
+use crate::deflate::core::{compress, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus};
+use crate::inflate::stream::{inflate, InflateState};
+use crate::{DataFormat, MZError, MZFlush, MZStatus};
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Compresses everything written to it into `output`, raw deflate or zlib framed.
+pub(crate) struct DeflateWriter<W: Write> {
+    output: Option<W>,
+    compressor: Box<CompressorOxide>,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl<W: Write> DeflateWriter<W> {
+    pub(crate) fn new(output: W, level: u8, zlib: bool) -> Self {
+        let window_bits = if zlib {1} else {0};
+        let flags = create_comp_flags_from_zip_params(level.into(), window_bits, 0);
+        Self {
+            output: Some(output),
+            compressor: Box::new(CompressorOxide::new(flags)),
+            buffer: vec![0; BUFFER_SIZE],
+            finished: false,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &W {
+        self.output.as_ref().unwrap()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        self.output.as_mut().unwrap()
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn compress(&mut self, mut data: &[u8], flush: TDEFLFlush) -> io::Result<()> {
+        let output = match &mut self.output {
+            Some(output) if !self.finished => output,
+            _ => return Err(io::Error::other("stream already finished")),
+        };
+        loop {
+            let (status, bytes_in, bytes_out) = compress(&mut self.compressor, data, &mut self.buffer, flush);
+            output.write_all(&self.buffer[0..bytes_out])?;
+            data = &data[bytes_in..];
+            match status {
+                TDEFLStatus::Done => return Ok(()),
+                TDEFLStatus::Okay => {
+                    // a full output buffer means there may be more to flush
+                    if data.is_empty() && (flush == TDEFLFlush::None || bytes_out < self.buffer.len()) {
+                        return Ok(());
+                    }
+                }
+                _ => return Err(io::Error::other("deflate failed")),
+            }
+        }
+    }
+
+    pub(crate) fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        self.compress(data, TDEFLFlush::None)
+    }
+
+    pub(crate) fn flush_data(&mut self) -> io::Result<()> {
+        self.compress(&[], TDEFLFlush::Sync)?;
+        self.get_mut().flush()
+    }
+
+    /// Ends the deflate stream, the output stays available for a trailer.
+    pub(crate) fn finish_stream(&mut self) -> io::Result<()> {
+        self.compress(&[], TDEFLFlush::Finish)?;
+        self.finished = true;
+        Ok(())
+    }
+
+    pub(crate) fn take_output(&mut self) -> W {
+        self.output.take().unwrap()
+    }
+}
+
+/// Inflates raw deflate or zlib data from `input`, with its own input buffer so the
+/// bytes following the compressed stream (like a gzip trailer) can be read too.
+pub(crate) struct InflateReader<R: Read> {
+    input: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    len: usize,
+    state: Box<InflateState>,
+    stream_end: bool,
+}
+
+impl<R: Read> InflateReader<R> {
+    pub(crate) fn new(input: R, format: DataFormat) -> Self {
+        Self {
+            input,
+            buffer: vec![0; BUFFER_SIZE],
+            pos: 0,
+            len: 0,
+            state: InflateState::new_boxed(format),
+            stream_end: false,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.input
+    }
+
+    /// Starts a new compressed stream at the current position.
+    pub(crate) fn reset(&mut self, format: DataFormat) {
+        self.state.reset(format);
+        self.stream_end = false;
+    }
+
+    // refills the buffer when it is empty, returns false at the end of the input
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.pos == self.len {
+            self.pos = 0;
+            self.len = loop {
+                match self.input.read(&mut self.buffer) {
+                    Ok(len) => break len,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            };
+        }
+        Ok(self.pos < self.len)
+    }
+
+    pub(crate) fn at_end(&mut self) -> io::Result<bool> {
+        Ok(!self.fill()?)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> io::Result<u8> {
+        if !self.fill()? {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of stream"));
+        }
+        self.pos += 1;
+        Ok(self.buffer[self.pos - 1])
+    }
+
+    pub(crate) fn read_bytes(&mut self, dst: &mut [u8]) -> io::Result<()> {
+        for byte in dst {
+            *byte = self.read_u8()?;
+        }
+        Ok(())
+    }
+
+    /// Reads decompressed data, returns 0 at the end of the compressed stream.
+    pub(crate) fn read_inflated(&mut self, output: &mut [u8]) -> io::Result<usize> {
+        if output.is_empty() {
+            return Ok(0);
+        }
+        while !self.stream_end {
+            let result = inflate(&mut self.state, &self.buffer[self.pos..self.len], output, MZFlush::None);
+            self.pos += result.bytes_consumed;
+            match result.status {
+                Ok(MZStatus::StreamEnd) => self.stream_end = true,
+                Ok(_) | Err(MZError::Buf) => {
+                    if result.bytes_written == 0 && self.pos == self.len && !self.fill()? {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "compressed stream is truncated"));
+                    }
+                }
+                Err(_) => return Err(invalid_data("corrupt compressed stream")),
+            }
+            if result.bytes_written > 0 {
+                return Ok(result.bytes_written);
+            }
+        }
+        Ok(0)
+    }
+}
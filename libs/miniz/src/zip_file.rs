@@ -1,7 +1,11 @@
-// zip fileformat reading
+// zip fileformat reading and writing
 
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
 pub use crate::inflate::decompress_to_vec;
+use crate::crc32::Crc32;
+use crate::inflate::stream::{inflate, InflateState};
+use crate::{DataFormat, MZError, MZFlush, MZStatus};
+use crate::deflate::core::{compress_to_output, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus};
 
 pub const COMPRESS_METHOD_UNCOMPRESSED:u16 = 0;
 pub const COMPRESS_METHOD_DEFLATED:u16 = 8;
@@ -73,15 +77,18 @@ pub struct CentralDirectoryFileHeader {
     pub last_mod_file_time: u16,
     pub last_mod_file_date: u16,
     pub crc32: u32,
-    pub compressed_size: u32,
-    pub uncompressed_size: u32,
+    /// resolved from the ZIP64 extra field if needed
+    pub compressed_size: u64,
+    /// resolved from the ZIP64 extra field if needed
+    pub uncompressed_size: u64,
     pub file_name_length: u16,
     pub extra_field_length: u16,
     pub file_comment_length: u16,
     pub disk_number_start: u16,
     pub internal_file_attributes: u16,
     pub external_file_attributes: u32,
-    pub relative_offset_of_local_header: u32,
+    /// resolved from the ZIP64 extra field if needed
+    pub relative_offset_of_local_header: u64,
 
     pub file_name: String,
     pub file_comment: String,
@@ -100,19 +107,28 @@ impl CentralDirectoryFileHeader{
         let last_mod_file_time = read_u16(zip_data)?;
         let last_mod_file_date = read_u16(zip_data)?;
         let crc32 = read_u32(zip_data)?;
-        let compressed_size = read_u32(zip_data)?;
-        let uncompressed_size = read_u32(zip_data)?;
+        let mut compressed_size = read_u32(zip_data)? as u64;
+        let mut uncompressed_size = read_u32(zip_data)? as u64;
         let file_name_length = read_u16(zip_data)?;
         let extra_field_length = read_u16(zip_data)?;
         let file_comment_length = read_u16(zip_data)?;
         let disk_number_start = read_u16(zip_data)?;
         let internal_file_attributes = read_u16(zip_data)?;
         let external_file_attributes = read_u32(zip_data)?;
-        let relative_offset_of_local_header = read_u32(zip_data)?;
+        let mut relative_offset_of_local_header = read_u32(zip_data)? as u64;
         let file_name = read_string(zip_data, file_name_length as usize)?;
-        zip_data.seek(SeekFrom::Current(extra_field_length as i64)).map_err(|_| ZipError::CantSeekSkip)?;
+        let extra_field = read_binary(zip_data, extra_field_length as usize)?;
         let file_comment = read_string(zip_data, file_comment_length as usize)?;
         
+        // the zip64 extra field holds exactly those values that are 0xFFFFFFFF in the header, in this order
+        if let Some(mut zip64) = find_extra_field(&extra_field, ZIP64_EXTRA_FIELD_ID){
+            for value in [&mut uncompressed_size, &mut compressed_size, &mut relative_offset_of_local_header]{
+                if *value == 0xFFFF_FFFF{
+                    *value = read_u64(&mut zip64)?;
+                }
+            }
+        }
+        
         Ok(Self{
             signature,
             version_made_by,
@@ -170,83 +186,222 @@ impl EndOfCentralDirectory{
     }
 }
 
-fn read_u16(zip_data:&mut impl Read)->Result<u16, ZipError>{
-    let mut bytes = [0u8;2];
-    if let Ok(size) = zip_data.read(&mut bytes){
-        if size != 2{
-            return Err(ZipError::DataReadError)
+#[derive(Clone, Debug)]
+pub struct Zip64EndOfCentralDirectory {
+    pub signature: u32,
+    pub size_of_record: u64,
+    pub version_made_by: u16,
+    pub version_needed_to_extract: u16,
+    pub number_of_disk: u32,
+    pub number_of_start_central_directory_disk: u32,
+    pub total_entries_this_disk: u64,
+    pub total_entries_all_disk: u64,
+    pub size_of_the_central_directory: u64,
+    pub central_directory_offset: u64,
+}
+
+impl Zip64EndOfCentralDirectory{
+    pub fn from_stream(zip_data:&mut impl Read)->Result<Self, ZipError>{
+        let signature =  read_u32(zip_data)?;
+        if signature != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE{
+            return Err(ZipError::EndOfCentralDirectoryInvalid)
         }
-        return Ok(u16::from_le_bytes(bytes))
+        Ok(Self{
+            signature,
+            size_of_record: read_u64(zip_data)?,
+            version_made_by: read_u16(zip_data)?,
+            version_needed_to_extract: read_u16(zip_data)?,
+            number_of_disk: read_u32(zip_data)?,
+            number_of_start_central_directory_disk: read_u32(zip_data)?,
+            total_entries_this_disk: read_u64(zip_data)?,
+            total_entries_all_disk: read_u64(zip_data)?,
+            size_of_the_central_directory: read_u64(zip_data)?,
+            central_directory_offset: read_u64(zip_data)?,
+        })
     }
-    return Err(ZipError::DataReadError)
 }
 
-fn read_u32(zip_data:&mut impl Read)->Result<u32, ZipError>{
-    let mut bytes = [0u8;4];
-    if let Ok(size) = zip_data.read(&mut bytes){
-        if size != 4{
-            return Err(ZipError::DataReadError)
+// returns the data of an extra field with the given header id
+fn find_extra_field(extra_field:&[u8], header_id:u16)->Option<&[u8]>{
+    let mut pos = 0;
+    while pos + 4 <= extra_field.len(){
+        let id = u16::from_le_bytes([extra_field[pos], extra_field[pos + 1]]);
+        let size = u16::from_le_bytes([extra_field[pos + 2], extra_field[pos + 3]]) as usize;
+        let data = extra_field.get(pos + 4..pos + 4 + size)?;
+        if id == header_id{
+            return Some(data)
         }
-        return Ok(u32::from_le_bytes(bytes))
+        pos += 4 + size;
     }
-    return Err(ZipError::DataReadError)
+    None
+}
+
+fn read_u16(zip_data:&mut impl Read)->Result<u16, ZipError>{
+    let mut bytes = [0u8;2];
+    zip_data.read_exact(&mut bytes).map_err(|_| ZipError::DataReadError)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(zip_data:&mut impl Read)->Result<u32, ZipError>{
+    let mut bytes = [0u8;4];
+    zip_data.read_exact(&mut bytes).map_err(|_| ZipError::DataReadError)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(zip_data:&mut impl Read)->Result<u64, ZipError>{
+    let mut bytes = [0u8;8];
+    zip_data.read_exact(&mut bytes).map_err(|_| ZipError::DataReadError)?;
+    Ok(u64::from_le_bytes(bytes))
 }
 
 fn read_string(zip_data:&mut impl Read, len:usize)->Result<String, ZipError>{
-    let mut data = Vec::new();
-    data.resize(len,0u8);
-    if let Ok(size) = zip_data.read(&mut data){
-        if size != data.len(){
-            return Err(ZipError::DataReadError)
-        }
-        if let Ok(s) = String::from_utf8(data){
-            return Ok(s)
-        }
-        return Err(ZipError::ReadStringError)
-    }
-    return Err(ZipError::DataReadError)
+    let data = read_binary(zip_data, len)?;
+    String::from_utf8(data).map_err(|_| ZipError::ReadStringError)
 }
 
 fn read_binary(zip_data:&mut impl Read, len:usize)->Result<Vec<u8>, ZipError>{
-    let mut data = Vec::new();
-    data.resize(len,0u8);
-    if let Ok(size) = zip_data.read(&mut data){
-        if size != data.len(){
-            return Err(ZipError::DataReadError)
-        }
-        return Ok(data)
-    }
-    return Err(ZipError::DataReadError)
+    let mut data = vec![0u8; len];
+    zip_data.read_exact(&mut data).map_err(|_| ZipError::DataReadError)?;
+    Ok(data)
 }
 
 pub struct ZipCentralDirectory{
     pub eocd: EndOfCentralDirectory,
+    pub eocd64: Option<Zip64EndOfCentralDirectory>,
     pub file_headers: Vec<CentralDirectoryFileHeader>,
 }
 
 impl CentralDirectoryFileHeader{
     // lets read and unzip specific files.
     pub fn extract(&self, zip_data: &mut (impl Seek+Read))->Result<Vec<u8>, ZipError>{
-        zip_data.seek(SeekFrom::Start(self.relative_offset_of_local_header as u64)).map_err(|_| ZipError::CantSeekToFileHeader)?;
+        let mut reader = self.reader(zip_data)?;
+        let mut decompressed = Vec::with_capacity(self.uncompressed_size.min(1 << 30) as usize);
+        reader.read_to_end(&mut decompressed).map_err(ZipError::from_io_error)?;
+        Ok(decompressed)
+    }
+    
+    /// Returns a `Read` that decompresses the file as it is read, and checks the CRC32 at the end.
+    pub fn reader<'a, R:Seek+Read>(&self, zip_data: &'a mut R)->Result<ZipEntryReader<'a, R>, ZipError>{
+        zip_data.seek(SeekFrom::Start(self.relative_offset_of_local_header)).map_err(|_| ZipError::CantSeekToFileHeader)?;
         let header = LocalFileHeader::from_stream(zip_data)?;
-        if header.compression_method == COMPRESS_METHOD_UNCOMPRESSED{
-            let decompressed = read_binary(zip_data, self.uncompressed_size as usize)?;
-            return Ok(decompressed)
-        }
-        else if header.compression_method == COMPRESS_METHOD_DEFLATED{
-            let compressed = read_binary(zip_data, self.compressed_size as usize)?;
-            if let Ok(decompressed) = decompress_to_vec(&compressed){
-                return Ok(decompressed);
+        let inflater = match header.compression_method{
+            COMPRESS_METHOD_UNCOMPRESSED=>None,
+            COMPRESS_METHOD_DEFLATED=>Some(InflateState::new_boxed(DataFormat::Raw)),
+            _=>return Err(ZipError::UnsupportedCompressionMethod)
+        };
+        // sizes come from the central directory, the local header may defer them to a data descriptor
+        if inflater.is_none() && self.compressed_size != self.uncompressed_size{
+            return Err(ZipError::SizeMismatch)
+        }
+        Ok(ZipEntryReader{
+            zip_data,
+            inflater,
+            remaining: self.compressed_size,
+            input: Vec::new(),
+            input_pos: 0,
+            inflate_done: false,
+            crc: Crc32::new(),
+            expected_crc32: self.crc32,
+            uncompressed_size: self.uncompressed_size,
+            total_out: 0,
+            done: false,
+        })
+    }
+}
+
+const ZIP_ENTRY_READ_BUFFER_SIZE:usize = 64 * 1024;
+
+/// Streaming reader for a single zip entry, made with `CentralDirectoryFileHeader::reader`.
+///
+/// At the end of the data the size and CRC32 are checked, a mismatch is returned
+/// as an `InvalidData` error wrapping a `ZipError`.
+pub struct ZipEntryReader<'a, R:Read>{
+    zip_data: &'a mut R,
+    inflater: Option<Box<InflateState>>,
+    remaining: u64, // compressed bytes not yet read from zip_data
+    input: Vec<u8>,
+    input_pos: usize,
+    inflate_done: bool,
+    crc: Crc32,
+    expected_crc32: u32,
+    uncompressed_size: u64,
+    total_out: u64,
+    done: bool,
+}
+
+impl<'a, R:Read> ZipEntryReader<'a, R>{
+    fn fill_input(&mut self)->std::io::Result<()>{
+        let len = (ZIP_ENTRY_READ_BUFFER_SIZE as u64).min(self.remaining) as usize;
+        self.input.resize(len, 0);
+        self.zip_data.read_exact(&mut self.input)?;
+        self.input_pos = 0;
+        self.remaining -= len as u64;
+        Ok(())
+    }
+    
+    fn read_stored(&mut self, buf:&mut [u8])->std::io::Result<usize>{
+        let len = (buf.len() as u64).min(self.remaining) as usize;
+        self.zip_data.read_exact(&mut buf[0..len])?;
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+    
+    fn read_deflated(&mut self, buf:&mut [u8])->std::io::Result<usize>{
+        loop{
+            if self.inflate_done{
+                return Ok(0)
             }
-            else{
-                return Err(ZipError::DecompressionError)
+            if self.input_pos == self.input.len() && self.remaining > 0{
+                self.fill_input()?;
+            }
+            let inflater = self.inflater.as_mut().unwrap();
+            let result = inflate(inflater, &self.input[self.input_pos..], buf, MZFlush::None);
+            self.input_pos += result.bytes_consumed;
+            match result.status{
+                Ok(MZStatus::StreamEnd)=>{
+                    self.inflate_done = true;
+                }
+                Ok(_) | Err(MZError::Buf)=>{
+                    if result.bytes_written == 0 && result.bytes_consumed == 0 && self.remaining == 0 && self.input_pos == self.input.len(){
+                        return Err(ZipError::DecompressionError.into_io_error())
+                    }
+                }
+                Err(_)=>return Err(ZipError::DecompressionError.into_io_error())
+            }
+            if result.bytes_written > 0{
+                return Ok(result.bytes_written)
             }
         }
-        return Err(ZipError::UnsupportedCompressionMethod)
     }
 }
 
-#[derive(Debug)]
+impl<'a, R:Read> Read for ZipEntryReader<'a, R>{
+    fn read(&mut self, buf:&mut [u8])->std::io::Result<usize>{
+        if self.done || buf.is_empty(){
+            return Ok(0)
+        }
+        let len = if self.inflater.is_some(){
+            self.read_deflated(buf)?
+        }
+        else{
+            self.read_stored(buf)?
+        };
+        self.crc.write_slice(&buf[0..len]);
+        self.total_out += len as u64;
+        if len == 0{
+            self.done = true;
+            if self.total_out != self.uncompressed_size{
+                return Err(ZipError::SizeMismatch.into_io_error())
+            }
+            if self.crc.checksum() != self.expected_crc32{
+                return Err(ZipError::CrcMismatch.into_io_error())
+            }
+        }
+        Ok(len)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum ZipError{
     LocalFileHeaderInvalid,
     CentralDirectoryFileHeaderInvalid,
@@ -259,20 +414,741 @@ pub enum ZipError{
     CantSeekToDirStart,
     UnsupportedCompressionMethod,
     DecompressionError,
-    DataReadError
+    DataReadError,
+    DataWriteError,
+    CompressionError,
+    FileTooLarge,
+    NoFileStarted,
+    CrcMismatch,
+    SizeMismatch,
+    FileNameTooLong,
+}
+
+impl ZipError{
+    fn into_io_error(self)->std::io::Error{
+        std::io::Error::new(std::io::ErrorKind::InvalidData, self)
+    }
+    
+    fn from_io_error(error:std::io::Error)->ZipError{
+        match error.get_ref().and_then(|e| e.downcast_ref::<ZipError>()){
+            Some(e)=>e.clone(),
+            None=>ZipError::DataReadError
+        }
+    }
+}
+
+impl std::fmt::Display for ZipError{
+    fn fmt(&self, f:&mut std::fmt::Formatter)->std::fmt::Result{
+        write!(f, "{:?}", self)
+    }
 }
 
+impl std::error::Error for ZipError{}
+
 pub fn zip_read_central_directory(zip_data:&mut (impl Seek+Read))->Result<ZipCentralDirectory, ZipError>{
-    // lets read the the dirend
-    zip_data.seek(SeekFrom::End(-(END_OF_CENTRAL_DIRECTORY_SIZE as i64))).map_err(|_| ZipError::CantSeekToDirEnd)?;
-    let eocd = EndOfCentralDirectory::from_stream(zip_data)?;
-    zip_data.seek(SeekFrom::Start(eocd.central_directory_offset as u64)).map_err(|_| ZipError::CantSeekToDirStart)?;
+    // lets find the the dirend, it is followed by a comment of up to 64k
+    let file_size = zip_data.seek(SeekFrom::End(0)).map_err(|_| ZipError::CantSeekToDirEnd)?;
+    let tail_size = file_size.min((END_OF_CENTRAL_DIRECTORY_SIZE + 0xFFFF) as u64);
+    zip_data.seek(SeekFrom::Start(file_size - tail_size)).map_err(|_| ZipError::CantSeekToDirEnd)?;
+    let tail = read_binary(zip_data, tail_size as usize)?;
+    let signature = END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+    let eocd_pos = (0..(tail.len() + 1).saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE)).rev()
+        .find(|pos| tail[*pos..*pos + 4] == signature)
+        .ok_or(ZipError::EndOfCentralDirectoryInvalid)?;
+    let eocd = EndOfCentralDirectory::from_stream(&mut &tail[eocd_pos..])?;
+    
+    // a zip64 locator right before the dirend points at the zip64 dirend
+    let eocd_offset = file_size - tail_size + eocd_pos as u64;
+    let mut eocd64 = None;
+    if eocd_offset >= ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE as u64{
+        zip_data.seek(SeekFrom::Start(eocd_offset - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE as u64)).map_err(|_| ZipError::CantSeekToDirEnd)?;
+        if read_u32(zip_data)? == ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE{
+            let _disk = read_u32(zip_data)?;
+            let eocd64_offset = read_u64(zip_data)?;
+            zip_data.seek(SeekFrom::Start(eocd64_offset)).map_err(|_| ZipError::CantSeekToDirEnd)?;
+            eocd64 = Some(Zip64EndOfCentralDirectory::from_stream(zip_data)?);
+        }
+    }
+    let (central_directory_offset, total_entries) = if let Some(eocd64) = &eocd64{
+        (eocd64.central_directory_offset, eocd64.total_entries_all_disk)
+    }
+    else{
+        (eocd.central_directory_offset as u64, eocd.total_entries_all_disk as u64)
+    };
+    
+    zip_data.seek(SeekFrom::Start(central_directory_offset)).map_err(|_| ZipError::CantSeekToDirStart)?;
     let mut file_headers = Vec::new();
-    for _ in 0..eocd.total_entries_all_disk as usize{
+    for _ in 0..total_entries{
         file_headers.push(CentralDirectoryFileHeader::from_stream(zip_data)?);
     }
     Ok(ZipCentralDirectory{
         eocd,
+        eocd64,
         file_headers
     })
 }
+
+pub const DATA_DESCRIPTOR_SIGNATURE:u32 = 0x08074b50;
+pub const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE:u32 = 0x06064b50;
+pub const ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE:usize = 56;
+pub const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE:u32 = 0x07064b50;
+pub const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE:usize = 20;
+pub const ZIP64_EXTRA_FIELD_ID:u16 = 0x0001;
+
+pub const GENERAL_PURPOSE_DATA_DESCRIPTOR:u16 = 0x0008;
+pub const GENERAL_PURPOSE_UTF8:u16 = 0x0800;
+
+const VERSION_DEFAULT:u16 = 20;
+const VERSION_ZIP64:u16 = 45;
+const VERSION_MADE_BY_UNIX:u16 = 3 << 8;
+
+/// Packs a date and time into the MS-DOS `(time, date)` format used by zip headers.
+pub fn dos_date_time(year:u16, month:u16, day:u16, hour:u16, minute:u16, second:u16)->(u16, u16){
+    let time = (hour << 11) | (minute << 5) | (second / 2);
+    let date = (year.saturating_sub(1980) << 9) | (month << 5) | day;
+    (time, date)
+}
+
+#[derive(Clone, Debug)]
+pub struct ZipFileOptions {
+    /// `COMPRESS_METHOD_DEFLATED` or `COMPRESS_METHOD_UNCOMPRESSED`
+    pub compression_method: u16,
+    /// deflate level 0-10
+    pub compression_level: u8,
+    pub last_mod_file_time: u16,
+    pub last_mod_file_date: u16,
+    /// unix mode bits, for instance 0o755 for executables
+    pub unix_permissions: u32,
+    /// write ZIP64 headers for a streamed file that may end up 4GB or larger
+    pub large_file: bool,
+}
+
+impl Default for ZipFileOptions{
+    fn default()->Self{
+        let (last_mod_file_time, last_mod_file_date) = dos_date_time(1980, 1, 1, 0, 0, 0);
+        Self{
+            compression_method: COMPRESS_METHOD_DEFLATED,
+            compression_level: 6,
+            last_mod_file_time,
+            last_mod_file_date,
+            unix_permissions: 0o644,
+            large_file: false,
+        }
+    }
+}
+
+struct ZipWriterEntry{
+    file_name: String,
+    general_purpose_bit_flag: u16,
+    compression_method: u16,
+    last_mod_file_time: u16,
+    last_mod_file_date: u16,
+    crc32: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    external_file_attributes: u32,
+    relative_offset_of_local_header: u64,
+    large_file: bool,
+    // the local header has a zip64 extra field, so the data descriptor has 8 byte sizes
+    zip64_header: bool,
+}
+
+impl ZipWriterEntry{
+    fn needs_zip64(&self)->bool{
+        self.large_file ||
+        self.compressed_size >= 0xFFFF_FFFF ||
+        self.uncompressed_size >= 0xFFFF_FFFF ||
+        self.relative_offset_of_local_header >= 0xFFFF_FFFF
+    }
+}
+
+// the file that is being written with `Write`
+struct ZipWriterStream{
+    entry: ZipWriterEntry,
+    crc: Crc32,
+    compressor: Option<Box<CompressorOxide>>,
+}
+
+/// Writes a zip archive to any `Write`, no seeking needed.
+///
+/// Files are either added in one go with `add_file`, or streamed with `start_file`
+/// followed by `write` calls, in which case the sizes and CRC follow the data in a data descriptor.
+/// `finish` writes the central directory.
+pub struct ZipWriter<W:Write>{
+    output: W,
+    offset: u64,
+    entries: Vec<ZipWriterEntry>,
+    stream: Option<ZipWriterStream>,
+}
+
+fn write_u16(dst:&mut Vec<u8>, value:u16){
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(dst:&mut Vec<u8>, value:u32){
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(dst:&mut Vec<u8>, value:u64){
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+fn clamp_u32(value:u64)->u32{
+    value.min(0xFFFF_FFFF) as u32
+}
+
+fn deflate_flags(level:u8)->u32{
+    create_comp_flags_from_zip_params(level.into(), 0, 0)
+}
+
+// the most deflate can turn `size` bytes into, the same bound as mz_compressBound
+fn deflate_bound(size:u64)->u64{
+    (128 + size * 110 / 100).max(128 + size + (size / (31 * 1024) + 1) * 5)
+}
+
+impl<W:Write> ZipWriter<W>{
+    pub fn new(output:W)->Self{
+        Self{
+            output,
+            offset: 0,
+            entries: Vec::new(),
+            stream: None,
+        }
+    }
+    
+    fn write_raw(&mut self, data:&[u8])->Result<(), ZipError>{
+        self.output.write_all(data).map_err(|_| ZipError::DataWriteError)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+    
+    fn new_entry(&self, file_name:&str, options:&ZipFileOptions, directory:bool)->ZipWriterEntry{
+        let mut general_purpose_bit_flag = 0;
+        if !file_name.is_ascii(){
+            general_purpose_bit_flag |= GENERAL_PURPOSE_UTF8;
+        }
+        // unix mode in the high word, msdos directory flag in the low word
+        let mode = if directory{0o040000 | options.unix_permissions}else{0o100000 | options.unix_permissions};
+        ZipWriterEntry{
+            file_name: file_name.to_string(),
+            general_purpose_bit_flag,
+            compression_method: if directory{COMPRESS_METHOD_UNCOMPRESSED}else{options.compression_method},
+            last_mod_file_time: options.last_mod_file_time,
+            last_mod_file_date: options.last_mod_file_date,
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            external_file_attributes: (mode << 16) | if directory{0x10}else{0},
+            relative_offset_of_local_header: self.offset,
+            large_file: options.large_file,
+            zip64_header: false,
+        }
+    }
+    
+    fn write_local_file_header(&mut self, entry:&mut ZipWriterEntry)->Result<(), ZipError>{
+        let zip64 = entry.needs_zip64();
+        entry.zip64_header = zip64;
+        let mut header = Vec::with_capacity(LOCAL_FILE_HEADER_SIZE + entry.file_name.len() + 20);
+        write_u32(&mut header, LOCAL_FILE_HEADER_SIGNATURE);
+        write_u16(&mut header, if zip64{VERSION_ZIP64}else{VERSION_DEFAULT});
+        write_u16(&mut header, entry.general_purpose_bit_flag);
+        write_u16(&mut header, entry.compression_method);
+        write_u16(&mut header, entry.last_mod_file_time);
+        write_u16(&mut header, entry.last_mod_file_date);
+        write_u32(&mut header, entry.crc32);
+        if zip64{
+            write_u32(&mut header, 0xFFFF_FFFF);
+            write_u32(&mut header, 0xFFFF_FFFF);
+        }
+        else{
+            write_u32(&mut header, entry.compressed_size as u32);
+            write_u32(&mut header, entry.uncompressed_size as u32);
+        }
+        write_u16(&mut header, entry.file_name.len() as u16);
+        write_u16(&mut header, if zip64{20}else{0});
+        header.extend_from_slice(entry.file_name.as_bytes());
+        if zip64{
+            write_u16(&mut header, ZIP64_EXTRA_FIELD_ID);
+            write_u16(&mut header, 16);
+            write_u64(&mut header, entry.uncompressed_size);
+            write_u64(&mut header, entry.compressed_size);
+        }
+        self.write_raw(&header)
+    }
+    
+    fn check_file_name(file_name:&str)->Result<(), ZipError>{
+        if file_name.len() > 0xFFFF{
+            return Err(ZipError::FileNameTooLong)
+        }
+        Ok(())
+    }
+    
+    /// Adds a file with all of its data at once.
+    pub fn add_file(&mut self, file_name:&str, data:&[u8], options:&ZipFileOptions)->Result<(), ZipError>{
+        self.end_stream()?;
+        Self::check_file_name(file_name)?;
+        let mut entry = self.new_entry(file_name, options, false);
+        let compressed = match entry.compression_method{
+            COMPRESS_METHOD_UNCOMPRESSED=>None,
+            COMPRESS_METHOD_DEFLATED=>Some(crate::deflate::compress_to_vec(data, options.compression_level)),
+            _=>return Err(ZipError::UnsupportedCompressionMethod)
+        };
+        let stored = compressed.as_deref().unwrap_or(data);
+        entry.crc32 = crate::crc32::crc32(data);
+        entry.uncompressed_size = data.len() as u64;
+        entry.compressed_size = stored.len() as u64;
+        self.write_local_file_header(&mut entry)?;
+        self.write_raw(stored)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+    
+    /// Adds an empty directory entry, a `/` is appended to the name if missing.
+    pub fn add_directory(&mut self, dir_name:&str, options:&ZipFileOptions)->Result<(), ZipError>{
+        self.end_stream()?;
+        let dir_name = if dir_name.ends_with('/'){dir_name.to_string()}else{format!("{}/", dir_name)};
+        Self::check_file_name(&dir_name)?;
+        let options = ZipFileOptions{
+            unix_permissions: 0o755,
+            large_file: false,
+            ..options.clone()
+        };
+        let mut entry = self.new_entry(&dir_name, &options, true);
+        self.write_local_file_header(&mut entry)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+    
+    /// Starts a file whose data is then written with `Write`, it ends with the next file or `finish`.
+    pub fn start_file(&mut self, file_name:&str, options:&ZipFileOptions)->Result<(), ZipError>{
+        self.end_stream()?;
+        Self::check_file_name(file_name)?;
+        let mut entry = self.new_entry(file_name, options, false);
+        entry.general_purpose_bit_flag |= GENERAL_PURPOSE_DATA_DESCRIPTOR;
+        let compressor = match entry.compression_method{
+            COMPRESS_METHOD_UNCOMPRESSED=>None,
+            COMPRESS_METHOD_DEFLATED=>Some(Box::new(CompressorOxide::new(deflate_flags(options.compression_level)))),
+            _=>return Err(ZipError::UnsupportedCompressionMethod)
+        };
+        self.write_local_file_header(&mut entry)?;
+        self.stream = Some(ZipWriterStream{
+            entry,
+            crc: Crc32::new(),
+            compressor,
+        });
+        Ok(())
+    }
+    
+    fn compress_stream(&mut self, stream:&mut ZipWriterStream, data:&[u8], flush:TDEFLFlush)->Result<(), ZipError>{
+        if let Some(compressor) = &mut stream.compressor{
+            let mut compressed = Vec::new();
+            let (status, _) = compress_to_output(compressor, data, flush, |out|{
+                compressed.extend_from_slice(out);
+                true
+            });
+            match status{
+                TDEFLStatus::Okay | TDEFLStatus::Done=>(),
+                _=>return Err(ZipError::CompressionError)
+            }
+            stream.entry.compressed_size += compressed.len() as u64;
+            self.write_raw(&compressed)?;
+        }
+        else{
+            stream.entry.compressed_size += data.len() as u64;
+            self.write_raw(data)?;
+        }
+        Ok(())
+    }
+    
+    // without a zip64 header, sizes have to fit the 4 byte fields of the data descriptor
+    fn check_stream_size(stream:&ZipWriterStream, size:u64)->Result<(), ZipError>{
+        if !stream.entry.zip64_header && size >= 0xFFFF_FFFF{
+            return Err(ZipError::FileTooLarge)
+        }
+        Ok(())
+    }
+    
+    /// Writes data to the file started with `start_file`.
+    ///
+    /// Sizes are checked before anything is written, against the most the compressed data can
+    /// grow to. If the data would make the file too large, it is rejected and the file can still
+    /// be ended.
+    pub fn write_data(&mut self, data:&[u8])->Result<(), ZipError>{
+        let mut stream = self.stream.take().ok_or(ZipError::NoFileStarted)?;
+        let size = stream.entry.uncompressed_size + data.len() as u64;
+        let max_size = if stream.compressor.is_some(){deflate_bound(size)}else{size};
+        if let Err(err) = Self::check_stream_size(&stream, max_size){
+            self.stream = Some(stream);
+            return Err(err)
+        }
+        self.compress_stream(&mut stream, data, TDEFLFlush::None)?;
+        stream.crc.write_slice(data);
+        stream.entry.uncompressed_size += data.len() as u64;
+        self.stream = Some(stream);
+        Ok(())
+    }
+    
+    fn end_stream(&mut self)->Result<(), ZipError>{
+        let mut stream = if let Some(stream) = self.stream.take(){stream}else{return Ok(())};
+        self.compress_stream(&mut stream, &[], TDEFLFlush::Finish)?;
+        let mut entry = stream.entry;
+        entry.crc32 = stream.crc.checksum();
+        let mut descriptor = Vec::with_capacity(24);
+        write_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+        write_u32(&mut descriptor, entry.crc32);
+        // sizes are 8 bytes if the local header had a zip64 extra field
+        if entry.zip64_header{
+            write_u64(&mut descriptor, entry.compressed_size);
+            write_u64(&mut descriptor, entry.uncompressed_size);
+        }
+        else{
+            write_u32(&mut descriptor, entry.compressed_size as u32);
+            write_u32(&mut descriptor, entry.uncompressed_size as u32);
+        }
+        self.write_raw(&descriptor)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+    
+    /// Writes the central directory and returns the output.
+    pub fn finish(mut self)->Result<W, ZipError>{
+        self.end_stream()?;
+        let central_directory_offset = self.offset;
+        let mut directory = Vec::new();
+        for entry in &self.entries{
+            let zip64 = entry.needs_zip64();
+            write_u32(&mut directory, CENTRAL_DIR_FILE_HEADER_SIGNATURE);
+            write_u16(&mut directory, VERSION_MADE_BY_UNIX | VERSION_ZIP64);
+            write_u16(&mut directory, if zip64{VERSION_ZIP64}else{VERSION_DEFAULT});
+            write_u16(&mut directory, entry.general_purpose_bit_flag);
+            write_u16(&mut directory, entry.compression_method);
+            write_u16(&mut directory, entry.last_mod_file_time);
+            write_u16(&mut directory, entry.last_mod_file_date);
+            write_u32(&mut directory, entry.crc32);
+            if zip64{
+                write_u32(&mut directory, 0xFFFF_FFFF);
+                write_u32(&mut directory, 0xFFFF_FFFF);
+            }
+            else{
+                write_u32(&mut directory, entry.compressed_size as u32);
+                write_u32(&mut directory, entry.uncompressed_size as u32);
+            }
+            write_u16(&mut directory, entry.file_name.len() as u16);
+            write_u16(&mut directory, if zip64{28}else{0});
+            write_u16(&mut directory, 0); // file comment length
+            write_u16(&mut directory, 0); // disk number start
+            write_u16(&mut directory, 0); // internal file attributes
+            write_u32(&mut directory, entry.external_file_attributes);
+            write_u32(&mut directory, if zip64{0xFFFF_FFFF}else{entry.relative_offset_of_local_header as u32});
+            directory.extend_from_slice(entry.file_name.as_bytes());
+            if zip64{
+                write_u16(&mut directory, ZIP64_EXTRA_FIELD_ID);
+                write_u16(&mut directory, 24);
+                write_u64(&mut directory, entry.uncompressed_size);
+                write_u64(&mut directory, entry.compressed_size);
+                write_u64(&mut directory, entry.relative_offset_of_local_header);
+            }
+        }
+        let central_directory_size = directory.len() as u64;
+        let total_entries = self.entries.len() as u64;
+        let zip64 = total_entries >= 0xFFFF ||
+            central_directory_offset + central_directory_size >= 0xFFFF_FFFF ||
+            self.entries.iter().any(|entry| entry.needs_zip64());
+        if zip64{
+            let zip64_eocd_offset = central_directory_offset + central_directory_size;
+            write_u32(&mut directory, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+            write_u64(&mut directory, (ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE - 12) as u64);
+            write_u16(&mut directory, VERSION_MADE_BY_UNIX | VERSION_ZIP64);
+            write_u16(&mut directory, VERSION_ZIP64);
+            write_u32(&mut directory, 0); // number of this disk
+            write_u32(&mut directory, 0); // disk with the central directory
+            write_u64(&mut directory, total_entries);
+            write_u64(&mut directory, total_entries);
+            write_u64(&mut directory, central_directory_size);
+            write_u64(&mut directory, central_directory_offset);
+            
+            write_u32(&mut directory, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE);
+            write_u32(&mut directory, 0); // disk with the zip64 end of central directory
+            write_u64(&mut directory, zip64_eocd_offset);
+            write_u32(&mut directory, 1); // total number of disks
+        }
+        write_u32(&mut directory, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        write_u16(&mut directory, 0);
+        write_u16(&mut directory, 0);
+        write_u16(&mut directory, total_entries.min(0xFFFF) as u16);
+        write_u16(&mut directory, total_entries.min(0xFFFF) as u16);
+        write_u32(&mut directory, clamp_u32(central_directory_size));
+        write_u32(&mut directory, clamp_u32(central_directory_offset));
+        write_u16(&mut directory, 0); // comment length
+        self.write_raw(&directory)?;
+        self.output.flush().map_err(|_| ZipError::DataWriteError)?;
+        Ok(self.output)
+    }
+}
+
+impl<W:Write> Write for ZipWriter<W>{
+    fn write(&mut self, buf:&[u8])->std::io::Result<usize>{
+        self.write_data(buf).map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self)->std::io::Result<()>{
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::io::Cursor;
+    use std::convert::TryInto;
+    
+    fn test_data(len:usize)->Vec<u8>{
+        (0..len).map(|i| ((i * 7) ^ (i >> 5)) as u8).collect()
+    }
+    
+    fn read_all(zip:&[u8])->Vec<(String, Vec<u8>)>{
+        let mut cursor = Cursor::new(zip);
+        let directory = zip_read_central_directory(&mut cursor).unwrap();
+        directory.file_headers.iter().map(|header|{
+            (header.file_name.clone(), header.extract(&mut cursor).unwrap())
+        }).collect()
+    }
+    
+    #[test]
+    fn write_and_read_back(){
+        let stored = ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()};
+        let executable = ZipFileOptions{unix_permissions: 0o755, ..Default::default()};
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("a.txt", b"hello hello hello hello", &ZipFileOptions::default()).unwrap();
+        zip.add_directory("dir", &ZipFileOptions::default()).unwrap();
+        zip.add_file("dir/stored.bin", &test_data(1000), &stored).unwrap();
+        zip.add_file("dir/empty", &[], &ZipFileOptions::default()).unwrap();
+        zip.start_file("dir/streamed.bin", &executable).unwrap();
+        for chunk in test_data(100000).chunks(777){
+            zip.write_all(chunk).unwrap();
+        }
+        zip.start_file("dir/streamed_stored.bin", &stored).unwrap();
+        zip.write_data(&test_data(5000)).unwrap();
+        zip.add_file("ünïcode.txt", b"x", &ZipFileOptions::default()).unwrap();
+        let data = zip.finish().unwrap();
+        
+        let files = read_all(&data);
+        let names:Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "dir/", "dir/stored.bin", "dir/empty", "dir/streamed.bin", "dir/streamed_stored.bin", "ünïcode.txt"]);
+        assert_eq!(files[0].1, b"hello hello hello hello");
+        assert!(files[1].1.is_empty());
+        assert_eq!(files[2].1, test_data(1000));
+        assert!(files[3].1.is_empty());
+        assert_eq!(files[4].1, test_data(100000));
+        assert_eq!(files[5].1, test_data(5000));
+        
+        let directory = zip_read_central_directory(&mut Cursor::new(&data)).unwrap();
+        let headers = &directory.file_headers;
+        assert_eq!(headers[0].compression_method, COMPRESS_METHOD_DEFLATED);
+        assert_eq!(headers[2].compression_method, COMPRESS_METHOD_UNCOMPRESSED);
+        assert_eq!(headers[4].crc32, crate::crc32::crc32(&test_data(100000)));
+        assert_ne!(headers[4].general_purpose_bit_flag & GENERAL_PURPOSE_DATA_DESCRIPTOR, 0);
+        assert!(headers[4].compressed_size < 100000);
+        assert_eq!(headers[4].external_file_attributes >> 16, 0o100755);
+        assert_eq!(headers[1].external_file_attributes >> 16, 0o040755);
+        assert_ne!(headers[6].general_purpose_bit_flag & GENERAL_PURPOSE_UTF8, 0);
+    }
+    
+    #[test]
+    fn data_descriptor_follows_data(){
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.start_file("f", &ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()}).unwrap();
+        zip.write_data(b"abc").unwrap();
+        let data = zip.finish().unwrap();
+        let descriptor = LOCAL_FILE_HEADER_SIZE + 1 + 3;
+        assert_eq!(u32::from_le_bytes(data[descriptor..descriptor + 4].try_into().unwrap()), DATA_DESCRIPTOR_SIGNATURE);
+        assert_eq!(u32::from_le_bytes(data[descriptor + 4..descriptor + 8].try_into().unwrap()), crate::crc32::crc32(b"abc"));
+        assert_eq!(u32::from_le_bytes(data[descriptor + 8..descriptor + 12].try_into().unwrap()), 3);
+    }
+    
+    #[test]
+    fn zip64_records(){
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.start_file("big", &ZipFileOptions{large_file: true, ..Default::default()}).unwrap();
+        zip.write_data(&test_data(3000)).unwrap();
+        let data = zip.finish().unwrap();
+        
+        // local header with zip64 extra field, 8 byte sizes in the data descriptor
+        assert_eq!(u16::from_le_bytes([data[4], data[5]]), VERSION_ZIP64);
+        assert_eq!(u32::from_le_bytes(data[22..26].try_into().unwrap()), 0xFFFF_FFFF);
+        assert_eq!(u16::from_le_bytes([data[30 + 3], data[30 + 4]]), ZIP64_EXTRA_FIELD_ID);
+        
+        // end of central directory is preceded by the zip64 locator and record
+        let eocd = data.len() - END_OF_CENTRAL_DIRECTORY_SIZE;
+        let locator = eocd - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE;
+        assert_eq!(u32::from_le_bytes(data[locator..locator + 4].try_into().unwrap()), ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE);
+        let record = u64::from_le_bytes(data[locator + 8..locator + 16].try_into().unwrap()) as usize;
+        assert_eq!(record, locator - ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE);
+        assert_eq!(u32::from_le_bytes(data[record..record + 4].try_into().unwrap()), ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        assert_eq!(u64::from_le_bytes(data[record + 24..record + 32].try_into().unwrap()), 1);
+        
+        // compressed size in the descriptor matches the data between header and descriptor
+        let data_start = 30 + 3 + 20;
+        let cd_offset = u64::from_le_bytes(data[record + 48..record + 56].try_into().unwrap()) as usize;
+        let descriptor = cd_offset - 24;
+        assert_eq!(u32::from_le_bytes(data[descriptor..descriptor + 4].try_into().unwrap()), DATA_DESCRIPTOR_SIGNATURE);
+        assert_eq!(u64::from_le_bytes(data[descriptor + 8..descriptor + 16].try_into().unwrap()) as usize, descriptor - data_start);
+        assert_eq!(u64::from_le_bytes(data[descriptor + 16..descriptor + 24].try_into().unwrap()), 3000);
+        let compressed = &data[data_start..descriptor];
+        assert_eq!(crate::inflate::decompress_to_vec(compressed).unwrap(), test_data(3000));
+    }
+    
+    #[test]
+    fn zip64_header_past_4gb_has_zip64_descriptor(){
+        let mut zip = ZipWriter::new(Vec::new());
+        // pretend 4GB were written before this entry
+        zip.offset = 0x1_0000_0000;
+        zip.start_file("far", &ZipFileOptions::default()).unwrap();
+        zip.write_data(b"abc").unwrap();
+        zip.end_stream().unwrap();
+        let data = zip.output.clone();
+        assert_eq!(u16::from_le_bytes([data[30 + 3], data[30 + 4]]), ZIP64_EXTRA_FIELD_ID);
+        // the data descriptor is the last 24 bytes, with 8 byte sizes
+        let descriptor = data.len() - 24;
+        assert_eq!(u32::from_le_bytes(data[descriptor..descriptor + 4].try_into().unwrap()), DATA_DESCRIPTOR_SIGNATURE);
+        assert_eq!(u64::from_le_bytes(data[descriptor + 16..descriptor + 24].try_into().unwrap()), 3);
+    }
+    
+    #[test]
+    fn too_large_data_is_rejected_before_writing(){
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.start_file("a", &ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()}).unwrap();
+        zip.write_data(b"abc").unwrap();
+        // pretend the file is almost 4GB already
+        zip.stream.as_mut().unwrap().entry.uncompressed_size = 0xFFFF_FFF0;
+        let len = zip.output.len();
+        assert!(matches!(zip.write_data(&[0; 16]), Err(ZipError::FileTooLarge)));
+        assert_eq!(zip.output.len(), len);
+        assert_eq!(zip.stream.as_ref().unwrap().entry.uncompressed_size, 0xFFFF_FFF0);
+        
+        // deflate can grow data, so compressed files are rejected before they reach the limit
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.start_file("b", &ZipFileOptions::default()).unwrap();
+        zip.write_data(b"abc").unwrap();
+        zip.stream.as_mut().unwrap().entry.uncompressed_size = 0xF000_0000;
+        let len = zip.output.len();
+        assert!(matches!(zip.write_data(&[0; 16]), Err(ZipError::FileTooLarge)));
+        assert_eq!(zip.output.len(), len);
+        zip.stream.as_mut().unwrap().entry.uncompressed_size = 3;
+        let data = zip.finish().unwrap();
+        let dir = zip_read_central_directory(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(dir.file_headers.len(), 1);
+        assert_eq!(dir.file_headers[0].extract(&mut Cursor::new(&data)).unwrap(), b"abc");
+    }
+    
+    #[test]
+    fn too_long_file_name_is_rejected_before_writing(){
+        let mut zip = ZipWriter::new(Vec::new());
+        let name = "a".repeat(0x10000);
+        assert_eq!(zip.add_file(&name, b"abc", &ZipFileOptions::default()), Err(ZipError::FileNameTooLong));
+        assert_eq!(zip.start_file(&name, &ZipFileOptions::default()), Err(ZipError::FileNameTooLong));
+        assert!(zip.output.is_empty());
+    }
+    
+    #[test]
+    fn many_entries_use_zip64_end_of_central_directory(){
+        let mut zip = ZipWriter::new(Vec::new());
+        let options = ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()};
+        for i in 0..70000{
+            zip.add_file(&format!("{}", i), &[], &options).unwrap();
+        }
+        let data = zip.finish().unwrap();
+        let eocd = data.len() - END_OF_CENTRAL_DIRECTORY_SIZE;
+        assert_eq!(u16::from_le_bytes([data[eocd + 10], data[eocd + 11]]), 0xFFFF);
+        let record = eocd - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE - ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE;
+        assert_eq!(u32::from_le_bytes(data[record..record + 4].try_into().unwrap()), ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        assert_eq!(u64::from_le_bytes(data[record + 32..record + 40].try_into().unwrap()), 70000);
+    }
+    
+    #[test]
+    fn read_zip64(){
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.start_file("big", &ZipFileOptions{large_file: true, ..Default::default()}).unwrap();
+        zip.write_data(&test_data(3000)).unwrap();
+        zip.add_file("small", b"small", &ZipFileOptions::default()).unwrap();
+        let data = zip.finish().unwrap();
+        let directory = zip_read_central_directory(&mut Cursor::new(&data)).unwrap();
+        assert!(directory.eocd64.is_some());
+        assert_eq!(directory.file_headers[0].uncompressed_size, 3000);
+        assert_eq!(read_all(&data), vec![("big".to_string(), test_data(3000)), ("small".to_string(), b"small".to_vec())]);
+        
+        let mut zip = ZipWriter::new(Vec::new());
+        let stored = ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()};
+        for i in 0..70000{
+            zip.add_file(&format!("{}", i), &[], &stored).unwrap();
+        }
+        let data = zip.finish().unwrap();
+        let directory = zip_read_central_directory(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(directory.file_headers.len(), 70000);
+        assert_eq!(directory.file_headers[69999].file_name, "69999");
+    }
+    
+    #[test]
+    fn streaming_reader(){
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("deflated", &test_data(300000), &ZipFileOptions::default()).unwrap();
+        zip.add_file("stored", &test_data(300000), &ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()}).unwrap();
+        let data = zip.finish().unwrap();
+        let mut cursor = Cursor::new(&data);
+        let directory = zip_read_central_directory(&mut cursor).unwrap();
+        for header in &directory.file_headers{
+            let mut reader = header.reader(&mut cursor).unwrap();
+            let mut output = Vec::new();
+            let mut buf = [0u8; 1000];
+            loop{
+                let len = reader.read(&mut buf).unwrap();
+                if len == 0{
+                    break
+                }
+                output.extend_from_slice(&buf[0..len]);
+            }
+            assert_eq!(output, test_data(300000));
+        }
+    }
+    
+    #[test]
+    fn archive_comment(){
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("a", b"abc", &ZipFileOptions::default()).unwrap();
+        let mut data = zip.finish().unwrap();
+        let comment = b"a comment that contains PK\x05\x06 text";
+        let len = data.len();
+        data[len - 2..].copy_from_slice(&(comment.len() as u16).to_le_bytes());
+        data.extend_from_slice(comment);
+        assert_eq!(read_all(&data), vec![("a".to_string(), b"abc".to_vec())]);
+    }
+    
+    #[test]
+    fn corrupt_data_is_detected(){
+        let stored = ZipFileOptions{compression_method: COMPRESS_METHOD_UNCOMPRESSED, ..Default::default()};
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("stored", &test_data(100), &stored).unwrap();
+        zip.add_file("deflated", &test_data(10000), &ZipFileOptions::default()).unwrap();
+        let data = zip.finish().unwrap();
+        let directory = zip_read_central_directory(&mut Cursor::new(&data)).unwrap();
+        
+        let mut corrupt = data.clone();
+        corrupt[LOCAL_FILE_HEADER_SIZE + 6 + 10] ^= 0x40;
+        assert_eq!(directory.file_headers[0].extract(&mut Cursor::new(&corrupt)), Err(ZipError::CrcMismatch));
+        
+        let mut header = directory.file_headers[1].clone();
+        header.crc32 ^= 1;
+        assert_eq!(header.extract(&mut Cursor::new(&data)), Err(ZipError::CrcMismatch));
+        
+        // compressed data cut short
+        let mut header = directory.file_headers[1].clone();
+        header.compressed_size -= 10;
+        assert!(header.extract(&mut Cursor::new(&data)).is_err());
+        
+        // stored data has the same size compressed and uncompressed
+        let mut header = directory.file_headers[0].clone();
+        header.uncompressed_size += 10;
+        assert_eq!(header.extract(&mut Cursor::new(&data)), Err(ZipError::SizeMismatch));
+    }
+}
@@ -0,0 +1,132 @@
+//! zlib (RFC 1950) streams with `Write` compression and `Read` decompression.
+
+use std::io::{self, Read, Write};
+
+use crate::stream_io::{DeflateWriter, InflateReader};
+use crate::DataFormat;
+
+/// Compresses everything written to it as a zlib stream into `W`.
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use makepad_miniz::zlib::{ZlibDecoder, ZlibEncoder};
+///
+/// let mut encoder = ZlibEncoder::new(Vec::new(), 6);
+/// encoder.write_all(b"hello zlib").unwrap();
+/// let compressed = encoder.finish().unwrap();
+///
+/// let mut decompressed = Vec::new();
+/// ZlibDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+/// assert_eq!(decompressed, b"hello zlib");
+/// ```
+pub struct ZlibEncoder<W: Write> {
+    writer: DeflateWriter<W>,
+}
+
+impl<W: Write> ZlibEncoder<W> {
+    /// `level` is the deflate level 0-10.
+    pub fn new(output: W, level: u8) -> Self {
+        Self {writer: DeflateWriter::new(output, level, true)}
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+
+    /// Writes the rest of the stream including the adler32 checksum and returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.finish_stream()?;
+        Ok(self.writer.take_output())
+    }
+}
+
+impl<W: Write> Write for ZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write_data(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush_data()
+    }
+}
+
+impl<W: Write> Drop for ZlibEncoder<W> {
+    fn drop(&mut self) {
+        if !self.writer.is_finished() {
+            let _ = self.writer.finish_stream();
+        }
+    }
+}
+
+/// Decompresses a zlib stream read from `R`, a wrong adler32 checksum is an `InvalidData` error.
+pub struct ZlibDecoder<R: Read> {
+    reader: InflateReader<R>,
+}
+
+impl<R: Read> ZlibDecoder<R> {
+    pub fn new(input: R) -> Self {
+        Self {reader: InflateReader::new(input, DataFormat::Zlib)}
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+}
+
+impl<R: Read> Read for ZlibDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read_inflated(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| ((i * 13) ^ (i >> 7)) as u8).collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = test_data(200000);
+        for level in [0, 1, 6, 10] {
+            let mut encoder = ZlibEncoder::new(Vec::new(), level);
+            for chunk in data.chunks(1234) {
+                encoder.write_all(chunk).unwrap();
+            }
+            let compressed = encoder.finish().unwrap();
+            assert_eq!(crate::decompress_to_vec_zlib(&compressed).unwrap(), data);
+
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn flush_makes_data_available() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), 6);
+        encoder.write_all(b"first part").unwrap();
+        encoder.flush().unwrap();
+        let partial = encoder.get_ref().clone();
+        let mut decoder = ZlibDecoder::new(&partial[..]);
+        let mut buf = [0u8; 10];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"first part");
+    }
+
+    #[test]
+    fn bad_checksum_and_truncation() {
+        let mut compressed = crate::compress_to_vec_zlib(&test_data(1000), 6);
+        let mut output = Vec::new();
+        let error = ZlibDecoder::new(&compressed[0..compressed.len() - 10]).read_to_end(&mut output).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let len = compressed.len();
+        compressed[len - 1] ^= 1;
+        let error = ZlibDecoder::new(&compressed[..]).read_to_end(&mut output).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,82 @@
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// The metrics of a piece of text.
+///
+/// Every node in a rope caches the info of the text below it, so that a position in any of these
+/// metrics can be found without looking at the text itself.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Info {
+    /// The length of the text in bytes.
+    pub byte_count: usize,
+    /// The length of the text in chars.
+    pub char_count: usize,
+    /// The length of the text in UTF-16 code units.
+    pub utf16_count: usize,
+    /// The number of line breaks in the text.
+    pub line_break_count: usize,
+}
+
+impl Info {
+    /// Returns the info of the given string.
+    pub fn of(string: &str) -> Info {
+        let mut info = Info {
+            byte_count: string.len(),
+            ..Info::default()
+        };
+        for ch in string.chars() {
+            info.char_count += 1;
+            info.utf16_count += ch.len_utf16();
+            if ch == '\n' {
+                info.line_break_count += 1;
+            }
+        }
+        info
+    }
+
+    pub(crate) fn of_char(ch: char) -> Info {
+        Info {
+            byte_count: ch.len_utf8(),
+            char_count: 1,
+            utf16_count: ch.len_utf16(),
+            line_break_count: (ch == '\n') as usize,
+        }
+    }
+}
+
+impl Add for Info {
+    type Output = Info;
+
+    fn add(self, other: Info) -> Info {
+        Info {
+            byte_count: self.byte_count + other.byte_count,
+            char_count: self.char_count + other.char_count,
+            utf16_count: self.utf16_count + other.utf16_count,
+            line_break_count: self.line_break_count + other.line_break_count,
+        }
+    }
+}
+
+impl AddAssign for Info {
+    fn add_assign(&mut self, other: Info) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Info {
+    type Output = Info;
+
+    fn sub(self, other: Info) -> Info {
+        Info {
+            byte_count: self.byte_count - other.byte_count,
+            char_count: self.char_count - other.char_count,
+            utf16_count: self.utf16_count - other.utf16_count,
+            line_break_count: self.line_break_count - other.line_break_count,
+        }
+    }
+}
+
+impl SubAssign for Info {
+    fn sub_assign(&mut self, other: Info) {
+        *self = *self - other;
+    }
+}
@@ -0,0 +1,142 @@
+use {
+    crate::rope::{Child, Node},
+    std::borrow::Cow,
+};
+
+/// An iterator over the chunks of a range of a rope.
+///
+/// Chunks are never empty, and never split a char.
+#[derive(Clone, Debug)]
+pub struct Chunks<'a> {
+    // The path from the root to the current leaf. Each entry holds the children of a node on the
+    // path, together with the index of the child that comes next on the path.
+    stack: Vec<(&'a [Child], usize)>,
+    // The byte offset into the current leaf at which the next chunk starts.
+    start: usize,
+    // The number of bytes that are left in the range.
+    remaining: usize,
+}
+
+impl<'a> Chunks<'a> {
+    pub(crate) fn new(root: &'a Child, start: usize, end: usize) -> Chunks<'a> {
+        let mut stack = Vec::new();
+        let mut children = std::slice::from_ref(root);
+        let mut offset = start;
+        loop {
+            let mut index = 0;
+            while index + 1 < children.len() && offset >= children[index].info.byte_count {
+                offset -= children[index].info.byte_count;
+                index += 1;
+            }
+            stack.push((children, index));
+            match &*children[index].node {
+                Node::Leaf(_) => break,
+                Node::Internal(next_children) => children = next_children,
+            }
+        }
+        Chunks {
+            stack,
+            start: offset,
+            remaining: end - start,
+        }
+    }
+
+    // Moves to the leaf after the current one, or clears the stack if there is none.
+    fn next_leaf(&mut self) {
+        while let Some((children, index)) = self.stack.pop() {
+            if index + 1 < children.len() {
+                self.stack.push((children, index + 1));
+                let mut child = &children[index + 1];
+                while let Node::Internal(children) = &*child.node {
+                    self.stack.push((children, 0));
+                    child = &children[0];
+                }
+                return;
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        while self.remaining > 0 {
+            let &(children, index) = self.stack.last()?;
+            let leaf = match &*children[index].node {
+                Node::Leaf(leaf) => leaf,
+                Node::Internal(_) => unreachable!(),
+            };
+            let chunk = &leaf[self.start..];
+            let chunk = &chunk[..chunk.len().min(self.remaining)];
+            self.start = 0;
+            self.remaining -= chunk.len();
+            self.next_leaf();
+            if !chunk.is_empty() {
+                return Some(chunk);
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the lines of a rope, without their line breaks.
+///
+/// A line that lies within a single chunk is borrowed from the rope.
+#[derive(Clone, Debug)]
+pub struct Lines<'a> {
+    chunks: Chunks<'a>,
+    chunk: &'a str,
+    is_done: bool,
+}
+
+impl<'a> Lines<'a> {
+    pub(crate) fn new(chunks: Chunks<'a>) -> Lines<'a> {
+        Lines {
+            chunks,
+            chunk: "",
+            is_done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        fn join<'a>(line: Option<String>, tail: &'a str) -> Cow<'a, str> {
+            match line {
+                Some(mut line) => {
+                    line.push_str(tail);
+                    Cow::Owned(line)
+                }
+                None => Cow::Borrowed(tail),
+            }
+        }
+
+        if self.is_done {
+            return None;
+        }
+        let mut line = None;
+        loop {
+            if let Some(index) = self.chunk.find('\n') {
+                let tail = &self.chunk[..index];
+                self.chunk = &self.chunk[index + 1..];
+                return Some(join(line, tail));
+            }
+            match self.chunks.next() {
+                Some(chunk) => {
+                    if !self.chunk.is_empty() {
+                        line.get_or_insert_with(String::new).push_str(self.chunk);
+                    }
+                    self.chunk = chunk;
+                }
+                None => {
+                    // The last line has no line break, and may be empty.
+                    self.is_done = true;
+                    return Some(join(line, std::mem::take(&mut self.chunk)));
+                }
+            }
+        }
+    }
+}
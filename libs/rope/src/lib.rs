@@ -0,0 +1,9 @@
+mod info;
+mod iter;
+mod rope;
+
+pub use self::{
+    info::Info,
+    iter::{Chunks, Lines},
+    rope::Rope,
+};
@@ -0,0 +1,651 @@
+use {
+    crate::{
+        info::Info,
+        iter::{Chunks, Lines},
+    },
+    std::{
+        fmt,
+        hash::{Hash, Hasher},
+        mem,
+        ops::Range,
+        sync::Arc,
+    },
+};
+
+const MAX_LEAF_LEN: usize = 1024;
+const MIN_LEAF_LEN: usize = MAX_LEAF_LEN / 2;
+const MAX_CHILD_COUNT: usize = 16;
+const MIN_CHILD_COUNT: usize = MAX_CHILD_COUNT / 2;
+
+/// A balanced tree of string chunks.
+///
+/// Every node caches the `Info` of the text below it, so that inserting or removing text, and
+/// converting between byte, char, UTF-16 and line positions, take logarithmic time in the length
+/// of the rope. Nodes are shared between clones of a rope, and only copied when they are changed,
+/// so cloning a rope is cheap.
+///
+/// All positions are measured from the start of the rope. Byte positions must lie on a char
+/// boundary.
+#[derive(Clone, Default)]
+pub struct Rope {
+    root: Child,
+}
+
+impl Rope {
+    /// Creates a new empty rope.
+    pub fn new() -> Rope {
+        Rope::default()
+    }
+
+    /// Returns the info of the text in this rope.
+    pub fn info(&self) -> Info {
+        self.root.info
+    }
+
+    /// Returns `true` if this rope is empty.
+    pub fn is_empty(&self) -> bool {
+        self.root.info.byte_count == 0
+    }
+
+    /// Returns the length of this rope in bytes.
+    pub fn len_bytes(&self) -> usize {
+        self.root.info.byte_count
+    }
+
+    /// Returns the length of this rope in chars.
+    pub fn len_chars(&self) -> usize {
+        self.root.info.char_count
+    }
+
+    /// Returns the length of this rope in UTF-16 code units.
+    pub fn len_utf16(&self) -> usize {
+        self.root.info.utf16_count
+    }
+
+    /// Returns the number of lines in this rope. This is always one more than the number of line
+    /// breaks, so an empty rope has one line.
+    pub fn len_lines(&self) -> usize {
+        self.root.info.line_break_count + 1
+    }
+
+    /// Returns the info of the text before the given byte position.
+    pub fn info_to_byte(&self, byte_index: usize) -> Info {
+        assert!(byte_index <= self.len_bytes(), "byte index out of bounds");
+        self.prefix_info(Metric::Byte, byte_index)
+    }
+
+    /// Returns the info of the text before the given char position.
+    pub fn info_to_char(&self, char_index: usize) -> Info {
+        assert!(char_index <= self.len_chars(), "char index out of bounds");
+        self.prefix_info(Metric::Char, char_index)
+    }
+
+    /// Returns the info of the text before the given UTF-16 position. A position in the middle of
+    /// a surrogate pair is rounded down.
+    pub fn info_to_utf16(&self, utf16_index: usize) -> Info {
+        assert!(utf16_index <= self.len_utf16(), "UTF-16 index out of bounds");
+        self.prefix_info(Metric::Utf16, utf16_index)
+    }
+
+    /// Returns the info of the text before the start of the given line.
+    pub fn info_to_line(&self, line_index: usize) -> Info {
+        assert!(line_index < self.len_lines(), "line index out of bounds");
+        self.prefix_info(Metric::Line, line_index)
+    }
+
+    /// Converts a byte position to a char position.
+    pub fn byte_to_char(&self, byte_index: usize) -> usize {
+        self.info_to_byte(byte_index).char_count
+    }
+
+    /// Converts a byte position to the index of the line that contains it.
+    pub fn byte_to_line(&self, byte_index: usize) -> usize {
+        self.info_to_byte(byte_index).line_break_count
+    }
+
+    /// Converts a char position to a byte position.
+    pub fn char_to_byte(&self, char_index: usize) -> usize {
+        self.info_to_char(char_index).byte_count
+    }
+
+    /// Converts a char position to a UTF-16 position.
+    pub fn char_to_utf16(&self, char_index: usize) -> usize {
+        self.info_to_char(char_index).utf16_count
+    }
+
+    /// Converts a char position to the index of the line that contains it.
+    pub fn char_to_line(&self, char_index: usize) -> usize {
+        self.info_to_char(char_index).line_break_count
+    }
+
+    /// Converts a UTF-16 position to a char position.
+    pub fn utf16_to_char(&self, utf16_index: usize) -> usize {
+        self.info_to_utf16(utf16_index).char_count
+    }
+
+    /// Returns the byte position of the start of the given line.
+    pub fn line_to_byte(&self, line_index: usize) -> usize {
+        self.info_to_line(line_index).byte_count
+    }
+
+    /// Returns the char position of the start of the given line.
+    pub fn line_to_char(&self, line_index: usize) -> usize {
+        self.info_to_line(line_index).char_count
+    }
+
+    /// Returns an iterator over the chunks of this rope.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks::new(&self.root, 0, self.len_bytes())
+    }
+
+    /// Returns an iterator over the chunks of the given byte range of this rope.
+    pub fn chunks_in(&self, byte_range: Range<usize>) -> Chunks<'_> {
+        assert!(byte_range.start <= byte_range.end && byte_range.end <= self.len_bytes(), "byte range out of bounds");
+        Chunks::new(&self.root, byte_range.start, byte_range.end)
+    }
+
+    /// Returns an iterator over the lines of this rope.
+    pub fn lines(&self) -> Lines<'_> {
+        Lines::new(self.chunks())
+    }
+
+    /// Returns an iterator over the lines of this rope, starting with the given line.
+    pub fn lines_at(&self, line_index: usize) -> Lines<'_> {
+        Lines::new(self.chunks_in(self.line_to_byte(line_index)..self.len_bytes()))
+    }
+
+    /// Returns a copy of the given byte range of this rope.
+    pub fn slice(&self, byte_range: Range<usize>) -> Rope {
+        let mut string = String::with_capacity(byte_range.len());
+        for chunk in self.chunks_in(byte_range) {
+            string.push_str(chunk);
+        }
+        Rope::from(string)
+    }
+
+    /// Inserts the given string at the given byte position.
+    pub fn insert(&mut self, byte_index: usize, string: &str) {
+        assert!(byte_index <= self.len_bytes(), "byte index out of bounds");
+        if string.is_empty() {
+            return;
+        }
+        let root = mem::take(&mut self.root);
+        self.root = from_children(insert(root, byte_index, string));
+    }
+
+    /// Removes the given byte range.
+    pub fn remove(&mut self, byte_range: Range<usize>) {
+        assert!(byte_range.start <= byte_range.end && byte_range.end <= self.len_bytes(), "byte range out of bounds");
+        if byte_range.is_empty() {
+            return;
+        }
+        let mut root = remove(mem::take(&mut self.root), byte_range);
+        loop {
+            root = match &*root.node {
+                Node::Internal(children) if children.is_empty() => Child::default(),
+                Node::Internal(children) if children.len() == 1 => children[0].clone(),
+                _ => break,
+            };
+        }
+        self.root = root;
+    }
+
+    /// Appends the given rope to this one.
+    pub fn append(&mut self, other: &Rope) {
+        for chunk in other.chunks() {
+            self.insert(self.len_bytes(), chunk);
+        }
+    }
+
+    /// Splits this rope in two at the given byte position, and returns the part after it.
+    pub fn split_off(&mut self, byte_index: usize) -> Rope {
+        let len = self.len_bytes();
+        let other = self.slice(byte_index..len);
+        self.remove(byte_index..len);
+        other
+    }
+
+    fn prefix_info(&self, metric: Metric, target: usize) -> Info {
+        if target == 0 {
+            return Info::default();
+        }
+        let mut info = Info::default();
+        let mut node = &*self.root.node;
+        loop {
+            match node {
+                Node::Internal(children) => {
+                    // Find the leftmost child that contains the target.
+                    let mut index = 0;
+                    while index + 1 < children.len() && metric.measure(info + children[index].info) < target {
+                        info += children[index].info;
+                        index += 1;
+                    }
+                    node = &children[index].node;
+                }
+                Node::Leaf(leaf) => {
+                    for ch in leaf.chars() {
+                        let next_info = info + Info::of_char(ch);
+                        match metric {
+                            Metric::Line => {
+                                info = next_info;
+                                if ch == '\n' && info.line_break_count == target {
+                                    break;
+                                }
+                            }
+                            _ => {
+                                if metric.measure(next_info) > target {
+                                    break;
+                                }
+                                info = next_info;
+                                if metric.measure(info) == target {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    return info;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Eq for Rope {}
+
+impl Hash for Rope {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hash the bytes rather than the chunks, since equal ropes may be chunked differently.
+        for chunk in self.chunks() {
+            state.write(chunk.as_bytes());
+        }
+        state.write_u8(0xff);
+    }
+}
+
+impl PartialEq for Rope {
+    fn eq(&self, other: &Rope) -> bool {
+        if self.root.info != other.root.info {
+            return false;
+        }
+        let mut chunks = self.chunks().map(str::as_bytes);
+        let mut other_chunks = other.chunks().map(str::as_bytes);
+        let mut chunk: &[u8] = &[];
+        let mut other_chunk: &[u8] = &[];
+        loop {
+            if chunk.is_empty() {
+                match chunks.next() {
+                    Some(next_chunk) => chunk = next_chunk,
+                    None => return true,
+                }
+            }
+            if other_chunk.is_empty() {
+                match other_chunks.next() {
+                    Some(next_chunk) => other_chunk = next_chunk,
+                    None => return true,
+                }
+            }
+            let len = chunk.len().min(other_chunk.len());
+            if chunk[..len] != other_chunk[..len] {
+                return false;
+            }
+            chunk = &chunk[len..];
+            other_chunk = &other_chunk[len..];
+        }
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(string: &str) -> Rope {
+        Rope {
+            root: from_children(split_leaf(string).into_iter().map(Child::leaf).collect()),
+        }
+    }
+}
+
+impl From<String> for Rope {
+    fn from(string: String) -> Rope {
+        if string.len() <= MAX_LEAF_LEN {
+            return Rope {
+                root: Child::leaf(string),
+            };
+        }
+        Rope::from(string.as_str())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Child {
+    pub(crate) info: Info,
+    pub(crate) node: Arc<Node>,
+}
+
+impl Child {
+    fn leaf(string: String) -> Child {
+        Child {
+            info: Info::of(&string),
+            node: Arc::new(Node::Leaf(string)),
+        }
+    }
+
+    fn internal(children: Vec<Child>) -> Child {
+        Child {
+            info: children.iter().fold(Info::default(), |info, child| info + child.info),
+            node: Arc::new(Node::Internal(children)),
+        }
+    }
+
+    fn is_underfull(&self) -> bool {
+        match &*self.node {
+            Node::Leaf(leaf) => leaf.len() < MIN_LEAF_LEN,
+            Node::Internal(children) => children.len() < MIN_CHILD_COUNT,
+        }
+    }
+
+    // Takes the node out of this child, copying it if it is shared with another rope.
+    fn into_node(self) -> Node {
+        Arc::try_unwrap(self.node).unwrap_or_else(|node| (*node).clone())
+    }
+}
+
+impl Default for Child {
+    fn default() -> Child {
+        Child::leaf(String::new())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Node {
+    Leaf(String),
+    Internal(Vec<Child>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Metric {
+    Byte,
+    Char,
+    Utf16,
+    Line,
+}
+
+impl Metric {
+    fn measure(self, info: Info) -> usize {
+        match self {
+            Metric::Byte => info.byte_count,
+            Metric::Char => info.char_count,
+            Metric::Utf16 => info.utf16_count,
+            Metric::Line => info.line_break_count,
+        }
+    }
+}
+
+// Splits the given string into leaves of roughly equal length, none of which is too long.
+fn split_leaf(string: &str) -> Vec<String> {
+    if string.len() <= MAX_LEAF_LEN {
+        return vec![string.to_string()];
+    }
+    let leaf_count = string.len().div_ceil(MAX_LEAF_LEN);
+    let leaf_len = string.len() / leaf_count;
+    let mut leaves = Vec::with_capacity(leaf_count);
+    let mut start = 0;
+    while string.len() - start > MAX_LEAF_LEN {
+        let mut end = start + leaf_len;
+        while !string.is_char_boundary(end) {
+            end -= 1;
+        }
+        leaves.push(string[start..end].to_string());
+        start = end;
+    }
+    leaves.push(string[start..].to_string());
+    leaves
+}
+
+// Groups the given children, which all have the same height, into as few nodes of roughly equal
+// size as possible.
+fn group(children: Vec<Child>) -> Vec<Child> {
+    if children.len() <= MAX_CHILD_COUNT {
+        return vec![Child::internal(children)];
+    }
+    let group_count = children.len().div_ceil(MAX_CHILD_COUNT);
+    let mut remaining = children.len();
+    let mut children = children.into_iter();
+    let mut groups = Vec::with_capacity(group_count);
+    for index in 0..group_count {
+        let group_len = remaining / (group_count - index);
+        groups.push(Child::internal(children.by_ref().take(group_len).collect()));
+        remaining -= group_len;
+    }
+    groups
+}
+
+// Builds a tree out of the given children, which all have the same height.
+fn from_children(mut children: Vec<Child>) -> Child {
+    while children.len() > 1 {
+        children = group(children);
+    }
+    children.pop().unwrap()
+}
+
+// Inserts the given string into the given child, and returns the children that replace it, all
+// of which have the same height as the original.
+fn insert(child: Child, byte_index: usize, string: &str) -> Vec<Child> {
+    match child.into_node() {
+        Node::Leaf(mut leaf) => {
+            if leaf.len() + string.len() <= MAX_LEAF_LEN {
+                leaf.insert_str(byte_index, string);
+                return vec![Child::leaf(leaf)];
+            }
+            let mut joined = String::with_capacity(leaf.len() + string.len());
+            joined.push_str(&leaf[..byte_index]);
+            joined.push_str(string);
+            joined.push_str(&leaf[byte_index..]);
+            split_leaf(&joined).into_iter().map(Child::leaf).collect()
+        }
+        Node::Internal(mut children) => {
+            let mut index = 0;
+            let mut byte_index = byte_index;
+            while index + 1 < children.len() && byte_index > children[index].info.byte_count {
+                byte_index -= children[index].info.byte_count;
+                index += 1;
+            }
+            let child = children.remove(index);
+            children.splice(index..index, insert(child, byte_index, string));
+            group(children)
+        }
+    }
+}
+
+// Removes the given byte range from the given child. The result may be empty or underfull.
+fn remove(child: Child, byte_range: Range<usize>) -> Child {
+    match child.into_node() {
+        Node::Leaf(mut leaf) => {
+            leaf.replace_range(byte_range, "");
+            Child::leaf(leaf)
+        }
+        Node::Internal(children) => {
+            let mut new_children = Vec::with_capacity(children.len());
+            let mut offset = 0;
+            for child in children {
+                let len = child.info.byte_count;
+                let start = byte_range.start.clamp(offset, offset + len) - offset;
+                let end = byte_range.end.clamp(offset, offset + len) - offset;
+                offset += len;
+                if start == end {
+                    new_children.push(child);
+                } else if start > 0 || end < len {
+                    new_children.push(remove(child, start..end));
+                }
+            }
+            Child::internal(rebalance(new_children))
+        }
+    }
+}
+
+// Removes empty children, and merges each underfull child with its left neighbour.
+fn rebalance(children: Vec<Child>) -> Vec<Child> {
+    let mut new_children: Vec<Child> = Vec::with_capacity(children.len());
+    for child in children {
+        if child.info.byte_count == 0 {
+            continue;
+        }
+        match new_children.pop() {
+            Some(last) if last.is_underfull() || child.is_underfull() => new_children.extend(merge(last, child)),
+            Some(last) => new_children.extend([last, child]),
+            None => new_children.push(child),
+        }
+    }
+    new_children
+}
+
+// Merges two children of the same height, and returns the children that replace them.
+fn merge(child: Child, other_child: Child) -> Vec<Child> {
+    match (child.into_node(), other_child.into_node()) {
+        (Node::Leaf(mut leaf), Node::Leaf(other_leaf)) => {
+            if leaf.len() + other_leaf.len() <= MAX_LEAF_LEN {
+                leaf.push_str(&other_leaf);
+                return vec![Child::leaf(leaf)];
+            }
+            leaf.push_str(&other_leaf);
+            split_leaf(&leaf).into_iter().map(Child::leaf).collect()
+        }
+        (Node::Internal(mut children), Node::Internal(other_children)) => {
+            children.extend(other_children);
+            group(children)
+        }
+        _ => unreachable!("children have different heights"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, makepad_test_rng::Rng};
+
+    // Chars of every UTF-8 and UTF-16 length, and line breaks.
+    const CHARS: [char; 5] = ['a', 'b', '\n', 'é', '😀'];
+
+    fn char_boundary(rng: &mut Rng, string: &str) -> usize {
+        let mut index = rng.below(string.len() + 1);
+        while !string.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    }
+
+    // Checks that the tree is balanced, and that the cached infos are correct. Returns the
+    // height of the given child.
+    fn check(child: &Child, is_root: bool) -> usize {
+        match &*child.node {
+            Node::Leaf(leaf) => {
+                assert!(leaf.len() <= MAX_LEAF_LEN);
+                assert!(is_root || !leaf.is_empty());
+                assert_eq!(child.info, Info::of(leaf));
+                0
+            }
+            Node::Internal(children) => {
+                assert!(children.len() <= MAX_CHILD_COUNT);
+                assert!(children.len() >= if is_root {2} else {1});
+                let heights: Vec<_> = children.iter().map( | child | check(child, false)).collect();
+                assert!(heights.iter().all( | height | *height == heights[0]));
+                let info = children.iter().fold(Info::default(), |info, child| info + child.info);
+                assert_eq!(child.info, info);
+                heights[0] + 1
+            }
+        }
+    }
+
+    #[test]
+    fn edits_match_string() {
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        let mut string = rng.string(10000, &CHARS);
+        let mut rope = Rope::from(string.as_str());
+        for _ in 0..1000 {
+            let max_len = if rng.below(10) == 0 {5000} else {10};
+            if rng.below(2) == 0 {
+                let index = char_boundary(&mut rng, &string);
+                let insert = rng.string(max_len, &CHARS);
+                string.insert_str(index, &insert);
+                rope.insert(index, &insert);
+            } else {
+                let start = char_boundary(&mut rng, &string);
+                let mut end = (start + rng.below(max_len + 1)).min(string.len());
+                while !string.is_char_boundary(end) {
+                    end -= 1;
+                }
+                string.replace_range(start..end, "");
+                rope.remove(start..end);
+            }
+            check(&rope.root, true);
+            assert_eq!(rope.info(), Info::of(&string));
+        }
+        assert_eq!(rope.to_string(), string);
+        assert_eq!(rope, Rope::from(string.as_str()));
+    }
+
+    #[test]
+    fn conversions() {
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+        let string = rng.string(10000, &CHARS);
+        let rope = Rope::from(string.as_str());
+        let mut prefix_info = Info::default();
+        for (char_index, (byte_index, ch)) in string.char_indices().enumerate() {
+            let utf16_index = prefix_info.utf16_count;
+            assert_eq!(rope.byte_to_char(byte_index), char_index);
+            assert_eq!(rope.char_to_byte(char_index), byte_index);
+            assert_eq!(rope.char_to_utf16(char_index), utf16_index);
+            assert_eq!(rope.utf16_to_char(utf16_index), char_index);
+            if ch.len_utf16() == 2 {
+                assert_eq!(rope.utf16_to_char(utf16_index + 1), char_index);
+            }
+            assert_eq!(rope.byte_to_line(byte_index), prefix_info.line_break_count);
+            prefix_info += Info::of_char(ch);
+        }
+        let mut line_start = 0;
+        for (line_index, line) in string.split('\n').enumerate() {
+            assert_eq!(rope.line_to_byte(line_index), line_start);
+            assert_eq!(rope.lines_at(line_index).next().unwrap(), line);
+            line_start += line.len() + 1;
+        }
+        assert_eq!(rope.len_lines(), string.split('\n').count());
+    }
+
+    #[test]
+    fn lines() {
+        let rope = Rope::from("abc\n\ndef\n");
+        assert_eq!(rope.lines().collect::<Vec<_>>(), ["abc", "", "def", ""]);
+        assert_eq!(rope.lines_at(2).collect::<Vec<_>>(), ["def", ""]);
+        assert_eq!(Rope::new().lines().collect::<Vec<_>>(), [""]);
+
+        let string = "x".repeat(5000) + "\n" + &"y".repeat(5000);
+        let rope = Rope::from(string.as_str());
+        assert!(rope.chunks().count() > 1);
+        assert_eq!(rope.lines().collect::<Vec<_>>(), string.split('\n').collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn clones_are_independent() {
+        let mut rope = Rope::from("a".repeat(10000));
+        let clone = rope.clone();
+        rope.insert(5000, "b");
+        rope.remove(0..10);
+        assert_eq!(clone.to_string(), "a".repeat(10000));
+        assert_eq!(rope.len_bytes(), 9991);
+        let tail = rope.split_off(4990);
+        assert_eq!(tail.to_string(), "b".to_string() + &"a".repeat(5000));
+        rope.append(&tail);
+        assert_eq!(rope.len_bytes(), 9991);
+    }
+}
@@ -0,0 +1,39 @@
+//! A xorshift random number generator for tests and benchmarks.
+//!
+//! Tests generate their inputs from a fixed seed, so that every failure is reproducible. This is
+//! not meant for anything else: the numbers are far from uniform, and easy to predict.
+
+/// A xorshift random number generator.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// Creates a generator with the given seed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the seed is zero, since the generator would only ever return zero.
+    pub fn new(seed: u64) -> Rng {
+        assert!(seed != 0, "seed must not be zero");
+        Rng(seed)
+    }
+
+    /// Returns the next number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns the next number below the given bound.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// Returns a string of at most the given number of chars, picked from the given chars.
+    pub fn string(&mut self, max_len: usize, chars: &[char]) -> String {
+        let len = self.below(max_len + 1);
+        (0..len).map( | _ | chars[self.below(chars.len())]).collect()
+    }
+}
@@ -1,2 +1,4 @@
 mod toml;
-pub use crate::toml::*;
\ No newline at end of file
+pub use crate::toml::*;
+mod toml_doc;
+pub use crate::toml_doc::*;
@@ -1,396 +1,916 @@
-use std::collections::{HashMap};
-use std::str::Chars;
+use std::fmt;
 
-#[derive(Default)]
-pub struct TomlParser {
-    pub cur: char,
-    pub pos: usize,
-}
-
-#[derive(PartialEq, Debug, Clone)]
+/// A byte range in the parsed source.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
 pub struct TomlSpan{
     pub start:usize,
     pub len:usize
 }
 
-#[derive(PartialEq, Debug)]
-pub struct TomlTokWithSpan{
-    pub span: TomlSpan,
-    pub tok: TomlTok
+/// How a table came to exist, TOML only allows some of these to be extended later.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TomlTableKind{
+    /// The root table, a `[header]` or an element of an `[[array]]`.
+    Header,
+    /// Created as the parent of a header like `[a.b]`, `[a]` may still define it.
+    Implicit,
+    /// Created by a dotted key like `a.b = 1`.
+    Dotted,
+    /// An `{ inline = "table" }`, closed for good.
+    Inline,
+}
+
+/// A table with its keys in source order.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TomlTable{
+    pub kind: TomlTableKind,
+    pub entries: Vec<(String, Toml)>,
 }
 
-#[derive(PartialEq, Debug)]
-pub enum TomlTok {
-    Ident(String),
-    Str(String),
-    U64(u64),
-    I64(i64),
-    F64(f64),
-    Bool(bool),
-    Nan(bool),
-    Inf(bool),
-    Date(String),
-    Equals,
-    BlockOpen,
-    BlockClose,
-    ObjectOpen,
-    ObjectClose,
-    Comma,
-    Bof,
-    Eof
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct TomlDate{
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
 }
 
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct TomlTime{
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TomlOffset{
+    Z,
+    Minutes(i16),
+}
+
+/// Any of the four TOML date-time flavours, offset date-times have all three parts.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct TomlDateTime{
+    pub date: Option<TomlDate>,
+    pub time: Option<TomlTime>,
+    pub offset: Option<TomlOffset>,
+}
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Toml{
     Str(String, TomlSpan),
     Bool(bool, TomlSpan),
-    Num(f64, TomlSpan),
-    Date(String, TomlSpan),
-    Array(Vec<Toml>),
+    Int(i64, TomlSpan),
+    Float(f64, TomlSpan),
+    DateTime(TomlDateTime, TomlSpan),
+    Array(Vec<Toml>, TomlSpan),
+    Table(TomlTable, TomlSpan),
+}
+
+pub struct TomlErr{
+    pub msg:String,
+    pub span:TomlSpan,
+}
+
+impl std::fmt::Debug for TomlErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Toml error: {}, start:{} len:{}", self.msg, self.span.start, self.span.len)
+    }
 }
 
 impl Toml{
+    pub fn span(&self)->TomlSpan{
+        match self{
+            Self::Str(_,s) | Self::Bool(_,s) | Self::Int(_,s) | Self::Float(_,s) |
+            Self::DateTime(_,s) | Self::Array(_,s) | Self::Table(_,s)=>*s
+        }
+    }
+
     pub fn into_str(self)->Option<String>{
         match self{
             Self::Str(v,_)=>Some(v),
             _=>None
         }
     }
+
+    pub fn as_str(&self)->Option<&str>{
+        match self{
+            Self::Str(v,_)=>Some(v),
+            _=>None
+        }
+    }
+
+    pub fn as_bool(&self)->Option<bool>{
+        match self{
+            Self::Bool(v,_)=>Some(*v),
+            _=>None
+        }
+    }
+
+    pub fn as_int(&self)->Option<i64>{
+        match self{
+            Self::Int(v,_)=>Some(*v),
+            _=>None
+        }
+    }
+
+    /// Floats, and integers converted to a float.
+    pub fn as_float(&self)->Option<f64>{
+        match self{
+            Self::Float(v,_)=>Some(*v),
+            Self::Int(v,_)=>Some(*v as f64),
+            _=>None
+        }
+    }
+
+    pub fn as_datetime(&self)->Option<&TomlDateTime>{
+        match self{
+            Self::DateTime(v,_)=>Some(v),
+            _=>None
+        }
+    }
+
+    pub fn as_array(&self)->Option<&[Toml]>{
+        match self{
+            Self::Array(v,_)=>Some(v),
+            _=>None
+        }
+    }
+
+    pub fn as_table(&self)->Option<&TomlTable>{
+        match self{
+            Self::Table(v,_)=>Some(v),
+            _=>None
+        }
+    }
+
+    /// The value of `key` if this is a table that has it.
+    pub fn get(&self, key:&str)->Option<&Toml>{
+        self.as_table()?.get(key)
+    }
 }
 
-pub struct TomlErr{
-    pub msg:String,
-    pub span:TomlSpan,
+impl TomlTable{
+    pub fn new(kind:TomlTableKind)->Self{
+        Self{kind, entries:Vec::new()}
+    }
+
+    pub fn get(&self, key:&str)->Option<&Toml>{
+        self.entries.iter().find(|(k,_)| k == key).map(|(_,v)| v)
+    }
+
+    pub fn get_mut(&mut self, key:&str)->Option<&mut Toml>{
+        self.entries.iter_mut().find(|(k,_)| k == key).map(|(_,v)| v)
+    }
+
+    /// Looks up a path like `package.metadata.docs`, the keys themselves can't contain dots.
+    pub fn lookup(&self, path:&str)->Option<&Toml>{
+        let mut parts = path.split('.');
+        let mut value = self.get(parts.next()?)?;
+        for part in parts{
+            value = value.get(part)?;
+        }
+        Some(value)
+    }
+
+    /// Walks a path of keys, elements of arrays are addressed by their index.
+    pub fn get_path(&self, path:&[&str])->Option<&Toml>{
+        let (first, rest) = path.split_first()?;
+        let mut value = self.get(first)?;
+        for part in rest{
+            value = match value{
+                Toml::Table(t,_)=>t.get(part)?,
+                Toml::Array(a,_)=>a.get(part.parse::<usize>().ok()?)?,
+                _=>return None
+            };
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self)->impl Iterator<Item=(&str, &Toml)>{
+        self.entries.iter().map(|(k,v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self)->usize{
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self)->bool{
+        self.entries.is_empty()
+    }
+
+    // walks to the table that key/values go into after a header
+    fn section_mut(&mut self, path:&[String])->&mut TomlTable{
+        let mut table = self;
+        let mut path = path.iter();
+        while let Some(key) = path.next(){
+            table = match table.get_mut(key){
+                Some(Toml::Table(t,_))=>t,
+                Some(Toml::Array(a,_))=>match a.get_mut(path.next().unwrap().parse::<usize>().unwrap()){
+                    Some(Toml::Table(t,_))=>t,
+                    _=>unreachable!()
+                }
+                _=>unreachable!()
+            };
+        }
+        table
+    }
 }
 
-impl std::fmt::Debug for TomlErr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Toml error: {}, start:{} len:{}", self.msg, self.span.start, self.span.len)
+// arrays made by [[headers]], as opposed to static `a = [...]` arrays
+pub(crate) fn is_table_array(items:&[Toml])->bool{
+    !items.is_empty() && items.iter().all(|v| matches!(v, Toml::Table(t,_) if t.kind == TomlTableKind::Header))
+}
+
+impl fmt::Display for TomlDateTime{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(d) = &self.date{
+            write!(f, "{:04}-{:02}-{:02}", d.year, d.month, d.day)?;
+            if self.time.is_some(){
+                write!(f, "T")?;
+            }
+        }
+        if let Some(t) = &self.time{
+            write!(f, "{:02}:{:02}:{:02}", t.hour, t.minute, t.second)?;
+            if t.nanosecond != 0{
+                write!(f, ".{}", format!("{:09}", t.nanosecond).trim_end_matches('0'))?;
+            }
+        }
+        match self.offset{
+            Some(TomlOffset::Z)=>write!(f, "Z"),
+            Some(TomlOffset::Minutes(m))=>write!(f, "{}{:02}:{:02}", if m < 0{'-'}else{'+'}, m.abs() / 60, m.abs() % 60),
+            None=>Ok(())
+        }
+    }
+}
+
+/// Parses a TOML 1.0 document into its root table.
+pub fn parse_toml(data:&str)->Result<TomlTable, TomlErr>{
+    parse_toml_items(data).map(|(root, _)| root)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum TomlItemKind{
+    Header,
+    ArrayHeader,
+    KeyValue,
+    InlineKeyValue,
+}
+
+// where a header or key/value sits in the source, used to edit documents in place
+#[derive(Debug, Clone)]
+pub(crate) struct TomlItem{
+    pub kind: TomlItemKind,
+    // the full path, elements of arrays of tables appear as their index
+    pub path: Vec<String>,
+    // how many of the path entries are spelled out by the (dotted) key
+    pub keys: usize,
+    // for headers both spans cover the brackets
+    pub key: TomlSpan,
+    pub value: TomlSpan,
+}
+
+impl TomlItem{
+    // the table this key/value or header was written in
+    pub fn parent(&self)->&[String]{
+        &self.path[..self.path.len() - self.keys]
     }
 }
 
-pub fn parse_toml(data:&str)->Result<HashMap<String, Toml>, TomlErr>{
-    let i = &mut data.chars();
-    let mut t = TomlParser::default();
-    t.next(i);
-    let mut out = HashMap::new();
-    let mut local_scope = String::new();
+pub(crate) fn parse_toml_items(data:&str)->Result<(TomlTable, Vec<TomlItem>), TomlErr>{
+    let mut t = TomlParser{src:data, pos:0, path:Vec::new(), inline:0, items:Vec::new()};
+    t.eat('\u{feff}');
+    let mut root = TomlTable::new(TomlTableKind::Header);
+    let mut section = Vec::new();
     loop{
-        let tok = t.next_tok(i)?;
-        match tok.tok{
-            TomlTok::Eof=>{ // at eof. 
-                return Ok(out);
-            },
-            TomlTok::BlockOpen=>{ // its a scope
-                // we should expect an ident or a string
-                let tok = t.next_tok(i)?;
-                let (tok, double_block) = if let TomlTok::BlockOpen = tok.tok{
-                    (t.next_tok(i)?, true)
-                }
-                else{(tok, false)};
-                
-                match tok.tok{
-                    TomlTok::Str(key)=>{ // a key
-                        local_scope = key;
-                    },
-                    TomlTok::Ident(key)=>{ // also a key
-                        local_scope = key;
-                    },
-                    _=>return Err(t.err_token(tok))
-                }
-                let tok = t.next_tok(i)?;
-                
-                if tok.tok != TomlTok::BlockClose{
-                    return Err(t.err_token(tok))
+        t.skip_ws_comment_newlines()?;
+        match t.peek(){
+            None=>return Ok((root, t.items)),
+            Some('[')=>{
+                section = t.parse_header(&mut root)?;
+            }
+            Some(_)=>{
+                t.path = section.clone();
+                t.parse_key_value(root.section_mut(&section))?;
+            }
+        }
+        t.skip_ws();
+        t.skip_comment()?;
+        if !t.eat_newline() && t.peek().is_some(){
+            return Err(t.err_here("Expected a newline"))
+        }
+    }
+}
+
+pub struct TomlParser<'a> {
+    src: &'a str,
+    pos: usize,
+    path: Vec<String>,
+    inline: usize,
+    items: Vec<TomlItem>,
+}
+
+fn is_bare_key(c:char)->bool{
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_control(c:char)->bool{
+    c < ' ' && c != '\t' || c == '\u{7f}'
+}
+
+// digits with single underscores between them
+fn strip_underscores(text:&str, is_digit:impl Fn(char)->bool)->Option<String>{
+    let mut out = String::new();
+    let mut last_digit = false;
+    for c in text.chars(){
+        if c == '_' && last_digit{
+            last_digit = false;
+        }
+        else if is_digit(c){
+            out.push(c);
+            last_digit = true;
+        }
+        else{
+            return None
+        }
+    }
+    if last_digit{Some(out)}else{None}
+}
+
+fn parse_number(text:&str, span:TomlSpan)->Option<Toml>{
+    let (neg, body) = match text.as_bytes().first(){
+        Some(b'+')=>(Some(false), &text[1..]),
+        Some(b'-')=>(Some(true), &text[1..]),
+        _=>(None, text)
+    };
+    let neg_f = if neg == Some(true){-1.0}else{1.0};
+    match body{
+        "inf"=>return Some(Toml::Float(neg_f * f64::INFINITY, span)),
+        "nan"=>return Some(Toml::Float(neg_f * f64::NAN, span)),
+        _=>()
+    }
+    let radix = match body.get(..2){
+        Some("0x")=>16,
+        Some("0o")=>8,
+        Some("0b")=>2,
+        _=>10
+    };
+    if radix != 10{
+        if neg.is_some(){
+            return None
+        }
+        let digits = strip_underscores(&body[2..], |c| c.is_digit(radix))?;
+        return i64::from_str_radix(&digits, radix).ok().map(|v| Toml::Int(v, span))
+    }
+    let (mantissa, exp) = match body.find(['e', 'E']){
+        Some(e)=>(&body[..e], Some(&body[e + 1..])),
+        None=>(body, None)
+    };
+    let (int, frac) = match mantissa.find('.'){
+        Some(d)=>(&mantissa[..d], Some(&mantissa[d + 1..])),
+        None=>(mantissa, None)
+    };
+    let int = strip_underscores(int, |c| c.is_ascii_digit())?;
+    if int.len() > 1 && int.starts_with('0'){
+        return None
+    }
+    let mut num = String::new();
+    if neg == Some(true){
+        num.push('-');
+    }
+    num.push_str(&int);
+    if frac.is_none() && exp.is_none(){
+        return num.parse().ok().map(|v| Toml::Int(v, span))
+    }
+    if let Some(frac) = frac{
+        num.push('.');
+        num.push_str(&strip_underscores(frac, |c| c.is_ascii_digit())?);
+    }
+    if let Some(exp) = exp{
+        num.push('e');
+        let exp = match exp.as_bytes().first(){
+            Some(b'+')=>&exp[1..],
+            Some(b'-')=>{num.push('-'); &exp[1..]}
+            _=>exp
+        };
+        num.push_str(&strip_underscores(exp, |c| c.is_ascii_digit())?);
+    }
+    num.parse().ok().map(|v| Toml::Float(v, span))
+}
+
+impl<'a> TomlParser<'a> {
+    fn rest(&self)->&'a str{
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self)->Option<char>{
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self)->Option<char>{
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c:char)->bool{
+        if self.peek() == Some(c){
+            self.pos += c.len_utf8();
+            return true
+        }
+        false
+    }
+
+    fn eat_str(&mut self, s:&str)->bool{
+        if self.rest().starts_with(s){
+            self.pos += s.len();
+            return true
+        }
+        false
+    }
+
+    fn eat_newline(&mut self)->bool{
+        self.eat('\n') || self.eat_str("\r\n")
+    }
+
+    fn skip_ws(&mut self){
+        while self.eat(' ') || self.eat('\t'){}
+    }
+
+    fn skip_comment(&mut self)->Result<(), TomlErr>{
+        if self.eat('#'){
+            while let Some(c) = self.peek(){
+                if c == '\n' || self.rest().starts_with("\r\n"){
+                    break
                 }
-                if double_block{
-                    let tok = t.next_tok(i)?;
-                    if tok.tok != TomlTok::BlockClose{
-                        return Err(t.err_token(tok))
-                    }
+                if is_control(c){
+                    return Err(self.err_here("Control characters are not allowed in comments"))
                 }
-            },
-            TomlTok::Str(key)=>{ // a key
-                t.parse_key_value(&local_scope, key, i, &mut out)?;
-            },
-            TomlTok::Ident(key)=>{ // also a key
-                t.parse_key_value(&local_scope, key, i, &mut out)?;
-            },
-            _=>return Err(t.err_token(tok))
+                self.bump();
+            }
         }
+        Ok(())
     }
-}
 
-impl TomlParser {
-    pub fn to_val(&mut self, tok:TomlTokWithSpan,  i: &mut Chars)->Result<Toml, TomlErr>{
-        match tok.tok{
-            TomlTok::BlockOpen=>{
-                let mut vals = Vec::new();
-                loop{
-                    let tok = self.next_tok(i)?;
-                    match tok.tok{
-                        TomlTok::BlockClose | TomlTok::Eof=>{
-                            break;
-                        }
-                        TomlTok::Comma=>{}
-                        _=>{
-                            vals.push(self.to_val(tok, i)?);
-                        }
+    fn skip_ws_comment_newlines(&mut self)->Result<(), TomlErr>{
+        loop{
+            self.skip_ws();
+            self.skip_comment()?;
+            if !self.eat_newline(){
+                return Ok(())
+            }
+        }
+    }
+
+    fn err_span(&self, msg:impl Into<String>, span:TomlSpan)->TomlErr{
+        TomlErr{msg:msg.into(), span}
+    }
+
+    fn err_here(&self, msg:&str)->TomlErr{
+        let len = self.peek().map(|c| c.len_utf8()).unwrap_or(0);
+        self.err_span(msg, TomlSpan{start:self.pos, len})
+    }
+
+    fn span_from(&self, start:usize)->TomlSpan{
+        TomlSpan{start, len:self.pos - start}
+    }
+
+    fn parse_header(&mut self, root:&mut TomlTable)->Result<Vec<String>, TomlErr>{
+        let start = self.pos;
+        self.bump();
+        let array = self.eat('[');
+        self.skip_ws();
+        let keys = self.parse_key()?;
+        if !self.eat_str(if array{"]]"}else{"]"}){
+            return Err(self.err_here(if array{"Expected `]]`"}else{"Expected `]`"}))
+        }
+        let span = self.span_from(start);
+
+        let mut path = Vec::new();
+        let mut table = root;
+        for (key, key_span) in &keys[..keys.len() - 1]{
+            if table.get(key).is_none(){
+                table.entries.push((key.clone(), Toml::Table(TomlTable::new(TomlTableKind::Implicit), span)));
+            }
+            path.push(key.clone());
+            table = match table.get_mut(key).unwrap(){
+                Toml::Table(t,_) if t.kind != TomlTableKind::Inline=>t,
+                Toml::Array(a,_) if is_table_array(a)=>{
+                    path.push((a.len() - 1).to_string());
+                    match a.last_mut(){
+                        Some(Toml::Table(t,_))=>t,
+                        _=>unreachable!()
                     }
                 }
-                Ok(Toml::Array(vals))
-            },
-            TomlTok::Str(v)=>Ok(Toml::Str(v, tok.span)),
-            TomlTok::U64(v)=>Ok(Toml::Num(v as f64, tok.span)),
-            TomlTok::I64(v)=>Ok(Toml::Num(v as f64, tok.span)),
-            TomlTok::F64(v)=>Ok(Toml::Num(v as f64, tok.span)),
-            TomlTok::Bool(v)=>Ok(Toml::Bool(v, tok.span)),
-            TomlTok::Nan(v)=>Ok(Toml::Num(if v{-std::f64::NAN}else{std::f64::NAN}, tok.span)),
-            TomlTok::Inf(v)=>Ok(Toml::Num(if v{-std::f64::INFINITY}else{std::f64::INFINITY}, tok.span)),
-            TomlTok::Date(v)=>Ok(Toml::Date(v, tok.span)),
-            _=>Err(self.err_token(tok))
-        }
-    }
-    
-    pub fn parse_key_value(&mut self, local_scope:&str, key:String, i: &mut Chars, out:&mut HashMap<String, Toml>)->Result<(), TomlErr>{
-        let tok = self.next_tok(i)?;
-        if tok.tok != TomlTok::Equals{
-            return Err(self.err_token(tok));
-        }
-        let tok = self.next_tok(i)?;
-        // if we are an ObjectOpen we do a subscope
-        if let TomlTok::ObjectOpen = tok.tok{
-            let local_scope = if local_scope.len()>0{
-                format!("{}.{}", local_scope, key)
-            }
-            else{
-                key
+                _=>return Err(self.err_span(format!("Key `{}` is already defined as a value", key), *key_span))
             };
-            loop{
-                let tok = self.next_tok(i)?;
-                match tok.tok{
-                    TomlTok::ObjectClose | TomlTok::Eof=>{
-                        break;
+        }
+        let (key, key_span) = keys.last().unwrap();
+        let header = Toml::Table(TomlTable::new(TomlTableKind::Header), span);
+        match table.get_mut(key){
+            None if array=>table.entries.push((key.clone(), Toml::Array(vec![header], span))),
+            None=>table.entries.push((key.clone(), header)),
+            Some(Toml::Array(a,_)) if array && is_table_array(a)=>a.push(header),
+            Some(Toml::Table(t,s)) if !array && t.kind == TomlTableKind::Implicit=>{
+                t.kind = TomlTableKind::Header;
+                *s = span;
+            }
+            Some(_) if array=>return Err(self.err_span(format!("Key `{}` is already defined and not an array of tables", key), *key_span)),
+            Some(_)=>return Err(self.err_span(format!("Table `{}` is already defined", key), *key_span)),
+        }
+        path.push(key.clone());
+        let mut keys = keys.len();
+        if let Some(Toml::Array(a,_)) = table.get(key){
+            path.push((a.len() - 1).to_string());
+            keys += 1;
+        }
+        self.items.push(TomlItem{
+            kind:if array{TomlItemKind::ArrayHeader}else{TomlItemKind::Header},
+            path:path.clone(),
+            keys,
+            key:span,
+            value:span,
+        });
+        Ok(path)
+    }
+
+    fn parse_key(&mut self)->Result<Vec<(String, TomlSpan)>, TomlErr>{
+        let mut keys = Vec::new();
+        loop{
+            let start = self.pos;
+            let key = match self.peek(){
+                Some('"') | Some('\'') if self.rest().starts_with("\"\"\"") || self.rest().starts_with("'''")=>{
+                    return Err(self.err_span("Multi-line strings cannot be keys", TomlSpan{start, len:3}))
+                }
+                Some('"')=>self.parse_basic_string()?,
+                Some('\'')=>self.parse_literal_string()?,
+                Some(c) if is_bare_key(c)=>{
+                    while self.peek().is_some_and(is_bare_key){
+                        self.bump();
                     }
-                    TomlTok::Comma=>{}
-                    TomlTok::Str(key)=>{ // a key
-                        self.parse_key_value(&local_scope, key, i, out)?;
-                    },
-                    TomlTok::Ident(key)=>{ // also a key
-                        self.parse_key_value(&local_scope, key, i, out)?;
-                    },
-                     _=>return Err(self.err_token(tok))
+                    self.src[start..self.pos].to_string()
                 }
+                _=>return Err(self.err_here("Expected a key"))
+            };
+            keys.push((key, self.span_from(start)));
+            self.skip_ws();
+            if !self.eat('.'){
+                return Ok(keys)
             }
+            self.skip_ws();
         }
-        else{
-            let val = self.to_val(tok, i)?;
-            let key = if local_scope.len()>0{
-                format!("{}.{}", local_scope, key)
+    }
+
+    fn parse_key_value(&mut self, table:&mut TomlTable)->Result<(), TomlErr>{
+        let keys = self.parse_key()?;
+        if !self.eat('='){
+            return Err(self.err_here("Expected `=` after a key"))
+        }
+        self.skip_ws();
+        let depth = self.path.len();
+        self.path.extend(keys.iter().map(|(key,_)| key.clone()));
+        let value_start = self.pos;
+        let value = self.parse_value()?;
+        let first = keys[0].1.start;
+        let last = keys[keys.len() - 1].1;
+        self.items.push(TomlItem{
+            kind:if self.inline > 0{TomlItemKind::InlineKeyValue}else{TomlItemKind::KeyValue},
+            path:self.path.clone(),
+            keys:keys.len(),
+            key:TomlSpan{start:first, len:last.start + last.len - first},
+            value:self.span_from(value_start),
+        });
+        self.path.truncate(depth);
+
+        let mut table = table;
+        for (key, key_span) in &keys[..keys.len() - 1]{
+            if table.get(key).is_none(){
+                table.entries.push((key.clone(), Toml::Table(TomlTable::new(TomlTableKind::Dotted), *key_span)));
             }
-            else{
-                key
+            table = match table.get_mut(key).unwrap(){
+                Toml::Table(t,_) if t.kind == TomlTableKind::Dotted=>t,
+                _=>return Err(self.err_span(format!("Key `{}` is already defined and cannot be extended with dotted keys", key), *key_span))
             };
-            out.insert(key, val);
         }
+        let (key, key_span) = keys.last().unwrap();
+        if table.get(key).is_some(){
+            return Err(self.err_span(format!("Duplicate key `{}`", key), *key_span))
+        }
+        table.entries.push((key.clone(), value));
         Ok(())
     }
-    
-    pub fn next(&mut self, i: &mut Chars) {
-        if let Some(c) = i.next() {
-            self.cur = c;
-            self.pos += 1;
+
+    fn parse_value(&mut self)->Result<Toml, TomlErr>{
+        let start = self.pos;
+        match self.peek(){
+            Some('"') | Some('\'')=>{
+                let v = self.parse_string()?;
+                Ok(Toml::Str(v, self.span_from(start)))
+            }
+            Some('[')=>self.parse_array(),
+            Some('{')=>self.parse_inline_table(),
+            Some('t') if self.eat_str("true")=>Ok(Toml::Bool(true, self.span_from(start))),
+            Some('f') if self.eat_str("false")=>Ok(Toml::Bool(false, self.span_from(start))),
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' || c == 'i' || c == 'n'=>{
+                self.parse_number_or_datetime()
+            }
+            _=>Err(self.err_here("Expected a value"))
+        }
+    }
+
+    fn parse_string(&mut self)->Result<String, TomlErr>{
+        if self.rest().starts_with("\"\"\""){
+            self.parse_multiline_string('"')
+        }
+        else if self.rest().starts_with("'''"){
+            self.parse_multiline_string('\'')
         }
-        else {
-            self.cur = '\0';
+        else if self.peek() == Some('"'){
+            self.parse_basic_string()
+        }
+        else{
+            self.parse_literal_string()
         }
     }
-    
-    pub fn err_token(&self, tok:TomlTokWithSpan) -> TomlErr {
-        TomlErr{msg:format!("Unexpected token {:?} ", tok), span:tok.span}
+
+    fn parse_basic_string(&mut self)->Result<String, TomlErr>{
+        let start = self.pos;
+        self.bump();
+        let mut out = String::new();
+        loop{
+            match self.peek(){
+                None | Some('\n')=>return Err(self.err_span("Unterminated string", self.span_from(start))),
+                Some('"')=>{
+                    self.bump();
+                    return Ok(out)
+                }
+                Some('\\')=>self.parse_escape(&mut out)?,
+                Some(c) if is_control(c)=>return Err(self.err_here("Control characters must be escaped")),
+                Some(c)=>{
+                    self.bump();
+                    out.push(c);
+                }
+            }
+        }
     }
-    
-    pub fn err_parse(&self, what:&str) -> TomlErr {
-        TomlErr{msg:format!("Cannot parse toml {} ", what), span:TomlSpan{start:self.pos, len:0}}
+
+    fn parse_escape(&mut self, out:&mut String)->Result<(), TomlErr>{
+        let start = self.pos;
+        self.bump();
+        let c = match self.bump(){
+            Some('b')=>'\u{8}',
+            Some('t')=>'\t',
+            Some('n')=>'\n',
+            Some('f')=>'\u{c}',
+            Some('r')=>'\r',
+            Some('"')=>'"',
+            Some('\\')=>'\\',
+            Some('u')=>self.parse_unicode(4, start)?,
+            Some('U')=>self.parse_unicode(8, start)?,
+            _=>return Err(self.err_span("Invalid escape sequence", self.span_from(start)))
+        };
+        out.push(c);
+        Ok(())
     }
-    
-    pub fn next_tok(&mut self, i: &mut Chars) -> Result<TomlTokWithSpan, TomlErr> {
-        
-        while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' || self.cur == '#'{
-            if self.cur == '#'{
-                while self.cur !='\n' && self.cur != '\0'{
-                    self.next(i);
+
+    fn parse_unicode(&mut self, digits:usize, start:usize)->Result<char, TomlErr>{
+        if let Some(hex) = self.rest().get(..digits){
+            if hex.chars().all(|c| c.is_ascii_hexdigit()){
+                self.pos += digits;
+                if let Some(c) = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32){
+                    return Ok(c)
                 }
-                continue
             }
-            self.next(i);
         }
+        Err(self.err_span("Invalid unicode escape", self.span_from(start)))
+    }
+
+    fn parse_literal_string(&mut self)->Result<String, TomlErr>{
         let start = self.pos;
-        match self.cur {
-            '\0'=>{
-                return Ok(TomlTokWithSpan{tok:TomlTok::Eof, span:TomlSpan{start, len:0}})
-            }
-            ',' => {
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::Comma, span:TomlSpan{start, len:1}})
-            }
-            '[' => {
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::BlockOpen, span:TomlSpan{start, len:1}})
-            }
-            ']' => {
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::BlockClose, span:TomlSpan{start, len:1}})
-            }
-            '{' => {
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::ObjectOpen, span:TomlSpan{start, len:1}})
-            }
-            '}' => {
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::ObjectClose, span:TomlSpan{start, len:1}})
-            }
-            '=' => {
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::Equals, span:TomlSpan{start, len:1}})
-            }
-            '+' | '-' | '0'..='9' => {
-                let mut num = String::new();
-                let is_neg = if self.cur == '-' {
-                    num.push(self.cur);
-                    self.next(i);
-                    true
-                }
-                else {
-                    if self.cur == '+' {
-                        self.next(i);
-                    }
-                    false
-                };
-                if self.cur == 'n' {
-                    self.next(i);
-                    if self.cur == 'a' {
-                        self.next(i);
-                        if self.cur == 'n' {
-                            self.next(i);
-                            return Ok(TomlTokWithSpan{tok:TomlTok::Nan(is_neg), span:TomlSpan{start, len:self.pos - start}})
-                        }
-                        else {
-                            return Err(self.err_parse("nan"))
-                        }
-                    }
-                    else {
-                        return Err(self.err_parse("nan"))
-                    }
+        self.bump();
+        let mut out = String::new();
+        loop{
+            match self.peek(){
+                None | Some('\n')=>return Err(self.err_span("Unterminated string", self.span_from(start))),
+                Some('\'')=>{
+                    self.bump();
+                    return Ok(out)
                 }
-                 if self.cur == 'i' {
-                    self.next(i);
-                    if self.cur == 'n' {
-                        self.next(i);
-                        if self.cur == 'f' {
-                            self.next(i);
-                            return Ok(TomlTokWithSpan{tok:TomlTok::Inf(is_neg), span:TomlSpan{start, len:self.pos - start}})
-                        }
-                        else {
-                            return Err(self.err_parse("inf"))
-                        }
-                    }
-                    else {
-                        return Err(self.err_parse("nan"))
-                    }
-                }
-                while self.cur >= '0' && self.cur <= '9' || self.cur == '_' {
-                    if self.cur != '_' {
-                        num.push(self.cur);
-                    }
-                    self.next(i);
+                Some(c) if is_control(c)=>return Err(self.err_here("Control characters are not allowed in literal strings")),
+                Some(c)=>{
+                    self.bump();
+                    out.push(c);
                 }
-                if self.cur == '.' {
-                    num.push(self.cur);
-                    self.next(i);
-                    while self.cur >= '0' && self.cur <= '9' || self.cur == '_' {
-                        if self.cur != '_' {
-                            num.push(self.cur);
-                        }
-                        self.next(i);
-                    }
-                    if let Ok(num) = num.parse() {
-                        return Ok(TomlTokWithSpan{tok:TomlTok::F64(num), span:TomlSpan{start, len:self.pos - start}})
+            }
+        }
+    }
+
+    fn parse_multiline_string(&mut self, quote:char)->Result<String, TomlErr>{
+        let start = self.pos;
+        self.pos += 3;
+        // a newline right after the opening quotes is not part of the string
+        self.eat_newline();
+        let mut out = String::new();
+        loop{
+            match self.peek(){
+                None=>return Err(self.err_span("Unterminated multi-line string", self.span_from(start))),
+                Some(c) if c == quote=>{
+                    // up to two quotes can sit right before the closing ones
+                    let count = self.rest().chars().take_while(|c| *c == quote).count();
+                    if count > 5{
+                        return Err(self.err_span("Too many quotes in a row", TomlSpan{start:self.pos, len:count}))
                     }
-                    else {
-                        return Err(self.err_parse("number"));
+                    self.pos += count;
+                    for _ in 0..if count >= 3{count - 3}else{count}{
+                        out.push(quote);
                     }
-                }
-                else if self.cur == '-' { // lets assume its a date. whatever. i don't feel like more parsing today
-                    num.push(self.cur);
-                    self.next(i);
-                    while self.cur >= '0' && self.cur <= '9' || self.cur == ':' || self.cur == '-' || self.cur == 'T' {
-                        num.push(self.cur);
-                        self.next(i);
+                    if count >= 3{
+                        return Ok(out)
                     }
-                    return Ok(TomlTokWithSpan{tok:TomlTok::Date(num), span:TomlSpan{start, len:self.pos - start}})
                 }
-                else {
-                    if is_neg {
-                        if let Ok(num) = num.parse() {
-                             return Ok(TomlTokWithSpan{tok:TomlTok::I64(num), span:TomlSpan{start, len:self.pos - start}})
+                Some('\\') if quote == '"'=>{
+                    // a backslash ending a line eats all whitespace up to the next text
+                    let rest = &self.rest()[1..];
+                    let trimmed = rest.trim_start_matches([' ', '\t']);
+                    if trimmed.starts_with('\n') || trimmed.starts_with("\r\n"){
+                        self.pos += 1 + rest.len() - trimmed.len();
+                        self.skip_ws();
+                        while self.eat_newline(){
+                            self.skip_ws();
                         }
-                        else {
-                            return Err(self.err_parse("number"));
-                        }
-                    }
-                    if let Ok(num) = num.parse() {
-                        return Ok(TomlTokWithSpan{tok:TomlTok::U64(num), span:TomlSpan{start, len:self.pos - start}})
                     }
-                    else {
-                        return Err(self.err_parse("number"));
+                    else{
+                        self.parse_escape(&mut out)?;
                     }
                 }
-            },
-            'a'..='z' | 'A'..='Z' | '_'  => {
-                let mut ident = String::new();
-                while self.cur >= 'a' && self.cur <= 'z'
-                    || self.cur >= 'A' && self.cur <= 'Z'
-                    || self.cur >= '0' && self.cur <= '9'
-                    || self.cur == '_' || self.cur == '-' || self.cur == '.'{
-                    ident.push(self.cur);
-                    self.next(i);
-                } 
-                if ident == "true" {
-                    return Ok(TomlTokWithSpan{tok:TomlTok::Bool(true), span:TomlSpan{start, len:self.pos - start}})
+                Some('\r') if self.eat_str("\r\n")=>out.push_str("\r\n"),
+                Some(c) if c != '\n' && is_control(c)=>return Err(self.err_here("Control characters must be escaped")),
+                Some(c)=>{
+                    self.bump();
+                    out.push(c);
                 }
-                if ident == "false" {
-                    return Ok(TomlTokWithSpan{tok:TomlTok::Bool(false), span:TomlSpan{start, len:self.pos - start}})
-                }
-                if ident == "inf" {
-                    return Ok(TomlTokWithSpan{tok:TomlTok::Inf(false), span:TomlSpan{start, len:self.pos - start}})
+            }
+        }
+    }
+
+    fn parse_array(&mut self)->Result<Toml, TomlErr>{
+        let start = self.pos;
+        self.bump();
+        let mut items = Vec::new();
+        loop{
+            self.skip_ws_comment_newlines()?;
+            if self.eat(']'){
+                break
+            }
+            self.path.push(items.len().to_string());
+            let item = self.parse_value();
+            self.path.pop();
+            items.push(item?);
+            self.skip_ws_comment_newlines()?;
+            if self.eat(']'){
+                break
+            }
+            if !self.eat(','){
+                return Err(self.err_here("Expected `,` or `]` in array"))
+            }
+        }
+        Ok(Toml::Array(items, self.span_from(start)))
+    }
+
+    fn parse_inline_table(&mut self)->Result<Toml, TomlErr>{
+        let start = self.pos;
+        self.bump();
+        let mut table = TomlTable::new(TomlTableKind::Inline);
+        self.skip_ws();
+        self.inline += 1;
+        if !self.eat('}'){
+            loop{
+                self.parse_key_value(&mut table)?;
+                self.skip_ws();
+                if self.eat('}'){
+                    break
                 }
-                if ident == "nan" {
-                    return Ok(TomlTokWithSpan{tok:TomlTok::Nan(false), span:TomlSpan{start, len:self.pos - start}})
+                if !self.eat(','){
+                    return Err(self.err_here("Expected `,` or `}` in inline table"))
                 }
-                return Ok(TomlTokWithSpan{tok:TomlTok::Ident(ident), span:TomlSpan{start, len:self.pos - start}})
-            },
-            
-            '"' => {
-                let mut val = String::new();
-                self.next(i);
-                while self.cur != '"' {
-                    if self.cur == '\\' {
-                        self.next(i);
-                    }
-                    if self.cur == '\0' {
-                        return Err(self.err_parse("string"));
-                    }
-                    val.push(self.cur);
-                    self.next(i);
+                self.skip_ws();
+            }
+        }
+        self.inline -= 1;
+        Ok(Toml::Table(table, self.span_from(start)))
+    }
+
+    fn parse_number_or_datetime(&mut self)->Result<Toml, TomlErr>{
+        let start = self.pos;
+        let b = self.rest().as_bytes();
+        let digits = |n:usize| b.len() > n && b[..n].iter().all(u8::is_ascii_digit);
+        if digits(4) && b[4] == b'-' || digits(2) && b[2] == b':'{
+            if let Some(dt) = self.scan_datetime(){
+                return Ok(Toml::DateTime(dt, self.span_from(start)))
+            }
+            self.pos = start;
+            let len = self.rest().find(|c:char| !(c.is_ascii_alphanumeric() || ":.+-".contains(c))).unwrap_or(self.rest().len());
+            return Err(self.err_span("Invalid date-time", TomlSpan{start, len}))
+        }
+        let rest = self.rest();
+        let len = rest.find(|c:char| !(c.is_ascii_alphanumeric() || "_.+-".contains(c))).unwrap_or(rest.len());
+        self.pos += len;
+        let span = self.span_from(start);
+        parse_number(&rest[..len], span).ok_or_else(|| self.err_span("Invalid number", span))
+    }
+
+    fn scan_char(&mut self, c:char)->Option<()>{
+        if self.eat(c){Some(())}else{None}
+    }
+
+    fn scan_digits(&mut self, n:usize)->Option<u32>{
+        let digits = self.rest().get(..n)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()){
+            return None
+        }
+        self.pos += n;
+        digits.parse().ok()
+    }
+
+    fn scan_datetime(&mut self)->Option<TomlDateTime>{
+        let mut dt = TomlDateTime::default();
+        if self.rest().as_bytes().get(4) == Some(&b'-'){
+            let year = self.scan_digits(4)?;
+            self.scan_char('-')?;
+            let month = self.scan_digits(2)?;
+            self.scan_char('-')?;
+            let day = self.scan_digits(2)?;
+            let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+            let days = [31, if leap{29}else{28}, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+            if !(1..=12).contains(&month) || day < 1 || day > days[month as usize - 1]{
+                return None
+            }
+            dt.date = Some(TomlDate{year:year as u16, month:month as u8, day:day as u8});
+            // a time follows after a `T` or a single space
+            let b = self.rest().as_bytes();
+            if b.len() < 3 || !matches!(b[0], b'T' | b't' | b' ') || !b[1].is_ascii_digit(){
+                return Some(dt)
+            }
+            self.pos += 1;
+        }
+        let hour = self.scan_digits(2)?;
+        self.scan_char(':')?;
+        let minute = self.scan_digits(2)?;
+        self.scan_char(':')?;
+        let second = self.scan_digits(2)?;
+        if hour > 23 || minute > 59 || second > 60{
+            return None
+        }
+        let mut nanosecond = 0;
+        if self.eat('.'){
+            let frac = &self.rest()[..self.rest().find(|c:char| !c.is_ascii_digit()).unwrap_or(self.rest().len())];
+            if frac.is_empty(){
+                return None
+            }
+            self.pos += frac.len();
+            // anything past nanoseconds is truncated
+            for i in 0..9{
+                nanosecond = nanosecond * 10 + frac.as_bytes().get(i).map_or(0, |b| (b - b'0') as u32);
+            }
+        }
+        dt.time = Some(TomlTime{hour:hour as u8, minute:minute as u8, second:second as u8, nanosecond});
+        if dt.date.is_some(){
+            if self.eat('Z') || self.eat('z'){
+                dt.offset = Some(TomlOffset::Z);
+            }
+            else if let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-'){
+                self.bump();
+                let hours = self.scan_digits(2)?;
+                self.scan_char(':')?;
+                let minutes = self.scan_digits(2)?;
+                if hours > 23 || minutes > 59{
+                    return None
                 }
-                self.next(i);
-                return Ok(TomlTokWithSpan{tok:TomlTok::Str(val), span:TomlSpan{start, len:self.pos - start}})
-            },
-            _ => {
-                return Err(self.err_parse("tokenizer"));
+                let offset = (hours * 60 + minutes) as i16;
+                dt.offset = Some(TomlOffset::Minutes(if sign == '-'{-offset}else{offset}));
             }
         }
+        Some(dt)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn spans() {
+        let src = "[package]\nname = \"é-crate\" # x\nversion.major = 0x10\n[[bin]]\n";
+        let toml = parse_toml(src).unwrap();
+        let name = toml.lookup("package.name").unwrap();
+        assert_eq!(&src[name.span().start..name.span().start + name.span().len], "\"é-crate\"");
+        assert_eq!(toml.lookup("package.version.major").and_then(|v| v.as_int()), Some(16));
+        let bin = &toml.get("bin").unwrap().as_array().unwrap()[0];
+        assert_eq!(bin.span(), TomlSpan{start:src.len() - 8, len:7});
+
+        let err = parse_toml("a = 1\nb = [1, 2,, 3]\n").unwrap_err();
+        assert_eq!(err.span, TomlSpan{start:16, len:1});
+        let err = parse_toml("[a]\nx = 1\n[b]\n[a]\n").unwrap_err();
+        assert_eq!(err.span, TomlSpan{start:15, len:1});
+    }
+}
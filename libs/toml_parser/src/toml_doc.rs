@@ -0,0 +1,549 @@
+use std::fmt::{self, Write};
+use crate::toml::*;
+
+/// A TOML document that is edited in place. Comments, whitespace and ordering outside of the
+/// edited keys stay as they are, and a document that isn't edited prints back byte for byte.
+///
+/// Paths are lists of keys, elements of arrays of tables are addressed by their index:
+/// `["bin", "0", "name"]`.
+pub struct TomlDoc{
+    src: String,
+    root: TomlTable,
+    items: Vec<TomlItem>,
+}
+
+fn err(msg:impl Into<String>, span:TomlSpan)->TomlErr{
+    TomlErr{msg:msg.into(), span}
+}
+
+fn same_path(a:&[String], b:&[&str])->bool{
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a == b)
+}
+
+fn is_bare_key(key:&str)->bool{
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn write_str(out:&mut impl Write, s:&str)->fmt::Result{
+    out.write_char('"')?;
+    for c in s.chars(){
+        match c{
+            '"'=>out.write_str("\\\"")?,
+            '\\'=>out.write_str("\\\\")?,
+            '\n'=>out.write_str("\\n")?,
+            '\r'=>out.write_str("\\r")?,
+            '\t'=>out.write_str("\\t")?,
+            '\u{8}'=>out.write_str("\\b")?,
+            '\u{c}'=>out.write_str("\\f")?,
+            c if c < ' ' || c == '\u{7f}'=>write!(out, "\\u{:04X}", c as u32)?,
+            c=>out.write_char(c)?
+        }
+    }
+    out.write_char('"')
+}
+
+/// Writes keys as a dotted key, quoting the ones that can't be bare.
+pub fn toml_dotted_key(keys:&[&str])->String{
+    let mut out = String::new();
+    for (i, key) in keys.iter().enumerate(){
+        if i != 0{
+            out.push('.');
+        }
+        if is_bare_key(key){
+            out.push_str(key);
+        }
+        else{
+            let _ = write_str(&mut out, key);
+        }
+    }
+    out
+}
+
+/// Writes the value as it would appear after `key = `, tables become inline tables.
+impl fmt::Display for Toml{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            Toml::Str(v,_)=>write_str(f, v),
+            Toml::Bool(v,_)=>write!(f, "{}", v),
+            Toml::Int(v,_)=>write!(f, "{}", v),
+            Toml::Float(v,_) if v.is_nan()=>write!(f, "nan"),
+            Toml::Float(v,_) if v.is_infinite()=>write!(f, "{}inf", if *v < 0.0{"-"}else{""}),
+            // debug formatting always has a `.` or an exponent
+            Toml::Float(v,_)=>write!(f, "{:?}", v),
+            Toml::DateTime(v,_)=>write!(f, "{}", v),
+            Toml::Array(v,_)=>{
+                write!(f, "[")?;
+                for (i, item) in v.iter().enumerate(){
+                    write!(f, "{}{}", if i == 0{""}else{", "}, item)?;
+                }
+                write!(f, "]")
+            }
+            Toml::Table(v,_) if v.is_empty()=>write!(f, "{{}}"),
+            Toml::Table(v,_)=>{
+                write!(f, "{{ ")?;
+                for (i, (key, value)) in v.iter().enumerate(){
+                    write!(f, "{}{} = {}", if i == 0{""}else{", "}, toml_dotted_key(&[key]), value)?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+// tables and arrays of tables that get their own [header]
+fn is_section(value:&Toml)->bool{
+    match value{
+        Toml::Table(t,_)=>t.kind == TomlTableKind::Header || t.kind == TomlTableKind::Implicit,
+        Toml::Array(a,_)=>is_table_array(a),
+        _=>false
+    }
+}
+
+impl TomlTable{
+    // `key = value` lines, dotted tables are written as dotted keys
+    fn write_values<'a>(&'a self, out:&mut String, prefix:&mut Vec<&'a str>)->fmt::Result{
+        for (key, value) in &self.entries{
+            prefix.push(key);
+            match value{
+                Toml::Table(t,_) if t.kind == TomlTableKind::Dotted=>t.write_values(out, prefix)?,
+                v if is_section(v)=>(),
+                v=>writeln!(out, "{} = {}", toml_dotted_key(prefix), v)?
+            }
+            prefix.pop();
+        }
+        Ok(())
+    }
+
+    fn write_sections<'a>(&'a self, out:&mut String, path:&mut Vec<&'a str>)->fmt::Result{
+        for (key, value) in &self.entries{
+            path.push(key);
+            match value{
+                Toml::Table(t,_) if t.kind == TomlTableKind::Dotted=>t.write_sections(out, path)?,
+                Toml::Table(t,_) if is_section(value)=>{
+                    // implicit tables only need a header when they have values of their own
+                    if t.kind == TomlTableKind::Header || t.entries.iter().any(|(_, v)| !is_section(v)){
+                        writeln!(out, "\n[{}]", toml_dotted_key(path))?;
+                    }
+                    t.write_values(out, &mut Vec::new())?;
+                    t.write_sections(out, path)?;
+                }
+                Toml::Array(items,_) if is_section(value)=>for item in items{
+                    if let Toml::Table(t,_) = item{
+                        writeln!(out, "\n[[{}]]", toml_dotted_key(path))?;
+                        t.write_values(out, &mut Vec::new())?;
+                        t.write_sections(out, path)?;
+                    }
+                }
+                _=>()
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Writes the table as a document, with a `[header]` for every header table.
+impl fmt::Display for TomlTable{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write_values(&mut out, &mut Vec::new())?;
+        self.write_sections(&mut out, &mut Vec::new())?;
+        f.write_str(out.trim_start_matches('\n'))
+    }
+}
+
+impl From<&str> for Toml{
+    fn from(v:&str)->Self{Toml::Str(v.to_string(), TomlSpan::default())}
+}
+
+impl From<String> for Toml{
+    fn from(v:String)->Self{Toml::Str(v, TomlSpan::default())}
+}
+
+impl From<bool> for Toml{
+    fn from(v:bool)->Self{Toml::Bool(v, TomlSpan::default())}
+}
+
+impl From<i64> for Toml{
+    fn from(v:i64)->Self{Toml::Int(v, TomlSpan::default())}
+}
+
+impl From<f64> for Toml{
+    fn from(v:f64)->Self{Toml::Float(v, TomlSpan::default())}
+}
+
+impl From<TomlDateTime> for Toml{
+    fn from(v:TomlDateTime)->Self{Toml::DateTime(v, TomlSpan::default())}
+}
+
+impl From<Vec<Toml>> for Toml{
+    fn from(v:Vec<Toml>)->Self{Toml::Array(v, TomlSpan::default())}
+}
+
+impl From<TomlTable> for Toml{
+    fn from(v:TomlTable)->Self{Toml::Table(v, TomlSpan::default())}
+}
+
+impl TomlDoc{
+    pub fn parse(src:&str)->Result<Self, TomlErr>{
+        let (root, items) = parse_toml_items(src)?;
+        Ok(Self{src:src.to_string(), root, items})
+    }
+
+    pub fn as_str(&self)->&str{
+        &self.src
+    }
+
+    pub fn root(&self)->&TomlTable{
+        &self.root
+    }
+
+    pub fn get(&self, path:&[&str])->Option<&Toml>{
+        self.root.get_path(path)
+    }
+
+    /// Replaces the value at `path`. A new key goes at the end of the table it belongs to,
+    /// missing tables in between are written as a dotted key.
+    pub fn set(&mut self, path:&[&str], value:impl Into<Toml>)->Result<(), TomlErr>{
+        let value = value.into().to_string();
+        if let Some(item) = self.items.iter().find(|i| {
+            matches!(i.kind, TomlItemKind::KeyValue | TomlItemKind::InlineKeyValue) && same_path(&i.path, path)
+        }){
+            let span = item.value;
+            return self.splice(vec![(span.start, span.start + span.len, value)])
+        }
+        if let Some(v) = self.get(path){
+            return Err(err(format!("Cannot replace `{}` with a value", path.join(".")), v.span()))
+        }
+        if path.is_empty(){
+            return Err(err("Cannot set an empty path", TomlSpan::default()))
+        }
+        // find the table to add to, keys next to a dotted key are added to the table it was written in
+        let mut parent = path.len() - 1;
+        while parent > 0 && self.get(&path[..parent]).is_none(){
+            parent -= 1;
+        }
+        while parent > 0 && matches!(self.get(&path[..parent]), Some(Toml::Table(t,_)) if t.kind == TomlTableKind::Dotted){
+            parent -= 1;
+        }
+        self.check_tables(&path[..parent])?;
+        let parent_path = &path[..parent];
+        let entry = format!("{} = {}", toml_dotted_key(&path[parent..]), value);
+        let kind = match self.get(parent_path){
+            Some(Toml::Table(t,_))=>t.kind,
+            _=>TomlTableKind::Header
+        };
+        match kind{
+            TomlTableKind::Inline=>{
+                let last = self.items.iter().rev().find(|i| i.kind == TomlItemKind::InlineKeyValue && same_path(i.parent(), parent_path));
+                if let Some(last) = last{
+                    let at = last.value.start + last.value.len;
+                    return self.splice(vec![(at, at, format!(", {}", entry))])
+                }
+                let braces = self.get(parent_path).unwrap().span();
+                self.splice(vec![(braces.start, braces.start + braces.len, format!("{{ {} }}", entry))])
+            }
+            TomlTableKind::Implicit=>{
+                let header = self.header_key(parent_path, false)?;
+                self.append(format!("{}\n{}\n", header, entry))
+            }
+            _=>{
+                // after the last key/value of the section, or right below its header
+                let last = self.items.iter().rev().find(|i| i.kind == TomlItemKind::KeyValue && same_path(i.parent(), parent_path))
+                    .or_else(|| self.items.iter().find(|i| i.kind != TomlItemKind::KeyValue && same_path(&i.path, parent_path)));
+                if let Some(last) = last{
+                    let indent = self.indent(last.key.start).to_string();
+                    let at = self.line_end(last.value.start + last.value.len);
+                    let text = if self.src[..at].ends_with('\n'){
+                        format!("{}{}\n", indent, entry)
+                    }
+                    else{
+                        format!("\n{}{}", indent, entry)
+                    };
+                    return self.splice(vec![(at, at, text)])
+                }
+                // the root without any keys yet, they go above the first header
+                match self.items.first(){
+                    Some(first)=>{
+                        let at = self.decor_start(first.key.start);
+                        self.splice(vec![(at, at, format!("{}\n\n", entry))])
+                    }
+                    None=>self.append(format!("{}\n", entry))
+                }
+            }
+        }
+    }
+
+    /// Adds a `[table]` header at the end of the document.
+    pub fn insert_table(&mut self, path:&[&str])->Result<(), TomlErr>{
+        match self.get(path){
+            Some(Toml::Table(t,_)) if t.kind == TomlTableKind::Implicit=>(),
+            Some(v)=>return Err(err(format!("`{}` already exists", path.join(".")), v.span())),
+            None=>()
+        }
+        self.check_tables(path)?;
+        let header = self.header_key(path, false)?;
+        self.append(format!("{}\n", header))
+    }
+
+    /// Adds a `[[table]]` header at the end of the document, a new element for the array of tables at `path`.
+    pub fn push_array_table(&mut self, path:&[&str])->Result<(), TomlErr>{
+        match self.get(path){
+            Some(Toml::Array(a,_)) if is_table_array(a)=>(),
+            Some(v)=>return Err(err(format!("`{}` is not an array of tables", path.join(".")), v.span())),
+            None=>()
+        }
+        self.check_tables(path)?;
+        let header = self.header_key(path, true)?;
+        self.append(format!("{}\n", header))
+    }
+
+    /// Removes a key or a whole table with its headers and sub tables, comment lines
+    /// right above a removed line go with it.
+    pub fn remove(&mut self, path:&[&str])->Result<Option<Toml>, TomlErr>{
+        let value = match self.get(path){
+            Some(v)=>v.clone(),
+            None=>return Ok(None)
+        };
+        let removed = |item:&TomlItem| item.path.len() >= path.len() && same_path(&item.path[..path.len()], path);
+        let mut ranges = Vec::new();
+        for (index, item) in self.items.iter().enumerate(){
+            if !removed(item){
+                continue
+            }
+            match item.kind{
+                // entries nested in a removed inline value go with it
+                TomlItemKind::InlineKeyValue if item.parent().len() >= path.len()=>(),
+                TomlItemKind::InlineKeyValue=>ranges.push(self.inline_entry_range(item)),
+                TomlItemKind::KeyValue=>{
+                    let end = self.line_end(item.value.start + item.value.len);
+                    ranges.push((self.decor_start(item.key.start), end));
+                }
+                // a header takes its whole section up to whatever comes next
+                _=>{
+                    let end = self.items[index + 1..].iter().find(|i| !removed(i))
+                        .map_or(self.src.len(), |next| self.decor_start(next.key.start));
+                    let mut start = self.decor_start(item.key.start);
+                    if end == self.src.len(){
+                        start = self.src[..start].trim_end().len();
+                        if start > 0{
+                            start += self.src[start..].find('\n').map_or(0, |i| i + 1);
+                        }
+                    }
+                    ranges.push((start, end));
+                }
+            }
+        }
+        if ranges.is_empty(){
+            return Err(err(format!("Cannot remove `{}`, it is part of an array value", path.join(".")), value.span()))
+        }
+        ranges.sort();
+        let mut merged:Vec<(usize, usize, String)> = Vec::new();
+        for (start, end) in ranges{
+            match merged.last_mut(){
+                Some(last) if start <= last.1=>last.1 = last.1.max(end),
+                _=>merged.push((start, end, String::new()))
+            }
+        }
+        self.splice(merged)?;
+        Ok(Some(value))
+    }
+
+    // every table on the way to `path` has to be one that can get more keys or headers
+    fn check_tables(&self, path:&[&str])->Result<(), TomlErr>{
+        for i in 1..=path.len(){
+            match self.get(&path[..i]){
+                Some(Toml::Table(t,_)) if t.kind != TomlTableKind::Inline || i == path.len()=>(),
+                Some(v) if is_section(v)=>(),
+                Some(Toml::Array(..)) if i < path.len()=>(),
+                None=>return Ok(()),
+                Some(v)=>return Err(err(format!("`{}` is not a table", path[..i].join(".")), v.span()))
+            }
+        }
+        Ok(())
+    }
+
+    // the [header] for a path, arrays of tables can only be extended at their last element
+    fn header_key(&self, path:&[&str], array:bool)->Result<String, TomlErr>{
+        let mut keys = Vec::new();
+        for i in 0..path.len(){
+            if let Some(Toml::Array(a, span)) = self.get(&path[..i]){
+                if path[i].parse::<usize>().ok() != Some(a.len() - 1){
+                    return Err(err(format!("`{}` can only be extended at its last element", path[..i].join(".")), *span))
+                }
+            }
+            else{
+                keys.push(path[i]);
+            }
+        }
+        let key = toml_dotted_key(&keys);
+        Ok(if array{format!("[[{}]]", key)}else{format!("[{}]", key)})
+    }
+
+    fn append(&mut self, section:String)->Result<(), TomlErr>{
+        let mut text = String::new();
+        if !self.src.is_empty() && !self.src.ends_with('\n'){
+            text.push('\n');
+        }
+        if !self.src.trim().is_empty(){
+            text.push('\n');
+        }
+        text.push_str(&section);
+        let at = self.src.len();
+        self.splice(vec![(at, at, text)])
+    }
+
+    fn line_start(&self, pos:usize)->usize{
+        self.src[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    // the whitespace in front of the key on its line
+    fn indent(&self, pos:usize)->&str{
+        let indent = &self.src[self.line_start(pos)..pos];
+        if indent.trim().is_empty(){indent}else{""}
+    }
+
+    // start of the line, moved up over comment lines directly above it
+    fn decor_start(&self, pos:usize)->usize{
+        let mut start = self.line_start(pos);
+        while start > 0{
+            let above = self.line_start(start - 1);
+            if !self.src[above..start].trim_start().starts_with('#'){
+                break
+            }
+            start = above;
+        }
+        start
+    }
+
+    // past the trailing whitespace, comment and newline after a value
+    fn line_end(&self, pos:usize)->usize{
+        let rest = &self.src[pos..];
+        let trimmed = rest.trim_start_matches([' ', '\t']);
+        let trimmed = if trimmed.starts_with('#'){
+            &trimmed[trimmed.find('\n').map_or(trimmed.len(), |i| if trimmed[..i].ends_with('\r'){i - 1}else{i})..]
+        }
+        else{
+            trimmed
+        };
+        let trimmed = trimmed.strip_prefix("\r\n").or_else(|| trimmed.strip_prefix('\n')).unwrap_or(trimmed);
+        self.src.len() - trimmed.len()
+    }
+
+    // an inline `key = value` with one of the commas next to it
+    fn inline_entry_range(&self, item:&TomlItem)->(usize, usize){
+        let (start, end) = (item.key.start, item.value.start + item.value.len);
+        let after = self.src[end..].trim_start_matches([' ', '\t']);
+        if let Some(next) = after.strip_prefix(','){
+            return (start, self.src.len() - next.trim_start_matches([' ', '\t']).len())
+        }
+        let before = self.src[..start].trim_end_matches([' ', '\t']);
+        if before.ends_with(','){
+            return (before.len() - 1, end)
+        }
+        // the only entry, leaves `{}`
+        (before.len(), self.src.len() - after.len())
+    }
+
+    // applies non overlapping replacements and parses the result, nothing changes on an error
+    fn splice(&mut self, mut edits:Vec<(usize, usize, String)>)->Result<(), TomlErr>{
+        edits.sort_by_key(|(start, _, _)| *start);
+        let mut src = self.src.clone();
+        for (start, end, text) in edits.iter().rev(){
+            src.replace_range(*start..*end, text);
+        }
+        let (root, items) = parse_toml_items(&src)?;
+        self.src = src;
+        self.root = root;
+        self.items = items;
+        Ok(())
+    }
+}
+
+impl fmt::Display for TomlDoc{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const CARGO: &str = "# the package\n[package]\nname = \"demo\"  # keep me\nversion = \"0.3.0\"\n\n[dependencies]\nmakepad-widgets = { path = \"../widgets\", version = \"0.3.0\" }\n# serde for the config\nserde = \"1\"\n\n[target.wasm32-unknown-unknown.dependencies]\nweb = {}\n";
+
+    fn doc() -> TomlDoc {
+        TomlDoc::parse(CARGO).unwrap()
+    }
+
+    #[test]
+    fn replace_values() {
+        let mut doc = doc();
+        assert_eq!(doc.to_string(), CARGO);
+        doc.set(&["package", "version"], "0.4.0").unwrap();
+        doc.set(&["dependencies", "makepad-widgets", "version"], "0.4.0").unwrap();
+        doc.set(&["package", "name"], "de\"mo").unwrap();
+        assert_eq!(doc.to_string(), CARGO
+            .replace("version = \"0.3.0\"", "version = \"0.4.0\"")
+            .replace("\"demo\"", "\"de\\\"mo\""));
+        assert_eq!(doc.get(&["package", "name"]).and_then(|v| v.as_str()), Some("de\"mo"));
+        assert!(doc.set(&["package"], 1).is_err());
+        assert!(doc.set(&["package", "name", "x"], 1).is_err());
+    }
+
+    #[test]
+    fn insert_keys() {
+        let mut doc = doc();
+        doc.set(&["package", "edition"], "2021").unwrap();
+        doc.set(&["dependencies", "makepad-widgets", "features"], vec![Toml::from("nightly")]).unwrap();
+        doc.set(&["target", "wasm32-unknown-unknown", "dependencies", "web", "path"], "../web").unwrap();
+        doc.set(&["dependencies", "log", "version"], "0.4").unwrap();
+        doc.set(&["target", "wasm32-unknown-unknown", "opt"], true).unwrap();
+        doc.set(&["title"], "root").unwrap();
+        assert_eq!(doc.to_string(), "title = \"root\"\n\n# the package\n[package]\nname = \"demo\"  # keep me\nversion = \"0.3.0\"\nedition = \"2021\"\n\n[dependencies]\nmakepad-widgets = { path = \"../widgets\", version = \"0.3.0\", features = [\"nightly\"] }\n# serde for the config\nserde = \"1\"\nlog.version = \"0.4\"\n\n[target.wasm32-unknown-unknown.dependencies]\nweb = { path = \"../web\" }\n\n[target.wasm32-unknown-unknown]\nopt = true\n");
+        assert_eq!(doc.get(&["dependencies", "log", "version"]).and_then(|v| v.as_str()), Some("0.4"));
+    }
+
+    #[test]
+    fn tables() {
+        let mut doc = TomlDoc::parse("a = 1").unwrap();
+        doc.insert_table(&["features"]).unwrap();
+        doc.set(&["features", "default"], Vec::<Toml>::new()).unwrap();
+        doc.push_array_table(&["bin"]).unwrap();
+        doc.set(&["bin", "0", "name"], "run").unwrap();
+        doc.push_array_table(&["bin"]).unwrap();
+        doc.insert_table(&["bin", "1", "sub"]).unwrap();
+        assert_eq!(doc.to_string(), "a = 1\n\n[features]\ndefault = []\n\n[[bin]]\nname = \"run\"\n\n[[bin]]\n\n[bin.sub]\n");
+        assert!(doc.insert_table(&["features"]).is_err());
+        assert!(doc.insert_table(&["bin", "0", "other"]).is_err());
+        assert!(doc.push_array_table(&["features"]).is_err());
+        assert!(doc.insert_table(&["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn remove() {
+        let mut doc = doc();
+        assert_eq!(doc.remove(&["dependencies", "serde"]).unwrap().and_then(|v| v.into_str()), Some("1".to_string()));
+        assert_eq!(doc.remove(&["dependencies", "makepad-widgets", "path"]).unwrap().and_then(|v| v.into_str()), Some("../widgets".to_string()));
+        assert!(doc.remove(&["package"]).unwrap().is_some());
+        assert!(doc.remove(&["target", "wasm32-unknown-unknown", "dependencies", "web"]).unwrap().is_some());
+        assert!(doc.remove(&["nothing"]).unwrap().is_none());
+        assert_eq!(doc.to_string(), "[dependencies]\nmakepad-widgets = { version = \"0.3.0\" }\n\n[target.wasm32-unknown-unknown.dependencies]\n");
+        doc.remove(&["dependencies", "makepad-widgets", "version"]).unwrap();
+        doc.remove(&["target"]).unwrap();
+        assert_eq!(doc.to_string(), "[dependencies]\nmakepad-widgets = {}\n");
+        doc.remove(&["dependencies"]).unwrap();
+        assert_eq!(doc.to_string(), "");
+
+        let mut doc = TomlDoc::parse("[[bin]]\nname = \"a\"\n[[bin]]\nname = \"b\"\nlist = [1, 2]\n").unwrap();
+        doc.remove(&["bin", "0"]).unwrap();
+        assert_eq!(doc.to_string(), "[[bin]]\nname = \"b\"\nlist = [1, 2]\n");
+        assert!(doc.remove(&["bin", "0", "list", "1"]).is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        let toml = parse_toml("x = { \"a b\" = [1.0, -inf, 1e300] }\nfruit.apple.color = \"red\"\n[fruit.apple.texture]\nsmooth = true\n[[fruit.kinds]]\nname = \"x\\ty\"\n[c.d]\n").unwrap();
+        assert_eq!(toml.to_string(), "x = { \"a b\" = [1.0, -inf, 1e300] }\nfruit.apple.color = \"red\"\n\n[fruit.apple.texture]\nsmooth = true\n\n[[fruit.kinds]]\nname = \"x\\ty\"\n\n[c.d]\n");
+    }
+}
@@ -0,0 +1,128 @@
+// Runs the cases in tests/toml-test, laid out like https://github.com/toml-lang/toml-test:
+// every valid/*.toml parses into the tagged JSON of its valid/*.json, every invalid/*.toml fails.
+use makepad_micro_serde::*;
+use makepad_toml_parser::*;
+use std::fs;
+use std::path::Path;
+
+fn tagged(ty:&str, value:String)->JsonValue{
+    JsonValue::Object(vec![
+        ("type".to_string(), JsonValue::String(ty.to_string())),
+        ("value".to_string(), JsonValue::String(value)),
+    ])
+}
+
+fn to_json(toml:&Toml)->JsonValue{
+    match toml{
+        Toml::Str(v,_)=>tagged("string", v.clone()),
+        Toml::Bool(v,_)=>tagged("bool", v.to_string()),
+        Toml::Int(v,_)=>tagged("integer", v.to_string()),
+        Toml::Float(v,_)=>tagged("float", v.to_string()),
+        Toml::DateTime(v,_)=>tagged(match (v.date, v.time, v.offset){
+            (Some(_), Some(_), Some(_))=>"datetime",
+            (Some(_), Some(_), None)=>"datetime-local",
+            (Some(_), None, _)=>"date-local",
+            _=>"time-local",
+        }, v.to_string()),
+        Toml::Array(v,_)=>JsonValue::Array(v.iter().map(to_json).collect()),
+        Toml::Table(v,_)=>table_to_json(v),
+    }
+}
+
+fn table_to_json(table:&TomlTable)->JsonValue{
+    JsonValue::Object(table.iter().map(|(k,v)| (k.to_string(), to_json(v))).collect())
+}
+
+fn parse_float(v:&str)->f64{
+    match v.trim_start_matches('+'){
+        "inf"=>f64::INFINITY,
+        "-inf"=>f64::NEG_INFINITY,
+        "nan" | "-nan"=>f64::NAN,
+        v=>v.parse().unwrap()
+    }
+}
+
+// tagged values compare by meaning, tables ignore key order
+fn same(a:&JsonValue, b:&JsonValue)->bool{
+    match (a, b){
+        (JsonValue::Object(oa), JsonValue::Object(ob))=>{
+            if let (Some(ta), Some(va), Some(tb), Some(vb), 2, 2) = (
+                a["type"].as_str(), a["value"].as_str(), b["type"].as_str(), b["value"].as_str(), oa.len(), ob.len()
+            ){
+                return ta == tb && match ta{
+                    "float"=>{
+                        let (fa, fb) = (parse_float(va), parse_float(vb));
+                        fa == fb || fa.is_nan() && fb.is_nan()
+                    }
+                    "integer"=>va.parse::<i64>().unwrap() == vb.parse::<i64>().unwrap(),
+                    _=>va == vb
+                }
+            }
+            oa.len() == ob.len() && oa.iter().all(|(k, v)| b.get(k).is_some_and(|w| same(v, w)))
+        }
+        (JsonValue::Array(xa), JsonValue::Array(xb))=>{
+            xa.len() == xb.len() && xa.iter().zip(xb.iter()).all(|(v, w)| same(v, w))
+        }
+        _=>false
+    }
+}
+
+fn cases(dir:&str, ext:&str)->Vec<std::path::PathBuf>{
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/toml-test").join(dir);
+    let mut cases:Vec<_> = fs::read_dir(dir).unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == ext))
+        .collect();
+    cases.sort();
+    cases
+}
+
+#[test]
+fn valid(){
+    let mut failed = Vec::new();
+    for case in cases("valid", "toml"){
+        let source = fs::read_to_string(&case).unwrap();
+        let expected = JsonValue::deserialize_json(&fs::read_to_string(case.with_extension("json")).unwrap()).unwrap();
+        match parse_toml(&source){
+            Ok(toml)=>{
+                let got = table_to_json(&toml);
+                if !same(&got, &expected){
+                    failed.push(format!("{:?}: got {}", case.file_name().unwrap(), got.serialize_json()));
+                }
+            }
+            Err(err)=>failed.push(format!("{:?}: {:?}", case.file_name().unwrap(), err))
+        }
+    }
+    assert!(failed.is_empty(), "\n{}", failed.join("\n"));
+}
+
+#[test]
+fn invalid(){
+    let mut failed = Vec::new();
+    for case in cases("invalid", "toml"){
+        let source = fs::read_to_string(&case).unwrap();
+        match parse_toml(&source){
+            Ok(_)=>failed.push(format!("{:?} should not parse", case.file_name().unwrap())),
+            Err(err)=>assert!(err.span.start + err.span.len <= source.len())
+        }
+    }
+    assert!(failed.is_empty(), "\n{}", failed.join("\n"));
+}
+
+#[test]
+fn roundtrip(){
+    let mut failed = Vec::new();
+    for case in cases("valid", "toml"){
+        let source = fs::read_to_string(&case).unwrap();
+        if TomlDoc::parse(&source).unwrap().to_string() != source{
+            failed.push(format!("{:?}: document changed", case.file_name().unwrap()));
+        }
+        let toml = parse_toml(&source).unwrap();
+        let written = toml.to_string();
+        match parse_toml(&written){
+            Ok(back) if same(&table_to_json(&back), &table_to_json(&toml))=>(),
+            _=>failed.push(format!("{:?}: wrote\n{}", case.file_name().unwrap(), written))
+        }
+    }
+    assert!(failed.is_empty(), "\n{}", failed.join("\n"));
+}
@@ -12,10 +12,13 @@ pub mod live_registry;
 pub mod live_expander;
 pub mod live_ptr;
 pub mod live_eval;
+pub mod live_eval_builtin;
 pub mod live_component;
 pub mod live_node_cbor;
 //pub mod live_node_cbor;
 pub mod live_node_reader;
+pub mod live_printer;
+pub mod live_fuzz;
 
 pub use makepad_math;
 pub use makepad_derive_live;
@@ -39,6 +42,10 @@ pub use {
             live_eval,
             LiveEval
         },
+        live_eval_builtin::{
+            LiveBuiltin,
+            LiveEvalTy
+        },
         live_registry::{
             LiveEditEvent,
             LiveRegistry,
@@ -68,6 +75,9 @@ pub use {
         live_node_reader::{
             LiveNodeReader,
         },
+        live_printer::{
+            LivePrinter,
+        },
         live_node::{
             LiveProp,
             LiveIdAsProp,
@@ -10,6 +10,7 @@ pub use {
         live_error::{LiveError},
         live_node_vec::*,
         live_registry::LiveRegistry,
+        live_eval_builtin::LiveBuiltin,
         live_node::*
     }
 };
@@ -40,7 +41,7 @@ impl LiveError {
     }
     
     fn eval_error_expression_call_not_implemented(origin: LiveErrorOrigin, index: usize, nodes: &[LiveNode], ident: LiveId, args: usize)->Self {
-        Self::eval_error(origin, index, nodes, format!("Expression call not implemented ident:{} with number of args: {}", ident, args))
+        Self::eval_error(origin, index, nodes, format!("Unknown function {} called with {} args", ident, args))
     }
     
     fn eval_error_cant_find_target(origin: LiveErrorOrigin, index: usize, nodes: &[LiveNode], id: LiveId)->Self {
@@ -60,10 +61,17 @@ pub fn live_eval(live_registry: &LiveRegistry, start: usize, index: &mut usize,
     Ok(match &nodes[*index].value {
         LiveValue::Str(_) |
         LiveValue::InlineString(_) => {
-            LiveEval::String(Rc::new(live_registry.live_node_as_string(&nodes[*index]).unwrap()))
+            *index += 1;
+            LiveEval::String(Rc::new(live_registry.live_node_as_string(&nodes[*index - 1]).unwrap()))
+        }
+        LiveValue::Dependency(v) => {
+            *index += 1;
+            LiveEval::String(v.clone())
+        }
+        LiveValue::String(v) => {
+            *index += 1;
+            LiveEval::String(v.clone())
         }
-        LiveValue::Dependency(v)=>LiveEval::String(v.clone()),
-        LiveValue::String(v)=>LiveEval::String(v.clone()),
         LiveValue::Float32(v) => {
             *index += 1;
             LiveEval::Float64(*v as f64)
@@ -189,27 +197,21 @@ pub fn live_eval(live_registry: &LiveRegistry, start: usize, index: &mut usize,
             }
         }
         LiveValue::ExprCall {ident, args} => {
+            let call_index = *index;
             *index += 1;
-            match ident {
-                live_id!(blend) if *args == 2 => {
-                    let a = live_eval(live_registry, start, index, nodes)?;
-                    let b = live_eval(live_registry, start, index, nodes)?;
-                    if let LiveEval::Vec4(va) = a {
-                        if let LiveEval::Vec4(vb) = b {
-                            // ok so how do we blend this eh.
-                            return Ok(LiveEval::Vec4(vec4(
-                                va.x + (vb.x - va.x) * vb.w,
-                                va.y + (vb.y - va.y) * vb.w,
-                                va.z + (vb.z - va.z) * vb.w,
-                                va.w
-                            )))
-                        }
-                    }
+            let mut arg_values = Vec::with_capacity(*args);
+            for _ in 0..*args {
+                arg_values.push(live_eval(live_registry, start, index, nodes)?);
+            }
+            if let Some(builtin) = LiveBuiltin::find(*ident) {
+                match builtin.call(arg_values) {
+                    Ok(value) => value,
+                    Err(message) => return Err(LiveError::eval_error(live_error_origin!(), call_index, nodes, message))
                 }
-                _ => {}
             }
-            
-            return Err(LiveError::eval_error_expression_call_not_implemented(live_error_origin!(), *index, nodes, *ident, *args))
+            else {
+                return Err(LiveError::eval_error_expression_call_not_implemented(live_error_origin!(), call_index, nodes, *ident, *args))
+            }
         }
         LiveValue::ExprBinOp(op) => {
             *index += 1;
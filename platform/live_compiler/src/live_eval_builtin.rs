@@ -0,0 +1,391 @@
+use {
+    std::rc::Rc,
+    crate::{
+        makepad_math::{vec2, vec3, vec4},
+        makepad_live_id::{LiveId, live_id},
+        live_eval::LiveEval,
+    },
+};
+
+// the builtin functions callable from live expressions. Names that overlap
+// with the shader builtins keep the same signatures, so a float broadcasts
+// against a vector like it does in a shader.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LiveEvalTy {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Int,
+    Bool,
+    String,
+}
+
+type Ty = LiveEvalTy;
+
+impl LiveEval {
+    pub fn ty(&self) -> LiveEvalTy {
+        match self {
+            Self::Float64(_) => Ty::Float,
+            Self::Vec2(_) => Ty::Vec2,
+            Self::Vec3(_) => Ty::Vec3,
+            Self::Vec4(_) => Ty::Vec4,
+            Self::Int64(_) => Ty::Int,
+            Self::Bool(_) => Ty::Bool,
+            Self::String(_) => Ty::String,
+        }
+    }
+}
+
+macro_rules! builtin {
+    ($f:ident, $eval:ident, [$(($($a:path),*) -> $b:path),*]) => {
+        builtin!($f, $eval, false, [$(($($a),*) -> $b),*])
+    };
+    ($f:ident, $eval:ident, $variadic:expr, [$(($($a:path),*) -> $b:path),*]) => {
+        LiveBuiltin {
+            id: live_id!($f),
+            name: stringify!($f),
+            variadic: $variadic,
+            eval: $eval,
+            maps: &[$(
+                (&[$($a),*], $b)
+            ),*]
+        }
+    }
+}
+
+pub struct LiveBuiltin {
+    pub id: LiveId,
+    pub name: &'static str,
+    // the last argument type of each signature may repeat
+    pub variadic: bool,
+    pub maps: &'static [(&'static [LiveEvalTy], LiveEvalTy)],
+    eval: fn(LiveEvalTy, &[LiveEval]) -> LiveEval,
+}
+
+impl LiveBuiltin {
+    pub fn find(id: LiveId) -> Option<&'static LiveBuiltin> {
+        LIVE_BUILTINS.iter().find( | b | b.id == id)
+    }
+
+    fn matches(&self, params: &[LiveEvalTy], args: &[LiveEval], promote: bool) -> bool {
+        let arity_ok = if self.variadic {args.len() >= params.len()} else {args.len() == params.len()};
+        arity_ok && args.iter().enumerate().all( | (i, arg) | {
+            let param = params[i.min(params.len() - 1)];
+            arg.ty() == param || promote && arg.ty() == Ty::Int && param == Ty::Float
+        })
+    }
+
+    // an exact signature match wins, otherwise ints may stand in for floats
+    pub fn call(&self, args: Vec<LiveEval>) -> Result<LiveEval, String> {
+        for promote in [false, true] {
+            if let Some((_, ret)) = self.maps.iter().find( | (params, _) | self.matches(params, &args, promote)) {
+                let args: Vec<LiveEval> = args.into_iter().map( | arg | match arg {
+                    LiveEval::Int64(v) if promote => LiveEval::Float64(v as f64),
+                    arg => arg
+                }).collect();
+                return Ok((self.eval)(*ret, &args))
+            }
+        }
+        Err(format!(
+            "no overload of {}({}), expected one of: {}",
+            self.name,
+            args.iter().map( | a | format!("{:?}", a.ty())).collect::<Vec<_>>().join(", "),
+            self.signatures()
+        ))
+    }
+
+    pub fn signatures(&self) -> String {
+        self.maps.iter().map( | (params, ret) | format!(
+            "{}({}{}) -> {:?}",
+            self.name,
+            params.iter().map( | p | format!("{:?}", p)).collect::<Vec<_>>().join(", "),
+            if self.variadic {", .."} else {""},
+            ret
+        )).collect::<Vec<_>>().join(", ")
+    }
+}
+
+static LIVE_BUILTINS: &[LiveBuiltin] = &[
+    builtin!(abs, eval_abs, [
+        (Ty::Float) -> Ty::Float,
+        (Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4) -> Ty::Vec4,
+        (Ty::Int) -> Ty::Int
+    ]),
+    builtin!(alpha, eval_alpha, [
+        (Ty::Vec4, Ty::Float) -> Ty::Vec4
+    ]),
+    builtin!(blend, eval_blend, [
+        (Ty::Vec4, Ty::Vec4) -> Ty::Vec4
+    ]),
+    builtin!(ceil, eval_ceil, [
+        (Ty::Float) -> Ty::Float,
+        (Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4) -> Ty::Vec4
+    ]),
+    builtin!(clamp, eval_clamp, [
+        (Ty::Float, Ty::Float, Ty::Float) -> Ty::Float,
+        (Ty::Vec2, Ty::Vec2, Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Vec3, Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Vec4, Ty::Vec4) -> Ty::Vec4,
+        (Ty::Vec2, Ty::Float, Ty::Float) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Float, Ty::Float) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Float, Ty::Float) -> Ty::Vec4,
+        (Ty::Int, Ty::Int, Ty::Int) -> Ty::Int
+    ]),
+    builtin!(concat, eval_concat, true, [
+        (Ty::String) -> Ty::String
+    ]),
+    builtin!(darken, eval_darken, [
+        (Ty::Vec4, Ty::Float) -> Ty::Vec4
+    ]),
+    builtin!(floor, eval_floor, [
+        (Ty::Float) -> Ty::Float,
+        (Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4) -> Ty::Vec4
+    ]),
+    builtin!(fract, eval_fract, [
+        (Ty::Float) -> Ty::Float,
+        (Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4) -> Ty::Vec4
+    ]),
+    builtin!(lighten, eval_lighten, [
+        (Ty::Vec4, Ty::Float) -> Ty::Vec4
+    ]),
+    builtin!(max, eval_max, [
+        (Ty::Float, Ty::Float) -> Ty::Float,
+        (Ty::Vec2, Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Vec4) -> Ty::Vec4,
+        (Ty::Vec2, Ty::Float) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Float) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Float) -> Ty::Vec4,
+        (Ty::Int, Ty::Int) -> Ty::Int
+    ]),
+    builtin!(min, eval_min, [
+        (Ty::Float, Ty::Float) -> Ty::Float,
+        (Ty::Vec2, Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Vec4) -> Ty::Vec4,
+        (Ty::Vec2, Ty::Float) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Float) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Float) -> Ty::Vec4,
+        (Ty::Int, Ty::Int) -> Ty::Int
+    ]),
+    builtin!(mix, eval_mix, [
+        (Ty::Float, Ty::Float, Ty::Float) -> Ty::Float,
+        (Ty::Vec2, Ty::Vec2, Ty::Vec2) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Vec3, Ty::Vec3) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Vec4, Ty::Vec4) -> Ty::Vec4,
+        (Ty::Vec2, Ty::Vec2, Ty::Float) -> Ty::Vec2,
+        (Ty::Vec3, Ty::Vec3, Ty::Float) -> Ty::Vec3,
+        (Ty::Vec4, Ty::Vec4, Ty::Float) -> Ty::Vec4
+    ]),
+    builtin!(vec2, eval_vec, [
+        (Ty::Float) -> Ty::Vec2,
+        (Ty::Float, Ty::Float) -> Ty::Vec2
+    ]),
+    builtin!(vec3, eval_vec, [
+        (Ty::Float) -> Ty::Vec3,
+        (Ty::Float, Ty::Float, Ty::Float) -> Ty::Vec3,
+        (Ty::Vec2, Ty::Float) -> Ty::Vec3,
+        (Ty::Float, Ty::Vec2) -> Ty::Vec3
+    ]),
+    builtin!(vec4, eval_vec, [
+        (Ty::Float) -> Ty::Vec4,
+        (Ty::Float, Ty::Float, Ty::Float, Ty::Float) -> Ty::Vec4,
+        (Ty::Vec3, Ty::Float) -> Ty::Vec4,
+        (Ty::Float, Ty::Vec3) -> Ty::Vec4,
+        (Ty::Vec2, Ty::Float, Ty::Float) -> Ty::Vec4,
+        (Ty::Vec2, Ty::Vec2) -> Ty::Vec4
+    ])
+];
+
+// component wise math happens on 4 lanes, floats broadcast to every lane
+fn lanes(v: &LiveEval) -> [f64; 4] {
+    match v {
+        LiveEval::Float64(v) => [*v; 4],
+        LiveEval::Int64(v) => [*v as f64; 4],
+        LiveEval::Vec2(v) => [v.x as f64, v.y as f64, 0.0, 0.0],
+        LiveEval::Vec3(v) => [v.x as f64, v.y as f64, v.z as f64, 0.0],
+        LiveEval::Vec4(v) => [v.x as f64, v.y as f64, v.z as f64, v.w as f64],
+        LiveEval::Bool(_) | LiveEval::String(_) => [0.0; 4]
+    }
+}
+
+fn from_lanes(ty: LiveEvalTy, l: [f64; 4]) -> LiveEval {
+    match ty {
+        Ty::Float => LiveEval::Float64(l[0]),
+        Ty::Vec2 => LiveEval::Vec2(vec2(l[0] as f32, l[1] as f32)),
+        Ty::Vec3 => LiveEval::Vec3(vec3(l[0] as f32, l[1] as f32, l[2] as f32)),
+        Ty::Vec4 => LiveEval::Vec4(vec4(l[0] as f32, l[1] as f32, l[2] as f32, l[3] as f32)),
+        Ty::Int => LiveEval::Int64(l[0] as i64),
+        Ty::Bool | Ty::String => unreachable!()
+    }
+}
+
+fn map1(ty: LiveEvalTy, args: &[LiveEval], f: fn(f64) -> f64) -> LiveEval {
+    let a = lanes(&args[0]);
+    from_lanes(ty, [f(a[0]), f(a[1]), f(a[2]), f(a[3])])
+}
+
+fn map2(ty: LiveEvalTy, args: &[LiveEval], f: fn(f64, f64) -> f64) -> LiveEval {
+    let (a, b) = (lanes(&args[0]), lanes(&args[1]));
+    from_lanes(ty, [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])])
+}
+
+fn map3(ty: LiveEvalTy, args: &[LiveEval], f: fn(f64, f64, f64) -> f64) -> LiveEval {
+    let (a, b, c) = (lanes(&args[0]), lanes(&args[1]), lanes(&args[2]));
+    from_lanes(ty, [f(a[0], b[0], c[0]), f(a[1], b[1], c[1]), f(a[2], b[2], c[2]), f(a[3], b[3], c[3])])
+}
+
+fn eval_abs(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    match args[0] {
+        LiveEval::Int64(v) => LiveEval::Int64(v.abs()),
+        _ => map1(ty, args, f64::abs)
+    }
+}
+
+fn eval_ceil(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    map1(ty, args, f64::ceil)
+}
+
+fn eval_floor(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    map1(ty, args, f64::floor)
+}
+
+fn eval_fract(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    map1(ty, args, | x | x - x.floor())
+}
+
+fn eval_min(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    match (&args[0], &args[1]) {
+        (LiveEval::Int64(a), LiveEval::Int64(b)) => LiveEval::Int64(*a.min(b)),
+        _ => map2(ty, args, f64::min)
+    }
+}
+
+fn eval_max(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    match (&args[0], &args[1]) {
+        (LiveEval::Int64(a), LiveEval::Int64(b)) => LiveEval::Int64(*a.max(b)),
+        _ => map2(ty, args, f64::max)
+    }
+}
+
+fn eval_clamp(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    match (&args[0], &args[1], &args[2]) {
+        (LiveEval::Int64(x), LiveEval::Int64(lo), LiveEval::Int64(hi)) => LiveEval::Int64(*x.max(lo).min(hi)),
+        _ => map3(ty, args, | x, lo, hi | x.max(lo).min(hi))
+    }
+}
+
+fn eval_mix(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    map3(ty, args, | a, b, t | a + (b - a) * t)
+}
+
+fn eval_vec(ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    if let [LiveEval::Float64(v)] = args {
+        return from_lanes(ty, [*v; 4])
+    }
+    let mut l = [0.0; 4];
+    let mut i = 0;
+    for arg in args {
+        let n = match arg.ty() {Ty::Vec2 => 2, Ty::Vec3 => 3, _ => 1};
+        l[i..i + n].copy_from_slice(&lanes(arg)[0..n]);
+        i += n;
+    }
+    from_lanes(ty, l)
+}
+
+// colors mix towards white or black, alpha is left alone
+fn eval_lighten(_ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    let (c, t) = (lanes(&args[0]), lanes(&args[1])[0]);
+    from_lanes(Ty::Vec4, [c[0] + (1.0 - c[0]) * t, c[1] + (1.0 - c[1]) * t, c[2] + (1.0 - c[2]) * t, c[3]])
+}
+
+fn eval_darken(_ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    let (c, t) = (lanes(&args[0]), lanes(&args[1])[0]);
+    from_lanes(Ty::Vec4, [c[0] * (1.0 - t), c[1] * (1.0 - t), c[2] * (1.0 - t), c[3]])
+}
+
+fn eval_alpha(_ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    let (c, a) = (lanes(&args[0]), lanes(&args[1])[0]);
+    from_lanes(Ty::Vec4, [c[0], c[1], c[2], a])
+}
+
+// blends b over a using b's alpha, keeping the alpha of a
+fn eval_blend(_ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    let (a, b) = (lanes(&args[0]), lanes(&args[1]));
+    from_lanes(Ty::Vec4, [a[0] + (b[0] - a[0]) * b[3], a[1] + (b[1] - a[1]) * b[3], a[2] + (b[2] - a[2]) * b[3], a[3]])
+}
+
+fn eval_concat(_ty: LiveEvalTy, args: &[LiveEval]) -> LiveEval {
+    let mut out = String::new();
+    for arg in args {
+        if let LiveEval::String(s) = arg {
+            out.push_str(s);
+        }
+    }
+    LiveEval::String(Rc::new(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(id: LiveId, args: Vec<LiveEval>) -> Result<LiveEval, String> {
+        LiveBuiltin::find(id).unwrap().call(args)
+    }
+
+    #[test]
+    fn overloads() {
+        match call(live_id!(mix), vec![LiveEval::Vec2(vec2(0.0, 2.0)), LiveEval::Vec2(vec2(1.0, 4.0)), LiveEval::Float64(0.5)]) {
+            Ok(LiveEval::Vec2(v)) => assert_eq!(v, vec2(0.5, 3.0)),
+            v => panic!("{:?}", v)
+        }
+        match call(live_id!(clamp), vec![LiveEval::Int64(7), LiveEval::Int64(0), LiveEval::Int64(5)]) {
+            Ok(LiveEval::Int64(5)) => (),
+            v => panic!("{:?}", v)
+        }
+        // ints promote to floats when there is no int overload
+        match call(live_id!(min), vec![LiveEval::Float64(1.5), LiveEval::Int64(1)]) {
+            Ok(LiveEval::Float64(v)) => assert_eq!(v, 1.0),
+            v => panic!("{:?}", v)
+        }
+        match call(live_id!(vec4), vec![LiveEval::Vec2(vec2(1.0, 2.0)), LiveEval::Int64(3), LiveEval::Float64(4.0)]) {
+            Ok(LiveEval::Vec4(v)) => assert_eq!(v, vec4(1.0, 2.0, 3.0, 4.0)),
+            v => panic!("{:?}", v)
+        }
+        match call(live_id!(concat), vec![LiveEval::String(Rc::new("a".into())), LiveEval::String(Rc::new("bc".into()))]) {
+            Ok(LiveEval::String(s)) => assert_eq!(*s, "abc"),
+            v => panic!("{:?}", v)
+        }
+    }
+
+    #[test]
+    fn colors() {
+        let c = LiveEval::Vec4(vec4(0.5, 0.0, 1.0, 0.8));
+        match call(live_id!(lighten), vec![LiveEval::Vec4(vec4(0.5, 0.0, 1.0, 0.8)), LiveEval::Float64(0.5)]) {
+            Ok(LiveEval::Vec4(v)) => assert_eq!(v, vec4(0.75, 0.5, 1.0, 0.8)),
+            v => panic!("{:?}", v)
+        }
+        match call(live_id!(darken), vec![c, LiveEval::Float64(0.5)]) {
+            Ok(LiveEval::Vec4(v)) => assert_eq!(v, vec4(0.25, 0.0, 0.5, 0.8)),
+            v => panic!("{:?}", v)
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let err = call(live_id!(mix), vec![LiveEval::Float64(1.0), LiveEval::Bool(true)]).unwrap_err();
+        assert!(err.starts_with("no overload of mix(Float, Bool), expected one of: mix(Float, Float, Float) -> Float"), "{}", err);
+        assert!(call(live_id!(concat), vec![]).is_err());
+        assert!(LiveBuiltin::find(live_id!(nope)).is_none());
+    }
+}
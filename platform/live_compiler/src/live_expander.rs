@@ -74,7 +74,9 @@ impl<'a> LiveExpander<'a> {
                     // lets verify it points anywhere
                     let mut found = false;
                     let is_glob = in_node.id == LiveId::empty();
-                    if let Some(nodes) = self.live_registry.module_id_to_expanded_nodes(*module_id){
+                    // a module that imports itself, or isnt expanded yet because of a circular import, has no nodes
+                    let nodes = self.live_registry.module_id_to_expanded_nodes(*module_id).filter( | nodes | nodes.len() > 1);
+                    if let Some(nodes) = nodes {
                         let file_id = self.live_registry.module_id_to_file_id(*module_id).unwrap();
                         let mut node_iter = Some(1);
                         while let Some(index) = node_iter {
@@ -224,7 +226,20 @@ impl<'a> LiveExpander<'a> {
                     }
                 },
                 LiveValue::Clone(clone) => {
-                    if let Some(target) = self.live_registry.find_scope_target(*clone, &out_doc.nodes) {
+                    let target = self.live_registry.find_scope_target(*clone, &out_doc.nodes);
+                    let target_is_open = match target {
+                        Some(LiveScopeTarget::LocalPtr(local_ptr)) => out_doc.nodes[local_ptr].is_open(),
+                        Some(LiveScopeTarget::LivePtr(live_ptr)) => self.live_registry.live_files[live_ptr.file_id.to_index()].expanded.nodes[live_ptr.node_index()].is_open(),
+                        None => true
+                    };
+                    if !target_is_open {
+                        self.errors.push(LiveError {
+                            origin: live_error_origin!(),
+                            span: in_doc.token_id_to_span(in_node.origin.token_id().unwrap()).into(),
+                            message: format!("Can't clone {}, it is not an object", clone)
+                        });
+                    }
+                    else if let Some(target) = target {
                         match target {
                             LiveScopeTarget::LocalPtr(local_ptr) => {
                                 
@@ -0,0 +1,201 @@
+use {
+    std::rc::Rc,
+    crate::{
+        makepad_live_tokenizer::Delim,
+        makepad_live_id::*,
+        live_token::{LiveToken, TokenWithSpan},
+        live_ptr::{LiveFileId, LiveModuleId},
+        live_parser::LiveParser,
+        live_printer::print_token,
+        live_registry::LiveRegistry,
+        live_node::LiveTypeInfo,
+        span::{TextSpan, TextPos},
+    }
+};
+
+// Fuzz entry points for the live compiler. Input bytes pick tokens from a
+// vocabulary that covers the live_design! grammar, so almost every input
+// exercises the parser instead of failing in the tokenizer. Malformed input
+// has to come back as a LiveError, never as a panic.
+// Hook them up to cargo-fuzz with
+//   fuzz_target!(|data: &[u8]| makepad_live_compiler::live_fuzz::fuzz_live_parser(data));
+
+const FUZZ_SEED_DOCUMENT: &str = r#"
+    import crate::fuzz_dep::*
+    Foo = {{Foo}} {
+        instance hover: 0.0
+        list: [1, {a: 1}, Fit]
+        expr: (a + b * mix(1, 2, 0.5))
+        key.{min: 0.0}: 1
+        layout: Padding {left: 1}
+        inset: Inset(1, 2)
+        <Button> {}
+        map: struct {a: 1}
+        size: vec2(1, 2)
+        icon: dep("crate://self/x.png")
+        path: a.b
+        fn pixel(self) -> vec4 {return #f00;}
+    }
+    Bar = <Foo> {hover: 1.0}
+"#;
+
+const FUZZ_DEP_DOCUMENT: &str = r#"
+    Button = {{Foo}} {a: 1}
+    b: 2
+"#;
+
+pub fn fuzz_token_vocabulary() -> Vec<LiveToken> {
+    let mut tokens = Vec::new();
+    for ident in [
+        "fn", "import", "struct", "dep", "vec2", "vec3", "vec4", "crate", "fuzz_dep", "self", "instance",
+        "a", "b", "value", "Fit", "Foo", "Bar", "Button"
+    ] {
+        tokens.push(LiveToken::Ident(LiveId::from_str(ident).unwrap()));
+    }
+    for punct in [
+        live_id!(<), live_id!(>), live_id!(:), live_id!(=), live_id!(,), live_id!(;), live_id!(.),
+        live_id!(::), live_id!(-), live_id!(!), live_id!(?), live_id!(*), live_id!(+), live_id!(/),
+        live_id!(&&), live_id!(||), live_id!(==), live_id!(!=), live_id!(<=), live_id!(>=)
+    ] {
+        tokens.push(LiveToken::Punct(punct));
+    }
+    for delim in [Delim::Paren, Delim::Brace, Delim::Bracket] {
+        tokens.push(LiveToken::Open(delim));
+        tokens.push(LiveToken::Close(delim));
+    }
+    tokens.push(LiveToken::Bool(true));
+    tokens.push(LiveToken::Int(0));
+    tokens.push(LiveToken::Int(7));
+    tokens.push(LiveToken::Float(1.5));
+    tokens.push(LiveToken::Color(0xff0000ff));
+    tokens.push(LiveToken::String(Rc::new("crate://self/x.png".to_string())));
+    tokens
+}
+
+struct LiveFuzzType;
+
+fn fuzz_type_infos() -> Vec<LiveTypeInfo> {
+    vec![LiveTypeInfo {
+        live_type: std::any::TypeId::of::<LiveFuzzType>(),
+        type_name: LiveId::from_str("Foo").unwrap(),
+        module_id: LiveModuleId::from_str("crate::fuzz").unwrap(),
+        live_ignore: false,
+        fields: Vec::new()
+    }]
+}
+
+// every byte is one token
+pub fn fuzz_tokens(data: &[u8]) -> Vec<TokenWithSpan> {
+    let vocabulary = fuzz_token_vocabulary();
+    let file_id = LiveFileId::new(0);
+    let mut tokens: Vec<TokenWithSpan> = data.iter().enumerate().map( | (i, byte) | TokenWithSpan {
+        span: TextSpan {
+            file_id,
+            start: TextPos {line: 0, column: i as u32},
+            end: TextPos {line: 0, column: i as u32 + 1}
+        },
+        token: vocabulary[*byte as usize % vocabulary.len()].clone()
+    }).collect();
+    tokens.push(TokenWithSpan {span: TextSpan::default(), token: LiveToken::Eof});
+    tokens
+}
+
+// every 3 bytes are one edit (replace, insert or remove) of the seed document
+pub fn fuzz_mutated_source(data: &[u8]) -> String {
+    let vocabulary = fuzz_token_vocabulary();
+    let mut tokens: Vec<LiveToken> = LiveRegistry::tokenize_from_str(FUZZ_SEED_DOCUMENT, TextPos::default(), LiveFileId::new(0))
+        .unwrap().into_iter().map( | t | t.token).collect();
+    tokens.pop();
+    for edit in data.chunks_exact(3) {
+        let at = edit[1] as usize * tokens.len() / 256;
+        let token = vocabulary[edit[2] as usize % vocabulary.len()].clone();
+        match edit[0] % 3 {
+            0 if at < tokens.len() => tokens[at] = token,
+            1 => tokens.insert(at, token),
+            _ if at < tokens.len() => {tokens.remove(at);}
+            _ => ()
+        }
+    }
+    let mut source = String::new();
+    for token in &tokens {
+        print_token(token, &mut source);
+        source.push(' ');
+    }
+    source
+}
+
+pub fn fuzz_live_parser(data: &[u8]) {
+    let tokens = fuzz_tokens(data);
+    let live_type_infos = fuzz_type_infos();
+    let mut parser = LiveParser::new(&tokens, &live_type_infos, LiveFileId::new(0));
+    let _ = parser.parse_live_document();
+}
+
+// registers a mutated document next to a dependency and expands both
+pub fn fuzz_live_registry(data: &[u8]) {
+    let mut live_registry = LiveRegistry::default();
+    live_registry.register_live_file(
+        "fuzz_dep.rs",
+        "",
+        LiveModuleId::from_str("crate::fuzz_dep").unwrap(),
+        FUZZ_DEP_DOCUMENT.to_string(),
+        fuzz_type_infos(),
+        TextPos::default()
+    ).unwrap();
+    let registered = live_registry.register_live_file(
+        "fuzz.rs",
+        "",
+        LiveModuleId::from_str("crate::fuzz").unwrap(),
+        fuzz_mutated_source(data),
+        fuzz_type_infos(),
+        TextPos::default()
+    );
+    if registered.is_ok() {
+        let mut errors = Vec::new();
+        live_registry.expand_all_documents(&mut errors);
+        for error in errors {
+            let _ = live_registry.live_error_to_live_file_error(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xorshift_inputs(seed: u64, count: usize, max_len: usize, f: fn(&[u8])) {
+        let mut seed = seed;
+        let mut data = Vec::new();
+        for round in 0..count {
+            data.clear();
+            for _ in 0..(round % max_len) {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                data.push(seed as u8);
+            }
+            f(&data);
+        }
+    }
+
+    #[test]
+    fn seed_document_expands() {
+        let mut live_registry = LiveRegistry::default();
+        for (name, source) in [("crate::fuzz_dep", FUZZ_DEP_DOCUMENT), ("crate::fuzz", FUZZ_SEED_DOCUMENT)] {
+            live_registry.register_live_file(name, "", LiveModuleId::from_str(name).unwrap(), source.to_string(), fuzz_type_infos(), TextPos::default()).unwrap();
+        }
+        let mut errors = Vec::new();
+        live_registry.expand_all_documents(&mut errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn random_token_streams() {
+        xorshift_inputs(0x2545_f491_4f6c_dd1d, 20000, 64, fuzz_live_parser);
+    }
+
+    #[test]
+    fn mutated_documents() {
+        xorshift_inputs(0x9e37_79b9_7f4a_7c15, 10000, 24, fuzz_live_registry);
+    }
+}
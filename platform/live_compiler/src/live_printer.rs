@@ -0,0 +1,493 @@
+use {
+    std::fmt::Write,
+    crate::{
+        makepad_live_id::*,
+        live_token::{LiveToken, TokenWithSpan},
+        live_document::LiveOriginal,
+        live_node::{LiveNode, LiveValue, LivePropType, LiveTypeInfo, LiveBinOp, LiveUnOp},
+    }
+};
+
+// Prints a LiveNode tree back as live_design! source. The output parses back
+// into the same tree. Prefixes (instance x: ..) and DSL blocks (fn ..) are
+// not stored in the nodes, so they are recovered from the original tokens.
+
+pub struct LivePrinter<'a> {
+    pub tokens: &'a [TokenWithSpan],
+    pub edit_info: &'a [LiveNode],
+    pub live_type_infos: &'a [LiveTypeInfo],
+    pub indent: &'a str,
+}
+
+const PUNCTS: [&str; 46] = [
+    "...", "..=", "<<=", ">>=", "!=", "%=", "&&", "&=", "*=", "+=", "-=", "->", "..", "/=", "::", "<<",
+    "<=", "==", "=>", ">=", ">>", "^=", "|=", "||", "!", "#", "$", "%", "&", "*", "+", ",", "-", ".",
+    "/", ":", ";", "<", "=", ">", "?", "@", "^", "|", "{", "}"
+];
+
+impl LiveOriginal {
+    pub fn to_live_design(&self, live_type_infos: &[LiveTypeInfo]) -> String {
+        LivePrinter::new(&self.tokens, &self.edit_info, live_type_infos).print_document(&self.nodes)
+    }
+}
+
+impl<'a> LivePrinter<'a> {
+    pub fn new(tokens: &'a [TokenWithSpan], edit_info: &'a [LiveNode], live_type_infos: &'a [LiveTypeInfo]) -> Self {
+        Self {tokens, edit_info, live_type_infos, indent: "    "}
+    }
+
+    // prints the children of the root node, without the enclosing braces
+    pub fn print_document(&self, nodes: &[LiveNode]) -> String {
+        let mut out = String::new();
+        if nodes.is_empty() {
+            return out
+        }
+        let mut index = 1;
+        while index < nodes.len() && !nodes[index].value.is_close() {
+            if nodes[index].value == LiveValue::None {
+                index += 1;
+                continue
+            }
+            index = self.print_prop(nodes, index, 0, &mut out);
+            out.push('\n');
+        }
+        out
+    }
+
+    // prints one node including its name, returns the index after it.
+    // the caller writes the indentation of the first line
+    pub fn print_prop(&self, nodes: &[LiveNode], index: usize, depth: usize, out: &mut String) -> usize {
+        let node = &nodes[index];
+        match &node.value {
+            LiveValue::DSL {token_start, token_count, ..} => {
+                self.print_tokens(*token_start as usize, *token_count as usize, depth, out);
+                return index + 1
+            }
+            LiveValue::Import(module_id) => {
+                let module = module_id.1.as_string( | s | s.unwrap_or("").to_string());
+                if node.id.is_empty() {
+                    write!(out, "import {}::{}::*", module_id.0, module).unwrap();
+                }
+                else {
+                    write!(out, "import {}::{}::{}", module_id.0, module, node.id).unwrap();
+                }
+                return index + 1
+            }
+            LiveValue::Clone(class) if node.id.is_unique() && node.origin.has_prop_type(LivePropType::Instance) => {
+                write!(out, "<{}> ", class).unwrap();
+                return self.print_body(nodes, index + 1, depth, out)
+            }
+            _ => ()
+        }
+        if !node.origin.has_prop_type(LivePropType::Nameless) {
+            if node.origin.node_has_prefix() {
+                if let Some(prefix) = self.prefix_token(node) {
+                    write!(out, "{} ", prefix).unwrap();
+                }
+            }
+            write!(out, "{}", node.id).unwrap();
+            self.print_edit_info(node, out);
+            out.push_str(match node.origin.prop_type() {
+                LivePropType::Instance => " = ",
+                _ => ": "
+            });
+        }
+        self.print_value(nodes, index, depth, out)
+    }
+
+    // prints the value of a node, returns the index after it (and its children)
+    pub fn print_value(&self, nodes: &[LiveNode], index: usize, depth: usize, out: &mut String) -> usize {
+        match &nodes[index].value {
+            LiveValue::None => (),
+            LiveValue::Str(s) => print_string(s, out),
+            LiveValue::String(s) => print_string(s, out),
+            LiveValue::InlineString(s) => print_string(s.as_str(), out),
+            LiveValue::Dependency(s) => {
+                out.push_str("dep(");
+                print_string(s, out);
+                out.push(')');
+            }
+            LiveValue::Bool(v) => write!(out, "{}", v).unwrap(),
+            LiveValue::Int64(v) => write!(out, "{}", v).unwrap(),
+            LiveValue::Float32(v) => write!(out, "{:?}", v).unwrap(),
+            LiveValue::Float64(v) => write!(out, "{:?}", v).unwrap(),
+            LiveValue::Color(v) => write!(out, "#x{:08x}", v).unwrap(),
+            LiveValue::Vec2(v) => write!(out, "vec2({:?}, {:?})", v.x, v.y).unwrap(),
+            LiveValue::Vec3(v) => write!(out, "vec3({:?}, {:?}, {:?})", v.x, v.y, v.z).unwrap(),
+            LiveValue::Vec4(v) => write!(out, "vec4({:?}, {:?}, {:?}, {:?})", v.x, v.y, v.z, v.w).unwrap(),
+            LiveValue::Id(id) | LiveValue::BareEnum(id) => write!(out, "{}", id).unwrap(),
+            LiveValue::IdPath(path) => {
+                for (i, id) in path.iter().enumerate() {
+                    if i > 0 {
+                        out.push('.');
+                    }
+                    write!(out, "{}", id).unwrap();
+                }
+            }
+            LiveValue::Expr {..} => {
+                out.push('(');
+                let index = self.print_expr(nodes, index + 1, out);
+                out.push(')');
+                return index + 1
+            }
+            LiveValue::ExprBinOp(_) | LiveValue::ExprUnOp(_) | LiveValue::ExprMember(_) | LiveValue::ExprCall {..} => {
+                out.push('(');
+                let index = self.print_expr(nodes, index, out);
+                out.push(')');
+                return index
+            }
+            LiveValue::Array => {
+                out.push('[');
+                let index = self.print_list(nodes, index + 1, depth, out);
+                out.push(']');
+                return index
+            }
+            LiveValue::TupleEnum(variant) => {
+                write!(out, "{}(", variant).unwrap();
+                let index = self.print_list(nodes, index + 1, depth, out);
+                out.push(')');
+                return index
+            }
+            LiveValue::NamedEnum(variant) => {
+                write!(out, "{} {{", variant).unwrap();
+                let mut index = index + 1;
+                let mut first = true;
+                while index < nodes.len() && !nodes[index].value.is_close() {
+                    out.push_str(if first {""} else {","});
+                    first = false;
+                    out.push(' ');
+                    index = self.print_prop(nodes, index, depth, out);
+                }
+                out.push_str(if first {"}"} else {" }"});
+                return index + 1
+            }
+            LiveValue::Object | LiveValue::Root {..} => {
+                return self.print_body(nodes, index + 1, depth, out)
+            }
+            LiveValue::Clone(class) => {
+                if *class == live_id!(struct) {
+                    out.push_str("struct ");
+                }
+                else {
+                    write!(out, "<{}> ", class).unwrap();
+                }
+                return self.print_body(nodes, index + 1, depth, out)
+            }
+            LiveValue::Class {live_type, ..} => {
+                match self.live_type_infos.iter().find( | info | info.live_type == *live_type) {
+                    Some(info) => write!(out, "{{{{{}}}}} ", info.type_name).unwrap(),
+                    None => out.push_str("{{}} ")
+                }
+                return self.print_body(nodes, index + 1, depth, out)
+            }
+            LiveValue::DSL {token_start, token_count, ..} => {
+                self.print_tokens(*token_start as usize, *token_count as usize, depth, out);
+            }
+            LiveValue::Import(_) => {
+                return self.print_prop(nodes, index, depth, out)
+            }
+            LiveValue::Close => ()
+        }
+        index + 1
+    }
+
+    fn print_body(&self, nodes: &[LiveNode], mut index: usize, depth: usize, out: &mut String) -> usize {
+        if index < nodes.len() && nodes[index].value.is_close() {
+            out.push_str("{}");
+            return index + 1
+        }
+        out.push_str("{\n");
+        while index < nodes.len() && !nodes[index].value.is_close() {
+            if nodes[index].value == LiveValue::None {
+                index += 1;
+                continue
+            }
+            self.print_indent(depth + 1, out);
+            index = self.print_prop(nodes, index, depth + 1, out);
+            out.push('\n');
+        }
+        self.print_indent(depth, out);
+        out.push('}');
+        index + 1
+    }
+
+    fn print_list(&self, nodes: &[LiveNode], mut index: usize, depth: usize, out: &mut String) -> usize {
+        let mut first = true;
+        while index < nodes.len() && !nodes[index].value.is_close() {
+            if !first {
+                out.push_str(", ");
+            }
+            first = false;
+            index = self.print_value(nodes, index, depth, out);
+        }
+        index + 1
+    }
+
+    // expressions only get the parentheses the parser needs to build the same tree
+    fn print_expr(&self, nodes: &[LiveNode], index: usize, out: &mut String) -> usize {
+        match &nodes[index].value {
+            LiveValue::ExprBinOp(op) => {
+                let index = self.print_expr_operand(nodes, index + 1, bin_op_prec(*op), out);
+                write!(out, " {} ", bin_op_str(*op)).unwrap();
+                self.print_expr_operand(nodes, index, bin_op_prec(*op) + 1, out)
+            }
+            LiveValue::ExprUnOp(op) => {
+                out.push_str(match op {
+                    LiveUnOp::Not => "!",
+                    LiveUnOp::Neg => "-"
+                });
+                self.print_expr_operand(nodes, index + 1, usize::MAX, out)
+            }
+            LiveValue::ExprMember(ident) => {
+                let next = self.print_expr_operand(nodes, index + 1, usize::MAX, out);
+                write!(out, ".{}", ident).unwrap();
+                next
+            }
+            LiveValue::ExprCall {ident, args} => {
+                write!(out, "{}(", ident).unwrap();
+                let mut index = index + 1;
+                for i in 0..*args {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    index = self.print_expr(nodes, index, out);
+                }
+                out.push(')');
+                index
+            }
+            _ => self.print_value(nodes, index, 0, out)
+        }
+    }
+
+    fn print_expr_operand(&self, nodes: &[LiveNode], index: usize, min_prec: usize, out: &mut String) -> usize {
+        let prec = match nodes[index].value {
+            LiveValue::ExprBinOp(op) => bin_op_prec(op),
+            LiveValue::ExprUnOp(_) if min_prec == usize::MAX => 0,
+            _ => usize::MAX
+        };
+        if prec < min_prec {
+            out.push('(');
+            let index = self.print_expr(nodes, index, out);
+            out.push(')');
+            index
+        }
+        else {
+            self.print_expr(nodes, index, out)
+        }
+    }
+
+    fn print_edit_info(&self, node: &LiveNode, out: &mut String) {
+        let edit_info = match node.origin.edit_info() {
+            Some(edit_info) => edit_info,
+            None => return
+        };
+        let mut index = edit_info.edit_info_index() + 1;
+        out.push_str(".{");
+        let mut first = true;
+        while index < self.edit_info.len() && !self.edit_info[index].value.is_close() {
+            if !first {
+                out.push_str(", ");
+            }
+            first = false;
+            write!(out, "{}: ", self.edit_info[index].id).unwrap();
+            index = self.print_value(self.edit_info, index, 0, out);
+        }
+        out.push('}');
+    }
+
+    fn prefix_token(&self, node: &LiveNode) -> Option<LiveId> {
+        let token_index = node.origin.token_id()?.token_index();
+        if token_index == 0 {
+            return None
+        }
+        match self.tokens.get(token_index - 1)?.token {
+            LiveToken::Ident(id) => Some(id),
+            _ => None
+        }
+    }
+
+    // DSL blocks keep their original layout, reindented to depth
+    fn print_tokens(&self, token_start: usize, token_count: usize, depth: usize, out: &mut String) {
+        let tokens = match self.tokens.get(token_start..token_start + token_count) {
+            Some(tokens) if !tokens.is_empty() => tokens,
+            _ => return
+        };
+        let base_column = tokens[0].span.start.column;
+        let mut last = tokens[0].span;
+        for (i, token) in tokens.iter().enumerate() {
+            let span = token.span;
+            if i > 0 {
+                if span.start.line > last.end.line {
+                    for _ in 0..(span.start.line - last.end.line).min(2) {
+                        out.push('\n');
+                    }
+                    self.print_indent(depth, out);
+                    for _ in 0..span.start.column.saturating_sub(base_column) {
+                        out.push(' ');
+                    }
+                }
+                else if span.start.column > last.end.column {
+                    out.push(' ');
+                }
+            }
+            print_token(&token.token, out);
+            last = span;
+        }
+    }
+
+    fn print_indent(&self, depth: usize, out: &mut String) {
+        for _ in 0..depth {
+            out.push_str(self.indent);
+        }
+    }
+}
+
+fn print_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        if c == '"' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+pub(crate) fn print_token(token: &LiveToken, out: &mut String) {
+    match token {
+        LiveToken::Punct(id) => {
+            match PUNCTS.iter().find( | p | LiveId::from_str_unchecked(p) == *id) {
+                Some(p) => out.push_str(p),
+                None => write!(out, "{}", id).unwrap()
+            }
+        }
+        LiveToken::String(s) => print_string(s, out),
+        LiveToken::Float(v) => write!(out, "{:?}", v).unwrap(),
+        LiveToken::Color(v) => write!(out, "#x{:08x}", v).unwrap(),
+        LiveToken::Eof => (),
+        token => write!(out, "{}", token).unwrap()
+    }
+}
+
+fn bin_op_prec(op: LiveBinOp) -> usize {
+    match op {
+        LiveBinOp::Or => 1,
+        LiveBinOp::And => 2,
+        LiveBinOp::Eq | LiveBinOp::Ne => 3,
+        LiveBinOp::Lt | LiveBinOp::Le | LiveBinOp::Gt | LiveBinOp::Ge => 4,
+        LiveBinOp::Add | LiveBinOp::Sub => 5,
+        LiveBinOp::Mul | LiveBinOp::Div => 6,
+    }
+}
+
+fn bin_op_str(op: LiveBinOp) -> &'static str {
+    match op {
+        LiveBinOp::Or => "||",
+        LiveBinOp::And => "&&",
+        LiveBinOp::Eq => "==",
+        LiveBinOp::Ne => "!=",
+        LiveBinOp::Lt => "<",
+        LiveBinOp::Le => "<=",
+        LiveBinOp::Gt => ">",
+        LiveBinOp::Ge => ">=",
+        LiveBinOp::Add => "+",
+        LiveBinOp::Sub => "-",
+        LiveBinOp::Mul => "*",
+        LiveBinOp::Div => "/",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::any::TypeId,
+        super::*,
+        crate::{
+            span::TextPos,
+            live_ptr::{LiveFileId, LiveModuleId},
+            live_parser::LiveParser,
+            live_registry::LiveRegistry,
+        }
+    };
+
+    const SOURCE: &str = r#"
+        import makepad_draw::shader::std::*
+        import crate::theme::Button
+        Foo = {{Foo}} {
+            instance hover: 0.0
+            width: 10.5
+            height = 3
+            neg: -2.5
+            color: #f00
+            title: "say \"hi\""
+            icon: dep("crate://self/icon.svg")
+            size: vec2(1.0, 2.5)
+            pos: vec3(1, 2, 3)
+            rect: vec4(0.1, 0.2, 0.3, 1.0)
+            flag: true
+            walk: Fit
+            target: self.button
+            id_ref: my_value
+            layout: Padding {left: 1.0, top: 2}
+            margin: Inset(1.0, 2.0)
+            list: [1, 2.5, {a: 1}, Fit]
+            computed: (mix(a, b, 0.5) + -c * (d - 1.0) - (e - f) / -(g + h))
+            cond: (a > 1 && !b || c == 2 ? 1.0 : 2.0)
+            member: (a.b)
+            inner = <Button> {
+                text: "x"
+            }
+            <Button> {text: "anon"}
+            map: struct {a: 1}
+            obj: {}
+            key.{min: 0.0, max: 1.0}: 0.5
+            fn pixel(self) -> vec4 {
+                let x = self.pos.x * 2.0;
+                
+                return #f00;
+            }
+        }
+    "#;
+
+    fn parse(source: &str, live_type_infos: &[LiveTypeInfo]) -> LiveOriginal {
+        let file_id = LiveFileId::new(0);
+        let tokens = LiveRegistry::tokenize_from_str(source, TextPos::default(), file_id).unwrap();
+        let mut original = LiveParser::new(&tokens, live_type_infos, file_id).parse_live_document().unwrap();
+        original.tokens = tokens;
+        original
+    }
+
+    // origins and DSL token ranges differ between parses, everything else has to match
+    fn same_tree(a: &LiveOriginal, b: &LiveOriginal) -> bool {
+        a.nodes.len() == b.nodes.len() && a.nodes.iter().zip(b.nodes.iter()).all( | (a, b) | {
+            let same_value = match (&a.value, &b.value) {
+                (LiveValue::DSL {..}, LiveValue::DSL {..}) => true,
+                (a, b) => a == b
+            };
+            same_value
+                && (a.id == b.id || a.id.is_unique() && b.id.is_unique())
+                && a.origin.prop_type() == b.origin.prop_type()
+                && a.origin.node_has_prefix() == b.origin.node_has_prefix()
+                && a.origin.edit_info().is_some() == b.origin.edit_info().is_some()
+        })
+    }
+
+    #[test]
+    fn roundtrip() {
+        let live_type_infos = vec![LiveTypeInfo {
+            live_type: TypeId::of::<u32>(),
+            type_name: LiveId::from_str("Foo").unwrap(),
+            module_id: LiveModuleId::from_str("test::printer").unwrap(),
+            live_ignore: false,
+            fields: Vec::new()
+        }];
+        let original = parse(SOURCE, &live_type_infos);
+        let printed = original.to_live_design(&live_type_infos);
+        let reparsed = parse(&printed, &live_type_infos);
+        assert!(same_tree(&original, &reparsed), "{}", printed);
+        assert_eq!(reparsed.to_live_design(&live_type_infos), printed);
+        assert!(printed.contains("    instance hover: 0.0\n"), "{}", printed);
+        assert!(printed.contains("    computed: (mix(a, b, 0.5) + -c * (d - 1.0) - (e - f) / -(g + h))\n"), "{}", printed);
+        assert!(printed.contains("    fn pixel(self) -> vec4 {\n        let x = self.pos.x * 2.0;\n\n        return #xff0000ff;\n    }\n"), "{}", printed);
+    }
+}
@@ -383,6 +383,12 @@ impl LiveRegistry {
         self.live_files[token_id.file_id().unwrap().to_index()].original.token_id_to_span(token_id)
     }
     
+    // prints the (possibly edited) original nodes of a file back as live_design! source
+    pub fn file_id_to_live_design(&self, file_id: LiveFileId) -> String {
+        let live_file = &self.live_files[file_id.to_index()];
+        live_file.original.to_live_design(&live_file.live_type_infos)
+    }
+    
     pub fn tokenize_from_str(source: &str, start_pos: TextPos, file_id: LiveFileId) -> Result<Vec<TokenWithSpan>, LiveError> {
         let mut line_chars = Vec::new();
         let mut state = State::default();
@@ -485,7 +491,11 @@ impl LiveRegistry {
                                 live_tokens.push(TokenWithSpan {span: span, token: new_string});
                             }
                             else if let LiveToken::String (_) = &live_tokens[live_index].token {
-                                todo!();
+                                if live_tokens[live_index].token != new_string { // string value changed
+                                    live_tokens[live_index].token = new_string;
+                                    mutated_tokens.push(LiveTokenId::new(file_id, live_index));
+                                }
+                                live_tokens[live_index].span = span;
                             }
                             else { // cant replace a sttring type with something else without a reparse
                                 if !parse_changed {
@@ -619,7 +629,7 @@ impl LiveRegistry {
                 && live_tokens[token_index - 2].is_ident()
                 && live_tokens[token_index - 1].is_punct_id(live_id!(:));
             
-            if is_prop_assign || live_tokens[token_index].is_value_type() {
+            if token_index >= 2 && (is_prop_assign || live_tokens[token_index].is_value_type()) {
                 let token_id = LiveTokenId::new(file_id, token_index - 2);
                 
                 // ok lets scan for this one.
@@ -724,7 +734,9 @@ impl LiveRegistry {
                     if module_id.0 == live_id!(crate) { // patch up crate refs
                         module_id.0 = own_module_id.0
                     };
-                    deps.insert(*module_id);
+                    if *module_id != own_module_id {
+                        deps.insert(*module_id);
+                    }
                 }, // import
                 /*LiveValue::Registry(component_id) => {
                     let reg = self.components.0.borrow();
@@ -774,13 +786,17 @@ impl LiveRegistry {
         // alright so. we iterate
         let mut dep_order = Vec::new();
         
-        fn recur_insert_dep(parent_index: usize, dep_order: &mut Vec<LiveModuleId>, current: LiveModuleId, files: &Vec<LiveFile>) {
+        fn recur_insert_dep(parent_index: usize, dep_order: &mut Vec<LiveModuleId>, current: LiveModuleId, files: &Vec<LiveFile>, visiting: &mut Vec<LiveModuleId>) {
             let file = if let Some(file) = files.iter().find( | v | v.module_id == current) {
                 file
             }
             else {
                 return
             };
+            // circular imports, the expander reports what it cant find
+            if visiting.contains(&current) {
+                return
+            }
             let final_index = if let Some(index) = dep_order.iter().position( | v | *v == current) {
                 if index > parent_index { // insert before
                     dep_order.remove(index);
@@ -796,17 +812,19 @@ impl LiveRegistry {
                 parent_index
             };
             
+            visiting.push(current);
             for dep in &file.deps {
-                recur_insert_dep(final_index, dep_order, *dep, files);
+                recur_insert_dep(final_index, dep_order, *dep, files, visiting);
             }
+            visiting.pop();
         }
         
         for file in &self.live_files {
-            recur_insert_dep(dep_order.len(), &mut dep_order, file.module_id, &self.live_files);
+            recur_insert_dep(dep_order.len(), &mut dep_order, file.module_id, &self.live_files, &mut Vec::new());
         }
         
         // now lets do the recursive recompile parsing.
-        fn recur_check_reexpand(current: LiveModuleId, files: &Vec<LiveFile>) -> bool {
+        fn recur_check_reexpand(current: LiveModuleId, files: &Vec<LiveFile>, visited: &mut Vec<LiveModuleId>) -> bool {
             let file = if let Some(file) = files.iter().find( | v | v.module_id == current) {
                 file
             }
@@ -817,9 +835,13 @@ impl LiveRegistry {
             if file.reexpand {
                 return true;
             }
+            if visited.contains(&current) {
+                return false
+            }
+            visited.push(current);
             
             for dep in &file.deps {
-                if recur_check_reexpand(*dep, files) {
+                if recur_check_reexpand(*dep, files, visited) {
                     return true
                 }
             }
@@ -827,7 +849,7 @@ impl LiveRegistry {
         }
         
         for i in 0..self.live_files.len() {
-            if recur_check_reexpand(self.live_files[i].module_id, &self.live_files) {
+            if recur_check_reexpand(self.live_files[i].module_id, &self.live_files, &mut Vec::new()) {
                 self.live_files[i].reexpand = true;
             }
         }
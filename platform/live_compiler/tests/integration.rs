@@ -1,4 +1,167 @@
+use makepad_live_compiler::{
+    makepad_live_tokenizer::{Cursor, State, TokenWithLen, TokenPos, TokenRange},
+    LiveRegistry,
+    LiveModuleId,
+    LiveTypeInfo,
+    LiveFileError,
+    LiveEditEvent,
+    LiveNode,
+    LiveNodeSliceApi,
+    LiveIdAsProp,
+    LiveValue,
+    LiveProp,
+    LiveId,
+    TextPos,
+};
+
+struct Widget;
+
+fn widget_type_infos() -> Vec<LiveTypeInfo> {
+    vec![LiveTypeInfo {
+        live_type: std::any::TypeId::of::<Widget>(),
+        type_name: LiveId::from_str("Widget").unwrap(),
+        module_id: LiveModuleId::from_str("crate::widgets").unwrap(),
+        live_ignore: false,
+        fields: Vec::new()
+    }]
+}
+
+const WIDGETS: &str = "
+Widget = {{Widget}} {
+    width: 100.0
+    color: #f00
+    label: \"hello\"
+}
+";
+
+const APP: &str = "
+import crate::widgets::*
+App = <Widget> {
+    width: 200.0
+    child = <Widget> {color: #0f0}
+}
+";
+
+fn register(live_registry: &mut LiveRegistry, module: &str, source: &str) -> Result<(), LiveFileError> {
+    live_registry.register_live_file(
+        &format!("{}.rs", module),
+        "",
+        LiveModuleId::from_str(module).unwrap(),
+        source.to_string(),
+        widget_type_infos(),
+        TextPos::default()
+    ).map( | _ | ())
+}
+
+fn expand(modules: &[(&str, &str)]) -> (LiveRegistry, Vec<LiveFileError>) {
+    let mut live_registry = LiveRegistry::default();
+    for (module, source) in modules {
+        register(&mut live_registry, module, source).unwrap();
+    }
+    let mut errors = Vec::new();
+    live_registry.expand_all_documents(&mut errors);
+    let errors = errors.into_iter().map( | e | live_registry.live_error_to_live_file_error(e)).collect();
+    (live_registry, errors)
+}
+
+fn field(name: &str) -> LiveProp {LiveId::from_str(name).unwrap().as_field()}
+fn instance(name: &str) -> LiveProp {LiveId::from_str(name).unwrap().as_instance()}
+
+fn value<'a>(nodes: &'a [LiveNode], path: &[LiveProp]) -> &'a LiveValue {
+    let index = nodes.child_by_path(0, path).unwrap_or_else( || panic!("{:?} not found", path));
+    &nodes[index].value
+}
+
 #[test]
-fn main() {
-    // todo :)
-}
\ No newline at end of file
+fn import_and_inherit() {
+    let (live_registry, errors) = expand(&[("crate::widgets", WIDGETS), ("crate::app", APP)]);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let nodes = live_registry.module_id_to_expanded_nodes(LiveModuleId::from_str("crate::app").unwrap()).unwrap();
+    assert_eq!(value(nodes, &[instance("App"), field("width")]), &LiveValue::Float64(200.0));
+    assert_eq!(value(nodes, &[instance("App"), field("color")]), &LiveValue::Color(0xff0000ff));
+    let label = nodes.child_by_path(0, &[instance("App"), field("label")]).unwrap();
+    assert_eq!(live_registry.live_node_as_string(&nodes[label]).as_deref(), Some("hello"));
+    assert_eq!(value(nodes, &[instance("App"), instance("child"), field("width")]), &LiveValue::Float64(100.0));
+    assert_eq!(value(nodes, &[instance("App"), instance("child"), field("color")]), &LiveValue::Color(0x00ff00ff));
+}
+
+#[test]
+fn parse_error_span() {
+    let mut live_registry = LiveRegistry::default();
+    let err = register(&mut live_registry, "crate::broken", "\nBroken = {{Widget}} {\n    width: 1.0\n    height: }\n").unwrap_err();
+    assert_eq!(err.file, "crate::broken.rs");
+    assert_eq!((err.span.start.line, err.span.start.column), (3, 12));
+}
+
+#[test]
+fn expander_error_span() {
+    let (_, errors) = expand(&[("crate::widgets", WIDGETS), ("crate::app", "import crate::widgets::*\nApp = <Widget> {}\nOther = <Missing> {}\n")]);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].file, "crate::app.rs");
+    assert_eq!((errors[0].span.start.line, errors[0].span.start.column), (2, 0));
+
+    let (_, errors) = expand(&[("crate::app", "import crate::app::*\nApp = {}\n")]);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].message.contains("Import statement nothing found"), "{}", errors[0].message);
+}
+
+fn tokenize_lines(source: &str) -> (Vec<Vec<char>>, Vec<Vec<TokenWithLen>>) {
+    let mut state = State::default();
+    let mut scratch = String::new();
+    let mut all_chars = Vec::new();
+    let mut all_tokens = Vec::new();
+    for line in source.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut tokens = Vec::new();
+        let mut cursor = Cursor::new(&chars, &mut scratch);
+        loop {
+            let (next_state, token) = state.next(&mut cursor);
+            if let Some(token) = token {
+                tokens.push(token);
+            }
+            else {
+                break;
+            }
+            state = next_state;
+        }
+        all_chars.push(chars);
+        all_tokens.push(tokens);
+    }
+    (all_chars, all_tokens)
+}
+
+fn live_edit(live_registry: &mut LiveRegistry, source: &str) -> Option<LiveEditEvent> {
+    let (chars, tokens) = tokenize_lines(source);
+    let range = TokenRange {start: TokenPos {line: 0, index: 0}, end: TokenPos {line: tokens.len(), index: 0}};
+    live_registry.live_edit_file("crate::widgets.rs", range, | line | (&chars[line], &tokens[line])).unwrap()
+}
+
+#[test]
+fn live_edit_hot_patch() {
+    let (mut live_registry, errors) = expand(&[("crate::widgets", WIDGETS), ("crate::app", APP)]);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    // same tokens, no event
+    assert!(live_edit(&mut live_registry, WIDGETS).is_none());
+
+    // value edits are patched in place
+    match live_edit(&mut live_registry, &WIDGETS.replace("100.0", "150.0").replace("hello", "world")) {
+        Some(LiveEditEvent::Mutation {tokens, live_ptrs, ..}) => {
+            assert_eq!(tokens.len(), 2);
+            assert!(!live_ptrs.is_empty());
+        }
+        _ => panic!("expected a mutation")
+    }
+    let nodes = live_registry.module_id_to_expanded_nodes(LiveModuleId::from_str("crate::widgets").unwrap()).unwrap();
+    assert_eq!(value(nodes, &[instance("Widget"), field("width")]), &LiveValue::Float64(150.0));
+
+    // structural edits need a reparse
+    match live_edit(&mut live_registry, &WIDGETS.replace("width: 100.0", "height: 100.0")) {
+        Some(LiveEditEvent::ReparseDocument) => (),
+        _ => panic!("expected a reparse")
+    }
+    live_registry.process_next_originals_and_expand().unwrap();
+    let nodes = live_registry.module_id_to_expanded_nodes(LiveModuleId::from_str("crate::widgets").unwrap()).unwrap();
+    assert_eq!(value(nodes, &[instance("Widget"), field("height")]), &LiveValue::Float64(100.0));
+}
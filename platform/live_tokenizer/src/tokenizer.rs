@@ -525,7 +525,10 @@ impl DoubleQuotedStringTailState {
                         FullToken::String(Rc::new(s)),
                     );
                 }
-                ('\\', '"') => cursor.skip(2),
+                ('\\', '"') => {
+                    s.push('"');
+                    cursor.skip(2);
+                }
                 (x,_) => {
                     s.push(x);
                     cursor.skip(1);
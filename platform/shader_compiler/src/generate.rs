@@ -77,6 +77,37 @@ pub trait BackendWriter {
     fn write_ty_lit(&self, string: &mut String, ty_lit: TyLit);
     fn write_builtin_call_ident(&self, string: &mut String, ident: Ident, arg_exprs: &[Expr]);
     
+    // the hooks below default to C-style output, backends with a different
+    // declaration or statement syntax (WGSL) override them
+    
+    fn write_fn_def_open(&self, string: &mut String, ident: &dyn fmt::Display, return_ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, return_ty);
+        write!(string, "(").unwrap();
+    }
+    
+    fn write_fn_def_close(&self, string: &mut String, _return_ty: &Ty) {
+        write!(string, ") ").unwrap();
+    }
+    
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty) {
+        self.write_var_decl(string, "", false, false, ident, ty);
+    }
+    
+    fn write_for_loop_var(&self, string: &mut String, ident: Ident) {
+        write!(string, "int {}", ident).unwrap();
+    }
+    
+    fn cond_expr_is_select(&self) -> bool {
+        false
+    }
+    
+    fn assign_is_stmt(&self) -> bool {
+        false
+    }
+    
+    fn inout_is_pointer(&self) -> bool {
+        false
+    }
 }
 
 pub struct BlockGenerator<'a> {
@@ -181,6 +212,10 @@ pub fn generate_cons_fn(backend_writer:&dyn BackendWriter, string: &mut String,
     writeln!(string, "}}\n").unwrap();
 }
 
+fn is_assign_op(op: BinOp) -> bool {
+    matches!(op, BinOp::Assign | BinOp::AddAssign | BinOp::SubAssign | BinOp::MulAssign | BinOp::DivAssign)
+}
+
 impl<'a> BlockGenerator<'a> {
     pub fn generate_block(&mut self, block: &Block) {
         write!(self.string, "{{\n").unwrap();
@@ -285,9 +320,11 @@ impl<'a> BlockGenerator<'a> {
         } else {
             -1
         };
+        write!(self.string, "for (").unwrap();
+        self.backend_writer.write_for_loop_var(self.string, ident);
         write!(
             self.string,
-            "for (int {0} = {1}; {0} {2} {3}; {0} {4} {5}) ",
+            " = {1}; {0} {2} {3}; {0} {4} {5}) ",
             ident,
             if from <= to {from} else {from - 1},
             if from <= to {"<"} else {">="},
@@ -354,11 +391,8 @@ impl<'a> BlockGenerator<'a> {
         expr: &Option<Expr>,
         shadow: &Cell<Option<ScopeSymShadow >>
     ) {
-        self.backend_writer.write_var_decl(
+        self.backend_writer.write_let_decl(
             &mut self.string,
-            "",
-            false, 
-            false,
             &DisplayVarName(ident, shadow.get().unwrap()),
             ty.borrow().as_ref().unwrap()
         );
@@ -384,10 +418,46 @@ impl<'a> BlockGenerator<'a> {
     }
     
     fn generate_expr_stmt(&mut self, _span: TokenSpan, expr: &Expr) {
+        if self.backend_writer.assign_is_stmt() {
+            match &expr.kind {
+                ExprKind::Bin {op, ..} if is_assign_op(*op) => {
+                    self.generate_assign_stmt(expr);
+                }
+                ExprKind::PlainCall {..} | ExprKind::MethodCall {..} => {
+                    self.generate_expr(expr);
+                }
+                _ => {
+                    write!(self.string, "_ = ").unwrap();
+                    self.generate_expr(expr);
+                }
+            }
+            writeln!(self.string, ";").unwrap();
+            return
+        }
         self.generate_expr(expr);
         writeln!(self.string, ";").unwrap();
     }
     
+    // chained assignments (a = b = c) are split up into b = c; a = b
+    fn generate_assign_stmt(&mut self, expr: &Expr) {
+        if let ExprKind::Bin {op, left_expr, right_expr, ..} = &expr.kind {
+            match &right_expr.kind {
+                ExprKind::Bin {op: right_op, left_expr: right_left_expr, ..} if is_assign_op(*right_op) => {
+                    self.generate_assign_stmt(right_expr);
+                    write!(self.string, "; ").unwrap();
+                    self.generate_expr(left_expr);
+                    write!(self.string, " {} ", op).unwrap();
+                    self.generate_expr(right_left_expr);
+                }
+                _ => {
+                    self.generate_expr(left_expr);
+                    write!(self.string, " {} ", op).unwrap();
+                    self.generate_expr(right_expr);
+                }
+            }
+        }
+    }
+    
     fn generate_expr(&mut self, expr: &Expr) {
         ExprGenerator {
             closure_site_info: self.closure_site_info.clone(),
@@ -562,6 +632,16 @@ impl<'a> ExprGenerator<'a> {
         expr_if_true: &Expr,
         expr_if_false: &Expr,
     ) {
+        if self.backend_writer.cond_expr_is_select() {
+            write!(self.string, "select(").unwrap();
+            self.generate_expr(expr_if_false);
+            write!(self.string, ", ").unwrap();
+            self.generate_expr(expr_if_true);
+            write!(self.string, ", ").unwrap();
+            self.generate_expr(expr);
+            write!(self.string, ")").unwrap();
+            return
+        }
         write!(self.string, "(").unwrap();
         self.generate_expr(expr);
         write!(self.string, " ? ").unwrap();
@@ -706,8 +786,9 @@ impl<'a> ExprGenerator<'a> {
                 fn_def.ident
             )).unwrap();
             
+            let param_offset = fn_def.params.len() - arg_exprs.len();
             let mut sep = "";
-            for arg_expr in arg_exprs {
+            for (index, arg_expr) in arg_exprs.iter().enumerate() {
                 // check if the args is a closure, ifso skip it
                 match arg_expr.ty.borrow().as_ref().unwrap(){
                     Ty::ClosureDef(_)=>{
@@ -717,7 +798,7 @@ impl<'a> ExprGenerator<'a> {
                 }
                 
                 write!(self.string, "{}", sep).unwrap();
-                self.generate_expr(arg_expr);
+                self.generate_call_arg(&fn_def.params[index + param_offset], arg_expr);
                 sep = ", ";
             }
             // and now the closed over values
@@ -743,10 +824,11 @@ impl<'a> ExprGenerator<'a> {
         }
         else {
             write!(self.string, "{}_{} (", fn_def.fn_ptr, fn_def.ident).unwrap();
+            let param_offset = fn_def.params.len() - arg_exprs.len();
             let mut sep = "";
-            for arg_expr in arg_exprs {
+            for (index, arg_expr) in arg_exprs.iter().enumerate() {
                 write!(self.string, "{}", sep).unwrap();
-                self.generate_expr(arg_expr);
+                self.generate_call_arg(&fn_def.params[index + param_offset], arg_expr);
                 sep = ", ";
             }
 
@@ -756,6 +838,20 @@ impl<'a> ExprGenerator<'a> {
         }
     }
     
+    fn generate_call_arg(&mut self, param: &Param, arg_expr: &Expr) {
+        if param.is_inout && self.backend_writer.inout_is_pointer() {
+            write!(self.string, "&").unwrap();
+        }
+        self.generate_expr(arg_expr);
+    }
+    
+    fn is_inout_param(&self, ident: Ident, shadow: ScopeSymShadow) -> bool {
+        if let Some(fn_def) = self.fn_def {
+            return fn_def.params.iter().any( | param | param.is_inout && param.ident == ident && param.shadow.get() == Some(shadow))
+        }
+        false
+    }
+    
     fn generate_field_expr(&mut self, _span: TokenSpan, expr: &Expr, field_ident: Ident, ty:&Ty) {
         match expr.ty.borrow().as_ref() {
             Some(Ty::DrawShader(_)) => {
@@ -918,6 +1014,10 @@ impl<'a> ExprGenerator<'a> {
     fn generate_var_expr(&mut self, _span: TokenSpan, kind: &Cell<Option<VarKind >>, _ty: &Option<Ty>) {
         // ok so we have a few varkinds
         match kind.get().unwrap() {
+            VarKind::Local {ident, shadow} | VarKind::MutLocal {ident, shadow}
+                if self.backend_writer.inout_is_pointer() && self.is_inout_param(ident, shadow) => {
+                write!(self.string, "(*{})", DisplayVarName(ident, shadow)).unwrap();
+            }
             VarKind::Local {ident, shadow} => {
                 write!(self.string, "{}", DisplayVarName(ident, shadow)).unwrap();
             }
@@ -956,15 +1056,11 @@ pub struct FnDefGenerator<'a> {
 impl<'a> FnDefGenerator<'a> {
     pub fn generate_fn_def(&mut self) {
         
-        self.backend_writer.write_var_decl(
+        self.backend_writer.write_fn_def_open(
             &mut self.string,
-            "",
-            false,
-            false,
             &DisplayFnName(self.fn_def.fn_ptr, self.fn_def.ident), // here we must expand IdentPath to something
             self.fn_def.return_ty.borrow().as_ref().unwrap()
         );
-        write!(self.string, "(").unwrap();
         let mut sep = "";
         for param in &self.fn_def.params {
             if !param.shadow.get().is_none() {
@@ -981,7 +1077,7 @@ impl<'a> FnDefGenerator<'a> {
             }
         }
         self.backend_writer.write_fn_def_hidden_params(self.string, self.fn_def.hidden_args.borrow().as_ref().unwrap(), sep);
-        write!(self.string, ") ").unwrap();
+        self.backend_writer.write_fn_def_close(self.string, self.fn_def.return_ty.borrow().as_ref().unwrap());
         self.generate_block(&self.fn_def.block);
         writeln!(self.string).unwrap();
         //self.visited.insert(self.decl.ident_path);
@@ -1073,11 +1169,8 @@ impl<'a> FnDefWithClosureArgsGenerator<'a> {
     
     pub fn generate_fn_def_with_closure_args(&mut self) {
         
-        self.backend_writer.write_var_decl(
+        self.backend_writer.write_fn_def_open(
             &mut self.string,
-            "",
-            false,
-            false,
             &DisplayFnNameWithClosureArgs(
                 self.closure_site_info.site_index,
                 self.call_def.fn_ptr,
@@ -1085,7 +1178,6 @@ impl<'a> FnDefWithClosureArgsGenerator<'a> {
             ), // here we must expand IdentPath to something
             self.fn_def.return_ty.borrow().as_ref().unwrap()
         );
-        write!(self.string, "(").unwrap();
         let mut sep = "";
         for param in &self.fn_def.params {
             if !param.shadow.get().is_none() {
@@ -1120,8 +1212,7 @@ impl<'a> FnDefWithClosureArgsGenerator<'a> {
         merged_hidden_args.extend(self.fn_def.hidden_args.borrow().as_ref().unwrap().iter().cloned());
         merged_hidden_args.extend(self.call_def.hidden_args.borrow().as_ref().unwrap().iter().cloned());
         self.backend_writer.write_fn_def_hidden_params(self.string, &merged_hidden_args, sep);
-        
-        write!(self.string, ") ").unwrap();
+        self.backend_writer.write_fn_def_close(self.string, self.fn_def.return_ty.borrow().as_ref().unwrap());
         // alright so here the block is generated.. however
         // we need to know the names and the closed-over-args passthrough
         self.generate_block(&self.fn_def.block);
@@ -1166,15 +1257,11 @@ impl<'a> ClosureDefGenerator<'a> {
         
         if let TyExprKind::ClosureDecl {params, return_ty, ..} = &fn_param.ty_expr.kind {
             
-            self.backend_writer.write_var_decl(
+            self.backend_writer.write_fn_def_open(
                 &mut self.string,
-                "",
-                false,
-                false,
                 &DisplayClosureName(self.call_def.fn_ptr, self.closure_site_arg.closure_def_index), // here we must expand IdentPath to something
                 return_ty.borrow().as_ref().unwrap(),
             );
-            write!(self.string, "(").unwrap();
             
             // ok we have now params and names
             for (param_index, param) in params.iter().enumerate() {
@@ -1214,8 +1301,10 @@ impl<'a> ClosureDefGenerator<'a> {
         merged_hidden_args.extend(self.fn_def.hidden_args.borrow().as_ref().unwrap().iter().cloned());
         merged_hidden_args.extend(self.call_def.hidden_args.borrow().as_ref().unwrap().iter().cloned());
         self.backend_writer.write_fn_def_hidden_params(self.string, &merged_hidden_args, sep);
-        
-        writeln!(self.string, ") {{").unwrap();
+        if let TyExprKind::ClosureDecl {return_ty, ..} = &fn_param.ty_expr.kind {
+            self.backend_writer.write_fn_def_close(self.string, return_ty.borrow().as_ref().unwrap());
+        }
+        writeln!(self.string, "{{").unwrap();
         
         match &self.closure_def.kind {
             ClosureDefKind::Expr(expr) => {
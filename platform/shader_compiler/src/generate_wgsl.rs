@@ -0,0 +1,728 @@
+use{
+    std::{
+        fmt,
+        fmt::Write,
+        cell::RefCell,
+        collections::{BTreeSet,BTreeMap},
+    },
+    crate::{
+        makepad_live_id::{
+            live_id,
+            LiveId,
+        },
+        generate::*,
+        shader_ast::*,
+        shader_registry::ShaderRegistry
+    }
+};
+
+// WGSL has no hidden args or inout params, so geometries, instances and
+// varyings live in module scope private vars that vertex_main and pixel_main
+// fill in from the stage inputs. inout params become function pointers.
+
+pub fn generate_shader(draw_shader_def: &DrawShaderDef, const_table:&DrawShaderConstTable, shader_registry: &ShaderRegistry) -> String {
+    let mut string = String::new();
+    let backend_writer = WgslBackendWriter {
+        shader_registry,
+        draw_shader_def,
+        const_table,
+        polyfills: RefCell::new(BTreeMap::new())
+    };
+    DrawShaderGenerator {
+        draw_shader_def,
+        shader_registry,
+        string: &mut string,
+        const_table,
+        backend_writer: &backend_writer
+    }
+    .generate_shader();
+    for polyfill in backend_writer.polyfills.borrow().values() {
+        string.push_str(polyfill);
+    }
+    string
+}
+
+struct DrawShaderGenerator<'a> {
+    draw_shader_def: &'a DrawShaderDef,
+    shader_registry: &'a ShaderRegistry,
+    string: &'a mut String,
+    backend_writer: &'a dyn BackendWriter,
+    const_table: &'a DrawShaderConstTable
+}
+
+impl<'a> DrawShaderGenerator<'a> {
+    fn generate_shader(&mut self) {
+
+        // LiveId orders by interned string, so a BTreeSet lookup of a live_id! that
+        // was never interned isnt reliable. compare by value instead
+        let mut builtin_deps = Vec::new();
+        for fn_iter in self.draw_shader_def.all_fns.borrow().iter() {
+            let fn_def = self.shader_registry.all_fns.get(fn_iter).unwrap();
+            builtin_deps.extend(fn_def.builtin_deps.borrow().as_ref().unwrap().iter().cloned());
+        }
+
+        // textureSample and derivatives are only allowed in uniform control flow,
+        // the other backends dont have this restriction so we switch it off
+        let uses_sampler = builtin_deps.contains(&Ident(live_id!(sample2d))) || builtin_deps.contains(&Ident(live_id!(sample2d_rt)));
+        if uses_sampler || builtin_deps.contains(&Ident(live_id!(dFdx))) || builtin_deps.contains(&Ident(live_id!(dFdy))) {
+            writeln!(self.string, "diagnostic(off, derivative_uniformity);").unwrap();
+        }
+
+        self.generate_struct_decls();
+        let fields_as_uniform_blocks = self.draw_shader_def.fields_as_uniform_blocks();
+        self.generate_uniform_structs(&fields_as_uniform_blocks);
+        self.generate_texture_defs(uses_sampler);
+        if builtin_deps.contains(&Ident(live_id!(sample2d))) {
+            writeln!(self.string, "fn sample2d(tex: texture_2d<f32>, pos: vec2<f32>) -> vec4<f32> {{return textureSample(tex, default_texture_sampler, pos);}}").unwrap();
+        }
+        if builtin_deps.contains(&Ident(live_id!(sample2d_rt))) {
+            writeln!(self.string, "fn sample2d_rt(tex: texture_2d<f32>, pos: vec2<f32>) -> vec4<f32> {{return textureSample(tex, default_texture_sampler, pos);}}").unwrap();
+        }
+        self.generate_private_vars();
+        let geometry_locations = self.generate_geometry_struct();
+        let instance_locations = self.generate_instance_struct(geometry_locations);
+        self.generate_varying_struct();
+
+        let all_fns = self.draw_shader_def.all_fns.borrow();
+        for fn_iter in all_fns.iter().rev() {
+            let const_table_offset = self.const_table.offsets.get(fn_iter).cloned();
+            let fn_def = self.shader_registry.all_fns.get(fn_iter).unwrap();
+            if fn_def.has_closure_args() {
+                for call_iter in all_fns.iter().rev() {
+                    // any function that depends on us, will have the closures we need
+                    let call_def = self.shader_registry.all_fns.get(call_iter).unwrap();
+                    if call_def.callees.borrow().as_ref().unwrap().contains(fn_iter) {
+                        FnDefWithClosureArgsGenerator::generate_fn_def_with_all_closures(
+                            self.string,
+                            self.shader_registry,
+                            fn_def,
+                            call_def,
+                            self.backend_writer,
+                            const_table_offset
+                        );
+                    }
+                }
+                continue
+            }
+            FnDefGenerator {
+                fn_def,
+                const_table_offset,
+                shader_registry: self.shader_registry,
+                backend_writer: self.backend_writer,
+                string: self.string,
+            }
+            .generate_fn_def()
+        }
+        self.generate_vertex_main(geometry_locations > 0, instance_locations > geometry_locations);
+        self.generate_pixel_main();
+    }
+
+    fn generate_struct_decls(&mut self) {
+        for struct_ptr in self.draw_shader_def.all_structs.borrow().iter().rev() {
+            let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+            writeln!(self.string, "struct {} {{", struct_ptr).unwrap();
+            for field in &struct_def.fields {
+                write!(self.string, "    ").unwrap();
+                self.write_var_decl(
+                    &DisplayStructField(field.ident),
+                    field.ty_expr.ty.borrow().as_ref().unwrap(),
+                );
+                writeln!(self.string, ",").unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+        }
+    }
+
+    fn generate_uniform_structs(&mut self, fields_as_uniform_blocks: &BTreeMap<Ident, Vec<(usize, Ident) >>) {
+        // WGSL structs cant be empty
+        if !self.draw_shader_def.all_live_refs.borrow().is_empty() {
+            writeln!(self.string, "struct LiveUniforms {{").unwrap();
+            for (value_node_ptr, ty) in self.draw_shader_def.all_live_refs.borrow().iter() {
+                write!(self.string, "    ").unwrap();
+                self.write_var_decl(value_node_ptr, ty);
+                writeln!(self.string, ",").unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+            writeln!(self.string, "@group(0) @binding(0) var<uniform> live_uniforms: LiveUniforms;").unwrap();
+        }
+
+        writeln!(self.string, "@group(0) @binding(1) var<uniform> const_table: array<vec4<f32>, {}>;", self.const_table.table.len() >> 2).unwrap();
+
+        for (ident, vec) in fields_as_uniform_blocks {
+            let index = match ident.0{
+                live_id!(draw)=>2,
+                live_id!(pass)=>3,
+                live_id!(user)=>5,
+                live_id!(view)=>4,
+                _=>panic!()
+            };
+            writeln!(self.string, "struct Uniforms_{} {{", ident).unwrap();
+            for (index, _item) in vec {
+                let field = &self.draw_shader_def.fields[*index];
+                write!(self.string, "    ").unwrap();
+                self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap(),);
+                writeln!(self.string, ",").unwrap();
+            }
+            writeln!(self.string, "}}").unwrap();
+            writeln!(self.string, "@group(0) @binding({0}) var<uniform> uniforms_{1}: Uniforms_{1};", index, ident).unwrap();
+        }
+    }
+
+    fn generate_texture_defs(&mut self, uses_sampler: bool) {
+        if uses_sampler {
+            writeln!(self.string, "@group(1) @binding(0) var default_texture_sampler: sampler;").unwrap();
+        }
+        let mut index = 1;
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Texture {..} = field.kind {
+                assert_eq!(*field.ty_expr.ty.borrow().as_ref().unwrap(), Ty::Texture2D);
+                writeln!(self.string, "@group(1) @binding({}) var {}: texture_2d<f32>;", index, DisplayDsIdent(field.ident)).unwrap();
+                index += 1;
+            }
+        }
+    }
+
+    fn generate_private_vars(&mut self) {
+        for field in &self.draw_shader_def.fields {
+            match field.kind {
+                DrawShaderFieldKind::Geometry {..} |
+                DrawShaderFieldKind::Instance {..} |
+                DrawShaderFieldKind::Varying {..} => {
+                    write!(self.string, "var<private> ").unwrap();
+                    self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap());
+                    writeln!(self.string, ";").unwrap();
+                }
+                _ => ()
+            }
+        }
+    }
+
+    fn generate_geometry_struct(&mut self) -> usize {
+        let mut location = 0;
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Geometry {..} = field.kind {
+                if location == 0 {
+                    writeln!(self.string, "struct Geometries {{").unwrap();
+                }
+                write!(self.string, "    @location({}) ", location).unwrap();
+                self.write_var_decl(&DisplayDsIdent(field.ident), field.ty_expr.ty.borrow().as_ref().unwrap(),);
+                writeln!(self.string, ",").unwrap();
+                location += 1;
+            }
+        }
+        if location > 0 {
+            writeln!(self.string, "}}").unwrap();
+        }
+        location
+    }
+
+    fn generate_instance_struct(&mut self, first_location: usize) -> usize {
+        // the locations continue after the geometries as both are vertex inputs
+        let mut location = first_location;
+        for field in &self.draw_shader_def.fields {
+            if let DrawShaderFieldKind::Instance {..} = field.kind {
+                if location == first_location {
+                    writeln!(self.string, "struct Instances {{").unwrap();
+                }
+                location = self.write_located_field(location, field, false);
+            }
+        }
+        if location > first_location {
+            writeln!(self.string, "}}").unwrap();
+        }
+        location
+    }
+
+    fn generate_varying_struct(&mut self) {
+        writeln!(self.string, "struct Varyings {{").unwrap();
+        writeln!(self.string, "    @builtin(position) position: vec4<f32>,").unwrap();
+        let mut location = 0;
+        for field in &self.draw_shader_def.fields {
+            match &field.kind {
+                DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} |
+                DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} if is_used_in_pixel_shader.get() => {
+                    location = self.write_located_field(location, field, true);
+                }
+                DrawShaderFieldKind::Varying {..} => {
+                    location = self.write_located_field(location, field, true);
+                }
+                _ => {}
+            }
+        }
+        writeln!(self.string, "}}").unwrap();
+    }
+
+    fn write_located_field(&mut self, mut location: usize, field: &DrawShaderFieldDef, is_varying: bool) -> usize {
+        // matrices dont fit in one location so they are split up in columns
+        let ty = field.ty_expr.ty.borrow();
+        match ty.as_ref().unwrap() {
+            Ty::Mat4 => {
+                for i in 0..4 {
+                    write!(self.string, "    @location({}) {}{}: ", location, DisplayDsIdent(field.ident), i).unwrap();
+                    self.write_ty_lit(TyLit::Vec4);
+                    writeln!(self.string, ",").unwrap();
+                    location += 1;
+                }
+            },
+            Ty::Mat3 => {
+                for i in 0..3 {
+                    write!(self.string, "    @location({}) {}{}: ", location, DisplayDsIdent(field.ident), i).unwrap();
+                    self.write_ty_lit(TyLit::Vec3);
+                    writeln!(self.string, ",").unwrap();
+                    location += 1;
+                }
+            },
+            Ty::Mat2 => {
+                write!(self.string, "    @location({}) {}: ", location, DisplayDsIdent(field.ident)).unwrap();
+                self.write_ty_lit(TyLit::Vec4);
+                writeln!(self.string, ",").unwrap();
+                location += 1;
+            },
+            Ty::Float | Ty::Vec2 | Ty::Vec3 | Ty::Vec4 | Ty::Enum(_) => {
+                write!(self.string, "    @location({}) ", location).unwrap();
+                self.write_var_decl(&DisplayDsIdent(field.ident), ty.as_ref().unwrap());
+                writeln!(self.string, ",").unwrap();
+                location += 1;
+            },
+            Ty::Int | Ty::Ivec2 | Ty::Ivec3 | Ty::Ivec4 => {
+                write!(self.string, "    @location({}) ", location).unwrap();
+                if is_varying {
+                    write!(self.string, "@interpolate(flat) ").unwrap();
+                }
+                self.write_var_decl(&DisplayDsIdent(field.ident), ty.as_ref().unwrap());
+                writeln!(self.string, ",").unwrap();
+                location += 1;
+            },
+            x => panic!("unsupported type in write_located_field {:?}", x)
+        }
+        location
+    }
+
+    fn write_unpack_field(&mut self, prefix: &str, field: &DrawShaderFieldDef) {
+        let ident = DisplayDsIdent(field.ident);
+        match field.ty_expr.ty.borrow().as_ref().unwrap() {
+            Ty::Mat4 => {
+                writeln!(self.string, "    {0} = mat4x4<f32>({1}.{0}0, {1}.{0}1, {1}.{0}2, {1}.{0}3);", ident, prefix).unwrap();
+            }
+            Ty::Mat3 => {
+                writeln!(self.string, "    {0} = mat3x3<f32>({1}.{0}0, {1}.{0}1, {1}.{0}2);", ident, prefix).unwrap();
+            }
+            Ty::Mat2 => {
+                writeln!(self.string, "    {0} = mat2x2<f32>({1}.{0}.xy, {1}.{0}.zw);", ident, prefix).unwrap();
+            }
+            _ => {
+                writeln!(self.string, "    {0} = {1}.{0};", ident, prefix).unwrap();
+            }
+        }
+    }
+
+    fn write_pack_varying(&mut self, field: &DrawShaderFieldDef) {
+        let ident = DisplayDsIdent(field.ident);
+        match field.ty_expr.ty.borrow().as_ref().unwrap() {
+            Ty::Mat4 => {
+                for i in 0..4 {
+                    writeln!(self.string, "    varyings.{0}{1} = {0}[{1}];", ident, i).unwrap();
+                }
+            }
+            Ty::Mat3 => {
+                for i in 0..3 {
+                    writeln!(self.string, "    varyings.{0}{1} = {0}[{1}];", ident, i).unwrap();
+                }
+            }
+            Ty::Mat2 => {
+                writeln!(self.string, "    varyings.{0} = vec4<f32>({0}[0], {0}[1]);", ident).unwrap();
+            }
+            _ => {
+                writeln!(self.string, "    varyings.{0} = {0};", ident).unwrap();
+            }
+        }
+    }
+
+    fn generate_vertex_main(&mut self, has_geometries: bool, has_instances: bool) {
+        writeln!(self.string, "@vertex").unwrap();
+        write!(self.string, "fn vertex_main(").unwrap();
+        let mut sep = "";
+        if has_geometries {
+            write!(self.string, "geometries: Geometries").unwrap();
+            sep = ", ";
+        }
+        if has_instances {
+            write!(self.string, "{}instances: Instances", sep).unwrap();
+        }
+        writeln!(self.string, ") -> Varyings {{").unwrap();
+
+        for field in &self.draw_shader_def.fields {
+            match &field.kind {
+                DrawShaderFieldKind::Geometry {..} => self.write_unpack_field("geometries", field),
+                DrawShaderFieldKind::Instance {..} => self.write_unpack_field("instances", field),
+                _ => ()
+            }
+        }
+        writeln!(self.string, "    var varyings: Varyings;").unwrap();
+
+        let vertex_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(live_id!(vertex))).unwrap();
+        write!(self.string, "    varyings.position = {}", DisplayFnName(vertex_def.fn_ptr, vertex_def.ident)).unwrap();
+        write!(self.string, "(").unwrap();
+        self.backend_writer.write_call_expr_hidden_args(self.string, vertex_def.hidden_args.borrow().as_ref().unwrap(), "");
+        writeln!(self.string, ");").unwrap();
+
+        for field in &self.draw_shader_def.fields {
+            match &field.kind {
+                DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} |
+                DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} if is_used_in_pixel_shader.get() => {
+                    self.write_pack_varying(field);
+                }
+                DrawShaderFieldKind::Varying {..} => {
+                    self.write_pack_varying(field);
+                }
+                _ => {}
+            }
+        }
+        writeln!(self.string, "    return varyings;").unwrap();
+        writeln!(self.string, "}}").unwrap();
+    }
+
+    fn generate_pixel_main(&mut self) {
+        writeln!(self.string, "@fragment").unwrap();
+        writeln!(self.string, "fn pixel_main(varyings: Varyings) -> @location(0) vec4<f32> {{").unwrap();
+        for field in &self.draw_shader_def.fields {
+            match &field.kind {
+                DrawShaderFieldKind::Geometry {is_used_in_pixel_shader, ..} |
+                DrawShaderFieldKind::Instance {is_used_in_pixel_shader, ..} if is_used_in_pixel_shader.get() => {
+                    self.write_unpack_field("varyings", field);
+                }
+                DrawShaderFieldKind::Varying {..} => {
+                    self.write_unpack_field("varyings", field);
+                }
+                _ => {}
+            }
+        }
+        let pixel_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(live_id!(pixel))).unwrap();
+        write!(self.string, "    return {}", DisplayFnName(pixel_def.fn_ptr, pixel_def.ident)).unwrap();
+        write!(self.string, "(").unwrap();
+        self.backend_writer.write_call_expr_hidden_args(self.string, pixel_def.hidden_args.borrow().as_ref().unwrap(), "");
+        writeln!(self.string, ");").unwrap();
+        writeln!(self.string, "}}").unwrap();
+    }
+
+    fn write_var_decl(&mut self, ident: &dyn fmt::Display, ty: &Ty) {
+        self.backend_writer.write_var_decl(self.string, "", false, false, ident, ty);
+    }
+
+    fn write_ty_lit(&mut self, ty_lit: TyLit) {
+        self.backend_writer.write_ty_lit(self.string, ty_lit);
+    }
+}
+
+struct WgslBackendWriter<'a> {
+    pub shader_registry: &'a ShaderRegistry,
+    pub draw_shader_def: &'a DrawShaderDef,
+    pub const_table: &'a DrawShaderConstTable,
+    // helper fns for GLSL builtins WGSL doesnt have, keyed by name
+    pub polyfills: RefCell<BTreeMap<String, String>>
+}
+
+impl<'a> WgslBackendWriter<'a> {
+    fn write_ty(&self, string: &mut String, ty: &Ty) {
+        match ty {
+            Ty::Array {elem_ty, len} => {
+                write!(string, "array<").unwrap();
+                self.write_ty(string, elem_ty);
+                write!(string, ", {}>", len).unwrap();
+            }
+            Ty::Struct(struct_ptr) => {
+                write!(string, "{}", struct_ptr).unwrap();
+            }
+            Ty::Enum(_) => {
+                self.write_ty_lit(string, TyLit::Float);
+            }
+            ty => {
+                self.write_ty_lit(string, ty.maybe_ty_lit().unwrap());
+            }
+        }
+    }
+
+    fn ty_to_string(&self, ty: &Ty) -> String {
+        let mut string = String::new();
+        self.write_ty(&mut string, ty);
+        string
+    }
+
+    fn add_polyfill(&self, string: &mut String, name: String, params: &[Ty], return_ty: &Ty, body: &str) {
+        write!(string, "{}", name).unwrap();
+        let mut polyfills = self.polyfills.borrow_mut();
+        if polyfills.contains_key(&name) {
+            return
+        }
+        let mut def = format!("fn {}(", name);
+        for (index, param) in params.iter().enumerate() {
+            write!(def, "{}a{}: {}", if index == 0 {""} else {", "}, index, self.ty_to_string(param)).unwrap();
+        }
+        writeln!(def, ") -> {} {{", self.ty_to_string(return_ty)).unwrap();
+        writeln!(def, "{}", body).unwrap();
+        writeln!(def, "}}").unwrap();
+        polyfills.insert(name, def);
+    }
+}
+
+fn polyfill_name(ident: Ident, arg_tys: &[Ty]) -> String {
+    let mut name = format!("{}", ident);
+    for ty in arg_tys {
+        write!(name, "_{}", ty).unwrap();
+    }
+    name
+}
+
+fn bool_vec_for(ty: &Ty) -> Ty {
+    match ty {
+        Ty::Vec2 | Ty::Ivec2 | Ty::Bvec2 => Ty::Bvec2,
+        Ty::Vec3 | Ty::Ivec3 | Ty::Bvec3 => Ty::Bvec3,
+        Ty::Vec4 | Ty::Ivec4 | Ty::Bvec4 => Ty::Bvec4,
+        _ => Ty::Bool
+    }
+}
+
+impl<'a> BackendWriter for WgslBackendWriter<'a> {
+
+    fn get_struct_cons_type(&self) -> StructConsType {
+        StructConsType::Paren
+    }
+
+    fn enum_is_float(&self)->bool{
+        true
+    }
+
+    fn needs_mul_fn_for_matrix_multiplication(&self) -> bool {
+        false
+    }
+
+    fn needs_unpack_for_matrix_multiplication(&self) -> bool {
+        false
+    }
+
+    fn const_table_is_vec4(&self) -> bool {
+        true
+    }
+
+    fn use_cons_fn(&self, _what: &str) -> bool {
+        false
+    }
+
+    fn write_var_decl(
+        &self,
+        string: &mut String,
+        sep: &'static str,
+        is_inout: bool,
+        _is_packed: bool,
+        ident: &dyn fmt::Display,
+        ty: &Ty,
+    ) -> bool {
+        match ty {
+            Ty::Void | Ty::DrawShader(_) | Ty::ClosureDef {..} | Ty::ClosureDecl => {
+                return false
+            }
+            _ => ()
+        }
+        write!(string, "{}{}: ", sep, ident).unwrap();
+        if is_inout {
+            write!(string, "ptr<function, ").unwrap();
+            self.write_ty(string, ty);
+            write!(string, ">").unwrap();
+        }
+        else {
+            self.write_ty(string, ty);
+        }
+        true
+    }
+
+    fn write_fn_def_open(&self, string: &mut String, ident: &dyn fmt::Display, _return_ty: &Ty) {
+        write!(string, "fn {}(", ident).unwrap();
+    }
+
+    fn write_fn_def_close(&self, string: &mut String, return_ty: &Ty) {
+        if let Ty::Void = return_ty {
+            write!(string, ") ").unwrap();
+        }
+        else {
+            write!(string, ") -> ").unwrap();
+            self.write_ty(string, return_ty);
+            write!(string, " ").unwrap();
+        }
+    }
+
+    fn write_let_decl(&self, string: &mut String, ident: &dyn fmt::Display, ty: &Ty) {
+        write!(string, "var ").unwrap();
+        self.write_var_decl(string, "", false, false, ident, ty);
+    }
+
+    fn write_for_loop_var(&self, string: &mut String, ident: Ident) {
+        write!(string, "var {}: i32", ident).unwrap();
+    }
+
+    fn cond_expr_is_select(&self) -> bool {
+        true
+    }
+
+    fn assign_is_stmt(&self) -> bool {
+        true
+    }
+
+    fn inout_is_pointer(&self) -> bool {
+        true
+    }
+
+    fn write_call_expr_hidden_args(&self, _string: &mut String, _hidden_args: &BTreeSet<HiddenArgKind >, _sep: &str) {
+    }
+
+    fn write_fn_def_hidden_params(&self, _string: &mut String, _hidden_args: &BTreeSet<HiddenArgKind >, _sep: &str) {
+    }
+
+    fn generate_live_value_prefix(&self, string: &mut String) {
+        write!(string, "live_uniforms.").unwrap();
+    }
+
+    fn generate_draw_shader_field_expr(&self, string: &mut String, field_ident: Ident, _ty: &Ty) {
+        let field_def = self.draw_shader_def.find_field(field_ident).unwrap();
+        if let DrawShaderFieldKind::Uniform {block_ident, ..} = &field_def.kind {
+            write!(string, "uniforms_{}.", block_ident).unwrap();
+        }
+        write!(string, "{}", &DisplayDsIdent(field_ident)).unwrap();
+    }
+
+    fn write_ty_lit(&self, string: &mut String, ty_lit: TyLit) {
+        write!(
+            string,
+            "{}",
+            match ty_lit {
+                TyLit::Bool => "bool",
+                TyLit::Int => "i32",
+                TyLit::Float => "f32",
+                TyLit::Bvec2 => "vec2<bool>",
+                TyLit::Bvec3 => "vec3<bool>",
+                TyLit::Bvec4 => "vec4<bool>",
+                TyLit::Ivec2 => "vec2<i32>",
+                TyLit::Ivec3 => "vec3<i32>",
+                TyLit::Ivec4 => "vec4<i32>",
+                TyLit::Vec2 => "vec2<f32>",
+                TyLit::Vec3 => "vec3<f32>",
+                TyLit::Vec4 => "vec4<f32>",
+                TyLit::Mat2 => "mat2x2<f32>",
+                TyLit::Mat3 => "mat3x3<f32>",
+                TyLit::Mat4 => "mat4x4<f32>",
+                TyLit::Texture2D => "texture_2d<f32>",
+            }
+        )
+            .unwrap();
+    }
+
+    fn write_builtin_call_ident(&self, string: &mut String, ident: Ident, arg_exprs: &[Expr]) {
+        let arg_tys: Vec<Ty> = arg_exprs.iter().map( | arg_expr | arg_expr.ty.borrow().as_ref().unwrap().clone()).collect();
+        match ident {
+            Ident(live_id!(atan)) => {
+                if arg_exprs.len() == 2 {
+                    write!(string, "atan2").unwrap();
+                }
+                else {
+                    write!(string, "atan").unwrap();
+                }
+            }
+            Ident(live_id!(dFdx)) => {
+                write!(string, "dpdx").unwrap();
+            }
+            Ident(live_id!(dFdy)) => {
+                write!(string, "dpdy").unwrap();
+            }
+            Ident(live_id!(inversesqrt)) => {
+                write!(string, "inverseSqrt").unwrap();
+            }
+            Ident(live_id!(mod)) => {
+                // GLSL mod rounds towards negative infinity, WGSL % truncates
+                self.add_polyfill(string, polyfill_name(ident, &arg_tys), &arg_tys, &arg_tys[0], "    return a0 - a1 * floor(a0 / a1);");
+            }
+            Ident(live_id!(lessThan)) |
+            Ident(live_id!(lessThanEqual)) |
+            Ident(live_id!(greaterThan)) |
+            Ident(live_id!(greaterThanEqual)) |
+            Ident(live_id!(equal)) |
+            Ident(live_id!(notEqual)) => {
+                let op = match ident.0 {
+                    live_id!(lessThan) => "<",
+                    live_id!(lessThanEqual) => "<=",
+                    live_id!(greaterThan) => ">",
+                    live_id!(greaterThanEqual) => ">=",
+                    live_id!(equal) => "==",
+                    _ => "!=",
+                };
+                let body = format!("    return a0 {} a1;", op);
+                self.add_polyfill(string, polyfill_name(ident, &arg_tys), &arg_tys, &bool_vec_for(&arg_tys[0]), &body);
+            }
+            Ident(live_id!(not)) => {
+                self.add_polyfill(string, polyfill_name(ident, &arg_tys), &arg_tys, &arg_tys[0], "    return !a0;");
+            }
+            Ident(live_id!(matrixCompMult)) => {
+                let columns = match arg_tys[0] {
+                    Ty::Mat2 => 2,
+                    Ty::Mat3 => 3,
+                    _ => 4
+                };
+                let mut body = format!("    return {}(", self.ty_to_string(&arg_tys[0]));
+                for i in 0..columns {
+                    write!(body, "{}a0[{1}] * a1[{1}]", if i == 0 {""} else {", "}, i).unwrap();
+                }
+                body.push_str(");");
+                self.add_polyfill(string, polyfill_name(ident, &arg_tys), &arg_tys, &arg_tys[0], &body);
+            }
+            Ident(live_id!(inverse)) => {
+                let body = match arg_tys[0] {
+                    Ty::Mat2 => "    return mat2x2<f32>(a0[1][1], -a0[0][1], -a0[1][0], a0[0][0]) * (1.0 / determinant(a0));",
+                    Ty::Mat3 => concat!(
+                        "    let c0 = cross(a0[1], a0[2]);\n",
+                        "    let c1 = cross(a0[2], a0[0]);\n",
+                        "    let c2 = cross(a0[0], a0[1]);\n",
+                        "    return transpose(mat3x3<f32>(c0, c1, c2)) * (1.0 / dot(a0[0], c0));"
+                    ),
+                    _ => concat!(
+                        "    let a = a0[0].xyz; let b = a0[1].xyz; let c = a0[2].xyz; let d = a0[3].xyz;\n",
+                        "    let x = a0[0].w; let y = a0[1].w; let z = a0[2].w; let w = a0[3].w;\n",
+                        "    var s = cross(a, b); var t = cross(c, d);\n",
+                        "    var u = a * y - b * x; var v = c * w - d * z;\n",
+                        "    let inv_det = 1.0 / (dot(s, v) + dot(t, u));\n",
+                        "    s *= inv_det; t *= inv_det; u *= inv_det; v *= inv_det;\n",
+                        "    return transpose(mat4x4<f32>(\n",
+                        "        vec4<f32>(cross(b, v) + t * y, -dot(b, t)),\n",
+                        "        vec4<f32>(cross(v, a) - t * x, dot(a, t)),\n",
+                        "        vec4<f32>(cross(d, u) + s * w, -dot(d, s)),\n",
+                        "        vec4<f32>(cross(u, c) - s * z, dot(c, s))\n",
+                        "    ));"
+                    )
+                };
+                self.add_polyfill(string, polyfill_name(ident, &arg_tys), &arg_tys, &arg_tys[0], body);
+            }
+            Ident(live_id!(min)) |
+            Ident(live_id!(max)) |
+            Ident(live_id!(clamp)) |
+            Ident(live_id!(step)) |
+            Ident(live_id!(smoothstep)) if arg_tys.contains(&Ty::Float) && arg_tys.iter().any( | ty | *ty != Ty::Float) => {
+                // GLSL allows mixing a vector with scalars here, WGSL wants them splatted
+                let vec_ty = arg_tys.iter().find( | ty | **ty != Ty::Float).unwrap().clone();
+                let mut body = format!("    return {}(", ident);
+                for (index, ty) in arg_tys.iter().enumerate() {
+                    if index != 0 {
+                        body.push_str(", ");
+                    }
+                    if *ty == Ty::Float {
+                        write!(body, "{}(a{})", self.ty_to_string(&vec_ty), index).unwrap();
+                    }
+                    else {
+                        write!(body, "a{}", index).unwrap();
+                    }
+                }
+                body.push_str(");");
+                self.add_polyfill(string, polyfill_name(ident, &arg_tys), &arg_tys, &vec_ty, &body);
+            }
+            _ => {
+                write!(string, "{}", ident).unwrap()
+            }
+        }
+    }
+}
@@ -0,0 +1,1236 @@
+// A CPU interpreter over the type checked shader AST, so draw shaders can be
+// evaluated without a GPU (headless rendering, unit tests on pixel colors).
+//
+// Every value is evaluated for LANES pixels in lockstep: the pixel itself, its
+// right neighbour and the one below it. That way dFdx/dFdy are plain
+// differences between lanes, like the 2x2 quads a GPU uses. Control flow
+// follows lane 0, which is what a GPU does for uniform branches and close
+// enough for the smooth functions draw shaders branch on.
+
+use {
+    std::{
+        collections::HashMap,
+        rc::Rc,
+    },
+    crate::{
+        makepad_live_compiler::{
+            LiveRegistry,
+            LiveValue,
+            LiveError,
+            LiveErrorOrigin,
+            live_error_origin,
+            TokenSpan,
+        },
+        makepad_live_id::*,
+        makepad_math::*,
+        shader_ast::*,
+        shader_registry::ShaderRegistry,
+        swizzle::Swizzle,
+    }
+};
+
+pub const LANES: usize = 3;
+
+/// A number, vector or matrix, stored as up to 16 floats per lane. Bools are
+/// 0.0 or 1.0, ints are truncated floats and matrices are column major.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Num {
+    pub ty: TyLit,
+    pub lanes: [[f32; 16]; LANES],
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Void,
+    Num(Num),
+    Struct(Vec<Value>),
+    Array(Vec<Value>),
+    Texture(Ident),
+    Closure(Rc<Closure>),
+    DrawShader,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    fn_ptr: FnPtr,
+    closure_def_index: ClosureDefIndex,
+    closed_over: Vec<((Ident, ScopeSymShadow), Value)>,
+}
+
+/// A texture bound to a draw shader field, sampled nearest with clamp to edge.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderTexture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec4>,
+}
+
+pub struct ShaderInterpreter<'a> {
+    live_registry: &'a LiveRegistry,
+    shader_registry: &'a ShaderRegistry,
+    draw_shader_def: &'a DrawShaderDef,
+    fields: HashMap<Ident, Value>,
+    textures: HashMap<Ident, &'a ShaderTexture>,
+}
+
+struct Frame<'a> {
+    fn_def: &'a FnDef,
+    locals: HashMap<(Ident, ScopeSymShadow), Value>,
+    loop_vars: Vec<(Ident, Value)>,
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+fn ty_lit_slots(ty: TyLit) -> usize {
+    match ty {
+        TyLit::Bool | TyLit::Int | TyLit::Float => 1,
+        TyLit::Bvec2 | TyLit::Ivec2 | TyLit::Vec2 => 2,
+        TyLit::Bvec3 | TyLit::Ivec3 | TyLit::Vec3 => 3,
+        TyLit::Bvec4 | TyLit::Ivec4 | TyLit::Vec4 | TyLit::Mat2 => 4,
+        TyLit::Mat3 => 9,
+        TyLit::Mat4 => 16,
+        TyLit::Texture2D => 0,
+    }
+}
+
+fn mat_dim(ty: TyLit) -> Option<usize> {
+    match ty {
+        TyLit::Mat2 => Some(2),
+        TyLit::Mat3 => Some(3),
+        TyLit::Mat4 => Some(4),
+        _ => None
+    }
+}
+
+fn vec_ty(len: usize) -> TyLit {
+    match len {
+        1 => TyLit::Float,
+        2 => TyLit::Vec2,
+        3 => TyLit::Vec3,
+        _ => TyLit::Vec4,
+    }
+}
+
+fn bool_ty(len: usize) -> TyLit {
+    match len {
+        1 => TyLit::Bool,
+        2 => TyLit::Bvec2,
+        3 => TyLit::Bvec3,
+        _ => TyLit::Bvec4,
+    }
+}
+
+fn is_bool_ty(ty: TyLit) -> bool {
+    matches!(ty, TyLit::Bool | TyLit::Bvec2 | TyLit::Bvec3 | TyLit::Bvec4)
+}
+
+fn is_int_ty(ty: TyLit) -> bool {
+    matches!(ty, TyLit::Int | TyLit::Ivec2 | TyLit::Ivec3 | TyLit::Ivec4)
+}
+
+fn from_bool(b: bool) -> f32 {
+    if b {1.0} else {0.0}
+}
+
+impl Num {
+    pub fn from_slots(ty: TyLit, slots: &[f32]) -> Num {
+        let mut lane = [0.0; 16];
+        for (i, v) in slots.iter().take(ty_lit_slots(ty)).enumerate() {
+            lane[i] = *v;
+        }
+        Num {ty, lanes: [lane; LANES]}
+    }
+
+    pub fn zero(ty: TyLit) -> Num {
+        Num {ty, lanes: [[0.0; 16]; LANES]}
+    }
+
+    pub fn len(&self) -> usize {
+        ty_lit_slots(self.ty)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The components of one lane.
+    pub fn lane(&self, lane: usize) -> &[f32] {
+        &self.lanes[lane][0..self.len()]
+    }
+
+    // component i, scalars broadcast over every component
+    fn comp(&self, lane: usize, i: usize) -> f32 {
+        if self.len() == 1 {self.lanes[lane][0]} else {self.lanes[lane][i]}
+    }
+
+    fn truthy(&self) -> bool {
+        self.lanes[0][0] != 0.0
+    }
+
+    fn per_lane(ty: TyLit, f: impl Fn(usize) -> [f32; 16]) -> Num {
+        Num {ty, lanes: [f(0), f(1), f(2)]}
+    }
+
+    fn map(&self, f: impl Fn(f32) -> f32) -> Num {
+        let n = self.len();
+        Num::per_lane(self.ty, | l | {
+            let mut r = [0.0; 16];
+            for (i, out) in r[0..n].iter_mut().enumerate() {
+                *out = f(self.lanes[l][i]);
+            }
+            r
+        })
+    }
+
+    fn zip(&self, other: &Num, f: impl Fn(f32, f32) -> f32) -> Num {
+        let ty = if self.len() >= other.len() {self.ty} else {other.ty};
+        let n = ty_lit_slots(ty);
+        Num::per_lane(ty, | l | {
+            let mut r = [0.0; 16];
+            for (i, out) in r[0..n].iter_mut().enumerate() {
+                *out = f(self.comp(l, i), other.comp(l, i));
+            }
+            r
+        })
+    }
+
+    fn zip3(&self, b: &Num, c: &Num, f: impl Fn(f32, f32, f32) -> f32) -> Num {
+        let ty = [self.ty, b.ty, c.ty].iter().copied().max_by_key( | ty | ty_lit_slots(*ty)).unwrap();
+        let n = ty_lit_slots(ty);
+        Num::per_lane(ty, | l | {
+            let mut r = [0.0; 16];
+            for (i, out) in r[0..n].iter_mut().enumerate() {
+                *out = f(self.comp(l, i), b.comp(l, i), c.comp(l, i));
+            }
+            r
+        })
+    }
+
+    fn reduce(&self, ty: TyLit, f: impl Fn(&[f32]) -> f32) -> Num {
+        Num::per_lane(ty, | l | {
+            let mut r = [0.0; 16];
+            r[0] = f(self.lane(l));
+            r
+        })
+    }
+
+    fn cast(&self, ty: TyLit) -> Num {
+        if is_bool_ty(ty) {
+            Num {ty, ..self.map( | x | from_bool(x != 0.0))}
+        }
+        else if is_int_ty(ty) {
+            Num {ty, ..self.map( | x | x.trunc())}
+        }
+        else {
+            Num {ty, ..*self}
+        }
+    }
+}
+
+impl Value {
+    pub fn float(v: f32) -> Value {
+        Value::Num(Num::from_slots(TyLit::Float, &[v]))
+    }
+
+    pub fn vec2(v: Vec2) -> Value {
+        Value::Num(Num::from_slots(TyLit::Vec2, &[v.x, v.y]))
+    }
+
+    pub fn vec3(v: Vec3) -> Value {
+        Value::Num(Num::from_slots(TyLit::Vec3, &[v.x, v.y, v.z]))
+    }
+
+    pub fn vec4(v: Vec4) -> Value {
+        Value::Num(Num::from_slots(TyLit::Vec4, &[v.x, v.y, v.z, v.w]))
+    }
+
+    pub fn mat4(m: Mat4) -> Value {
+        Value::Num(Num::from_slots(TyLit::Mat4, &m.v))
+    }
+
+    /// Builds a value from the flat f32 slots a draw call stores it in, as laid
+    /// out in instance and uniform buffers.
+    pub fn from_slots(ty: &Ty, slots: &[f32]) -> Option<Value> {
+        match ty {
+            Ty::Enum(_) => Some(Value::Num(Num::from_slots(TyLit::Float, slots))),
+            Ty::Texture2D => None,
+            ty => ty.maybe_ty_lit().map( | ty_lit | Value::Num(Num::from_slots(ty_lit, slots)))
+        }
+    }
+
+    pub fn as_num(&self) -> Option<&Num> {
+        if let Value::Num(num) = self {Some(num)} else {None}
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        self.as_num().map( | num | num.lanes[0][0])
+    }
+
+    pub fn as_vec2(&self) -> Option<Vec2> {
+        self.as_num().map( | num | vec2(num.lanes[0][0], num.lanes[0][1]))
+    }
+
+    pub fn as_vec4(&self) -> Option<Vec4> {
+        self.as_num().map( | num | {
+            let v = &num.lanes[0];
+            vec4(v[0], v[1], v[2], v[3])
+        })
+    }
+
+    fn num(&self, span: TokenSpan) -> Result<&Num, LiveError> {
+        self.as_num().ok_or_else( || error(span, "expected a number, vector or matrix"))
+    }
+}
+
+fn error(span: TokenSpan, message: &str) -> LiveError {
+    LiveError {
+        origin: live_error_origin!(),
+        span: span.into(),
+        message: message.to_string()
+    }
+}
+
+impl ShaderTexture {
+    fn sample(&self, x: f32, y: f32) -> Vec4 {
+        if self.width == 0 || self.height == 0 {
+            return Vec4::default()
+        }
+        let x = ((x * self.width as f32).floor().max(0.0) as usize).min(self.width - 1);
+        let y = ((y * self.height as f32).floor().max(0.0) as usize).min(self.height - 1);
+        self.pixels.get(y * self.width + x).cloned().unwrap_or_default()
+    }
+}
+
+impl<'a> ShaderInterpreter<'a> {
+    pub fn new(live_registry: &'a LiveRegistry, shader_registry: &'a ShaderRegistry, draw_shader_ptr: DrawShaderPtr) -> Self {
+        let draw_shader_def = shader_registry.draw_shader_defs.get(&draw_shader_ptr).expect("draw shader not analysed");
+        let mut interpreter = Self {
+            live_registry,
+            shader_registry,
+            draw_shader_def,
+            fields: HashMap::new(),
+            textures: HashMap::new(),
+        };
+        for field in &draw_shader_def.fields {
+            let value = match field.ty_expr.ty.borrow().as_ref().unwrap() {
+                Ty::Texture2D => Value::Texture(field.ident),
+                ty => interpreter.zero_value(ty)
+            };
+            interpreter.fields.insert(field.ident, value);
+        }
+        interpreter
+    }
+
+    /// Sets a geometry, instance, uniform or varying field. Fails if the draw
+    /// shader has no such field.
+    pub fn set_field(&mut self, ident: LiveId, value: Value) -> Result<(), LiveError> {
+        let field = self.find_field(ident)?;
+        self.fields.insert(field.ident, value);
+        Ok(())
+    }
+
+    /// Sets a field from the flat f32 slots of an instance or uniform buffer.
+    pub fn set_field_slots(&mut self, ident: LiveId, slots: &[f32]) -> Result<(), LiveError> {
+        let field = self.find_field(ident)?;
+        if let Some(value) = Value::from_slots(field.ty_expr.ty.borrow().as_ref().unwrap(), slots) {
+            self.fields.insert(field.ident, value);
+        }
+        Ok(())
+    }
+
+    fn find_field(&self, ident: LiveId) -> Result<&'a DrawShaderFieldDef, LiveError> {
+        self.draw_shader_def.find_field(Ident(ident))
+            .ok_or_else( || error(TokenSpan::default(), &format!("draw shader has no field {}", ident)))
+    }
+
+    pub fn field(&self, ident: LiveId) -> Option<&Value> {
+        self.fields.get(&Ident(ident))
+    }
+
+    pub fn set_texture(&mut self, ident: LiveId, texture: &'a ShaderTexture) {
+        self.textures.insert(Ident(ident), texture);
+    }
+
+    /// Calls a method of the draw shader, like `vertex` or `pixel`.
+    pub fn call_method(&mut self, ident: LiveId, args: Vec<Value>) -> Result<Value, LiveError> {
+        let fn_def = self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, Ident(ident))
+            .ok_or_else( || error(TokenSpan::default(), &format!("draw shader has no method {}", ident)))?;
+        let mut frame = Frame::new(fn_def);
+        let mut args = args.into_iter();
+        for param in &fn_def.params {
+            let value = if param.ident == Ident(live_id!(self)) {
+                Value::DrawShader
+            }
+            else {
+                args.next().ok_or_else( || error(fn_def.span, "not enough arguments"))?
+            };
+            frame.bind(param.ident, param.shadow.get(), value);
+        }
+        self.run_fn(&mut frame)
+    }
+
+    pub fn vertex(&mut self) -> Result<Value, LiveError> {
+        self.call_method(live_id!(vertex), Vec::new())
+    }
+
+    pub fn pixel(&mut self) -> Result<Value, LiveError> {
+        self.call_method(live_id!(pixel), Vec::new())
+    }
+
+    /// Runs the vertex and pixel shader for one pixel, with the geometry
+    /// field set to `pos` and its neighbours `step` away for the derivatives.
+    pub fn pixel_at(&mut self, geometry: LiveId, pos: Vec2, step: Vec2) -> Result<Vec4, LiveError> {
+        let mut num = Num::from_slots(TyLit::Vec2, &[pos.x, pos.y]);
+        num.lanes[1][0] += step.x;
+        num.lanes[2][1] += step.y;
+        self.set_field(geometry, Value::Num(num))?;
+        self.vertex()?;
+        let color = self.pixel()?;
+        color.as_vec4().ok_or_else( || error(TokenSpan::default(), "pixel shader did not return a vec4"))
+    }
+
+    /// Renders the quad spanned by a 0..1 geometry field into `width` x `height`
+    /// pixels, sampled at the pixel centers, in rows from the top.
+    pub fn render(&mut self, geometry: LiveId, width: usize, height: usize) -> Result<Vec<Vec4>, LiveError> {
+        let step = vec2(1.0 / width as f32, 1.0 / height as f32);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let pos = vec2((x as f32 + 0.5) * step.x, (y as f32 + 0.5) * step.y);
+                pixels.push(self.pixel_at(geometry, pos, step)?);
+            }
+        }
+        Ok(pixels)
+    }
+
+    fn zero_value(&self, ty: &Ty) -> Value {
+        match ty {
+            Ty::Void | Ty::ClosureDecl | Ty::ClosureDef(_) => Value::Void,
+            Ty::Struct(struct_ptr) => {
+                let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+                Value::Struct(struct_def.fields.iter().map( | field | {
+                    self.zero_value(field.ty_expr.ty.borrow().as_ref().unwrap())
+                }).collect())
+            }
+            Ty::Array {elem_ty, len} => Value::Array(vec![self.zero_value(elem_ty); *len]),
+            Ty::DrawShader(_) => Value::DrawShader,
+            Ty::Texture2D => Value::Texture(Ident(LiveId(0))),
+            Ty::Enum(_) => Value::Num(Num::zero(TyLit::Float)),
+            ty => Value::Num(Num::zero(ty.maybe_ty_lit().unwrap()))
+        }
+    }
+
+    fn run_fn(&mut self, frame: &mut Frame<'a>) -> Result<Value, LiveError> {
+        match self.exec_block(frame, &frame.fn_def.block)? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Void)
+        }
+    }
+
+    fn exec_block(&mut self, frame: &mut Frame<'a>, block: &'a Block) -> Result<Flow, LiveError> {
+        for stmt in &block.stmts {
+            match self.exec_stmt(frame, stmt)? {
+                Flow::Next => (),
+                flow => return Ok(flow)
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_stmt(&mut self, frame: &mut Frame<'a>, stmt: &'a Stmt) -> Result<Flow, LiveError> {
+        match stmt {
+            Stmt::Break {..} => Ok(Flow::Break),
+            Stmt::Continue {..} => Ok(Flow::Continue),
+            Stmt::For {span, ident, from_expr, to_expr, step_expr, block} => {
+                let from = self.eval_int(frame, from_expr)?;
+                let to = self.eval_int(frame, to_expr)?;
+                let step = if let Some(step_expr) = step_expr {self.eval_int(frame, step_expr)?.abs()} else {1};
+                if step == 0 {
+                    return Err(error(*span, "for loop step is zero"))
+                }
+                // same iteration order as the generated for loops
+                let (mut i, forward) = if from <= to {(from, true)} else {(from - 1, false)};
+                let mut flow = Flow::Next;
+                frame.loop_vars.push((*ident, Value::Void));
+                while (forward && i < to) || (!forward && i >= to) {
+                    frame.loop_vars.last_mut().unwrap().1 = Value::Num(Num::from_slots(TyLit::Int, &[i as f32]));
+                    match self.exec_block(frame, block)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => {
+                            flow = Flow::Return(value);
+                            break;
+                        }
+                        _ => ()
+                    }
+                    i = if forward {i + step} else {i - step};
+                }
+                frame.loop_vars.pop();
+                Ok(flow)
+            }
+            Stmt::If {expr, block_if_true, block_if_false, ..} => {
+                if self.eval_num(frame, expr)?.truthy() {
+                    self.exec_block(frame, block_if_true)
+                }
+                else if let Some(block_if_false) = block_if_false {
+                    self.exec_block(frame, block_if_false)
+                }
+                else {
+                    Ok(Flow::Next)
+                }
+            }
+            Stmt::Match {expr, matches, ..} => {
+                let value = self.eval_num(frame, expr)?.lanes[0][0];
+                for m in matches {
+                    if let Some(enum_value) = m.enum_value.get() {
+                        if (value - enum_value as f32).abs() < 0.5 {
+                            return self.exec_block(frame, &m.block)
+                        }
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            Stmt::Let {ty, shadow, ident, expr, ..} => {
+                let value = if let Some(expr) = expr {
+                    self.eval_expr(frame, expr)?
+                }
+                else {
+                    self.zero_value(ty.borrow().as_ref().unwrap())
+                };
+                frame.bind(*ident, shadow.get(), value);
+                Ok(Flow::Next)
+            }
+            Stmt::Return {expr, ..} => {
+                let value = if let Some(expr) = expr {self.eval_expr(frame, expr)?} else {Value::Void};
+                Ok(Flow::Return(value))
+            }
+            Stmt::Block {block, ..} => self.exec_block(frame, block),
+            Stmt::Expr {expr, ..} => {
+                self.eval_expr(frame, expr)?;
+                Ok(Flow::Next)
+            }
+        }
+    }
+
+    fn eval_num(&mut self, frame: &mut Frame<'a>, expr: &'a Expr) -> Result<Num, LiveError> {
+        let value = self.eval_expr(frame, expr)?;
+        value.num(expr.span).cloned()
+    }
+
+    fn eval_int(&mut self, frame: &mut Frame<'a>, expr: &'a Expr) -> Result<i32, LiveError> {
+        Ok(self.eval_num(frame, expr)?.lanes[0][0] as i32)
+    }
+
+    fn eval_expr(&mut self, frame: &mut Frame<'a>, expr: &'a Expr) -> Result<Value, LiveError> {
+        if let Some(Some(val)) = &*expr.const_val.borrow() {
+            return Ok(Value::Num(match val {
+                Val::Bool(v) => Num::from_slots(TyLit::Bool, &[from_bool(*v)]),
+                Val::Int(v) => Num::from_slots(TyLit::Int, &[*v as f32]),
+                Val::Float(v) => Num::from_slots(TyLit::Float, &[*v]),
+                Val::Vec4(v) => Num::from_slots(TyLit::Vec4, &[v.x, v.y, v.z, v.w]),
+            }))
+        }
+        match &expr.kind {
+            ExprKind::Cond {expr, expr_if_true, expr_if_false, ..} => {
+                if self.eval_num(frame, expr)?.truthy() {
+                    self.eval_expr(frame, expr_if_true)
+                }
+                else {
+                    self.eval_expr(frame, expr_if_false)
+                }
+            }
+            ExprKind::Bin {span, op, left_expr, right_expr} => self.eval_bin_expr(frame, *span, *op, left_expr, right_expr),
+            ExprKind::Un {op, expr, ..} => {
+                let num = self.eval_num(frame, expr)?;
+                Ok(Value::Num(match op {
+                    UnOp::Not => num.map( | x | from_bool(x == 0.0)),
+                    UnOp::Neg => num.map( | x | -x),
+                }))
+            }
+            ExprKind::Field {span, expr, field_ident} => {
+                let value = self.eval_expr(frame, expr)?;
+                self.field_of(*span, expr, value, *field_ident)
+            }
+            ExprKind::Index {span, expr, index_expr} => {
+                let value = self.eval_expr(frame, expr)?;
+                let index = self.eval_int(frame, index_expr)?;
+                index_of(*span, value, index)
+            }
+            ExprKind::MethodCall {span, ident, arg_exprs, ..} => {
+                let fn_def = match arg_exprs[0].ty.borrow().as_ref().unwrap() {
+                    Ty::Struct(struct_ptr) => {
+                        let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+                        self.shader_registry.struct_method_decl_from_ident(struct_def, *ident)
+                    }
+                    Ty::DrawShader(_) => self.shader_registry.draw_shader_method_decl_from_ident(self.draw_shader_def, *ident),
+                    _ => None
+                }.ok_or_else( || error(*span, &format!("cannot find method {}", ident)))?;
+                self.call_fn(frame, fn_def, arg_exprs)
+            }
+            ExprKind::PlainCall {span, fn_ptr, param_index, arg_exprs, ..} => {
+                if let Some(param_index) = param_index.get() {
+                    let param = &frame.fn_def.params[param_index];
+                    match frame.get(param.ident, param.shadow.get()) {
+                        Some(Value::Closure(closure)) => self.call_closure(frame, closure, arg_exprs),
+                        _ => Err(error(*span, "closure parameter is not bound"))
+                    }
+                }
+                else {
+                    let fn_def = fn_ptr.and_then( | fn_ptr | self.shader_registry.all_fns.get(&fn_ptr))
+                        .ok_or_else( || error(*span, "cannot find function"))?;
+                    self.call_fn(frame, fn_def, arg_exprs)
+                }
+            }
+            ExprKind::BuiltinCall {span, ident, arg_exprs} => {
+                let mut args = Vec::new();
+                for arg_expr in arg_exprs {
+                    args.push(self.eval_expr(frame, arg_expr)?);
+                }
+                self.eval_builtin(*span, *ident, &args)
+            }
+            ExprKind::ClosureDef(closure_def_index) => {
+                let closure_def = &frame.fn_def.closure_defs[closure_def_index.0];
+                let mut closed_over = Vec::new();
+                for sym in closure_def.closed_over_syms.borrow().iter().flatten() {
+                    if let Some(value) = frame.get(sym.ident, Some(sym.shadow)) {
+                        closed_over.push(((sym.ident, sym.shadow), value));
+                    }
+                }
+                Ok(Value::Closure(Rc::new(Closure {
+                    fn_ptr: frame.fn_def.fn_ptr,
+                    closure_def_index: *closure_def_index,
+                    closed_over
+                })))
+            }
+            ExprKind::ConsCall {span, ty_lit, arg_exprs} => {
+                let mut args = Vec::new();
+                for arg_expr in arg_exprs {
+                    args.push(self.eval_num(frame, arg_expr)?);
+                }
+                cons(*span, *ty_lit, &args).map(Value::Num)
+            }
+            ExprKind::StructCons {struct_ptr, span, args} => {
+                let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+                let mut fields = Vec::new();
+                for field in &struct_def.fields {
+                    let (_, arg_expr) = args.iter().find( | (ident, _) | *ident == field.ident)
+                        .ok_or_else( || error(*span, &format!("missing field {}", field.ident)))?;
+                    fields.push(self.eval_expr(frame, arg_expr)?);
+                }
+                Ok(Value::Struct(fields))
+            }
+            ExprKind::Var {span, ident, kind, ..} => match kind.get() {
+                Some(VarKind::Local {ident, shadow}) | Some(VarKind::MutLocal {ident, shadow}) => {
+                    frame.get(ident, Some(shadow)).ok_or_else( || error(*span, &format!("variable {} is not bound", ident)))
+                }
+                Some(VarKind::LiveValue(value_ptr)) => self.live_value(*span, value_ptr, expr.ty.borrow().as_ref().unwrap()),
+                None => ident.and_then( | ident | frame.get(ident, None))
+                    .ok_or_else( || error(*span, "variable is not resolved"))
+            }
+            ExprKind::Lit {lit, ..} => Ok(Value::Num(match lit {
+                Lit::Bool(v) => Num::from_slots(TyLit::Bool, &[from_bool(*v)]),
+                Lit::Int(v) => Num::from_slots(TyLit::Int, &[*v as f32]),
+                Lit::Float(v) => Num::from_slots(TyLit::Float, &[*v]),
+                Lit::Color(v) => {
+                    let v = Vec4::from_u32(*v);
+                    Num::from_slots(TyLit::Vec4, &[v.x, v.y, v.z, v.w])
+                }
+            }))
+        }
+    }
+
+    fn eval_bin_expr(&mut self, frame: &mut Frame<'a>, span: TokenSpan, op: BinOp, left_expr: &'a Expr, right_expr: &'a Expr) -> Result<Value, LiveError> {
+        let arith_op = match op {
+            BinOp::Assign => {
+                let value = self.eval_expr(frame, right_expr)?;
+                self.assign(frame, left_expr, value.clone())?;
+                return Ok(value)
+            }
+            BinOp::AddAssign => Some(BinOp::Add),
+            BinOp::SubAssign => Some(BinOp::Sub),
+            BinOp::MulAssign => Some(BinOp::Mul),
+            BinOp::DivAssign => Some(BinOp::Div),
+            _ => None
+        };
+        let left = self.eval_num(frame, left_expr)?;
+        let right = self.eval_num(frame, right_expr)?;
+        if let Some(arith_op) = arith_op {
+            let value = Value::Num(bin_op(span, arith_op, &left, &right)?.cast(left.ty));
+            self.assign(frame, left_expr, value.clone())?;
+            return Ok(value)
+        }
+        bin_op(span, op, &left, &right).map(Value::Num)
+    }
+
+    fn field_of(&self, span: TokenSpan, expr: &Expr, value: Value, field_ident: Ident) -> Result<Value, LiveError> {
+        match value {
+            Value::DrawShader => self.fields.get(&field_ident).cloned()
+                .ok_or_else( || error(span, &format!("draw shader has no field {}", field_ident))),
+            Value::Struct(mut fields) => {
+                let index = self.struct_field_index(span, expr, field_ident)?;
+                Ok(fields.swap_remove(index))
+            }
+            Value::Num(num) => {
+                let swizzle = Swizzle::parse(field_ident).ok_or_else( || error(span, "invalid swizzle"))?;
+                let indices: Vec<usize> = swizzle.iter().cloned().collect();
+                let ty = if is_bool_ty(num.ty) {bool_ty(indices.len())}
+                else if is_int_ty(num.ty) {
+                    [TyLit::Int, TyLit::Ivec2, TyLit::Ivec3, TyLit::Ivec4][indices.len() - 1]
+                }
+                else {vec_ty(indices.len())};
+                Ok(Value::Num(Num::per_lane(ty, | l | {
+                    let mut r = [0.0; 16];
+                    for (i, index) in indices.iter().enumerate() {
+                        r[i] = num.lanes[l][*index];
+                    }
+                    r
+                })))
+            }
+            _ => Err(error(span, &format!("cannot access field {}", field_ident)))
+        }
+    }
+
+    fn struct_field_index(&self, span: TokenSpan, expr: &Expr, field_ident: Ident) -> Result<usize, LiveError> {
+        if let Some(Ty::Struct(struct_ptr)) = expr.ty.borrow().as_ref() {
+            let struct_def = self.shader_registry.structs.get(struct_ptr).unwrap();
+            if let Some(index) = struct_def.fields.iter().position( | field | field.ident == field_ident) {
+                return Ok(index)
+            }
+        }
+        Err(error(span, &format!("struct has no field {}", field_ident)))
+    }
+
+    // writes a value back to an lvalue, recursing through fields, swizzles and
+    // indices so `a.b.xy = v` updates `a`
+    fn assign(&mut self, frame: &mut Frame<'a>, expr: &'a Expr, value: Value) -> Result<(), LiveError> {
+        match &expr.kind {
+            ExprKind::Var {span, ident, kind, ..} => match kind.get() {
+                Some(VarKind::Local {ident, shadow}) | Some(VarKind::MutLocal {ident, shadow}) => {
+                    frame.set(ident, shadow, value);
+                    Ok(())
+                }
+                _ => {
+                    if let Some(slot) = ident.and_then( | ident | frame.loop_vars.iter_mut().rev().find( | (i, _) | *i == ident)) {
+                        slot.1 = value;
+                        return Ok(())
+                    }
+                    Err(error(*span, "cannot assign to this variable"))
+                }
+            }
+            ExprKind::Field {span, expr: base_expr, field_ident} => {
+                let base = self.eval_expr(frame, base_expr)?;
+                match base {
+                    Value::DrawShader => {
+                        self.fields.insert(*field_ident, value);
+                        Ok(())
+                    }
+                    Value::Struct(mut fields) => {
+                        let index = self.struct_field_index(*span, base_expr, *field_ident)?;
+                        fields[index] = value;
+                        self.assign(frame, base_expr, Value::Struct(fields))
+                    }
+                    Value::Num(mut num) => {
+                        let swizzle = Swizzle::parse(*field_ident).ok_or_else( || error(*span, "invalid swizzle"))?;
+                        let value = value.num(*span)?;
+                        for l in 0..LANES {
+                            for (i, index) in swizzle.iter().enumerate() {
+                                num.lanes[l][*index] = value.comp(l, i);
+                            }
+                        }
+                        self.assign(frame, base_expr, Value::Num(num))
+                    }
+                    _ => Err(error(*span, "cannot assign to this field"))
+                }
+            }
+            ExprKind::Index {span, expr: base_expr, index_expr} => {
+                let index = self.eval_int(frame, index_expr)?;
+                let base = self.eval_expr(frame, base_expr)?;
+                match base {
+                    Value::Array(mut elems) => {
+                        let elem = elems.get_mut(index as usize).ok_or_else( || error(*span, "index out of bounds"))?;
+                        *elem = value;
+                        self.assign(frame, base_expr, Value::Array(elems))
+                    }
+                    Value::Num(mut num) => {
+                        let value = value.num(*span)?;
+                        let (start, len) = match mat_dim(num.ty) {
+                            Some(dim) => (index as usize * dim, dim),
+                            None => (index as usize, 1)
+                        };
+                        if start + len > num.len() {
+                            return Err(error(*span, "index out of bounds"))
+                        }
+                        for l in 0..LANES {
+                            for i in 0..len {
+                                num.lanes[l][start + i] = value.comp(l, i);
+                            }
+                        }
+                        self.assign(frame, base_expr, Value::Num(num))
+                    }
+                    _ => Err(error(*span, "cannot index this value"))
+                }
+            }
+            _ => Err(error(expr.span, "expression is not assignable"))
+        }
+    }
+
+    fn call_fn(&mut self, frame: &mut Frame<'a>, fn_def: &'a FnDef, arg_exprs: &'a [Expr]) -> Result<Value, LiveError> {
+        // draw shader methods don't get the receiver as an argument
+        let (param_offset, arg_exprs) = if fn_def.params.len() < arg_exprs.len() {
+            (0, &arg_exprs[1..])
+        }
+        else {
+            (fn_def.params.len() - arg_exprs.len(), arg_exprs)
+        };
+        let mut callee = Frame::new(fn_def);
+        for param in &fn_def.params[0..param_offset] {
+            callee.bind(param.ident, param.shadow.get(), Value::DrawShader);
+        }
+        for (param, arg_expr) in fn_def.params[param_offset..].iter().zip(arg_exprs) {
+            let value = self.eval_expr(frame, arg_expr)?;
+            callee.bind(param.ident, param.shadow.get(), value);
+        }
+        let ret = self.run_fn(&mut callee)?;
+        // inout parameters are copy in, copy out
+        for (param, arg_expr) in fn_def.params[param_offset..].iter().zip(arg_exprs) {
+            if param.is_inout {
+                let value = callee.get(param.ident, param.shadow.get()).unwrap();
+                self.assign(frame, arg_expr, value)?;
+            }
+        }
+        Ok(ret)
+    }
+
+    fn call_closure(&mut self, frame: &mut Frame<'a>, closure: Rc<Closure>, arg_exprs: &'a [Expr]) -> Result<Value, LiveError> {
+        let fn_def = self.shader_registry.all_fns.get(&closure.fn_ptr).unwrap();
+        let closure_def = &fn_def.closure_defs[closure.closure_def_index.0];
+        let mut callee = Frame::new(fn_def);
+        for ((ident, shadow), value) in &closure.closed_over {
+            callee.bind(*ident, Some(*shadow), value.clone());
+        }
+        for (param, arg_expr) in closure_def.params.iter().zip(arg_exprs) {
+            let value = self.eval_expr(frame, arg_expr)?;
+            callee.bind(param.ident, param.shadow.get(), value);
+        }
+        match &closure_def.kind {
+            ClosureDefKind::Expr(expr) => self.eval_expr(&mut callee, expr),
+            ClosureDefKind::Block(block) => match self.exec_block(&mut callee, block)? {
+                Flow::Return(value) => Ok(value),
+                _ => Ok(Value::Void)
+            }
+        }
+    }
+
+    fn live_value(&self, span: TokenSpan, value_ptr: ValuePtr, ty: &Ty) -> Result<Value, LiveError> {
+        let ty_lit = match ty {
+            Ty::Enum(_) => TyLit::Float,
+            ty => ty.maybe_ty_lit().ok_or_else( || error(span, "unsupported live value type"))?
+        };
+        let slots = match &self.live_registry.ptr_to_node(value_ptr.0).value {
+            LiveValue::Float64(v) => vec![*v as f32],
+            LiveValue::Float32(v) => vec![*v],
+            LiveValue::Int64(v) => vec![*v as f32],
+            LiveValue::Bool(v) => vec![from_bool(*v)],
+            LiveValue::Color(v) => {
+                let v = Vec4::from_u32(*v);
+                vec![v.x, v.y, v.z, v.w]
+            }
+            LiveValue::Vec2(v) => vec![v.x, v.y],
+            LiveValue::Vec3(v) => vec![v.x, v.y, v.z],
+            LiveValue::Vec4(v) => vec![v.x, v.y, v.z, v.w],
+            _ => return Err(error(span, "unsupported live value"))
+        };
+        let num = Num::from_slots(ty_lit, &slots);
+        // a single number splats, like `#f` in a vec4
+        if slots.len() == 1 && num.len() > 1 {
+            return Ok(Value::Num(Num::zero(ty_lit).zip(&num, | _, v | v)))
+        }
+        Ok(Value::Num(num))
+    }
+
+    fn eval_builtin(&self, span: TokenSpan, ident: Ident, args: &[Value]) -> Result<Value, LiveError> {
+        if let (Some(Value::Texture(texture)), Some(pos)) = (args.first(), args.get(1)) {
+            let pos = pos.num(span)?;
+            let texture = self.textures.get(texture);
+            return Ok(Value::Num(Num::per_lane(TyLit::Vec4, | l | {
+                let mut r = [0.0; 16];
+                if let Some(texture) = texture {
+                    let v = texture.sample(pos.lanes[l][0], pos.lanes[l][1]);
+                    r[0..4].copy_from_slice(&[v.x, v.y, v.z, v.w]);
+                }
+                r
+            })))
+        }
+        let mut nums = Vec::new();
+        for arg in args {
+            nums.push(arg.num(span)?);
+        }
+        builtin(span, ident, &nums).map(Value::Num)
+    }
+}
+
+impl<'a> Frame<'a> {
+    fn new(fn_def: &'a FnDef) -> Self {
+        Self {
+            fn_def,
+            locals: HashMap::new(),
+            loop_vars: Vec::new(),
+        }
+    }
+
+    fn bind(&mut self, ident: Ident, shadow: Option<ScopeSymShadow>, value: Value) {
+        self.locals.insert((ident, shadow.unwrap_or(ScopeSymShadow(0))), value);
+    }
+
+    fn set(&mut self, ident: Ident, shadow: ScopeSymShadow, value: Value) {
+        if let Some(slot) = self.loop_vars.iter_mut().rev().find( | (i, _) | *i == ident) {
+            if !self.locals.contains_key(&(ident, shadow)) {
+                slot.1 = value;
+                return
+            }
+        }
+        self.locals.insert((ident, shadow), value);
+    }
+
+    // loop variables have no shadow of their own, so they're found by ident
+    fn get(&self, ident: Ident, shadow: Option<ScopeSymShadow>) -> Option<Value> {
+        if let Some(value) = self.locals.get(&(ident, shadow.unwrap_or(ScopeSymShadow(0)))) {
+            return Some(value.clone())
+        }
+        if let Some((_, value)) = self.loop_vars.iter().rev().find( | (i, _) | *i == ident) {
+            return Some(value.clone())
+        }
+        if shadow.is_none() {
+            return self.locals.iter().find( | ((i, _), _) | *i == ident).map( | (_, value) | value.clone())
+        }
+        None
+    }
+}
+
+fn index_of(span: TokenSpan, value: Value, index: i32) -> Result<Value, LiveError> {
+    match value {
+        Value::Array(mut elems) if (index as usize) < elems.len() => Ok(elems.swap_remove(index as usize)),
+        Value::Num(num) => {
+            let (start, len) = match mat_dim(num.ty) {
+                Some(dim) => (index as usize * dim, dim),
+                None => (index as usize, 1)
+            };
+            if start + len > num.len() {
+                return Err(error(span, "index out of bounds"))
+            }
+            let ty = if len == 1 && is_int_ty(num.ty) {TyLit::Int} else if len == 1 && is_bool_ty(num.ty) {TyLit::Bool} else {vec_ty(len)};
+            Ok(Value::Num(Num::per_lane(ty, | l | {
+                let mut r = [0.0; 16];
+                r[0..len].copy_from_slice(&num.lanes[l][start..start + len]);
+                r
+            })))
+        }
+        _ => Err(error(span, "index out of bounds"))
+    }
+}
+
+fn bin_op(span: TokenSpan, op: BinOp, left: &Num, right: &Num) -> Result<Num, LiveError> {
+    Ok(match op {
+        BinOp::Or => left.zip(right, | a, b | from_bool(a != 0.0 || b != 0.0)),
+        BinOp::And => left.zip(right, | a, b | from_bool(a != 0.0 && b != 0.0)),
+        BinOp::Eq | BinOp::Ne => {
+            let eq = matches!(op, BinOp::Eq);
+            Num::per_lane(TyLit::Bool, | l | {
+                let mut r = [0.0; 16];
+                r[0] = from_bool((left.lane(l) == right.lane(l)) == eq);
+                r
+            })
+        }
+        BinOp::Lt => Num {ty: TyLit::Bool, ..left.zip(right, | a, b | from_bool(a < b))},
+        BinOp::Le => Num {ty: TyLit::Bool, ..left.zip(right, | a, b | from_bool(a <= b))},
+        BinOp::Gt => Num {ty: TyLit::Bool, ..left.zip(right, | a, b | from_bool(a > b))},
+        BinOp::Ge => Num {ty: TyLit::Bool, ..left.zip(right, | a, b | from_bool(a >= b))},
+        BinOp::Add => left.zip(right, | a, b | a + b),
+        BinOp::Sub => left.zip(right, | a, b | a - b),
+        BinOp::Mul => match (mat_dim(left.ty), mat_dim(right.ty)) {
+            (Some(dim), Some(_)) => Num::per_lane(left.ty, | l | {
+                let (a, b) = (&left.lanes[l], &right.lanes[l]);
+                let mut r = [0.0; 16];
+                for c in 0..dim {
+                    for i in 0..dim {
+                        r[c * dim + i] = (0..dim).map( | k | a[k * dim + i] * b[c * dim + k]).sum();
+                    }
+                }
+                r
+            }),
+            (Some(dim), None) if right.len() == dim => Num::per_lane(right.ty, | l | {
+                let (m, v) = (&left.lanes[l], &right.lanes[l]);
+                let mut r = [0.0; 16];
+                for i in 0..dim {
+                    r[i] = (0..dim).map( | c | m[c * dim + i] * v[c]).sum();
+                }
+                r
+            }),
+            (None, Some(dim)) if left.len() == dim => Num::per_lane(left.ty, | l | {
+                let (v, m) = (&left.lanes[l], &right.lanes[l]);
+                let mut r = [0.0; 16];
+                for c in 0..dim {
+                    r[c] = (0..dim).map( | i | v[i] * m[c * dim + i]).sum();
+                }
+                r
+            }),
+            _ => left.zip(right, | a, b | a * b)
+        },
+        BinOp::Div => {
+            if is_int_ty(left.ty) && is_int_ty(right.ty) {
+                left.zip(right, | a, b | if b == 0.0 {0.0} else {(a / b).trunc()})
+            }
+            else {
+                left.zip(right, | a, b | a / b)
+            }
+        }
+        _ => return Err(error(span, "unexpected assignment operator"))
+    })
+}
+
+fn cons(span: TokenSpan, ty: TyLit, args: &[Num]) -> Result<Num, LiveError> {
+    let n = ty_lit_slots(ty);
+    if let [arg] = args {
+        if arg.len() == 1 {
+            // a scalar splats into a vector, or fills the diagonal of a matrix
+            return Ok(match mat_dim(ty) {
+                Some(dim) => Num::per_lane(ty, | l | {
+                    let mut r = [0.0; 16];
+                    for i in 0..dim {
+                        r[i * dim + i] = arg.lanes[l][0];
+                    }
+                    r
+                }),
+                None => Num::zero(ty).zip(arg, | _, v | v).cast(ty)
+            })
+        }
+        if let (Some(dim), Some(arg_dim)) = (mat_dim(ty), mat_dim(arg.ty)) {
+            // matrix from matrix, missing parts come from the identity
+            return Ok(Num::per_lane(ty, | l | {
+                let mut r = [0.0; 16];
+                for c in 0..dim {
+                    for i in 0..dim {
+                        r[c * dim + i] = if c < arg_dim && i < arg_dim {
+                            arg.lanes[l][c * arg_dim + i]
+                        }
+                        else {
+                            from_bool(c == i)
+                        };
+                    }
+                }
+                r
+            }))
+        }
+    }
+    let total: usize = args.iter().map( | arg | arg.len()).sum();
+    if total < n {
+        return Err(error(span, "not enough components for constructor"))
+    }
+    Ok(Num::per_lane(ty, | l | {
+        let mut r = [0.0; 16];
+        for (i, v) in args.iter().flat_map( | arg | arg.lane(l).iter()).take(n).enumerate() {
+            r[i] = *v;
+        }
+        r
+    }).cast(ty))
+}
+
+fn builtin(span: TokenSpan, ident: Ident, args: &[&Num]) -> Result<Num, LiveError> {
+    let arg = | i: usize | -> Result<&Num, LiveError> {
+        args.get(i).copied().ok_or_else( || error(span, &format!("not enough arguments for {}", ident)))
+    };
+    let dot = | a: &[f32], b: &[f32] | -> f32 {a.iter().zip(b).map( | (a, b) | a * b).sum()};
+    Ok(match ident.0 {
+        live_id!(abs) => arg(0)?.map(f32::abs),
+        live_id!(acos) => arg(0)?.map(f32::acos),
+        live_id!(asin) => arg(0)?.map(f32::asin),
+        live_id!(atan) => if args.len() == 2 {
+            arg(0)?.zip(arg(1)?, f32::atan2)
+        }
+        else {
+            arg(0)?.map(f32::atan)
+        },
+        live_id!(ceil) => arg(0)?.map(f32::ceil),
+        live_id!(cos) => arg(0)?.map(f32::cos),
+        live_id!(degrees) => arg(0)?.map(f32::to_degrees),
+        live_id!(exp) => arg(0)?.map(f32::exp),
+        live_id!(exp2) => arg(0)?.map(f32::exp2),
+        live_id!(floor) => arg(0)?.map(f32::floor),
+        live_id!(fract) => arg(0)?.map( | x | x - x.floor()),
+        live_id!(inversesqrt) => arg(0)?.map( | x | 1.0 / x.sqrt()),
+        live_id!(log) => arg(0)?.map(f32::ln),
+        live_id!(log2) => arg(0)?.map(f32::log2),
+        live_id!(radians) => arg(0)?.map(f32::to_radians),
+        live_id!(sign) => arg(0)?.map( | x | if x > 0.0 {1.0} else if x < 0.0 {-1.0} else {0.0}),
+        live_id!(sin) => arg(0)?.map(f32::sin),
+        live_id!(sqrt) => arg(0)?.map(f32::sqrt),
+        live_id!(tan) => arg(0)?.map(f32::tan),
+        live_id!(max) => arg(0)?.zip(arg(1)?, f32::max),
+        live_id!(min) => arg(0)?.zip(arg(1)?, f32::min),
+        live_id!(mod) => arg(0)?.zip(arg(1)?, | x, y | x - y * (x / y).floor()),
+        live_id!(pow) => arg(0)?.zip(arg(1)?, f32::powf),
+        live_id!(step) => arg(1)?.zip(arg(0)?, | x, edge | from_bool(x >= edge)),
+        live_id!(matrixCompMult) => arg(0)?.zip(arg(1)?, | a, b | a * b),
+        live_id!(clamp) => arg(0)?.zip3(arg(1)?, arg(2)?, | x, lo, hi | x.max(lo).min(hi)),
+        live_id!(mix) => arg(0)?.zip3(arg(1)?, arg(2)?, | a, b, t | a + (b - a) * t),
+        live_id!(smoothstep) => arg(2)?.zip3(arg(0)?, arg(1)?, | x, e0, e1 | {
+            let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }),
+        live_id!(lessThan) => compare(arg(0)?, arg(1)?, | a, b | a < b),
+        live_id!(lessThanEqual) => compare(arg(0)?, arg(1)?, | a, b | a <= b),
+        live_id!(greaterThan) => compare(arg(0)?, arg(1)?, | a, b | a > b),
+        live_id!(greaterThanEqual) => compare(arg(0)?, arg(1)?, | a, b | a >= b),
+        live_id!(equal) => compare(arg(0)?, arg(1)?, | a, b | a == b),
+        live_id!(notEqual) => compare(arg(0)?, arg(1)?, | a, b | a != b),
+        live_id!(not) => arg(0)?.map( | x | from_bool(x == 0.0)),
+        live_id!(all) => arg(0)?.reduce(TyLit::Bool, | v | from_bool(v.iter().all( | x | *x != 0.0))),
+        live_id!(any) => arg(0)?.reduce(TyLit::Bool, | v | from_bool(v.iter().any( | x | *x != 0.0))),
+        live_id!(dot) => {
+            let (a, b) = (arg(0)?, arg(1)?);
+            Num::per_lane(TyLit::Float, | l | {
+                let mut r = [0.0; 16];
+                r[0] = dot(a.lane(l), b.lane(l));
+                r
+            })
+        }
+        live_id!(length) => arg(0)?.reduce(TyLit::Float, | v | dot(v, v).sqrt()),
+        live_id!(distance) => {
+            let d = arg(0)?.zip(arg(1)?, | a, b | a - b);
+            d.reduce(TyLit::Float, | v | dot(v, v).sqrt())
+        }
+        live_id!(normalize) => {
+            let v = arg(0)?;
+            Num::per_lane(v.ty, | l | {
+                let len = dot(v.lane(l), v.lane(l)).sqrt();
+                let mut r = [0.0; 16];
+                for (i, x) in v.lane(l).iter().enumerate() {
+                    r[i] = x / len;
+                }
+                r
+            })
+        }
+        live_id!(cross) => {
+            let (a, b) = (arg(0)?, arg(1)?);
+            Num::per_lane(TyLit::Vec3, | l | {
+                let (a, b) = (&a.lanes[l], &b.lanes[l]);
+                let mut r = [0.0; 16];
+                r[0] = a[1] * b[2] - a[2] * b[1];
+                r[1] = a[2] * b[0] - a[0] * b[2];
+                r[2] = a[0] * b[1] - a[1] * b[0];
+                r
+            })
+        }
+        live_id!(reflect) => {
+            let (i, n) = (arg(0)?, arg(1)?);
+            Num::per_lane(i.ty, | l | {
+                let d = dot(n.lane(l), i.lane(l));
+                let mut r = [0.0; 16];
+                for (k, out) in r[0..i.len()].iter_mut().enumerate() {
+                    *out = i.lanes[l][k] - 2.0 * d * n.lanes[l][k];
+                }
+                r
+            })
+        }
+        live_id!(refract) => {
+            let (i, n, eta) = (arg(0)?, arg(1)?, arg(2)?);
+            Num::per_lane(i.ty, | l | {
+                let eta = eta.lanes[l][0];
+                let d = dot(n.lane(l), i.lane(l));
+                let k = 1.0 - eta * eta * (1.0 - d * d);
+                let mut r = [0.0; 16];
+                if k >= 0.0 {
+                    for (c, out) in r[0..i.len()].iter_mut().enumerate() {
+                        *out = eta * i.lanes[l][c] - (eta * d + k.sqrt()) * n.lanes[l][c];
+                    }
+                }
+                r
+            })
+        }
+        live_id!(faceforward) => {
+            let (n, i, nref) = (arg(0)?, arg(1)?, arg(2)?);
+            Num::per_lane(n.ty, | l | {
+                let s = if dot(nref.lane(l), i.lane(l)) < 0.0 {1.0} else {-1.0};
+                let mut r = [0.0; 16];
+                for (c, out) in r[0..n.len()].iter_mut().enumerate() {
+                    *out = s * n.lanes[l][c];
+                }
+                r
+            })
+        }
+        live_id!(transpose) => {
+            let m = arg(0)?;
+            let dim = mat_dim(m.ty).ok_or_else( || error(span, "transpose needs a matrix"))?;
+            Num::per_lane(m.ty, | l | {
+                let mut r = [0.0; 16];
+                for c in 0..dim {
+                    for i in 0..dim {
+                        r[c * dim + i] = m.lanes[l][i * dim + c];
+                    }
+                }
+                r
+            })
+        }
+        live_id!(inverse) => {
+            let m = arg(0)?;
+            let dim = mat_dim(m.ty).ok_or_else( || error(span, "inverse needs a matrix"))?;
+            Num::per_lane(m.ty, | l | invert(&m.lanes[l], dim))
+        }
+        live_id!(dFdx) | live_id!(dFdy) => {
+            let v = arg(0)?;
+            let lane = if ident.0 == live_id!(dFdx) {1} else {2};
+            let mut r = [0.0; 16];
+            for (i, out) in r[0..v.len()].iter_mut().enumerate() {
+                *out = v.lanes[lane][i] - v.lanes[0][i];
+            }
+            Num {ty: v.ty, lanes: [r; LANES]}
+        }
+        _ => return Err(error(span, &format!("builtin {} is not supported by the interpreter", ident)))
+    })
+}
+
+fn compare(a: &Num, b: &Num, f: impl Fn(f32, f32) -> bool) -> Num {
+    Num {ty: bool_ty(a.len()), ..a.zip(b, | a, b | from_bool(f(a, b)))}
+}
+
+// gauss-jordan on a column major matrix, singular matrices give zeros
+fn invert(m: &[f32; 16], dim: usize) -> [f32; 16] {
+    let mut a = [[0.0f32; 8]; 4];
+    for i in 0..dim {
+        for c in 0..dim {
+            a[i][c] = m[c * dim + i];
+        }
+        a[i][dim + i] = 1.0;
+    }
+    for c in 0..dim {
+        let pivot = (c..dim).max_by( | x, y | a[*x][c].abs().total_cmp(&a[*y][c].abs())).unwrap();
+        if a[pivot][c] == 0.0 {
+            return [0.0; 16]
+        }
+        a.swap(c, pivot);
+        let p = a[c][c];
+        for x in &mut a[c][0..dim * 2] {
+            *x /= p;
+        }
+        for i in 0..dim {
+            if i != c {
+                let f = a[i][c];
+                let row = a[c];
+                for (x, y) in a[i][0..dim * 2].iter_mut().zip(row) {
+                    *x -= f * y;
+                }
+            }
+        }
+    }
+    let mut r = [0.0; 16];
+    for i in 0..dim {
+        for c in 0..dim {
+            r[c * dim + i] = a[i][dim + c];
+        }
+    }
+    r
+}
@@ -14,13 +14,16 @@ pub mod lhs_check;
 pub mod swizzle;
 pub mod util;
 pub mod generate;
+pub mod interpret;
 
-#[cfg(any(target_os = "android", target_os = "linux", target_arch = "wasm32"))]
+#[cfg(feature = "glsl")]
 pub mod generate_glsl;
-#[cfg(any(target_os = "macos"))]
+#[cfg(feature = "metal")]
 pub mod generate_metal;
-#[cfg(any(target_os = "windows"))]
+#[cfg(feature = "hlsl")]
 pub mod generate_hlsl;
+#[cfg(feature = "wgsl")]
+pub mod generate_wgsl;
 
 pub use makepad_live_compiler;
 pub use makepad_live_compiler::makepad_math;
@@ -164,6 +164,7 @@ pub enum OsType {
     Android(AndroidParams),
     LinuxWindow (LinuxWindowParams),
     LinuxDirect,
+    LinuxHeadless,
     Web(WebParams)
 }
 
@@ -180,6 +181,7 @@ impl OsType {
             OsType::IOS=>true,
             OsType::Android(_) => true,
             OsType::LinuxDirect=> true,
+            OsType::LinuxHeadless=> true,
             _=> false
         }
     }
@@ -0,0 +1,26 @@
+use {
+    crate::{
+        event::{
+            MouseDownEvent,
+            MouseUpEvent,
+            MouseMoveEvent,
+            ScrollEvent,
+            KeyEvent,
+            TextInputEvent,
+            TimerEvent,
+        },
+    }
+};
+
+#[derive(Debug)]
+pub enum HeadlessEvent {
+    Paint,
+    MouseDown(MouseDownEvent),
+    MouseUp(MouseUpEvent),
+    MouseMove(MouseMoveEvent),
+    Scroll(ScrollEvent),
+    KeyDown(KeyEvent),
+    KeyUp(KeyEvent),
+    TextInput(TextInputEvent),
+    Timer(TimerEvent),
+}
@@ -0,0 +1,62 @@
+use crate::{
+    cx::Cx,
+    audio::*,
+    midi::*,
+    video::*,
+    media_api::CxMediaApi,
+};
+
+// The headless backend runs on CI machines without sound devices, so it does not link alsa or
+// pulseaudio. Midi inputs never receive anything, and everything sent to an output is dropped.
+
+pub struct OsMidiInput;
+
+impl OsMidiInput {
+    pub fn receive(&mut self) -> Option<(MidiPortId, MidiData)> {
+        None
+    }
+}
+
+pub struct OsMidiOutput;
+
+impl OsMidiOutput {
+    pub fn send(&self, _port_id: Option<MidiPortId>, _data: MidiData) {
+    }
+}
+
+impl CxMediaApi for Cx {
+    fn midi_input(&mut self) -> MidiInput {
+        MidiInput(Some(OsMidiInput))
+    }
+    
+    fn midi_output(&mut self) -> MidiOutput {
+        MidiOutput(Some(OsMidiOutput))
+    }
+    
+    fn midi_reset(&mut self) {
+    }
+    
+    fn use_midi_inputs(&mut self, _ports: &[MidiPortId]) {
+    }
+    
+    fn use_midi_outputs(&mut self, _ports: &[MidiPortId]) {
+    }
+    
+    fn use_audio_inputs(&mut self, _devices: &[AudioDeviceId]) {
+    }
+    
+    fn use_audio_outputs(&mut self, _devices: &[AudioDeviceId]) {
+    }
+    
+    fn audio_output_box(&mut self, _index: usize, _f: AudioOutputFn) {
+    }
+    
+    fn audio_input_box(&mut self, _index: usize, _f: AudioInputFn) {
+    }
+    
+    fn video_input_box(&mut self, _index: usize, _f: VideoInputFn) {
+    }
+    
+    fn use_video_input(&mut self, _inputs: &[(VideoInputId, VideoFormatId)]) {
+    }
+}
@@ -0,0 +1,308 @@
+use {
+    std::{
+        rc::Rc,
+        cell::RefCell,
+        path::PathBuf,
+        time::Duration,
+    },
+    self::super::{
+        headless_event::*,
+        software::HeadlessFramebuffer,
+    },
+    self::super::super::{
+        select_timer::SelectTimers,
+    },
+    crate::{
+        cx_api::{CxOsOp, CxOsApi},
+        makepad_live_id::*,
+        makepad_error_log::*,
+        makepad_math::*,
+        thread::Signal,
+        event::{
+            TimerEvent,
+            WebSocket,
+            WebSocketAutoReconnect,
+            Event,
+            WindowGeom,
+        },
+        window::WindowId,
+        pass::CxPassParent,
+        cx::{Cx, OsType,},
+        gpu_info::GpuPerformance,
+        os::cx_native::EventFlow,
+    }
+};
+
+pub struct HeadlessWindow {
+    pub window_id: WindowId,
+    pub window_geom: WindowGeom,
+    pub framebuffer: HeadlessFramebuffer,
+}
+
+/// Runs an app without a display. Window passes are rendered on the cpu into
+/// framebuffers that can be inspected or dumped as png, for snapshot tests.
+///
+/// `new` reads its settings from the environment:
+/// - `MAKEPAD_HEADLESS_SIZE=1280x720` window size, instead of the size the app asks for
+/// - `MAKEPAD_HEADLESS_DPI=2.0` dpi factor, 1.0 by default
+/// - `MAKEPAD_HEADLESS_DUMP=dir` writes every painted frame to dir/frame_00000.png
+/// - `MAKEPAD_HEADLESS_FRAMES=10` exits after painting that many frames
+///
+/// Without a frame limit the event loop exits as soon as the app is idle:
+/// nothing to redraw and no timers running.
+pub struct HeadlessApp {
+    timers: SelectTimers,
+    pub inner_size: Option<DVec2>,
+    pub dpi_factor: f64,
+    pub dump_dir: Option<PathBuf>,
+    pub max_frames: Option<usize>,
+    pub frames: usize,
+    pub windows: Vec<HeadlessWindow>,
+}
+
+impl HeadlessApp {
+    pub fn new() -> Self {
+        let env = | name | std::env::var(name).ok();
+        let inner_size = env("MAKEPAD_HEADLESS_SIZE").and_then( | size | {
+            let (w, h) = size.split_once('x')?;
+            Some(dvec2(w.trim().parse().ok()?, h.trim().parse().ok()?))
+        });
+        Self {
+            timers: SelectTimers::new(),
+            inner_size,
+            dpi_factor: env("MAKEPAD_HEADLESS_DPI").and_then( | dpi | dpi.parse().ok()).unwrap_or(1.0),
+            dump_dir: env("MAKEPAD_HEADLESS_DUMP").map(PathBuf::from),
+            max_frames: env("MAKEPAD_HEADLESS_FRAMES").and_then( | frames | frames.parse().ok()),
+            frames: 0,
+            windows: Vec::new(),
+        }
+    }
+
+    pub fn window(&self, window_id: WindowId) -> Option<&HeadlessWindow> {
+        self.windows.iter().find( | w | w.window_id == window_id)
+    }
+
+    pub fn time_now(&self) -> f64 {
+        self.timers.time_now()
+    }
+}
+
+impl Default for HeadlessApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cx {
+    pub fn event_loop(cx: Rc<RefCell<Cx>>) {
+        cx.borrow_mut().self_ref = Some(cx.clone());
+        let mut headless_app = HeadlessApp::new();
+        cx.borrow_mut().headless_event_loop(&mut headless_app);
+    }
+
+    pub fn headless_event_loop(&mut self, headless_app: &mut HeadlessApp) {
+        self.os_type = OsType::LinuxHeadless;
+        self.gpu_info.performance = GpuPerformance::Tier1;
+
+        self.call_event_handler(&Event::Construct);
+        self.redraw_all();
+
+        let mut timer_ids = Vec::new();
+        loop {
+            headless_app.timers.update_timers(&mut timer_ids);
+            for timer_id in &timer_ids {
+                self.headless_event_callback(
+                    headless_app,
+                    HeadlessEvent::Timer(TimerEvent {timer_id: *timer_id})
+                );
+            }
+            let event_flow = self.headless_event_callback(headless_app, HeadlessEvent::Paint);
+            if event_flow == EventFlow::Exit {
+                break
+            }
+            if let Some(max_frames) = headless_app.max_frames {
+                if headless_app.frames >= max_frames {
+                    break
+                }
+            }
+            if event_flow == EventFlow::Wait {
+                // there is no input to wait for, only timers
+                match headless_app.timers.next_timeout() {
+                    Some(timeout) => std::thread::sleep(Duration::from_secs_f64(timeout.max(0.0))),
+                    None => break
+                }
+            }
+        }
+    }
+
+    pub fn headless_event_callback(
+        &mut self,
+        headless_app: &mut HeadlessApp,
+        event: HeadlessEvent,
+    ) -> EventFlow {
+        if let EventFlow::Exit = self.handle_platform_ops(headless_app) {
+            return EventFlow::Exit
+        }
+
+        match event {
+            HeadlessEvent::Paint => {
+                if Signal::check_and_clear_ui_signal() {
+                    self.call_event_handler(&Event::Signal);
+                }
+                if !self.new_next_frames.is_empty() {
+                    self.call_next_frame_event(headless_app.time_now());
+                }
+                if self.need_redrawing() {
+                    self.call_draw_event();
+                    self.headless_compile_shaders();
+                }
+                // windows created during the draw event get their size before the repaint
+                if let EventFlow::Exit = self.handle_platform_ops(headless_app) {
+                    return EventFlow::Exit
+                }
+                self.handle_repaint(headless_app);
+            }
+            HeadlessEvent::MouseDown(e) => {
+                self.fingers.process_tap_count(
+                    e.abs,
+                    e.time
+                );
+                self.fingers.mouse_down(e.button);
+                self.call_event_handler(&Event::MouseDown(e))
+            }
+            HeadlessEvent::MouseMove(e) => {
+                self.call_event_handler(&Event::MouseMove(e));
+                self.fingers.cycle_hover_area(live_id!(mouse).into());
+                self.fingers.switch_captures();
+            }
+            HeadlessEvent::MouseUp(e) => {
+                let button = e.button;
+                self.call_event_handler(&Event::MouseUp(e));
+                self.fingers.mouse_up(button);
+                self.fingers.cycle_hover_area(live_id!(mouse).into());
+            }
+            HeadlessEvent::Scroll(e) => {
+                self.call_event_handler(&Event::Scroll(e))
+            }
+            HeadlessEvent::KeyDown(e) => {
+                self.keyboard.process_key_down(e.clone());
+                self.call_event_handler(&Event::KeyDown(e))
+            }
+            HeadlessEvent::KeyUp(e) => {
+                self.keyboard.process_key_up(e.clone());
+                self.call_event_handler(&Event::KeyUp(e))
+            }
+            HeadlessEvent::TextInput(e) => {
+                self.call_event_handler(&Event::TextInput(e))
+            }
+            HeadlessEvent::Timer(e) => {
+                self.call_event_handler(&Event::Timer(e))
+            }
+        }
+        if self.any_passes_dirty() || self.need_redrawing() || !self.new_next_frames.is_empty() {
+            EventFlow::Poll
+        } else {
+            EventFlow::Wait
+        }
+    }
+
+    pub (crate) fn handle_repaint(&mut self, headless_app: &mut HeadlessApp) {
+        let mut passes_todo = Vec::new();
+        self.compute_pass_repaint_order(&mut passes_todo);
+        self.repaint_id += 1;
+        for pass_id in &passes_todo {
+            match self.passes[*pass_id].parent.clone() {
+                CxPassParent::Window(window_id) => {
+                    if let Some(window) = headless_app.windows.iter_mut().find( | w | w.window_id == window_id) {
+                        self.draw_pass_to_framebuffer(*pass_id, &mut window.framebuffer, &window.window_geom);
+                        if let Some(dump_dir) = &headless_app.dump_dir {
+                            let path = dump_dir.join(format!("frame_{:05}.png", headless_app.frames));
+                            if let Err(err) = window.framebuffer.save_png(&path) {
+                                error!("Cannot write headless frame {:?}: {}", path, err);
+                            }
+                        }
+                        headless_app.frames += 1;
+                    }
+                }
+                CxPassParent::Pass(_) | CxPassParent::None => {
+                    self.draw_pass_to_texture(*pass_id);
+                }
+            }
+        }
+    }
+
+    fn handle_platform_ops(&mut self, headless_app: &mut HeadlessApp) -> EventFlow {
+        let mut ret = EventFlow::Poll;
+        while let Some(op) = self.platform_ops.pop() {
+            match op {
+                CxOsOp::CreateWindow(window_id) => {
+                    let window = &mut self.windows[window_id];
+                    let size = headless_app.inner_size
+                        .or(window.create_inner_size)
+                        .unwrap_or(dvec2(800., 600.));
+                    window.window_geom = WindowGeom {
+                        dpi_factor: window.dpi_override.unwrap_or(headless_app.dpi_factor),
+                        can_fullscreen: false,
+                        xr_is_presenting: false,
+                        is_fullscreen: false,
+                        is_topmost: false,
+                        position: window.create_position.unwrap_or(dvec2(0.0, 0.0)),
+                        inner_size: size,
+                        outer_size: size
+                    };
+                    window.is_created = true;
+                    headless_app.windows.push(HeadlessWindow {
+                        window_id,
+                        window_geom: window.window_geom.clone(),
+                        framebuffer: HeadlessFramebuffer::default(),
+                    });
+                },
+                CxOsOp::CloseWindow(window_id) => {
+                    if let Some(index) = headless_app.windows.iter().position( | w | w.window_id == window_id) {
+                        self.windows[window_id].is_created = false;
+                        headless_app.windows.remove(index);
+                        if headless_app.windows.is_empty() {
+                            ret = EventFlow::Exit
+                        }
+                    }
+                },
+                CxOsOp::StartTimer {timer_id, interval, repeats} => {
+                    headless_app.timers.start_timer(timer_id, interval, repeats);
+                },
+                CxOsOp::StopTimer(timer_id) => {
+                    headless_app.timers.stop_timer(timer_id);
+                },
+                _ => ()
+            }
+        }
+        ret
+    }
+}
+
+impl CxOsApi for Cx {
+    fn init_cx_os(&mut self) {
+        self.live_expand();
+        self.live_scan_dependencies();
+        self.native_load_dependencies();
+    }
+
+    fn spawn_thread<F>(&mut self, f: F) where F: FnOnce() + Send + 'static {
+        std::thread::spawn(f);
+    }
+
+    // There is no network access in headless runs, so web sockets never open, and whatever is
+    // sent over them is dropped.
+    fn web_socket_open(&mut self, url: String, _rec: WebSocketAutoReconnect) -> WebSocket {
+        let web_socket_id = self.web_socket_id;
+        self.web_socket_id += 1;
+        log!("Headless: not opening web socket to {}", url);
+        WebSocket(web_socket_id)
+    }
+
+    fn web_socket_send(&mut self, _websocket: WebSocket, _data: Vec<u8>) {
+    }
+}
+
+#[derive(Default)]
+pub struct CxOs {
+}
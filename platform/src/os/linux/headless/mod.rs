@@ -0,0 +1,4 @@
+pub mod linux_headless;
+pub mod headless_event;
+pub mod software;
+pub mod headless_media;
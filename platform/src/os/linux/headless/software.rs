@@ -0,0 +1,582 @@
+// Renders passes on the cpu for the headless backend. It follows opengl.rs:
+// the same pass setup, a LEQUAL depth test and premultiplied alpha blending,
+// but vertex and pixel shaders run in the shader interpreter and the targets
+// are plain pixel buffers, stored in rows from the top.
+
+use {
+    std::{
+        io,
+        path::Path,
+    },
+    crate::{
+        makepad_live_compiler::LiveError,
+        makepad_live_id::*,
+        makepad_error_log::*,
+        makepad_shader_compiler::{
+            shader_ast::{DrawShaderFieldKind, TyLit},
+            interpret::{ShaderInterpreter, ShaderTexture, Num, Value, LANES},
+        },
+        makepad_math::*,
+        cx::Cx,
+        event::WindowGeom,
+        texture::TextureDesc,
+        pass::{PassClearColor, PassClearDepth, PassId},
+        draw_list::DrawListId,
+        draw_shader::DrawShaderInputs,
+    },
+};
+
+/// A color and depth buffer. Colors are premultiplied, like the GPU backends
+/// render them.
+#[derive(Clone, Default)]
+pub struct HeadlessFramebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub color: Vec<Vec4>,
+    pub depth: Vec<f32>,
+}
+
+impl HeadlessFramebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            color: vec![Vec4::default(); width * height],
+            depth: vec![1.0; width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vec4 {
+        self.color[y * self.width + x]
+    }
+
+    fn clear(&mut self, color: Option<Vec4>, depth: Option<f32>) {
+        if let Some(color) = color {
+            self.color.iter_mut().for_each( | c | *c = color);
+        }
+        if let Some(depth) = depth {
+            self.depth.iter_mut().for_each( | d | *d = depth);
+        }
+    }
+
+    /// 8 bit RGBA, rows from the top.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.color.len() * 4);
+        for color in &self.color {
+            for v in [color.x, color.y, color.z, color.w].iter() {
+                out.push((v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8);
+            }
+        }
+        out
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        fn chunk(out: &mut Vec<u8>, ty: &[u8], data: &[u8]) {
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let start = out.len();
+            out.extend_from_slice(ty);
+            out.extend_from_slice(data);
+            let crc = makepad_miniz::crc32::crc32(&out[start..]);
+            out.extend_from_slice(&crc.to_be_bytes());
+        }
+        let mut header = Vec::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bit RGBA, default compression and filtering, not interlaced
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let rgba = self.to_rgba8();
+        let mut scanlines = Vec::with_capacity(rgba.len() + self.height);
+        for row in rgba.chunks(self.width.max(1) * 4) {
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+
+        let mut out = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        chunk(&mut out, b"IHDR", &header);
+        chunk(&mut out, b"IDAT", &makepad_miniz::compress_to_vec_zlib(&scanlines, 6));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.encode_png())
+    }
+}
+
+struct ClipVertex {
+    pos: Vec4,
+    varyings: Vec<[f32; 16]>,
+}
+
+impl Cx {
+    pub (crate) fn render_view(
+        &mut self,
+        pass_id: PassId,
+        draw_list_id: DrawListId,
+        zbias: &mut f32,
+        zbias_step: f32,
+        framebuffer: &mut HeadlessFramebuffer,
+    ) {
+        let draw_items_len = self.draw_lists[draw_list_id].draw_items.len();
+        self.draw_lists[draw_list_id].uniform_view_transform(&Mat4::identity());
+
+        for draw_item_id in 0..draw_items_len {
+            if let Some(sub_list_id) = self.draw_lists[draw_list_id].draw_items[draw_item_id].kind.sub_list() {
+                self.render_view(
+                    pass_id,
+                    sub_list_id,
+                    zbias,
+                    zbias_step,
+                    framebuffer,
+                );
+            }
+            else {
+                let draw_item = &mut self.draw_lists[draw_list_id].draw_items[draw_item_id];
+                let draw_call = if let Some(draw_call) = draw_item.kind.draw_call_mut() {
+                    draw_call
+                }else {
+                    continue;
+                };
+
+                draw_call.draw_uniforms.set_zbias(*zbias);
+                *zbias += zbias_step;
+                draw_call.instance_dirty = false;
+                draw_call.uniforms_dirty = false;
+
+                for texture_id in draw_call.texture_slots.iter().flatten() {
+                    let cxtexture = &mut self.textures[*texture_id];
+                    if cxtexture.update_image || !cxtexture.image_u32.is_empty() && cxtexture.os.texture.pixels.is_empty() {
+                        cxtexture.update_image = false;
+                        cxtexture.os.update_image(
+                            cxtexture.desc.width.unwrap(),
+                            cxtexture.desc.height.unwrap(),
+                            &cxtexture.image_u32
+                        );
+                    }
+                }
+
+                if let Err(err) = self.rasterize_draw_call(pass_id, draw_list_id, draw_item_id, framebuffer) {
+                    error!("{}", self.live_registry.borrow().live_error_to_live_file_error(err));
+                }
+            }
+        }
+    }
+
+    fn rasterize_draw_call(
+        &self,
+        pass_id: PassId,
+        draw_list_id: DrawListId,
+        draw_item_id: usize,
+        framebuffer: &mut HeadlessFramebuffer,
+    ) -> Result<(), LiveError> {
+        let draw_list = &self.draw_lists[draw_list_id];
+        let draw_item = &draw_list.draw_items[draw_item_id];
+        let draw_call = draw_item.kind.draw_call().unwrap();
+        let mapping = &self.draw_shaders.shaders[draw_call.draw_shader.draw_shader_id].mapping;
+
+        let instances = draw_item.instances.as_ref().unwrap();
+        if mapping.instances.total_slots == 0 || instances.len() < mapping.instances.total_slots {
+            return Ok(())
+        }
+        let geometry = if let Some(geometry_id) = draw_call.geometry_id {
+            &self.geometries[geometry_id]
+        }
+        else {
+            return Ok(())
+        };
+        let draw_shader_ptr = draw_call.draw_shader.draw_shader_ptr;
+        let draw_shader_def = if let Some(draw_shader_def) = self.shader_registry.draw_shader_defs.get(&draw_shader_ptr) {
+            draw_shader_def
+        }
+        else { // shader didnt compile somehow
+            return Ok(())
+        };
+
+        let live_registry = self.live_registry.borrow();
+        let mut interpreter = ShaderInterpreter::new(&live_registry, &self.shader_registry, draw_shader_ptr);
+
+        let draw_uniforms = &draw_call.draw_uniforms;
+        set_inputs(&mut interpreter, &mapping.pass_uniforms, self.passes[pass_id].pass_uniforms.as_slice())?;
+        set_inputs(&mut interpreter, &mapping.view_uniforms, &draw_list.draw_list_uniforms.view_transform)?;
+        set_inputs(&mut interpreter, &mapping.draw_uniforms, &[draw_uniforms.draw_zbias, draw_uniforms.pad1, draw_uniforms.pad2, draw_uniforms.pad3])?;
+        set_inputs(&mut interpreter, &mapping.user_uniforms, &draw_call.user_uniforms)?;
+        set_inputs(&mut interpreter, &mapping.live_uniforms, &mapping.live_uniforms_buf)?;
+
+        for (input, texture_id) in mapping.textures.iter().zip(draw_call.texture_slots.iter()) {
+            if let Some(texture_id) = texture_id {
+                interpreter.set_texture(input.id, &self.textures[*texture_id].os.texture);
+            }
+        }
+
+        // what the pixel shader gets interpolated between the vertices
+        let varyings: Vec<(LiveId, TyLit)> = draw_shader_def.fields.iter().filter_map( | field | {
+            match field.kind {
+                DrawShaderFieldKind::Geometry {..} | DrawShaderFieldKind::Varying {..} => {
+                    Some((field.ident.0, field.ty_expr.ty.borrow().as_ref()?.maybe_ty_lit()?))
+                }
+                _ => None
+            }
+        }).collect();
+
+        let mut vertices = Vec::new();
+        for instance in instances.chunks_exact(mapping.instances.total_slots) {
+            set_inputs(&mut interpreter, &mapping.instances, instance)?;
+
+            vertices.clear();
+            for vertex in geometry.vertices.chunks_exact(mapping.geometries.total_slots.max(1)) {
+                set_inputs(&mut interpreter, &mapping.geometries, vertex)?;
+                let pos = interpreter.vertex()?.as_vec4().unwrap_or_default();
+                let varyings = varyings.iter().map( | (id, _) | {
+                    interpreter.field(*id).and_then(Value::as_num).map( | num | num.lanes[0]).unwrap_or([0.0; 16])
+                }).collect();
+                vertices.push(ClipVertex {pos, varyings});
+            }
+
+            for triangle in geometry.indices.chunks_exact(3) {
+                let a = vertices.get(triangle[0] as usize);
+                let b = vertices.get(triangle[1] as usize);
+                let c = vertices.get(triangle[2] as usize);
+                if let (Some(a), Some(b), Some(c)) = (a, b, c) {
+                    rasterize_triangle(&mut interpreter, [a, b, c], &varyings, framebuffer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn setup_render_pass(&mut self, pass_id: PassId,) -> Option<DVec2> {
+
+        let dpi_factor = self.passes[pass_id].dpi_factor.unwrap();
+        let pass_rect = self.get_pass_rect(pass_id, dpi_factor).unwrap();
+
+        self.passes[pass_id].paint_dirty = false;
+
+        if pass_rect.size.x <0.5 || pass_rect.size.y < 0.5 {
+            return None
+        }
+
+        self.passes[pass_id].set_matrix(pass_rect.pos, pass_rect.size);
+        self.passes[pass_id].set_dpi_factor(dpi_factor);
+        Some(pass_rect.size)
+    }
+
+    pub fn draw_pass_to_framebuffer(
+        &mut self,
+        pass_id: PassId,
+        framebuffer: &mut HeadlessFramebuffer,
+        window_geom: &WindowGeom,
+    ) {
+        let draw_list_id = self.passes[pass_id].main_draw_list_id.unwrap();
+
+        self.setup_render_pass(pass_id);
+
+        let width = (window_geom.inner_size.x * window_geom.dpi_factor).floor() as usize;
+        let height = (window_geom.inner_size.y * window_geom.dpi_factor).floor() as usize;
+        if framebuffer.width != width || framebuffer.height != height {
+            *framebuffer = HeadlessFramebuffer::new(width, height);
+        }
+
+        let clear_color = if self.passes[pass_id].color_textures.is_empty() {
+            self.passes[pass_id].clear_color
+        }
+        else {
+            match self.passes[pass_id].color_textures[0].clear_color {
+                PassClearColor::InitWith(color) => color,
+                PassClearColor::ClearWith(color) => color
+            }
+        };
+        let clear_depth = match self.passes[pass_id].clear_depth {
+            PassClearDepth::InitWith(depth) => depth,
+            PassClearDepth::ClearWith(depth) => depth
+        };
+
+        if !self.passes[pass_id].dont_clear {
+            framebuffer.clear(Some(clear_color), Some(clear_depth));
+        }
+
+        let mut zbias = 0.0;
+        let zbias_step = self.passes[pass_id].zbias_step;
+
+        self.render_view(
+            pass_id,
+            draw_list_id,
+            &mut zbias,
+            zbias_step,
+            framebuffer,
+        );
+    }
+
+    pub fn draw_pass_to_texture(
+        &mut self,
+        pass_id: PassId,
+    ) {
+        let draw_list_id = self.passes[pass_id].main_draw_list_id.unwrap();
+
+        let pass_size = if let Some(pz) = self.setup_render_pass(pass_id) {
+            pz
+        }
+        else {
+            return
+        };
+
+        let dpi_factor = self.passes[pass_id].dpi_factor.unwrap();
+        let default_size = dpi_factor * pass_size;
+
+        let mut framebuffer = HeadlessFramebuffer::default();
+        let mut clear_color = None;
+        let mut clear_depth = None;
+
+        // the pixel shader has a single output, so only the first color texture is drawn into
+        let color_texture = self.passes[pass_id].color_textures.first().cloned();
+        if let Some(color_texture) = &color_texture {
+            let cxtexture = &mut self.textures[color_texture.texture_id];
+            let allocated = cxtexture.os.update_render_target(&cxtexture.desc, default_size, false);
+            match color_texture.clear_color {
+                PassClearColor::InitWith(color) => if allocated {
+                    clear_color = Some(color);
+                },
+                PassClearColor::ClearWith(color) => {
+                    clear_color = Some(color);
+                }
+            }
+            framebuffer.width = cxtexture.os.texture.width;
+            framebuffer.height = cxtexture.os.texture.height;
+            framebuffer.color = std::mem::take(&mut cxtexture.os.texture.pixels);
+        }
+        else {
+            framebuffer = HeadlessFramebuffer::new(default_size.x as usize, default_size.y as usize);
+            framebuffer.depth.clear();
+        }
+
+        let depth_texture = self.passes[pass_id].depth_texture;
+        if let Some(depth_texture_id) = depth_texture {
+            let cxtexture = &mut self.textures[depth_texture_id];
+            let allocated = cxtexture.os.update_render_target(&cxtexture.desc, default_size, true);
+            match self.passes[pass_id].clear_depth {
+                PassClearDepth::InitWith(depth) => if allocated {
+                    clear_depth = Some(depth);
+                },
+                PassClearDepth::ClearWith(depth) => {
+                    clear_depth = Some(depth);
+                }
+            }
+            if cxtexture.os.depth.len() == framebuffer.color.len() {
+                framebuffer.depth = std::mem::take(&mut cxtexture.os.depth);
+            }
+        }
+
+        framebuffer.clear(clear_color, clear_depth);
+
+        let mut zbias = 0.0;
+        let zbias_step = self.passes[pass_id].zbias_step;
+
+        self.render_view(
+            pass_id,
+            draw_list_id,
+            &mut zbias,
+            zbias_step,
+            &mut framebuffer,
+        );
+
+        if let Some(color_texture) = &color_texture {
+            self.textures[color_texture.texture_id].os.texture.pixels = framebuffer.color;
+        }
+        if let Some(depth_texture_id) = depth_texture {
+            if !framebuffer.depth.is_empty() {
+                self.textures[depth_texture_id].os.depth = framebuffer.depth;
+            }
+        }
+    }
+
+    pub fn headless_compile_shaders(&mut self) {
+        // the interpreter runs the analysed shaders directly, nothing to generate
+        self.draw_shaders.compile_set.clear();
+    }
+}
+
+fn set_inputs(interpreter: &mut ShaderInterpreter, inputs: &DrawShaderInputs, slots: &[f32]) -> Result<(), LiveError> {
+    for input in &inputs.inputs {
+        if let Some(slots) = slots.get(input.offset..input.offset + input.slots) {
+            interpreter.set_field_slots(input.id, slots)?;
+        }
+    }
+    Ok(())
+}
+
+fn edge(a: Vec3, b: Vec3, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+// pixels exactly on an edge shared by two triangles belong to one of them
+fn owns_edge(a: Vec3, b: Vec3) -> bool {
+    b.y > a.y || b.y == a.y && b.x < a.x
+}
+
+fn rasterize_triangle(
+    interpreter: &mut ShaderInterpreter,
+    vertices: [&ClipVertex; 3],
+    varyings: &[(LiveId, TyLit)],
+    framebuffer: &mut HeadlessFramebuffer,
+) -> Result<(), LiveError> {
+    let width = framebuffer.width as f32;
+    let height = framebuffer.height as f32;
+
+    let mut screen = [Vec3::default(); 3];
+    let mut inv_w = [0.0; 3];
+    for (i, vertex) in vertices.iter().enumerate() {
+        // there is no clipping, triangles reaching behind the camera are dropped
+        if vertex.pos.w <= 0.0 {
+            return Ok(())
+        }
+        inv_w[i] = 1.0 / vertex.pos.w;
+        screen[i] = vec3(
+            (vertex.pos.x * inv_w[i] * 0.5 + 0.5) * width,
+            (0.5 - vertex.pos.y * inv_w[i] * 0.5) * height,
+            vertex.pos.z * inv_w[i] * 0.5 + 0.5
+        );
+    }
+
+    let mut order = [0, 1, 2];
+    let mut area = edge(screen[0], screen[1], screen[2].x, screen[2].y);
+    if area == 0.0 {
+        return Ok(())
+    }
+    if area < 0.0 {
+        order.swap(1, 2);
+        area = -area;
+    }
+    let s = [screen[order[0]], screen[order[1]], screen[order[2]]];
+    let inv_w = [inv_w[order[0]], inv_w[order[1]], inv_w[order[2]]];
+    let v = [vertices[order[0]], vertices[order[1]], vertices[order[2]]];
+    // the weight of vertex k comes from the edge opposite of it
+    let edges = [(s[1], s[2]), (s[2], s[0]), (s[0], s[1])];
+    let owned = [owns_edge(s[1], s[2]), owns_edge(s[2], s[0]), owns_edge(s[0], s[1])];
+
+    let x0 = s.iter().map( | p | p.x).fold(f32::INFINITY, f32::min).floor().max(0.0) as usize;
+    let y0 = s.iter().map( | p | p.y).fold(f32::INFINITY, f32::min).floor().max(0.0) as usize;
+    let x1 = s.iter().map( | p | p.x).fold(f32::NEG_INFINITY, f32::max).ceil().min(width) as usize;
+    let y1 = s.iter().map( | p | p.y).fold(f32::NEG_INFINITY, f32::max).ceil().min(height) as usize;
+
+    let weights = | x: f32, y: f32 | {
+        let mut w = [0.0; 3];
+        for (k, (a, b)) in edges.iter().enumerate() {
+            w[k] = edge(*a, *b, x, y) / area;
+        }
+        w
+    };
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let w = weights(px, py);
+            if (0..3).any( | k | w[k] < 0.0 || w[k] == 0.0 && !owned[k]) {
+                continue;
+            }
+            // relative to the first vertex, so flat triangles get exactly the same depth
+            let depth = s[0].z + w[1] * (s[1].z - s[0].z) + w[2] * (s[2].z - s[0].z);
+            if !(0.0..=1.0).contains(&depth) {
+                continue;
+            }
+            let index = y * framebuffer.width + x;
+            if let Some(dst_depth) = framebuffer.depth.get(index) {
+                if depth > *dst_depth {
+                    continue;
+                }
+            }
+
+            // lane 0 is the pixel, lanes 1 and 2 its right and lower neighbour
+            let mut lane_weights = [w, weights(px + 1.0, py), weights(px, py + 1.0)];
+            for w in &mut lane_weights {
+                let sum = w[0] * inv_w[0] + w[1] * inv_w[1] + w[2] * inv_w[2];
+                for k in 0..3 {
+                    w[k] *= inv_w[k] / sum;
+                }
+            }
+            for (j, (id, ty)) in varyings.iter().enumerate() {
+                let mut lanes = [[0.0; 16]; LANES];
+                for (lane, w) in lanes.iter_mut().zip(lane_weights.iter()) {
+                    for (slot, out) in lane.iter_mut().enumerate() {
+                        *out = w[0] * v[0].varyings[j][slot] + w[1] * v[1].varyings[j][slot] + w[2] * v[2].varyings[j][slot];
+                    }
+                }
+                interpreter.set_field(*id, Value::Num(Num {ty: *ty, lanes}))?;
+            }
+
+            let color = interpreter.pixel()?.as_vec4().unwrap_or_default();
+            let src = vec4(
+                color.x.clamp(0.0, 1.0),
+                color.y.clamp(0.0, 1.0),
+                color.z.clamp(0.0, 1.0),
+                color.w.clamp(0.0, 1.0)
+            );
+            let dst = framebuffer.color[index];
+            framebuffer.color[index] = src + dst * (1.0 - src.w);
+            if let Some(dst_depth) = framebuffer.depth.get_mut(index) {
+                *dst_depth = depth;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+pub struct CxOsDrawShader {
+}
+
+#[derive(Clone, Default)]
+pub struct CxOsGeometry {
+}
+
+#[derive(Clone, Default)]
+pub struct CxOsView {
+}
+
+#[derive(Default, Clone)]
+pub struct CxOsDrawCall {
+}
+
+#[derive(Default, Clone)]
+pub struct CxOsTexture {
+    pub alloc_desc: Option<TextureDesc>,
+    pub texture: ShaderTexture,
+    pub depth: Vec<f32>,
+}
+
+impl CxOsTexture {
+    /// Converts the ARGB pixels of an image to the colors sample2d returns.
+    fn update_image(&mut self, width: usize, height: usize, image_u32: &[u32]) {
+        self.texture.width = width;
+        self.texture.height = height;
+        self.texture.pixels = image_u32.iter().map( | v | vec4(
+            ((v >> 16) & 0xff) as f32 / 255.0,
+            ((v >> 8) & 0xff) as f32 / 255.0,
+            (v & 0xff) as f32 / 255.0,
+            (v >> 24) as f32 / 255.0,
+        )).collect();
+    }
+
+    /// Sizes the texture for a pass, returns true when it was (re)allocated.
+    fn update_render_target(&mut self, desc: &TextureDesc, default_size: DVec2, is_depth: bool) -> bool {
+        let width = desc.width.unwrap_or(default_size.x as usize);
+        let height = desc.height.unwrap_or(default_size.y as usize);
+
+        if self.texture.width == width && self.texture.height == height && self.alloc_desc.as_ref() == Some(desc) {
+            return false
+        }
+        self.alloc_desc = Some(*desc);
+        self.texture.width = width;
+        self.texture.height = height;
+        if is_depth {
+            self.depth = vec![1.0; width * height];
+        }
+        else {
+            self.texture.pixels = vec![Vec4::default(); width * height];
+        }
+        true
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct CxOsPass {
+}
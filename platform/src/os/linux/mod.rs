@@ -1,26 +1,30 @@
-#[cfg(not(any(linux_direct, target_os="android")))]
+#[cfg(not(any(linux_direct, feature="headless", target_os="android")))]
 pub mod x11; 
 
-#[cfg(linux_direct)]
+#[cfg(all(linux_direct, not(feature="headless")))]
 pub mod direct;
 
+#[cfg(feature="headless")]
+pub mod headless;
+
 pub mod gl_sys;
 pub mod libc_sys;
+#[cfg(not(feature="headless"))]
 pub mod opengl;
 
-#[cfg(not(target_os="android"))]
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub mod alsa_sys;
-#[cfg(not(target_os="android"))]
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub mod linux_media;
-#[cfg(not(target_os="android"))]
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub mod alsa_audio;
-#[cfg(not(target_os="android"))]
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub mod alsa_midi;
 #[cfg(not(target_os="android"))]
 pub mod select_timer;
-#[cfg(not(target_os="android"))] 
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub mod pulse_audio; 
-#[cfg(not(target_os="android"))]
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub mod pulse_sys;
 
 #[cfg(target_os="android")]
@@ -29,18 +33,35 @@ pub mod android;
 #[cfg(target_os="android")]
 pub(crate) use self::android::android::CxOs;
 
-#[cfg(not(any(linux_direct, target_os="android")))]
+#[cfg(not(any(linux_direct, feature="headless", target_os="android")))]
 pub(crate) use self::x11::linux_x11::*;
 
 
-#[cfg(linux_direct)]
+#[cfg(all(linux_direct, not(feature="headless")))]
 pub(crate) use self::direct::linux_direct::*;
 
+#[cfg(feature="headless")]
+pub(crate) use self::headless::linux_headless::*;
+
+#[cfg(not(feature="headless"))]
 pub(crate) use self::opengl::*; 
 
-#[cfg(not(target_os="android"))]
+#[cfg(feature="headless")]
+pub(crate) use self::headless::software::*;
+
+#[cfg(feature="headless")]
+pub use self::headless::{
+    linux_headless::{HeadlessApp, HeadlessWindow},
+    headless_event::HeadlessEvent,
+    software::HeadlessFramebuffer,
+};
+
+#[cfg(not(any(feature="headless", target_os="android")))]
 pub(crate) use self::alsa_midi::{OsMidiInput, OsMidiOutput};
 
+#[cfg(feature="headless")]
+pub(crate) use self::headless::headless_media::{OsMidiInput, OsMidiOutput};
+
 #[cfg(target_os="android")]
 pub(crate) use self::android::android_midi::{OsMidiInput, OsMidiOutput};
 
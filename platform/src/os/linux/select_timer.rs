@@ -69,6 +69,11 @@ impl SelectTimers {
        // println!("RETURNED!");
     }
     
+    /// Seconds from the last `update_timers` until the first timer fires.
+    pub fn next_timeout(&self) -> Option<f64> {
+        self.timers.front().map( | timer | timer.delta_timeout)
+    }
+    
     pub fn time_now(&self) -> f64 {
         let time_now = Instant::now(); //unsafe {mach_absolute_time()};
         (time_now.duration_since(self.time_start)).as_micros() as f64 / 1_000_000.0
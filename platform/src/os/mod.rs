@@ -34,13 +34,13 @@ pub use crate::os::linux::*;
 #[cfg(target_os = "android")]
 pub use crate::os::linux::android::android_media::*;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
 pub use crate::os::linux::linux_media::*;
 
 #[cfg(target_os = "linux")]
 pub use crate::os::linux::*;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
 pub use crate::os::linux::linux_media::*;
 
 
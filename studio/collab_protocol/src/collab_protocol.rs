@@ -2,6 +2,7 @@ use {
     crate::{
         makepad_editor_core::{
             delta::Delta,
+            position::Position,
             text::Text
         },
         makepad_live_id::*,
@@ -32,12 +33,33 @@ use {
 /// Whenever a server applies a delta to a file, it notifies all the participants of that file
 /// except the one from which the request to apply the delta originated of this fact. This allows
 /// the participants to update their revision of the file accordingly.
+/// 
+/// Deltas are only applied in memory. A file is written back to disk when a client requests to
+/// save it. The server also watches the disk for changes made by other programs. When an open file
+/// without unsaved changes is changed on disk, the server applies a delta of its own that brings
+/// the file in line with the disk, and notifies every participant of it. When the file does have
+/// unsaved changes, the server leaves it alone and notifies the participants of the conflict
+/// instead.
+/// 
+/// Participants can see each other. When a client joins or leaves a file, the other participants
+/// are notified. Each participant has a set of selections, which it sends to the server for a
+/// given revision of the file, just like a delta. The server transforms the selections against any
+/// deltas the client has not yet seen, keeps them up to date as further deltas are applied, and
+/// notifies the other participants whenever they change.
+/// 
+/// Every delta that the server applies to a file is also appended to a log on disk. This allows a
+/// client that lost its connection to catch up: after reconnecting, it requests to resync each of
+/// its files from the last revision it saw, and the server replays the deltas it missed. The log
+/// also allows the server to recover files with unsaved changes after it was restarted.
  
 /// A type for representing a request to the collab server.
 #[derive(Clone, Debug, SerBin, DeBin)]
 pub enum CollabRequest {
     /// Requests the collab server to return its file tree. 
     LoadFileTree{ with_data: bool },
+    /// Requests the collab server to set the name under which the client is shown to other
+    /// participants.
+    SetParticipantName(String),
     /// Requests the collab server to add the client as a participant to the file with the given id.
     /// If the client is the first participant for the file, this also causes the file to be opened
     /// on the server.
@@ -45,10 +67,32 @@ pub enum CollabRequest {
     /// Requests the collab server to apply the given delta to the given revision of the file with
     /// the given id.
     ApplyDelta(TextFileId, u32, Delta),
+    /// Requests the collab server to set the selections of the client for the given revision of the
+    /// file with the given id.
+    UpdateSelections(TextFileId, u32, Vec<Selection>),
     /// Requests the collab server to remove the client as a participant from the file with the
     /// given id. If the client was the last participant for the file, this also closes the file on
     /// the collab server.
     CloseFile(TextFileId),
+    /// Requests the collab server to write the newest revision of the file with the given id to
+    /// disk. If the file was changed on disk since it was last read or written by the server, this
+    /// fails with `CollabError::FileChangedOnDisk`, unless `overwrite` is set.
+    SaveFile { file_id: TextFileId, overwrite: bool },
+    /// Requests the collab server to discard the unsaved changes to the file with the given id, by
+    /// applying a delta that brings it in line with its contents on disk.
+    RevertFile(TextFileId),
+    /// Requests the collab server to create a new, empty file at the given path.
+    CreateFile(UnixPathBuf),
+    /// Requests the collab server to rename the file or directory at the first path to the second
+    /// path. Open files keep their id.
+    RenameFile(UnixPathBuf, UnixPathBuf),
+    /// Requests the collab server to delete the file or directory at the given path.
+    DeleteFile(UnixPathBuf),
+    /// Requests the collab server to add the client as a participant to the file at the given path,
+    /// and to return every delta that was applied to the file after the given revision. This is
+    /// used by a client that reconnects to resume editing a file it had open before, without
+    /// losing its own unconfirmed deltas.
+    Resync(UnixPathBuf, u32),
 }
 
 /// A type for representing either a response or a notification from the collab server.
@@ -56,6 +100,9 @@ pub enum CollabRequest {
 pub enum CollabClientAction {
     Response(CollabResponse),
     Notification(CollabNotification),
+    /// Never sent by the collab server itself. The client sends this to itself when the connection
+    /// to the server was lost and has been reestablished, so every open file should be resynced.
+    Reconnected,
 }
 
 /// A type for representing a response from the collab server.
@@ -65,15 +112,53 @@ pub enum CollabClientAction {
 pub enum CollabResponse {
     /// The result of requesting the collab server to return its file tree.
     LoadFileTree(Result<FileTreeData, CollabError>),
+    /// The result of requesting the collab server to set the name of the client, and the id under
+    /// which the client is known to other participants.
+    SetParticipantName(Result<ParticipantId, CollabError>),
     /// The result of requesting the collab server to add the client as a participant to the file
-    /// with the given id.
-    OpenFile(Result<(TextFileId, u32, Text), CollabError>),
+    /// with the given id, with the revision and contents of the file, and the other participants
+    /// for it. The selections of the other participants are for the same revision.
+    OpenFile(Result<(TextFileId, u32, Text, Vec<ParticipantData>), CollabError>),
     /// The result of requesting the collab server to apply a delta to a revision of the file with
     /// the given id.
     ApplyDelta(Result<TextFileId, CollabError>),
+    /// The result of requesting the collab server to set the selections of the client for a
+    /// revision of the file with the given id.
+    UpdateSelections(Result<TextFileId, CollabError>),
     /// The result of requesting the collab server to remove the client as a participant from the
     /// file with the given id.
     CloseFile(Result<TextFileId, CollabError>),
+    /// The result of requesting the collab server to save the file with the given id, and the
+    /// revision that was written to disk.
+    SaveFile(Result<(TextFileId, u32), CollabError>),
+    /// The result of requesting the collab server to revert the file with the given id. The delta
+    /// that reverts the file is sent to every participant as a `FileWasChangedOnDisk` notification.
+    RevertFile(Result<TextFileId, CollabError>),
+    /// The result of requesting the collab server to create a file at the given path.
+    CreateFile(Result<UnixPathBuf, CollabError>),
+    /// The result of requesting the collab server to rename a file or directory.
+    RenameFile(Result<(UnixPathBuf, UnixPathBuf), CollabError>),
+    /// The result of requesting the collab server to delete a file or directory.
+    DeleteFile(Result<UnixPathBuf, CollabError>),
+    /// The result of requesting the collab server to resync the file at the given path.
+    Resync(Result<ResyncData, CollabError>),
+}
+
+/// A type for representing the data that is needed to resync a file.
+#[derive(Clone, Debug, SerBin, DeBin)]
+pub struct ResyncData {
+    /// The path to the file.
+    pub path: UnixPathBuf,
+    /// The id of the file. This is not necessarily the same id the file had before.
+    pub file_id: TextFileId,
+    /// The revision of the file after applying `deltas`.
+    pub revision: u32,
+    /// The deltas that were applied to the file after the requested revision, in order, together
+    /// with the id of the participant that applied them. Deltas that were applied by the server
+    /// itself, such as for changes on disk, have no participant.
+    pub deltas: Vec<(Option<ParticipantId>, Delta)>,
+    /// The other participants for the file. Their selections are for `revision`.
+    pub participants: Vec<ParticipantData>,
 }
 
 /// A type for representing data about a file tree.
@@ -111,6 +196,70 @@ pub enum CollabNotification {
     /// Notifies the client that another client applied the given delta to the file with the given
     /// id. This is only sent for files for which the client is a participant.
     DeltaWasApplied(TextFileId, Delta),
+    /// Notifies the client that a participant joined the file with the given id, or changed its
+    /// name.
+    ParticipantJoined(TextFileId, ParticipantData),
+    /// Notifies the client that the participant with the given id left the file with the given id.
+    ParticipantLeft(TextFileId, ParticipantId),
+    /// Notifies the client that the participant with the given id changed its selections in the
+    /// file with the given id. The selections are for the given revision of the file, which
+    /// includes every delta the client was notified of so far, but may also include deltas of the
+    /// client itself that it has not yet seen confirmed.
+    SelectionsWereUpdated(TextFileId, u32, ParticipantId, Vec<Selection>),
+    /// Notifies the client that the file with the given id was changed on disk, and that the server
+    /// applied the given delta to bring it in line with the disk. This is handled in the same way
+    /// as `DeltaWasApplied`, except that it is sent to every participant, and leaves the file
+    /// without unsaved changes.
+    FileWasChangedOnDisk(TextFileId, Delta),
+    /// Notifies the client that the file with the given id was changed on disk while it had
+    /// unsaved changes. The server keeps the unsaved changes. The client can either save them with
+    /// `overwrite` set, or discard them with a `RevertFile` request.
+    FileConflict(TextFileId),
+    /// Notifies the client that the file with the given id was saved at the given revision by
+    /// another client.
+    FileWasSaved(TextFileId, u32),
+    /// Notifies the client that a file was created at the given path, either by another client or
+    /// on disk. This is sent to every connected client.
+    FileWasCreated(UnixPathBuf),
+    /// Notifies the client that the file or directory at the first path was renamed to the second
+    /// path by another client. This is sent to every connected client.
+    FileWasRenamed(UnixPathBuf, UnixPathBuf),
+    /// Notifies the client that the file or directory at the given path was deleted, either by
+    /// another client or on disk. This is sent to every connected client.
+    FileWasDeleted(UnixPathBuf),
+}
+
+/// An identifier for a participant. A client has the same participant id for every file.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, SerBin, DeBin)]
+pub struct ParticipantId(pub u64);
+
+/// A type for representing data about a participant for a file.
+#[derive(Clone, Debug, SerBin, DeBin)]
+pub struct ParticipantData {
+    /// The id of this participant.
+    pub id: ParticipantId,
+    /// The name of this participant. This is empty if the participant did not set a name.
+    pub name: String,
+    /// The selections of this participant in the file.
+    pub selections: Vec<Selection>,
+}
+
+/// A type for representing a selection of a participant.
+/// 
+/// The caret of the selection is at `head`. The selection extends from `head` to `tail`, which
+/// can lie either before or after it. If both are the same, the selection is empty.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, SerBin, DeBin)]
+pub struct Selection {
+    pub head: Position,
+    pub tail: Position,
+}
+
+impl Selection {
+    /// Applies the given delta to this selection.
+    pub fn apply_delta(&mut self, delta: &Delta) {
+        self.head = self.head.apply_delta(delta);
+        self.tail = self.tail.apply_delta(delta);
+    }
 }
 
 /// A type for representing errors from the collab server.
@@ -122,6 +271,17 @@ pub enum CollabError {
     /// Attempted to either apply a delta to, or remove the client as a participant from a file for
     /// which it was not a participant.
     NotAParticipant,
+    /// Attempted to save a file that was changed on disk since it was last read or written by the
+    /// collab server.
+    FileChangedOnDisk,
+    /// Attempted to access a path outside the root of the collab server.
+    InvalidPath,
+    /// Attempted to resync a file from, or apply a delta or selections to, a revision that is no
+    /// longer (or not yet) available. The client has to open the file again instead.
+    RevisionUnavailable,
+    /// Attempted to save a file that is not valid UTF-8 on disk. Saving it would replace the
+    /// invalid bytes.
+    NotUtf8,
     /// Unknown error
     Unknown(String),
 }
@@ -1,5 +1,6 @@
 use {
     crate::{
+        delta_log::{self, DeltaLog},
         makepad_editor_core::{
             delta::Delta,
             text::Text
@@ -14,18 +15,27 @@ use {
             CollabNotification,
             CollabRequest,
             CollabResponse,
+            ParticipantData,
+            ParticipantId,
+            ResyncData,
+            Selection,
+            unix_path::{Component, UnixPath, UnixPathBuf},
             unix_str::UnixString,
         },
     },
     std::{ 
         cmp::Ordering,
-        collections::{HashMap, VecDeque},
+        collections::{vec_deque, HashMap, HashSet, VecDeque},
+        ffi::OsStr,
         fmt,
         fs,
+        io,
         mem,
-        io::prelude::*,
+        os::unix::ffi::{OsStrExt, OsStringExt},
         path::{Path, PathBuf},
-        sync::{Arc, Mutex, RwLock},
+        sync::{atomic::{self, AtomicBool}, Arc, Mutex, PoisonError, RwLock},
+        thread::{self, JoinHandle},
+        time::{Duration, SystemTime, UNIX_EPOCH},
     },
 };
 
@@ -43,14 +53,56 @@ pub struct CollabServer {
 
 impl CollabServer {
     /// Creates a new collab server rooted at the given path.
+    /// 
+    /// Files that still had unsaved changes when the server last stopped are recovered from their
+    /// logs, and kept open until a client opens and closes them.
     pub fn new<P: Into<PathBuf>>(path: P) -> CollabServer {
+        let mut shared = Shared {
+            path: path.into(),
+            files: LiveIdMap::new(),
+            file_ids_by_path: HashMap::new(),
+            notification_senders_by_connection_id: Mutex::new(HashMap::new()),
+            participant_names_by_connection_id: HashMap::new(),
+            known_file_paths: None,
+        };
+        recover_files(&mut shared);
         CollabServer {
-            next_connection_id: 0,
-            shared: Arc::new(RwLock::new(Shared {
-                path: path.into(),
-                files: LiveIdMap::new(),
-                file_ids_by_path: HashMap::new(),
-            })),
+            // Participant ids are stored in the logs, and clients that resync use them to recognize
+            // their own deltas. Starting from the current time makes sure they are not reused by a
+            // server that was restarted.
+            next_connection_id: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map( | duration | duration.as_micros() as usize)
+                .unwrap_or(0),
+            shared: Arc::new(RwLock::new(shared)),
+        }
+    }
+    
+    /// Compares the files on disk against what the server last saw of them.
+    /// 
+    /// Files that were created or deleted by other programs are broadcast to every connection.
+    /// Open files that were changed on disk are brought in line with the disk if they have no
+    /// unsaved changes, and otherwise reported as a conflict. The first call only takes a snapshot
+    /// of the file tree.
+    pub fn check_disk(&self) {
+        check_disk(&self.shared)
+    }
+    
+    /// Starts a thread that calls `check_disk` every `interval`, until the returned watcher is
+    /// dropped.
+    pub fn watch_files(&self, interval: Duration) -> FileWatcher {
+        let shared = self.shared.clone();
+        let is_stopped = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let is_stopped = is_stopped.clone();
+            move || while !is_stopped.load(atomic::Ordering::Relaxed) {
+                check_disk(&shared);
+                thread::sleep(interval);
+            }
+        });
+        FileWatcher {
+            is_stopped,
+            thread: Some(thread),
         }
     }
     
@@ -61,6 +113,10 @@ impl CollabServer {
     pub fn connect(&mut self, notification_sender: Box<dyn NotificationSender>) -> CollabConnection {
         let connection_id = ConnectionId(self.next_connection_id);
         self.next_connection_id += 1;
+        self.shared.read().unwrap().notification_senders_by_connection_id.lock().unwrap().insert(
+            connection_id,
+            notification_sender.clone(),
+        );
         CollabConnection {
             connection_id,
             shared: self.shared.clone(),
@@ -69,6 +125,21 @@ impl CollabServer {
     }
 }
 
+/// Watches the files of a collab server for changes on disk. See `CollabServer::watch_files`.
+pub struct FileWatcher {
+    is_stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.is_stopped.store(true, atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
 /// A connection to a collab server.
 pub struct CollabConnection {
     // The id for this connection.
@@ -85,27 +156,37 @@ impl CollabConnection {
     /// The embedder is responsible for receiving requests, calling this method to handle them, and
     /// sending back the response.
     pub fn handle_request(&self, request: CollabRequest) -> CollabResponse {
-        use std::{ffi::OsString, os::unix::ffi::OsStringExt};
-
         match request {
             CollabRequest::LoadFileTree {with_data} => CollabResponse::LoadFileTree(self.load_file_tree(with_data)),
+            CollabRequest::SetParticipantName(name) => {
+                CollabResponse::SetParticipantName(self.set_participant_name(name))
+            }
             CollabRequest::OpenFile(path) => {
-                let path = PathBuf::from(OsString::from_vec(path.into_unix_string().into_vec()));
-                let mut base_path = self.shared.read().unwrap().path.clone();
-                base_path.push(path);
-                CollabResponse::OpenFile(self.open_file(base_path))
+                let disk_path = self.shared.read().unwrap().disk_path(&path);
+                CollabResponse::OpenFile(disk_path.and_then( | disk_path | self.open_file(disk_path)))
             }
             CollabRequest::ApplyDelta(text_file_id, revision, delta) => {
                 CollabResponse::ApplyDelta(self.apply_delta(text_file_id, revision, delta))
             }
+            CollabRequest::UpdateSelections(text_file_id, revision, selections) => {
+                CollabResponse::UpdateSelections(self.update_selections(text_file_id, revision, selections))
+            }
             CollabRequest::CloseFile(path) => CollabResponse::CloseFile(self.close_file(path)),
+            CollabRequest::SaveFile {file_id, overwrite} => {
+                CollabResponse::SaveFile(self.save_file(file_id, overwrite))
+            }
+            CollabRequest::RevertFile(file_id) => CollabResponse::RevertFile(self.revert_file(file_id)),
+            CollabRequest::CreateFile(path) => CollabResponse::CreateFile(self.create_file(path)),
+            CollabRequest::RenameFile(old_path, new_path) => {
+                CollabResponse::RenameFile(self.rename_file(old_path, new_path))
+            }
+            CollabRequest::DeleteFile(path) => CollabResponse::DeleteFile(self.delete_file(path)),
+            CollabRequest::Resync(path, revision) => CollabResponse::Resync(self.resync(path, revision)),
         }
     }
     
     // Handles a `LoadFileTree` request.
     fn load_file_tree(&self, with_data: bool) -> Result<FileTreeData, CollabError> {
-        use std::os::unix::ffi::OsStringExt;
-        
         // A recursive helper function for traversing the entries of a directory and creating the
         // data structures that describe them.
         fn get_directory_entries(path: &Path, with_data: bool) -> Result<Vec<DirectoryEntry>, CollabError> {
@@ -117,19 +198,7 @@ impl CollabConnection {
                 let entry_path = entry.path();
                 // Get the file name for the entry.
                 let name = entry.file_name();
-                if let Ok(name_string) = name.into_string() {
-                    if entry_path.is_dir() && name_string == "target"
-                        || name_string.starts_with('.') {
-                        // Skip over directories called "target". This is sort of a hack. The reason
-                        // it's here is that the "target" directory for Rust projects is huge, and
-                        // our current implementation of the file tree widget is not yet fast enough
-                        // to display vast numbers of nodes. We paper over this by pretending the
-                        // "target" directory does not exist.
-                        continue;
-                    }
-                }
-                else {
-                    // Skip over entries with a non UTF-8 file name.
+                if is_hidden(&entry_path, name) {
                     continue;
                 }
                 // Create a `DirectoryEntry` for this entry and add it to the list of entries.
@@ -185,12 +254,40 @@ impl CollabConnection {
         Ok(FileTreeData {path:"".into(), root})
     }
     
+    // Handles a `SetParticipantName` request.
+    fn set_participant_name(&self, name: String) -> Result<ParticipantId, CollabError> {
+        let mut shared_guard = self.shared.write().unwrap();
+        
+        shared_guard.participant_names_by_connection_id.insert(self.connection_id, name.clone());
+        
+        // Update the name for every file for which the client is already a participant.
+        for (file_id, file) in shared_guard.files.iter() {
+            let mut file_guard = file.lock().unwrap();
+            if let Some(participant) = file_guard.participants_by_connection_id.get_mut(&self.connection_id) {
+                participant.name = name.clone();
+                let participant_data = participant.to_participant_data(self.connection_id);
+                file_guard.notify_other_participants(
+                    self.connection_id,
+                    CollabNotification::ParticipantJoined(*file_id, participant_data),
+                );
+            }
+        }
+        
+        Ok(self.connection_id.participant_id())
+    }
+    
     // Handles an `OpenFile` request.
-    fn open_file(&self, path: PathBuf) -> Result<(TextFileId, u32, Text), CollabError> {
+    fn open_file(&self, path: PathBuf) -> Result<(TextFileId, u32, Text, Vec<ParticipantData>), CollabError> {
         // We need to update the list of files in the shared state, so lock it for writing. This is
         // necessary so other clients cannot close the file while we are still in the process of
         // opening it.
         let mut shared_guard = self.shared.write().unwrap();
+        
+        let name = shared_guard
+            .participant_names_by_connection_id
+            .get(&self.connection_id)
+            .cloned()
+            .unwrap_or_default();
 
         match shared_guard.file_ids_by_path.get(&path) {
             Some(&file_id) => {
@@ -213,67 +310,65 @@ impl CollabConnection {
                     // The client is already a participant for this file. Raise an error.
                     return Err(CollabError::AlreadyAParticipant);
                 }
-                // Add the client as a participant.
-                file_guard.participants_by_connection_id.insert(
+                
+                // Get the data for the other participants, so the client can show them, and then
+                // let the other participants know the client joined.
+                let participants = file_guard
+                    .participants_by_connection_id
+                    .iter()
+                    .map( | (connection_id, participant) | participant.to_participant_data(*connection_id))
+                    .collect();
+                let participant = Participant {
+                    their_revision,
+                    name,
+                    selections: Vec::new(),
+                    notification_sender: self.notification_sender.clone(),
+                };
+                file_guard.notify_other_participants(
                     self.connection_id,
-                    Participant {
-                        their_revision,
-                        notification_sender: self.notification_sender.clone(),
-                    },
+                    CollabNotification::ParticipantJoined(file_id, participant.to_participant_data(self.connection_id)),
                 );
                 
+                // Add the client as a participant.
+                file_guard.participants_by_connection_id.insert(self.connection_id, participant);
+                
                 // It's now safe to drop our locks.
                 drop(file_guard);
                 
                 drop(shared_guard);
                 
-                Ok((file_id, their_revision as u32, text))
+                Ok((file_id, their_revision, text, participants))
             }
             None => {
                 // The file was not yet opened, so we need to open it, and then add the client as
                 // the only participant to it. In this case we don't need to lock the file, since
                 // it doesn't yet exist, so nobody else can have a reference to it.
 
-                // Get the contents of the file from disk. If this fails for some unknown reason,
-                // raise an error.
-                let bytes = fs::read(&path).map_err(
-                    | error | CollabError::Unknown(error.to_string())
-                ) ?;
-                // Converts the file contents to a `Text`. This is necessarily a lossy conversion
-                // because `Text` assumes everything is UTF-8 encoded, and this isn't always the
-                // case for files on disk (is this a problem?)
-                let text: Text = Text::from_lines(String::from_utf8_lossy(&bytes)
-                    .lines()
-                    .map( | line | line.chars().collect::<Vec<_ >> ())
-                    .collect::<Vec<_ >>());
+                // Get the contents of the file, either from disk or from its log. If this fails
+                // for some unknown reason, raise an error.
+                let mut file = load_file(&shared_guard, path.clone())?;
+                let our_revision = file.our_revision;
+                let text = file.text.clone();
                 
-                // Create the list of participants for this file and add the file to it.
-                let mut participants_by_connection_id = HashMap::new();
-                participants_by_connection_id.insert(
+                // Add the client as the only participant for this file.
+                file.participants_by_connection_id.insert(
                     self.connection_id,
                     Participant {
-                        their_revision: 0,
+                        their_revision: our_revision,
+                        name,
+                        selections: Vec::new(),
                         notification_sender: self.notification_sender.clone(),
                     },
                 );
-
-                // Create the file
-                let file = Mutex::new(File {
-                    path: path.clone(),
-                    our_revision: 0,
-                    text: text.clone(),
-                    outstanding_deltas: VecDeque::new(),
-                    participants_by_connection_id,
-                });
                 
                 // Insert the file in the shared list of files.
-                let file_id = shared_guard.files.insert_unique(file);
+                let file_id = shared_guard.files.insert_unique(Mutex::new(file));
                 shared_guard.file_ids_by_path.insert(path, file_id);
                 
                 // It's now safe to drop our locks.
                 drop(shared_guard);
                 
-                Ok((file_id, 0, text))
+                Ok((file_id, our_revision, text, Vec::new()))
             }
         }
     }
@@ -292,34 +387,25 @@ impl CollabConnection {
         
         // We're going to modify the state of the file. Lock the file for access so other clients
         // cannot make concurrent modifications to the state while we are still working on it.
-        let mut file_guard = shared_guard.files[file_id].lock().unwrap();
+        let mut file_guard = shared_guard.files.get(&file_id).ok_or(CollabError::NotAParticipant)?.lock().unwrap();
+        if !file_guard
+            .participants_by_connection_id
+            .contains_key(&self.connection_id)
+        {
+            // This can happen when a client that lost its connection still has requests for its
+            // old connection in flight. Raise an error.
+            return Err(CollabError::NotAParticipant);
+        }
         
-        // The number of deltas that has been seen by the server but not the client.
-        let unseen_delta_count = file_guard.our_revision - their_revision ;
-        // The number of deltas that has been seen by both the server and the client.
-        let seen_delta_count = file_guard.outstanding_deltas.len() as u32 - unseen_delta_count;
-
         // Transform the delta against each delta that has been seen by the server but not by the
         // client to obtain a delta that can be applied to the newest revision of the file.
         let mut delta = delta;
-        for unseen_delta in file_guard.outstanding_deltas.iter().skip(seen_delta_count as usize) {
+        for unseen_delta in file_guard.unseen_deltas(their_revision)? {
             delta = unseen_delta.clone().transform(delta).1;
         }
         
-        // Apply the delta to the file, increment its revision by one, and then store the delta
-        // in the list of deltas that has been seen by the server but not *every* client.
-        file_guard.our_revision += 1;
-        file_guard.text.apply_delta(delta.clone());
-        file_guard.outstanding_deltas.push_back(delta.clone());
-        
-        if let Ok(mut file) = fs::File::create(&file_guard.path){
-            if let Err(_) = file.write_all(format!("{}", file_guard.text).as_bytes()){
-                eprintln!("Error writing file {:?}", file_guard.path)
-            }
-        }
-        else{
-            eprintln!("Error opening file {:?}", file_guard.path)
-        }
+        // Apply the delta to the file. It is only written back to disk when a client saves it.
+        file_guard.apply_delta(Some(self.connection_id.participant_id()), delta.clone());
         
         // Update the last revision that has been seen by the client.
         let participant = file_guard
@@ -357,6 +443,53 @@ impl CollabConnection {
         Ok(file_id)
     }
     
+    // Handles an `UpdateSelections` request.
+    fn update_selections(
+        &self,
+        file_id: TextFileId,
+        their_revision: u32,
+        selections: Vec<Selection>,
+    ) -> Result<TextFileId, CollabError> {
+        let shared_guard = self.shared.read().unwrap();
+        
+        let mut file_guard = shared_guard.files.get(&file_id).ok_or(CollabError::NotAParticipant)?.lock().unwrap();
+        if !file_guard
+            .participants_by_connection_id
+            .contains_key(&self.connection_id)
+        {
+            return Err(CollabError::NotAParticipant);
+        }
+        
+        // Transform the selections against each delta that has been seen by the server but not by
+        // the client, just like we do for deltas, so they apply to the newest revision of the file.
+        let mut selections = selections;
+        for unseen_delta in file_guard.unseen_deltas(their_revision)? {
+            for selection in &mut selections {
+                selection.apply_delta(unseen_delta);
+            }
+        }
+        
+        let participant = file_guard
+            .participants_by_connection_id
+            .get_mut(&self.connection_id)
+            .unwrap();
+        participant.their_revision = their_revision;
+        participant.selections = selections.clone();
+        
+        let our_revision = file_guard.our_revision;
+        file_guard.notify_other_participants(
+            self.connection_id,
+            CollabNotification::SelectionsWereUpdated(
+                file_id,
+                our_revision,
+                self.connection_id.participant_id(),
+                selections,
+            ),
+        );
+        
+        Ok(file_id)
+    }
+    
     // Handles a `CloseFile` request.
     fn close_file(&self, file_id: TextFileId) -> Result<TextFileId, CollabError> {
         // We need to update the list of files in the shared state, so lock it for writing. This is
@@ -389,13 +522,20 @@ impl CollabConnection {
         
         if is_empty {
             // If the list of participants for the file is now empty, it's time to close the file
-            // and remove it from the shared list of files.
+            // and remove it from the shared list of files. Unsaved changes stay in the log, so they
+            // are not lost.
+            file_guard.compact_log();
             let path = mem::replace(&mut file_guard.path, PathBuf::new());
             drop(file_guard);
             shared_guard.file_ids_by_path.remove(&path);
             shared_guard.files.remove(&file_id);
         } else {
-            // Otherwise, we can just drop the lock for the file.
+            // Otherwise, let the remaining participants know the client left, and then drop the
+            // lock for the file.
+            file_guard.notify_other_participants(
+                self.connection_id,
+                CollabNotification::ParticipantLeft(file_id, self.connection_id.participant_id()),
+            );
             drop(file_guard);
         }
         
@@ -404,6 +544,513 @@ impl CollabConnection {
         
         Ok(file_id)
     }
+    
+    // Handles a `SaveFile` request.
+    fn save_file(&self, file_id: TextFileId, overwrite: bool) -> Result<(TextFileId, u32), CollabError> {
+        let shared_guard = self.shared.read().unwrap();
+        
+        // Lock the file so no deltas can be applied while we are writing it to disk.
+        let mut file_guard = shared_guard.files.get(&file_id).ok_or(CollabError::NotAParticipant)?.lock().unwrap();
+        if !file_guard
+            .participants_by_connection_id
+            .contains_key(&self.connection_id)
+        {
+            return Err(CollabError::NotAParticipant);
+        }
+        
+        // Writing back a file that was not valid UTF-8 would replace the invalid bytes on disk.
+        if file_guard.disk_format.lossy {
+            return Err(CollabError::NotUtf8);
+        }
+        
+        // Unless we were asked to overwrite it, refuse to save over changes that were made on disk
+        // since we last read or wrote the file. A file that no longer exists can always be saved.
+        if !overwrite && file_guard.path.exists()
+            && read_text(&file_guard.path)? != (file_guard.disk_text.clone(), file_guard.disk_format)
+        {
+            return Err(CollabError::FileChangedOnDisk);
+        }
+        
+        fs::write(&file_guard.path, encode_text(&file_guard.text, file_guard.disk_format)).map_err(
+            | error | CollabError::Unknown(error.to_string())
+        ) ?;
+        file_guard.disk_text = file_guard.text.clone();
+        file_guard.disk_stamp = DiskStamp::of(&file_guard.path);
+        if let Some(log) = &mut file_guard.log {
+            log_error(log.append_save());
+        }
+        
+        let revision = file_guard.our_revision;
+        file_guard.notify_other_participants(
+            self.connection_id,
+            CollabNotification::FileWasSaved(file_id, revision),
+        );
+        
+        Ok((file_id, revision))
+    }
+    
+    // Handles a `RevertFile` request.
+    fn revert_file(&self, file_id: TextFileId) -> Result<TextFileId, CollabError> {
+        let shared_guard = self.shared.read().unwrap();
+        
+        let mut file_guard = shared_guard.files.get(&file_id).ok_or(CollabError::NotAParticipant)?.lock().unwrap();
+        if !file_guard
+            .participants_by_connection_id
+            .contains_key(&self.connection_id)
+        {
+            return Err(CollabError::NotAParticipant);
+        }
+        
+        let disk_stamp = DiskStamp::of(&file_guard.path);
+        let (disk_text, disk_format) = read_text(&file_guard.path)?;
+        file_guard.apply_disk_text(file_id, disk_text, disk_format, disk_stamp);
+        
+        Ok(file_id)
+    }
+    
+    // Handles a `CreateFile` request.
+    fn create_file(&self, path: UnixPathBuf) -> Result<UnixPathBuf, CollabError> {
+        let mut shared_guard = self.shared.write().unwrap();
+        
+        let disk_path = shared_guard.disk_path(&path)?;
+        if let Some(parent) = disk_path.parent() {
+            fs::create_dir_all(parent).map_err( | error | CollabError::Unknown(error.to_string()))?;
+        }
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&disk_path)
+            .map_err( | error | CollabError::Unknown(error.to_string()))?;
+        
+        // Make sure the file watcher does not report the file as created a second time.
+        if let Some(known_file_paths) = &mut shared_guard.known_file_paths {
+            known_file_paths.insert(disk_path);
+        }
+        shared_guard.notify_connections(
+            Some(self.connection_id),
+            CollabNotification::FileWasCreated(path.clone()),
+        );
+        
+        Ok(path)
+    }
+    
+    // Handles a `RenameFile` request.
+    fn rename_file(
+        &self,
+        old_path: UnixPathBuf,
+        new_path: UnixPathBuf,
+    ) -> Result<(UnixPathBuf, UnixPathBuf), CollabError> {
+        // We need to update the paths of open files, so lock the shared state for writing.
+        let mut shared_guard = self.shared.write().unwrap();
+        
+        let old_disk_path = shared_guard.disk_path(&old_path)?;
+        let new_disk_path = shared_guard.disk_path(&new_path)?;
+        // Renaming would silently replace the destination, and an open file at the destination
+        // would lose its entry in the list of files, even if it was since deleted on disk.
+        if new_disk_path.exists() || shared_guard.file_ids_by_path.contains_key(&new_disk_path) {
+            return Err(CollabError::Unknown(io::Error::from(io::ErrorKind::AlreadyExists).to_string()));
+        }
+        fs::rename(&old_disk_path, &new_disk_path).map_err(
+            | error | CollabError::Unknown(error.to_string())
+        ) ?;
+        
+        // Move the logs along with the files, so their history is kept.
+        for (path, log_path) in shared_guard.log_paths() {
+            if path.starts_with(&old_disk_path) {
+                let renamed_log_path = shared_guard.log_path(&rename_path(&path, &old_disk_path, &new_disk_path));
+                log_error(fs::rename(&log_path, &renamed_log_path));
+            }
+        }
+        
+        // Move every open file in the renamed file or directory to its new path. The file ids
+        // stay the same, so participants are not affected.
+        let renamed_file_ids: Vec<_> = shared_guard
+            .file_ids_by_path
+            .iter()
+            .filter( | (path, _) | path.starts_with(&old_disk_path))
+            .map( | (path, file_id) | (path.clone(), *file_id))
+            .collect();
+        for (path, file_id) in renamed_file_ids {
+            let renamed_path = rename_path(&path, &old_disk_path, &new_disk_path);
+            shared_guard.file_ids_by_path.remove(&path);
+            shared_guard.file_ids_by_path.insert(renamed_path.clone(), file_id);
+            let renamed_log_path = shared_guard.log_path(&renamed_path);
+            let mut file_guard = shared_guard.files[file_id].lock().unwrap();
+            file_guard.path = renamed_path;
+            if let Some(log) = &mut file_guard.log {
+                log.set_path(renamed_log_path);
+            }
+        }
+        
+        if let Some(known_file_paths) = &mut shared_guard.known_file_paths {
+            let renamed_paths: Vec<_> = known_file_paths
+                .iter()
+                .filter( | path | path.starts_with(&old_disk_path))
+                .cloned()
+                .collect();
+            for path in renamed_paths {
+                known_file_paths.remove(&path);
+                known_file_paths.insert(rename_path(&path, &old_disk_path, &new_disk_path));
+            }
+        }
+        shared_guard.notify_connections(
+            Some(self.connection_id),
+            CollabNotification::FileWasRenamed(old_path.clone(), new_path.clone()),
+        );
+        
+        Ok((old_path, new_path))
+    }
+    
+    // Handles a `DeleteFile` request.
+    fn delete_file(&self, path: UnixPathBuf) -> Result<UnixPathBuf, CollabError> {
+        let mut shared_guard = self.shared.write().unwrap();
+        
+        let disk_path = shared_guard.disk_path(&path)?;
+        if disk_path.is_dir() {
+            fs::remove_dir_all(&disk_path)
+        } else {
+            fs::remove_file(&disk_path)
+        }.map_err( | error | CollabError::Unknown(error.to_string()))?;
+        
+        // Files that are still open keep their contents in memory, so they can be saved again.
+        // Their logs are kept for the same reason.
+        for (path, log_path) in shared_guard.log_paths() {
+            if path.starts_with(&disk_path) && !shared_guard.file_ids_by_path.contains_key(&path) {
+                log_error(fs::remove_file(&log_path));
+            }
+        }
+        if let Some(known_file_paths) = &mut shared_guard.known_file_paths {
+            known_file_paths.retain( | known_file_path | !known_file_path.starts_with(&disk_path));
+        }
+        shared_guard.notify_connections(
+            Some(self.connection_id),
+            CollabNotification::FileWasDeleted(path.clone()),
+        );
+        
+        Ok(path)
+    }
+    
+    // Handles a `Resync` request.
+    fn resync(&self, path: UnixPathBuf, their_revision: u32) -> Result<ResyncData, CollabError> {
+        let disk_path = self.shared.read().unwrap().disk_path(&path)?;
+        let (file_id, revision, _, participants) = self.open_file(disk_path)?;
+        
+        // Deltas that are applied after we joined the file are sent to us as notifications, so
+        // we only need the ones up to the revision at which we joined.
+        let deltas = {
+            let shared_guard = self.shared.read().unwrap();
+            let file_guard = shared_guard.files[file_id].lock().unwrap();
+            file_guard
+                .log
+                .as_ref()
+                .and_then( | log | DeltaLog::read(log.path()).ok())
+                .filter( | contents | {
+                    contents.base_revision <= their_revision
+                        && their_revision <= revision
+                        && revision <= contents.revision()
+                })
+                .map( | contents | {
+                    let start = (their_revision - contents.base_revision) as usize;
+                    let end = (revision - contents.base_revision) as usize;
+                    contents.deltas[start..end].to_vec()
+                })
+        };
+        match deltas {
+            Some(deltas) => Ok(ResyncData {
+                path,
+                file_id,
+                revision,
+                deltas,
+                participants,
+            }),
+            None => {
+                // The log no longer goes back far enough. Don't leave the client as a participant,
+                // since it cannot catch up.
+                let _ = self.close_file(file_id);
+                Err(CollabError::RevisionUnavailable)
+            }
+        }
+    }
+}
+
+impl Drop for CollabConnection {
+    fn drop(&mut self) {
+        // Leave every file for which the client is still a participant, so the other participants
+        // no longer see it.
+        let file_ids: Vec<_> = self.shared
+            .read()
+            .unwrap()
+            .files
+            .iter()
+            .filter( | (_, file) | {
+                // Still clean up if a request for this file panicked while holding its lock.
+                file.lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .participants_by_connection_id
+                    .contains_key(&self.connection_id)
+            })
+            .map( | (file_id, _) | *file_id)
+            .collect();
+        for file_id in file_ids {
+            let _ = self.close_file(file_id);
+        }
+        
+        let mut shared_guard = self.shared.write().unwrap();
+        shared_guard.participant_names_by_connection_id.remove(&self.connection_id);
+        shared_guard
+            .notification_senders_by_connection_id
+            .lock()
+            .unwrap()
+            .remove(&self.connection_id);
+    }
+}
+
+// Handles `CollabServer::check_disk`.
+fn check_disk(shared: &RwLock<Shared>) {
+    // Scan the file tree before taking any locks, since this can take a while.
+    let root_path = shared.read().unwrap().path.clone();
+    let mut file_paths = HashSet::new();
+    collect_file_paths(&root_path, &mut file_paths);
+    
+    let mut shared_guard = shared.write().unwrap();
+    
+    // Report files that were created or deleted since the last scan. Files that were created,
+    // renamed or deleted by a client are already accounted for by the corresponding request.
+    if let Some(known_file_paths) = &shared_guard.known_file_paths {
+        let mut created_paths: Vec<_> = file_paths.difference(known_file_paths).collect();
+        let mut deleted_paths: Vec<_> = known_file_paths.difference(&file_paths).collect();
+        created_paths.sort();
+        deleted_paths.sort();
+        for path in deleted_paths {
+            shared_guard.notify_connections(
+                None,
+                CollabNotification::FileWasDeleted(shared_guard.unix_path(path)),
+            );
+        }
+        for path in created_paths {
+            shared_guard.notify_connections(
+                None,
+                CollabNotification::FileWasCreated(shared_guard.unix_path(path)),
+            );
+        }
+    }
+    
+    // Bring open files in line with the disk. Checking the stamp first means we only have to read
+    // files that actually changed.
+    for (file_id, file) in shared_guard.files.iter() {
+        let mut file_guard = file.lock().unwrap();
+        let disk_stamp = match DiskStamp::of(&file_guard.path) {
+            Some(disk_stamp) => disk_stamp,
+            // The file was deleted on disk. We keep its contents in memory, so it can be saved
+            // again.
+            None => continue,
+        };
+        if file_guard.disk_stamp == Some(disk_stamp) {
+            continue;
+        }
+        let (disk_text, disk_format) = match read_text(&file_guard.path) {
+            Ok(disk_text) => disk_text,
+            Err(_) => continue,
+        };
+        if disk_text == file_guard.disk_text {
+            // Only the stamp changed, for instance because the file was touched, or its line
+            // breaks were converted.
+            file_guard.disk_format = disk_format;
+            file_guard.disk_stamp = Some(disk_stamp);
+        } else if file_guard.text == file_guard.disk_text || file_guard.text == disk_text {
+            // The file has no unsaved changes (or the same changes were made on disk), so we can
+            // safely bring it in line with the disk.
+            file_guard.apply_disk_text(*file_id, disk_text, disk_format, Some(disk_stamp));
+        } else {
+            // The file has unsaved changes that would be lost. Keep them, and let the participants
+            // decide. Updating the stamp makes sure we only report each change on disk once.
+            file_guard.disk_stamp = Some(disk_stamp);
+            file_guard.notify_all_participants(CollabNotification::FileConflict(*file_id));
+        }
+    }
+    
+    shared_guard.known_file_paths = Some(file_paths);
+}
+
+// Loads the file at the given path. If the file has a log, its revisions continue where the log
+// left off, and any unsaved changes in the log are kept. Otherwise, a new log is started.
+fn load_file(shared: &Shared, path: PathBuf) -> Result<File, CollabError> {
+    let log_path = shared.log_path(&path);
+    let mut file = match DeltaLog::open(&log_path) {
+        Ok((log, contents)) => File {
+            path,
+            our_revision: contents.revision(),
+            text: contents.text,
+            disk_text: contents.disk_text,
+            disk_format: DiskFormat::default(),
+            disk_stamp: None,
+            outstanding_deltas: VecDeque::new(),
+            participants_by_connection_id: HashMap::new(),
+            log: Some(log),
+        },
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                log_error::<()>(Err(error));
+            }
+            let disk_stamp = DiskStamp::of(&path);
+            let (text, disk_format) = read_text(&path)?;
+            let log = DeltaLog::create(&log_path, 0, &text);
+            return Ok(File {
+                path,
+                our_revision: 0,
+                text: text.clone(),
+                disk_text: text,
+                disk_format,
+                disk_stamp,
+                outstanding_deltas: VecDeque::new(),
+                participants_by_connection_id: HashMap::new(),
+                log: log_error(log),
+            });
+        }
+    };
+    
+    // The file may have been changed on disk since the log was written. Without unsaved changes,
+    // we follow the disk. Otherwise, we leave the stamp unset, so the watcher reports a conflict.
+    let disk_stamp = DiskStamp::of(&file.path);
+    if let Ok((disk_text, disk_format)) = read_text(&file.path) {
+        file.disk_format = disk_format;
+        if disk_text == file.disk_text {
+            file.disk_stamp = disk_stamp;
+        } else if file.text == file.disk_text {
+            file.replace_with_disk_text(disk_text, disk_format, disk_stamp);
+        }
+    }
+    Ok(file)
+}
+
+// Loads every file that still has unsaved changes in its log, so they survive a restart of the
+// server. Logs that cannot be read are removed.
+fn recover_files(shared: &mut Shared) {
+    for (path, log_path) in shared.log_paths() {
+        match DeltaLog::open(&log_path) {
+            Ok((log, contents)) => {
+                if contents.text == contents.disk_text {
+                    continue;
+                }
+                let file_id = shared.files.insert_unique(Mutex::new(File {
+                    path: path.clone(),
+                    our_revision: contents.revision(),
+                    text: contents.text,
+                    disk_text: contents.disk_text,
+                    // The file is saved the way it is on disk now, if it still exists.
+                    disk_format: read_text(&path).map( | (_, disk_format) | disk_format).unwrap_or_default(),
+                    disk_stamp: None,
+                    outstanding_deltas: VecDeque::new(),
+                    participants_by_connection_id: HashMap::new(),
+                    log: Some(log),
+                }));
+                shared.file_ids_by_path.insert(path, file_id);
+            }
+            Err(error) => {
+                log_error::<()>(Err(error));
+                log_error(fs::remove_file(&log_path));
+            }
+        }
+    }
+}
+
+// Reports the given error, if any, and returns the value otherwise. Failing to write a log is not
+// fatal, since the file itself is still in memory, so we carry on without it.
+fn log_error<T>(result: io::Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(error) => {
+            eprintln!("Collab server failed to access delta log: {}", error);
+            None
+        }
+    }
+}
+
+// Returns the path that the given path moves to when `old_path` is renamed to `new_path`. The given
+// path is either `old_path` itself, or inside it.
+fn rename_path(path: &Path, old_path: &Path, new_path: &Path) -> PathBuf {
+    let path_in_old_path = path.strip_prefix(old_path).unwrap();
+    if path_in_old_path.as_os_str().is_empty() {
+        // Joining an empty path would add a trailing separator.
+        new_path.to_path_buf()
+    } else {
+        new_path.join(path_in_old_path)
+    }
+}
+
+// Returns `true` if the entry at the given path should not be visible to clients.
+fn is_hidden(path: &Path, name: std::ffi::OsString) -> bool {
+    match name.into_string() {
+        // Skip over directories called "target". This is sort of a hack. The reason it's here is
+        // that the "target" directory for Rust projects is huge, and our current implementation of
+        // the file tree widget is not yet fast enough to display vast numbers of nodes. We paper
+        // over this by pretending the "target" directory does not exist.
+        Ok(name) => path.is_dir() && name == "target" || name.starts_with('.'),
+        // Skip over entries with a non UTF-8 file name.
+        Err(_) => true,
+    }
+}
+
+// Recursively collects the paths of all files visible to clients in the given directory.
+fn collect_file_paths(path: &Path, file_paths: &mut HashSet<PathBuf>) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let entry_path = entry.path();
+        if is_hidden(&entry_path, entry.file_name()) {
+            continue;
+        }
+        if entry_path.is_dir() {
+            collect_file_paths(&entry_path, file_paths);
+        } else if entry_path.is_file() {
+            file_paths.insert(entry_path);
+        }
+    }
+}
+
+// Reads the file at the given path as a `Text`, together with the format needed to write it back
+// unchanged. Invalid UTF-8 is replaced, and the format records that this happened.
+fn read_text(path: &Path) -> Result<(Text, DiskFormat), CollabError> {
+    let bytes = fs::read(path).map_err( | error | CollabError::Unknown(error.to_string()))?;
+    let mut disk_format = DiskFormat::default();
+    let mut string = match String::from_utf8(bytes) {
+        Ok(string) => string,
+        Err(error) => {
+            disk_format.lossy = true;
+            String::from_utf8_lossy(error.as_bytes()).into_owned()
+        }
+    };
+    // Only use "\r\n" line breaks if every line break is one, so files with mixed line breaks keep
+    // their "\r"s in the text, and are written back unchanged.
+    let bytes = string.as_bytes();
+    disk_format.crlf = string.contains('\n') && bytes
+        .iter()
+        .enumerate()
+        .all( | (index, byte) | *byte != b'\n' || index > 0 && bytes[index - 1] == b'\r');
+    if disk_format.crlf {
+        string = string.replace("\r\n", "\n");
+    }
+    if string.ends_with('\n') {
+        string.pop();
+        disk_format.trailing_newline = true;
+    }
+    let text = Text::from_lines(string.split('\n').map( | line | line.chars().collect()).collect());
+    Ok((text, disk_format))
+}
+
+// Returns the contents of a file on disk with the given format for the given text. This is the
+// inverse of `read_text`.
+fn encode_text(text: &Text, disk_format: DiskFormat) -> String {
+    let mut string = text.to_string();
+    if disk_format.trailing_newline {
+        string.push('\n');
+    }
+    if disk_format.crlf {
+        string = string.replace('\n', "\r\n");
+    }
+    string
 }
 
 /// A trait for sending notifications over a connection.
@@ -443,12 +1090,84 @@ struct Shared {
     path: PathBuf,
     files: LiveIdMap<TextFileId, Mutex<File >>,
     file_ids_by_path: HashMap<PathBuf, TextFileId>,
+    // Used to send notifications that concern every connection, such as changes to the file tree.
+    notification_senders_by_connection_id: Mutex<HashMap<ConnectionId, Box<dyn NotificationSender >>>,
+    // The names that clients set for themselves, shown to other participants.
+    participant_names_by_connection_id: HashMap<ConnectionId, String>,
+    // The paths of the files on disk as of the last time they were checked, or `None` if they
+    // were never checked.
+    known_file_paths: Option<HashSet<PathBuf >>,
+}
+
+impl Shared {
+    // Returns the path on disk for the given path, which is relative to the root of the server.
+    // Raises an error if the path does not name an entry inside the root.
+    fn disk_path(&self, path: &UnixPath) -> Result<PathBuf, CollabError> {
+        let mut disk_path = self.path.clone();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => disk_path.push(OsStr::from_bytes(name.as_bytes())),
+                Component::CurDir => {}
+                Component::RootDir | Component::ParentDir => return Err(CollabError::InvalidPath),
+            }
+        }
+        if disk_path == self.path {
+            return Err(CollabError::InvalidPath);
+        }
+        Ok(disk_path)
+    }
+    
+    // Returns the path relative to the root of the server for the given path on disk.
+    fn unix_path(&self, disk_path: &Path) -> UnixPathBuf {
+        let path = disk_path.strip_prefix(&self.path).unwrap_or(disk_path);
+        UnixString::from_vec(path.as_os_str().to_os_string().into_vec()).into()
+    }
+    
+    // Returns the path of the log for the given path on disk. Logs are stored in a hidden
+    // directory, so they are not visible to clients.
+    fn log_path(&self, disk_path: &Path) -> PathBuf {
+        self.path.join(".collab").join(delta_log::log_name(&self.unix_path(disk_path)))
+    }
+    
+    // Returns the path on disk and the path of the log for every log that currently exists.
+    fn log_paths(&self) -> Vec<(PathBuf, PathBuf)> {
+        let entries = match fs::read_dir(self.path.join(".collab")) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .flatten()
+            .filter_map( | entry | {
+                let path = delta_log::path_from_log_name(entry.file_name().to_str()?)?;
+                Some((self.disk_path(&path).ok()?, entry.path()))
+            })
+            .collect()
+    }
+    
+    // Sends the given `notification` to every connection except the one with the given
+    // `connection_id`, if any.
+    fn notify_connections(&self, connection_id: Option<ConnectionId>, notification: CollabNotification) {
+        let notification_senders_by_connection_id = self.notification_senders_by_connection_id.lock().unwrap();
+        for (other_connection_id, notification_sender) in notification_senders_by_connection_id.iter() {
+            if Some(*other_connection_id) == connection_id {
+                continue;
+            }
+            notification_sender.send_notification(notification.clone())
+        }
+    }
 }
 
 /// An identifier for a connection.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 struct ConnectionId(usize);
 
+impl ConnectionId {
+    // Returns the id under which the client for this connection is known to other participants.
+    fn participant_id(self) -> ParticipantId {
+        ParticipantId(self.0 as u64)
+    }
+}
+
 #[derive(Debug)]
 struct File {
     // The path to this file on the disk
@@ -457,13 +1176,119 @@ struct File {
     our_revision: u32,
     // The current contents of this file
     text: Text,
+    // The contents of this file on disk, as of the last time it was read or written. The file has
+    // unsaved changes if this differs from `text`.
+    disk_text: Text,
+    // How the file is encoded on disk, so it is saved the same way.
+    disk_format: DiskFormat,
+    // The stamp of this file on disk, as of the last time it was checked.
+    disk_stamp: Option<DiskStamp>,
     // The list of deltas that has been seen by the server, but not yet by *every* client.
     outstanding_deltas: VecDeque<Delta>,
     // A map from connection ids to the participants for this file.
     participants_by_connection_id: HashMap<ConnectionId, Participant>,
+    // The log of every delta applied to this file, or `None` if the log could not be created.
+    log: Option<DeltaLog>,
 }
 
 impl File {
+    // Applies the given `delta` to the newest revision of this file, increments its revision by
+    // one, and then stores the delta in the list of deltas that has been seen by the server but
+    // not *every* client. The delta is also appended to the log, together with the participant
+    // that applied it, or `None` if it originates from the server itself.
+    // 
+    // The selections of every participant are kept up to date with the newest revision.
+    fn apply_delta(&mut self, participant_id: Option<ParticipantId>, delta: Delta) {
+        if let Some(log) = &mut self.log {
+            log_error(log.append_delta(participant_id, delta.clone()));
+        }
+        self.our_revision += 1;
+        self.text.apply_delta(delta.clone());
+        for participant in self.participants_by_connection_id.values_mut() {
+            for selection in &mut participant.selections {
+                selection.apply_delta(&delta);
+            }
+        }
+        self.outstanding_deltas.push_back(delta);
+    }
+    
+    // Returns the deltas that have been seen by the server but not by a client at the given
+    // revision. Raises an error if the revision does not exist yet, or is older than the oldest
+    // revision that was not seen by every client.
+    fn unseen_deltas(&self, their_revision: u32) -> Result<vec_deque::Iter<'_, Delta>, CollabError> {
+        // The number of deltas that has been seen by the server but not the client.
+        let unseen_delta_count = self.our_revision
+            .checked_sub(their_revision)
+            .ok_or(CollabError::RevisionUnavailable)?;
+        // The number of deltas that has been seen by both the server and the client.
+        let seen_delta_count = (self.outstanding_deltas.len() as u32)
+            .checked_sub(unseen_delta_count)
+            .ok_or(CollabError::RevisionUnavailable)?;
+        Ok(self.outstanding_deltas.range(seen_delta_count as usize..))
+    }
+    
+    // Brings this file in line with the given contents on disk, discarding any unsaved changes.
+    // The change is applied as a delta that originates from the server itself, so it is sent to
+    // every participant.
+    fn apply_disk_text(
+        &mut self,
+        file_id: TextFileId,
+        disk_text: Text,
+        disk_format: DiskFormat,
+        disk_stamp: Option<DiskStamp>,
+    ) {
+        if let Some(delta) = self.replace_with_disk_text(disk_text, disk_format, disk_stamp) {
+            self.notify_all_participants(CollabNotification::FileWasChangedOnDisk(file_id, delta));
+        }
+    }
+    
+    // Like `apply_disk_text`, but without notifying the participants. Returns the delta that was
+    // applied, if any.
+    fn replace_with_disk_text(
+        &mut self,
+        disk_text: Text,
+        disk_format: DiskFormat,
+        disk_stamp: Option<DiskStamp>,
+    ) -> Option<Delta> {
+        let delta = Delta::diff(&self.text, &disk_text);
+        self.disk_text = disk_text;
+        self.disk_format = disk_format;
+        self.disk_stamp = disk_stamp;
+        if delta == Delta::identity() {
+            return None;
+        }
+        self.apply_delta(None, delta.clone());
+        Some(delta)
+    }
+    
+    // Replaces the log of this file by one that starts at the newest revision, if the file has no
+    // unsaved changes, or removes it if the file no longer exists. A file with unsaved changes
+    // keeps its log, so they can be recovered.
+    fn compact_log(&mut self) {
+        let log_path = match &self.log {
+            Some(log) => log.path().to_path_buf(),
+            None => return,
+        };
+        if self.text != self.disk_text {
+            return;
+        }
+        if !self.path.exists() {
+            self.log = None;
+            log_error(fs::remove_file(&log_path));
+            return;
+        }
+        self.log = log_error(DeltaLog::create(&log_path, self.our_revision, &self.text));
+    }
+    
+    // Sends the given `notification` to every participant.
+    fn notify_all_participants(&self, notification: CollabNotification) {
+        for participant in self.participants_by_connection_id.values() {
+            participant
+                .notification_sender
+                .send_notification(notification.clone())
+        }
+    }
+    
     // Sends the given `notification` except for the one with the given `connection_id`. This is
     // usually the participant that sent the request that caused this notification to happen in
     // the first place (so there's no need to notify it that something happened).
@@ -484,6 +1309,471 @@ impl File {
 struct Participant {
     // The last revision that has been seen by this participant.
     their_revision: u32,
+    // The name of this participant, shown to other participants.
+    name: String,
+    // The selections of this participant, for the newest revision of the file.
+    selections: Vec<Selection>,
     // Used to send notifications to (the connection of) this participant.
     notification_sender: Box<dyn NotificationSender>,
 }
+
+impl Participant {
+    // Returns the data for this participant that is sent to other participants.
+    fn to_participant_data(&self, connection_id: ConnectionId) -> ParticipantData {
+        ParticipantData {
+            id: connection_id.participant_id(),
+            name: self.name.clone(),
+            selections: self.selections.clone(),
+        }
+    }
+}
+
+// How the text of a file is encoded on disk. `Text` always separates lines with a single "\n", and
+// has no line break after the last line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct DiskFormat {
+    // Every line break on disk is "\r\n".
+    crlf: bool,
+    // The file ends with a line break.
+    trailing_newline: bool,
+    // The file is not valid UTF-8, so the text has replacement chars where the invalid bytes were.
+    lossy: bool,
+}
+
+// The modification time and length of a file on disk. If neither changed, we assume the contents
+// of the file did not change either.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct DiskStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl DiskStamp {
+    fn of(path: &Path) -> Option<DiskStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(DiskStamp {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::makepad_editor_core::{delta, position::Position, size::Size},
+    };
+    
+    // A collab server rooted at a fresh temporary directory, with one connection that records
+    // its notifications.
+    struct TestServer {
+        path: PathBuf,
+        server: CollabServer,
+    }
+    
+    impl TestServer {
+        fn new(name: &str) -> TestServer {
+            let path = std::env::temp_dir().join(format!("makepad_collab_server_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("a.txt"), "abc\ndef").unwrap();
+            TestServer {
+                server: CollabServer::new(path.clone()),
+                path,
+            }
+        }
+        
+        fn connect(&mut self) -> (CollabConnection, Arc<Mutex<Vec<CollabNotification >>>) {
+            let notifications = Arc::new(Mutex::new(Vec::new()));
+            let connection = self.server.connect(Box::new({
+                let notifications = notifications.clone();
+                move | notification | notifications.lock().unwrap().push(notification)
+            }));
+            (connection, notifications)
+        }
+        
+        // The tests change the length of a file with every write, so the change is seen even if
+        // the modification time stays the same.
+        fn write(&self, name: &str, contents: &str) {
+            fs::write(self.path.join(name), contents).unwrap();
+        }
+        
+        fn read(&self, name: &str) -> String {
+            fs::read_to_string(self.path.join(name)).unwrap()
+        }
+    }
+    
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+    
+    fn open(connection: &CollabConnection, name: &str) -> (TextFileId, u32, Text, Vec<ParticipantData>) {
+        match connection.handle_request(CollabRequest::OpenFile(name.into())) {
+            CollabResponse::OpenFile(response) => response.unwrap(),
+            response => panic!("{:?}", response),
+        }
+    }
+    
+    fn insert(text: &str, line: u32, column: u32) -> Delta {
+        let mut builder = delta::Builder::new();
+        builder.retain(Size {line, column});
+        builder.insert(Text::from(text));
+        builder.build()
+    }
+    
+    fn caret(line: usize, column: usize) -> Selection {
+        let position = Position {line, column};
+        Selection {head: position, tail: position}
+    }
+    
+    #[test]
+    fn presence() {
+        let mut server = TestServer::new("presence");
+        let (connection_0, notifications_0) = server.connect();
+        let (connection_1, notifications_1) = server.connect();
+        let participant_id_0 = match connection_0.handle_request(CollabRequest::SetParticipantName("zero".into())) {
+            CollabResponse::SetParticipantName(response) => response.unwrap(),
+            response => panic!("{:?}", response),
+        };
+        let (file_id, _, _, participants) = open(&connection_0, "a.txt");
+        assert!(participants.is_empty());
+        connection_0.handle_request(CollabRequest::UpdateSelections(file_id, 0, vec![caret(1, 1)]));
+        
+        // The second client sees the first one, with its selections.
+        let (_, _, _, participants) = open(&connection_1, "a.txt");
+        assert_eq!(participants.len(), 1);
+        assert_eq!((participants[0].id, participants[0].name.as_str()), (participant_id_0, "zero"));
+        assert_eq!(participants[0].selections, vec![caret(1, 1)]);
+        assert!(matches!(
+            notifications_0.lock().unwrap().pop(),
+            Some(CollabNotification::ParticipantJoined(id, data)) if id == file_id && data.name.is_empty()
+        ));
+        
+        // A delta from the second client moves the selections of the first one.
+        connection_1.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x\ny", 0, 0)));
+        notifications_0.lock().unwrap().clear();
+        
+        // Selections for an old revision are transformed against the deltas the client has not
+        // seen, like deltas are.
+        connection_0.handle_request(CollabRequest::UpdateSelections(file_id, 0, vec![caret(0, 1)]));
+        match notifications_1.lock().unwrap().pop() {
+            Some(CollabNotification::SelectionsWereUpdated(id, 1, participant_id, selections)) => {
+                assert_eq!((id, participant_id), (file_id, participant_id_0));
+                assert_eq!(selections, vec![caret(1, 2)]);
+            }
+            notification => panic!("{:?}", notification),
+        }
+        
+        // Dropping a connection leaves its files.
+        drop(connection_0);
+        assert!(matches!(
+            notifications_1.lock().unwrap().pop(),
+            Some(CollabNotification::ParticipantLeft(id, participant_id)) if id == file_id && participant_id == participant_id_0
+        ));
+        let (_, _, _, participants) = open(&server.connect().0, "a.txt");
+        assert_eq!(participants.len(), 1);
+    }
+    
+    #[test]
+    fn invalid_revision() {
+        let mut server = TestServer::new("invalid_revision");
+        let (connection_0, _) = server.connect();
+        let (connection_1, notifications_1) = server.connect();
+        let (file_id, _, _, _) = open(&connection_0, "a.txt");
+        open(&connection_1, "a.txt");
+        
+        // A revision the server does not have yet is an error, and leaves the file usable.
+        assert!(matches!(
+            connection_0.handle_request(CollabRequest::ApplyDelta(file_id, 5, insert("x", 0, 0))),
+            CollabResponse::ApplyDelta(Err(CollabError::RevisionUnavailable))
+        ));
+        assert!(matches!(
+            connection_0.handle_request(CollabRequest::UpdateSelections(file_id, 5, vec![caret(0, 0)])),
+            CollabResponse::UpdateSelections(Err(CollabError::RevisionUnavailable))
+        ));
+        assert!(matches!(
+            connection_0.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x", 0, 0))),
+            CollabResponse::ApplyDelta(Ok(_))
+        ));
+        drop(connection_0);
+        assert!(matches!(
+            notifications_1.lock().unwrap().pop(),
+            Some(CollabNotification::ParticipantLeft(id, _)) if id == file_id
+        ));
+    }
+    
+    #[test]
+    fn rename_onto_existing_file() {
+        let mut server = TestServer::new("rename_existing");
+        let (connection, _) = server.connect();
+        server.write("b.txt", "b");
+        let (file_id, _, _, _) = open(&connection, "b.txt");
+        assert!(matches!(
+            connection.handle_request(CollabRequest::RenameFile("a.txt".into(), "b.txt".into())),
+            CollabResponse::RenameFile(Err(CollabError::Unknown(_)))
+        ));
+        assert_eq!((server.read("a.txt").as_str(), server.read("b.txt").as_str()), ("abc\ndef", "b"));
+        
+        // The open destination still works, also after it was deleted on disk.
+        fs::remove_file(server.path.join("b.txt")).unwrap();
+        assert!(matches!(
+            connection.handle_request(CollabRequest::RenameFile("a.txt".into(), "b.txt".into())),
+            CollabResponse::RenameFile(Err(CollabError::Unknown(_)))
+        ));
+        connection.handle_request(CollabRequest::SaveFile {file_id, overwrite: false});
+        assert_eq!(server.read("b.txt"), "b");
+    }
+    
+    #[test]
+    fn diff_applies() {
+        for (old, new) in [("abc\ndef", "abc\ndxef"), ("abc\ndef", "def"), ("a", "a\nb\nc"), ("abc", "abc"), ("ab\ncd", "x")] {
+            let mut text = Text::from(old);
+            text.apply_delta(Delta::diff(&Text::from(old), &Text::from(new)));
+            assert_eq!(text, Text::from(new), "{:?} -> {:?}", old, new);
+        }
+    }
+    
+    #[test]
+    fn save_file() {
+        let mut server = TestServer::new("save");
+        let (connection_0, _) = server.connect();
+        let (connection_1, notifications_1) = server.connect();
+        let (file_id, _, _, _) = open(&connection_0, "a.txt");
+        open(&connection_1, "a.txt");
+        
+        // Deltas are not written to disk until the file is saved.
+        connection_0.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x", 0, 1)));
+        assert_eq!(server.read("a.txt"), "abc\ndef");
+        match connection_0.handle_request(CollabRequest::SaveFile {file_id, overwrite: false}) {
+            CollabResponse::SaveFile(response) => assert_eq!(response.unwrap(), (file_id, 1)),
+            response => panic!("{:?}", response),
+        }
+        assert_eq!(server.read("a.txt"), "axbc\ndef");
+        assert!(matches!(
+            notifications_1.lock().unwrap().last(),
+            Some(CollabNotification::FileWasSaved(id, 1)) if *id == file_id
+        ));
+    }
+    
+    #[test]
+    fn save_keeps_line_breaks() {
+        let mut server = TestServer::new("line_breaks");
+        let (connection, _) = server.connect();
+        for (contents, text, saved) in [
+            ("abc\n", "abc", "xabc\n"),
+            ("a\r\nb\r\n", "a\nb", "xa\r\nb\r\n"),
+            ("a\r\nb", "a\nb", "xa\r\nb"),
+            ("a\nb\r\n", "a\nb\r", "xa\nb\r\n"),
+            ("", "", "x"),
+            ("\n", "", "x\n"),
+        ] {
+            server.write("b.txt", contents);
+            let (file_id, _, opened_text, _) = open(&connection, "b.txt");
+            assert_eq!(opened_text.to_string(), text, "{:?}", contents);
+            connection.handle_request(CollabRequest::SaveFile {file_id, overwrite: false});
+            assert_eq!(server.read("b.txt"), contents);
+            connection.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x", 0, 0)));
+            connection.handle_request(CollabRequest::SaveFile {file_id, overwrite: false});
+            assert_eq!(server.read("b.txt"), saved);
+            connection.handle_request(CollabRequest::CloseFile(file_id));
+            fs::remove_dir_all(server.path.join(".collab")).unwrap();
+        }
+    }
+    
+    #[test]
+    fn invalid_files() {
+        let mut server = TestServer::new("invalid");
+        let (connection, _) = server.connect();
+        
+        // A file that is not valid UTF-8 can be opened, but not saved.
+        fs::write(server.path.join("b.txt"), b"a\xffb").unwrap();
+        let (file_id, _, text, _) = open(&connection, "b.txt");
+        assert_eq!(text.to_string(), "a\u{fffd}b");
+        assert!(matches!(
+            connection.handle_request(CollabRequest::SaveFile {file_id, overwrite: true}),
+            CollabResponse::SaveFile(Err(CollabError::NotUtf8))
+        ));
+        assert_eq!(fs::read(server.path.join("b.txt")).unwrap(), b"a\xffb");
+        
+        // Paths outside the root cannot be opened.
+        for path in ["../a.txt", "/etc/passwd", "."] {
+            assert!(matches!(
+                connection.handle_request(CollabRequest::OpenFile(path.into())),
+                CollabResponse::OpenFile(Err(CollabError::InvalidPath))
+            ), "{}", path);
+        }
+    }
+    
+    #[test]
+    fn changed_on_disk() {
+        let mut server = TestServer::new("changed");
+        let (connection, notifications) = server.connect();
+        let (file_id, _, _, _) = open(&connection, "a.txt");
+        server.server.check_disk();
+        
+        // Without unsaved changes, the file follows the disk.
+        server.write("a.txt", "abc\ndxxef");
+        server.server.check_disk();
+        let delta = match notifications.lock().unwrap().pop() {
+            Some(CollabNotification::FileWasChangedOnDisk(id, delta)) if id == file_id => delta,
+            notification => panic!("{:?}", notification),
+        };
+        assert_eq!(delta, insert("xx", 1, 1));
+        
+        // The client saw revision 1, and makes a change that is not saved.
+        connection.handle_request(CollabRequest::ApplyDelta(file_id, 1, insert("y", 0, 0)));
+        server.write("a.txt", "abc\nd");
+        server.server.check_disk();
+        assert!(matches!(
+            notifications.lock().unwrap().pop(),
+            Some(CollabNotification::FileConflict(id)) if id == file_id
+        ));
+        // A conflict is only reported once.
+        server.server.check_disk();
+        assert!(notifications.lock().unwrap().is_empty());
+        
+        // Saving refuses to overwrite the change on disk unless asked to.
+        assert!(matches!(
+            connection.handle_request(CollabRequest::SaveFile {file_id, overwrite: false}),
+            CollabResponse::SaveFile(Err(CollabError::FileChangedOnDisk))
+        ));
+        assert_eq!(server.read("a.txt"), "abc\nd");
+        
+        // Reverting takes the contents on disk instead.
+        connection.handle_request(CollabRequest::RevertFile(file_id));
+        let delta = match notifications.lock().unwrap().pop() {
+            Some(CollabNotification::FileWasChangedOnDisk(id, delta)) if id == file_id => delta,
+            notification => panic!("{:?}", notification),
+        };
+        let mut text = Text::from("yabc\ndxxef");
+        text.apply_delta(delta);
+        assert_eq!(text, Text::from("abc\nd"));
+    }
+    
+    #[test]
+    fn resync() {
+        let mut server = TestServer::new("resync");
+        let (connection_0, _) = server.connect();
+        let (connection_1, _) = server.connect();
+        let participant_id_0 = match connection_0.handle_request(CollabRequest::SetParticipantName("zero".into())) {
+            CollabResponse::SetParticipantName(response) => response.unwrap(),
+            response => panic!("{:?}", response),
+        };
+        let (file_id, _, _, _) = open(&connection_0, "a.txt");
+        open(&connection_1, "a.txt");
+        connection_0.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x", 0, 0)));
+        connection_1.handle_request(CollabRequest::ApplyDelta(file_id, 1, insert("y", 1, 0)));
+        
+        // The first client loses its connection after seeing revision 0, and reconnects.
+        drop(connection_0);
+        let (connection_0, _) = server.connect();
+        let data = match connection_0.handle_request(CollabRequest::Resync("a.txt".into(), 0)) {
+            CollabResponse::Resync(response) => response.unwrap(),
+            response => panic!("{:?}", response),
+        };
+        assert_eq!(data.revision, 2);
+        assert_eq!(data.participants.len(), 1);
+        assert_eq!(data.deltas.len(), 2);
+        assert_eq!(data.deltas[0], (Some(participant_id_0), insert("x", 0, 0)));
+        let mut text = Text::from("abc\ndef");
+        for (_, delta) in data.deltas {
+            text.apply_delta(delta);
+        }
+        assert_eq!(text, Text::from("xabc\nydef"));
+        
+        // Revisions the server has never seen cannot be resynced, and leave the file alone.
+        let (connection_2, _) = server.connect();
+        assert!(matches!(
+            connection_2.handle_request(CollabRequest::Resync("a.txt".into(), 3)),
+            CollabResponse::Resync(Err(CollabError::RevisionUnavailable))
+        ));
+        assert_eq!(open(&connection_2, "a.txt").3.len(), 2);
+    }
+    
+    #[test]
+    fn recover_after_restart() {
+        let mut server = TestServer::new("recover");
+        let (connection, _) = server.connect();
+        let (file_id, _, _, _) = open(&connection, "a.txt");
+        connection.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x", 0, 0)));
+        connection.handle_request(CollabRequest::ApplyDelta(file_id, 1, insert("y", 0, 0)));
+        
+        // Simulate a crash by starting a new server without closing anything first. The unsaved
+        // changes are recovered from the log, at the same revision.
+        mem::forget(connection);
+        server.server = CollabServer::new(server.path.clone());
+        let (connection, _) = server.connect();
+        let (file_id, revision, text, _) = open(&connection, "a.txt");
+        assert_eq!((revision, text), (2, Text::from("yxabc\ndef")));
+        assert_eq!(server.read("a.txt"), "abc\ndef");
+        
+        // Once saved and closed, the log is compacted, but revisions continue where they were.
+        connection.handle_request(CollabRequest::SaveFile {file_id, overwrite: false});
+        drop(connection);
+        server.server = CollabServer::new(server.path.clone());
+        server.write("a.txt", "abc");
+        let (connection, _) = server.connect();
+        let (_, revision, text, _) = open(&connection, "a.txt");
+        assert_eq!((revision, text), (3, Text::from("abc")));
+    }
+    
+    #[test]
+    fn file_tree_changes() {
+        let mut server = TestServer::new("tree");
+        let (connection_0, notifications_0) = server.connect();
+        let (connection_1, notifications_1) = server.connect();
+        server.server.check_disk();
+        let (file_id, _, _, _) = open(&connection_0, "a.txt");
+        
+        connection_0.handle_request(CollabRequest::CreateFile("dir/b.txt".into()));
+        assert!(server.path.join("dir/b.txt").is_file());
+        connection_0.handle_request(CollabRequest::RenameFile("a.txt".into(), "dir/c.txt".into()));
+        assert_eq!(server.read("dir/c.txt"), "abc\ndef");
+        connection_0.handle_request(CollabRequest::DeleteFile("dir/b.txt".into()));
+        assert!(matches!(
+            connection_0.handle_request(CollabRequest::DeleteFile("../a.txt".into())),
+            CollabResponse::DeleteFile(Err(CollabError::InvalidPath))
+        ));
+        
+        // Only the other connection is notified, and the watcher does not report the same changes
+        // again.
+        server.server.check_disk();
+        assert!(notifications_0.lock().unwrap().is_empty());
+        assert_eq!(
+            format!("{:?}", notifications_1.lock().unwrap().drain(..).collect::<Vec<_>>()),
+            format!("{:?}", vec![
+                CollabNotification::FileWasCreated("dir/b.txt".into()),
+                CollabNotification::FileWasRenamed("a.txt".into(), "dir/c.txt".into()),
+                CollabNotification::FileWasDeleted("dir/b.txt".into()),
+            ])
+        );
+        
+        // The renamed file keeps its id, and is saved to its new path.
+        connection_0.handle_request(CollabRequest::ApplyDelta(file_id, 0, insert("x", 0, 0)));
+        connection_0.handle_request(CollabRequest::SaveFile {file_id, overwrite: false});
+        assert_eq!(server.read("dir/c.txt"), "xabc\ndef");
+        
+        // Changes made by other programs are sent to every connection.
+        server.write("d.txt", "");
+        fs::remove_file(server.path.join("dir/c.txt")).unwrap();
+        server.server.check_disk();
+        assert_eq!(
+            format!("{:?}", notifications_0.lock().unwrap().drain(..).collect::<Vec<_>>()),
+            format!("{:?}", vec![
+                CollabNotification::FileWasDeleted("dir/c.txt".into()),
+                CollabNotification::FileWasCreated("d.txt".into()),
+            ])
+        );
+        
+        // Connections that were dropped are no longer notified.
+        notifications_1.lock().unwrap().clear();
+        drop(connection_1);
+        server.write("e.txt", "");
+        server.server.check_disk();
+        assert!(notifications_1.lock().unwrap().is_empty());
+        assert_eq!(notifications_0.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,324 @@
+use {
+    crate::{
+        makepad_editor_core::{
+            delta::{Delta, Operation},
+            position::Position,
+            text::Text,
+        },
+        makepad_micro_serde::{SerBin, DeBin, DeBinErr},
+        makepad_collab_protocol::{
+            ParticipantId,
+            unix_path::{UnixPath, UnixPathBuf},
+            unix_str::UnixString,
+        },
+    },
+    std::{
+        fs,
+        io::{self, prelude::*},
+        path::{Path, PathBuf},
+    },
+};
+
+/// An append-only log of the deltas that were applied to a file.
+///
+/// The log starts with the revision and contents of the file at the time the log was created, and
+/// is followed by each delta that was applied to the file since, in order. Each entry is flushed
+/// to disk before the delta it records is acknowledged, so the newest revision of a file can always
+/// be recovered from its log, even after a crash.
+///
+/// Entries are stored as a 4-byte little endian length, followed by the entry itself. If the
+/// server crashed while appending an entry, the log ends with an incomplete entry. Such an entry
+/// was never acknowledged, so it is simply cut off the next time the log is opened.
+#[derive(Debug)]
+pub struct DeltaLog {
+    path: PathBuf,
+    file: fs::File,
+}
+
+impl DeltaLog {
+    /// Creates a new log at the given path that starts with the given revision and contents,
+    /// replacing any existing log.
+    ///
+    /// The new log is written next to the old one first, and then moved over it, so a crash in
+    /// the middle of this leaves either the old or the new log intact.
+    pub fn create(path: &Path, revision: u32, text: &Text) -> io::Result<DeltaLog> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        write_entry(&mut file, &LogEntry::Open {revision, text: text.clone()})?;
+        fs::rename(&temp_path, path)?;
+        Ok(DeltaLog {
+            path: path.to_path_buf(),
+            file: fs::OpenOptions::new().append(true).open(path)?,
+        })
+    }
+
+    /// Opens the existing log at the given path, and returns it together with its contents. An
+    /// incomplete entry at the end of the log is cut off. A complete entry that does not decode, or
+    /// does not fit the text, is an `InvalidData` error, and leaves the log as it is.
+    pub fn open(path: &Path) -> io::Result<(DeltaLog, LogContents)> {
+        let (contents, len) = read_log(path)?;
+        let file = fs::OpenOptions::new().append(true).open(path)?;
+        file.set_len(len)?;
+        Ok((DeltaLog {path: path.to_path_buf(), file}, contents))
+    }
+
+    /// Reads the contents of the log at the given path, without opening it for appending.
+    pub fn read(path: &Path) -> io::Result<LogContents> {
+        Ok(read_log(path)?.0)
+    }
+
+    /// Returns the path to this log.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records that this log was moved to the given path. This does not move the log on disk.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    /// Appends the given delta, applied by the participant with the given id, if any, to this
+    /// log.
+    pub fn append_delta(&mut self, participant_id: Option<ParticipantId>, delta: Delta) -> io::Result<()> {
+        write_entry(&mut self.file, &LogEntry::Delta(participant_id, delta))
+    }
+
+    /// Appends a record that the newest revision of the file was written to disk to this log.
+    pub fn append_save(&mut self) -> io::Result<()> {
+        write_entry(&mut self.file, &LogEntry::Save)
+    }
+}
+
+/// The contents of a log, obtained by replaying its entries.
+#[derive(Clone, Debug)]
+pub struct LogContents {
+    /// The revision with which the log starts.
+    pub base_revision: u32,
+    /// The deltas in the log, together with the id of the participant that applied them, if any.
+    /// The delta at index `i` turns revision `base_revision + i` into the next one.
+    pub deltas: Vec<(Option<ParticipantId>, Delta)>,
+    /// The contents of the file at the newest revision.
+    pub text: Text,
+    /// The contents of the file as of the last time it was written to disk.
+    pub disk_text: Text,
+}
+
+impl LogContents {
+    /// Returns the newest revision in the log.
+    pub fn revision(&self) -> u32 {
+        self.base_revision + self.deltas.len() as u32
+    }
+}
+
+// An entry in a log.
+#[derive(Clone, Debug, SerBin, DeBin)]
+enum LogEntry {
+    // The log starts at the given revision, with the given contents, which were also the contents
+    // of the file on disk. This is always the first entry.
+    Open {revision: u32, text: Text},
+    // The participant with the given id, if any, applied the given delta.
+    Delta(Option<ParticipantId>, Delta),
+    // The newest revision was written to disk.
+    Save,
+}
+
+/// Returns the name of the log for the file at the given path, which is relative to the root of
+/// the server. Every byte that could have a special meaning in a file name is percent-encoded, so
+/// that logs for different paths never collide.
+pub fn log_name(path: &UnixPath) -> String {
+    let mut name = String::new();
+    for byte in path.as_bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => name.push(*byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name.push_str(".log");
+    name
+}
+
+/// Returns the path of the file for the log with the given name, or `None` if the name was not
+/// obtained with `log_name`.
+pub fn path_from_log_name(name: &str) -> Option<UnixPathBuf> {
+    let name = name.strip_suffix(".log")?;
+    let mut bytes = Vec::new();
+    let mut iter = name.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let high = (iter.next()? as char).to_digit(16)?;
+            let low = (iter.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    Some(UnixString::from_vec(bytes).into())
+}
+
+fn write_entry(file: &mut fs::File, entry: &LogEntry) -> io::Result<()> {
+    // Reserve space for the length, and fill it in once we know it.
+    let mut bytes = vec![0; 4];
+    entry.ser_bin(&mut bytes);
+    let len = (bytes.len() - 4) as u32;
+    bytes[..4].copy_from_slice(&len.to_le_bytes());
+    // A single write, so an entry is never interleaved with another one.
+    file.write_all(&bytes)?;
+    file.sync_data()
+}
+
+// Reads the log at the given path, and returns its contents together with the length of the part
+// of the log that consists of complete entries.
+fn read_log(path: &Path) -> io::Result<(LogContents, u64)> {
+    fn invalid_data(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+    }
+
+    let bytes = fs::read(path)?;
+    let mut contents: Option<LogContents> = None;
+    let mut offset = 0;
+    while bytes.len() - offset >= 4 {
+        let mut len_bytes = [0; 4];
+        len_bytes.copy_from_slice(&bytes[offset..offset + 4]);
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        if bytes.len() - offset - 4 < len {
+            // The server crashed while appending this entry.
+            break;
+        }
+        // A complete entry that does not decode was not torn by a crash, and cutting it off
+        // would lose every entry after it.
+        let entry = LogEntry::deserialize_bin(&bytes[offset + 4..offset + 4 + len])
+            .map_err( | _ | invalid_data("corrupt delta log entry"))?;
+        match (&mut contents, entry) {
+            (None, LogEntry::Open {revision, text}) => {
+                contents = Some(LogContents {
+                    base_revision: revision,
+                    deltas: Vec::new(),
+                    text: text.clone(),
+                    disk_text: text,
+                });
+            }
+            (None, _) | (Some(_), LogEntry::Open {..}) => return Err(invalid_data("malformed delta log")),
+            (Some(contents), LogEntry::Delta(participant_id, delta)) => {
+                // Applying a delta that does not fit the text would panic.
+                if !fits(&delta, &contents.text) {
+                    return Err(invalid_data("delta log entry out of bounds"));
+                }
+                contents.text.apply_delta(delta.clone());
+                contents.deltas.push((participant_id, delta));
+            }
+            (Some(contents), LogEntry::Save) => contents.disk_text = contents.text.clone(),
+        }
+        offset += 4 + len;
+    }
+    let contents = contents.ok_or_else( | | invalid_data("empty delta log"))?;
+    Ok((contents, offset as u64))
+}
+
+// Returns `true` if every operation of the given delta stays within the given text, so the delta
+// can be applied to it.
+fn fits(delta: &Delta, text: &Text) -> bool {
+    let mut position = Position::origin();
+    for operation in delta {
+        if let Operation::Retain(count) | Operation::Delete(count) = operation {
+            position += *count;
+            if position.line >= text.line_count() || position.column > text.line_len(position.line) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::makepad_editor_core::{delta, size::Size},
+    };
+
+    #[test]
+    fn torn_entry_is_cut_off() {
+        let path = std::env::temp_dir().join(format!("makepad_delta_log_{}.log", std::process::id()));
+        let mut log = DeltaLog::create(&path, 3, &Text::from("abc")).unwrap();
+        let mut builder = delta::Builder::new();
+        builder.retain(Size {line: 0, column: 1});
+        builder.insert(Text::from("x"));
+        log.append_delta(Some(ParticipantId(7)), builder.build()).unwrap();
+        log.append_save().unwrap();
+        drop(log);
+        let len = fs::metadata(&path).unwrap().len();
+
+        // Simulate a crash in the middle of appending an entry.
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[9, 0, 0, 0, 1]).unwrap();
+        let (mut log, contents) = DeltaLog::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!((contents.base_revision, contents.revision()), (3, 4));
+        assert_eq!(contents.deltas[0].0, Some(ParticipantId(7)));
+        assert_eq!((contents.text.to_string(), contents.disk_text.to_string()), ("axbc".to_string(), "axbc".to_string()));
+
+        // The log can be appended to again.
+        log.append_delta(None, Delta::identity()).unwrap();
+        assert_eq!(DeltaLog::read(&path).unwrap().revision(), 5);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn out_of_bounds_entry_is_rejected() {
+        let path = std::env::temp_dir().join(format!("makepad_delta_log_bounds_{}.log", std::process::id()));
+        for (count, deleted) in [
+            (Size {line: 0, column: 4}, Size::zero()),
+            (Size {line: 1, column: 0}, Size::zero()),
+            (Size {line: 0, column: 1}, Size {line: 0, column: 3}),
+        ] {
+            let mut log = DeltaLog::create(&path, 0, &Text::from("abc")).unwrap();
+            let mut builder = delta::Builder::new();
+            builder.retain(count);
+            builder.insert(Text::from("x"));
+            builder.delete(deleted);
+            log.append_delta(None, builder.build()).unwrap();
+            drop(log);
+            assert_eq!(DeltaLog::open(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn corrupt_entry_is_rejected() {
+        let path = std::env::temp_dir().join(format!("makepad_delta_log_corrupt_{}.log", std::process::id()));
+        let mut log = DeltaLog::create(&path, 0, &Text::from("abc")).unwrap();
+        log.append_save().unwrap();
+        drop(log);
+        let valid_len = fs::metadata(&path).unwrap().len();
+        
+        // An inserted text with invalid UTF-8, followed by a valid entry.
+        let mut builder = delta::Builder::new();
+        builder.insert(Text::from("é"));
+        let mut entry = LogEntry::Delta(None, builder.build()).serialize_bin();
+        let index = entry.iter().position( | byte | *byte == 0xc3).unwrap();
+        entry[index] = 0xff;
+        let mut bytes = (entry.len() as u32).to_le_bytes().to_vec();
+        bytes.extend(entry);
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&bytes).unwrap();
+        write_entry(&mut file, &LogEntry::Save).unwrap();
+        drop(file);
+        let len = fs::metadata(&path).unwrap().len();
+        assert_eq!(DeltaLog::open(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert!(len > valid_len);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn log_names() {
+        for path in ["a.txt", "dir/b c.rs", "%.log"] {
+            let path = UnixPathBuf::from(path);
+            let name = log_name(&path);
+            assert!(!name.contains('/'));
+            assert_eq!(path_from_log_name(&name), Some(path));
+        }
+    }
+}
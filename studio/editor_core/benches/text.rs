@@ -0,0 +1,121 @@
+// Benchmarks for the operations the editor performs on large texts.
+//
+// Run with `cargo bench`. Each benchmark is run on a generated text of several megabytes, and
+// reports the average time per operation. Edits and lookups are spread over random positions in
+// the text, generated from a fixed xorshift seed so that runs are comparable.
+
+use {
+    makepad_editor_core::{delta, Position, Size, Text},
+    makepad_test_rng::Rng,
+    std::{
+        hint::black_box,
+        time::Instant,
+    },
+};
+
+const LINE_COUNT: usize = 100_000;
+
+fn random_position(rng: &mut Rng, text: &Text) -> Position {
+    let line = rng.below(text.line_count());
+    Position {
+        line,
+        column: rng.below(text.line_len(line) + 1),
+    }
+}
+
+// Generates a text of `LINE_COUNT` lines that look vaguely like code, with some non-ASCII chars
+// so that char and byte positions differ.
+fn generate_text(rng: &mut Rng) -> String {
+    const WORDS: [&str; 8] = ["let", "x", "=", "foo(bar);", "{", "}", "// déjà vu", "\"ünïcödé\""];
+    let mut string = String::new();
+    for _ in 0..LINE_COUNT {
+        for _ in 0..rng.below(4) {
+            string.push_str("    ");
+        }
+        for _ in 0..rng.below(10) {
+            string.push_str(WORDS[rng.below(WORDS.len())]);
+            string.push(' ');
+        }
+        string.push('\n');
+    }
+    string
+}
+
+fn bench(name: &str, iterations: usize, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<40} {:>12?} per iteration ({} iterations)",
+        name,
+        elapsed / iterations as u32,
+        iterations
+    );
+}
+
+fn main() {
+    let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+    let string = generate_text(&mut rng);
+    println!(
+        "text of {:.1} MB, {} lines",
+        string.len() as f64 / (1024.0 * 1024.0),
+        LINE_COUNT
+    );
+
+    bench("Text::from", 5, || {
+        black_box(Text::from(black_box(string.as_str())));
+    });
+
+    let mut text = Text::from(string.as_str());
+    bench("apply_delta (insert char)", 10_000, || {
+        let position = random_position(&mut rng, &text);
+        let mut builder = delta::Builder::new();
+        builder.retain(position - Position::origin());
+        builder.insert(Text::from("x"));
+        text.apply_delta(builder.build());
+    });
+    bench("apply_delta (insert lines)", 10_000, || {
+        let position = random_position(&mut rng, &text);
+        let mut builder = delta::Builder::new();
+        builder.retain(position - Position::origin());
+        builder.insert(Text::from_lines(vec![vec!['{'], vec![' '; 4], vec!['}']]));
+        text.apply_delta(builder.build());
+    });
+    bench("apply_delta (delete)", 10_000, || {
+        let position = random_position(&mut rng, &text);
+        let count = if position.line + 1 < text.line_count() {
+            Size { line: 1, column: 0 }
+        } else {
+            Size { line: 0, column: (text.line_len(position.line) - position.column) as u32 }
+        };
+        let mut builder = delta::Builder::new();
+        builder.retain(position - Position::origin());
+        builder.delete(count);
+        text.apply_delta(builder.build());
+    });
+
+    bench("line_len", 100_000, || {
+        let line = rng.below(text.line_count());
+        black_box(text.line_len(line));
+    });
+    bench("line", 100_000, || {
+        let line = rng.below(text.line_count());
+        black_box(text.line(line));
+    });
+    bench("lines_at (100 lines)", 10_000, || {
+        let line = rng.below(text.line_count());
+        for line in text.lines_at(line).take(100) {
+            black_box(line);
+        }
+    });
+    bench("lines (all)", 5, || {
+        for line in text.lines() {
+            black_box(line);
+        }
+    });
+    bench("clone", 100_000, || {
+        black_box(text.clone());
+    });
+}
@@ -52,6 +52,65 @@ impl Delta {
         Delta::default()
     }
     
+    /// Returns a delta that turns `old_text` into `new_text`.
+    /// 
+    /// The delta retains the longest common prefix and suffix of both texts, and replaces
+    /// everything in between. This is not a minimal delta, but changes tend to be local, and it
+    /// keeps positions outside the change in place.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use makepad_editor_core::{Delta, Text};
+    /// 
+    /// let mut text = Text::from("abc\ndef");
+    /// text.apply_delta(Delta::diff(&Text::from("abc\ndef"), &Text::from("abc\ndxef")));
+    /// assert_eq!(text, Text::from("abc\ndxef"));
+    /// ```
+    pub fn diff(old_text: &Text, new_text: &Text) -> Delta {
+        fn chars(text: &Text) -> Vec<char> {
+            text.as_rope().chunks().flat_map( | chunk | chunk.chars()).collect()
+        }
+        
+        fn size(chars: &[char]) -> Size {
+            match chars.iter().rposition( | ch | *ch == '\n') {
+                Some(index) => Size {
+                    line: chars.iter().filter( | ch | **ch == '\n').count() as u32,
+                    column: (chars.len() - index - 1) as u32,
+                },
+                None => Size {
+                    line: 0,
+                    column: chars.len() as u32,
+                },
+            }
+        }
+        
+        let old_chars = chars(old_text);
+        let new_chars = chars(new_text);
+        let prefix_len = old_chars
+            .iter()
+            .zip(new_chars.iter())
+            .take_while( | (old_ch, new_ch) | old_ch == new_ch)
+            .count();
+        let suffix_len = old_chars[prefix_len..]
+            .iter()
+            .rev()
+            .zip(new_chars[prefix_len..].iter().rev())
+            .take_while( | (old_ch, new_ch) | old_ch == new_ch)
+            .count();
+        
+        let mut builder = Builder::new();
+        builder.retain(size(&old_chars[..prefix_len]));
+        builder.delete(size(&old_chars[prefix_len..old_chars.len() - suffix_len]));
+        builder.insert(Text::from_lines(
+            new_chars[prefix_len..new_chars.len() - suffix_len]
+                .split( | ch | *ch == '\n')
+                .map( | line | line.to_vec())
+                .collect(),
+        ));
+        builder.build()
+    }
+    
     /// Returns an iterator over the ranges of the operations in this delta, and their kind.
     /// 
     /// The range of an operation is defined as follows: for an insert operation, it is the range
@@ -6,24 +6,24 @@ use {
         range::Range,
         size::Size,
     },
-    std::{fmt, iter, mem, ops::AddAssign},
+    makepad_rope::Rope,
+    std::{fmt, mem, ops::AddAssign},
 };
 
 /// A type for representing text.
 ///
-/// A text is structured as a vec of lines, where each line is a vec of chars. This is not a very
-/// compact representation, since most characters are expected to be ASCII, and thus take up only
-/// a single byte, whereas a char always takes up 4 bytes. This means that for a typical text, up
-/// to 75% of memory it uses is wasted. However, this representation is very convenient to work
-/// with, because it closely matches the structure expected by most operations performed on a text.
-/// Since large texts are relatively rare, we've thus decided to accept this inefficiency for now.
-/// In the future, we might want to replace this representation with a more efficient one, such as
-/// an UTF-8 rope.
+/// A text is stored as a UTF-8 rope, that is, a balanced tree of chunks in which every node caches
+/// the number of bytes, chars and line breaks below it. This makes looking up a position,
+/// inserting or deleting text, and starting to iterate over the lines at a given position take
+/// logarithmic time in the length of the text, even for texts that are several megabytes large.
 ///
-/// A text maintains the invariant that it always contains at least one (possibly empty) line.
-#[derive(Clone, Debug, Eq, Hash, PartialEq, SerBin, DeBin)]
+/// Positions and sizes are measured in lines and chars, so they never split a char. Lines are
+/// separated by a single `'\n'`.
+///
+/// A text always contains at least one (possibly empty) line.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Text {
-    lines: Vec<Vec<char>>,
+    rope: Rope,
 }
 
 impl Text {
@@ -42,16 +42,29 @@ impl Text {
 
     /// Create a text from a vec of lines.
     /// 
-    /// # Panics
-    /// 
-    /// Panics if the vec is empty.
+    /// If the vec is empty, the text consists of a single space.
     pub fn from_lines(lines: Vec<Vec<char>>) -> Text {
-        if lines.is_empty(){
-            Text{lines:vec![vec![' ']]}
+        if lines.is_empty() {
+            return Text::from_rope(Rope::from(" "));
         }
-        else{
-            Text { lines }
+        let mut string = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                string.push('\n');
+            }
+            string.extend(line.iter());
         }
+        Text::from_rope(Rope::from(string))
+    }
+
+    /// Creates a text from the given rope.
+    pub fn from_rope(rope: Rope) -> Text {
+        Text { rope }
+    }
+
+    /// Returns the rope in which this text is stored.
+    pub fn as_rope(&self) -> &Rope {
+        &self.rope
     }
 
     /// Returns `true` if this text is empty.
@@ -67,7 +80,7 @@ impl Text {
     /// assert!(!text.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.len().is_zero()
+        self.rope.is_empty()
     }
 
     /// Returns the length of this text.
@@ -81,30 +94,96 @@ impl Text {
     /// assert!(text.len() == Size { line: 1, column: 3 });
     /// ```
     pub fn len(&self) -> Size {
+        let last_line = self.line_count() - 1;
         Size {
-            line: self.lines.len() as u32 - 1 ,
-            column: self.lines.last().unwrap().len() as u32,
+            line: last_line as u32,
+            column: (self.rope.len_chars() - self.rope.line_to_char(last_line)) as u32,
         }
     }
 
-    /// Returns a slice of the lines in this text.
+    /// Returns the number of lines in this text.
     /// 
     /// # Examples
     /// 
     /// ```
-    /// use makepad_editor_core::{Size, Text};
+    /// use makepad_editor_core::Text;
+    /// 
+    /// assert_eq!(Text::new().line_count(), 1);
+    /// assert_eq!(Text::from("abc\ndef").line_count(), 2);
+    /// ```
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Returns the length of the given line in chars, without its line break.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the line is out of bounds.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use makepad_editor_core::Text;
+    /// 
+    /// let text = Text::from("abc\ndéf!");
+    /// assert_eq!(text.line_len(0), 3);
+    /// assert_eq!(text.line_len(1), 4);
+    /// ```
+    pub fn line_len(&self, line: usize) -> usize {
+        let start = self.rope.line_to_char(line);
+        if line + 1 < self.line_count() {
+            self.rope.line_to_char(line + 1) - 1 - start
+        } else {
+            self.rope.len_chars() - start
+        }
+    }
+
+    /// Returns the chars in the given line, without its line break.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the line is out of bounds.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use makepad_editor_core::Text;
     /// 
     /// let text = Text::from("abc\ndef");
-    /// assert_eq!(text.as_lines(), &[vec!['a', 'b', 'c'], vec!['d', 'e', 'f']]);
+    /// assert_eq!(text.line(1), vec!['d', 'e', 'f']);
     /// ```
-    pub fn as_lines(&self) -> &[Vec<char>] {
-        &self.lines
+    pub fn line(&self, line: usize) -> Vec<char> {
+        self.lines_at(line).next().unwrap()
+    }
+
+    /// Returns an iterator over the lines in this text.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use makepad_editor_core::Text;
+    /// 
+    /// let text = Text::from("abc\ndef");
+    /// assert_eq!(text.lines().collect::<Vec<_>>(), [vec!['a', 'b', 'c'], vec!['d', 'e', 'f']]);
+    /// ```
+    pub fn lines(&self) -> impl Iterator<Item = Vec<char>> + '_ {
+        self.lines_at(0)
+    }
+
+    /// Returns an iterator over the lines in this text, starting with the given line.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the line is out of bounds.
+    pub fn lines_at(&self, line: usize) -> impl Iterator<Item = Vec<char>> + '_ {
+        self.rope.lines_at(line).map(|line| line.chars().collect())
     }
 
     /// Copies the given range from this text into a new text.
-    ///
+    /// 
     /// # Panics
-    ///
+    /// 
     /// Panics if the range is out of bounds.
     /// 
     /// # Examples
@@ -124,36 +203,7 @@ impl Text {
     /// );
     /// ```
     pub fn copy(&self, range: Range) -> Text {
-        Text {
-            lines: if range.start.line == range.end.line {
-                vec![
-                    self.lines[range.start.line][range.start.column..range.end.column]
-                        .iter()
-                        .cloned()
-                        .collect::<Vec<_>>(),
-                ]
-            } else {
-                let mut lines = Vec::with_capacity(range.end.line - range.start.line + 1);
-                lines.push(
-                    self.lines[range.start.line][range.start.column..]
-                        .iter()
-                        .cloned()
-                        .collect::<Vec<_>>(),
-                );
-                lines.extend(
-                    self.lines[range.start.line + 1..range.end.line]
-                        .iter()
-                        .cloned(),
-                );
-                lines.push(
-                    self.lines[range.end.line][..range.end.column]
-                        .iter()
-                        .cloned()
-                        .collect::<Vec<_>>(),
-                );
-                lines
-            },
-        }
+        Text::from_rope(self.rope.slice(self.byte_range(range)))
     }
 
     /// Appends the given range of this text to the given string.
@@ -176,29 +226,8 @@ impl Text {
     /// assert_eq!(string, "bc\nde");
     /// ```
     pub fn append_to_string(&self, range: Range, out: &mut String) {
-        if range.start.line == range.end.line {
-            out.extend(
-                self.lines[range.start.line][range.start.column..range.end.column]
-                    .iter()
-                    .cloned(),
-            );
-        } else {
-            out.extend(
-                self.lines[range.start.line][range.start.column..]
-                    .iter()
-                    .cloned()
-                    .chain(iter::once('\n'))
-                    .chain(
-                        self.lines[range.start.line + 1..range.end.line]
-                            .iter()
-                            .flat_map(|line| line.iter().cloned().chain(iter::once('\n'))),
-                    )
-                    .chain(
-                        self.lines[range.end.line][..range.end.column]
-                            .iter()
-                            .cloned(),
-                    ),
-            );
+        for chunk in self.rope.chunks_in(self.byte_range(range)) {
+            out.push_str(chunk);
         }
     }
 
@@ -212,15 +241,8 @@ impl Text {
     /// assert_eq!(text, Text::from("ef"));
     /// ```
     pub fn take(&mut self, len: Size) -> Text {
-        let mut lines = self.lines.drain(..len.line as usize).collect::<Vec<_>>();
-        lines.push(
-            self.lines
-                .first_mut()
-                .unwrap()
-                .drain(..len.column as usize)
-                .collect::<Vec<_>>(),
-        );
-        Text { lines }
+        let rest = self.rope.split_off(self.byte_index(Position::origin() + len));
+        Text::from_rope(mem::replace(&mut self.rope, rest))
     }
 
     /// Removes the given amount of text from the start of this text.
@@ -233,8 +255,7 @@ impl Text {
     /// assert_eq!(text, Text::from("ef"));
     /// ```
     pub fn skip(&mut self, len: Size) {
-        self.lines.drain(..len.line as usize);
-        self.lines.first_mut().unwrap().drain(..len.column as usize);
+        self.rope.remove(0..self.byte_index(Position::origin() + len));
     }
 
     /// Inserts the given text at the given position in this text.
@@ -252,23 +273,11 @@ impl Text {
     /// text.insert(Position { line: 1, column: 1 }, Text::from("xyz"));
     /// assert_eq!(text, Text::from("abc\ndxyzef"));
     /// ```
-    pub fn insert(&mut self, position: Position, mut text: Text) {
-        if text.len().line == 0 {
-            self.lines[position.line].splice(
-                position.column..position.column,
-                text.lines.first().unwrap().iter().cloned(),
-            );
-        } else {
-            text.lines.first_mut().unwrap().splice(
-                ..0,
-                self.lines[position.line][..position.column].iter().cloned(),
-            );
-            text.lines
-                .last_mut()
-                .unwrap()
-                .extend(self.lines[position.line][position.column..].iter().cloned());
-            self.lines
-                .splice(position.line..position.line + 1, text.lines.into_iter());
+    pub fn insert(&mut self, position: Position, text: Text) {
+        let mut byte_index = self.byte_index(position);
+        for chunk in text.rope.chunks() {
+            self.rope.insert(byte_index, chunk);
+            byte_index += chunk.len();
         }
     }
 
@@ -288,24 +297,10 @@ impl Text {
     /// assert_eq!(text, Text::from("abef"));
     /// ```
     pub fn delete(&mut self, position: Position, count: Size) {
-        if count.line == 0 {
-            self.lines[position.line].splice(
-                position.column..position.column + count.column as usize,
-                iter::empty(),
-            );
-        } else {
-            let mut line = mem::replace(&mut self.lines[position.line], Vec::new());
-            line.splice(
-                position.column..,
-                self.lines[position.line + count.line as usize][count.column as usize..]
-                    .iter()
-                    .cloned(),
-            );
-            self.lines.splice(
-                position.line..position.line + count.line as usize + 1,
-                iter::once(line),
-            );
-        }
+        self.rope.remove(self.byte_range(Range {
+            start: position,
+            end: position + count,
+        }));
     }
 
     /// Applies the given delta to this text.
@@ -327,35 +322,32 @@ impl Text {
             }
         }
     }
-}
 
-impl AddAssign for Text {
-    fn add_assign(&mut self, other: Text) {
-        self.lines
-            .last_mut()
-            .unwrap()
-            .extend(other.lines.first().unwrap());
-        self.lines.extend(other.lines.into_iter().skip(1))
+    // Returns the byte index in the rope of the given position.
+    fn byte_index(&self, position: Position) -> usize {
+        let line_start = self.rope.info_to_line(position.line);
+        let byte_index = self.rope.char_to_byte(line_start.char_count + position.column);
+        assert!(
+            self.rope.byte_to_line(byte_index) == position.line,
+            "position out of bounds"
+        );
+        byte_index
+    }
+
+    fn byte_range(&self, range: Range) -> std::ops::Range<usize> {
+        self.byte_index(range.start)..self.byte_index(range.end)
     }
 }
 
-impl Default for Text {
-    fn default() -> Text {
-        Text::from_lines(vec![vec![]])
+impl AddAssign for Text {
+    fn add_assign(&mut self, other: Text) {
+        self.rope.append(&other.rope);
     }
 }
 
 impl fmt::Display for Text {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut sep = "";
-        for line in self.lines.iter() {
-            write!(f, "{}", sep)?;
-            for ch in line {
-                write!(f, "{}", ch)?;
-            }
-            sep = "\n";
-        }
-        Ok(())
+        fmt::Display::fmt(&self.rope, f)
     }
 }
 
@@ -367,11 +359,48 @@ impl From<String> for Text {
 
 impl From<&str> for Text {
     fn from(string: &str) -> Text {
-        Text::from_lines(
-            string
-                .lines()
-                .map(|line| line.chars().collect::<Vec<_>>())
-                .collect::<Vec<_>>(),
-        )
+        // Split the string into the same lines as `str::lines` would, so a trailing line break
+        // does not start a new line, and "\r\n" is a single line break.
+        if string.is_empty() {
+            return Text::from_lines(Vec::new());
+        }
+        let string = string
+            .strip_suffix('\n')
+            .map(|string| string.strip_suffix('\r').unwrap_or(string))
+            .unwrap_or(string);
+        Text::from_rope(if string.contains('\r') {
+            Rope::from(string.replace("\r\n", "\n"))
+        } else {
+            Rope::from(string)
+        })
+    }
+}
+
+// A text is serialized as a vec of lines, where each line is a vec of chars, which is how it was
+// stored before it was stored as a rope.
+impl SerBin for Text {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (self.line_count() as u64).ser_bin(s);
+        for line in self.rope.lines() {
+            (line.chars().count() as u64).ser_bin(s);
+            s.extend_from_slice(line.as_bytes());
+        }
+    }
+}
+
+impl DeBin for Text {
+    fn de_bin(o: &mut usize, d: &[u8]) -> Result<Text, DeBinErr> {
+        let line_count: u64 = DeBin::de_bin(o, d)?;
+        let mut string = String::new();
+        for index in 0..line_count {
+            if index > 0 {
+                string.push('\n');
+            }
+            let char_count: u64 = DeBin::de_bin(o, d)?;
+            for _ in 0..char_count {
+                string.push(DeBin::de_bin(o, d)?);
+            }
+        }
+        Ok(Text::from_rope(Rope::from(string)))
     }
 }
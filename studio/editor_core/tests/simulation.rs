@@ -0,0 +1,497 @@
+// Deterministic simulation tests for the operational transform functions on `Delta`.
+//
+// Random texts and edits are generated from a fixed xorshift seed, so every failure is
+// reproducible. Edits are described relative to the text they are applied to (see `Edit`), which
+// means any part of a failing case can be removed and what is left still makes sense. This is what
+// lets us shrink a failing case to a minimal one before reporting it.
+//
+// Besides the algebraic properties of `transform`, `compose` and `invert`, we simulate a number of
+// clients that edit the same text concurrently through a mock collab server. The server and the
+// clients follow the same protocol as the collab server and the editor: the server keeps a linear
+// history and transforms incoming deltas against the ones the client has not seen, while each client
+// keeps at most one delta in flight and transforms incoming deltas against its outstanding ones. Once
+// every message is delivered, every client must end up with the same text as the server.
+
+use {
+    makepad_editor_core::{delta, Delta, Size, Text},
+    makepad_test_rng::Rng,
+    std::{cell::Cell, collections::VecDeque, fmt, panic, sync::Once},
+};
+
+const CLIENT_COUNT: usize = 3;
+
+// The chars that generated texts consist of. A small alphabet makes it likely that edits touch
+// the same chars and line breaks.
+const CHARS: [char; 4] = ['a', 'b', 'c', '\n'];
+
+fn generate_edit(rng: &mut Rng) -> Edit {
+    Edit((0..1 + rng.below(3)).map( | _ | Change {
+        skip: rng.below(6),
+        delete: if rng.below(2) == 0 {0} else {rng.below(4)},
+        insert: if rng.below(3) == 0 {String::new()} else {rng.string(3, &CHARS)},
+    }).collect())
+}
+
+// A change at some point in a text. The counts are clamped to the text the change is applied to,
+// so a change can be applied to any text.
+#[derive(Clone, Debug, PartialEq)]
+struct Change {
+    skip: usize,
+    delete: usize,
+    insert: String,
+}
+
+// A description of a delta as a sequence of changes, from the start of a text to its end.
+#[derive(Clone, Debug, PartialEq)]
+struct Edit(Vec<Change>);
+
+impl Edit {
+    // Returns the delta for this edit when applied to the given text.
+    fn to_delta(&self, text: &Text) -> Delta {
+        let chars = chars(text);
+        let mut builder = delta::Builder::new();
+        let mut index = 0;
+        for change in &self.0 {
+            let skip = change.skip.min(chars.len() - index);
+            builder.retain(size(&chars[index..index + skip]));
+            index += skip;
+            let delete = change.delete.min(chars.len() - index);
+            builder.delete(size(&chars[index..index + delete]));
+            index += delete;
+            builder.insert(text_from_str(&change.insert));
+        }
+        builder.build()
+    }
+
+    // Returns every edit that is one step simpler than this one.
+    fn shrink(&self) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        for index in 0..self.0.len() {
+            let mut changes = self.0.clone();
+            changes.remove(index);
+            edits.push(Edit(changes));
+            let change = &self.0[index];
+            for simpler_change in [
+                Change {skip: change.skip / 2, ..change.clone()},
+                Change {delete: change.delete / 2, ..change.clone()},
+                Change {insert: change.insert[..change.insert.len() / 2].to_string(), ..change.clone()},
+            ] {
+                if simpler_change != *change {
+                    let mut changes = self.0.clone();
+                    changes[index] = simpler_change;
+                    edits.push(Edit(changes));
+                }
+            }
+        }
+        edits
+    }
+}
+
+fn text_from_str(string: &str) -> Text {
+    // Unlike `Text::from`, this keeps a trailing newline.
+    Text::from_lines(string.split('\n').map( | line | line.chars().collect()).collect())
+}
+
+fn chars(text: &Text) -> Vec<char> {
+    text.to_string().chars().collect()
+}
+
+fn size(chars: &[char]) -> Size {
+    match chars.iter().rposition( | ch | *ch == '\n') {
+        Some(index) => Size {
+            line: chars.iter().filter( | ch | **ch == '\n').count() as u32,
+            column: (chars.len() - index - 1) as u32,
+        },
+        None => Size {line: 0, column: chars.len() as u32},
+    }
+}
+
+fn apply(text: &Text, delta: Delta) -> Text {
+    let mut text = text.clone();
+    text.apply_delta(delta);
+    text
+}
+
+// Returns every string that is one step simpler than the given one.
+fn shrink_string(string: &str) -> Vec<String> {
+    (0..string.len()).map( | index | {
+        let mut string = string.to_string();
+        string.remove(index);
+        string
+    }).collect()
+}
+
+// Runs `check` on `count` cases generated from `seed`. If a case fails, it is shrunk by repeatedly
+// replacing it with the first simpler case that still fails, and the minimal case is reported.
+fn check_cases<T: Clone + fmt::Debug>(
+    seed: u64,
+    count: usize,
+    generate: impl Fn(&mut Rng) -> T,
+    shrink: impl Fn(&T) -> Vec<T>,
+    check: impl Fn(&T) -> Result<(), String>,
+) {
+    let mut rng = Rng::new(seed);
+    for _ in 0..count {
+        let case = generate(&mut rng);
+        let mut error = match check(&case) {
+            Ok(()) => continue,
+            Err(error) => error,
+        };
+        let mut case = case;
+        while let Some((simpler_case, simpler_error)) = shrink(&case)
+            .into_iter()
+            .find_map( | simpler_case | check(&simpler_case).err().map( | error | (simpler_case, error)))
+        {
+            case = simpler_case;
+            error = simpler_error;
+        }
+        panic!("{}\nminimal case: {:#?}", error, case);
+    }
+}
+
+thread_local! {
+    // Whether the current thread is running a check inside `catch`.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Wraps a check that may panic, so that panics are shrunk like any other failure.
+fn catch(check: impl FnOnce() -> Result<(), String>) -> Result<(), String> {
+    // The panic hook is shared by every thread, and tests run in parallel, so install it once
+    // rather than swapping it in and out. It only silences panics that `catch` reports itself.
+    static SILENCE_CAUGHT_PANICS: Once = Once::new();
+    SILENCE_CAUGHT_PANICS.call_once( | | {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move | info | {
+            if !CATCHING.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+    CATCHING.with( | catching | catching.set(true));
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(check));
+    CATCHING.with( | catching | catching.set(false));
+    match result {
+        Ok(result) => result,
+        Err(payload) => Err(format!(
+            "panicked: {}",
+            payload
+                .downcast_ref::<&str>()
+                .map( | message | message.to_string())
+                .or_else( | | payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default()
+        )),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AlgebraCase {
+    text: String,
+    edits: [Edit; 3],
+}
+
+fn generate_algebra_case(rng: &mut Rng) -> AlgebraCase {
+    AlgebraCase {
+        text: rng.string(12, &CHARS),
+        edits: [generate_edit(rng), generate_edit(rng), generate_edit(rng)],
+    }
+}
+
+fn shrink_algebra_case(case: &AlgebraCase) -> Vec<AlgebraCase> {
+    let mut cases: Vec<_> = shrink_string(&case.text)
+        .into_iter()
+        .map( | text | AlgebraCase {text, ..case.clone()})
+        .collect();
+    for index in 0..3 {
+        for edit in case.edits[index].shrink() {
+            let mut simpler_case = case.clone();
+            simpler_case.edits[index] = edit;
+            cases.push(simpler_case);
+        }
+    }
+    cases
+}
+
+#[test]
+fn transform_converges() {
+    // TP1: for deltas A and B on the same text, applying A and then B' gives the same text as
+    // applying B and then A', where (A', B') = A.transform(B).
+    check_cases(0x2545_f491_4f6c_dd1d, 5000, generate_algebra_case, shrink_algebra_case, | case | catch( | | {
+        let text = text_from_str(&case.text);
+        let delta_0 = case.edits[0].to_delta(&text);
+        let delta_1 = case.edits[1].to_delta(&text);
+        let (delta_0_prime, delta_1_prime) = delta_0.clone().transform(delta_1.clone());
+        let text_0 = apply(&apply(&text, delta_0), delta_1_prime);
+        let text_1 = apply(&apply(&text, delta_1), delta_0_prime);
+        if text_0 != text_1 {
+            return Err(format!("diverged: {:?} != {:?}", text_0.to_string(), text_1.to_string()));
+        }
+        Ok(())
+    }));
+}
+
+#[test]
+fn invert_round_trips() {
+    check_cases(0x9e37_79b9_7f4a_7c15, 5000, generate_algebra_case, shrink_algebra_case, | case | catch( | | {
+        let text = text_from_str(&case.text);
+        let delta = case.edits[0].to_delta(&text);
+        let inverse_delta = delta.clone().invert(&text);
+        let new_text = apply(&text, delta);
+        let old_text = apply(&new_text, inverse_delta);
+        if old_text != text {
+            return Err(format!("did not round trip: {:?} != {:?}", old_text.to_string(), text.to_string()));
+        }
+        Ok(())
+    }));
+}
+
+#[test]
+fn compose_is_associative() {
+    // Composing deltas has the same effect as applying them in order, and since deltas are always
+    // built in normal form, composites that have the same effect compare equal.
+    check_cases(0xd1b5_4a32_d192_ed03, 5000, generate_algebra_case, shrink_algebra_case, | case | catch( | | {
+        let text_0 = text_from_str(&case.text);
+        let delta_0 = case.edits[0].to_delta(&text_0);
+        let text_1 = apply(&text_0, delta_0.clone());
+        let delta_1 = case.edits[1].to_delta(&text_1);
+        let text_2 = apply(&text_1, delta_1.clone());
+        let delta_2 = case.edits[2].to_delta(&text_2);
+        let text_3 = apply(&text_2, delta_2.clone());
+
+        let composite_delta = delta_0.clone().compose(delta_1.clone());
+        if apply(&text_0, composite_delta.clone()) != text_2 {
+            return Err("composite does not match applying in order".to_string());
+        }
+        let left = composite_delta.compose(delta_2.clone());
+        let right = delta_0.compose(delta_1.compose(delta_2));
+        if left != right {
+            return Err(format!("not associative: {:?} != {:?}", left, right));
+        }
+        if apply(&text_0, left) != text_3 {
+            return Err("composite of three does not match applying in order".to_string());
+        }
+        Ok(())
+    }));
+}
+
+// A step in a simulation.
+#[derive(Clone, Debug)]
+enum Step {
+    // The given client makes an edit to its local text.
+    Edit(usize, Edit),
+    // The server handles the next request from the given client, if any.
+    ServerReceive(usize),
+    // The given client handles the next message from the server, if any.
+    ClientReceive(usize),
+}
+
+#[derive(Clone, Debug)]
+struct SimulationCase {
+    text: String,
+    steps: Vec<Step>,
+}
+
+// A message from the server to a client.
+enum Message {
+    // The delta the client sent last was applied.
+    Ack,
+    // Another client applied the given delta.
+    Delta(Delta),
+}
+
+// A mock collab server, with a single file.
+struct MockServer {
+    text: Text,
+    // Every delta applied so far. Unlike the real server, we never prune this.
+    history: Vec<Delta>,
+    requests: Vec<VecDeque<(usize, Delta)>>,
+    messages: Vec<VecDeque<Message>>,
+}
+
+impl MockServer {
+    // Handles the next request from the given client, like `CollabConnection::apply_delta`.
+    fn receive(&mut self, client: usize) -> bool {
+        let (their_revision, delta) = match self.requests[client].pop_front() {
+            Some(request) => request,
+            None => return false,
+        };
+        let mut delta = delta;
+        for unseen_delta in &self.history[their_revision..] {
+            delta = unseen_delta.clone().transform(delta).1;
+        }
+        self.text.apply_delta(delta.clone());
+        self.history.push(delta.clone());
+        for (other_client, messages) in self.messages.iter_mut().enumerate() {
+            messages.push_back(if other_client == client {
+                Message::Ack
+            } else {
+                Message::Delta(delta.clone())
+            });
+        }
+        true
+    }
+}
+
+// A mock client, with the same bookkeeping as a document in the editor.
+struct MockClient {
+    text: Text,
+    revision: usize,
+    // The first delta is in flight. Any further edits are composed into a second one.
+    outstanding_deltas: VecDeque<Delta>,
+}
+
+impl MockClient {
+    // Applies a local edit, like `Document::schedule_apply_delta_request`.
+    fn edit(&mut self, edit: &Edit, requests: &mut VecDeque<(usize, Delta)>) {
+        let delta = edit.to_delta(&self.text);
+        self.text.apply_delta(delta.clone());
+        if self.outstanding_deltas.len() == 2 {
+            let outstanding_delta = self.outstanding_deltas.pop_back().unwrap();
+            self.outstanding_deltas.push_back(outstanding_delta.compose(delta));
+        } else {
+            self.outstanding_deltas.push_back(delta.clone());
+            if self.outstanding_deltas.len() == 1 {
+                requests.push_back((self.revision, delta));
+            }
+        }
+    }
+
+    // Handles a message from the server, like `EditorState::handle_apply_delta_response` and
+    // `EditorState::handle_delta_applied_notification`.
+    fn receive(&mut self, message: Message, requests: &mut VecDeque<(usize, Delta)>) {
+        self.revision += 1;
+        match message {
+            Message::Ack => {
+                self.outstanding_deltas.pop_front();
+                if let Some(outstanding_delta) = self.outstanding_deltas.front() {
+                    requests.push_back((self.revision, outstanding_delta.clone()));
+                }
+            }
+            Message::Delta(delta) => {
+                let mut delta = delta;
+                for outstanding_delta in &mut self.outstanding_deltas {
+                    let (new_delta, new_outstanding_delta) = delta.transform(outstanding_delta.clone());
+                    delta = new_delta;
+                    *outstanding_delta = new_outstanding_delta;
+                }
+                self.text.apply_delta(delta);
+            }
+        }
+    }
+}
+
+fn run_simulation(case: &SimulationCase) -> Result<(), String> {
+    let text = text_from_str(&case.text);
+    let mut server = MockServer {
+        text: text.clone(),
+        history: Vec::new(),
+        requests: (0..CLIENT_COUNT).map( | _ | VecDeque::new()).collect(),
+        messages: (0..CLIENT_COUNT).map( | _ | VecDeque::new()).collect(),
+    };
+    let mut clients: Vec<_> = (0..CLIENT_COUNT).map( | _ | MockClient {
+        text: text.clone(),
+        revision: 0,
+        outstanding_deltas: VecDeque::new(),
+    }).collect();
+
+    let client_receive = | server: &mut MockServer, clients: &mut [MockClient], client: usize | {
+        match server.messages[client].pop_front() {
+            Some(message) => {
+                clients[client].receive(message, &mut server.requests[client]);
+                true
+            }
+            None => false,
+        }
+    };
+    for step in &case.steps {
+        match step {
+            Step::Edit(client, edit) => clients[*client].edit(edit, &mut server.requests[*client]),
+            Step::ServerReceive(client) => {
+                server.receive(*client);
+            }
+            Step::ClientReceive(client) => {
+                client_receive(&mut server, &mut clients, *client);
+            }
+        }
+    }
+
+    // Deliver every remaining message.
+    loop {
+        let mut is_done = true;
+        for client in 0..CLIENT_COUNT {
+            while server.receive(client) | client_receive(&mut server, &mut clients, client) {
+                is_done = false;
+            }
+        }
+        if is_done {
+            break;
+        }
+    }
+
+    for (index, client) in clients.iter().enumerate() {
+        if client.text != server.text {
+            return Err(format!(
+                "client {} diverged from the server: {:?} != {:?}",
+                index,
+                client.text.to_string(),
+                server.text.to_string()
+            ));
+        }
+        if client.revision != server.history.len() || !client.outstanding_deltas.is_empty() {
+            return Err(format!("client {} did not settle", index));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn clients_converge() {
+    check_cases(
+        0x6a09_e667_f3bc_c909,
+        1000,
+        | rng | SimulationCase {
+            text: rng.string(12, &CHARS),
+            steps: (0..rng.below(30)).map( | _ | {
+                let client = rng.below(CLIENT_COUNT);
+                match rng.below(3) {
+                    0 => Step::Edit(client, generate_edit(rng)),
+                    1 => Step::ServerReceive(client),
+                    _ => Step::ClientReceive(client),
+                }
+            }).collect(),
+        },
+        | case | {
+            let mut cases: Vec<_> = shrink_string(&case.text)
+                .into_iter()
+                .map( | text | SimulationCase {text, ..case.clone()})
+                .collect();
+            for index in 0..case.steps.len() {
+                let mut steps = case.steps.clone();
+                steps.remove(index);
+                cases.push(SimulationCase {steps, ..case.clone()});
+                if let Step::Edit(client, edit) = &case.steps[index] {
+                    for edit in edit.shrink() {
+                        let mut steps = case.steps.clone();
+                        steps[index] = Step::Edit(*client, edit);
+                        cases.push(SimulationCase {steps, ..case.clone()});
+                    }
+                }
+            }
+            cases
+        },
+        | case | catch( | | run_simulation(case)),
+    );
+}
+
+#[test]
+fn shrinking_finds_minimal_case() {
+    // Sanity check for the harness itself: a property that fails for every text with a newline is
+    // shrunk to the text "\n".
+    let result = panic::catch_unwind( | | {
+        check_cases(1, 100, | rng | rng.string(12, &CHARS), | string | shrink_string(string), | string | {
+            if string.contains('\n') {Err("contains a newline".to_string())} else {Ok(())}
+        })
+    });
+    let payload = result.unwrap_err();
+    let message = payload.downcast_ref::<String>().unwrap();
+    assert!(message.ends_with("minimal case: \"\\n\""), "{}", message);
+}
@@ -137,12 +137,19 @@ impl AppInner {
         }
     }
     
+    // Sends the name under which other participants see our carets.
+    fn send_participant_name(&mut self) {
+        let name = std::env::var("USER").unwrap_or_default();
+        self.collab_client.send_request(CollabRequest::SetParticipantName(name));
+    }
+    
     pub fn handle_event(&mut self, cx: &mut Cx, event: &Event, state: &mut AppState) {
         self.window.handle_event_with(cx, event, &mut | _, _ | {});
         
         match event {
             Event::Construct => {
                 self.collab_client.send_request(CollabRequest::LoadFileTree {with_data: false});
+                self.send_participant_name();
                 /*self.create_code_editor_tab(
                     cx,
                     state,
@@ -296,11 +303,26 @@ impl AppInner {
                     }
                     response=>{
                         self.build_manager.handle_collab_response(cx, state, &response);
-                        self.editors.handle_collab_response(cx, &mut state.editor_state, response, &mut self.collab_client.request_sender())
+                        let message_count = state.editor_state.messages.len();
+                        self.editors.handle_collab_response(cx, &mut state.editor_state, response, &mut self.collab_client.request_sender());
+                        // File conflicts are reported in the log.
+                        if state.editor_state.messages.len() != message_count {
+                            self.log_view.redraw(cx);
+                        }
                     }
                 },
                 CollabClientAction::Notification(notification) => {
-                    self.editors.handle_collab_notification(cx, &mut state.editor_state, notification)
+                    let message_count = state.editor_state.messages.len();
+                    self.editors.handle_collab_notification(cx, &mut state.editor_state, notification);
+                    if state.editor_state.messages.len() != message_count {
+                        self.log_view.redraw(cx);
+                    }
+                }
+                CollabClientAction::Reconnected => {
+                    // The server gives us a new participant id, so set our name before the files
+                    // are joined again.
+                    self.send_participant_name();
+                    state.editor_state.resync_documents(&mut self.collab_client.request_sender());
                 }
             }
         }
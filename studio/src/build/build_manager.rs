@@ -142,9 +142,8 @@ impl BuildManager {
         response: &CollabResponse,
     ) {
         match response {
-            CollabResponse::ApplyDelta(response) => {
-                // something changed for file_id
-                let _file_id = response.clone().unwrap();
+            CollabResponse::SaveFile(Ok(_)) => {
+                // something changed on disk for file_id
                 cx.stop_timer(self.recompile_timer);
                 self.recompile_timer = cx.start_timeout(self.recompile_timeout);
             }
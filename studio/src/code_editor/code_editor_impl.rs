@@ -32,9 +32,12 @@ use {
         build::{
             build_protocol::{BuildMsg, BuildMsgLevel}
         },
-        makepad_collab_protocol::CollabRequest,
+        makepad_collab_protocol::{CollabRequest, ParticipantData, ParticipantId},
+    },
+    std::{
+        collections::HashMap,
+        mem,
     },
-    std::mem,
 };
 
 live_design!{
@@ -148,6 +151,10 @@ live_design!{
             color: (COLOR_FG_CURSOR)
         }
         
+        remote_caret_quad: {
+            color: #f0a030
+        }
+        
         current_line_quad: {
             color: (COLOR_BG_CURSOR)
         }
@@ -213,6 +220,7 @@ pub struct CodeEditorImpl {
     #[live] selection_quad: DrawSelection,
     #[live] code_text: DrawText,
     #[live] caret_quad: DrawColor,
+    #[live] remote_caret_quad: DrawColor,
     #[live] line_num_quad: DrawColor,
     #[live] line_num_text: DrawText,
     #[live] indent_line_quad: DrawIndentLine,
@@ -369,7 +377,7 @@ impl CodeEditorImpl {
         
         let viewport_end = viewport_start + viewport_size;
         
-        if document_inner.text.as_lines().len() != document_inner.indent_cache.len() {
+        if document_inner.text.line_count() != document_inner.indent_cache.len() {
             panic!()
         }
         
@@ -382,9 +390,9 @@ impl CodeEditorImpl {
         let mut end = None;
         let mut max_line_width = 0;
         
-        for (line_index, text_line) in document_inner.text.as_lines().iter().enumerate() {
+        for (line_index, text_line) in document_inner.text.as_rope().lines().enumerate() {
             
-            max_line_width = text_line.len().max(max_line_width);
+            max_line_width = text_line.chars().count().max(max_line_width);
             
             let output = compute_height(
                 cx,
@@ -426,7 +434,7 @@ impl CodeEditorImpl {
         lines_layout.total_height = start_y;
         lines_layout.max_line_width = max_line_width as f64 * self.text_glyph_size.x;
         lines_layout.view_start = start.unwrap_or(0);
-        lines_layout.view_end = end.unwrap_or(document_inner.text.as_lines().len());
+        lines_layout.view_end = end.unwrap_or(document_inner.text.line_count());
         lines_layout.start_y = start_line_y.unwrap_or(0.0);
     }
     
@@ -440,6 +448,7 @@ impl CodeEditorImpl {
         self.indent_line_quad.begin_many_instances(cx);
         self.msg_line_quad.begin_many_instances(cx);
         self.caret_quad.begin_many_instances(cx);
+        self.remote_caret_quad.begin_many_instances(cx);
     }
     
     pub fn end_instances(&mut self, cx: &mut Cx2d) {
@@ -448,6 +457,7 @@ impl CodeEditorImpl {
         self.indent_line_quad.end_many_instances(cx);
         self.msg_line_quad.end_many_instances(cx);
         self.caret_quad.end_many_instances(cx);
+        self.remote_caret_quad.end_many_instances(cx);
     }
     
     pub fn start_zoom_anim(&mut self, cx: &mut Cx, state: &mut EditorState, lines_layout: &LinesLayout, anim: &StatePair) {
@@ -521,7 +531,7 @@ impl CodeEditorImpl {
         // Note that since the iterator always points to the next line, the current line is not
         // defined until after the first iteration, and the previous line is not defined until after
         // the second iteration.
-        for (next_line_index, next_line) in text.as_lines()[lines_layout.view_start..lines_layout.view_end].iter().enumerate() {
+        for (next_line_index, next_line) in text.lines_at(lines_layout.view_start).take(lines_layout.view_end - lines_layout.view_start).enumerate() {
             let line_index = next_line_index + lines_layout.view_start;
             
             let layout = &lines_layout.lines[line_index];
@@ -803,6 +813,32 @@ impl CodeEditorImpl {
         }
     }
     
+    pub fn draw_remote_carets(
+        &mut self,
+        cx: &mut Cx2d,
+        participants: &HashMap<ParticipantId, ParticipantData>,
+        lines_layout: &LinesLayout,
+    ) {
+        let origin = cx.turtle().pos();
+        for participant in participants.values() {
+            for selection in &participant.selections {
+                let caret = selection.head;
+                if caret.line < lines_layout.view_start || caret.line >= lines_layout.view_end {
+                    continue;
+                }
+                let layout = &lines_layout.lines[caret.line];
+                let pos = self.position_to_dvec2(caret, lines_layout);
+                self.remote_caret_quad.draw_abs(cx, Rect {
+                    pos: pos + origin,
+                    size: DVec2 {
+                        x: 2.0 * layout.font_scale,
+                        y: self.text_glyph_size.y * layout.font_scale,
+                    },
+                });
+            }
+        }
+    }
+    
     pub fn draw_code_chunk(
         &mut self,
         cx: &mut Cx2d,
@@ -1051,6 +1087,25 @@ impl CodeEditorImpl {
                     dispatch_action(cx, CodeEditorAction::RedrawViewsForDocument(session.document_id))
                 }
             }
+            Hit::KeyDown(KeyEvent {
+                key_code: KeyCode::KeyS,
+                modifiers,
+                ..
+            }) if modifiers.control || modifiers.logo => {
+                if let Some(session_id) = self.session_id {
+                    // Shift overwrites the file, even if it was changed on disk.
+                    state.save_file(session_id, modifiers.shift, send_request);
+                }
+            }
+            Hit::KeyDown(KeyEvent {
+                key_code: KeyCode::KeyR,
+                modifiers,
+                ..
+            }) if (modifiers.control || modifiers.logo) && modifiers.shift => {
+                if let Some(session_id) = self.session_id {
+                    state.revert_file(session_id, send_request);
+                }
+            }
             Hit::KeyDown(KeyEvent {
                 key_code: KeyCode::KeyA,
                 modifiers,
@@ -1099,6 +1154,11 @@ impl CodeEditorImpl {
             }
             _ => {}
         }
+        
+        // Let the other participants for the document know where our cursors are.
+        if let Some(session_id) = self.session_id {
+            state.send_selections(session_id, send_request);
+        }
     }
     
     fn handle_select_scroll_in_finger_move(&mut self, fe: &FingerMoveEvent) {
@@ -1229,14 +1289,14 @@ impl CodeEditorImpl {
                 };
                 return Position {
                     line,
-                    column: column.min(text.as_lines()[line].len()),
+                    column: column.min(text.line_len(line)),
                 }
             }
         }
         
         return Position {
-            line: text.as_lines().len() - 1,
-            column: text.as_lines().last().unwrap().len()
+            line: text.line_count() - 1,
+            column: text.line_len(text.line_count() - 1)
         }
     }
 }
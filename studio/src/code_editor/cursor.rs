@@ -136,7 +136,7 @@ impl Cursor {
                 return
             }
             self.head.line -= 1;
-            self.head.column = text.as_lines()[self.head.line].len();
+            self.head.column = text.line_len(self.head.line);
         } else {
             self.head.column -= 1;
         }
@@ -181,8 +181,8 @@ impl Cursor {
     /// );
     /// ```
     pub fn move_right(&mut self, text: &Text, select: bool) {
-        if self.head.column == text.as_lines()[self.head.line].len() {
-            if self.head.line == text.as_lines().len() - 1 {
+        if self.head.column == text.line_len(self.head.line) {
+            if self.head.line == text.line_count() - 1 {
                 return;
             }
             self.head.line += 1;
@@ -235,7 +235,7 @@ impl Cursor {
         self.head.line -= 1;
         self.head.column = self
             .max_column
-            .min(text.as_lines()[self.head.line].len());
+            .min(text.line_len(self.head.line));
         if !select {
             self.tail = self.head;
         }
@@ -274,13 +274,13 @@ impl Cursor {
     /// );
     /// ```
     pub fn move_down(&mut self, text: &Text, select: bool) {
-        if self.head.line == text.as_lines().len() - 1 {
+        if self.head.line == text.line_count() - 1 {
             return;
         }
         self.head.line += 1;
         self.head.column = self
             .max_column
-            .min(text.as_lines()[self.head.line].len());
+            .min(text.line_len(self.head.line));
         if !select {
             self.tail = self.head;
         }
@@ -220,15 +220,9 @@ impl CursorSet {
     pub fn select_all(&mut self, text: &Text) {
         self.cursors.clear();
         self.last_inserted_index = 0;
-        let lines = text.as_lines();
         self.cursors.push(Cursor {
             head: Position {line: 0, column: 0},
-            tail: if let Some(last) = lines.last() {
-                Position {line: text.as_lines().len() - 1, column: last.len()}
-            }
-            else {
-                Position {line: 0, column: 0}
-            },
+            tail: Position::origin() + text.len(),
             max_column: 0
         });
     }
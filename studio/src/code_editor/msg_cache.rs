@@ -15,7 +15,7 @@ pub struct MsgCache {
 impl MsgCache {
     pub fn new(text: &Text) -> MsgCache {
         let cache = MsgCache {
-            lines: (0..text.as_lines().len()).map(|_| Line::default()).collect::<Vec<_>>(),
+            lines: (0..text.line_count()).map(|_| Line::default()).collect::<Vec<_>>(),
         };
         //cache.refresh(text, msg_ranges);
         cache
@@ -49,18 +49,17 @@ impl MsgCache {
         // ok so.. we now have to go from line to line
         let start = range.start;
         let end = range.end;
-        let lines = text.as_lines();
         if start.line < self.lines.len() && end.line < self.lines.len(){
             if start.line != end.line{
                 self.lines[start.line].spans.push(BuilderMsgSpan{
                     start_column: start.column,
-                    end_column: lines[start.line].len(),
+                    end_column: text.line_len(start.line),
                     msg_id
                 });
                 for line in start.line+1..end.line{
                     self.lines[line].spans.push(BuilderMsgSpan{
                         start_column: 0,
-                        end_column: lines[start.line].len(),
+                        end_column: text.line_len(start.line),
                         msg_id
                     });
                 }
@@ -3,14 +3,19 @@ use {
         makepad_micro_serde::*,
         makepad_platform::*,
         makepad_collab_protocol::{CollabRequest, CollabClientAction},
-        makepad_collab_server::{CollabConnection, CollabServer},
+        makepad_collab_server::{CollabConnection, CollabServer, FileWatcher},
     },
     std::{
         env,
         io::{Read, Write},
-        net::{TcpListener, TcpStream},
-        sync::mpsc::{self, Receiver, Sender, TryRecvError},
+        net::{Shutdown, TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+            Arc,
+        },
         thread,
+        time::Duration,
         path::PathBuf
     },
 };
@@ -38,6 +43,7 @@ pub struct CollabClientInner {
     pub request_sender: Sender<CollabRequest>,
     pub action_signal: Signal,
     pub action_receiver: Receiver<CollabClientAction>,
+    pub file_watcher: Option<FileWatcher>,
 }
 
 impl CollabClient {
@@ -83,6 +89,7 @@ impl CollabClientInner {
         let base_path = env::current_dir().unwrap();
         let final_path = base_path.join(subdir.split('/').collect::<PathBuf>());
         let mut server = CollabServer::new(final_path);
+        let file_watcher = server.watch_files(Duration::from_millis(500));
         spawn_local_request_handler(
             request_receiver,
             server.connect(Box::new({
@@ -101,7 +108,8 @@ impl CollabClientInner {
         Self {
             request_sender,
             action_signal,
-            action_receiver
+            action_receiver,
+            file_watcher: Some(file_watcher),
         }
     }
     
@@ -110,14 +118,13 @@ impl CollabClientInner {
         let action_signal = Signal::new();
         let (action_sender, action_receiver) = mpsc::channel();
         
-        let stream = TcpStream::connect(to_server).unwrap();
-        spawn_request_sender(request_receiver, stream.try_clone().unwrap());
-        spawn_response_or_notification_receiver(stream, action_signal.clone(), action_sender,);
+        spawn_remote_connection(to_server.to_string(), request_receiver, action_signal.clone(), action_sender);
         
         Self {
             request_sender,
             action_signal,
-            action_receiver
+            action_receiver,
+            file_watcher: None,
         }
     }
     
@@ -152,12 +159,11 @@ fn spawn_remote_request_handler(
     action_sender: Sender<CollabClientAction>,
 ) {
     thread::spawn(move || loop {
-        let mut len_bytes = [0; 4];
-        stream.read_exact(&mut len_bytes).unwrap();
-        let len = u32::from_be_bytes(len_bytes);
-        let mut request_bytes = vec![0; len as usize];
-        stream.read_exact(&mut request_bytes).unwrap();
-        
+        // The client disconnected. Dropping the connection leaves its files.
+        let request_bytes = match read_message(&mut stream) {
+            Ok(request_bytes) => request_bytes,
+            Err(_) => break,
+        };
         let request = DeBin::deserialize_bin(request_bytes.as_slice()).unwrap();
         let response = connection.handle_request(request);
         action_sender.send(CollabClientAction::Response(response)).unwrap();
@@ -168,47 +174,115 @@ fn spawn_response_or_notification_sender(
     action_receiver: Receiver<CollabClientAction>,
     mut stream: TcpStream,
 ) {
-    thread::spawn(move || loop {
-        let action = action_receiver.recv().unwrap();
+    thread::spawn(move || while let Ok(action) = action_receiver.recv() {
         let mut action_bytes = Vec::new();
-        
         action.ser_bin(&mut action_bytes);
-        
-        let len_bytes = action_bytes.len().to_be_bytes();
-        stream.write_all(&len_bytes).unwrap();
-        stream.write_all(&action_bytes).unwrap();
+        if write_message(&mut stream, &action_bytes).is_err() {
+            break;
+        }
     });
 }
 
-fn spawn_request_sender(request_receiver: Receiver<CollabRequest>, mut stream: TcpStream) {
-    thread::spawn(move || loop {
-        let request = request_receiver.recv().unwrap();
+// Keeps a connection to the collab server at the given address. Whenever the connection is lost,
+// this keeps trying to reconnect, and sends a `Reconnected` action once it succeeds. Requests made
+// while there is no connection are dropped, since the client resyncs its files after reconnecting.
+fn spawn_remote_connection(
+    to_server: String,
+    request_receiver: Receiver<CollabRequest>,
+    action_signal: Signal,
+    action_sender: Sender<CollabClientAction>,
+) {
+    thread::spawn(move || {
+        let mut is_reconnect = false;
+        loop {
+            let stream = TcpStream::connect(&to_server);
+            loop {
+                match request_receiver.try_recv() {
+                    Ok(_) => {}
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => {
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+            
+            let is_connected = Arc::new(AtomicBool::new(true));
+            spawn_response_or_notification_receiver(
+                stream.try_clone().unwrap(),
+                is_connected.clone(),
+                action_signal.clone(),
+                action_sender.clone(),
+            );
+            if is_reconnect {
+                action_sender.send(CollabClientAction::Reconnected).unwrap();
+                action_signal.set();
+            }
+            is_reconnect = true;
+            
+            if !send_requests(&request_receiver, stream, &is_connected) {
+                return;
+            }
+        }
+    });
+}
+
+// Sends requests over the given stream until the connection is lost. Returns `false` if there will
+// be no more requests to send.
+fn send_requests(request_receiver: &Receiver<CollabRequest>, mut stream: TcpStream, is_connected: &AtomicBool) -> bool {
+    while is_connected.load(Ordering::Relaxed) {
+        let request = match request_receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(request) => request,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        };
         let mut request_bytes = Vec::new();
         request.ser_bin(&mut request_bytes);
-        let len_bytes = request_bytes.len().to_be_bytes();
-        stream.write_all(&len_bytes).unwrap();
-        stream.write_all(&request_bytes).unwrap();
-    });
+        if write_message(&mut stream, &request_bytes).is_err() {
+            break;
+        }
+    }
+    // Make sure the receiving end stops as well.
+    let _ = stream.shutdown(Shutdown::Both);
+    true
 }
 
 fn spawn_response_or_notification_receiver(
     mut stream: TcpStream,
+    is_connected: Arc<AtomicBool>,
     action_signal: Signal,
     action_sender: Sender<CollabClientAction>,
 ) {
-    thread::spawn(move || loop {
-        let mut len_bytes = [0; 4];
-        stream.read_exact(&mut len_bytes).unwrap();
-        
-        let len = u32::from_be_bytes(len_bytes);
-        let mut action_bytes = vec![0; len as usize];
-        stream.read_exact(&mut action_bytes).unwrap();
-        let action = DeBin::deserialize_bin(action_bytes.as_slice()).unwrap();
-        action_sender.send(action).unwrap();
-        action_signal.set()
+    thread::spawn(move || {
+        while let Ok(action_bytes) = read_message(&mut stream) {
+            let action = DeBin::deserialize_bin(action_bytes.as_slice()).unwrap();
+            if action_sender.send(action).is_err() {
+                break;
+            }
+            action_signal.set()
+        }
+        is_connected.store(false, Ordering::Relaxed);
     });
 }
 
+// Messages are sent as a 4-byte big endian length, followed by the message itself.
+fn write_message(stream: &mut TcpStream, bytes: &[u8]) -> std::io::Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(bytes)
+}
+
+fn read_message(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut len_bytes = [0; 4];
+    stream.read_exact(&mut len_bytes)?;
+    let mut bytes = vec![0; u32::from_be_bytes(len_bytes) as usize];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
 fn spawn_local_request_handler(
     request_receiver: Receiver<CollabRequest>,
     connection: CollabConnection,
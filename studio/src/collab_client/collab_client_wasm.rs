@@ -20,6 +20,8 @@ pub struct CollabClient {
     #[live] bind: Option<String>,
     #[live] path: String,
     #[rust] web_socket: Option<WebSocket>,
+    // Set when the web socket was closed, until it is reopened.
+    #[rust] is_disconnected: bool,
     #[rust] requests: Rc<RefCell<Vec<CollabRequest >> >,
     #[rust(LiveId::unique())] signal: Signal
 }
@@ -78,8 +80,25 @@ impl CollabClient {
                 dispatch_action(cx, action);
             }
             
+            Event::WebSocketClose(web_socket) if *web_socket == self.web_socket.unwrap() => {
+                self.is_disconnected = true;
+            }
+            Event::WebSocketError(error_event) if error_event.web_socket == self.web_socket.unwrap() => {
+                self.is_disconnected = true;
+            }
+            // The web socket reconnects automatically. The server does not know us anymore, so
+            // let the app resync its files.
+            Event::WebSocketOpen(web_socket) if *web_socket == self.web_socket.unwrap() && self.is_disconnected => {
+                self.is_disconnected = false;
+                dispatch_action(cx, CollabClientAction::Reconnected);
+            }
+            
             Event::Signal(signal_event) if signal_event.signals.contains(&self.signal) => {
                 let mut requests = self.requests.borrow_mut();
+                // Requests made while disconnected are stale, they are replaced by resyncing.
+                if self.is_disconnected {
+                    requests.clear();
+                }
                 for request in requests.iter(){
                     let mut buf = Vec::new();
                     request.ser_bin(&mut buf);
@@ -18,6 +18,10 @@ use {
         rust_editor::rust_tokenizer::token_cache::TokenCache,
         makepad_collab_protocol::{
             CollabRequest,
+            ParticipantData,
+            ParticipantId,
+            ResyncData,
+            Selection,
             TextFileId,
             unix_path::UnixPathBuf,
         },
@@ -65,6 +69,12 @@ pub struct EditorState {
     /// The queue of outstanding documents for this code editor. A document is outstanding if it has
     /// been created, but we have not yet received its contents from the collab server.
     pub outstanding_document_queue: VecDeque<DocumentId>,
+    /// The paths of the documents for which we sent a resync request, in the order in which we
+    /// sent them, so we know which document a failed resync was for.
+    pub resync_queue: VecDeque<UnixPathBuf>,
+    /// Every id under which the collab server has known us. We get a new id each time we
+    /// reconnect, and need the old ones to recognize our own deltas when resyncing.
+    pub own_participant_ids: HashSet<ParticipantId>,
     pub messages: Vec<BuildMsg>,
 }
 
@@ -157,15 +167,23 @@ impl EditorState {
     /// document. If the document was scheduled to be destroyed while we were waiting for
     /// the document to become fully initialized, it can now be destroyed, and the `send_request`
     /// callback is used to send a request to the collab server to close the document's file.
+    ///
+    /// If the document was already initialized, it was opened again because resyncing it failed.
+    /// In that case, our outstanding deltas are rebased onto the new text, and sent again.
     pub fn handle_open_file_response(
         &mut self,
         file_id: TextFileId,
         revision: u32,
         text: Text,
+        participants: Vec<ParticipantData>,
         send_request: &mut dyn FnMut(CollabRequest),
     ) -> DocumentId {
         let document_id = self.outstanding_document_queue.pop_front().unwrap();
         let document = &mut self.documents[document_id];
+        if document.inner.is_some() {
+            self.handle_reopen_file_response(document_id, file_id, revision, text, participants, send_request);
+            return document_id;
+        }
         let token_cache = TokenCache::new(&text);
         let indent_cache = IndentCache::new(&text);
         let msg_cache = MsgCache::new(&text);
@@ -173,6 +191,7 @@ impl EditorState {
         document.inner = Some(DocumentInner {
             file_id,
             revision: revision as usize,
+            server_text: text.clone(),
             text,
             token_cache,
             indent_cache,
@@ -181,6 +200,12 @@ impl EditorState {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             outstanding_deltas: VecDeque::new(),
+            participants: participants
+                .into_iter()
+                .map( | participant | (participant.id, participant))
+                .collect(),
+            sent_selections: Vec::new(),
+            pending_selections_session_id: None,
         });
         self.documents_by_file.insert(file_id, document_id);
         if document.should_be_destroyed {
@@ -189,6 +214,170 @@ impl EditorState {
         document_id
     }
 
+    /// Resyncs every document after the connection to the collab server was lost and has been
+    /// reestablished.
+    ///
+    /// The server no longer knows us as a participant for any file. For each initialized document,
+    /// the `send_request` callback is used to request the deltas we missed since the last revision
+    /// we saw, after which the document can continue where it left off. Documents that were still
+    /// waiting for their contents are opened again.
+    pub fn resync_documents(&mut self, send_request: &mut dyn FnMut(CollabRequest)) {
+        // Responses to requests on the old connection will never arrive.
+        self.resync_queue.clear();
+        for (document_id, document) in self.documents.iter_mut() {
+            if self.outstanding_document_queue.contains(document_id) {
+                // The document is being opened (again), which is done below.
+                continue;
+            }
+            if let Some(document_inner) = &mut document.inner {
+                // The server forgot our selections, so make sure they are sent again.
+                document_inner.sent_selections.clear();
+                self.resync_queue.push_back(document.path.clone());
+                send_request(CollabRequest::Resync(document.path.clone(), document_inner.revision as u32));
+            }
+        }
+        for document_id in &self.outstanding_document_queue {
+            send_request(CollabRequest::OpenFile(self.documents[*document_id].path.clone()));
+        }
+    }
+
+    /// Handles a resync response from the collab server.
+    ///
+    /// The deltas we missed are applied in order. One of them may be the delta that we sent right
+    /// before the connection was lost. It was applied by the server, so it confirms our first
+    /// outstanding delta. Any outstanding deltas that remain are sent again, to the new revision.
+    pub fn handle_resync_response(
+        &mut self,
+        data: ResyncData,
+        send_request: &mut dyn FnMut(CollabRequest),
+    ) -> Option<DocumentId> {
+        self.resync_queue.pop_front();
+        let document_id = match self.documents_by_path.get(&data.path) {
+            Some(document_id) if self.documents[*document_id].inner.is_some() => *document_id,
+            _ => {
+                // The document was destroyed while we were resyncing it.
+                send_request(CollabRequest::CloseFile(data.file_id));
+                return None;
+            }
+        };
+
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+        self.documents_by_file.remove(&document_inner.file_id);
+        self.documents_by_file.insert(data.file_id, document_id);
+        document_inner.file_id = data.file_id;
+
+        for (participant_id, delta) in data.deltas {
+            let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+            let is_own_delta = match participant_id {
+                Some(participant_id) => self.own_participant_ids.contains(&participant_id),
+                None => false,
+            };
+            if is_own_delta && !document_inner.outstanding_deltas.is_empty() {
+                document_inner.outstanding_deltas.pop_front();
+                document_inner.revision += 1;
+                document_inner.server_text.apply_delta(delta);
+            } else {
+                self.handle_delta_applied_notification(data.file_id, delta);
+            }
+        }
+
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+        document_inner.revision = data.revision as usize;
+        document_inner.participants = data
+            .participants
+            .into_iter()
+            .map( | participant | (participant.id, participant))
+            .collect();
+        self.send_next_request(document_id, send_request);
+        Some(document_id)
+    }
+
+    /// Handles a resync request that failed, because the collab server no longer has the deltas we
+    /// missed, or cannot open the file.
+    ///
+    /// The document is opened again instead, after which our outstanding deltas are rebased onto
+    /// its new text (see `handle_open_file_response`). Returns the id of the document, if it still
+    /// exists.
+    pub fn handle_resync_error(&mut self, send_request: &mut dyn FnMut(CollabRequest)) -> Option<DocumentId> {
+        let path = self.resync_queue.pop_front()?;
+        let document_id = *self.documents_by_path.get(&path)?;
+        self.outstanding_document_queue.push_back(document_id);
+        send_request(CollabRequest::OpenFile(path));
+        Some(document_id)
+    }
+
+    /// Handles an open file request that failed. The document stays as it is, unless it was
+    /// scheduled to be destroyed while we were waiting for the response, in which case it is
+    /// destroyed now. The server does not know the document's file, so there is nothing to close.
+    pub fn handle_open_file_error(&mut self) {
+        let document_id = match self.outstanding_document_queue.pop_front() {
+            Some(document_id) => document_id,
+            None => return,
+        };
+        let document = &self.documents[document_id];
+        if document.should_be_destroyed {
+            if let Some(document_inner) = &document.inner {
+                self.documents_by_file.remove(&document_inner.file_id);
+            }
+            self.documents_by_path.remove(&document.path);
+            self.documents.remove(&document_id);
+        }
+    }
+
+    // Handles an open file response for a document that was opened again, because resyncing it
+    // failed. The text on the server is compared with the last text we know the server had, and
+    // the difference is applied like a remote delta, so our outstanding deltas are rebased onto the
+    // new text, and sent again.
+    fn handle_reopen_file_response(
+        &mut self,
+        document_id: DocumentId,
+        file_id: TextFileId,
+        revision: u32,
+        text: Text,
+        participants: Vec<ParticipantData>,
+        send_request: &mut dyn FnMut(CollabRequest),
+    ) {
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+        let delta = Delta::diff(&document_inner.server_text, &text);
+        self.documents_by_file.remove(&document_inner.file_id);
+        self.documents_by_file.insert(file_id, document_id);
+        document_inner.file_id = file_id;
+        document_inner.participants = participants
+            .into_iter()
+            .map( | participant | (participant.id, participant))
+            .collect();
+        self.handle_delta_applied_notification(file_id, delta);
+
+        let document = &mut self.documents[document_id];
+        let document_inner = document.inner.as_mut().unwrap();
+        document_inner.revision = revision as usize;
+        document_inner.server_text = text;
+        if document.should_be_destroyed {
+            self.destroy_document_deferred(document_id, send_request);
+        } else {
+            self.send_next_request(document_id, send_request);
+        }
+    }
+
+    // Sends the first outstanding delta of the given document, if any. Otherwise, sends the
+    // selections that could not be sent while there were outstanding deltas, if any.
+    fn send_next_request(&mut self, document_id: DocumentId, send_request: &mut dyn FnMut(CollabRequest)) {
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+        if let Some(outstanding_delta) = document_inner.outstanding_deltas.front() {
+            send_request(CollabRequest::ApplyDelta(
+                document_inner.file_id,
+                document_inner.revision as u32,
+                outstanding_delta.clone(),
+            ));
+        } else if let Some(session_id) = document_inner.pending_selections_session_id.take() {
+            // The selections could not be sent while there were outstanding deltas, so send them
+            // now.
+            if self.sessions.contains_key(&session_id) {
+                self.send_selections(session_id, send_request);
+            }
+        }
+    }
+
     /// Schedules the document with the given `document_id` to be destroyed.
     ///
     /// If the document is already initialized, it is destroyed immediately, and the `send_request`
@@ -199,12 +388,13 @@ impl EditorState {
         document_id: DocumentId,
         send_request: &mut dyn FnMut(CollabRequest),
     ) {
+        let is_opening = self.outstanding_document_queue.contains(&document_id);
         let document = &mut self.documents[document_id];
-        if document.inner.is_some() {
+        if document.inner.is_some() && !is_opening {
             // The document is already initialized, so destroy it immediately.
             self.destroy_document_deferred(document_id, send_request);
         } else {
-            // The document is not yet initialized, so scheduled it to be destroyed later.
+            // The document is not yet (re)initialized, so scheduled it to be destroyed later.
             document.should_be_destroyed = true;
         }
     }
@@ -331,7 +521,7 @@ impl EditorState {
         // If the character to be inserted is the same as an automatically injected character, we
         // skip over the automatically injected character rather than insert the same character
         // again.
-        if let Some(ch) = text.as_lines().first().and_then( | line | line.first()) {
+        if let Some(ch) = text.line(0).first() {
             if let Some(injected_char) = session.injected_char_stack.last() {
                 if ch == injected_char {
                     let session = &mut self.sessions[session_id];
@@ -348,9 +538,8 @@ impl EditorState {
         // If the character to be inserted is an opening delimiter, we automatically insert the
         // corresponding closing delimiter.
         let injected_char = text
-            .as_lines()
+            .line(0)
             .first()
-            .and_then( | line | line.first())
             .and_then( | ch | match ch {
             '(' => Some(')'),
             '[' => Some(']'),
@@ -441,16 +630,15 @@ impl EditorState {
             // This should be refactored in the future, by in the meantime we work around the 
             // problem by only performing autoindenting if there is just a single cursor.
             if session.cursors.len() == 1 {
-                let lines = document_inner.text.as_lines();
                 if let Some((first_non_whitespace_line_before, first_non_whitespace_char_before)) =
-                lines[cursor.start().line][..cursor.start().column]
+                document_inner.text.line(cursor.start().line)[..cursor.start().column]
                     .iter()
                     .rev()
                     .find( | ch | !ch.is_whitespace())
                     .map( | &ch | (cursor.start().line, ch))
                     .or_else( || {
                     (0..cursor.start().line).rev().find_map( | line | {
-                        lines[line]
+                        document_inner.text.line(line)
                             .iter()
                             .rev()
                             .find( | ch | !ch.is_whitespace())
@@ -530,8 +718,7 @@ impl EditorState {
                         builder_1.retain(
                             Position {
                                 line: cursor.start().line - 1,
-                                column: document_inner.text.as_lines()[cursor.start().line - 1]
-                                    .len(),
+                                column: document_inner.text.line_len(cursor.start().line - 1),
                             } -position,
                         );
                         builder_1.delete(Size {line: 1, column: 0});
@@ -545,7 +732,7 @@ impl EditorState {
                     //
                     // This should be refactored in the future, by in the meantime we work around
                     // the problem by only performing autoindenting if there is just a single cursor.
-                    let lines = &document_inner.text.as_lines()[cursor.start().line];
+                    let lines = document_inner.text.line(cursor.start().line);
                     
                     if session.cursors.len() == 1
                         && lines[..cursor.start().column]
@@ -567,8 +754,7 @@ impl EditorState {
                             builder_1.retain(
                                 Position {
                                     line: cursor.start().line - 1,
-                                    column: document_inner.text.as_lines()[cursor.start().line - 1]
-                                        .len(),
+                                    column: document_inner.text.line_len(cursor.start().line - 1),
                                 } -position,
                             );
                             builder_1.delete(Size {
@@ -588,7 +774,7 @@ impl EditorState {
                         // If we're deleting the character that triggered an automatic character
                         // injection, we also remove the automatically injected character.
                         if let Some(last_injected_char_inverse) = last_injected_char_inverse {
-                            if document_inner.text.as_lines()[cursor.start().line]
+                            if document_inner.text.line(cursor.start().line)
                             [cursor.start().column - 1]
                                 == last_injected_char_inverse
                             {
@@ -642,8 +828,8 @@ impl EditorState {
                 continue;
             }
             builder_1.retain(cursor.start() - position);
-            if cursor.start().column == document_inner.text.as_lines()[cursor.start().line].len() {
-                if cursor.start().line == document_inner.text.as_lines().len() - 1 {
+            if cursor.start().column == document_inner.text.line_len(cursor.start().line) {
+                if cursor.start().line == document_inner.text.line_count() - 1 {
                     continue;
                 }
                 builder_1.delete(Size {line: 1, column: 0});
@@ -799,14 +985,133 @@ impl EditorState {
         let document = &mut self.documents[document_id];
         let document_inner = document.inner.as_mut().unwrap();
 
-        document_inner.outstanding_deltas.pop_front();
+        let delta = document_inner.outstanding_deltas.pop_front().unwrap();
+        document_inner.server_text.apply_delta(delta);
         document_inner.revision += 1;
-        if let Some(outstanding_delta) = document_inner.outstanding_deltas.front() {
-            send_request(CollabRequest::ApplyDelta(
-                file_id,
-                document_inner.revision as u32,
-                outstanding_delta.clone(),
-            ));
+        self.send_next_request(document_id, send_request);
+    }
+
+    /// Sends the selections of the session with the given `session_id` to the collab server, so
+    /// the other participants for its document can see them. Does nothing if the selections did
+    /// not change since they were last sent.
+    ///
+    /// Selections are sent for a revision of the document. While there are outstanding deltas,
+    /// the local text is ahead of every revision, so sending is deferred until the last
+    /// outstanding delta is confirmed.
+    pub fn send_selections(&mut self, session_id: SessionId, send_request: &mut dyn FnMut(CollabRequest)) {
+        let session = &self.sessions[session_id];
+        let document = &mut self.documents[session.document_id];
+        let document_inner = match &mut document.inner {
+            Some(document_inner) => document_inner,
+            None => return,
+        };
+        if !document_inner.outstanding_deltas.is_empty() {
+            document_inner.pending_selections_session_id = Some(session_id);
+            return;
+        }
+        let selections: Vec<_> = session
+            .cursors
+            .iter()
+            .map( | cursor | Selection {
+                head: cursor.head,
+                tail: cursor.tail,
+            })
+            .collect();
+        if selections == document_inner.sent_selections {
+            return;
+        }
+        document_inner.sent_selections = selections.clone();
+        send_request(CollabRequest::UpdateSelections(
+            document_inner.file_id,
+            document_inner.revision as u32,
+            selections,
+        ));
+    }
+
+    /// Handles a notification from the collab server that a participant joined a document, or
+    /// changed its name.
+    pub fn handle_participant_joined_notification(
+        &mut self,
+        file_id: TextFileId,
+        participant: ParticipantData,
+    ) -> DocumentId {
+        let document_id = self.documents_by_file[file_id];
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+        document_inner.participants.insert(participant.id, participant);
+        document_id
+    }
+
+    /// Handles a notification from the collab server that a participant left a document.
+    pub fn handle_participant_left_notification(
+        &mut self,
+        file_id: TextFileId,
+        participant_id: ParticipantId,
+    ) -> DocumentId {
+        let document_id = self.documents_by_file[file_id];
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+        document_inner.participants.remove(&participant_id);
+        document_id
+    }
+
+    /// Handles a notification from the collab server that a participant changed its selections.
+    pub fn handle_selections_updated_notification(
+        &mut self,
+        file_id: TextFileId,
+        revision: u32,
+        participant_id: ParticipantId,
+        selections: Vec<Selection>,
+    ) -> DocumentId {
+        let document_id = self.documents_by_file[file_id];
+        let document_inner = self.documents[document_id].inner.as_mut().unwrap();
+
+        // The selections include the outstanding deltas that the server applied before it sent
+        // them, but we have not yet seen confirmed. Transform them against the remaining ones.
+        let seen_delta_count = (revision as usize).saturating_sub(document_inner.revision);
+        let mut selections = selections;
+        for outstanding_delta in document_inner.outstanding_deltas.iter().skip(seen_delta_count) {
+            for selection in &mut selections {
+                selection.apply_delta(outstanding_delta);
+            }
+        }
+
+        if let Some(participant) = document_inner.participants.get_mut(&participant_id) {
+            participant.selections = selections;
+        }
+        document_id
+    }
+
+    /// Requests the collab server to write the document referred to by the session with the given
+    /// `session_id` to disk.
+    ///
+    /// The server saves the newest revision it has seen. Deltas that are still outstanding are not
+    /// part of it. If the file was changed on disk since it was last read or written, saving fails,
+    /// unless `overwrite` is set.
+    pub fn save_file(
+        &mut self,
+        session_id: SessionId,
+        overwrite: bool,
+        send_request: &mut dyn FnMut(CollabRequest),
+    ) {
+        let session = &self.sessions[session_id];
+        let document = &self.documents[session.document_id];
+        if let Some(document_inner) = &document.inner {
+            send_request(CollabRequest::SaveFile {
+                file_id: document_inner.file_id,
+                overwrite,
+            });
+        }
+    }
+
+    /// Requests the collab server to discard the unsaved changes to the document referred to by the
+    /// session with the given `session_id`, and bring it in line with the file on disk.
+    ///
+    /// The server sends the delta that does this as a notification, which is handled like any other
+    /// remote delta.
+    pub fn revert_file(&mut self, session_id: SessionId, send_request: &mut dyn FnMut(CollabRequest)) {
+        let session = &self.sessions[session_id];
+        let document = &self.documents[session.document_id];
+        if let Some(document_inner) = &document.inner {
+            send_request(CollabRequest::RevertFile(document_inner.file_id));
         }
     }
 
@@ -819,6 +1124,7 @@ impl EditorState {
         let document_id = self.documents_by_file[file_id];
         let document = &mut self.documents[document_id];
         let document_inner = document.inner.as_mut().unwrap();
+        document_inner.server_text.apply_delta(delta.clone());
 
         let mut delta = delta;
         for outstanding_delta_ref in &mut document_inner.outstanding_deltas {
@@ -916,6 +1222,12 @@ impl Document {
     fn apply_delta(&mut self, delta: Delta) {
         let inner = self.inner.as_mut().unwrap();
 
+        for participant in inner.participants.values_mut() {
+            for selection in &mut participant.selections {
+                selection.apply_delta(&delta);
+            }
+        }
+
         inner.token_cache.invalidate(&delta);
         inner.indent_cache.invalidate(&delta);
         inner.msg_cache.invalidate(&delta);
@@ -959,6 +1271,9 @@ pub struct DocumentInner {
     pub file_id: TextFileId,
     /// The revision of this document.
     pub revision: usize,
+    /// The text of this document at `revision`, that is, without the outstanding deltas. Used to
+    /// rebase the outstanding deltas if the document has to be opened again.
+    pub server_text: Text,
     /// The text for this document
     pub text: Text,
     /// A line-based cache containing the tokens for each line.
@@ -976,6 +1291,12 @@ pub struct DocumentInner {
     /// applied to the local document, but we have not yet received confirmation from the collab
     /// server that it has been applied to the remote document.
     pub outstanding_deltas: VecDeque<Delta>,
+    /// The other participants for this document, with their selections for the local text.
+    pub participants: HashMap<ParticipantId, ParticipantData>,
+    /// The selections that were last sent to the collab server.
+    pub sent_selections: Vec<Selection>,
+    /// The session whose selections should be sent once there are no more outstanding deltas.
+    pub pending_selections_session_id: Option<SessionId>,
 }
 
 /// An `EditGroup` keeps track of whether the last typed character was a backspace character or a
@@ -16,10 +16,16 @@ use {
             },
         },
         makepad_collab_protocol::{
+            CollabError,
             CollabNotification,
             CollabRequest,
             CollabResponse,
         },
+        build::build_protocol::{
+            BuildMsg,
+            BuildMsgBare,
+            BuildMsgLevel,
+        },
         rust_editor::{
             rust_editor::{
                RustEditor
@@ -29,6 +35,9 @@ use {
 
 };
 
+const RESOLVE_FILE_CONFLICT_HINT: &str =
+    "Press Ctrl+Shift+S to overwrite it with your changes, or Ctrl+Shift+R to discard them.";
+
 enum EditorView {
     RustEditor(RustEditor)
 }
@@ -184,15 +193,52 @@ impl Editors {
         send_request: &mut dyn FnMut(CollabRequest),
     ) {
         match response {
-            CollabResponse::OpenFile(response) => {
-                let (file_id, revision, text) = response.unwrap();
-                let document_id = state.handle_open_file_response(file_id, revision, text, send_request);
-                self.redraw_views_for_document(cx, state, document_id);
+            CollabResponse::OpenFile(Ok((file_id, revision, text, participants))) => {
+                let document_id = state.handle_open_file_response(file_id, revision, text, participants, send_request);
+                if state.documents.contains_key(&document_id) {
+                    self.redraw_views_for_document(cx, state, document_id);
+                }
+            }
+            CollabResponse::OpenFile(Err(error)) => {
+                error!("Cannot open file: {:?}", error);
+                state.handle_open_file_error();
             }
-            CollabResponse::ApplyDelta(response) => {
-                let file_id = response.unwrap();
+            CollabResponse::ApplyDelta(Ok(file_id)) => {
                 state.handle_apply_delta_response(file_id, send_request);
             }
+            CollabResponse::ApplyDelta(Err(error)) => {
+                // The delta was sent on a connection that has since been lost. It is sent again
+                // once the document is resynced.
+                error!("Cannot apply delta: {:?}", error);
+            }
+            CollabResponse::SetParticipantName(Ok(participant_id)) => {
+                state.own_participant_ids.insert(participant_id);
+            }
+            CollabResponse::Resync(Ok(data)) => {
+                if let Some(document_id) = state.handle_resync_response(data, send_request) {
+                    self.redraw_views_for_document(cx, state, document_id);
+                }
+            }
+            CollabResponse::Resync(Err(error)) => {
+                // The document is opened again instead.
+                error!("Cannot resync file: {:?}", error);
+                state.handle_resync_error(send_request);
+            }
+            CollabResponse::SaveFile(Err(CollabError::FileChangedOnDisk)) => {
+                state.messages.push(BuildMsg::Bare(BuildMsgBare {
+                    level: BuildMsgLevel::Warning,
+                    line: format!(
+                        "Cannot save file, because it was changed on disk. {}",
+                        RESOLVE_FILE_CONFLICT_HINT
+                    ),
+                }));
+            }
+            CollabResponse::SaveFile(Err(error)) => {
+                error!("Cannot save file: {:?}", error);
+            }
+            CollabResponse::RevertFile(Err(error)) => {
+                error!("Cannot revert file: {:?}", error);
+            }
             _ => {}
         }
     }
@@ -204,11 +250,41 @@ impl Editors {
         notification: CollabNotification,
     ) {
         match notification {
-            CollabNotification::DeltaWasApplied(file_id, delta) => {
+            CollabNotification::DeltaWasApplied(file_id, delta) |
+            CollabNotification::FileWasChangedOnDisk(file_id, delta) => {
                 let document_id = state.handle_delta_applied_notification(file_id, delta);
                 self.redraw_views_for_document(cx, state, document_id);
                 
             }
+            CollabNotification::ParticipantJoined(file_id, participant) => {
+                let document_id = state.handle_participant_joined_notification(file_id, participant);
+                self.redraw_views_for_document(cx, state, document_id);
+            }
+            CollabNotification::ParticipantLeft(file_id, participant_id) => {
+                let document_id = state.handle_participant_left_notification(file_id, participant_id);
+                self.redraw_views_for_document(cx, state, document_id);
+            }
+            CollabNotification::SelectionsWereUpdated(file_id, revision, participant_id, selections) => {
+                let document_id = state.handle_selections_updated_notification(file_id, revision, participant_id, selections);
+                self.redraw_views_for_document(cx, state, document_id);
+            }
+            CollabNotification::FileConflict(file_id) => {
+                let path = match state.documents_by_file.get(&file_id) {
+                    Some(document_id) => {
+                        String::from_utf8_lossy(state.documents[*document_id].path.as_bytes()).into_owned()
+                    }
+                    None => return,
+                };
+                state.messages.push(BuildMsg::Bare(BuildMsgBare {
+                    level: BuildMsgLevel::Warning,
+                    line: format!(
+                        "{} was changed on disk while it had unsaved changes. {}",
+                        path,
+                        RESOLVE_FILE_CONFLICT_HINT
+                    ),
+                }));
+            }
+            _ => {}
         }
     }
     
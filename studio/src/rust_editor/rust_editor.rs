@@ -201,6 +201,12 @@ impl RustEditor {
             &self.lines_layout
         );
         
+        self.editor_impl.draw_remote_carets(
+            cx,
+            &document_inner.participants,
+            &self.lines_layout
+        );
+        
         self.draw_text(
             cx,
             &document_inner.text,
@@ -239,10 +245,8 @@ impl RustEditor {
         let origin = cx.turtle().pos();
         //let mut start_y = visible_lines.start_y;
         for (line_index, (chars, token_info)) in text
-            .as_lines()
-            .iter()
-            .zip(token_cache.iter())
-            .skip(lines_layout.view_start)
+            .lines_at(lines_layout.view_start)
+            .zip(token_cache.iter().skip(lines_layout.view_start))
             .take(lines_layout.view_end - lines_layout.view_start)
             .enumerate()
         {
@@ -18,7 +18,7 @@ pub struct TokenCache {
 impl TokenCache {
     pub fn new(text: &Text) -> TokenCache {
         let mut cache = TokenCache {
-            lines: (0..text.as_lines().len()).map(|_| Line::default()).collect::<Vec<_>>(),
+            lines: (0..text.line_count()).map(|_| Line::default()).collect::<Vec<_>>(),
         };
         cache.refresh(text);
         cache
@@ -57,7 +57,8 @@ impl TokenCache {
                 _ => {
                     let start_state = state;
                     let mut tokens = Vec::new();
-                    let mut cursor = Cursor::new(&text.as_lines()[index], &mut scratch);
+                    let chars = text.line(index);
+                    let mut cursor = Cursor::new(&chars, &mut scratch);
                     loop {
                         let (next_state, token) = state.next(&mut cursor);
                         state = next_state;
@@ -85,14 +85,14 @@ fn main() {
         let cargo_str = fs::read_to_string(&c.cargo).unwrap();
         let toml = makepad_toml_parser::parse_toml(&cargo_str).unwrap();
 
-        let old_sha1 = if let Some(Toml::Str(ver, _)) = toml.get("package.metadata.makepad-auto-version") {
+        let old_sha1 = if let Some(Toml::Str(ver, _)) = toml.lookup("package.metadata.makepad-auto-version") {
             ver.to_string()
         }
         else {
             continue;
         };
-        let package_name = toml.get("package.name").unwrap().clone().into_str().unwrap();
-        let package_version = toml.get("package.version").unwrap().clone().into_str().unwrap();
+        let package_name = toml.lookup("package.name").unwrap().clone().into_str().unwrap();
+        let package_version = toml.lookup("package.version").unwrap().clone().into_str().unwrap();
 
         // hash all the rs files
         let mut sha1 = sha1::Sha1::new();
@@ -104,10 +104,10 @@ fn main() {
         let new_sha1 = String::from_utf8(base64::base64_encode(&data, &base64::BASE64_URL_SAFE)).unwrap();
         let mut deps = Vec::new();
         // scan our toml file for all dependencies
-        for key in toml.keys() {
-            for pref in target_deps {
-                if let Some(pref) = key.strip_prefix(pref) {
-                    if let Some(dep) = pref.strip_suffix(".version") {
+        for pref in target_deps {
+            if let Some(Toml::Table(table, _)) = toml.lookup(pref.trim_end_matches('.')) {
+                for (dep, value) in table.iter() {
+                    if value.get("version").is_some() {
                         deps.push(dep.to_string());
                     }
                 }
@@ -172,20 +172,12 @@ fn main() {
 
 fn patch_cargo(cargo: &Path, toml_path: &str, with: &str, write: bool) {
     let old_cargo = fs::read_to_string(&cargo).unwrap();
-    let toml = makepad_toml_parser::parse_toml(&old_cargo).unwrap();
+    let mut doc = makepad_toml_parser::TomlDoc::parse(&old_cargo).unwrap();
+    let toml_path: Vec<&str> = toml_path.split('.').collect();
     
-    if let Some(Toml::Str(_, span)) = toml.get(toml_path) {
-        let mut new_cargo = String::new();
-        for (i, c) in old_cargo.chars().enumerate() {
-            if i == span.start {
-                for c in with.chars() {
-                    new_cargo.push(c);
-                }
-            }
-            if i < span.start || i >= span.start + span.len - 2 {
-                new_cargo.push(c);
-            }
-        }
+    if let Some(Toml::Str(..)) = doc.get(&toml_path) {
+        doc.set(&toml_path, with).unwrap();
+        let new_cargo = doc.to_string();
         // lets write it back to disk
         if write {
             fs::File::create(&cargo).unwrap().write_all(new_cargo.as_bytes()).unwrap();
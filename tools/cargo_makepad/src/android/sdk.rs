@@ -3,7 +3,6 @@ use makepad_miniz::zip_file::*;
 use std::{
     path::{Path},
     fs::File,
-    io::Write,
 };
 
 use crate::{
@@ -118,13 +117,12 @@ pub fn expand_sdk(sdk_dir: &Path, host_os: HostOs, _args: &[String]) -> Result<(
             if let Some(file_header) = directory.file_headers.iter().find( | v | v.file_name == file_name) {
                 let is_symlink = (file_header.external_file_attributes >> 16) & 0o120000 == 0o120000;
                 
-                let data = file_header.extract(zip_file).map_err( | e | {
-                    format!("Can't extract file from {file_name} {:?}", e)
-                }) ?;
-                
                 mkdir(output_file.parent().unwrap()) ?;
                 
                 if is_symlink {
+                    let data = file_header.extract(zip_file).map_err( | e | {
+                        format!("Can't extract file from {file_name} {:?}", e)
+                    }) ?;
                     let link_to = std::str::from_utf8(&data).unwrap().to_string();
                     #[cfg(any(target_os = "macos", target_os = "linux"))]
                     use std::os::unix::fs::symlink;
@@ -135,8 +133,11 @@ pub fn expand_sdk(sdk_dir: &Path, host_os: HostOs, _args: &[String]) -> Result<(
                     let mut output = File::create(output_file)
                         .map_err( | _ | format!("Cant open output file {:?}", output_file)) ?;
                     
-                    output.write(&data)
-                        .map_err( | _ | format!("Cant write output file {:?}", output_file)) ?;
+                    let mut reader = file_header.reader(zip_file).map_err( | e | {
+                        format!("Can't extract file from {file_name} {:?}", e)
+                    }) ?;
+                    std::io::copy(&mut reader, &mut output)
+                        .map_err( | e | format!("Cant extract to output file {:?} {}", output_file, e)) ?;
                     
                     #[cfg(any(target_os = "macos", target_os = "linux"))]
                     if exec {
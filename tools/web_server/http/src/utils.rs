@@ -73,6 +73,29 @@ pub struct HttpHeaders {
 }
 
 impl HttpHeaders {
+    /// True if the client listed `encoding` in Accept-Encoding, by name or as `*` (and did not
+    /// refuse it with q=0). The entry with the name wins over `*`.
+    pub fn accepts_encoding(&self, encoding: &str) -> bool {
+        let mut wildcard = false;
+        if let Some(accept_encoding) = &self.accept_encoding {
+            for item in accept_encoding.split(',') {
+                let mut parts = item.split(';');
+                let name = parts.next().unwrap_or("").trim();
+                let refused = parts.any(|v| {
+                    let v = v.trim();
+                    v.strip_prefix("q=").map(|q| q.trim().parse::<f32>().unwrap_or(1.0) == 0.0).unwrap_or(false)
+                });
+                if name.eq_ignore_ascii_case(encoding) {
+                    return !refused
+                }
+                if name == "*" {
+                    wildcard = !refused;
+                }
+            }
+        }
+        wildcard
+    }
+    
     pub fn from_tcp_stream(tcp_stream: &mut TcpStream) -> Option<HttpHeaders> {
       let mut reader = BufReader::new(tcp_stream);
                       
@@ -1,4 +1,5 @@
 use makepad_http::server::*;
+use makepad_miniz::compress_to_vec_gzip;
 use makepad_collab_server::{
     NotificationSender,
     CollabClientAction,
@@ -15,6 +16,7 @@ use std::{
     io::BufReader,
     fs::File,
     fs,
+    time::Duration,
 };
 
 #[derive(Clone)]
@@ -34,6 +36,12 @@ impl NotificationSender for CollabNotificationSender{
     }
 }
 
+// text formats shrink well, images like png and jpg, woff2 fonts and brotli'd files
+// are already compressed and are sent as they are
+fn is_compressible(mime_type: &str) -> bool {
+    mime_type.starts_with("text/") || matches!(mime_type, "application/wasm" | "application/ttf" | "application/json" | "image/svg+xml")
+}
+
 fn main() {
     let (tx_request, rx_request) = mpsc::channel::<HttpRequest> ();
     
@@ -49,6 +57,7 @@ fn main() {
     });
     println!("Server listening on {}", addr);
     let mut clb_server = CollabServer::new("./");
+    let _file_watcher = clb_server.watch_files(Duration::from_millis(500));
     let mut clb_connections = HashMap::new();
     
     let route_secret = fs::read_to_string("route_secret.txt").unwrap_or("\nNO\nACCESS\n".to_string()).trim().to_string();
@@ -140,16 +149,25 @@ fn main() {
                     if let Ok(mut file_handle) = File::open(base) {
                         let mut body = Vec::<u8>::new();
                         if file_handle.read_to_end(&mut body).is_ok() {
+                            let content_encoding = if is_compressible(mime_type) && headers.accepts_encoding("gzip"){
+                                body = compress_to_vec_gzip(&body, 6);
+                                "gzip"
+                            }
+                            else{
+                                "none"
+                            };
                             let header = format!(
                                 "HTTP/1.1 200 OK\r\n\
                                 Content-Type: {}\r\n\
                                 Cross-Origin-Embedder-Policy: require-corp\r\n\
                                 Cross-Origin-Opener-Policy: same-origin\r\n\
-                                Content-encoding: none\r\n\
+                                Content-encoding: {}\r\n\
+                                Vary: Accept-Encoding\r\n\
                                 Cache-Control: max-age:0\r\n\
                                 Content-Length: {}\r\n\
                                 Connection: close\r\n\r\n",
                                 mime_type,
+                                content_encoding,
                                 body.len()
                             );
                             let _ = response_sender.send(HttpResponse{header, body});
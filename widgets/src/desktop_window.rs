@@ -149,6 +149,7 @@ impl LiveHook for DesktopWindow {
             }
             OsType::LinuxWindow(_) |
             OsType::LinuxDirect |
+            OsType::LinuxHeadless |
             OsType::Android(_) => {
                 self.frame.get_frame(id!(caption_bar)).set_visible(false);
             }
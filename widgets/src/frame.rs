@@ -4,6 +4,8 @@ use {
         makepad_derive_widget::*,
         makepad_image_formats::jpeg,
         makepad_image_formats::png,
+        makepad_image_formats::gif,
+        makepad_image_formats::{AnimatedImage, ImageBuffer},
         makepad_draw::*,
         widget::*,
         scroll_bars::ScrollBars,
@@ -387,7 +389,12 @@ pub struct Frame { // draw info per UI element
     #[rust] defer_walks: Vec<(LiveId, DeferWalk)>,
     #[rust] draw_state: DrawStateWrap<DrawState>,
     #[rust] children: ComponentMap<LiveId, WidgetRef>,
-    #[rust] draw_order: Vec<LiveId>
+    #[rust] draw_order: Vec<LiveId>,
+    
+    #[rust] image_animation: Option<AnimatedImage>,
+    #[rust] image_animation_start: Option<f64>,
+    #[rust] image_frame_index: usize,
+    #[rust] image_next_frame: NextFrame,
 }
 
 struct FrameTextureCache {
@@ -414,6 +421,8 @@ impl LiveHook for Frame {
         let image_path = self.image.as_str();
         if image_path.len()>0 {
             let mut image_buffer = None;
+            let mut animation = None;
+            self.image_animation = None;
             match cx.get_dependency(image_path) {
                 Ok(data) => {
                     if image_path.ends_with(".jpg") {
@@ -430,12 +439,25 @@ impl LiveHook for Frame {
                         }
                     }
                     else if image_path.ends_with(".png") {
-                        match png::decode(data) {
+                        match png::decode_animated(data) {
                             Ok(image) => {
                                 if self.image_scale != 0.0 {
                                     self.walk = Walk::fixed_size(DVec2 {x: image.width as f64 * self.image_scale, y: image.height as f64 * self.image_scale});
                                 }
-                                image_buffer = Some(image);
+                                animation = Some(image);
+                            }
+                            Err(err) => {
+                                cx.apply_image_decoding_failed(live_error_origin!(), index, nodes, image_path, &err);
+                            }
+                        }
+                    }
+                    else if image_path.ends_with(".gif") {
+                        match gif::decode_animated(data) {
+                            Ok(image) => {
+                                if self.image_scale != 0.0 {
+                                    self.walk = Walk::fixed_size(DVec2 {x: image.width as f64 * self.image_scale, y: image.height as f64 * self.image_scale});
+                                }
+                                animation = Some(image);
                             }
                             Err(err) => {
                                 cx.apply_image_decoding_failed(live_error_origin!(), index, nodes, image_path, &err);
@@ -450,19 +472,22 @@ impl LiveHook for Frame {
                     cx.apply_resource_not_loaded(live_error_origin!(), index, nodes, image_path, &err);
                 }
             }
-            if let Some(mut image_buffer) = image_buffer.take() {
-                if self.image_texture.is_none() {
-                    self.image_texture = Some(Texture::new(cx));
+            if let Some(mut animation) = animation {
+                // animations keep their frames around, and start playing on the next frame
+                if animation.frames.len() > 1 {
+                    image_buffer = Some(animation.frames[0].image.clone());
+                    self.image_animation = Some(animation);
+                    self.image_animation_start = None;
+                    self.image_frame_index = 0;
+                    self.image_next_frame = cx.new_next_frame();
                 }
-                if let Some(image_texture) = &mut self.image_texture {
-                    image_texture.set_desc(cx, TextureDesc {
-                        format: TextureFormat::ImageBGRA,
-                        width: Some(image_buffer.width),
-                        height: Some(image_buffer.height),
-                    });
-                    image_texture.swap_image_u32(cx, &mut image_buffer.data);
+                else {
+                    image_buffer = Some(animation.frames.remove(0).image);
                 }
             }
+            if let Some(mut image_buffer) = image_buffer.take() {
+                self.set_image_texture(cx, &mut image_buffer);
+            }
         }
     }
     
@@ -608,6 +633,9 @@ impl Widget for Frame {
                 return
             }
         }
+        if let Some(ne) = self.image_next_frame.is_event(event) {
+            self.animate_image(cx, ne.time);
+        }
         if let Some(scroll_bars) = &mut self.scroll_bars_obj {
             let mut redraw = false;
             scroll_bars.handle_main_event(cx, event, &mut | _, _ | {
@@ -734,6 +762,38 @@ impl Frame {
         self.area
     }
     
+    fn set_image_texture(&mut self, cx: &mut Cx, image_buffer: &mut ImageBuffer) {
+        if self.image_texture.is_none() {
+            self.image_texture = Some(Texture::new(cx));
+        }
+        if let Some(image_texture) = &mut self.image_texture {
+            image_texture.set_desc(cx, TextureDesc {
+                format: TextureFormat::ImageBGRA,
+                width: Some(image_buffer.width),
+                height: Some(image_buffer.height),
+            });
+            image_texture.swap_image_u32(cx, &mut image_buffer.data);
+        }
+    }
+    
+    // shows the frame of the image animation that is due at the given time, and keeps
+    // asking for next frames until the animation has played its last loop
+    fn animate_image(&mut self, cx: &mut Cx, time: f64) {
+        let animation = if let Some(animation) = &self.image_animation {animation} else {return};
+        let elapsed = time - *self.image_animation_start.get_or_insert(time);
+        let index = animation.frame_index_at(elapsed);
+        let is_done = animation.loop_count != 0 && elapsed >= animation.duration() * animation.loop_count as f64;
+        if index != self.image_frame_index {
+            let mut image_buffer = animation.frames[index].image.clone();
+            self.image_frame_index = index;
+            self.set_image_texture(cx, &mut image_buffer);
+            cx.redraw_area(self.area);
+        }
+        if !is_done {
+            self.image_next_frame = cx.new_next_frame();
+        }
+    }
+    
     pub fn draw(&mut self, cx: &mut Cx2d,) -> WidgetDraw {
         self.draw_walk(cx, self.get_walk())
     }